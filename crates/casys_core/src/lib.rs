@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 pub type NodeId = u64;
 pub type EdgeId = u64;
@@ -13,6 +13,20 @@ pub struct Node {
     pub id: NodeId,
     pub labels: Vec<String>,
     pub properties: HashMap<String, Value>,
+    /// Tombstone flag set by soft deletes; tombstoned nodes are hidden from reads
+    pub deleted: bool,
+}
+
+impl Node {
+    /// Whether both describe the same node: same id, tombstone flag and properties, and
+    /// the same labels in any order. Properties compare with `Value`'s `==`.
+    pub fn semantically_equals(&self, other: &Node) -> bool {
+        use std::collections::BTreeSet;
+        self.id == other.id
+            && self.deleted == other.deleted
+            && self.labels.iter().collect::<BTreeSet<_>>() == other.labels.iter().collect::<BTreeSet<_>>()
+            && self.properties == other.properties
+    }
 }
 
 /// A graph edge connecting two nodes
@@ -23,6 +37,101 @@ pub struct Edge {
     pub to_node: NodeId,
     pub edge_type: String,
     pub properties: HashMap<String, Value>,
+    /// Tombstone flag set by soft deletes; tombstoned edges are hidden from reads
+    pub deleted: bool,
+}
+
+impl Edge {
+    /// Whether both describe the same edge: same id, endpoints, type, tombstone flag and
+    /// properties, compared as in `Node::semantically_equals`.
+    pub fn semantically_equals(&self, other: &Edge) -> bool {
+        self.id == other.id
+            && self.from_node == other.from_node
+            && self.to_node == other.to_node
+            && self.edge_type == other.edge_type
+            && self.deleted == other.deleted
+            && self.properties == other.properties
+    }
+}
+
+/// A walk through the graph: `nodes[i]` and `nodes[i + 1]` are joined by `edges[i]`.
+///
+/// Named `GraphPath` to stay clear of `std::path::Path`, which the storage ports use.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GraphPath {
+    pub nodes: Vec<NodeId>,
+    pub edges: Vec<EdgeId>,
+}
+
+impl GraphPath {
+    /// Number of edges (hops) in the path.
+    pub fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// True for the zero-hop path from a node to itself.
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+}
+
+/// Which incident edges of a node a query follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Edges whose `from_node` is the node
+    Outgoing,
+    /// Edges whose `to_node` is the node
+    Incoming,
+    /// Either endpoint; a self-loop counts once
+    Both,
+}
+
+/// How a multi-label scan combines its labels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LabelMatch {
+    /// The node carries every label
+    All,
+    /// The node carries at least one of the labels
+    Any,
+}
+
+/// Resume point for `GraphReadStore::scan_page`.
+///
+/// Pages are served in ascending id order and the cursor records the last id returned, so
+/// nodes created between calls can never make an earlier node come back. Round-trip it
+/// through `to_string` / `parse` to hand it to a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScanCursor {
+    last: NodeId,
+}
+
+impl ScanCursor {
+    /// Cursor resuming right after node `id`.
+    pub fn after(id: NodeId) -> Self {
+        Self { last: id }
+    }
+
+    /// The last node id already returned.
+    pub fn last_id(&self) -> NodeId {
+        self.last
+    }
+}
+
+impl std::fmt::Display for ScanCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sc1:{:x}", self.last)
+    }
+}
+
+impl std::str::FromStr for ScanCursor {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix("sc1:")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .map(Self::after)
+            .ok_or_else(|| EngineError::InvalidArgument(format!("invalid scan cursor: {}", s)))
+    }
 }
 
 // -----------------------
@@ -30,21 +139,772 @@ pub struct Edge {
 // -----------------------
 
 /// Read-only graph storage interface
+///
+/// Unless a method documents an order, results come in whatever order the store finds
+/// cheapest and may differ between runs; stores may offer an option to sort them by id.
 pub trait GraphReadStore {
     fn scan_all(&self) -> Result<Vec<Node>, EngineError>;
     fn scan_by_label(&self, label: &str) -> Result<Vec<Node>, EngineError>;
     fn get_node(&self, id: NodeId) -> Result<Option<Node>, EngineError>;
     fn get_neighbors(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node)>, EngineError>;
     fn get_neighbors_incoming(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node)>, EngineError>;
+
+    /// Borrow every live node in turn, without cloning. Stores that can't hand out
+    /// references keep the `NotImplemented` default; callers then fall back to `scan_all`.
+    fn iter_nodes(&self) -> Result<Box<dyn Iterator<Item = &Node> + '_>, EngineError> {
+        Err(EngineError::NotImplemented("iter_nodes".into()))
+    }
+
+    /// Borrow every node carrying `label` in turn. Same fallback rule as `iter_nodes`.
+    fn iter_nodes_by_label(&self, _label: &str) -> Result<Box<dyn Iterator<Item = &Node> + '_>, EngineError> {
+        Err(EngineError::NotImplemented("iter_nodes_by_label".into()))
+    }
+
+    /// Call `f` on live node `id` in place; returns whether the node exists. Stores that
+    /// own their nodes override this to skip the clone the `get_node` default pays.
+    fn visit_node(&self, id: NodeId, f: &mut dyn FnMut(&Node)) -> Result<bool, EngineError> {
+        Ok(match self.get_node(id)? {
+            Some(node) => {
+                f(&node);
+                true
+            }
+            None => false,
+        })
+    }
+
+    /// Call `f` on every node carrying `label`, borrowed when the store supports
+    /// `iter_nodes_by_label` and cloned from `scan_by_label` otherwise.
+    fn for_each_node_with_label(&self, label: &str, f: &mut dyn FnMut(&Node)) -> Result<(), EngineError> {
+        match self.iter_nodes_by_label(label) {
+            Ok(nodes) => nodes.for_each(&mut *f),
+            Err(EngineError::NotImplemented(_)) => self.scan_by_label(label)?.iter().for_each(f),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Compute `f` over a borrowed node; `Ok(None)` when `id` is not a live node.
+    /// Built on `visit_node`, so unavailable through `dyn GraphReadStore`.
+    fn with_node<R>(&self, id: NodeId, f: impl FnOnce(&Node) -> R) -> Result<Option<R>, EngineError>
+    where
+        Self: Sized,
+    {
+        let mut f = Some(f);
+        let mut out = None;
+        self.visit_node(id, &mut |node| out = f.take().map(|f| f(node)))?;
+        Ok(out)
+    }
+
+    /// One page of at most `limit` nodes (optionally only those carrying `label`) in
+    /// ascending id order, starting after `cursor`. The returned cursor is `None` once the
+    /// scan is exhausted.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `limit` is 0.
+    fn scan_page(&self, label: Option<&str>, cursor: Option<ScanCursor>, limit: usize) -> Result<(Vec<Node>, Option<ScanCursor>), EngineError> {
+        if limit == 0 {
+            return Err(EngineError::InvalidArgument("scan_page: limit must be positive".into()));
+        }
+        let after = cursor.map(|c| c.last_id());
+        let mut nodes: Vec<Node> = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        nodes.retain(|n| after.is_none_or(|last| n.id > last));
+        nodes.sort_unstable_by_key(|n| n.id);
+        let more = nodes.len() > limit;
+        nodes.truncate(limit);
+        let next = if more { nodes.last().map(|n| ScanCursor::after(n.id)) } else { None };
+        Ok((nodes, next))
+    }
+
+    /// Nodes carrying all (or any) of `labels`, each listed once.
+    ///
+    /// An empty label set matches every node with `All` and none with `Any`. The default
+    /// filters a full scan.
+    fn scan_by_labels(&self, labels: &[&str], mode: LabelMatch) -> Result<Vec<Node>, EngineError> {
+        let has = |n: &Node, label: &&str| n.labels.iter().any(|l| l == label);
+        Ok(self.scan_all()?
+            .into_iter()
+            .filter(|n| match mode {
+                LabelMatch::All => labels.iter().all(|l| has(n, l)),
+                LabelMatch::Any => labels.iter().any(|l| has(n, l)),
+            })
+            .collect())
+    }
+
+    /// Ids (ascending) of `label` nodes that have a `key` property. An explicit
+    /// `Value::Null` counts as present; use `scan_null_property` to single those out.
+    fn scan_has_property(&self, label: &str, key: &str) -> Result<Vec<NodeId>, EngineError> {
+        self.scan_property_state(label, key, &|v| v.is_some())
+    }
+
+    /// Ids (ascending) of `label` nodes without a `key` property. Nodes holding an explicit
+    /// `Value::Null` are not missing it.
+    fn scan_missing_property(&self, label: &str, key: &str) -> Result<Vec<NodeId>, EngineError> {
+        self.scan_property_state(label, key, &|v| v.is_none())
+    }
+
+    /// Ids (ascending) of `label` nodes whose `key` property is present but `Value::Null`.
+    fn scan_null_property(&self, label: &str, key: &str) -> Result<Vec<NodeId>, EngineError> {
+        self.scan_property_state(label, key, &|v| matches!(v, Some(Value::Null)))
+    }
+
+    /// Ids (ascending) of `label` nodes for which `test` accepts the `key` property
+    /// (`None` when absent). Backs the presence scans above.
+    fn scan_property_state(&self, label: &str, key: &str, test: &dyn Fn(Option<&Value>) -> bool) -> Result<Vec<NodeId>, EngineError> {
+        let mut ids: Vec<NodeId> = self.scan_by_label(label)?
+            .into_iter()
+            .filter(|n| test(n.properties.get(key)))
+            .map(|n| n.id)
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Nodes (optionally restricted to `label`) for which `pred` holds. The predicate sees
+    /// each candidate by reference; stores that can borrow only clone the matches.
+    fn scan_filter(&self, label: Option<&str>, pred: &dyn Fn(&Node) -> bool) -> Result<Vec<Node>, EngineError> {
+        self.scan_filter_limit(label, pred, usize::MAX)
+    }
+
+    /// Like `scan_filter`, but stops after the first `limit` matches (in scan order).
+    fn scan_filter_limit(&self, label: Option<&str>, pred: &dyn Fn(&Node) -> bool, limit: usize) -> Result<Vec<Node>, EngineError> {
+        let candidates = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        Ok(candidates.into_iter().filter(|n| pred(n)).take(limit).collect())
+    }
+
+    /// Nodes (optionally restricted to `label`) whose numeric `key` property lies between
+    /// `min` and `max`, with the comparison rules of `NumericRange`: `Int` and `Float`
+    /// compare by value, and nodes missing the key or holding a non-numeric value never
+    /// match. The default filters a label or full scan.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if a bound is not numeric.
+    fn scan_by_property_range(&self, label: Option<&str>, key: &str, min: Option<Value>, max: Option<Value>, inclusive: bool) -> Result<Vec<Node>, EngineError> {
+        let range = NumericRange::new(min, max, inclusive)?;
+        let candidates = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        Ok(candidates.into_iter().filter(|n| n.properties.get(key).is_some_and(|v| range.contains(v))).collect())
+    }
+
+    /// Number of live nodes. The default counts a full scan; stores should override it.
+    fn node_count(&self) -> Result<usize, EngineError> {
+        Ok(self.scan_all()?.len())
+    }
+
+    /// Number of live edges. The default counts `scan_all_edges`.
+    fn edge_count(&self) -> Result<usize, EngineError> {
+        Ok(self.scan_all_edges()?.len())
+    }
+
+    /// Number of nodes carrying `label`.
+    fn count_by_label(&self, label: &str) -> Result<usize, EngineError> {
+        Ok(self.scan_by_label(label)?.len())
+    }
+
+    /// Number of edges of type `edge_type`.
+    fn count_edges_by_type(&self, edge_type: &str) -> Result<usize, EngineError> {
+        Ok(self.scan_edges_by_type(edge_type)?.len())
+    }
+
+    /// Labels of a node, as recorded on the node itself.
+    ///
+    /// # Errors
+    /// `NotFound` if the node does not exist.
+    fn labels_of(&self, id: NodeId) -> Result<Vec<String>, EngineError> {
+        self.get_node(id)?
+            .map(|n| n.labels)
+            .ok_or_else(|| EngineError::NotFound(format!("node {}", id)))
+    }
+
+    /// Sorted property keys of a node, or `None` if it does not exist.
+    fn node_property_keys(&self, id: NodeId) -> Result<Option<Vec<String>>, EngineError> {
+        Ok(self.get_node(id)?.map(|n| {
+            let mut keys: Vec<String> = n.properties.into_keys().collect();
+            keys.sort();
+            keys
+        }))
+    }
+
+    /// How many nodes (optionally only those carrying `label`) have each property key.
+    fn property_key_stats(&self, label: Option<&str>) -> Result<HashMap<String, usize>, EngineError> {
+        let nodes = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        let mut stats: HashMap<String, usize> = HashMap::new();
+        for node in nodes {
+            for key in node.properties.into_keys() {
+                *stats.entry(key).or_default() += 1;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Up to `n` nodes (optionally only those carrying `label`) drawn uniformly without
+    /// replacement, in ascending id order. Candidates are visited in id order, so a given
+    /// `seed` reproduces the same sample on the same data; asking for more nodes than
+    /// exist returns them all.
+    fn sample_nodes(&self, n: usize, label: Option<&str>, seed: Option<u64>) -> Result<Vec<Node>, EngineError> {
+        let mut candidates = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        candidates.sort_unstable_by_key(|n| n.id);
+        let mut sample = sample_reservoir(candidates, n, seed);
+        sample.sort_unstable_by_key(|n| n.id);
+        Ok(sample)
+    }
+
+    /// Up to `n` edges (optionally only of `edge_type`), sampled like `sample_nodes`.
+    fn sample_edges(&self, n: usize, edge_type: Option<&str>, seed: Option<u64>) -> Result<Vec<Edge>, EngineError> {
+        let mut candidates = match edge_type {
+            Some(edge_type) => self.scan_edges_by_type(edge_type)?,
+            None => self.scan_all_edges()?,
+        };
+        candidates.sort_unstable_by_key(|e| e.id);
+        let mut sample = sample_reservoir(candidates, n, seed);
+        sample.sort_unstable_by_key(|e| e.id);
+        Ok(sample)
+    }
+
+    /// The `n` nodes (optionally only those carrying `label`) with the highest `key`
+    /// property, or the lowest with `ascending`, best first. Only numeric values rank
+    /// (`Int` and `Float` compare by value, see `Value::cmp_numeric`); other nodes are
+    /// skipped. Ties go to the lower id.
+    fn top_by_property(&self, label: Option<&str>, key: &str, n: usize, ascending: bool) -> Result<Vec<Node>, EngineError> {
+        let candidates = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        let keyed = candidates.into_iter().map(|node| (node.id, node.properties.get(key).cloned(), node));
+        Ok(top_n_by_value(keyed, n, ascending))
+    }
+
+    /// Ids (ascending) of nodes with no edges at all.
+    fn find_orphans(&self) -> Result<Vec<NodeId>, EngineError> {
+        self.nodes_without_edges(Direction::Both, None)
+    }
+
+    /// Ids (ascending) of nodes with no incoming edge (of `edge_type`, when given). Nodes
+    /// that only have edges of other types, or none, count.
+    fn find_sources(&self, edge_type: Option<&str>) -> Result<Vec<NodeId>, EngineError> {
+        self.nodes_without_edges(Direction::Incoming, edge_type)
+    }
+
+    /// Ids (ascending) of nodes with no outgoing edge (of `edge_type`, when given).
+    fn find_sinks(&self, edge_type: Option<&str>) -> Result<Vec<NodeId>, EngineError> {
+        self.nodes_without_edges(Direction::Outgoing, edge_type)
+    }
+
+    /// Ids (ascending) of nodes whose `degree` in `direction` is zero; backs
+    /// `find_orphans`, `find_sources` and `find_sinks`.
+    fn nodes_without_edges(&self, direction: Direction, edge_type: Option<&str>) -> Result<Vec<NodeId>, EngineError> {
+        let mut ids = Vec::new();
+        for node in self.scan_all()? {
+            if self.degree(node.id, direction, edge_type)? == 0 {
+                ids.push(node.id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// Every distinct label with the number of nodes carrying it, most common first (ties
+    /// by name). The default counts a full scan.
+    fn labels(&self) -> Result<Vec<(String, usize)>, EngineError> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for node in self.scan_all()? {
+            for label in node.labels {
+                *counts.entry(label).or_default() += 1;
+            }
+        }
+        Ok(sorted_counts(counts))
+    }
+
+    /// Every distinct edge type with the number of edges of that type, ordered like `labels`.
+    fn edge_types(&self) -> Result<Vec<(String, usize)>, EngineError> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for edge in self.scan_all_edges()? {
+            *counts.entry(edge.edge_type).or_default() += 1;
+        }
+        Ok(sorted_counts(counts))
+    }
+
+    /// Nodes (optionally restricted to `label`) whose `key` property equals `value`.
+    /// Values of different variants never match. The default filters a label or full scan.
+    fn scan_by_property(&self, label: Option<&str>, key: &str, value: &Value) -> Result<Vec<Node>, EngineError> {
+        let candidates = match label {
+            Some(label) => self.scan_by_label(label)?,
+            None => self.scan_all()?,
+        };
+        Ok(candidates.into_iter().filter(|n| n.properties.get(key) == Some(value)).collect())
+    }
+
+    /// A fewest-hops path from `from` to `to` along `direction` (optionally only over
+    /// `edge_type` edges), found by breadth-first search over `get_neighbor_edge_ids`.
+    ///
+    /// `from == to` yields the zero-hop path `[from]`. Returns `Ok(None)` when `to` cannot
+    /// be reached within `max_depth` hops (unbounded when `None`).
+    ///
+    /// # Errors
+    /// `NotFound` if either node does not exist.
+    fn shortest_path(&self, from: NodeId, to: NodeId, edge_type: Option<&str>, direction: Direction, max_depth: Option<usize>) -> Result<Option<GraphPath>, EngineError> {
+        for id in [from, to] {
+            if !self.node_exists(id)? {
+                return Err(EngineError::NotFound(format!("node {}", id)));
+            }
+        }
+        // node -> (previous node, edge taken), filled as nodes are discovered
+        let mut parent: HashMap<NodeId, (NodeId, EdgeId)> = HashMap::new();
+        let mut frontier = vec![from];
+        let mut depth = 0;
+        while from != to && !frontier.is_empty() && max_depth.is_none_or(|max| depth < max) {
+            depth += 1;
+            let mut next = Vec::new();
+            for id in frontier {
+                for (edge, other) in self.get_neighbor_edge_ids(id, edge_type, direction)? {
+                    if other == from || parent.contains_key(&other) {
+                        continue;
+                    }
+                    parent.insert(other, (id, edge));
+                    if other == to {
+                        next.clear();
+                        break;
+                    }
+                    next.push(other);
+                }
+                if parent.contains_key(&to) {
+                    break;
+                }
+            }
+            frontier = next;
+        }
+        if from != to && !parent.contains_key(&to) {
+            return Ok(None);
+        }
+        let mut path = GraphPath { nodes: vec![to], edges: Vec::new() };
+        let mut at = to;
+        while let Some((prev, edge)) = parent.get(&at) {
+            path.nodes.push(*prev);
+            path.edges.push(*edge);
+            at = *prev;
+        }
+        path.nodes.reverse();
+        path.edges.reverse();
+        Ok(Some(path))
+    }
+
+    /// Whether `to` can be reached from `from` along outgoing edges (optionally only
+    /// `edge_type` edges) in at most `max_depth` hops. `from == to` is always reachable.
+    ///
+    /// Runs a bidirectional breadth-first search over ids (forward from `from`, backward
+    /// from `to`, always growing the smaller frontier) and stops as soon as they meet.
+    ///
+    /// # Errors
+    /// `NotFound` if either node does not exist.
+    fn is_reachable(&self, from: NodeId, to: NodeId, edge_type: Option<&str>, max_depth: Option<usize>) -> Result<bool, EngineError> {
+        for id in [from, to] {
+            if !self.node_exists(id)? {
+                return Err(EngineError::NotFound(format!("node {}", id)));
+            }
+        }
+        if from == to {
+            return Ok(true);
+        }
+        let mut ahead = (vec![from], std::collections::HashSet::from([from]), Direction::Outgoing);
+        let mut behind = (vec![to], std::collections::HashSet::from([to]), Direction::Incoming);
+        let mut depth = 0;
+        while !ahead.0.is_empty() && !behind.0.is_empty() && max_depth.is_none_or(|max| depth < max) {
+            let (grow, other) = if ahead.0.len() <= behind.0.len() { (&mut ahead, &behind) } else { (&mut behind, &ahead) };
+            let mut next = Vec::new();
+            for id in std::mem::take(&mut grow.0) {
+                for n in self.get_neighbor_ids(id, edge_type, grow.2)? {
+                    if other.1.contains(&n) {
+                        return Ok(true);
+                    }
+                    if grow.1.insert(n) {
+                        next.push(n);
+                    }
+                }
+            }
+            grow.0 = next;
+            depth += 1;
+        }
+        Ok(false)
+    }
+
+    /// Incident edges of a live node counted per `(edge_type, direction)`, with direction
+    /// `Outgoing` or `Incoming`. A self-loop is counted under both directions.
+    ///
+    /// # Errors
+    /// `NotFound` if the node does not exist.
+    fn neighbor_summary(&self, node_id: NodeId) -> Result<HashMap<(String, Direction), usize>, EngineError> {
+        if !self.node_exists(node_id)? {
+            return Err(EngineError::NotFound(format!("node {}", node_id)));
+        }
+        let mut summary: HashMap<(String, Direction), usize> = HashMap::new();
+        for (edge, _) in self.get_neighbors(node_id, None)? {
+            *summary.entry((edge.edge_type, Direction::Outgoing)).or_default() += 1;
+        }
+        for (edge, _) in self.get_neighbors_incoming(node_id, None)? {
+            *summary.entry((edge.edge_type, Direction::Incoming)).or_default() += 1;
+        }
+        Ok(summary)
+    }
+
+    /// Ids of the nodes at the other end of `node_id`'s edges in `direction` (optionally
+    /// only `edge_type` edges), one entry per edge; with `Both` a self-loop counts once.
+    /// The default goes through `get_neighbor_edge_ids`.
+    fn get_neighbor_ids(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction) -> Result<Vec<NodeId>, EngineError> {
+        Ok(self.get_neighbor_edge_ids(node_id, edge_type, direction)?.into_iter().map(|(_, n)| n).collect())
+    }
+
+    /// `(edge, neighbor)` id pairs with the same rules as `get_neighbor_ids`. The default
+    /// goes through the cloning neighbor lists; stores should override it.
+    fn get_neighbor_edge_ids(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction) -> Result<Vec<(EdgeId, NodeId)>, EngineError> {
+        let mut ids = Vec::new();
+        if direction != Direction::Incoming {
+            ids.extend(self.get_neighbors(node_id, edge_type)?.into_iter().map(|(e, n)| (e.id, n.id)));
+        }
+        if direction != Direction::Outgoing {
+            for (e, n) in self.get_neighbors_incoming(node_id, edge_type)? {
+                if !(direction == Direction::Both && e.from_node == node_id) {
+                    ids.push((e.id, n.id));
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Neighbors of `node_id` in `direction` reached through edges (optionally of
+    /// `edge_type`) accepted by `edge_pred`; with `Both` a self-loop is listed once. Stores
+    /// should test the predicate on the borrowed edge so rejected neighbors are never
+    /// cloned; the default filters the cloning neighbor lists.
+    fn get_neighbors_where(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction, edge_pred: &dyn Fn(&Edge) -> bool) -> Result<Vec<(Edge, Node)>, EngineError> {
+        let mut result = Vec::new();
+        if direction != Direction::Incoming {
+            result.extend(self.get_neighbors(node_id, edge_type)?.into_iter().filter(|(e, _)| edge_pred(e)));
+        }
+        if direction != Direction::Outgoing {
+            let self_loop_seen = |e: &Edge| direction == Direction::Both && e.from_node == node_id;
+            result.extend(self.get_neighbors_incoming(node_id, edge_type)?
+                .into_iter()
+                .filter(|(e, _)| !self_loop_seen(e) && edge_pred(e)));
+        }
+        Ok(result)
+    }
+
+    /// Every edge incident to `node_id` with the node at its other end, each edge listed
+    /// once and tagged `Direction::Outgoing` or `Direction::Incoming` depending on which
+    /// side of it `node_id` is. Self-loops are listed once, as `Outgoing`.
+    fn get_neighbors_undirected(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node, Direction)>, EngineError> {
+        let mut result: Vec<(Edge, Node, Direction)> = self.get_neighbors(node_id, edge_type)?
+            .into_iter()
+            .map(|(edge, node)| (edge, node, Direction::Outgoing))
+            .collect();
+        for (edge, node) in self.get_neighbors_incoming(node_id, edge_type)? {
+            // A self-loop is already listed from the outgoing side
+            if edge.from_node != node_id {
+                result.push((edge, node, Direction::Incoming));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Every node reachable from `start` in at most `max_depth` hops along `direction`
+    /// (optionally only over `edge_type` edges), mapped to its minimum hop distance.
+    /// `start` itself is included at distance 0, so `max_depth == 0` yields just `start`.
+    ///
+    /// # Errors
+    /// `NotFound` if `start` does not exist.
+    fn neighborhood(&self, start: NodeId, max_depth: usize, edge_type: Option<&str>, direction: Direction) -> Result<HashMap<NodeId, usize>, EngineError> {
+        if self.get_node(start)?.is_none() {
+            return Err(EngineError::NotFound(format!("node {}", start)));
+        }
+        let mut dist = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            let depth = dist[&id];
+            if depth == max_depth {
+                continue;
+            }
+            let mut next: Vec<NodeId> = Vec::new();
+            if direction != Direction::Incoming {
+                next.extend(self.get_neighbors(id, edge_type)?.into_iter().map(|(_, n)| n.id));
+            }
+            if direction != Direction::Outgoing {
+                next.extend(self.get_neighbors_incoming(id, edge_type)?.into_iter().map(|(_, n)| n.id));
+            }
+            for n in next {
+                if let std::collections::hash_map::Entry::Vacant(slot) = dist.entry(n) {
+                    slot.insert(depth + 1);
+                    queue.push_back(n);
+                }
+            }
+        }
+        Ok(dist)
+    }
+
+    /// Look an edge up by id.
+    fn get_edge(&self, _id: EdgeId) -> Result<Option<Edge>, EngineError> {
+        Err(EngineError::NotImplemented("get_edge".into()))
+    }
+
+    /// Every edge `from -> to` (optionally of `edge_type`), parallel edges included.
+    /// The default filters the outgoing neighbors of `from`.
+    fn get_edges_between(&self, from: NodeId, to: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>, EngineError> {
+        Ok(self.get_neighbors(from, edge_type)?
+            .into_iter()
+            .filter(|(edge, _)| edge.to_node == to)
+            .map(|(edge, _)| edge)
+            .collect())
+    }
+
+    /// Number of edges incident to a live node in `direction`, optionally of one type.
+    ///
+    /// With `Direction::Both` every incident edge counts once, so a self-loop adds 1 (not
+    /// 2) and `Both` can be less than `Outgoing + Incoming`. The default goes through the
+    /// neighbor lists.
+    ///
+    /// # Errors
+    /// `NotFound` if the node does not exist.
+    fn degree(&self, node_id: NodeId, direction: Direction, edge_type: Option<&str>) -> Result<usize, EngineError> {
+        if self.get_node(node_id)?.is_none() {
+            return Err(EngineError::NotFound(format!("node {}", node_id)));
+        }
+        let outgoing = || self.get_neighbors(node_id, edge_type);
+        Ok(match direction {
+            Direction::Outgoing => outgoing()?.len(),
+            Direction::Incoming => self.get_neighbors_incoming(node_id, edge_type)?.len(),
+            Direction::Both => {
+                let out = outgoing()?;
+                let loops = out.iter().filter(|(e, _)| e.to_node == node_id).count();
+                out.len() + self.get_neighbors_incoming(node_id, edge_type)?.len() - loops
+            }
+        })
+    }
+
+    /// Whether a live node with `id` exists. The default goes through `get_node`.
+    fn node_exists(&self, id: NodeId) -> Result<bool, EngineError> {
+        Ok(self.get_node(id)?.is_some())
+    }
+
+    /// Whether at least one edge `from -> to` (optionally of `edge_type`) exists. The default
+    /// goes through `get_edges_between`.
+    fn has_edge(&self, from: NodeId, to: NodeId, edge_type: Option<&str>) -> Result<bool, EngineError> {
+        Ok(!self.get_edges_between(from, to, edge_type)?.is_empty())
+    }
+
+    /// Look several nodes up at once. `result[i]` is the node for `ids[i]`, or `None` if it
+    /// does not exist.
+    fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Option<Node>>, EngineError> {
+        ids.iter().map(|id| self.get_node(*id)).collect()
+    }
+
+    /// Look several edges up at once, in the same shape as `get_nodes`.
+    fn get_edges(&self, ids: &[EdgeId]) -> Result<Vec<Option<Edge>>, EngineError> {
+        ids.iter().map(|id| self.get_edge(*id)).collect()
+    }
+
+    /// Every edge in the store, in no particular order.
+    fn scan_all_edges(&self) -> Result<Vec<Edge>, EngineError> {
+        Err(EngineError::NotImplemented("scan_all_edges".into()))
+    }
+
+    /// Every edge of type `edge_type`. The default filters `scan_all_edges`.
+    fn scan_edges_by_type(&self, edge_type: &str) -> Result<Vec<Edge>, EngineError> {
+        Ok(self.scan_all_edges()?.into_iter().filter(|e| e.edge_type == edge_type).collect())
+    }
+
+    /// Edges of type `edge_type` whose `key` property equals `value`. The default filters
+    /// `scan_edges_by_type`; stores with edge property indexes answer from them.
+    fn scan_edges_by_property(&self, edge_type: &str, key: &str, value: &Value) -> Result<Vec<Edge>, EngineError> {
+        Ok(self.scan_edges_by_type(edge_type)?
+            .into_iter()
+            .filter(|e| e.properties.get(key) == Some(value))
+            .collect())
+    }
+}
+
+/// Uniformly sample up to `n` items of `items` (reservoir sampling, O(n) memory). The same
+/// `seed` over the same sequence always picks the same items; without one the seed comes
+/// from the clock. Fewer than `n` items are all returned.
+pub fn sample_reservoir<T>(items: impl IntoIterator<Item = T>, n: usize, seed: Option<u64>) -> Vec<T> {
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    });
+    // SplitMix64: tiny, seedable, good enough for sampling
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let mut reservoir = Vec::with_capacity(n.min(1024));
+    if n == 0 {
+        return reservoir;
+    }
+    for (seen, item) in items.into_iter().enumerate() {
+        if seen < n {
+            reservoir.push(item);
+        } else {
+            let slot = (next() % (seen as u64 + 1)) as usize;
+            if slot < n {
+                reservoir[slot] = item;
+            }
+        }
+    }
+    reservoir
+}
+
+/// The `n` items with the highest (or, with `ascending`, lowest) numeric key, best first,
+/// ties broken by lower id. Items whose key is `None`, non-numeric or NaN are skipped.
+/// Keeps a bounded heap, so memory is O(n) whatever the input size.
+pub fn top_n_by_value<T>(items: impl IntoIterator<Item = (NodeId, Option<Value>, T)>, n: usize, ascending: bool) -> Vec<T> {
+    /// Ordered so that the worst kept entry is the heap's maximum.
+    struct Ranked<T> {
+        value: Value,
+        id: NodeId,
+        ascending: bool,
+        item: T,
+    }
+    impl<T> Ranked<T> {
+        fn rank(&self, other: &Self) -> std::cmp::Ordering {
+            let by_value = self.value.cmp_numeric(&other.value).unwrap_or(std::cmp::Ordering::Equal);
+            let by_value = if self.ascending { by_value } else { by_value.reverse() };
+            by_value.then(self.id.cmp(&other.id))
+        }
+    }
+    impl<T> PartialEq for Ranked<T> {
+        fn eq(&self, other: &Self) -> bool {
+            self.rank(other).is_eq()
+        }
+    }
+    impl<T> Eq for Ranked<T> {}
+    impl<T> PartialOrd for Ranked<T> {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+    impl<T> Ord for Ranked<T> {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.rank(other)
+        }
+    }
+
+    let mut heap = std::collections::BinaryHeap::with_capacity(n.min(1024) + 1);
+    if n == 0 {
+        return Vec::new();
+    }
+    for (id, value, item) in items {
+        let Some(value) = value.filter(|v| v.cmp_numeric(v).is_some()) else { continue };
+        let entry = Ranked { value, id, ascending, item };
+        if heap.len() < n {
+            heap.push(entry);
+        } else if heap.peek().is_some_and(|worst| entry < *worst) {
+            heap.pop();
+            heap.push(entry);
+        }
+    }
+    heap.into_sorted_vec().into_iter().map(|r| r.item).collect()
+}
+
+/// Order `(name, count)` pairs by count descending, then name, as returned by
+/// `GraphReadStore::labels` and `edge_types`.
+pub fn sorted_counts(counts: impl IntoIterator<Item = (String, usize)>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
 }
 
 /// Write-capable storage interface (extends read)
 pub trait GraphWriteStore: GraphReadStore {
     fn add_node(&mut self, labels: Vec<String>, properties: HashMap<String, Value>) -> Result<NodeId, EngineError>;
     fn add_edge(&mut self, from: NodeId, to: NodeId, edge_type: String, properties: HashMap<String, Value>) -> Result<EdgeId, EngineError>;
+
+    /// Remove an edge by id. Returns `Ok(false)` when the edge does not exist.
+    fn delete_edge(&mut self, _id: EdgeId) -> Result<bool, EngineError> {
+        Err(EngineError::NotImplemented("delete_edge".into()))
+    }
+
+    /// Remove a node by id. Returns `Ok(false)` when the node does not exist.
+    ///
+    /// With `detach` the node's incident edges are removed too; without it a node that
+    /// still has edges is rejected.
+    fn delete_node(&mut self, _id: NodeId, _detach: bool) -> Result<bool, EngineError> {
+        Err(EngineError::NotImplemented("delete_node".into()))
+    }
+
+    /// Set (insert or overwrite) a single property on an existing node.
+    fn set_node_property(&mut self, _id: NodeId, _key: String, _value: Value) -> Result<(), EngineError> {
+        Err(EngineError::NotImplemented("set_node_property".into()))
+    }
+
+    /// Remove a single property from an existing node, returning its previous value.
+    fn remove_node_property(&mut self, _id: NodeId, _key: &str) -> Result<Option<Value>, EngineError> {
+        Err(EngineError::NotImplemented("remove_node_property".into()))
+    }
+
+    /// Merge `props` into the property map of an existing node (incoming values win).
+    fn update_node_properties(&mut self, _id: NodeId, _props: HashMap<String, Value>) -> Result<(), EngineError> {
+        Err(EngineError::NotImplemented("update_node_properties".into()))
+    }
+
+    /// Attach a label to an existing node. Returns `Ok(false)` if the node already carries it.
+    fn add_label(&mut self, _id: NodeId, _label: String) -> Result<bool, EngineError> {
+        Err(EngineError::NotImplemented("add_label".into()))
+    }
+
+    /// Detach a label from an existing node. Returns `Ok(false)` if the node did not carry it.
+    fn remove_label(&mut self, _id: NodeId, _label: &str) -> Result<bool, EngineError> {
+        Err(EngineError::NotImplemented("remove_label".into()))
+    }
+
+    /// Merge `props` into the property map of an existing edge (incoming values win).
+    fn update_edge_properties(&mut self, _id: EdgeId, _props: HashMap<String, Value>) -> Result<(), EngineError> {
+        Err(EngineError::NotImplemented("update_edge_properties".into()))
+    }
+
+    /// Change the type of an existing edge; typed traversals see the new type immediately.
+    fn set_edge_type(&mut self, _id: EdgeId, _new_type: String) -> Result<(), EngineError> {
+        Err(EngineError::NotImplemented("set_edge_type".into()))
+    }
+
+    /// Insert many nodes at once, returning the assigned ids in input order.
+    fn add_nodes_bulk(&mut self, nodes: Vec<(Vec<String>, HashMap<String, Value>)>) -> Result<Vec<NodeId>, EngineError> {
+        nodes.into_iter()
+            .map(|(labels, properties)| self.add_node(labels, properties))
+            .collect()
+    }
+
+    /// Insert many edges at once, returning the assigned ids in input order.
+    /// Implementations should validate every endpoint before inserting anything.
+    fn add_edges_bulk(&mut self, edges: Vec<(NodeId, NodeId, String, HashMap<String, Value>)>) -> Result<Vec<EdgeId>, EngineError> {
+        for (from, to, _, _) in &edges {
+            for id in [*from, *to] {
+                if self.get_node(id)?.is_none() {
+                    return Err(EngineError::NodeNotFound(id));
+                }
+            }
+        }
+        edges.into_iter()
+            .map(|(from, to, edge_type, properties)| self.add_edge(from, to, edge_type, properties))
+            .collect()
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Value {
     Null,
     Bool(bool),
@@ -57,6 +917,185 @@ pub enum Value {
     NodeId(NodeId),
 }
 
+/// Structural equality with float handling suited to property comparisons:
+/// `NaN` equals `NaN` (so a stored NaN can be matched) and `0.0` equals `-0.0`.
+/// Values of different variants are never equal (`Int(1) != Float(1.0)`).
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Map(a), Value::Map(b)) => a == b,
+            (Value::NodeId(a), Value::NodeId(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    /// Numeric ordering used by range filters. `Int` and `Float` compare with each other
+    /// (the int is widened to `f64`); anything non-numeric, and `NaN`, is unordered.
+    pub fn cmp_numeric(&self, other: &Value) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+/// Hashable projection of a `Value`, used as a property index key.
+///
+/// Two values project to the same key exactly when they are `==`: floats are keyed by
+/// their bits with `-0.0` folded into `0.0` and every NaN into one canonical NaN.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ValueKey {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(u64),
+    String(String),
+    Bytes(Vec<u8>),
+    Array(Vec<ValueKey>),
+    Map(std::collections::BTreeMap<String, ValueKey>),
+    NodeId(NodeId),
+}
+
+impl From<&Value> for ValueKey {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => ValueKey::Null,
+            Value::Bool(b) => ValueKey::Bool(*b),
+            Value::Int(i) => ValueKey::Int(*i),
+            Value::Float(f) if f.is_nan() => ValueKey::Float(f64::NAN.to_bits()),
+            Value::Float(f) if *f == 0.0 => ValueKey::Float(0.0f64.to_bits()),
+            Value::Float(f) => ValueKey::Float(f.to_bits()),
+            Value::String(s) => ValueKey::String(s.clone()),
+            Value::Bytes(b) => ValueKey::Bytes(b.clone()),
+            Value::Array(items) => ValueKey::Array(items.iter().map(ValueKey::from).collect()),
+            Value::Map(map) => ValueKey::Map(map.iter().map(|(k, v)| (k.clone(), ValueKey::from(v))).collect()),
+            Value::NodeId(id) => ValueKey::NodeId(*id),
+        }
+    }
+}
+
+/// Totally ordered projection of an `Int`, `Float` or `String` value, used as a range
+/// index key.
+///
+/// Numbers sort before strings. `Int` and `Float` share one numeric order (the int is
+/// widened to `f64`, as in `Value::cmp_numeric`), with `-0.0` equal to `0.0`; NaN and the
+/// other variants have no projection.
+#[derive(Debug, Clone)]
+pub enum OrderedValue {
+    Number(f64),
+    String(String),
+}
+
+impl OrderedValue {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(OrderedValue::Number(*i as f64)),
+            Value::Float(f) if f.is_nan() => None,
+            Value::Float(f) => Some(OrderedValue::Number(if *f == 0.0 { 0.0 } else { *f })),
+            Value::String(s) => Some(OrderedValue::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for OrderedValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for OrderedValue {}
+
+impl PartialOrd for OrderedValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrderedValue {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+        match (self, other) {
+            (OrderedValue::Number(a), OrderedValue::Number(b)) => a.total_cmp(b),
+            (OrderedValue::String(a), OrderedValue::String(b)) => a.cmp(b),
+            (OrderedValue::Number(_), OrderedValue::String(_)) => Ordering::Less,
+            (OrderedValue::String(_), OrderedValue::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl std::hash::Hash for OrderedValue {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            OrderedValue::Number(n) => (0u8, n.to_bits()).hash(state),
+            OrderedValue::String(s) => (1u8, s).hash(state),
+        }
+    }
+}
+
+/// Bounds of a numeric property range scan; see `GraphReadStore::scan_by_property_range`.
+#[derive(Debug, Clone)]
+pub struct NumericRange {
+    min: Option<Value>,
+    max: Option<Value>,
+    inclusive: bool,
+}
+
+impl NumericRange {
+    /// A range between optional `min` and `max`; `inclusive` applies to both ends.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if a bound is not an `Int` or a non-NaN `Float`.
+    pub fn new(min: Option<Value>, max: Option<Value>, inclusive: bool) -> Result<Self, EngineError> {
+        for bound in min.iter().chain(max.iter()) {
+            if bound.cmp_numeric(bound).is_none() {
+                return Err(EngineError::InvalidArgument(format!("range bound must be numeric, got {:?}", bound)));
+            }
+        }
+        Ok(Self { min, max, inclusive })
+    }
+
+    pub fn min(&self) -> Option<&Value> {
+        self.min.as_ref()
+    }
+
+    pub fn max(&self) -> Option<&Value> {
+        self.max.as_ref()
+    }
+
+    pub fn inclusive(&self) -> bool {
+        self.inclusive
+    }
+
+    /// Whether `value` lies in the range. Non-numeric values never do.
+    pub fn contains(&self, value: &Value) -> bool {
+        use std::cmp::Ordering::{Greater, Less};
+        let above_min = self.min.as_ref().is_none_or(|min| match value.cmp_numeric(min) {
+            Some(Greater) => true,
+            Some(Less) | None => false,
+            Some(_) => self.inclusive,
+        });
+        let below_max = self.max.as_ref().is_none_or(|max| match value.cmp_numeric(max) {
+            Some(Less) => true,
+            Some(Greater) | None => false,
+            Some(_) => self.inclusive,
+        });
+        above_min && below_max && value.cmp_numeric(value).is_some()
+    }
+}
+
 // -----------------------
 // Granular Storage Ports (optional for adapters)
 // -----------------------
@@ -94,6 +1133,51 @@ pub trait ManifestStore: Send + Sync + 'static {
 pub trait SegmentStore: Send + Sync + 'static {
     fn write_segment(&self, root: &Path, db: &DatabaseName, segment_id: &SegmentId, data: &[u8], node_count: u64, edge_count: u64) -> Result<(), EngineError>;
     fn read_segment(&self, root: &Path, db: &DatabaseName, segment_id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError>;
+
+    /// Write a segment whose data `write` produces piece by piece. The default collects it
+    /// and calls `write_segment`; stores that can write it as it comes override this.
+    fn write_segment_with(
+        &self,
+        root: &Path,
+        db: &DatabaseName,
+        segment_id: &SegmentId,
+        node_count: u64,
+        edge_count: u64,
+        write: &mut dyn FnMut(&mut dyn std::io::Write) -> std::io::Result<()>,
+    ) -> Result<(), EngineError> {
+        let mut data = Vec::new();
+        write(&mut data).map_err(|e| EngineError::StorageIo(format!("write segment {}: {}", segment_id.0, e)))?;
+        self.write_segment(root, db, segment_id, &data, node_count, edge_count)
+    }
+
+    /// Write a segment as part of a group that `publish_segments` makes current at once;
+    /// until then reads return its previous version. The default writes it right away, so
+    /// a store without staging gives no all-or-nothing guarantee.
+    fn stage_segment_with(
+        &self,
+        root: &Path,
+        db: &DatabaseName,
+        segment_id: &SegmentId,
+        node_count: u64,
+        edge_count: u64,
+        write: &mut dyn FnMut(&mut dyn std::io::Write) -> std::io::Result<()>,
+    ) -> Result<(), EngineError> {
+        self.write_segment_with(root, db, segment_id, node_count, edge_count, write)
+    }
+
+    /// Make the segments staged with `stage_segment_with` current together. A failure or
+    /// crash before this returns leaves every one of them at its previous version.
+    fn publish_segments(&self, _root: &Path, _db: &DatabaseName, _segment_ids: &[SegmentId]) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    /// Open a segment to read its data as a stream, with its node and edge counts. The
+    /// default reads it whole with `read_segment`; a store may instead report a damaged
+    /// segment as a read error once its end is reached.
+    fn open_segment(&self, root: &Path, db: &DatabaseName, segment_id: &SegmentId) -> Result<(Box<dyn std::io::Read + '_>, u64, u64), EngineError> {
+        let (data, node_count, edge_count) = self.read_segment(root, db, segment_id)?;
+        Ok((Box::new(std::io::Cursor::new(data)), node_count, edge_count))
+    }
 }
 
 pub trait WalSink: Send + Sync + 'static {
@@ -210,6 +1294,19 @@ pub enum EngineError {
     Concurrency(String),
     #[error("not implemented: {0}")]
     NotImplemented(String),
+    #[error("type mismatch: {0}")]
+    TypeMismatch(String),
+    #[error("node not found: {0}")]
+    NodeNotFound(NodeId),
+    #[error("unique constraint on {label}.{key} violated by value {value:?}")]
+    UniqueViolation { label: String, key: String, value: Value },
+    /// A WAL frame starting at byte `offset` is cut short or fails its checksum
+    #[error("WAL corrupt at byte {offset}")]
+    WalCorruption { offset: u64 },
+    /// A segment fails its checksum or does not decode; `path` is its file, or its id on
+    /// stores that do not expose files
+    #[error("segment {path} corrupt: {detail}")]
+    SegmentCorruption { path: String, detail: String },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -17,6 +17,7 @@ fn test_node_creation() {
         id: 1,
         labels: vec!["Person".to_string(), "Employee".to_string()],
         properties: props,
+        deleted: false,
     };
 
     assert_eq!(node.id, 1);
@@ -31,6 +32,7 @@ fn test_node_clone() {
         id: 42,
         labels: vec!["Test".to_string()],
         properties: HashMap::new(),
+        deleted: false,
     };
 
     let cloned = node.clone();
@@ -44,6 +46,7 @@ fn test_node_debug() {
         id: 1,
         labels: vec![],
         properties: HashMap::new(),
+        deleted: false,
     };
 
     let debug_str = format!("{:?}", node);
@@ -51,6 +54,25 @@ fn test_node_debug() {
     assert!(debug_str.contains("id: 1"));
 }
 
+#[test]
+fn test_node_semantically_equals_ignores_label_order() {
+    let node = Node {
+        id: 1,
+        labels: vec!["Person".to_string(), "Admin".to_string()],
+        properties: HashMap::from([("score".to_string(), Value::Float(f64::NAN))]),
+        deleted: false,
+    };
+
+    let reordered = Node { labels: vec!["Admin".to_string(), "Person".to_string()], ..node.clone() };
+    assert!(node.semantically_equals(&reordered));
+    assert!(!node.semantically_equals(&Node { labels: vec!["Person".to_string()], ..node.clone() }));
+    assert!(!node.semantically_equals(&Node { deleted: true, ..node.clone() }));
+    assert!(!node.semantically_equals(&Node { id: 2, ..node.clone() }));
+    let mut changed = node.clone();
+    changed.properties.insert("score".to_string(), Value::Int(0));
+    assert!(!node.semantically_equals(&changed));
+}
+
 // =============================================================================
 // Edge struct tests
 // =============================================================================
@@ -66,6 +88,7 @@ fn test_edge_creation() {
         to_node: 2,
         edge_type: "KNOWS".to_string(),
         properties: props,
+        deleted: false,
     };
 
     assert_eq!(edge.id, 100);
@@ -83,6 +106,7 @@ fn test_edge_clone() {
         to_node: 20,
         edge_type: "LINKS".to_string(),
         properties: HashMap::new(),
+        deleted: false,
     };
 
     let cloned = edge.clone();
@@ -100,6 +124,7 @@ fn test_edge_debug() {
         to_node: 2,
         edge_type: "REL".to_string(),
         properties: HashMap::new(),
+        deleted: false,
     };
 
     let debug_str = format!("{:?}", edge);
@@ -108,6 +133,25 @@ fn test_edge_debug() {
     assert!(debug_str.contains("to_node: 2"));
 }
 
+#[test]
+fn test_edge_semantically_equals() {
+    let edge = Edge {
+        id: 1,
+        from_node: 1,
+        to_node: 2,
+        edge_type: "REL".to_string(),
+        properties: HashMap::from([("w".to_string(), Value::Float(0.0))]),
+        deleted: false,
+    };
+
+    let mut same = edge.clone();
+    same.properties.insert("w".to_string(), Value::Float(-0.0));
+    assert!(edge.semantically_equals(&same));
+    assert!(!edge.semantically_equals(&Edge { from_node: 2, to_node: 1, ..edge.clone() }));
+    assert!(!edge.semantically_equals(&Edge { edge_type: "OTHER".to_string(), ..edge.clone() }));
+    assert!(!edge.semantically_equals(&Edge { properties: HashMap::new(), ..edge.clone() }));
+}
+
 // =============================================================================
 // Mock implementation to test trait definitions
 // =============================================================================
@@ -186,14 +230,14 @@ impl GraphWriteStore for MockGraphStore {
     fn add_node(&mut self, labels: Vec<String>, properties: HashMap<String, Value>) -> Result<NodeId, EngineError> {
         let id = self.next_node_id;
         self.next_node_id += 1;
-        self.nodes.insert(id, Node { id, labels, properties });
+        self.nodes.insert(id, Node { id, labels, properties, deleted: false });
         Ok(id)
     }
 
     fn add_edge(&mut self, from: NodeId, to: NodeId, edge_type: String, properties: HashMap<String, Value>) -> Result<EdgeId, EngineError> {
         let id = self.next_edge_id;
         self.next_edge_id += 1;
-        self.edges.insert(id, Edge { id, from_node: from, to_node: to, edge_type, properties });
+        self.edges.insert(id, Edge { id, from_node: from, to_node: to, edge_type, properties, deleted: false });
         Ok(id)
     }
 }
@@ -0,0 +1,60 @@
+//! Tests for casys_core::Value equality semantics
+
+use casys_core::{OrderedValue, Value, ValueKey};
+use std::collections::BTreeMap;
+
+#[test]
+fn test_value_float_equality() {
+    assert_eq!(Value::Float(1.5), Value::Float(1.5));
+    assert_ne!(Value::Float(1.5), Value::Float(2.5));
+    assert_eq!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+    assert_eq!(Value::Float(0.0), Value::Float(-0.0));
+    assert_ne!(Value::Float(f64::NAN), Value::Float(0.0));
+}
+
+#[test]
+fn test_value_variants_are_distinct() {
+    assert_ne!(Value::Int(1), Value::Float(1.0));
+    assert_ne!(Value::Int(1), Value::NodeId(1));
+    assert_ne!(Value::Null, Value::Bool(false));
+    assert_ne!(Value::String("1".into()), Value::Int(1));
+}
+
+#[test]
+fn test_value_nested_equality() {
+    let a = Value::Array(vec![Value::Float(f64::NAN), Value::Int(2)]);
+    let b = Value::Array(vec![Value::Float(f64::NAN), Value::Int(2)]);
+    assert_eq!(a, b);
+
+    let mut m1 = BTreeMap::new();
+    m1.insert("x".to_string(), Value::Float(0.0));
+    let mut m2 = BTreeMap::new();
+    m2.insert("x".to_string(), Value::Float(-0.0));
+    assert_eq!(Value::Map(m1), Value::Map(m2));
+}
+
+#[test]
+fn test_value_key_agrees_with_equality() {
+    let key = |v: &Value| ValueKey::from(v);
+    assert_eq!(key(&Value::Float(0.0)), key(&Value::Float(-0.0)));
+    assert_eq!(key(&Value::Float(f64::NAN)), key(&Value::Float(-f64::NAN)));
+    assert_ne!(key(&Value::Int(1)), key(&Value::Float(1.0)));
+    assert_ne!(key(&Value::Int(1)), key(&Value::NodeId(1)));
+    assert_eq!(
+        key(&Value::Array(vec![Value::Float(-0.0), Value::String("a".into())])),
+        key(&Value::Array(vec![Value::Float(0.0), Value::String("a".into())])),
+    );
+}
+
+#[test]
+fn test_ordered_value_total_order() {
+    let key = |v: Value| OrderedValue::from_value(&v).unwrap();
+    assert!(key(Value::Int(3)) < key(Value::Float(3.5)));
+    assert!(key(Value::Float(3.5)) < key(Value::Int(4)));
+    assert_eq!(key(Value::Int(2)), key(Value::Float(2.0)));
+    assert_eq!(key(Value::Float(-0.0)), key(Value::Float(0.0)));
+    assert!(key(Value::Float(f64::INFINITY)) < key(Value::String(String::new())));
+    assert!(key(Value::String("a".into())) < key(Value::String("b".into())));
+    assert!(OrderedValue::from_value(&Value::Float(f64::NAN)).is_none());
+    assert!(OrderedValue::from_value(&Value::Bool(true)).is_none());
+}
@@ -197,6 +197,35 @@ impl Query {
 }
 
 impl Expr {
+    /// Collect the variables this expression reads (`x` and `x.prop` both count as `x`).
+    /// Returns false if it contains something whose inputs can't be determined statically
+    /// (aggregates, EXISTS subqueries).
+    pub fn collect_variables(&self, vars: &mut HashSet<String>) -> bool {
+        match self {
+            Expr::Ident(name) | Expr::Property(name, _) => {
+                vars.insert(name.clone());
+                true
+            }
+            Expr::Literal(_) | Expr::Parameter(_) => true,
+            Expr::BinaryOp(left, _, right) => left.collect_variables(vars) && right.collect_variables(vars),
+            Expr::UnaryOp(_, operand) | Expr::IsNull(operand) | Expr::IsNotNull(operand) => operand.collect_variables(vars),
+            Expr::FunctionCall(_, args) => args.iter().all(|a| a.collect_variables(vars)),
+            Expr::Aggregate(..) | Expr::Exists(_) => false,
+        }
+    }
+
+    /// Split a predicate on its top-level ANDs.
+    pub fn into_conjuncts(self) -> Vec<Expr> {
+        match self {
+            Expr::BinaryOp(left, BinOp::And, right) => {
+                let mut conjuncts = left.into_conjuncts();
+                conjuncts.extend(right.into_conjuncts());
+                conjuncts
+            }
+            other => vec![other],
+        }
+    }
+
     /// Recursively collect all parameter names in this expression
     pub fn collect_parameters(&self, params: &mut HashSet<String>) {
         match self {
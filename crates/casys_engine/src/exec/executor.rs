@@ -75,6 +75,16 @@ impl ValueExt for Value {
     }
 }
 
+/// Bind edge variable `ev` in `tuple`: its id, `ev.edge_type` and every `ev.<property>`.
+fn bind_edge(tuple: &mut Tuple, ev: &str, edge: &crate::index::Edge) {
+    tuple.insert(ev.to_string(), Value::Int(edge.id as i64));
+    // Edge type for union type support
+    tuple.insert(format!("{}.edge_type", ev), Value::String(edge.edge_type.clone()));
+    for (k, v) in &edge.properties {
+        tuple.insert(format!("{}.{}", ev, k), v.clone());
+    }
+}
+
 /// Simple base64 encoding for Bytes variant (no external dependency)
 fn base64_encode(data: &[u8]) -> String {
     const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
@@ -99,6 +109,17 @@ fn base64_encode(data: &[u8]) -> String {
     result
 }
 
+/// A node scan, optionally filtered and cut short (LIMIT pushed into the scan)
+struct NodeScan<'p> {
+    variable: &'p str,
+    /// `None` scans every node
+    label: Option<&'p str>,
+    /// Only tuples for which this holds are kept
+    predicate: Option<&'p Expr>,
+    /// Stop once this many tuples are kept
+    limit: Option<usize>,
+}
+
 pub struct Executor<'a> {
     read: Option<&'a dyn GraphReadStore>,
     parameters: HashMap<String, Value>,
@@ -282,23 +303,8 @@ impl<'a> Executor<'a> {
                 }
                 
                 // Variable doesn't exist in parent - normal scan
-                let nodes = if let Some(r) = self.read {
-                    r.scan_by_label(label)?
-                } else if let Some(w) = write.as_deref_mut() {
-                    w.scan_by_label(label)?
-                } else {
-                    Vec::new()
-                };
-                counters.scanned += nodes.len() as u64;
-                Ok(nodes.into_iter().map(|n| {
-                    let mut tuple = parent_tuple.clone();
-                    tuple.insert(variable.clone(), Value::NodeId(n.id));
-                    for (k, v) in n.properties {
-                        let prop_key = format!("{}.{}", variable, k);
-                        tuple.insert(prop_key, v);
-                    }
-                    tuple
-                }).collect())
+                let scan = NodeScan { variable, label: Some(label), predicate: None, limit: None };
+                self.scan_nodes(scan, parent_tuple, write, counters)
             }
             PlanNode::FullScan { variable } => {
                 // Correlated subquery: if the variable already exists in parent context, reuse it
@@ -323,47 +329,16 @@ impl<'a> Executor<'a> {
                     return Ok(vec![]);
                 }
                 // Non-correlated: scan all nodes
-                let nodes = if let Some(r) = self.read { r.scan_all()? } else if let Some(w) = write.as_deref_mut() { w.scan_all()? } else { Vec::new() };
-                counters.scanned += nodes.len() as u64;
-                Ok(nodes.into_iter().map(|n| {
-                    let mut tuple = parent_tuple.clone();
-                    tuple.insert(variable.clone(), Value::NodeId(n.id));
-                    for (k, v) in n.properties {
-                        let prop_key = format!("{}.{}", variable, k);
-                        tuple.insert(prop_key, v.clone());
-                    }
-                    tuple
-                }).collect())
+                let scan = NodeScan { variable, label: None, predicate: None, limit: None };
+                self.scan_nodes(scan, parent_tuple, write, counters)
             }
             PlanNode::Filter { input, predicate } => {
                 let tuples = self.execute_node_with_context(input, parent_tuple, write, counters)?;
-                Ok(tuples.into_iter().filter(|t| {
-                    self.eval_expr(predicate, t, None).ok()
-                        .and_then(|v| match v {
-                            Value::Bool(b) => Some(b),
-                            _ => None,
-                        })
-                        .unwrap_or(false)
-                }).collect())
+                Ok(tuples.into_iter().filter(|t| self.predicate_holds(predicate, t)).collect())
             }
             PlanNode::Project { input, items } => {
                 let tuples = self.execute_node_with_context(input, parent_tuple, write, counters)?;
-                Ok(tuples.into_iter().map(|t| {
-                    let mut result = HashMap::new();
-                    for item in items {
-                        if let Ok(val) = self.eval_expr(&item.expr, &t, None) {
-                            let key = item.alias.clone().unwrap_or_else(|| {
-                                match &item.expr {
-                                    Expr::Ident(name) => name.clone(),
-                                    Expr::Property(var, prop) => format!("{}.{}", var, prop),
-                                    _ => "?".to_string(),
-                                }
-                            });
-                            result.insert(key, val);
-                        }
-                    }
-                    result
-                }).collect())
+                Ok(self.project(items, tuples))
             }
             PlanNode::Aggregate { input, group_by, aggregates } => {
                 let tuples = self.execute_node_with_context(input, parent_tuple, write, counters)?;
@@ -445,10 +420,27 @@ impl<'a> Executor<'a> {
                 Ok(tuples)
             }
             PlanNode::Limit { input, count } => {
+                // LIMIT over a plain (optionally filtered) scan stops the scan early
+                if let PlanNode::Project { input: projected, items } = input.as_ref() {
+                    let (scan, predicate) = match projected.as_ref() {
+                        PlanNode::Filter { input, predicate } => (input.as_ref(), Some(predicate)),
+                        other => (other, None),
+                    };
+                    let scan = match scan {
+                        PlanNode::LabelScan { variable, label } => Some((variable, Some(label.as_str()))),
+                        PlanNode::FullScan { variable } => Some((variable, None)),
+                        _ => None,
+                    };
+                    if let Some((variable, label)) = scan.filter(|(v, _)| !parent_tuple.contains_key(*v)) {
+                        let scan = NodeScan { variable, label, predicate, limit: Some(*count as usize) };
+                        let tuples = self.scan_nodes(scan, parent_tuple, write, counters)?;
+                        return Ok(self.project(items, tuples));
+                    }
+                }
                 let tuples = self.execute_node_with_context(input, parent_tuple, write, counters)?;
                 Ok(tuples.into_iter().take(*count as usize).collect())
             }
-            PlanNode::Expand { input, from_var, edge_var, to_var, edge_type, direction, depth } => {
+            PlanNode::Expand { input, from_var, edge_var, to_var, edge_type, direction, depth, edge_filter } => {
                 use super::ast::Direction;
                 
                 let input_tuples = { self.execute_node_with_context(input, parent_tuple, write, counters)? };
@@ -512,27 +504,38 @@ impl<'a> Executor<'a> {
                             } else {
                                 vec![]
                             };
-                            let mut neighbors = match direction {
+                            // Edge types and pushed-down WHERE conditions are checked on the
+                            // borrowed edge, before the neighbor is fetched
+                            let edge_ok = |edge: &crate::index::Edge| {
+                                if !edge_types.is_empty() && !edge_types.contains(&edge.edge_type.as_str()) {
+                                    return false;
+                                }
+                                match (edge_filter, edge_var) {
+                                    (Some(filter), Some(ev)) => {
+                                        let mut edge_tuple = Tuple::new();
+                                        bind_edge(&mut edge_tuple, ev, edge);
+                                        self.predicate_holds(filter, &edge_tuple)
+                                    }
+                                    _ => true,
+                                }
+                            };
+                            let neighbors = match direction {
                                 Direction::Right => {
                                     // Outgoing: (from)-[:REL]->(to)
-                                    reader.get_neighbors(*from_id, None)?
+                                    reader.get_neighbors_where(*from_id, None, casys_core::Direction::Outgoing, &edge_ok)?
                                 }
                                 Direction::Left => {
                                     // Incoming: (to)-[:REL]->(from) donc on cherche incoming
-                                    reader.get_neighbors_incoming(*from_id, None)?
+                                    reader.get_neighbors_where(*from_id, None, casys_core::Direction::Incoming, &edge_ok)?
                                 }
                                 Direction::Both => {
                                     // Both directions: combine outgoing + incoming
-                                    let mut out = reader.get_neighbors(*from_id, None)?;
-                                    let incoming = reader.get_neighbors_incoming(*from_id, None)?;
+                                    let mut out = reader.get_neighbors_where(*from_id, None, casys_core::Direction::Outgoing, &edge_ok)?;
+                                    let incoming = reader.get_neighbors_where(*from_id, None, casys_core::Direction::Incoming, &edge_ok)?;
                                     out.extend(incoming);
                                     out
                                 }
                             };
-                            // Filter by edge types if union specified
-                            if !edge_types.is_empty() {
-                                neighbors.retain(|(edge, _)| edge_types.contains(&edge.edge_type.as_str()));
-                            }
                             counters.expanded += neighbors.len() as u64;
                             for (edge, to_node) in neighbors {
                                 let mut new_tuple = tuple.clone();
@@ -550,14 +553,7 @@ impl<'a> Executor<'a> {
                                 
                                 // Add edge to tuple if variable specified
                                 if let Some(ref ev) = edge_var {
-                                    new_tuple.insert(ev.clone(), Value::Int(edge.id as i64));
-                                    // Add edge type for union type support
-                                    let edge_type_key = format!("{}.edge_type", ev);
-                                    new_tuple.insert(edge_type_key, Value::String(edge.edge_type.clone()));
-                                    for (k, v) in &edge.properties {
-                                        let prop_key = format!("{}.{}", ev, k);
-                                        new_tuple.insert(prop_key, v.clone());
-                                    }
+                                    bind_edge(&mut new_tuple, ev, &edge);
                                 }
                                 
                                 result.push(new_tuple);
@@ -571,6 +567,86 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// Run a node scan into tuples extending `parent_tuple`.
+    ///
+    /// Streams over `iter_nodes*` so only the visited nodes are touched; stores without
+    /// borrowing iterators fall back to the cloning scans.
+    fn scan_nodes(
+        &self,
+        scan: NodeScan<'_>,
+        parent_tuple: &Tuple,
+        write: &mut Option<&mut dyn GraphWriteStore>,
+        counters: &mut ExecCounters,
+    ) -> Result<Vec<Tuple>, EngineError> {
+        let NodeScan { variable, label, predicate, limit } = scan;
+        let mut tuples = Vec::new();
+        let reader: &dyn GraphReadStore = if let Some(r) = self.read { r } else if let Some(w) = write.as_deref_mut() { w } else { return Ok(tuples) };
+        if limit == Some(0) {
+            return Ok(tuples);
+        }
+        // Returns false once the limit is reached
+        let mut visit = |n: &crate::index::Node| {
+            counters.scanned += 1;
+            let mut tuple = parent_tuple.clone();
+            tuple.insert(variable.to_string(), Value::NodeId(n.id));
+            for (k, v) in &n.properties {
+                tuple.insert(format!("{}.{}", variable, k), v.clone());
+            }
+            if predicate.is_none_or(|p| self.predicate_holds(p, &tuple)) {
+                tuples.push(tuple);
+            }
+            limit.is_none_or(|l| tuples.len() < l)
+        };
+        let iter = match label {
+            Some(label) => reader.iter_nodes_by_label(label),
+            None => reader.iter_nodes(),
+        };
+        match iter {
+            Ok(mut nodes) => {
+                nodes.try_for_each(|n| if visit(n) { Some(()) } else { None });
+            }
+            Err(EngineError::NotImplemented(_)) => {
+                let nodes = match label {
+                    Some(label) => reader.scan_by_label(label)?,
+                    None => reader.scan_all()?,
+                };
+                nodes.iter().try_for_each(|n| if visit(n) { Some(()) } else { None });
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(tuples)
+    }
+
+    /// Whether `predicate` evaluates to `true` on `tuple` (errors and non-booleans count as false).
+    fn predicate_holds(&self, predicate: &Expr, tuple: &Tuple) -> bool {
+        self.eval_expr(predicate, tuple, None).ok()
+            .and_then(|v| match v {
+                Value::Bool(b) => Some(b),
+                _ => None,
+            })
+            .unwrap_or(false)
+    }
+
+    /// Evaluate RETURN / WITH items over each tuple.
+    fn project(&self, items: &[super::ast::ReturnItem], tuples: Vec<Tuple>) -> Vec<Tuple> {
+        tuples.into_iter().map(|t| {
+            let mut result = HashMap::new();
+            for item in items {
+                if let Ok(val) = self.eval_expr(&item.expr, &t, None) {
+                    let key = item.alias.clone().unwrap_or_else(|| {
+                        match &item.expr {
+                            Expr::Ident(name) => name.clone(),
+                            Expr::Property(var, prop) => format!("{}.{}", var, prop),
+                            _ => "?".to_string(),
+                        }
+                    });
+                    result.insert(key, val);
+                }
+            }
+            result
+        }).collect()
+    }
+
     /// Traverse variable-length paths using BFS with optional union edge types and direction
     fn traverse_variable_length(
         &self,
@@ -63,6 +63,8 @@ pub enum PlanNode {
         edge_type: Option<String>,
         direction: Direction,  // Left (<-), Right (->), Both (-)
         depth: Option<super::ast::DepthRange>,  // For variable-length paths
+        // WHERE conjuncts on edge_var alone, tested on each edge before its neighbor is fetched
+        edge_filter: Option<Expr>,
     },
     // Cartesian product (for MATCH (a), (b) patterns)
     CartesianProduct {
@@ -118,12 +120,22 @@ impl Planner {
             };
         }
 
-        // Apply WHERE filter if present
+        // Apply WHERE filter if present; conditions on a relationship alone move into its Expand
         if let Some(ref where_clause) = query.where_clause {
-            plan = PlanNode::Filter {
-                input: Box::new(plan),
-                predicate: where_clause.expr.clone(),
-            };
+            let mut remaining = Vec::new();
+            for conjunct in where_clause.expr.clone().into_conjuncts() {
+                if !Self::push_down_edge_predicate(&mut plan, &conjunct) {
+                    remaining.push(conjunct);
+                }
+            }
+            let predicate = remaining.into_iter()
+                .reduce(|acc, c| Expr::BinaryOp(Box::new(acc), BinOp::And, Box::new(c)));
+            if let Some(predicate) = predicate {
+                plan = PlanNode::Filter {
+                    input: Box::new(plan),
+                    predicate,
+                };
+            }
         }
 
         // RETURN is optional for CREATE
@@ -413,6 +425,7 @@ impl Planner {
                         edge_type: edge.edge_type.clone(),
                         direction: edge.direction.clone(),
                         depth: edge.depth.clone(),
+                        edge_filter: None,
                     };
                     // If an adjacent Node pattern binds the same to_var and defines inline properties,
                     // translate those properties into a post-Expand Filter predicate.
@@ -581,6 +594,30 @@ impl Planner {
         })
     }
     
+    /// Attach `conjunct` to the single-hop Expand binding the only variable it reads, looking
+    /// down through Filter and Expand inputs. Returns false (plan unchanged) if there is none.
+    fn push_down_edge_predicate(plan: &mut PlanNode, conjunct: &Expr) -> bool {
+        let mut vars = std::collections::HashSet::new();
+        if !conjunct.collect_variables(&mut vars) || vars.len() != 1 {
+            return false;
+        }
+        let var = vars.into_iter().next().expect("one variable");
+        let mut node = plan;
+        loop {
+            match node {
+                PlanNode::Expand { edge_var: Some(ev), depth: None, edge_filter, .. } if *ev == var => {
+                    *edge_filter = Some(match edge_filter.take() {
+                        Some(existing) => Expr::BinaryOp(Box::new(existing), BinOp::And, Box::new(conjunct.clone())),
+                        None => conjunct.clone(),
+                    });
+                    return true;
+                }
+                PlanNode::Expand { input, .. } | PlanNode::Filter { input, .. } => node = input,
+                _ => return false,
+            }
+        }
+    }
+
     fn has_aggregate(expr: &Expr) -> bool {
         match expr {
             Expr::Aggregate(_, _) => true,
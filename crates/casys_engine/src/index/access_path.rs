@@ -0,0 +1,76 @@
+//! Access path selection for property lookups
+//!
+//! `scan_by_property` and `scan_by_property_range` pick the cheapest structure that can
+//! answer them, in a fixed priority order, and the `_explain` variants report the pick.
+//! An equality lookup with a label tries the property index on `(label, key)`, then a
+//! point lookup in the range index, then a composite index leading with `key`, and
+//! otherwise scans the label. A range lookup with a label uses the range index or scans
+//! the label. Without a label every lookup is a full scan: indexes are per label.
+//! Whatever the path, candidates are rechecked against the exact predicate, so results
+//! do not depend on which indexes exist.
+
+use super::index_stats::IndexKind;
+use super::{InMemoryGraphStore, Node, NodeId, Value};
+use crate::types::EngineError;
+use casys_core::NumericRange;
+use std::borrow::Cow;
+
+/// How a property lookup found its candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPath {
+    /// One bucket of an equality index: `IndexKind::Property`, or `IndexKind::Composite`
+    /// for an index whose first key is the looked-up one
+    IndexEquality(IndexKind),
+    /// The range index on `(label, key)`, for a range or for a single value
+    IndexRange,
+    /// Every live node carrying the label
+    LabelScan,
+    /// Every live node
+    FullScan,
+}
+
+impl InMemoryGraphStore {
+    /// `scan_by_property`, also returning the access path that served it.
+    pub fn scan_by_property_explain(&self, label: Option<&str>, key: &str, value: &Value) -> Result<(Vec<Node>, AccessPath), EngineError> {
+        let matches = |n: &&Node| n.properties.get(key) == Some(value);
+        let Some(label) = label else {
+            let nodes = self.nodes.values().filter(|n| !n.deleted).filter(matches).cloned().collect();
+            return Ok((self.ordered(nodes, |n| n.id), AccessPath::FullScan));
+        };
+        let (candidates, path): (Cow<[NodeId]>, AccessPath) = if let Some(ids) = self.indexed_nodes(label, key, value) {
+            (Cow::Borrowed(ids), AccessPath::IndexEquality(IndexKind::Property))
+        } else if let Some(ids) = self.range_point_nodes(label, key, value) {
+            (Cow::Borrowed(ids), AccessPath::IndexRange)
+        } else if let Some(ids) = self.composite_prefix_nodes(label, key, value) {
+            (Cow::Owned(ids), AccessPath::IndexEquality(IndexKind::Composite))
+        } else {
+            (Cow::Borrowed(self.label_index.get(label).map_or(&[][..], Vec::as_slice)), AccessPath::LabelScan)
+        };
+        // Only matches are cloned
+        let nodes = candidates.iter().filter_map(|id| self.nodes.get(id)).filter(matches).cloned().collect();
+        Ok((self.ordered(nodes, |n| n.id), path))
+    }
+
+    /// `scan_by_property_range`, also returning the access path that served it.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if a bound is not numeric.
+    pub fn scan_by_property_range_explain(
+        &self,
+        label: Option<&str>,
+        key: &str,
+        min: Option<Value>,
+        max: Option<Value>,
+        inclusive: bool,
+    ) -> Result<(Vec<Node>, AccessPath), EngineError> {
+        let range = NumericRange::new(min, max, inclusive)?;
+        // Number keys are widened to f64; recheck so the result matches the full scan exactly
+        let in_range = |n: &&Node| n.properties.get(key).is_some_and(|v| range.contains(v));
+        if let Some(ids) = label.and_then(|label| self.range_indexed_nodes(label, key, &range)) {
+            let nodes = ids.into_iter().filter_map(|id| self.nodes.get(&id)).filter(in_range).cloned().collect();
+            return Ok((self.ordered(nodes, |n| n.id), AccessPath::IndexRange));
+        }
+        let path = if label.is_some() { AccessPath::LabelScan } else { AccessPath::FullScan };
+        Ok((self.iter_nodes_ordered(label).filter(in_range).cloned().collect(), path))
+    }
+}
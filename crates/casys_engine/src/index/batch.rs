@@ -0,0 +1,342 @@
+//! All-or-nothing application of a batch of mutations
+//!
+//! `apply_batch` first replays the whole batch against a staged view (which records only
+//! what the batch creates and deletes) and touches the real maps only once every mutation
+//! has been validated.
+
+use super::{InMemoryGraphStore, NodeId, EdgeId, Value, GraphWriteStore};
+use super::constraints::violation;
+use crate::types::EngineError;
+use casys_core::ValueKey;
+use std::collections::{HashMap, HashSet};
+
+/// A node referenced by a batch mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeRef {
+    /// A node that already exists in the store.
+    Existing(NodeId),
+    /// The n-th `Mutation::AddNode` of the same batch (0-based).
+    Batch(usize),
+}
+
+/// An edge referenced by a batch mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeRef {
+    /// An edge that already exists in the store.
+    Existing(EdgeId),
+    /// The n-th `Mutation::AddEdge` of the same batch (0-based).
+    Batch(usize),
+}
+
+/// One write in a batch passed to `InMemoryGraphStore::apply_batch`.
+#[derive(Debug, Clone)]
+pub enum Mutation {
+    AddNode {
+        labels: Vec<String>,
+        properties: HashMap<String, Value>,
+    },
+    AddEdge {
+        from: NodeRef,
+        to: NodeRef,
+        edge_type: String,
+        properties: HashMap<String, Value>,
+    },
+    DeleteNode {
+        node: NodeRef,
+        detach: bool,
+    },
+    DeleteEdge {
+        edge: EdgeRef,
+    },
+    SetNodeProperty {
+        node: NodeRef,
+        key: String,
+        value: Value,
+    },
+    RemoveNodeProperty {
+        node: NodeRef,
+        key: String,
+    },
+    SetEdgeProperty {
+        edge: EdgeRef,
+        key: String,
+        value: Value,
+    },
+}
+
+/// Real ids assigned to the nodes and edges a batch created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchResult {
+    /// `nodes[n]` is the id of `NodeRef::Batch(n)`
+    pub nodes: Vec<NodeId>,
+    /// `edges[n]` is the id of `EdgeRef::Batch(n)`
+    pub edges: Vec<EdgeId>,
+}
+
+impl BatchResult {
+    pub fn node_id(&self, node: NodeRef) -> Option<NodeId> {
+        match node {
+            NodeRef::Existing(id) => Some(id),
+            NodeRef::Batch(n) => self.nodes.get(n).copied(),
+        }
+    }
+
+    pub fn edge_id(&self, edge: EdgeRef) -> Option<EdgeId> {
+        match edge {
+            EdgeRef::Existing(id) => Some(id),
+            EdgeRef::Batch(n) => self.edges.get(n).copied(),
+        }
+    }
+}
+
+/// What the batch has done so far, layered over the unchanged store.
+struct Stage<'a> {
+    store: &'a InMemoryGraphStore,
+    nodes_created: usize,
+    /// Endpoints of the edges created so far, by batch index
+    edges_created: Vec<(NodeRef, NodeRef)>,
+    deleted_nodes: HashSet<NodeRef>,
+    deleted_edges: HashSet<EdgeRef>,
+    /// Unique constraints of the store, in a fixed order so they can be referred to by index
+    constraints: Vec<(String, String)>,
+    /// Labels of the nodes created so far, by batch index
+    batch_labels: Vec<Vec<String>>,
+    /// Constrained value of a node as the batch leaves it, overriding the store
+    values: HashMap<(NodeRef, usize), Option<ValueKey>>,
+    /// Holder of a constrained value as the batch leaves it, overriding the store
+    holders: HashMap<(usize, ValueKey), Option<NodeRef>>,
+}
+
+impl<'a> Stage<'a> {
+    fn new(store: &'a InMemoryGraphStore) -> Self {
+        Self {
+            store,
+            nodes_created: 0,
+            edges_created: Vec::new(),
+            deleted_nodes: HashSet::new(),
+            deleted_edges: HashSet::new(),
+            constraints: store.unique_constraints(),
+            batch_labels: Vec::new(),
+            values: HashMap::new(),
+            holders: HashMap::new(),
+        }
+    }
+
+    fn has_label(&self, node: NodeRef, label: &str) -> bool {
+        match node {
+            NodeRef::Existing(id) => self.store.live_node(id).is_some_and(|n| n.labels.iter().any(|l| l == label)),
+            NodeRef::Batch(n) => self.batch_labels[n].iter().any(|l| l == label),
+        }
+    }
+
+    /// Indexes of the constraints on `key` (any key when `None`) covering `node`.
+    fn constraints_on(&self, node: NodeRef, key: Option<&str>) -> Vec<usize> {
+        (0..self.constraints.len())
+            .filter(|c| key.is_none_or(|k| self.constraints[*c].1 == k))
+            .filter(|c| self.has_label(node, &self.constraints[*c].0))
+            .collect()
+    }
+
+    /// Give `node` the constrained `value` under constraint `c`, unless another node holds it.
+    fn claim(&mut self, c: usize, node: NodeRef, value: &Value) -> Result<(), EngineError> {
+        let key = ValueKey::from(value);
+        let holder = match self.holders.get(&(c, key.clone())) {
+            Some(holder) => *holder,
+            None => {
+                let (label, constrained) = &self.constraints[c];
+                self.store.unique_owner(label, constrained, value, &[]).map(NodeRef::Existing)
+            }
+        };
+        if holder.is_some_and(|h| h != node) {
+            let (label, constrained) = &self.constraints[c];
+            return Err(violation(label, constrained, value));
+        }
+        self.release(c, node);
+        self.holders.insert((c, key.clone()), Some(node));
+        self.values.insert((node, c), Some(key));
+        Ok(())
+    }
+
+    /// Drop whatever value `node` holds under constraint `c`.
+    fn release(&mut self, c: usize, node: NodeRef) {
+        let current = match (self.values.get(&(node, c)), node) {
+            (Some(value), _) => value.clone(),
+            (None, NodeRef::Existing(id)) => self.store.live_node(id)
+                .and_then(|n| n.properties.get(&self.constraints[c].1))
+                .map(ValueKey::from),
+            (None, NodeRef::Batch(_)) => None,
+        };
+        if let Some(value) = current {
+            self.holders.insert((c, value), None);
+        }
+        self.values.insert((node, c), None);
+    }
+
+    fn node_alive(&self, node: NodeRef) -> bool {
+        let exists = match node {
+            NodeRef::Existing(id) => self.store.live_node(id).is_some(),
+            NodeRef::Batch(n) => n < self.nodes_created,
+        };
+        exists && !self.deleted_nodes.contains(&node)
+    }
+
+    fn require_node(&self, step: usize, node: NodeRef) -> Result<(), EngineError> {
+        if self.node_alive(node) {
+            return Ok(());
+        }
+        Err(match node {
+            NodeRef::Existing(id) => EngineError::NotFound(format!("node {} (mutation {})", id, step)),
+            NodeRef::Batch(n) => EngineError::InvalidArgument(format!(
+                "mutation {}: batch node #{} does not exist at this point", step, n
+            )),
+        })
+    }
+
+    fn require_edge(&self, step: usize, edge: EdgeRef) -> Result<(), EngineError> {
+        let exists = match edge {
+            EdgeRef::Existing(id) => self.store.edges.get(&id).is_some_and(|e| !e.deleted),
+            EdgeRef::Batch(n) => n < self.edges_created.len(),
+        };
+        if exists && !self.deleted_edges.contains(&edge) {
+            return Ok(());
+        }
+        Err(match edge {
+            EdgeRef::Existing(id) => EngineError::NotFound(format!("edge {} (mutation {})", id, step)),
+            EdgeRef::Batch(n) => EngineError::InvalidArgument(format!(
+                "mutation {}: batch edge #{} does not exist at this point", step, n
+            )),
+        })
+    }
+
+    /// Edges touching `node` that are still alive in the staged view.
+    fn incident_edges(&self, node: NodeRef) -> Vec<EdgeRef> {
+        let mut edges: Vec<EdgeRef> = match node {
+            NodeRef::Existing(id) => self.store.incident_edge_ids(id).into_iter().map(EdgeRef::Existing).collect(),
+            NodeRef::Batch(_) => Vec::new(),
+        };
+        for (n, (from, to)) in self.edges_created.iter().enumerate() {
+            if *from == node || *to == node {
+                edges.push(EdgeRef::Batch(n));
+            }
+        }
+        edges.retain(|e| !self.deleted_edges.contains(e));
+        edges
+    }
+
+    fn stage(&mut self, step: usize, mutation: &Mutation) -> Result<(), EngineError> {
+        match mutation {
+            Mutation::AddNode { labels, properties } => {
+                let node = NodeRef::Batch(self.nodes_created);
+                self.nodes_created += 1;
+                self.batch_labels.push(labels.clone());
+                for c in self.constraints_on(node, None) {
+                    if let Some(value) = properties.get(&self.constraints[c].1) {
+                        self.claim(c, node, value)?;
+                    }
+                }
+            }
+            Mutation::AddEdge { from, to, .. } => {
+                for node in [*from, *to] {
+                    match node {
+                        // Same rule as add_edge; lenient stores accept unknown existing ids
+                        NodeRef::Existing(id) => {
+                            if self.store.options.strict_edges && !self.node_alive(node) {
+                                return Err(EngineError::NodeNotFound(id));
+                            }
+                        }
+                        NodeRef::Batch(_) => self.require_node(step, node)?,
+                    }
+                }
+                self.edges_created.push((*from, *to));
+            }
+            Mutation::DeleteNode { node, detach } => {
+                self.require_node(step, *node)?;
+                let incident = self.incident_edges(*node);
+                if !*detach && !incident.is_empty() {
+                    return Err(EngineError::InvalidArgument(format!(
+                        "mutation {}: node still has {} edges; delete with detach", step, incident.len()
+                    )));
+                }
+                self.deleted_edges.extend(incident);
+                for c in self.constraints_on(*node, None) {
+                    self.release(c, *node);
+                }
+                self.deleted_nodes.insert(*node);
+            }
+            Mutation::DeleteEdge { edge } => {
+                self.require_edge(step, *edge)?;
+                self.deleted_edges.insert(*edge);
+            }
+            Mutation::SetNodeProperty { node, key, value } => {
+                self.require_node(step, *node)?;
+                for c in self.constraints_on(*node, Some(key)) {
+                    self.claim(c, *node, value)?;
+                }
+            }
+            Mutation::RemoveNodeProperty { node, key } => {
+                self.require_node(step, *node)?;
+                for c in self.constraints_on(*node, Some(key)) {
+                    self.release(c, *node);
+                }
+            }
+            Mutation::SetEdgeProperty { edge, .. } => self.require_edge(step, *edge)?,
+        }
+        Ok(())
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Apply `mutations` in order, all or nothing.
+    ///
+    /// Mutations may reference nodes and edges created earlier in the same batch through
+    /// `NodeRef::Batch` / `EdgeRef::Batch`; the returned `BatchResult` maps those handles
+    /// to the ids actually assigned. Each applied mutation is WAL-logged like its
+    /// single-call equivalent.
+    ///
+    /// # Errors
+    /// The first mutation that would fail is reported (its index is in the message) and
+    /// the store is left untouched.
+    pub fn apply_batch(&mut self, mutations: Vec<Mutation>) -> Result<BatchResult, EngineError> {
+        let mut stage = Stage::new(self);
+        for (step, mutation) in mutations.iter().enumerate() {
+            stage.stage(step, mutation)?;
+        }
+
+        let mut result = BatchResult::default();
+        let node = |result: &BatchResult, node: NodeRef| result.node_id(node).expect("validated by stage");
+        let edge = |result: &BatchResult, edge: EdgeRef| result.edge_id(edge).expect("validated by stage");
+        for mutation in mutations {
+            match mutation {
+                Mutation::AddNode { labels, properties } => {
+                    let id = self.add_node(labels, properties)?;
+                    result.nodes.push(id);
+                }
+                Mutation::AddEdge { from, to, edge_type, properties } => {
+                    let (from, to) = (node(&result, from), node(&result, to));
+                    let id = if self.options.strict_edges {
+                        self.add_edge(from, to, edge_type, properties)?
+                    } else {
+                        self.add_edge_unchecked(from, to, edge_type, properties)?
+                    };
+                    result.edges.push(id);
+                }
+                Mutation::DeleteNode { node: target, detach } => {
+                    self.delete_node(node(&result, target), detach)?;
+                }
+                Mutation::DeleteEdge { edge: target } => {
+                    self.delete_edge(edge(&result, target))?;
+                }
+                Mutation::SetNodeProperty { node: target, key, value } => {
+                    self.set_node_property(node(&result, target), key, value)?;
+                }
+                Mutation::RemoveNodeProperty { node: target, key } => {
+                    self.remove_node_property(node(&result, target), &key)?;
+                }
+                Mutation::SetEdgeProperty { edge: target, key, value } => {
+                    self.update_edge_properties(edge(&result, target), HashMap::from([(key, value)]))?;
+                }
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,161 @@
+//! Computed indexes: `(label, name) -> derived value -> node ids`
+//!
+//! A computed index files every live node carrying `label` under a value that an
+//! application function derives from the node. Examples are a lowercased email or the
+//! first two characters of a postcode. The derived value is never stored as a property.
+//! The function runs when the index is created. It runs again on every write to a node
+//! carrying `label`, through `index_node_properties` / `unindex_node_properties`, before
+//! and after the change. It must therefore be deterministic and depend on the node alone.
+//! Nodes for which it returns `None` are not filed.
+//!
+//! Functions cannot be persisted. `flush` writes only the `(label, name)` definition,
+//! even with `StoreOptions::persist_index_data`. A loaded store holds each computed index
+//! as awaiting re-registration: `scan_by_computed` fails on it and writes skip it. The
+//! application re-registers the function by calling `create_computed_index` again with
+//! the same label and name. That call backfills the index from the nodes as they are at
+//! that point. `unregistered_computed_indexes` lists the indexes still waiting.
+
+use super::index_stats::IndexKind;
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, Node, NodeId, Value};
+use crate::types::EngineError;
+use casys_core::ValueKey;
+use std::collections::HashMap;
+
+/// The function of a computed index: the value a node is filed under, if any.
+pub type ComputedKey = Box<dyn Fn(&Node) -> Option<Value> + Send + Sync>;
+
+/// One computed index. `function` is `None` while it awaits re-registration.
+pub(crate) struct ComputedIndex {
+    pub(crate) function: Option<ComputedKey>,
+    pub(crate) buckets: HashMap<ValueKey, Vec<NodeId>>,
+}
+
+impl ComputedIndex {
+    /// The bucket `node` is filed under for `label`, if any.
+    pub(crate) fn key_of(&self, node: &Node, label: &str) -> Option<ValueKey> {
+        let function = self.function.as_ref()?;
+        if !node.labels.iter().any(|l| l == label) {
+            return None;
+        }
+        function(node).as_ref().map(ValueKey::from)
+    }
+
+    /// File the live nodes in `ids` (the label's bucket, so in ascending id order).
+    fn fill(&mut self, label: &str, ids: &[NodeId], nodes: &HashMap<NodeId, Node>) {
+        for id in ids {
+            if let Some(bucket) = nodes.get(id).and_then(|node| self.key_of(node, label)) {
+                self.buckets.entry(bucket).or_default().push(*id);
+            }
+        }
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Index nodes carrying `label` by `function`, under `name`, and backfill the index
+    /// from the nodes already stored. `scan_by_computed` uses the index from then on.
+    /// Calling this for an index that awaits re-registration after a load installs
+    /// `function` and backfills the index.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `name` is empty, or if the index already exists
+    /// with a function.
+    pub fn create_computed_index(&mut self, label: &str, name: &str, function: ComputedKey) -> Result<(), EngineError> {
+        if label.is_empty() || name.is_empty() {
+            return Err(EngineError::InvalidArgument("computed index needs a label and a name".into()));
+        }
+        let def = (label.to_string(), name.to_string());
+        if self.computed_indexes.get(&def).is_some_and(|index| index.function.is_some()) {
+            return Err(EngineError::InvalidArgument(format!("computed index on {}.{} already exists", label, name)));
+        }
+        let mut index = ComputedIndex { function: Some(function), buckets: HashMap::new() };
+        index.fill(label, self.label_index.get(label).map_or(&[][..], Vec::as_slice), &self.nodes);
+        self.computed_indexes.insert(def, index);
+        self.track_index(IndexKind::Computed, label, &[name]);
+        self.log_create_index(DdlKind::Index(IndexKind::Computed), label, &[name], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the computed index `name` on `label`, registered or not. Returns `false` if
+    /// there was none.
+    pub fn drop_computed_index(&mut self, label: &str, name: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Computed, label, &[name]);
+        let dropped = self.computed_indexes.remove(&(label.to_string(), name.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Computed), label, &[name]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every computed index as `(label, name)`, sorted, whether registered or not.
+    pub fn computed_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.computed_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// The computed indexes loaded from segments whose function has not been
+    /// re-registered yet, sorted.
+    pub fn unregistered_computed_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.computed_indexes.iter()
+            .filter(|(_, index)| index.function.is_none())
+            .map(|(def, _)| def.clone())
+            .collect();
+        defs.sort();
+        defs
+    }
+
+    /// Nodes carrying `label` whose computed value under `name` equals `value`.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if there is no such index, or if it was loaded from segments and
+    /// its function has not been re-registered with `create_computed_index`.
+    pub fn scan_by_computed(&self, label: &str, name: &str, value: &Value) -> Result<Vec<Node>, EngineError> {
+        let index = self.computed_indexes.get(&(label.to_string(), name.to_string()))
+            .ok_or_else(|| EngineError::InvalidArgument(format!("no computed index on {}.{}", label, name)))?;
+        if index.function.is_none() {
+            return Err(EngineError::InvalidArgument(format!(
+                "computed index on {}.{} was loaded without its function; re-register it with create_computed_index",
+                label, name
+            )));
+        }
+        let ids = index.buckets.get(&ValueKey::from(value)).map_or(&[][..], Vec::as_slice);
+        self.record_index_lookup(IndexKind::Computed, label, &[name], !ids.is_empty());
+        let nodes = ids.iter().filter_map(|id| self.nodes.get(id).cloned()).collect();
+        Ok(self.ordered(nodes, |n| n.id))
+    }
+
+    /// File live node `id` in the registered computed indexes it qualifies for. Called
+    /// from `index_node_properties`.
+    pub(crate) fn index_node_computed(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, _), index) in self.computed_indexes.iter_mut() {
+            if let Some(bucket) = index.key_of(node, label) {
+                index.buckets.entry(bucket).or_default().push(id);
+            }
+        }
+    }
+
+    /// Remove live node `id` from the computed index buckets it is currently filed under.
+    /// Called from `unindex_node_properties`.
+    pub(crate) fn unindex_node_computed(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, _), index) in self.computed_indexes.iter_mut() {
+            let Some(bucket) = index.key_of(node, label) else { continue };
+            if let Some(ids) = index.buckets.get_mut(&bucket) {
+                ids.retain(|n| *n != id);
+                if ids.is_empty() {
+                    index.buckets.remove(&bucket);
+                }
+            }
+        }
+    }
+
+    /// Refill every registered computed index from the live nodes.
+    pub(crate) fn rebuild_computed_indexes(&mut self) {
+        for ((label, _), index) in self.computed_indexes.iter_mut() {
+            index.buckets.clear();
+            index.fill(label, self.label_index.get(label).map_or(&[][..], Vec::as_slice), &self.nodes);
+        }
+    }
+}
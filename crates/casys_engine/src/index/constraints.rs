@@ -0,0 +1,146 @@
+//! Unique constraints: at most one live node per `(label, key)` value
+//!
+//! A constraint is checked before every write that could give a node carrying `label` a
+//! `key` value another live node with `label` already has; the write then fails with
+//! `EngineError::UniqueViolation` and changes nothing. Lookups go through the property
+//! index on `(label, key)`, which `create_unique_constraint` creates if needed. WAL replay
+//! and segment loading apply records as they were accepted and are not re-checked; a
+//! loaded constraint is re-validated against the loaded data instead. A constraint
+//! replayed from the WAL is not validated either: over segments, nodes that broke it
+//! before it was created may still be present until the records deleting them replay.
+
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, NodeId, Value};
+use crate::types::EngineError;
+use casys_core::ValueKey;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+impl InMemoryGraphStore {
+    /// Require `key` values to be unique among live nodes carrying `label`.
+    ///
+    /// Also creates the property index on `(label, key)` when missing. Dropping that
+    /// index later keeps the constraint working, only with scans instead of lookups.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty, the constraint already exists, or
+    /// existing nodes already share a value (every duplicated value is listed).
+    pub fn create_unique_constraint(&mut self, label: &str, key: &str) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("unique constraint needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.unique_constraints.contains(&def) {
+            return Err(EngineError::InvalidArgument(format!("unique constraint on {}.{} already exists", label, key)));
+        }
+        let mut holders: BTreeMap<ValueKey, (&Value, Vec<NodeId>)> = BTreeMap::new();
+        for node in self.iter_nodes_matching(Some(label)) {
+            if let Some(value) = node.properties.get(key) {
+                holders.entry(ValueKey::from(value)).or_insert_with(|| (value, Vec::new())).1.push(node.id);
+            }
+        }
+        let duplicates: Vec<String> = holders.into_values()
+            .filter(|(_, ids)| ids.len() > 1)
+            .map(|(value, mut ids)| {
+                ids.sort_unstable();
+                format!("{:?} (nodes {:?})", value, ids)
+            })
+            .collect();
+        if !duplicates.is_empty() {
+            return Err(EngineError::InvalidArgument(format!(
+                "cannot create unique constraint on {}.{}: duplicate values {}", label, key, duplicates.join(", ")
+            )));
+        }
+        if !self.has_property_index(label, key) {
+            self.create_property_index(label, key)?;
+        }
+        self.unique_constraints.insert(def);
+        self.log_create_index(DdlKind::UniqueConstraint, label, &[key], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the constraint on `(label, key)`, keeping its property index. Returns `false`
+    /// if there was none.
+    pub fn drop_unique_constraint(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        let dropped = self.unique_constraints.remove(&(label.to_string(), key.to_string()));
+        if dropped {
+            self.log_drop_index(DdlKind::UniqueConstraint, label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every constrained `(label, key)`, sorted.
+    pub fn unique_constraints(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.unique_constraints.iter().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// The live node other than `except` holding `value` under the constraint on
+    /// `(label, key)`, if any.
+    pub(crate) fn unique_owner(&self, label: &str, key: &str, value: &Value, except: &[NodeId]) -> Option<NodeId> {
+        self.find_by_label_and_property(label, key, value).into_iter().find(|id| !except.contains(id))
+    }
+
+    /// Fail if setting `key = value` on a node with `labels` (`id`, or a new node when
+    /// `None`) would break a unique constraint.
+    pub(crate) fn check_unique(&self, id: Option<NodeId>, labels: &[String], key: &str, value: &Value) -> Result<(), EngineError> {
+        let except: Vec<NodeId> = id.into_iter().collect();
+        for (label, constrained) in &self.unique_constraints {
+            if constrained == key && labels.contains(label) && self.unique_owner(label, key, value, &except).is_some() {
+                return Err(violation(label, key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// `check_unique` for every property of a node about to be stored with `labels`.
+    /// Nodes listed in `except` do not count as holders (e.g. the node itself).
+    pub(crate) fn check_unique_node(&self, except: &[NodeId], labels: &[String], properties: &HashMap<String, Value>) -> Result<(), EngineError> {
+        for (label, key) in &self.unique_constraints {
+            let Some(value) = properties.get(key).filter(|_| labels.contains(label)) else { continue };
+            if self.unique_owner(label, key, value, except).is_some() {
+                return Err(violation(label, key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// `check_unique_node` for several new nodes at once, which must not collide with
+    /// each other either.
+    pub(crate) fn check_unique_nodes<'a>(&self, nodes: impl IntoIterator<Item = (&'a [String], &'a HashMap<String, Value>)>) -> Result<(), EngineError> {
+        if self.unique_constraints.is_empty() {
+            return Ok(());
+        }
+        let mut claimed: HashSet<(&str, &str, ValueKey)> = HashSet::new();
+        for (labels, properties) in nodes {
+            self.check_unique_node(&[], labels, properties)?;
+            for (label, key) in &self.unique_constraints {
+                let Some(value) = properties.get(key).filter(|_| labels.contains(label)) else { continue };
+                if !claimed.insert((label, key, ValueKey::from(value))) {
+                    return Err(violation(label, key, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if merging `remove` into `keep` (labels unioned, `keep`'s properties winning)
+    /// would give `keep` a constrained value some third node holds.
+    pub(crate) fn check_unique_merge(&self, keep: NodeId, remove: NodeId) -> Result<(), EngineError> {
+        let (kept, removed) = (&self.nodes[&keep], &self.nodes[&remove]);
+        for (label, key) in &self.unique_constraints {
+            if !kept.labels.contains(label) && !removed.labels.contains(label) {
+                continue;
+            }
+            let Some(value) = kept.properties.get(key).or_else(|| removed.properties.get(key)) else { continue };
+            if self.unique_owner(label, key, value, &[keep, remove]).is_some() {
+                return Err(violation(label, key, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn violation(label: &str, key: &str, value: &Value) -> EngineError {
+    EngineError::UniqueViolation { label: label.to_string(), key: key.to_string(), value: value.clone() }
+}
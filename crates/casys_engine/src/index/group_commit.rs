@@ -0,0 +1,149 @@
+//! Group commit: many committers sharing one `WalWriter`, one fsync per group
+//!
+//! `GroupCommitWriter::commit` buffers a record and blocks until it is durable. Records
+//! committed while a group is open join it; the group is written with
+//! `WalWriter::append_group`, one write and one fsync, once it holds
+//! `GroupCommitConfig::max_records` records or its first record has waited
+//! `GroupCommitConfig::max_wait`. The committer that finds it due writes it, outside
+//! the lock, so the next group fills meanwhile; every member then returns with the
+//! group's outcome. A failed write fails every member of the group, and the writer
+//! stays failed for the groups after it.
+//!
+//! Records can also be appended to the writer directly, through `writer`, alongside the
+//! groups: the writer numbers and writes each append or group atomically, and appends
+//! waiting for an fsync join the one a group takes.
+
+use super::persistence::WalRecord;
+use super::wal_file::{Lsn, WalWriter};
+use crate::types::EngineError;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// When a `GroupCommitWriter` writes the group being filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCommitConfig {
+    /// Records that make a group due at once (`0` behaves like `1`)
+    pub max_records: usize,
+    /// Longest the first record of a group waits for others to join it
+    pub max_wait: Duration,
+}
+
+impl Default for GroupCommitConfig {
+    fn default() -> Self {
+        Self { max_records: 128, max_wait: Duration::from_millis(2) }
+    }
+}
+
+/// A `WalWriter` shared by concurrent committers; see the module docs.
+pub struct GroupCommitWriter {
+    writer: WalWriter,
+    config: GroupCommitConfig,
+    state: Mutex<GroupState>,
+    written: Condvar,
+}
+
+struct GroupState {
+    /// Whether a committer is writing a group
+    writing: bool,
+    /// Records of the group being filled
+    pending: Vec<WalRecord>,
+    /// When the first of them was committed
+    opened: Instant,
+    /// Number of the group being filled; the groups before it are written
+    group: u64,
+    /// Outcome of written groups (first LSN or the error) and how many members have not
+    /// collected it yet
+    outcomes: HashMap<u64, (Result<Lsn, EngineError>, usize)>,
+}
+
+impl GroupState {
+    fn collect(&mut self, group: u64) -> Option<Result<Lsn, EngineError>> {
+        let (result, left) = self.outcomes.get_mut(&group)?;
+        let result = result.clone();
+        *left -= 1;
+        if *left == 0 {
+            self.outcomes.remove(&group);
+        }
+        Some(result)
+    }
+}
+
+impl GroupCommitWriter {
+    pub fn new(writer: WalWriter, config: GroupCommitConfig) -> Self {
+        Self {
+            writer,
+            config,
+            state: Mutex::new(GroupState {
+                writing: false,
+                pending: Vec::new(),
+                opened: Instant::now(),
+                group: 0,
+                outcomes: HashMap::new(),
+            }),
+            written: Condvar::new(),
+        }
+    }
+
+    pub fn config(&self) -> GroupCommitConfig {
+        self.config
+    }
+
+    pub fn writer(&self) -> &WalWriter {
+        &self.writer
+    }
+
+    /// Add `record` to the group being filled and return its LSN once the group is
+    /// written and fsynced.
+    ///
+    /// # Errors
+    /// The error of the group's write, for every record of the group.
+    pub fn commit(&self, record: &WalRecord) -> Result<Lsn, EngineError> {
+        let mut state = self.lock();
+        let group = state.group;
+        let index = state.pending.len() as u64;
+        if state.pending.is_empty() {
+            state.opened = Instant::now();
+        }
+        state.pending.push(record.clone());
+        loop {
+            if let Some(result) = state.collect(group) {
+                return result.map(|first| first + index);
+            }
+            let filling = state.group == group && !state.writing;
+            let deadline = state.opened + self.config.max_wait;
+            if filling && (state.pending.len() >= self.config.max_records.max(1) || Instant::now() >= deadline) {
+                state = self.write_group(state);
+            } else if filling {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                state = self.written.wait_timeout(state, wait).expect("group commit lock poisoned").0;
+            } else {
+                state = self.written.wait(state).expect("group commit lock poisoned");
+            }
+        }
+    }
+
+    /// Write the group being filled, letting the next one fill meanwhile.
+    fn write_group<'a>(&'a self, mut state: MutexGuard<'a, GroupState>) -> MutexGuard<'a, GroupState> {
+        state.writing = true;
+        let records = std::mem::take(&mut state.pending);
+        let group = state.group;
+        state.group += 1;
+        drop(state);
+        let result = self.writer.append_group(&records);
+        let mut state = self.lock();
+        state.writing = false;
+        state.outcomes.insert(group, (result, records.len()));
+        self.written.notify_all();
+        state
+    }
+
+    /// Hand back the writer. Every commit has returned, so no record is left buffered.
+    pub fn into_inner(self) -> WalWriter {
+        self.writer
+    }
+
+    fn lock(&self) -> MutexGuard<'_, GroupState> {
+        self.state.lock().expect("group commit lock poisoned")
+    }
+}
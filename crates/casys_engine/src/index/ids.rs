@@ -0,0 +1,92 @@
+//! Id allocation for nodes and edges
+//!
+//! Ids start at 1 and grow monotonically. When recycling is enabled, released ids go to a
+//! free list and are handed out again (lowest first) before the high-water mark moves.
+
+use std::ops::Range;
+
+/// Allocator for one id space (nodes or edges).
+#[derive(Debug, Clone)]
+pub struct IdAllocator {
+    /// Lowest id never handed out
+    next: u64,
+    /// Released ids available for reuse, kept sorted descending so `pop` yields the lowest
+    free: Vec<u64>,
+    reuse: bool,
+}
+
+impl IdAllocator {
+    /// A fresh allocator starting at 1. With `reuse` off, `release` is a no-op.
+    pub fn new(reuse: bool) -> Self {
+        Self { next: 1, free: Vec::new(), reuse }
+    }
+
+    /// Hand out one id, preferring a recycled one.
+    pub fn next_id(&mut self) -> u64 {
+        if let Some(id) = self.free.pop() {
+            return id;
+        }
+        let id = self.next;
+        self.next += 1;
+        id
+    }
+
+    /// Hand out `n` contiguous ids above the high-water mark. The free list is never used,
+    /// so the range is always contiguous.
+    pub fn reserve(&mut self, n: u64) -> Range<u64> {
+        let start = self.next;
+        self.next += n;
+        start..self.next
+    }
+
+    /// Return an id whose record was physically removed. Ignored unless reuse is enabled.
+    pub fn release(&mut self, id: u64) {
+        if !self.reuse || id == 0 || id >= self.next {
+            return;
+        }
+        if let Err(pos) = self.free.binary_search_by(|probe| id.cmp(probe)) {
+            self.free.insert(pos, id);
+        }
+    }
+
+    /// Record that `id` is in use (e.g. chosen by the caller or loaded from storage):
+    /// moves the high-water mark past it and drops it from the free list.
+    pub fn observe(&mut self, id: u64) {
+        if id >= self.next {
+            self.next = id + 1;
+        } else if let Ok(pos) = self.free.binary_search_by(|probe| id.cmp(probe)) {
+            self.free.remove(pos);
+        }
+    }
+
+    /// Forget everything handed out so far and start again at 1.
+    pub fn reset(&mut self) {
+        self.next = 1;
+        self.free.clear();
+    }
+
+    /// The lowest id never handed out.
+    pub fn high_water(&self) -> u64 {
+        self.next
+    }
+
+    /// Ids waiting to be reused, lowest first.
+    pub fn free_ids(&self) -> Vec<u64> {
+        self.free.iter().rev().copied().collect()
+    }
+
+    pub fn reuses_ids(&self) -> bool {
+        self.reuse
+    }
+
+    /// Restore persisted state. `high_water` never moves backwards, and free ids are only
+    /// kept when reuse is enabled.
+    pub(crate) fn restore(&mut self, high_water: u64, free: &[u64]) {
+        self.next = self.next.max(high_water);
+        if self.reuse {
+            for id in free {
+                self.release(*id);
+            }
+        }
+    }
+}
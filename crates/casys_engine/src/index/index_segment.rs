@@ -0,0 +1,341 @@
+//! The index segment: every secondary index and unique constraint, written by `flush`
+//!
+//! Layout: `{"format_version": 1, "indexes": [definition, ...]}`, where a definition is
+//! `{"kind": ..., "label": ..., "key": ...}` (`"keys"` for composite indexes, `"edge_type"`
+//! for edge indexes, `"name"` for computed indexes), plus the normalization flags of
+//! property and prefix indexes and the `"covered"` keys of covering indexes. Loading
+//! recreates each index once the node and edge segments are in: an index is backfilled by
+//! its `create_*` method unless its definition carries `"data"`, the buckets as they were
+//! flushed, which `flush` writes when `StoreOptions::persist_index_data` is on. Index keys
+//! are stored in a tagged form (`["int", 1]`, `["float", bits]`, ...) so they come back
+//! exactly; text indexes always come back with `SimpleTokenizer`, and computed indexes
+//! (never written with data) come back awaiting re-registration of their function. An
+//! absent segment loads as "no indexes", and definitions found in node and edge segments
+//! written before this segment existed are still honoured.
+
+use super::persistence::index_definition;
+use super::computed_index::ComputedIndex;
+use super::{InMemoryGraphStore, NodeId};
+use super::prefix_index::PrefixIndex;
+use super::property_index::{IndexOptions, PropertyIndex};
+use super::text_index::{SimpleTokenizer, TextIndex};
+use crate::types::EngineError;
+use casys_core::{OrderedValue, ValueKey};
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub(crate) const INDEX_SEGMENT_ID: &str = "indexes";
+
+/// Layout version of the index segment; loading rejects any other version.
+const FORMAT_VERSION: u64 = 1;
+
+impl InMemoryGraphStore {
+    pub(crate) fn serialize_indexes(&self) -> Result<Vec<u8>, EngineError> {
+        let data = self.options.persist_index_data;
+        let mut defs = Vec::new();
+        for (label, key) in self.property_indexes() {
+            let index = &self.property_indexes[&(label.clone(), key.clone())];
+            let mut def = json!({
+                "kind": "property",
+                "label": label,
+                "key": key,
+                "case_insensitive": index.options.case_insensitive,
+                "trim_whitespace": index.options.trim_whitespace,
+                "multi_value": index.options.multi_value,
+            });
+            if !index.covered.is_empty() {
+                def["covered"] = json!(index.covered);
+            }
+            if data {
+                def["data"] = buckets_json(&index.buckets, encode_key);
+            }
+            defs.push(def);
+        }
+        for (label, key) in self.range_indexes() {
+            let mut def = json!({ "kind": "range", "label": label, "key": key });
+            if data {
+                def["data"] = buckets_json(&self.range_indexes[&(label, key)], encode_ordered);
+            }
+            defs.push(def);
+        }
+        for (label, keys) in self.composite_indexes() {
+            let mut def = json!({ "kind": "composite", "label": label, "keys": keys });
+            if data {
+                let encode = |tuple: &Vec<ValueKey>| Json::Array(tuple.iter().map(encode_key).collect());
+                def["data"] = buckets_json(&self.composite_indexes[&(label, keys)], encode);
+            }
+            defs.push(def);
+        }
+        for (label, key) in self.text_indexes() {
+            let mut def = json!({ "kind": "text", "label": label, "key": key });
+            if data {
+                def["data"] = buckets_json(&self.text_indexes[&(label, key)].postings, |t: &String| json!(t));
+            }
+            defs.push(def);
+        }
+        for (label, key, case_sensitive) in self.prefix_indexes() {
+            let mut def = json!({ "kind": "prefix", "label": label, "key": key, "case_sensitive": case_sensitive });
+            if data {
+                def["data"] = buckets_json(&self.prefix_indexes[&(label, key)].entries, |v: &String| json!(v));
+            }
+            defs.push(def);
+        }
+        for (edge_type, key) in self.edge_indexes() {
+            let mut def = json!({ "kind": "edge", "edge_type": edge_type, "key": key });
+            if data {
+                def["data"] = buckets_json(&self.edge_indexes[&(edge_type, key)], encode_key);
+            }
+            defs.push(def);
+        }
+        for (label, key) in self.ttl_indexes() {
+            let mut def = json!({ "kind": "ttl", "label": label, "key": key });
+            if data {
+                def["data"] = buckets_json(&self.ttl_indexes[&(label, key)], |at: &i64| json!(at));
+            }
+            defs.push(def);
+        }
+        // The function cannot be written, so neither are the buckets
+        for (label, name) in self.computed_indexes() {
+            defs.push(json!({ "kind": "computed", "label": label, "name": name }));
+        }
+        for (label, key) in self.unique_constraints() {
+            defs.push(json!({ "kind": "unique", "label": label, "key": key }));
+        }
+
+        serde_json::to_vec(&json!({ "format_version": FORMAT_VERSION, "indexes": defs }))
+            .map_err(|e| EngineError::StorageIo(format!("serialize indexes: {}", e)))
+    }
+
+    /// Recreate the indexes of an index segment. Indexes already defined (e.g. from the
+    /// definitions of an older node segment) are left alone.
+    pub(crate) fn deserialize_indexes(&mut self, data: &[u8]) -> Result<(), EngineError> {
+        let json: Json = serde_json::from_slice(data)
+            .map_err(|e| EngineError::StorageIo(format!("parse indexes: {}", e)))?;
+        match json["format_version"].as_u64() {
+            Some(FORMAT_VERSION) => {}
+            other => {
+                return Err(EngineError::StorageIo(format!("unsupported index segment format version: {:?}", other)));
+            }
+        }
+        let defs = json["indexes"].as_array().map(Vec::as_slice).unwrap_or_default();
+        // Constraints last, so they find the property indexes they rely on
+        for def in defs.iter().filter(|d| d["kind"] != "unique") {
+            self.load_index(def)?;
+        }
+        for def in defs.iter().filter(|d| d["kind"] == "unique") {
+            let (label, key) = index_definition(def)?;
+            if self.unique_constraints.contains(&(label.to_string(), key.to_string())) {
+                continue;
+            }
+            // The property index was not flushed with the constraint: it had been dropped
+            let indexed = self.has_property_index(label, key);
+            self.create_unique_constraint(label, key)?;
+            if !indexed {
+                self.drop_property_index(label, key)?;
+            }
+        }
+        self.track_all_indexes();
+        Ok(())
+    }
+
+    fn load_index(&mut self, def: &Json) -> Result<(), EngineError> {
+        // Buckets of the whole graph; a partial store backfills from the nodes it holds
+        let data = def.get("data").filter(|_| self.partial.is_none());
+        match def["kind"].as_str() {
+            Some("property") => {
+                let (label, key) = index_definition(def)?;
+                let name = (label.to_string(), key.to_string());
+                let options = IndexOptions {
+                    case_insensitive: def["case_insensitive"].as_bool().unwrap_or(false),
+                    trim_whitespace: def["trim_whitespace"].as_bool().unwrap_or(false),
+                    multi_value: def["multi_value"].as_bool().unwrap_or(false),
+                };
+                let covered: Vec<&str> = def["covered"].as_array().map(Vec::as_slice).unwrap_or_default()
+                    .iter()
+                    .map(Json::as_str)
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid(def))?;
+                if self.property_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => {
+                        let buckets = parse_buckets(data, decode_key, def)?;
+                        let mut index = PropertyIndex::new(options, covered.iter().map(|k| k.to_string()).collect());
+                        index.buckets = buckets;
+                        index.refill_rows(&self.nodes);
+                        self.property_indexes.insert(name, index);
+                    }
+                    None if covered.is_empty() => self.create_property_index_with(label, key, options)?,
+                    None => self.create_covering_index(label, key, options, &covered)?,
+                }
+            }
+            Some("range") => {
+                let (label, key) = index_definition(def)?;
+                let name = (label.to_string(), key.to_string());
+                if self.range_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => { self.range_indexes.insert(name, parse_buckets(data, decode_ordered, def)?); }
+                    None => self.create_range_index(label, key)?,
+                }
+            }
+            Some("composite") => {
+                let label = def["label"].as_str();
+                let keys: Option<Vec<&str>> = def["keys"].as_array().and_then(|keys| keys.iter().map(Json::as_str).collect());
+                let (Some(label), Some(keys)) = (label, keys) else { return Err(invalid(def)) };
+                let name = (label.to_string(), keys.iter().map(|k| k.to_string()).collect::<Vec<_>>());
+                if self.composite_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => {
+                        let decode = |json: &Json| json.as_array()?.iter().map(decode_key).collect::<Option<Vec<_>>>();
+                        self.composite_indexes.insert(name, parse_buckets(data, decode, def)?);
+                    }
+                    None => self.create_composite_index(label, &keys)?,
+                }
+            }
+            Some("text") => {
+                let (label, key) = index_definition(def)?;
+                let name = (label.to_string(), key.to_string());
+                if self.text_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => {
+                        let postings = parse_buckets(data, |t| t.as_str().map(str::to_string), def)?;
+                        self.text_indexes.insert(name, TextIndex { tokenizer: Arc::new(SimpleTokenizer), postings });
+                    }
+                    None => self.create_text_index(label, key)?,
+                }
+            }
+            Some("prefix") => {
+                let (label, key) = index_definition(def)?;
+                let name = (label.to_string(), key.to_string());
+                let case_sensitive = def["case_sensitive"].as_bool().unwrap_or(true);
+                if self.prefix_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => {
+                        let entries = parse_buckets(data, |v| v.as_str().map(str::to_string), def)?;
+                        self.prefix_indexes.insert(name, PrefixIndex { case_sensitive, entries });
+                    }
+                    None => self.create_prefix_index(label, key, case_sensitive)?,
+                }
+            }
+            Some("edge") => {
+                let (Some(edge_type), Some(key)) = (def["edge_type"].as_str(), def["key"].as_str()) else {
+                    return Err(invalid(def));
+                };
+                let name = (edge_type.to_string(), key.to_string());
+                if self.edge_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => { self.edge_indexes.insert(name, parse_buckets(data, decode_key, def)?); }
+                    None => self.create_edge_index(edge_type, key)?,
+                }
+            }
+            Some("ttl") => {
+                let (label, key) = index_definition(def)?;
+                let name = (label.to_string(), key.to_string());
+                if self.ttl_indexes.contains_key(&name) {
+                    return Ok(());
+                }
+                match data {
+                    Some(data) => { self.ttl_indexes.insert(name, parse_buckets(data, Json::as_i64, def)?); }
+                    None => self.create_ttl_index(label, key)?,
+                }
+            }
+            Some("computed") => {
+                let (Some(label), Some(name)) = (def["label"].as_str(), def["name"].as_str()) else {
+                    return Err(invalid(def));
+                };
+                // Awaits re-registration of its function
+                self.computed_indexes.entry((label.to_string(), name.to_string()))
+                    .or_insert_with(|| ComputedIndex { function: None, buckets: HashMap::new() });
+            }
+            _ => return Err(invalid(def)),
+        }
+        Ok(())
+    }
+}
+
+fn invalid(def: &Json) -> EngineError {
+    EngineError::StorageIo(format!("invalid index definition: {}", def))
+}
+
+/// `[[key, [ids...]], ...]`, sorted by key so identical stores flush identical bytes.
+fn buckets_json<'a, K: Ord + 'a>(buckets: impl IntoIterator<Item = (&'a K, &'a Vec<NodeId>)>, encode: impl Fn(&K) -> Json) -> Json {
+    let mut buckets: Vec<(&K, &Vec<NodeId>)> = buckets.into_iter().collect();
+    buckets.sort_by(|a, b| a.0.cmp(b.0));
+    Json::Array(buckets.into_iter().map(|(key, ids)| json!([encode(key), ids])).collect())
+}
+
+/// Inverse of `buckets_json`, into any bucket map.
+fn parse_buckets<K, C>(data: &Json, decode: impl Fn(&Json) -> Option<K>, def: &Json) -> Result<C, EngineError>
+where
+    C: FromIterator<(K, Vec<NodeId>)>,
+{
+    let buckets = data.as_array().ok_or_else(|| invalid(def))?;
+    buckets.iter()
+        .map(|bucket| {
+            let key = decode(&bucket[0])?;
+            let ids = bucket[1].as_array()?.iter().map(Json::as_u64).collect::<Option<Vec<_>>>()?;
+            Some((key, ids))
+        })
+        .collect::<Option<C>>()
+        .ok_or_else(|| invalid(def))
+}
+
+fn encode_key(key: &ValueKey) -> Json {
+    match key {
+        ValueKey::Null => json!(["null"]),
+        ValueKey::Bool(b) => json!(["bool", b]),
+        ValueKey::Int(i) => json!(["int", i]),
+        ValueKey::Float(bits) => json!(["float", bits]),
+        ValueKey::String(s) => json!(["string", s]),
+        ValueKey::Bytes(b) => json!(["bytes", b]),
+        ValueKey::Array(items) => json!(["array", items.iter().map(encode_key).collect::<Vec<_>>()]),
+        ValueKey::Map(map) => {
+            let map: serde_json::Map<String, Json> = map.iter().map(|(k, v)| (k.clone(), encode_key(v))).collect();
+            json!(["map", map])
+        }
+        ValueKey::NodeId(id) => json!(["node", id]),
+    }
+}
+
+fn decode_key(json: &Json) -> Option<ValueKey> {
+    let value = &json[1];
+    Some(match json[0].as_str()? {
+        "null" => ValueKey::Null,
+        "bool" => ValueKey::Bool(value.as_bool()?),
+        "int" => ValueKey::Int(value.as_i64()?),
+        "float" => ValueKey::Float(value.as_u64()?),
+        "string" => ValueKey::String(value.as_str()?.to_string()),
+        "bytes" => ValueKey::Bytes(value.as_array()?.iter().map(|b| u8::try_from(b.as_u64()?).ok()).collect::<Option<_>>()?),
+        "array" => ValueKey::Array(value.as_array()?.iter().map(decode_key).collect::<Option<_>>()?),
+        "map" => ValueKey::Map(value.as_object()?.iter().map(|(k, v)| Some((k.clone(), decode_key(v)?))).collect::<Option<_>>()?),
+        "node" => ValueKey::NodeId(value.as_u64()?),
+        _ => return None,
+    })
+}
+
+pub(crate) fn encode_ordered(value: &OrderedValue) -> Json {
+    match value {
+        OrderedValue::Number(n) => json!(["number", n.to_bits()]),
+        OrderedValue::String(s) => json!(["string", s]),
+    }
+}
+
+pub(crate) fn decode_ordered(json: &Json) -> Option<OrderedValue> {
+    Some(match json[0].as_str()? {
+        "number" => OrderedValue::Number(f64::from_bits(json[1].as_u64()?)),
+        "string" => OrderedValue::String(json[1].as_str()?.to_string()),
+        _ => return None,
+    })
+}
@@ -0,0 +1,193 @@
+//! Index introspection: what secondary indexes exist, how big they are and how often
+//! their lookups find something
+//!
+//! Every property, range, composite, text, prefix, edge, computed and TTL index has a pair of counters,
+//! bumped by the lookup paths that read it: a lookup is a hit when the index yields at
+//! least one id and a miss otherwise (checks made on behalf of unique constraints count
+//! too). Counters are relaxed atomics so `&self` lookups can bump them without a lock;
+//! they live as long as the index, survive `rebuild_indexes` and `truncate`, and are not
+//! persisted.
+
+use super::InMemoryGraphStore;
+use casys_core::{OrderedValue, ValueKey};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The kind of a secondary index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IndexKind {
+    Property,
+    Range,
+    Composite,
+    Text,
+    Prefix,
+    /// Keyed by edge type rather than label
+    Edge,
+    /// Keyed by the index name rather than a property key
+    Computed,
+    Ttl,
+}
+
+/// One secondary index as reported by `list_indexes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexInfo {
+    pub kind: IndexKind,
+    /// The indexed label, or edge type for `IndexKind::Edge`
+    pub label: String,
+    /// The indexed keys: one, except for composite indexes
+    pub keys: Vec<String>,
+    /// Ids filed in the index, summed over its buckets
+    pub entries: usize,
+    /// Rough size of the buckets in bytes (keys, id lists and map overhead per bucket)
+    pub approx_bytes: usize,
+}
+
+/// Lookup counters of one secondary index, as reported by `index_stats`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexStats {
+    pub kind: IndexKind,
+    pub label: String,
+    pub keys: Vec<String>,
+    /// Lookups that found at least one id
+    pub hits: u64,
+    /// Lookups that found nothing
+    pub misses: u64,
+}
+
+/// `(kind, label or edge type, keys)` of an index.
+pub(crate) type IndexId = (IndexKind, String, Vec<String>);
+
+#[derive(Debug, Default)]
+pub(crate) struct IndexCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Counters of every index, keyed by `IndexId`.
+pub(crate) type IndexUsage = HashMap<IndexId, IndexCounters>;
+
+impl InMemoryGraphStore {
+    /// Every secondary index with its size, ordered by kind, label and keys.
+    pub fn list_indexes(&self) -> Vec<IndexInfo> {
+        let info = |kind, label: &str, keys: Vec<String>, (entries, approx_bytes)| IndexInfo {
+            kind,
+            label: label.to_string(),
+            keys,
+            entries,
+            approx_bytes,
+        };
+        let mut list = Vec::new();
+        for ((label, key), index) in &self.property_indexes {
+            list.push(info(IndexKind::Property, label, vec![key.clone()], footprint(&index.buckets, value_key_heap)));
+        }
+        for ((label, key), buckets) in &self.range_indexes {
+            list.push(info(IndexKind::Range, label, vec![key.clone()], footprint(buckets, ordered_heap)));
+        }
+        for ((label, keys), buckets) in &self.composite_indexes {
+            let tuple_heap = |tuple: &Vec<ValueKey>| tuple.iter().map(|k| size_of::<ValueKey>() + value_key_heap(k)).sum();
+            list.push(info(IndexKind::Composite, label, keys.clone(), footprint(buckets, tuple_heap)));
+        }
+        for ((label, key), index) in &self.text_indexes {
+            list.push(info(IndexKind::Text, label, vec![key.clone()], footprint(&index.postings, String::capacity)));
+        }
+        for ((label, key), index) in &self.prefix_indexes {
+            list.push(info(IndexKind::Prefix, label, vec![key.clone()], footprint(&index.entries, String::capacity)));
+        }
+        for ((edge_type, key), buckets) in &self.edge_indexes {
+            list.push(info(IndexKind::Edge, edge_type, vec![key.clone()], footprint(buckets, value_key_heap)));
+        }
+        for ((label, key), buckets) in &self.ttl_indexes {
+            list.push(info(IndexKind::Ttl, label, vec![key.clone()], footprint(buckets, |_| 0)));
+        }
+        for ((label, name), index) in &self.computed_indexes {
+            list.push(info(IndexKind::Computed, label, vec![name.clone()], footprint(&index.buckets, value_key_heap)));
+        }
+        list.sort_by(|a, b| (a.kind, &a.label, &a.keys).cmp(&(b.kind, &b.label, &b.keys)));
+        list
+    }
+
+    /// Hit and miss counts of every secondary index, in `list_indexes` order.
+    pub fn index_stats(&self) -> Vec<IndexStats> {
+        self.list_indexes()
+            .into_iter()
+            .map(|info| {
+                let counters = self.index_usage.get(&(info.kind, info.label.clone(), info.keys.clone()));
+                let read = |counter: fn(&IndexCounters) -> &AtomicU64| counters.map_or(0, |c| counter(c).load(Ordering::Relaxed));
+                IndexStats {
+                    kind: info.kind,
+                    label: info.label,
+                    keys: info.keys,
+                    hits: read(|c| &c.hits),
+                    misses: read(|c| &c.misses),
+                }
+            })
+            .collect()
+    }
+
+    /// Zero the counters of every index.
+    pub fn reset_index_stats(&self) {
+        for counters in self.index_usage.values() {
+            counters.hits.store(0, Ordering::Relaxed);
+            counters.misses.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Count one lookup of the index `(kind, label, keys)`, a hit if it found anything.
+    pub(crate) fn record_index_lookup(&self, kind: IndexKind, label: &str, keys: &[&str], found: bool) {
+        let id = (kind, label.to_string(), keys.iter().map(|k| k.to_string()).collect());
+        if let Some(counters) = self.index_usage.get(&id) {
+            let counter = if found { &counters.hits } else { &counters.misses };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Give the index `(kind, label, keys)` counters, keeping existing ones. Called by
+    /// every `create_*` method, so rebuilding an index keeps its counts.
+    pub(crate) fn track_index(&mut self, kind: IndexKind, label: &str, keys: &[&str]) {
+        let id = (kind, label.to_string(), keys.iter().map(|k| k.to_string()).collect());
+        self.index_usage.entry(id).or_default();
+    }
+
+    /// Drop the counters of a dropped index.
+    pub(crate) fn untrack_index(&mut self, kind: IndexKind, label: &str, keys: &[&str]) {
+        let id = (kind, label.to_string(), keys.iter().map(|k| k.to_string()).collect());
+        self.index_usage.remove(&id);
+    }
+
+    /// Give counters to every index that lacks them, e.g. indexes loaded with their
+    /// flushed contents rather than through `create_*`.
+    pub(crate) fn track_all_indexes(&mut self) {
+        for info in self.list_indexes() {
+            self.index_usage.entry((info.kind, info.label, info.keys)).or_default();
+        }
+    }
+}
+
+/// `(entries, approximate bytes)` of a bucket map, given the heap size of one key.
+fn footprint<'a, K: 'a, M>(buckets: M, key_heap: impl Fn(&K) -> usize) -> (usize, usize)
+where
+    M: IntoIterator<Item = (&'a K, &'a Vec<u64>)>,
+{
+    buckets.into_iter().fold((0, 0), |(entries, bytes), (key, ids)| {
+        let bucket = size_of::<K>() + size_of::<Vec<u64>>() + key_heap(key) + ids.capacity() * size_of::<u64>();
+        (entries + ids.len(), bytes + bucket)
+    })
+}
+
+fn value_key_heap(key: &ValueKey) -> usize {
+    match key {
+        ValueKey::String(s) => s.capacity(),
+        ValueKey::Bytes(b) => b.capacity(),
+        ValueKey::Array(items) => items.iter().map(|k| size_of::<ValueKey>() + value_key_heap(k)).sum(),
+        ValueKey::Map(map) => map.iter().map(|(k, v)| k.capacity() + size_of::<(String, ValueKey)>() + value_key_heap(v)).sum(),
+        ValueKey::Null | ValueKey::Bool(_) | ValueKey::Int(_) | ValueKey::Float(_) | ValueKey::NodeId(_) => 0,
+    }
+}
+
+fn ordered_heap(value: &OrderedValue) -> usize {
+    match value {
+        OrderedValue::String(s) => s.capacity(),
+        OrderedValue::Number(_) => 0,
+    }
+}
@@ -0,0 +1,564 @@
+//! Index integrity: verify secondary indexes against primary data and rebuild them
+//!
+//! `nodes` and `edges` are the source of truth. `label_index`, `edge_type_index`,
+//! `adjacency_out`, `adjacency_in`, the typed adjacency maps, the property, text, prefix,
+//! computed, TTL and edge indexes must hold exactly one entry per live record (tombstones are not indexed).
+//! `verify_indexes` lists the differences; `check_indexes` sorts them into a report, and
+//! `repair_indexes` rebuilds exactly the structures the report implicates.
+
+use super::index_stats::IndexKind;
+use super::ttl_index::expiry_of;
+use super::{InMemoryGraphStore, NodeId, EdgeId};
+use casys_core::{OrderedValue, ValueKey};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// One entry of a secondary index.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IndexEntry {
+    /// `label_index[label]` contains `node`
+    Label { label: String, node: NodeId },
+    /// `adjacency_out[node]` contains `edge`
+    Outgoing { node: NodeId, edge: EdgeId },
+    /// `adjacency_in[node]` contains `edge`
+    Incoming { node: NodeId, edge: EdgeId },
+    /// `edge_type_index[edge_type]` contains `edge`
+    EdgeType { edge_type: String, edge: EdgeId },
+    /// `typed_out[node][edge_type]` contains `edge` (only with `typed_adjacency`)
+    TypedOutgoing { node: NodeId, edge_type: String, edge: EdgeId },
+    /// `typed_in[node][edge_type]` contains `edge` (only with `typed_adjacency`)
+    TypedIncoming { node: NodeId, edge_type: String, edge: EdgeId },
+    /// The property index on `(label, key)` files `node` under `value`
+    Property { label: String, key: String, value: ValueKey, node: NodeId },
+    /// The covering index on `(label, key)` holds `node` under `value` with these copies
+    /// of its covered properties
+    Covered { label: String, key: String, value: ValueKey, node: NodeId, covered: Vec<Option<ValueKey>> },
+    /// The range index on `(label, key)` files `node` under `value`
+    Range { label: String, key: String, value: OrderedValue, node: NodeId },
+    /// The composite index on `(label, keys)` files `node` under `values`
+    Composite { label: String, keys: Vec<String>, values: Vec<ValueKey>, node: NodeId },
+    /// The text index on `(label, key)` files `node` under `token`
+    Text { label: String, key: String, token: String, node: NodeId },
+    /// The prefix index on `(label, key)` files `node` under `value` (case-folded if the
+    /// index is case-insensitive)
+    Prefix { label: String, key: String, value: String, node: NodeId },
+    /// The computed index `name` on `label` files `node` under `value` (registered
+    /// indexes only: one awaiting its function holds and expects nothing)
+    Computed { label: String, name: String, value: ValueKey, node: NodeId },
+    /// The TTL index on `(label, key)` files `node` under timestamp `at`
+    Ttl { label: String, key: String, at: i64, node: NodeId },
+    /// The edge index on `(edge_type, key)` files `edge` under `value`
+    EdgeProperty { edge_type: String, key: String, value: ValueKey, edge: EdgeId },
+}
+
+/// A difference between an index and the primary data, as reported by `verify_indexes`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IndexInconsistency {
+    /// The primary data implies the entry but the index lacks it
+    Missing(IndexEntry),
+    /// The index has an entry no live record accounts for
+    Extra(IndexEntry),
+    /// The entry is expected once but listed `count` times
+    Duplicate { entry: IndexEntry, count: usize },
+}
+
+/// The structure an `IndexEntry` lives in, and the unit `repair_indexes` rebuilds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IndexStructure {
+    /// `label_index`
+    Labels,
+    /// `adjacency_out` and `adjacency_in`
+    Adjacency,
+    /// `edge_type_index`
+    EdgeTypes,
+    /// `typed_out` and `typed_in`
+    TypedAdjacency,
+    /// The property, range, composite, text, prefix, edge, computed or TTL indexes
+    Secondary(IndexKind),
+}
+
+/// A tombstone counter that disagrees with the records it counts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountMismatch {
+    /// `"deleted_nodes"` or `"deleted_edges"`
+    pub counter: &'static str,
+    pub recorded: usize,
+    pub actual: usize,
+}
+
+/// What `check_indexes` / `repair_indexes` found, each list sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexCheckReport {
+    /// Entries pointing at a node or edge that does not exist or is tombstoned
+    pub dangling: Vec<IndexEntry>,
+    /// Entries the live records imply but the index lacks
+    pub missing: Vec<IndexEntry>,
+    /// Entries for live records filed where the records do not put them (wrong label,
+    /// value, endpoint or type)
+    pub stale: Vec<IndexEntry>,
+    /// Entries listed more than once, with their count
+    pub duplicates: Vec<(IndexEntry, usize)>,
+    /// Tombstone counters behind `node_count` / `edge_count` that are off
+    pub count_mismatches: Vec<CountMismatch>,
+    /// Structures rebuilt by `repair_indexes`; always empty from `check_indexes`
+    pub repaired: Vec<IndexStructure>,
+}
+
+impl IndexCheckReport {
+    /// True when nothing was found.
+    pub fn is_clean(&self) -> bool {
+        self.dangling.is_empty()
+            && self.missing.is_empty()
+            && self.stale.is_empty()
+            && self.duplicates.is_empty()
+            && self.count_mismatches.is_empty()
+    }
+
+    /// The structures holding at least one of the reported entries, sorted.
+    pub fn affected(&self) -> Vec<IndexStructure> {
+        let entries = self.dangling.iter()
+            .chain(&self.missing)
+            .chain(&self.stale)
+            .chain(self.duplicates.iter().map(|(entry, _)| entry));
+        let affected: BTreeSet<IndexStructure> = entries.map(IndexEntry::structure).collect();
+        affected.into_iter().collect()
+    }
+}
+
+/// Where the secondary indexes mention a node id, as returned by `index_references`.
+///
+/// Read straight from the indexes, independently of what the node record (if any) claims.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexRefs {
+    /// Labels whose `label_index` bucket lists the node (sorted, once per bucket)
+    pub label_buckets: Vec<String>,
+    /// `adjacency_out[node]`, as stored
+    pub outgoing: Vec<EdgeId>,
+    /// `adjacency_in[node]`, as stored
+    pub incoming: Vec<EdgeId>,
+}
+
+impl IndexRefs {
+    /// True when no index mentions the node.
+    pub fn is_empty(&self) -> bool {
+        self.label_buckets.is_empty() && self.outgoing.is_empty() && self.incoming.is_empty()
+    }
+}
+
+impl IndexEntry {
+    /// The structure this entry belongs to.
+    pub fn structure(&self) -> IndexStructure {
+        match self {
+            IndexEntry::Label { .. } => IndexStructure::Labels,
+            IndexEntry::Outgoing { .. } | IndexEntry::Incoming { .. } => IndexStructure::Adjacency,
+            IndexEntry::EdgeType { .. } => IndexStructure::EdgeTypes,
+            IndexEntry::TypedOutgoing { .. } | IndexEntry::TypedIncoming { .. } => IndexStructure::TypedAdjacency,
+            IndexEntry::Property { .. } | IndexEntry::Covered { .. } => IndexStructure::Secondary(IndexKind::Property),
+            IndexEntry::Range { .. } => IndexStructure::Secondary(IndexKind::Range),
+            IndexEntry::Composite { .. } => IndexStructure::Secondary(IndexKind::Composite),
+            IndexEntry::Text { .. } => IndexStructure::Secondary(IndexKind::Text),
+            IndexEntry::Prefix { .. } => IndexStructure::Secondary(IndexKind::Prefix),
+            IndexEntry::Computed { .. } => IndexStructure::Secondary(IndexKind::Computed),
+            IndexEntry::Ttl { .. } => IndexStructure::Secondary(IndexKind::Ttl),
+            IndexEntry::EdgeProperty { .. } => IndexStructure::Secondary(IndexKind::Edge),
+        }
+    }
+}
+
+impl fmt::Display for IndexEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexEntry::Label { label, node } => write!(f, "label_index[{}] -> node {}", label, node),
+            IndexEntry::Outgoing { node, edge } => write!(f, "adjacency_out[{}] -> edge {}", node, edge),
+            IndexEntry::Incoming { node, edge } => write!(f, "adjacency_in[{}] -> edge {}", node, edge),
+            IndexEntry::EdgeType { edge_type, edge } => write!(f, "edge_type_index[{}] -> edge {}", edge_type, edge),
+            IndexEntry::TypedOutgoing { node, edge_type, edge } => write!(f, "typed_out[{}][{}] -> edge {}", node, edge_type, edge),
+            IndexEntry::TypedIncoming { node, edge_type, edge } => write!(f, "typed_in[{}][{}] -> edge {}", node, edge_type, edge),
+            IndexEntry::Property { label, key, value, node } => {
+                write!(f, "property_index[{}.{} = {:?}] -> node {}", label, key, value, node)
+            }
+            IndexEntry::Covered { label, key, value, node, covered } => {
+                write!(f, "property_index[{}.{} = {:?}] -> node {} covering {:?}", label, key, value, node, covered)
+            }
+            IndexEntry::Range { label, key, value, node } => {
+                write!(f, "range_index[{}.{} = {:?}] -> node {}", label, key, value, node)
+            }
+            IndexEntry::Composite { label, keys, values, node } => {
+                write!(f, "composite_index[{}{:?} = {:?}] -> node {}", label, keys, values, node)
+            }
+            IndexEntry::Text { label, key, token, node } => {
+                write!(f, "text_index[{}.{} : {}] -> node {}", label, key, token, node)
+            }
+            IndexEntry::Prefix { label, key, value, node } => {
+                write!(f, "prefix_index[{}.{} = {:?}] -> node {}", label, key, value, node)
+            }
+            IndexEntry::Computed { label, name, value, node } => {
+                write!(f, "computed_index[{}.{} = {:?}] -> node {}", label, name, value, node)
+            }
+            IndexEntry::Ttl { label, key, at, node } => write!(f, "ttl_index[{}.{} = {}] -> node {}", label, key, at, node),
+            IndexEntry::EdgeProperty { edge_type, key, value, edge } => {
+                write!(f, "edge_index[{}.{} = {:?}] -> edge {}", edge_type, key, value, edge)
+            }
+        }
+    }
+}
+
+impl fmt::Display for IndexInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexInconsistency::Missing(entry) => write!(f, "missing {}", entry),
+            IndexInconsistency::Extra(entry) => write!(f, "extra {}", entry),
+            IndexInconsistency::Duplicate { entry, count } => write!(f, "{} listed {} times", entry, count),
+        }
+    }
+}
+
+impl fmt::Display for CountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is {} but {} records are tombstoned", self.counter, self.recorded, self.actual)
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Compare every secondary index with the primary maps without changing anything.
+    ///
+    /// # Errors
+    /// Returns every inconsistency found, sorted, so the report is stable between runs.
+    pub fn verify_indexes(&self) -> Result<(), Vec<IndexInconsistency>> {
+        let problems = self.index_differences();
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    /// Check every index and the tombstone counters against the records, telling dangling
+    /// entries (pointing at a missing or tombstoned record) from stale ones (a live record
+    /// filed in the wrong place). Cheap enough to call right after `load` as a guard.
+    pub fn check_indexes(&self) -> IndexCheckReport {
+        let mut report = IndexCheckReport::default();
+        for problem in self.index_differences() {
+            match problem {
+                IndexInconsistency::Missing(entry) => report.missing.push(entry),
+                IndexInconsistency::Duplicate { entry, count } => report.duplicates.push((entry, count)),
+                IndexInconsistency::Extra(entry) if self.points_at_live_record(&entry) => report.stale.push(entry),
+                IndexInconsistency::Extra(entry) => report.dangling.push(entry),
+            }
+        }
+        let counters = [
+            ("deleted_nodes", self.deleted_nodes, self.nodes.values().filter(|n| n.deleted).count()),
+            ("deleted_edges", self.deleted_edges, self.edges.values().filter(|e| e.deleted).count()),
+        ];
+        for (counter, recorded, actual) in counters {
+            if recorded != actual {
+                report.count_mismatches.push(CountMismatch { counter, recorded, actual });
+            }
+        }
+        report
+    }
+
+    /// `check_indexes`, then rebuild every structure the report implicates (and recount
+    /// the tombstones if they were off) from `nodes` and `edges`. Returns what was found,
+    /// with `repaired` listing what was rebuilt; the store is consistent afterwards.
+    pub fn repair_indexes(&mut self) -> IndexCheckReport {
+        let mut report = self.check_indexes();
+        let affected = report.affected();
+        let secondary = affected.iter().any(|s| matches!(s, IndexStructure::Secondary(_)));
+        if affected.contains(&IndexStructure::Labels) {
+            self.rebuild_label_index();
+        }
+        if affected.contains(&IndexStructure::Adjacency) || affected.contains(&IndexStructure::EdgeTypes) {
+            self.rebuild_adjacency();
+        }
+        if affected.iter().any(|s| matches!(s, IndexStructure::Adjacency | IndexStructure::TypedAdjacency)) {
+            // Typed lists are derived from the plain ones
+            self.rebuild_typed_adjacency();
+        }
+        if secondary {
+            self.rebuild_property_indexes();
+        }
+        if !report.count_mismatches.is_empty() {
+            self.recount_tombstones();
+        }
+        report.repaired = affected;
+        report
+    }
+
+    /// Whether the record an entry points at exists and is live.
+    fn points_at_live_record(&self, entry: &IndexEntry) -> bool {
+        let live_node = |id: &NodeId| self.nodes.get(id).is_some_and(|n| !n.deleted);
+        let live_edge = |id: &EdgeId| self.edges.get(id).is_some_and(|e| !e.deleted);
+        match entry {
+            IndexEntry::Label { node, .. }
+            | IndexEntry::Property { node, .. }
+            | IndexEntry::Covered { node, .. }
+            | IndexEntry::Range { node, .. }
+            | IndexEntry::Composite { node, .. }
+            | IndexEntry::Text { node, .. }
+            | IndexEntry::Prefix { node, .. }
+            | IndexEntry::Computed { node, .. }
+            | IndexEntry::Ttl { node, .. } => live_node(node),
+            IndexEntry::Outgoing { edge, .. }
+            | IndexEntry::Incoming { edge, .. }
+            | IndexEntry::EdgeType { edge, .. }
+            | IndexEntry::TypedOutgoing { edge, .. }
+            | IndexEntry::TypedIncoming { edge, .. }
+            | IndexEntry::EdgeProperty { edge, .. } => live_edge(edge),
+        }
+    }
+
+    /// Every difference between the indexes and the records, sorted.
+    fn index_differences(&self) -> Vec<IndexInconsistency> {
+        let mut expected: BTreeSet<IndexEntry> = BTreeSet::new();
+        for node in self.nodes.values().filter(|n| !n.deleted) {
+            for label in &node.labels {
+                expected.insert(IndexEntry::Label { label: label.clone(), node: node.id });
+            }
+            for ((label, key), index) in &self.property_indexes {
+                let Some(value) = node.properties.get(key).filter(|_| node.labels.contains(label)) else { continue };
+                let covered: Vec<Option<ValueKey>> = index.covered_values(node).iter().map(|v| v.as_ref().map(ValueKey::from)).collect();
+                for value in index.options.keys(value) {
+                    if !index.covered.is_empty() {
+                        let (label, key, covered) = (label.clone(), key.clone(), covered.clone());
+                        expected.insert(IndexEntry::Covered { label, key, value: value.clone(), node: node.id, covered });
+                    }
+                    expected.insert(IndexEntry::Property { label: label.clone(), key: key.clone(), value, node: node.id });
+                }
+            }
+            for (label, key) in self.range_indexes.keys() {
+                let value = node.properties.get(key).filter(|_| node.labels.contains(label)).and_then(OrderedValue::from_value);
+                if let Some(value) = value {
+                    expected.insert(IndexEntry::Range { label: label.clone(), key: key.clone(), value, node: node.id });
+                }
+            }
+            for (label, keys) in self.composite_indexes.keys() {
+                let values: Option<Vec<ValueKey>> = keys.iter().map(|k| node.properties.get(k).map(ValueKey::from)).collect();
+                if let Some(values) = values.filter(|_| node.labels.contains(label)) {
+                    expected.insert(IndexEntry::Composite { label: label.clone(), keys: keys.clone(), values, node: node.id });
+                }
+            }
+            for ((label, key), index) in &self.text_indexes {
+                for token in index.tokens_of(node, label, key) {
+                    expected.insert(IndexEntry::Text { label: label.clone(), key: key.clone(), token, node: node.id });
+                }
+            }
+            for ((label, key), index) in &self.prefix_indexes {
+                if let Some(value) = index.entry_of(node, label, key) {
+                    expected.insert(IndexEntry::Prefix { label: label.clone(), key: key.clone(), value, node: node.id });
+                }
+            }
+            for (label, key) in self.ttl_indexes.keys() {
+                if let Some(at) = expiry_of(node, label, key) {
+                    expected.insert(IndexEntry::Ttl { label: label.clone(), key: key.clone(), at, node: node.id });
+                }
+            }
+            for ((label, name), index) in &self.computed_indexes {
+                if let Some(value) = index.key_of(node, label) {
+                    expected.insert(IndexEntry::Computed { label: label.clone(), name: name.clone(), value, node: node.id });
+                }
+            }
+        }
+        for edge in self.edges.values().filter(|e| !e.deleted) {
+            expected.insert(IndexEntry::Outgoing { node: edge.from_node, edge: edge.id });
+            expected.insert(IndexEntry::Incoming { node: edge.to_node, edge: edge.id });
+            expected.insert(IndexEntry::EdgeType { edge_type: edge.edge_type.clone(), edge: edge.id });
+            if self.options.typed_adjacency {
+                let edge_type = edge.edge_type.clone();
+                expected.insert(IndexEntry::TypedOutgoing { node: edge.from_node, edge_type: edge_type.clone(), edge: edge.id });
+                expected.insert(IndexEntry::TypedIncoming { node: edge.to_node, edge_type, edge: edge.id });
+            }
+            for (edge_type, key) in self.edge_indexes.keys() {
+                if let Some(value) = edge.properties.get(key).filter(|_| edge.edge_type == *edge_type) {
+                    let (edge_type, key, value) = (edge_type.clone(), key.clone(), ValueKey::from(value));
+                    expected.insert(IndexEntry::EdgeProperty { edge_type, key, value, edge: edge.id });
+                }
+            }
+        }
+
+        let mut actual: BTreeMap<IndexEntry, usize> = BTreeMap::new();
+        for (label, ids) in &self.label_index {
+            for id in ids {
+                *actual.entry(IndexEntry::Label { label: label.clone(), node: *id }).or_default() += 1;
+            }
+        }
+        for (node, ids) in &self.adjacency_out {
+            for id in ids {
+                *actual.entry(IndexEntry::Outgoing { node: *node, edge: *id }).or_default() += 1;
+            }
+        }
+        for (node, ids) in &self.adjacency_in {
+            for id in ids {
+                *actual.entry(IndexEntry::Incoming { node: *node, edge: *id }).or_default() += 1;
+            }
+        }
+        for (edge_type, ids) in &self.edge_type_index {
+            for id in ids {
+                *actual.entry(IndexEntry::EdgeType { edge_type: edge_type.clone(), edge: *id }).or_default() += 1;
+            }
+        }
+        for (node, buckets) in &self.typed_out {
+            for (edge_type, ids) in buckets {
+                for id in ids {
+                    let entry = IndexEntry::TypedOutgoing { node: *node, edge_type: edge_type.clone(), edge: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for (node, buckets) in &self.typed_in {
+            for (edge_type, ids) in buckets {
+                for id in ids {
+                    let entry = IndexEntry::TypedIncoming { node: *node, edge_type: edge_type.clone(), edge: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, key), index) in &self.property_indexes {
+            for (value, ids) in &index.buckets {
+                for id in ids {
+                    let entry = IndexEntry::Property { label: label.clone(), key: key.clone(), value: value.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+            for (value, rows) in &index.rows {
+                for (id, covered) in rows {
+                    let covered = covered.iter().map(|v| v.as_ref().map(ValueKey::from)).collect();
+                    let entry = IndexEntry::Covered { label: label.clone(), key: key.clone(), value: value.clone(), node: *id, covered };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, key), buckets) in &self.range_indexes {
+            for (value, ids) in buckets {
+                for id in ids {
+                    let entry = IndexEntry::Range { label: label.clone(), key: key.clone(), value: value.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, keys), buckets) in &self.composite_indexes {
+            for (values, ids) in buckets {
+                for id in ids {
+                    let entry = IndexEntry::Composite { label: label.clone(), keys: keys.clone(), values: values.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, key), index) in &self.text_indexes {
+            for (token, ids) in &index.postings {
+                for id in ids {
+                    let entry = IndexEntry::Text { label: label.clone(), key: key.clone(), token: token.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, key), index) in &self.prefix_indexes {
+            for (value, ids) in &index.entries {
+                for id in ids {
+                    let entry = IndexEntry::Prefix { label: label.clone(), key: key.clone(), value: value.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((label, key), buckets) in &self.ttl_indexes {
+            for (at, ids) in buckets {
+                for id in ids {
+                    *actual.entry(IndexEntry::Ttl { label: label.clone(), key: key.clone(), at: *at, node: *id }).or_default() += 1;
+                }
+            }
+        }
+        for ((label, name), index) in &self.computed_indexes {
+            for (value, ids) in &index.buckets {
+                for id in ids {
+                    let entry = IndexEntry::Computed { label: label.clone(), name: name.clone(), value: value.clone(), node: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+        for ((edge_type, key), buckets) in &self.edge_indexes {
+            for (value, ids) in buckets {
+                for id in ids {
+                    let entry = IndexEntry::EdgeProperty { edge_type: edge_type.clone(), key: key.clone(), value: value.clone(), edge: *id };
+                    *actual.entry(entry).or_default() += 1;
+                }
+            }
+        }
+
+        let mut problems = Vec::new();
+        for entry in &expected {
+            match actual.get(entry) {
+                None => problems.push(IndexInconsistency::Missing(entry.clone())),
+                Some(&count) if count > 1 => {
+                    problems.push(IndexInconsistency::Duplicate { entry: entry.clone(), count });
+                }
+                Some(_) => {}
+            }
+        }
+        for entry in actual.keys() {
+            if !expected.contains(entry) {
+                problems.push(IndexInconsistency::Extra(entry.clone()));
+            }
+        }
+
+        problems.sort();
+        problems
+    }
+
+    /// List every index entry keyed by or pointing at node `id`, whether or not the node
+    /// exists. Comparing this with `labels_of` and the node's edges pinpoints index drift.
+    pub fn index_references(&self, id: NodeId) -> IndexRefs {
+        let mut label_buckets: Vec<String> = self.label_index.iter()
+            .filter(|(_, ids)| ids.contains(&id))
+            .map(|(label, _)| label.clone())
+            .collect();
+        label_buckets.sort();
+        IndexRefs {
+            label_buckets,
+            outgoing: self.adjacency_out.get(&id).cloned().unwrap_or_default(),
+            incoming: self.adjacency_in.get(&id).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Discard `label_index`, `edge_type_index`, the adjacency maps (typed ones included)
+    /// and the property, range, composite, text, prefix, edge, computed and TTL index
+    /// buckets and rebuild them purely from `nodes` and `edges`. Buckets are filled in ascending id
+    /// order, and the tombstone counters behind `node_count` / `edge_count` are recounted.
+    pub fn rebuild_indexes(&mut self) {
+        self.recount_tombstones();
+        self.rebuild_label_index();
+        self.rebuild_adjacency();
+        self.rebuild_typed_adjacency();
+        self.rebuild_property_indexes();
+    }
+
+    /// Recount the tombstone counters behind `node_count` / `edge_count`.
+    fn recount_tombstones(&mut self) {
+        self.deleted_nodes = self.nodes.values().filter(|n| n.deleted).count();
+        self.deleted_edges = self.edges.values().filter(|e| e.deleted).count();
+    }
+
+    /// Refill `label_index` from the live nodes.
+    fn rebuild_label_index(&mut self) {
+        self.label_index.clear();
+        let mut node_ids: Vec<NodeId> = self.nodes.iter().filter(|(_, n)| !n.deleted).map(|(id, _)| *id).collect();
+        node_ids.sort_unstable();
+        for id in node_ids {
+            // A label repeated on the node is indexed once
+            for label in self.nodes[&id].labels.clone() {
+                self.index_label(&label, id);
+            }
+        }
+    }
+
+    /// Refill `adjacency_out`, `adjacency_in` and `edge_type_index`, in ascending id order.
+    fn rebuild_adjacency(&mut self) {
+        self.adjacency_out.clear();
+        self.adjacency_in.clear();
+        self.edge_type_index.clear();
+        let mut edge_ids: Vec<EdgeId> = self.edges.iter().filter(|(_, e)| !e.deleted).map(|(id, _)| *id).collect();
+        edge_ids.sort_unstable();
+        for id in edge_ids {
+            let edge = &self.edges[&id];
+            self.adjacency_out.entry(edge.from_node).or_default().push(id);
+            self.adjacency_in.entry(edge.to_node).or_default().push(id);
+            self.edge_type_index.entry(edge.edge_type.clone()).or_default().push(id);
+        }
+        self.resort_all_adjacency();
+    }
+}
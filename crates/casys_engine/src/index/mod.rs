@@ -4,50 +4,1271 @@
 //! Core persistence (flush/load with SegmentStore trait) is always available.
 //! FS convenience methods (flush_to_fs/load_from_fs) require the `fs` feature.
 
+pub mod access_path;
+pub mod batch;
+mod computed_index;
+pub mod constraints;
+mod group_commit;
+pub mod ids;
+mod index_segment;
+pub mod index_stats;
+pub mod integrity;
 pub mod persistence;
+mod prefix_index;
+mod segment_binary;
+mod segment_compress;
+mod segment_delta;
+mod segment_partition;
+pub mod property_index;
+pub mod sorted_adjacency;
+pub mod statistics;
+pub mod text_index;
+mod ttl_index;
+mod typed_adjacency;
+mod wal_archive;
+mod wal_binary;
+mod wal_compress;
+mod wal_file;
+mod wal_inspect;
+mod wal_verify;
 
 use crate::types::EngineError;
-use std::collections::HashMap;
+use computed_index::ComputedIndex;
+use ids::IdAllocator;
+use index_stats::IndexUsage;
+use persistence::{Lsn, TxnId, WalRecord, WalWriter};
+use prefix_index::PrefixIndex;
+use segment_delta::ChangeSet;
+use segment_partition::PartialLoad;
+use property_index::{CompositeIndex, EdgeIndex, PropertyIndex, RangeIndex};
+use text_index::TextIndex;
+use ttl_index::TtlIndex;
+use typed_adjacency::TypedAdjacency;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub use access_path::AccessPath;
+pub use batch::{BatchResult, EdgeRef, Mutation, NodeRef};
+pub use computed_index::ComputedKey;
+pub use index_stats::{IndexInfo, IndexKind, IndexStats};
+pub use integrity::{CountMismatch, IndexCheckReport, IndexEntry, IndexInconsistency, IndexRefs, IndexStructure};
+pub use property_index::{CoveredNode, IndexOptions};
+pub use segment_binary::SegmentFormat;
+pub use segment_compress::CompressionOption;
+pub use segment_delta::CompactionReport;
+pub use segment_partition::PartialEdges;
+pub use sorted_adjacency::NeighborOrder;
+pub use statistics::{GraphStatistics, Histogram, PropertyStatistics};
+pub use text_index::{SimpleTokenizer, TextMatch, Tokenizer};
+pub use ttl_index::ExpiredReport;
 
 // Re-export graph types and traits from casys_core (AC5: backward compatibility)
 pub use casys_core::{
     Value, NodeId, EdgeId,
-    Node, Edge,
+    Node, Edge, Direction, LabelMatch, ScanCursor,
     GraphReadStore, GraphWriteStore,
 };
 
+/// A node or edge whose properties an operation targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyTarget {
+    Node(NodeId),
+    Edge(EdgeId),
+}
+
+/// What `copy_properties` does when the destination already has a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyConflict {
+    /// Replace the destination value.
+    Overwrite,
+    /// Keep the destination value and skip the key.
+    SkipExisting,
+    /// Fail without writing anything.
+    Error,
+}
+
+/// One end of an edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    From,
+    To,
+}
+
+/// Behaviour switches for an `InMemoryGraphStore`, fixed at construction.
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Deletes mark records as tombstoned instead of removing them; see `purge_tombstones`.
+    pub soft_delete: bool,
+    /// `add_edge` and friends reject endpoints that are not live nodes (default: on).
+    /// Loaders that insert edges before nodes can turn this off or use `add_edge_unchecked`.
+    pub strict_edges: bool,
+    /// Ids of physically removed nodes and edges are handed out again (default: off, so
+    /// ids cached by callers are never reassigned).
+    pub reuse_ids: bool,
+    /// `load_with_options` runs `verify_indexes` after loading and fails on any drift.
+    pub verify_on_load: bool,
+    /// Every read API that returns a list of nodes or edges returns it sorted by id
+    /// (neighbor lists by edge id), and `iter_nodes*` yield in id order. Off by default:
+    /// unordered results skip the extra O(n log n) sort.
+    pub deterministic_iteration: bool,
+    /// Also keep every node's adjacency split by edge type, so typed neighbor queries and
+    /// `degree` walk only the edges of that type. Costs a second list entry per edge end
+    /// (default: off).
+    pub typed_adjacency: bool,
+    /// `flush` also writes the contents of every index, not just its definition, so
+    /// loading skips the backfill scans at the cost of a larger index segment (default: off).
+    pub persist_index_data: bool,
+    /// Keep adjacency lists sorted by edge type, then neighbor id, then edge id, at the
+    /// cost of a binary-search insert per edge end (default: off).
+    pub sorted_adjacency: bool,
+    /// Layout `flush` writes the node and edge segments in (default: binary). Loading
+    /// reads either.
+    pub segment_format: SegmentFormat,
+    /// Compression of the binary node and edge segments `flush` writes (default: none).
+    /// Anything else needs the `compression` feature; loading decompresses as needed.
+    pub segment_compression: CompressionOption,
+    /// `flush` writes the nodes as one segment per first label, which `load_labels` can
+    /// load alone (default: off). Loading reads either layout.
+    pub partition_nodes_by_label: bool,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self { soft_delete: false, strict_edges: true, reuse_ids: false, verify_on_load: false, deterministic_iteration: false, typed_adjacency: false, persist_index_data: false, sorted_adjacency: false, segment_format: SegmentFormat::Binary, segment_compression: CompressionOption::None, partition_nodes_by_label: false }
+    }
+}
+
 /// In-memory graph store with indexes
+///
+/// `label_index`, `edge_type_index` and the adjacency maps only ever reference live records; tombstoned
+/// nodes and edges stay in `nodes`/`edges` until purged.
 pub struct InMemoryGraphStore {
     pub(crate) nodes: HashMap<NodeId, Node>,
     pub(crate) edges: HashMap<EdgeId, Edge>,
+    /// label -> node ids, sorted and without duplicates; see `index_label`
     pub(crate) label_index: HashMap<String, Vec<NodeId>>,
     pub(crate) adjacency_out: HashMap<NodeId, Vec<EdgeId>>,
     pub(crate) adjacency_in: HashMap<NodeId, Vec<EdgeId>>,
-    pub(crate) next_node_id: NodeId,
-    pub(crate) next_edge_id: EdgeId,
+    /// `adjacency_out` / `adjacency_in` split by edge type; empty unless `typed_adjacency`
+    pub(crate) typed_out: TypedAdjacency,
+    pub(crate) typed_in: TypedAdjacency,
+    pub(crate) edge_type_index: HashMap<String, Vec<EdgeId>>,
+    /// `(label, key)` -> value -> node ids; see `create_property_index`
+    pub(crate) property_indexes: HashMap<(String, String), PropertyIndex>,
+    /// `(label, key)` -> ordered value -> node ids; see `create_range_index`
+    pub(crate) range_indexes: HashMap<(String, String), RangeIndex>,
+    /// `(label, keys)` -> value tuple -> node ids; see `create_composite_index`
+    pub(crate) composite_indexes: HashMap<(String, Vec<String>), CompositeIndex>,
+    /// `(edge_type, key)` -> value -> edge ids; see `create_edge_index`
+    pub(crate) edge_indexes: HashMap<(String, String), EdgeIndex>,
+    /// `(label, key)` -> token -> node ids; see `create_text_index`
+    pub(crate) text_indexes: HashMap<(String, String), TextIndex>,
+    /// `(label, key)` -> string value -> node ids; see `create_prefix_index`
+    pub(crate) prefix_indexes: HashMap<(String, String), PrefixIndex>,
+    /// `(label, name)` -> derived value -> node ids; see `create_computed_index`
+    pub(crate) computed_indexes: HashMap<(String, String), ComputedIndex>,
+    /// `(label, key)` -> expiry timestamp -> node ids; see `create_ttl_index`
+    pub(crate) ttl_indexes: HashMap<(String, String), TtlIndex>,
+    /// `(label, key)` pairs whose values must be unique; see `create_unique_constraint`
+    pub(crate) unique_constraints: HashSet<(String, String)>,
+    /// Lookup counters of the indexes above; see `index_stats`
+    pub(crate) index_usage: IndexUsage,
+    /// Snapshot taken by `collect_statistics`, or loaded with the segments
+    pub(crate) statistics: Option<GraphStatistics>,
+    pub(crate) node_ids: IdAllocator,
+    pub(crate) edge_ids: IdAllocator,
+    /// Tombstoned records still held in `nodes` / `edges`, so live counts are O(1)
+    pub(crate) deleted_nodes: usize,
+    pub(crate) deleted_edges: usize,
+    /// Captured WAL records (None when capture is disabled)
+    pub(crate) wal_log: Option<Vec<WalRecord>>,
+    /// Writer every logged mutation is appended to; see `attach_wal_writer`
+    pub(crate) wal_writer: Option<WalWriter>,
+    /// LSN of the last record appended or replayed (0 before any); see `last_applied_lsn`
+    pub(crate) last_applied_lsn: Lsn,
+    /// Open WAL transaction; see `begin_wal_txn`
+    pub(crate) wal_txn: Option<TxnId>,
+    /// Last WAL transaction aborted, whose mutations this store still holds; see
+    /// `abort_wal_txn`
+    pub(crate) aborted_txn: Option<TxnId>,
+    /// Id the next WAL transaction gets; kept above every id flushed or replayed
+    pub(crate) next_txn_id: TxnId,
+    /// Nodes and edges changed since the segments were loaded or last written by
+    /// `flush_incremental`; `None` until then. See `segment_delta`.
+    pub(crate) changes: Option<ChangeSet>,
+    /// Set by `load_labels`, for a store holding part of the graph. See `segment_partition`.
+    pub(crate) partial: Option<PartialLoad>,
+    pub(crate) options: StoreOptions,
 }
 
 impl InMemoryGraphStore {
     pub fn new() -> Self {
+        Self::with_options(StoreOptions::default())
+    }
+
+    pub fn with_options(options: StoreOptions) -> Self {
         Self {
             nodes: HashMap::new(),
             edges: HashMap::new(),
             label_index: HashMap::new(),
             adjacency_out: HashMap::new(),
             adjacency_in: HashMap::new(),
-            next_node_id: 1,
-            next_edge_id: 1,
+            typed_out: HashMap::new(),
+            typed_in: HashMap::new(),
+            edge_type_index: HashMap::new(),
+            property_indexes: HashMap::new(),
+            range_indexes: HashMap::new(),
+            composite_indexes: HashMap::new(),
+            edge_indexes: HashMap::new(),
+            text_indexes: HashMap::new(),
+            prefix_indexes: HashMap::new(),
+            computed_indexes: HashMap::new(),
+            ttl_indexes: HashMap::new(),
+            unique_constraints: HashSet::new(),
+            index_usage: HashMap::new(),
+            statistics: None,
+            node_ids: IdAllocator::new(options.reuse_ids),
+            edge_ids: IdAllocator::new(options.reuse_ids),
+            deleted_nodes: 0,
+            deleted_edges: 0,
+            wal_log: None,
+            wal_writer: None,
+            last_applied_lsn: 0,
+            wal_txn: None,
+            aborted_txn: None,
+            next_txn_id: 1,
+            changes: None,
+            partial: None,
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    /// Insert a node under a caller-chosen id (e.g. when importing from another system).
+    ///
+    /// Subsequent `add_node` calls never reuse `id` or anything below it.
+    ///
+    /// # Errors
+    /// Returns `EngineError::InvalidArgument` if a node with `id` already exists.
+    pub fn add_node_with_id(&mut self, id: NodeId, labels: Vec<String>, properties: HashMap<String, Value>) -> Result<NodeId, EngineError> {
+        if self.nodes.contains_key(&id) {
+            return Err(EngineError::InvalidArgument(format!("node {} already exists", id)));
+        }
+        self.check_unique_node(&[], &labels, &properties)?;
+        self.log_wal(|| WalRecord::AddNode { id, labels: labels.clone(), properties: properties.clone() });
+        self.insert_node(Node { id, labels, properties, deleted: false });
+        Ok(id)
+    }
+
+    /// Insert an edge under a caller-chosen id.
+    ///
+    /// # Errors
+    /// Returns `EngineError::InvalidArgument` if an edge with `id` already exists.
+    pub fn add_edge_with_id(
+        &mut self,
+        id: EdgeId,
+        from: NodeId,
+        to: NodeId,
+        edge_type: String,
+        properties: HashMap<String, Value>,
+    ) -> Result<EdgeId, EngineError> {
+        if self.edges.contains_key(&id) {
+            return Err(EngineError::InvalidArgument(format!("edge {} already exists", id)));
+        }
+        self.check_endpoints(from, to)?;
+        self.log_wal(|| WalRecord::AddEdge {
+            id,
+            from_node: from,
+            to_node: to,
+            edge_type: edge_type.clone(),
+            properties: properties.clone(),
+        });
+        self.insert_edge(Edge { id, from_node: from, to_node: to, edge_type, properties, deleted: false });
+        Ok(id)
+    }
+
+    /// Insert an edge without checking that its endpoints exist, regardless of
+    /// `StoreOptions::strict_edges`. Meant for bulk loaders that write edges before nodes;
+    /// run `validate_references` once loading is done.
+    pub fn add_edge_unchecked(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        edge_type: String,
+        properties: HashMap<String, Value>,
+    ) -> Result<EdgeId, EngineError> {
+        let id = self.edge_ids.next_id();
+        self.log_wal(|| WalRecord::AddEdge {
+            id,
+            from_node: from,
+            to_node: to,
+            edge_type: edge_type.clone(),
+            properties: properties.clone(),
+        });
+        self.insert_edge(Edge { id, from_node: from, to_node: to, edge_type, properties, deleted: false });
+        Ok(id)
+    }
+
+    /// Ids of live edges whose source or target is not a live node, in ascending order.
+    pub fn validate_references(&self) -> Vec<EdgeId> {
+        let mut dangling: Vec<EdgeId> = self.edges.values()
+            .filter(|e| !e.deleted)
+            .filter(|e| self.live_node(e.from_node).is_none() || self.live_node(e.to_node).is_none())
+            .map(|e| e.id)
+            .collect();
+        dangling.sort_unstable();
+        dangling
+    }
+
+    /// Atomically set `key` to `new` only if its current value equals `expected`.
+    ///
+    /// `expected = None` means "the property is absent". Returns whether the swap happened;
+    /// a mismatch leaves the node untouched and is not an error.
+    ///
+    /// # Errors
+    /// Returns `EngineError::NotFound` if the node does not exist.
+    pub fn compare_and_set_property(
+        &mut self,
+        id: NodeId,
+        key: &str,
+        expected: Option<Value>,
+        new: Value,
+    ) -> Result<bool, EngineError> {
+        let current = self.node_mut(id)?.properties.get(key);
+        if current != expected.as_ref() {
+            return Ok(false);
+        }
+        self.set_node_property(id, key.to_string(), new)?;
+        Ok(true)
+    }
+
+    /// Add `delta` (an `Int` or `Float`) to the numeric property `key` in place and
+    /// return the new value. An absent property is created with `delta` as its value.
+    ///
+    /// `Int + Int` stays an `Int` (overflow is an error); mixing in a `Float` yields a `Float`.
+    /// With WAL capture enabled the record carries the resulting value, not the delta,
+    /// so replaying it is idempotent.
+    ///
+    /// # Errors
+    /// `NotFound` for a missing node/edge, `TypeMismatch` if `delta` or the existing value
+    /// is not numeric.
+    pub fn increment_property(&mut self, target: PropertyTarget, key: &str, delta: Value) -> Result<Value, EngineError> {
+        if !matches!(delta, Value::Int(_) | Value::Float(_)) {
+            return Err(EngineError::TypeMismatch(format!("increment delta for {} must be numeric", key)));
+        }
+        let current = match target {
+            PropertyTarget::Node(id) => self.node_mut(id)?.properties.get(key),
+            PropertyTarget::Edge(id) => self.edge_mut(id)?.properties.get(key),
+        };
+        let next = match (current, &delta) {
+            (None, _) => delta.clone(),
+            (Some(Value::Int(a)), Value::Int(b)) => Value::Int(a.checked_add(*b).ok_or_else(|| {
+                EngineError::InvalidArgument(format!("increment of {} overflows i64", key))
+            })?),
+            (Some(Value::Int(a)), Value::Float(b)) => Value::Float(*a as f64 + b),
+            (Some(Value::Float(a)), Value::Int(b)) => Value::Float(a + *b as f64),
+            (Some(Value::Float(a)), Value::Float(b)) => Value::Float(a + b),
+            (Some(other), _) => {
+                return Err(EngineError::TypeMismatch(format!("property {} is not numeric: {:?}", key, other)));
+            }
+        };
+        match target {
+            PropertyTarget::Node(id) => self.set_node_property(id, key.to_string(), next.clone())?,
+            PropertyTarget::Edge(id) => {
+                self.update_edge_properties(id, HashMap::from([(key.to_string(), next.clone())]))?
+            }
+        }
+        Ok(next)
+    }
+
+    /// Reset the store to empty.
+    ///
+    /// Id counters stay monotonic unless `reset_ids` is set, so ids cached by callers are
+    /// never handed out again by default (with `StoreOptions::reuse_ids` the removed ids
+    /// become reusable like any other delete).
+    pub fn truncate(&mut self, reset_ids: bool) {
+        self.log_wal(|| WalRecord::Truncate { reset_ids });
+        if let Some(changes) = &mut self.changes {
+            changes.nodes.extend(self.nodes.keys());
+            changes.edges.extend(self.edges.keys());
+        }
+        if reset_ids {
+            self.node_ids.reset();
+            self.edge_ids.reset();
+        } else {
+            for id in self.nodes.keys() {
+                self.node_ids.release(*id);
+            }
+            for id in self.edges.keys() {
+                self.edge_ids.release(*id);
+            }
+        }
+        self.nodes.clear();
+        self.edges.clear();
+        self.deleted_nodes = 0;
+        self.deleted_edges = 0;
+        self.label_index.clear();
+        self.adjacency_out.clear();
+        self.adjacency_in.clear();
+        self.typed_out.clear();
+        self.typed_in.clear();
+        self.edge_type_index.clear();
+        for index in self.property_indexes.values_mut() {
+            index.buckets.clear();
+            index.rows.clear();
+        }
+        for buckets in self.range_indexes.values_mut() {
+            buckets.clear();
+        }
+        for buckets in self.composite_indexes.values_mut() {
+            buckets.clear();
+        }
+        for buckets in self.edge_indexes.values_mut() {
+            buckets.clear();
+        }
+        for index in self.text_indexes.values_mut() {
+            index.postings.clear();
+        }
+        for index in self.prefix_indexes.values_mut() {
+            index.entries.clear();
+        }
+        for index in self.computed_indexes.values_mut() {
+            index.buckets.clear();
+        }
+        for buckets in self.ttl_indexes.values_mut() {
+            buckets.clear();
+        }
+        self.statistics = None;
+    }
+
+    /// Delete every node carrying `label`, returning how many were removed.
+    ///
+    /// With `detach` incident edges go too; without it the call fails before deleting
+    /// anything if any of the nodes still has edges.
+    pub fn delete_nodes_by_label(&mut self, label: &str, detach: bool) -> Result<usize, EngineError> {
+        let ids = self.label_index.get(label).cloned().unwrap_or_default();
+        if !detach {
+            if let Some(id) = ids.iter().find(|id| !self.incident_edge_ids(**id).is_empty()) {
+                return Err(EngineError::InvalidArgument(format!(
+                    "node {} with label {} still has edges; delete with detach", id, label
+                )));
+            }
+        }
+        let mut removed = 0;
+        for id in ids {
+            if self.delete_node(id, detach)? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Delete every node reachable from `seed` (edges followed in both directions) together
+    /// with their edges. Returns `(nodes_removed, edges_removed)`.
+    ///
+    /// The component is discovered with an explicit worklist, so long chains cannot
+    /// overflow the stack.
+    pub fn delete_component(&mut self, seed: NodeId) -> Result<(usize, usize), EngineError> {
+        if self.live_node(seed).is_none() {
+            return Err(EngineError::NotFound(format!("node {}", seed)));
+        }
+        let mut component = vec![seed];
+        let mut seen: HashSet<NodeId> = HashSet::from([seed]);
+        let mut cursor = 0;
+        while cursor < component.len() {
+            let id = component[cursor];
+            cursor += 1;
+            for edge_id in self.incident_edge_ids(id) {
+                if let Some(edge) = self.edges.get(&edge_id) {
+                    let other = if edge.from_node == id { edge.to_node } else { edge.from_node };
+                    if seen.insert(other) {
+                        component.push(other);
+                    }
+                }
+            }
+        }
+
+        let mut edges_removed = 0;
+        for id in &component {
+            edges_removed += self.remove_node(*id, true);
+        }
+        Ok((component.len(), edges_removed))
+    }
+
+    /// Rename every live edge of type `old` to `new`, returning how many edges changed.
+    /// Tombstoned edges keep the type they were deleted with.
+    ///
+    /// The whole rename is logged as a single WAL record, however many edges it touches.
+    pub fn rename_edge_type(&mut self, old: &str, new: &str) -> Result<usize, EngineError> {
+        if new.is_empty() {
+            return Err(EngineError::InvalidArgument("edge type must not be empty".into()));
+        }
+        if old == new {
+            return Ok(0);
+        }
+        let Some(ids) = self.edge_type_index.remove(old) else { return Ok(0) };
+        self.log_wal(|| WalRecord::RenameEdgeType { old: old.to_string(), new: new.to_string() });
+        for id in &ids {
+            self.edge_changed(*id);
+            self.unindex_edge_properties(*id);
+            self.unlink_typed(*id);
+            if let Some(edge) = self.edges.get_mut(id) {
+                edge.edge_type = new.to_string();
+            }
+            self.link_typed(*id);
+            self.index_edge_properties(*id);
+        }
+        let ends: Vec<NodeId> = ids.iter().filter_map(|id| self.edges.get(id)).flat_map(|e| [e.from_node, e.to_node]).collect();
+        self.resort_adjacency(ends);
+        let count = ids.len();
+        self.edge_type_index.entry(new.to_string()).or_default().extend(ids);
+        Ok(count)
+    }
+
+    /// Set `key` to `value` on every node with `label` that does not have it yet,
+    /// returning how many nodes were modified. Existing values are left untouched.
+    pub fn set_default_property(&mut self, label: &str, key: &str, value: Value) -> Result<usize, EngineError> {
+        let missing: Vec<NodeId> = self.label_index.get(label)
+            .map(|ids| ids.iter()
+                .copied()
+                .filter(|id| self.nodes.get(id).is_some_and(|n| !n.properties.contains_key(key)))
+                .collect())
+            .unwrap_or_default();
+        // Every node gets the same value, so a constraint covering two of them fails up front
+        for (constrained_label, constrained_key) in &self.unique_constraints {
+            let covered = missing.iter().filter(|id| self.nodes[*id].labels.contains(constrained_label));
+            if constrained_key == key && covered.count() > 1 {
+                return Err(constraints::violation(constrained_label, key, &value));
+            }
+        }
+        for id in &missing {
+            self.check_unique(Some(*id), &self.nodes[id].labels, key, &value)?;
+        }
+        for id in &missing {
+            self.set_node_property(*id, key.to_string(), value.clone())?;
+        }
+        Ok(missing.len())
+    }
+
+    /// Create an edge `from -> to` of `edge_type`, or merge `props` into the existing one.
+    ///
+    /// Returns the edge id and `true` if a new edge was created. The lookup walks
+    /// `adjacency_out` of `from` only. If several parallel edges already match, the one with
+    /// the lowest id is updated.
+    pub fn upsert_edge(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        edge_type: String,
+        props: HashMap<String, Value>,
+    ) -> Result<(EdgeId, bool), EngineError> {
+        let existing = self.adjacency_out.get(&from).and_then(|ids| {
+            ids.iter()
+                .copied()
+                .filter(|id| self.edges.get(id).is_some_and(|e| e.to_node == to && e.edge_type == edge_type))
+                .min()
+        });
+        match existing {
+            Some(id) => {
+                self.update_edge_properties(id, props)?;
+                Ok((id, false))
+            }
+            None => Ok((self.add_edge(from, to, edge_type, props)?, true)),
+        }
+    }
+
+    /// Point one end of an edge at `new_node`, keeping its id, type and properties.
+    ///
+    /// # Errors
+    /// `NotFound` if the edge or `new_node` does not exist.
+    pub fn set_edge_endpoint(&mut self, id: EdgeId, endpoint: Endpoint, new_node: NodeId) -> Result<(), EngineError> {
+        self.edge_mut(id)?;
+        if self.live_node(new_node).is_none() {
+            return Err(EngineError::NotFound(format!("node {}", new_node)));
+        }
+        self.log_wal(|| WalRecord::SetEdgeEndpoint { id, endpoint, node: new_node });
+        self.unlink_typed(id);
+        // Borrow the fields separately so the edge and the adjacency map can be updated together
+        let Some(edge) = self.edges.get_mut(&id) else { return Ok(()) };
+        let (slot, adjacency) = match endpoint {
+            Endpoint::From => (&mut edge.from_node, &mut self.adjacency_out),
+            Endpoint::To => (&mut edge.to_node, &mut self.adjacency_in),
+        };
+        let old = std::mem::replace(slot, new_node);
+        if old != new_node {
+            unlink_edge(adjacency, old, id);
+            let outgoing = endpoint == Endpoint::From;
+            self.link_adjacency_end(id, outgoing);
+            // The far end's list is keyed by the endpoint that moved
+            let edge = &self.edges[&id];
+            self.resort_adjacency([if outgoing { edge.to_node } else { edge.from_node }]);
+        }
+        self.link_typed(id);
+        Ok(())
+    }
+
+    /// Swap the endpoints of an edge in place.
+    pub fn reverse_edge(&mut self, id: EdgeId) -> Result<(), EngineError> {
+        self.edge_mut(id)?;
+        self.log_wal(|| WalRecord::ReverseEdge { id });
+        let (from, to) = (self.edges[&id].from_node, self.edges[&id].to_node);
+        if from == to {
+            return Ok(());
+        }
+        self.unlink_typed(id);
+        let edge = self.edge_mut(id)?;
+        edge.from_node = to;
+        edge.to_node = from;
+        unlink_edge(&mut self.adjacency_out, from, id);
+        unlink_edge(&mut self.adjacency_in, to, id);
+        self.link_adjacency(id);
+        self.link_typed(id);
+        Ok(())
+    }
+
+    /// Copy properties from node `from` onto node `to`, returning how many keys were written.
+    ///
+    /// `keys` restricts the copy to the named properties; names the source does not have
+    /// are ignored. A missing source node copies nothing.
+    ///
+    /// # Errors
+    /// `NotFound` if `to` does not exist; `InvalidArgument` under `CopyConflict::Error` when
+    /// a copied key already exists on `to` (nothing is written in that case).
+    pub fn copy_properties(
+        &mut self,
+        from: NodeId,
+        to: NodeId,
+        keys: Option<&[String]>,
+        on_conflict: CopyConflict,
+    ) -> Result<usize, EngineError> {
+        let target = self.live_node(to)
+            .ok_or_else(|| EngineError::NotFound(format!("node {}", to)))?;
+        let Some(source) = self.live_node(from) else { return Ok(0) };
+
+        let mut selected: Vec<(String, Value)> = match keys {
+            Some(keys) => keys.iter()
+                .filter_map(|k| source.properties.get(k).map(|v| (k.clone(), v.clone())))
+                .collect(),
+            None => source.properties.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        };
+        match on_conflict {
+            CopyConflict::Overwrite => {}
+            CopyConflict::SkipExisting => selected.retain(|(k, _)| !target.properties.contains_key(k)),
+            CopyConflict::Error => {
+                if let Some((k, _)) = selected.iter().find(|(k, _)| target.properties.contains_key(k)) {
+                    return Err(EngineError::InvalidArgument(format!(
+                        "property {} already exists on node {}", k, to
+                    )));
+                }
+            }
+        }
+
+        for (key, value) in &selected {
+            self.check_unique(Some(to), &target.labels, key, value)?;
+        }
+        let written = selected.len();
+        for (key, value) in selected {
+            self.set_node_property(to, key, value)?;
+        }
+        Ok(written)
+    }
+
+    /// Copy the selected nodes into a fresh store, preserving their ids.
+    ///
+    /// Edges whose endpoints are both selected are always copied. With
+    /// `include_boundary_edges`, edges with exactly one selected endpoint are copied too and
+    /// their outside endpoint becomes a placeholder node (same id, no labels, no
+    /// properties). All indexes of the returned store are fully built.
+    ///
+    /// # Errors
+    /// Returns `EngineError::NotFound` if any id in `node_ids` does not exist.
+    pub fn extract_subgraph(&self, node_ids: &[NodeId], include_boundary_edges: bool) -> Result<InMemoryGraphStore, EngineError> {
+        let mut sub = InMemoryGraphStore::new();
+        for id in node_ids {
+            let node = self.live_node(*id)
+                .ok_or_else(|| EngineError::NotFound(format!("node {}", id)))?;
+            if !sub.nodes.contains_key(id) {
+                sub.insert_node(node.clone());
+            }
+        }
+
+        // Walk outgoing and incoming adjacency of the selection; each edge is seen at most twice
+        for id in node_ids {
+            for edge_id in self.incident_edge_ids(*id) {
+                if sub.edges.contains_key(&edge_id) {
+                    continue;
+                }
+                let Some(edge) = self.edges.get(&edge_id) else { continue };
+                let from_in = sub.nodes.contains_key(&edge.from_node);
+                let to_in = sub.nodes.contains_key(&edge.to_node);
+                if !(from_in && to_in) {
+                    if !include_boundary_edges {
+                        continue;
+                    }
+                    let outside = if from_in { edge.to_node } else { edge.from_node };
+                    sub.insert_node(Node { id: outside, labels: Vec::new(), properties: HashMap::new(), deleted: false });
+                }
+                sub.insert_edge(edge.clone());
+            }
+        }
+        Ok(sub)
+    }
+
+    /// Breadth-first `neighborhood` that also records how each node was reached: every
+    /// reachable node maps to `(distance, edge)`, where `edge` is the edge it was first
+    /// reached through (`None` for `start`). Those edges form a shortest-path spanning
+    /// tree of the neighborhood.
+    ///
+    /// # Errors
+    /// `NotFound` if `start` is not a live node.
+    pub fn neighborhood_tree(&self, start: NodeId, max_depth: usize, edge_type: Option<&str>, direction: Direction) -> Result<HashMap<NodeId, (usize, Option<EdgeId>)>, EngineError> {
+        if self.live_node(start).is_none() {
+            return Err(EngineError::NotFound(format!("node {}", start)));
+        }
+        let mut reached = HashMap::from([(start, (0, None))]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(id) = queue.pop_front() {
+            let depth = reached[&id].0;
+            if depth == max_depth {
+                continue;
+            }
+            for (edge_id, other) in self.adjacent(id, direction, edge_type) {
+                if let std::collections::hash_map::Entry::Vacant(slot) = reached.entry(other) {
+                    slot.insert((depth + 1, Some(edge_id)));
+                    queue.push_back(other);
+                }
+            }
+        }
+        Ok(reached)
+    }
+
+    /// Find the single node with `label` whose `key` equals `key_value`, or create it.
+    ///
+    /// On creation the node gets `label`, `extra_props` and `key = key_value`; an existing
+    /// match is returned untouched. The bool is `true` when the node was created.
+    ///
+    /// With a unique constraint on `(label, key)` several matches cannot exist, and the
+    /// lookup is served by the constraint's index.
+    ///
+    /// # Errors
+    /// Returns `EngineError::InvalidArgument` when several nodes already match, rather than
+    /// picking one arbitrarily.
+    pub fn merge_node(
+        &mut self,
+        label: &str,
+        key: &str,
+        key_value: Value,
+        extra_props: HashMap<String, Value>,
+    ) -> Result<(NodeId, bool), EngineError> {
+        let matches = self.find_by_label_and_property(label, key, &key_value);
+        match matches.as_slice() {
+            [id] => Ok((*id, false)),
+            [] => {
+                let mut properties = extra_props;
+                properties.insert(key.to_string(), key_value);
+                let id = self.add_node(vec![label.to_string()], properties)?;
+                Ok((id, true))
+            }
+            many => Err(EngineError::InvalidArgument(format!(
+                "merge_node: {} nodes with label {} match {}", many.len(), label, key
+            ))),
+        }
+    }
+
+    /// Fetch a node even if it is tombstoned.
+    pub fn get_node_including_deleted(&self, id: NodeId) -> Result<Option<Node>, EngineError> {
+        Ok(self.nodes.get(&id).cloned())
+    }
+
+    /// Fetch an edge even if it is tombstoned.
+    pub fn get_edge_including_deleted(&self, id: EdgeId) -> Result<Option<Edge>, EngineError> {
+        Ok(self.edges.get(&id).cloned())
+    }
+
+    /// Bring a tombstoned node back, re-indexing its labels. Returns `Ok(false)` if the
+    /// node is already live.
+    ///
+    /// Edges tombstoned together with the node stay deleted; restore them with
+    /// `undelete_edge`.
+    ///
+    /// # Errors
+    /// `NotFound` if no record with `id` exists (never created, or already purged).
+    pub fn undelete_node(&mut self, id: NodeId) -> Result<bool, EngineError> {
+        let node = self.nodes.get(&id)
+            .ok_or_else(|| EngineError::NotFound(format!("node {}", id)))?;
+        if !node.deleted {
+            return Ok(false);
+        }
+        self.check_unique_node(&[id], &node.labels, &node.properties)?;
+        self.log_wal(|| WalRecord::UndeleteNode { id });
+        self.restore_node(id);
+        Ok(true)
+    }
+
+    /// Bring a tombstoned edge back. Returns `Ok(false)` if the edge is already live.
+    ///
+    /// # Errors
+    /// `NotFound` if no record with `id` exists; `InvalidArgument` if either endpoint is
+    /// not live.
+    pub fn undelete_edge(&mut self, id: EdgeId) -> Result<bool, EngineError> {
+        let edge = self.edges.get(&id)
+            .ok_or_else(|| EngineError::NotFound(format!("edge {}", id)))?;
+        if !edge.deleted {
+            return Ok(false);
+        }
+        for endpoint in [edge.from_node, edge.to_node] {
+            if self.live_node(endpoint).is_none() {
+                return Err(EngineError::InvalidArgument(format!(
+                    "cannot undelete edge {}: node {} is deleted", id, endpoint
+                )));
+            }
+        }
+        self.log_wal(|| WalRecord::UndeleteEdge { id });
+        self.restore_edge(id);
+        Ok(true)
+    }
+
+    /// Physically remove every tombstoned node and edge, returning `(nodes, edges)` purged.
+    pub fn purge_tombstones(&mut self) -> (usize, usize) {
+        self.log_wal(|| WalRecord::PurgeTombstones);
+        let before = (self.nodes.len(), self.edges.len());
+        // Tombstones hold no index entries, so dropping the records is enough
+        let (node_ids, edge_ids, changes) = (&mut self.node_ids, &mut self.edge_ids, &mut self.changes);
+        self.nodes.retain(|id, n| {
+            if n.deleted {
+                node_ids.release(*id);
+                if let Some(changes) = changes.as_mut() {
+                    changes.nodes.insert(*id);
+                }
+            }
+            !n.deleted
+        });
+        self.edges.retain(|id, e| {
+            if e.deleted {
+                edge_ids.release(*id);
+                if let Some(changes) = changes.as_mut() {
+                    changes.edges.insert(*id);
+                }
+            }
+            !e.deleted
+        });
+        self.deleted_nodes = 0;
+        self.deleted_edges = 0;
+        (before.0 - self.nodes.len(), before.1 - self.edges.len())
+    }
+
+    /// With `strict_edges`, fail with `NodeNotFound` unless both endpoints are live nodes.
+    fn check_endpoints(&self, from: NodeId, to: NodeId) -> Result<(), EngineError> {
+        if self.options.strict_edges {
+            for id in [from, to] {
+                if self.live_node(id).is_none() {
+                    return Err(EngineError::NodeNotFound(id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A node that exists and is not tombstoned.
+    fn live_node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(&id).filter(|n| !n.deleted)
+    }
+
+    /// Mutable access to a live node, or `NotFound` if it does not exist or is tombstoned.
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut Node, EngineError> {
+        self.node_changed(id);
+        self.nodes.get_mut(&id)
+            .filter(|n| !n.deleted)
+            .ok_or_else(|| EngineError::NotFound(format!("node {}", id)))
+    }
+
+    /// Mutable access to a live edge, or `NotFound` if it does not exist or is tombstoned.
+    fn edge_mut(&mut self, id: EdgeId) -> Result<&mut Edge, EngineError> {
+        self.edge_changed(id);
+        self.edges.get_mut(&id)
+            .filter(|e| !e.deleted)
+            .ok_or_else(|| EngineError::NotFound(format!("edge {}", id)))
+    }
+
+    /// Insert a node record with its own id, indexing its labels and marking the id as used.
+    ///
+    /// Shared by `add_node`, `add_node_with_id`, segment loading and WAL replay.
+    /// Tombstoned records are stored but not indexed.
+    pub(crate) fn insert_node(&mut self, node: Node) {
+        let id = node.id;
+        self.node_changed(id);
+        self.unindex_node_properties(id);
+        // Replacing a live record (WAL replay over a loaded segment): drop its old labels
+        if let Some(prev) = self.nodes.get(&id).filter(|n| !n.deleted) {
+            for label in prev.labels.clone() {
+                self.unindex_label(&label, id);
+            }
+        }
+        if node.deleted {
+            self.deleted_nodes += 1;
+        } else {
+            for label in &node.labels {
+                self.index_label(label, id);
+            }
+        }
+        if self.nodes.insert(id, node).is_some_and(|prev| prev.deleted) {
+            self.deleted_nodes -= 1;
+        }
+        self.index_node_properties(id);
+        self.node_ids.observe(id);
+    }
+
+    /// Insert an edge record with its own id, updating adjacency and marking the id as used.
+    pub(crate) fn insert_edge(&mut self, edge: Edge) {
+        let id = edge.id;
+        self.edge_changed(id);
+        self.unindex_edge_properties(id);
+        let live = !edge.deleted;
+        if live {
+            self.edge_type_index.entry(edge.edge_type.clone()).or_default().push(id);
+        } else {
+            self.deleted_edges += 1;
+        }
+        if self.edges.insert(id, edge).is_some_and(|prev| prev.deleted) {
+            self.deleted_edges -= 1;
+        }
+        if live {
+            self.link_adjacency(id);
+        }
+        self.link_typed(id);
+        self.index_edge_properties(id);
+        self.edge_ids.observe(id);
+    }
+
+    /// Borrow the live nodes carrying `label`, or all live nodes when `label` is `None`.
+    fn iter_nodes_matching<'s>(&'s self, label: Option<&str>) -> Box<dyn Iterator<Item = &'s Node> + 's> {
+        match label {
+            Some(label) => {
+                let ids = self.label_index.get(label).map_or(&[][..], Vec::as_slice);
+                Box::new(ids.iter().filter_map(|id| self.nodes.get(id)))
+            }
+            None => Box::new(self.nodes.values().filter(|n| !n.deleted)),
+        }
+    }
+
+    /// `items` sorted by `key` when `deterministic_iteration` is on, untouched otherwise.
+    fn ordered<T>(&self, mut items: Vec<T>, key: impl FnMut(&T) -> u64) -> Vec<T> {
+        if self.options.deterministic_iteration {
+            items.sort_unstable_by_key(key);
+        }
+        items
+    }
+
+    /// `iter_nodes_matching`, in id order when `deterministic_iteration` is on.
+    fn iter_nodes_ordered<'s>(&'s self, label: Option<&str>) -> Box<dyn Iterator<Item = &'s Node> + 's> {
+        if !self.options.deterministic_iteration {
+            return self.iter_nodes_matching(label);
+        }
+        let nodes = self.ordered(self.iter_nodes_matching(label).collect(), |n| n.id);
+        Box::new(nodes.into_iter())
+    }
+
+    /// Live edges `from -> to`, found by walking the shorter of `adjacency_out[from]` and
+    /// `adjacency_in[to]` (each holds every candidate, seen from one side).
+    fn edges_between<'s>(&'s self, from: NodeId, to: NodeId, edge_type: Option<&'s str>) -> impl Iterator<Item = &'s Edge> + 's {
+        let (out, inc) = (self.adjacency_list(from, true, edge_type), self.adjacency_list(to, false, edge_type));
+        let candidates = if out.len() <= inc.len() { out } else { inc };
+        candidates.iter()
+            .filter_map(|id| self.edges.get(id))
+            .filter(move |e| e.from_node == from && e.to_node == to)
+            .filter(move |e| edge_type.is_none_or(|t| e.edge_type == t))
+    }
+
+    /// `(edge, other endpoint)` for the live edges of `id` in `direction`, without cloning.
+    /// With `Both` a self-loop is yielded once.
+    fn adjacent<'s>(&'s self, id: NodeId, direction: Direction, edge_type: Option<&'s str>) -> impl Iterator<Item = (EdgeId, NodeId)> + 's {
+        let out = if direction != Direction::Incoming { self.adjacency_list(id, true, edge_type) } else { &[] };
+        let inc = if direction != Direction::Outgoing { self.adjacency_list(id, false, edge_type) } else { &[] };
+        out.iter().map(|e| (e, true))
+            .chain(inc.iter().map(|e| (e, false)))
+            .filter_map(move |(e, outgoing)| {
+                let edge = self.edges.get(e)?;
+                if edge_type.is_some_and(|t| edge.edge_type != t) {
+                    return None;
+                }
+                if !outgoing && direction == Direction::Both && edge.from_node == id {
+                    return None;
+                }
+                Some((*e, if outgoing { edge.to_node } else { edge.from_node }))
+            })
+    }
+
+    /// Ids of every edge touching `id` in either direction (self-loops listed once).
+    fn incident_edge_ids(&self, id: NodeId) -> Vec<EdgeId> {
+        let mut ids: Vec<EdgeId> = self.adjacency_out.get(&id).cloned().unwrap_or_default();
+        if let Some(incoming) = self.adjacency_in.get(&id) {
+            for e in incoming {
+                if !ids.contains(e) {
+                    ids.push(*e);
+                }
+            }
+        }
+        ids
+    }
+
+    /// Remove a node, its label index entries and all incident edges without logging.
+    /// Returns the removed node and the number of edges removed with it.
+    fn detach_node(&mut self, id: NodeId) -> Option<(Node, usize)> {
+        self.node_changed(id);
+        self.unindex_node_properties(id);
+        let node = self.nodes.remove(&id)?;
+        self.node_ids.release(id);
+        if node.deleted {
+            self.deleted_nodes -= 1;
+        }
+        for label in &node.labels {
+            self.unindex_label(label, id);
+        }
+        let edges = self.incident_edge_ids(id);
+        for edge_id in &edges {
+            self.detach_edge(*edge_id);
+        }
+        Some((node, edges.len()))
+    }
+
+    /// Remove an edge and its adjacency entries without logging it.
+    fn detach_edge(&mut self, id: EdgeId) -> Option<Edge> {
+        self.edge_changed(id);
+        self.unindex_edge_properties(id);
+        self.unlink_typed(id);
+        let edge = self.edges.remove(&id)?;
+        self.edge_ids.release(id);
+        if edge.deleted {
+            self.deleted_edges -= 1;
+        }
+        // Drop the id from both endpoints' adjacency lists
+        unlink_edge(&mut self.adjacency_out, edge.from_node, id);
+        unlink_edge(&mut self.adjacency_in, edge.to_node, id);
+        self.unindex_edge_type(&edge.edge_type, id);
+        Some(edge)
+    }
+
+    /// Delete a live node according to `options.soft_delete`, logging the matching record.
+    /// Returns the number of incident edges deleted with it.
+    fn remove_node(&mut self, id: NodeId, detach: bool) -> usize {
+        if self.options.soft_delete {
+            self.log_wal(|| WalRecord::TombstoneNode { id });
+            self.tombstone_node(id)
+        } else {
+            self.log_wal(|| WalRecord::DeleteNode { id, detach });
+            self.detach_node(id).map_or(0, |(_, edges)| edges)
+        }
+    }
+
+    /// Flag a node and its incident edges as deleted and drop their index entries, without
+    /// logging. Returns the number of edges tombstoned with it.
+    pub(crate) fn tombstone_node(&mut self, id: NodeId) -> usize {
+        self.node_changed(id);
+        self.unindex_node_properties(id);
+        let Some(node) = self.nodes.get_mut(&id).filter(|n| !n.deleted) else { return 0 };
+        node.deleted = true;
+        self.deleted_nodes += 1;
+        let labels = node.labels.clone();
+        for label in &labels {
+            self.unindex_label(label, id);
+        }
+        let edges = self.incident_edge_ids(id);
+        for edge_id in &edges {
+            self.tombstone_edge(*edge_id);
+        }
+        edges.len()
+    }
+
+    /// Flag an edge as deleted and unlink it from adjacency, without logging.
+    pub(crate) fn tombstone_edge(&mut self, id: EdgeId) -> bool {
+        self.edge_changed(id);
+        self.unindex_edge_properties(id);
+        self.unlink_typed(id);
+        let Some(edge) = self.edges.get_mut(&id).filter(|e| !e.deleted) else { return false };
+        edge.deleted = true;
+        self.deleted_edges += 1;
+        let (from, to, edge_type) = (edge.from_node, edge.to_node, edge.edge_type.clone());
+        unlink_edge(&mut self.adjacency_out, from, id);
+        unlink_edge(&mut self.adjacency_in, to, id);
+        self.unindex_edge_type(&edge_type, id);
+        true
+    }
+
+    /// Clear a node's tombstone and re-index its labels, without logging.
+    pub(crate) fn restore_node(&mut self, id: NodeId) {
+        self.node_changed(id);
+        let Some(node) = self.nodes.get_mut(&id).filter(|n| n.deleted) else { return };
+        node.deleted = false;
+        self.deleted_nodes -= 1;
+        for label in node.labels.clone() {
+            self.index_label(&label, id);
+        }
+        self.index_node_properties(id);
+    }
+
+    /// Clear an edge's tombstone and re-link its adjacency, without logging.
+    pub(crate) fn restore_edge(&mut self, id: EdgeId) {
+        self.edge_changed(id);
+        let Some(edge) = self.edges.get_mut(&id).filter(|e| e.deleted) else { return };
+        edge.deleted = false;
+        self.deleted_edges -= 1;
+        self.edge_type_index.entry(edge.edge_type.clone()).or_default().push(id);
+        self.link_adjacency(id);
+        self.link_typed(id);
+        self.index_edge_properties(id);
+    }
+
+    /// Merge node `remove` into node `keep` and delete `remove`.
+    ///
+    /// Every edge incident to `remove` is repointed to `keep` (edge ids and properties are
+    /// preserved), labels are unioned and properties combined with `keep` winning on
+    /// conflict. Edges between the two nodes become self-loops on `keep`; they are kept
+    /// when `keep_self_loops` is true and dropped otherwise. Self-loops `keep` already had
+    /// are never touched.
+    ///
+    /// # Errors
+    /// `NotFound` if either node is missing, `InvalidArgument` if `keep == remove`.
+    pub fn merge_nodes(&mut self, keep: NodeId, remove: NodeId, keep_self_loops: bool) -> Result<(), EngineError> {
+        if keep == remove {
+            return Err(EngineError::InvalidArgument(format!("merge_nodes: cannot merge node {} into itself", keep)));
+        }
+        self.node_mut(keep)?;
+        self.node_mut(remove)?;
+        self.check_unique_merge(keep, remove)?;
+        self.unindex_node_properties(keep);
+        self.unindex_node_properties(remove);
+        let removed = self.nodes.remove(&remove).expect("checked above");
+        self.node_ids.release(remove);
+        self.log_wal(|| WalRecord::MergeNodes { keep, remove, keep_self_loops });
+
+        // Labels: union, moving index entries over
+        for label in &removed.labels {
+            self.unindex_label(label, remove);
+        }
+        let kept = self.nodes.get_mut(&keep).expect("checked above");
+        let mut new_labels = Vec::new();
+        for label in removed.labels {
+            if !kept.labels.contains(&label) {
+                kept.labels.push(label.clone());
+                new_labels.push(label);
+            }
+        }
+        // Properties: keep wins on conflict
+        for (k, v) in removed.properties {
+            kept.properties.entry(k).or_insert(v);
+        }
+        for label in new_labels {
+            self.index_label(&label, keep);
+        }
+        self.index_node_properties(keep);
+
+        // Edges: repoint both directions, then hand the ids over to `keep`
+        let out_ids = self.adjacency_out.remove(&remove).unwrap_or_default();
+        let in_ids = self.adjacency_in.remove(&remove).unwrap_or_default();
+        for id in out_ids.iter().chain(in_ids.iter()) {
+            self.edge_changed(*id);
+            if let Some(edge) = self.edges.get_mut(id) {
+                if edge.from_node == remove { edge.from_node = keep; }
+                if edge.to_node == remove { edge.to_node = keep; }
+            }
+        }
+        let moved: Vec<EdgeId> = out_ids.iter().chain(in_ids.iter()).copied().collect();
+        self.adjacency_out.entry(keep).or_default().extend(out_ids);
+        self.adjacency_in.entry(keep).or_default().extend(in_ids);
+        let ends: Vec<NodeId> = moved.iter().filter_map(|id| self.edges.get(id)).flat_map(|e| [e.from_node, e.to_node]).collect();
+        self.resort_adjacency(ends);
+        self.move_typed_adjacency(remove, keep);
+
+        if !keep_self_loops {
+            for id in moved {
+                let is_loop = self.edges.get(&id).is_some_and(|e| e.from_node == keep && e.to_node == keep);
+                if is_loop {
+                    self.detach_edge(id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids of the nodes carrying `label` whose `key` property equals `value` (no cloning),
+    /// read from the property index on `(label, key)` when there is one.
+    fn find_by_label_and_property(&self, label: &str, key: &str, value: &Value) -> Vec<NodeId> {
+        if let Some(ids) = self.indexed_nodes(label, key, value) {
+            // A normalizing index's bucket can also hold values that are not equal
+            return ids.iter()
+                .copied()
+                .filter(|id| self.nodes.get(id).is_some_and(|n| n.properties.get(key) == Some(value)))
+                .collect();
+        }
+        self.label_index.get(label)
+            .map(|ids| ids.iter()
+                .copied()
+                .filter(|id| self.nodes.get(id)
+                    .is_some_and(|n| n.properties.get(key) == Some(value)))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Change the type of a live edge and move it between `edge_type_index` buckets,
+    /// without logging.
+    pub(crate) fn retype_edge(&mut self, id: EdgeId, new_type: String) {
+        self.edge_changed(id);
+        self.unindex_edge_properties(id);
+        self.unlink_typed(id);
+        let Some(edge) = self.edges.get_mut(&id).filter(|e| !e.deleted) else { return };
+        let old = std::mem::replace(&mut edge.edge_type, new_type.clone());
+        if old != new_type {
+            let ends = [edge.from_node, edge.to_node];
+            self.unindex_edge_type(&old, id);
+            self.edge_type_index.entry(new_type).or_default().push(id);
+            self.resort_adjacency(ends);
+        }
+        self.link_typed(id);
+        self.index_edge_properties(id);
+    }
+
+    /// Remove `id` from the bucket of `edge_type`, dropping the bucket once empty.
+    fn unindex_edge_type(&mut self, edge_type: &str, id: EdgeId) {
+        if let Some(ids) = self.edge_type_index.get_mut(edge_type) {
+            ids.retain(|e| *e != id);
+            if ids.is_empty() {
+                self.edge_type_index.remove(edge_type);
+            }
+        }
+    }
+
+    /// File `id` in the bucket of `label`, keeping the bucket sorted. Filing an id twice
+    /// is a no-op, so overlapping loads and replays cannot duplicate scan results.
+    pub(crate) fn index_label(&mut self, label: &str, id: NodeId) {
+        let ids = self.label_index.entry(label.to_string()).or_default();
+        if let Err(pos) = ids.binary_search(&id) {
+            ids.insert(pos, id);
+        }
+    }
+
+    /// Remove `id` from the bucket of `label`, dropping the bucket once empty.
+    fn unindex_label(&mut self, label: &str, id: NodeId) {
+        if let Some(ids) = self.label_index.get_mut(label) {
+            if let Ok(pos) = ids.binary_search(&id) {
+                ids.remove(pos);
+            }
+            if ids.is_empty() {
+                self.label_index.remove(label);
+            }
         }
     }
 }
 
 impl GraphReadStore for InMemoryGraphStore {
     fn scan_all(&self) -> Result<Vec<Node>, EngineError> {
-        Ok(self.nodes.values().cloned().collect())
+        Ok(self.ordered(self.nodes.values().filter(|n| !n.deleted).cloned().collect(), |n| n.id))
     }
 
     fn scan_by_label(&self, label: &str) -> Result<Vec<Node>, EngineError> {
         if let Some(node_ids) = self.label_index.get(label) {
+            // Buckets are kept sorted, so this is id order whatever the options
             Ok(node_ids.iter()
                 .filter_map(|id| self.nodes.get(id).cloned())
                 .collect())
@@ -57,85 +1278,564 @@ impl GraphReadStore for InMemoryGraphStore {
     }
 
     fn get_node(&self, id: NodeId) -> Result<Option<Node>, EngineError> {
-        Ok(self.nodes.get(&id).cloned())
+        Ok(self.live_node(id).cloned())
     }
 
     fn get_neighbors(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node)>, EngineError> {
         let mut result = Vec::new();
 
-        if let Some(edge_ids) = self.adjacency_out.get(&node_id) {
-            for edge_id in edge_ids {
-                if let Some(edge) = self.edges.get(edge_id) {
-                    if let Some(et) = edge_type {
-                        if edge.edge_type != et {
-                            continue;
-                        }
-                    }
-                    if let Some(node) = self.nodes.get(&edge.to_node) {
-                        result.push((edge.clone(), node.clone()));
+        for edge_id in self.adjacency_list(node_id, true, edge_type) {
+            if let Some(edge) = self.edges.get(edge_id) {
+                if let Some(et) = edge_type {
+                    if edge.edge_type != et {
+                        continue;
                     }
                 }
+                if let Some(node) = self.nodes.get(&edge.to_node) {
+                    result.push((edge.clone(), node.clone()));
+                }
             }
         }
 
-        Ok(result)
+        Ok(self.ordered(result, |(e, _)| e.id))
     }
 
     fn get_neighbors_incoming(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node)>, EngineError> {
         let mut result = Vec::new();
 
-        if let Some(edge_ids) = self.adjacency_in.get(&node_id) {
-            for edge_id in edge_ids {
-                if let Some(edge) = self.edges.get(edge_id) {
-                    if let Some(et) = edge_type {
-                        if edge.edge_type != et {
-                            continue;
-                        }
+        for edge_id in self.adjacency_list(node_id, false, edge_type) {
+            if let Some(edge) = self.edges.get(edge_id) {
+                if let Some(et) = edge_type {
+                    if edge.edge_type != et {
+                        continue;
                     }
-                    if let Some(node) = self.nodes.get(&edge.from_node) {
-                        result.push((edge.clone(), node.clone()));
+                }
+                if let Some(node) = self.nodes.get(&edge.from_node) {
+                    result.push((edge.clone(), node.clone()));
+                }
+            }
+        }
+
+        Ok(self.ordered(result, |(e, _)| e.id))
+    }
+
+    fn neighbor_summary(&self, node_id: NodeId) -> Result<HashMap<(String, Direction), usize>, EngineError> {
+        if self.live_node(node_id).is_none() {
+            return Err(EngineError::NotFound(format!("node {}", node_id)));
+        }
+        // Count per borrowed type first; only one String per group is allocated
+        let mut counts: HashMap<(&str, Direction), usize> = HashMap::new();
+        for (adjacency, direction) in [(&self.adjacency_out, Direction::Outgoing), (&self.adjacency_in, Direction::Incoming)] {
+            for id in adjacency.get(&node_id).into_iter().flatten() {
+                if let Some(edge) = self.edges.get(id) {
+                    *counts.entry((edge.edge_type.as_str(), direction)).or_default() += 1;
+                }
+            }
+        }
+        Ok(counts.into_iter().map(|((t, d), n)| ((t.to_string(), d), n)).collect())
+    }
+
+    fn get_neighbors_where(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction, edge_pred: &dyn Fn(&Edge) -> bool) -> Result<Vec<(Edge, Node)>, EngineError> {
+        let result = self.adjacent(node_id, direction, edge_type)
+            .filter_map(|(edge_id, other)| {
+                let edge = self.edges.get(&edge_id).filter(|e| edge_pred(e))?;
+                Some((edge.clone(), self.nodes.get(&other)?.clone()))
+            })
+            .collect();
+        Ok(self.ordered(result, |(e, _)| e.id))
+    }
+
+    fn get_neighbor_ids(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction) -> Result<Vec<NodeId>, EngineError> {
+        if !self.options.deterministic_iteration {
+            return Ok(self.adjacent(node_id, direction, edge_type).map(|(_, n)| n).collect());
+        }
+        Ok(self.get_neighbor_edge_ids(node_id, edge_type, direction)?.into_iter().map(|(_, n)| n).collect())
+    }
+
+    fn get_neighbor_edge_ids(&self, node_id: NodeId, edge_type: Option<&str>, direction: Direction) -> Result<Vec<(EdgeId, NodeId)>, EngineError> {
+        Ok(self.ordered(self.adjacent(node_id, direction, edge_type).collect(), |(e, _)| *e))
+    }
+
+    fn get_neighbors_undirected(&self, node_id: NodeId, edge_type: Option<&str>) -> Result<Vec<(Edge, Node, Direction)>, EngineError> {
+        let mut result = Vec::new();
+        for (outgoing, direction) in [(true, Direction::Outgoing), (false, Direction::Incoming)] {
+            for edge_id in self.adjacency_list(node_id, outgoing, edge_type) {
+                let Some(edge) = self.edges.get(edge_id) else { continue };
+                if edge_type.is_some_and(|t| edge.edge_type != t) {
+                    continue;
+                }
+                let other = match direction {
+                    Direction::Incoming if edge.from_node == node_id => continue, // self-loop, already listed
+                    Direction::Incoming => edge.from_node,
+                    _ => edge.to_node,
+                };
+                if let Some(node) = self.nodes.get(&other) {
+                    result.push((edge.clone(), node.clone(), direction));
+                }
+            }
+        }
+        Ok(self.ordered(result, |(e, _, _)| e.id))
+    }
+
+    fn neighborhood(&self, start: NodeId, max_depth: usize, edge_type: Option<&str>, direction: Direction) -> Result<HashMap<NodeId, usize>, EngineError> {
+        Ok(self.neighborhood_tree(start, max_depth, edge_type, direction)?
+            .into_iter()
+            .map(|(id, (depth, _))| (id, depth))
+            .collect())
+    }
+
+    fn iter_nodes(&self) -> Result<Box<dyn Iterator<Item = &Node> + '_>, EngineError> {
+        Ok(self.iter_nodes_ordered(None))
+    }
+
+    fn iter_nodes_by_label(&self, label: &str) -> Result<Box<dyn Iterator<Item = &Node> + '_>, EngineError> {
+        Ok(self.iter_nodes_ordered(Some(label)))
+    }
+
+    fn visit_node(&self, id: NodeId, f: &mut dyn FnMut(&Node)) -> Result<bool, EngineError> {
+        Ok(self.live_node(id).map(f).is_some())
+    }
+
+    fn for_each_node_with_label(&self, label: &str, f: &mut dyn FnMut(&Node)) -> Result<(), EngineError> {
+        self.iter_nodes_ordered(Some(label)).for_each(f);
+        Ok(())
+    }
+
+    fn scan_page(&self, label: Option<&str>, cursor: Option<ScanCursor>, limit: usize) -> Result<(Vec<Node>, Option<ScanCursor>), EngineError> {
+        if limit == 0 {
+            return Err(EngineError::InvalidArgument("scan_page: limit must be positive".into()));
+        }
+        let after = cursor.map(|c| c.last_id());
+        let remaining = |id: &NodeId| after.is_none_or(|last| *id > last);
+        let mut ids: Vec<NodeId> = match label {
+            Some(label) => self.label_index.get(label)
+                .map(|ids| ids.iter().copied().filter(remaining).collect())
+                .unwrap_or_default(),
+            None => self.nodes.values().filter(|n| !n.deleted).map(|n| n.id).filter(remaining).collect(),
+        };
+        // Neither map is ordered: pick the `limit` lowest ids in O(n), then sort just those
+        let more = ids.len() > limit;
+        if more {
+            ids.select_nth_unstable(limit);
+            ids.truncate(limit);
+        }
+        ids.sort_unstable();
+        let next = if more { ids.last().map(|id| ScanCursor::after(*id)) } else { None };
+        Ok((ids.into_iter().filter_map(|id| self.nodes.get(&id).cloned()).collect(), next))
+    }
+
+    fn scan_by_labels(&self, labels: &[&str], mode: LabelMatch) -> Result<Vec<Node>, EngineError> {
+        if labels.is_empty() {
+            return match mode {
+                LabelMatch::All => self.scan_all(),
+                LabelMatch::Any => Ok(Vec::new()),
+            };
+        }
+        let empty = Vec::new();
+        let mut buckets: Vec<&Vec<NodeId>> = labels.iter()
+            .map(|l| self.label_index.get(*l).unwrap_or(&empty))
+            .collect();
+        let ids: Vec<NodeId> = match mode {
+            LabelMatch::All => {
+                // Walk the smallest bucket and probe the others
+                buckets.sort_by_key(|b| b.len());
+                let rest: Vec<HashSet<NodeId>> = buckets[1..].iter()
+                    .map(|b| b.iter().copied().collect())
+                    .collect();
+                buckets[0].iter()
+                    .copied()
+                    .filter(|id| rest.iter().all(|set| set.contains(id)))
+                    .collect()
+            }
+            LabelMatch::Any => {
+                let mut seen = HashSet::new();
+                buckets.iter()
+                    .flat_map(|b| b.iter().copied())
+                    .filter(|id| seen.insert(*id))
+                    .collect()
+            }
+        };
+        let ids = self.ordered(ids, |id| *id);
+        Ok(ids.into_iter().filter_map(|id| self.nodes.get(&id).cloned()).collect())
+    }
+
+    fn node_count(&self) -> Result<usize, EngineError> {
+        Ok(self.nodes.len() - self.deleted_nodes)
+    }
+
+    fn edge_count(&self) -> Result<usize, EngineError> {
+        Ok(self.edges.len() - self.deleted_edges)
+    }
+
+    fn count_by_label(&self, label: &str) -> Result<usize, EngineError> {
+        Ok(self.label_index.get(label).map_or(0, Vec::len))
+    }
+
+    fn count_edges_by_type(&self, edge_type: &str) -> Result<usize, EngineError> {
+        Ok(self.edge_type_index.get(edge_type).map_or(0, Vec::len))
+    }
+
+    fn degree(&self, node_id: NodeId, direction: Direction, edge_type: Option<&str>) -> Result<usize, EngineError> {
+        if self.live_node(node_id).is_none() {
+            return Err(EngineError::NotFound(format!("node {}", node_id)));
+        }
+        let of_type = |id: &&EdgeId| edge_type.is_none_or(|t| self.edges[id].edge_type == t);
+        let count = |outgoing: bool| {
+            let ids = self.adjacency_list(node_id, outgoing, edge_type);
+            match edge_type {
+                Some(_) if !self.options.typed_adjacency => ids.iter().filter(of_type).count(),
+                _ => ids.len(),
+            }
+        };
+        Ok(match direction {
+            Direction::Outgoing => count(true),
+            Direction::Incoming => count(false),
+            Direction::Both => {
+                // Self-loops sit in both lists of the node; count them once
+                let loops = self.adjacency_list(node_id, true, edge_type).iter()
+                    .filter(of_type)
+                    .filter(|id| self.edges[*id].to_node == node_id)
+                    .count();
+                count(true) + count(false) - loops
+            }
+        })
+    }
+
+    fn labels_of(&self, id: NodeId) -> Result<Vec<String>, EngineError> {
+        self.live_node(id)
+            .map(|n| n.labels.clone())
+            .ok_or_else(|| EngineError::NotFound(format!("node {}", id)))
+    }
+
+    fn node_property_keys(&self, id: NodeId) -> Result<Option<Vec<String>>, EngineError> {
+        Ok(self.live_node(id).map(|n| {
+            let mut keys: Vec<String> = n.properties.keys().cloned().collect();
+            keys.sort();
+            keys
+        }))
+    }
+
+    fn property_key_stats(&self, label: Option<&str>) -> Result<HashMap<String, usize>, EngineError> {
+        let mut stats: HashMap<String, usize> = HashMap::new();
+        for node in self.iter_nodes_matching(label) {
+            for key in node.properties.keys() {
+                // Only the first sighting of a key allocates
+                match stats.get_mut(key) {
+                    Some(count) => *count += 1,
+                    None => {
+                        stats.insert(key.clone(), 1);
                     }
                 }
             }
         }
+        Ok(stats)
+    }
+
+    fn sample_nodes(&self, n: usize, label: Option<&str>, seed: Option<u64>) -> Result<Vec<Node>, EngineError> {
+        // Sample sorted ids (neither map has a stable order); only the winners are cloned
+        let mut ids: Vec<NodeId> = self.iter_nodes_matching(label).map(|n| n.id).collect();
+        ids.sort_unstable();
+        let mut sample = casys_core::sample_reservoir(ids, n, seed);
+        sample.sort_unstable();
+        Ok(sample.into_iter().filter_map(|id| self.nodes.get(&id).cloned()).collect())
+    }
+
+    fn sample_edges(&self, n: usize, edge_type: Option<&str>, seed: Option<u64>) -> Result<Vec<Edge>, EngineError> {
+        let mut ids: Vec<EdgeId> = match edge_type {
+            Some(t) => self.edge_type_index.get(t).cloned().unwrap_or_default(),
+            None => self.edges.values().filter(|e| !e.deleted).map(|e| e.id).collect(),
+        };
+        ids.sort_unstable();
+        let mut sample = casys_core::sample_reservoir(ids, n, seed);
+        sample.sort_unstable();
+        Ok(sample.into_iter().filter_map(|id| self.edges.get(&id).cloned()).collect())
+    }
+
+    fn top_by_property(&self, label: Option<&str>, key: &str, n: usize, ascending: bool) -> Result<Vec<Node>, EngineError> {
+        // Rank by reference; only the winners are cloned
+        let keyed = self.iter_nodes_matching(label).map(|node| (node.id, node.properties.get(key).cloned(), node));
+        Ok(casys_core::top_n_by_value(keyed, n, ascending).into_iter().cloned().collect())
+    }
+
+    fn nodes_without_edges(&self, direction: Direction, edge_type: Option<&str>) -> Result<Vec<NodeId>, EngineError> {
+        let mut ids: Vec<NodeId> = self.iter_nodes_matching(None)
+            .map(|n| n.id)
+            .filter(|id| self.adjacent(*id, direction, edge_type).next().is_none())
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn labels(&self) -> Result<Vec<(String, usize)>, EngineError> {
+        Ok(casys_core::sorted_counts(self.label_index.iter().map(|(l, ids)| (l.clone(), ids.len()))))
+    }
+
+    fn edge_types(&self) -> Result<Vec<(String, usize)>, EngineError> {
+        Ok(casys_core::sorted_counts(self.edge_type_index.iter().map(|(t, ids)| (t.clone(), ids.len()))))
+    }
 
-        Ok(result)
+    fn scan_by_property(&self, label: Option<&str>, key: &str, value: &Value) -> Result<Vec<Node>, EngineError> {
+        Ok(self.scan_by_property_explain(label, key, value)?.0)
+    }
+
+    fn scan_property_state(&self, label: &str, key: &str, test: &dyn Fn(Option<&Value>) -> bool) -> Result<Vec<NodeId>, EngineError> {
+        let mut ids: Vec<NodeId> = self.iter_nodes_matching(Some(label))
+            .filter(|n| test(n.properties.get(key)))
+            .map(|n| n.id)
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn scan_filter_limit(&self, label: Option<&str>, pred: &dyn Fn(&Node) -> bool, limit: usize) -> Result<Vec<Node>, EngineError> {
+        Ok(self.iter_nodes_ordered(label).filter(|n| pred(n)).take(limit).cloned().collect())
+    }
+
+    fn scan_by_property_range(&self, label: Option<&str>, key: &str, min: Option<Value>, max: Option<Value>, inclusive: bool) -> Result<Vec<Node>, EngineError> {
+        Ok(self.scan_by_property_range_explain(label, key, min, max, inclusive)?.0)
+    }
+
+    fn get_edge(&self, id: EdgeId) -> Result<Option<Edge>, EngineError> {
+        Ok(self.edges.get(&id).filter(|e| !e.deleted).cloned())
+    }
+
+    fn get_edges_between(&self, from: NodeId, to: NodeId, edge_type: Option<&str>) -> Result<Vec<Edge>, EngineError> {
+        Ok(self.ordered(self.edges_between(from, to, edge_type).cloned().collect(), |e| e.id))
+    }
+
+    fn node_exists(&self, id: NodeId) -> Result<bool, EngineError> {
+        Ok(self.live_node(id).is_some())
+    }
+
+    fn has_edge(&self, from: NodeId, to: NodeId, edge_type: Option<&str>) -> Result<bool, EngineError> {
+        Ok(self.edges_between(from, to, edge_type).next().is_some())
+    }
+
+    fn get_nodes(&self, ids: &[NodeId]) -> Result<Vec<Option<Node>>, EngineError> {
+        Ok(ids.iter().map(|id| self.live_node(*id).cloned()).collect())
+    }
+
+    fn get_edges(&self, ids: &[EdgeId]) -> Result<Vec<Option<Edge>>, EngineError> {
+        Ok(ids.iter().map(|id| self.edges.get(id).filter(|e| !e.deleted).cloned()).collect())
+    }
+
+    fn scan_all_edges(&self) -> Result<Vec<Edge>, EngineError> {
+        Ok(self.ordered(self.edges.values().filter(|e| !e.deleted).cloned().collect(), |e| e.id))
+    }
+
+    fn scan_edges_by_property(&self, edge_type: &str, key: &str, value: &Value) -> Result<Vec<Edge>, EngineError> {
+        let edges = match self.indexed_edges(edge_type, key, value) {
+            Some(ids) => ids.iter().filter_map(|id| self.edges.get(id).cloned()).collect(),
+            None => self.edge_type_index.get(edge_type).into_iter().flatten()
+                .filter_map(|id| self.edges.get(id))
+                .filter(|e| e.properties.get(key) == Some(value))
+                .cloned()
+                .collect(),
+        };
+        Ok(self.ordered(edges, |e| e.id))
+    }
+
+    fn scan_edges_by_type(&self, edge_type: &str) -> Result<Vec<Edge>, EngineError> {
+        let edges = self.edge_type_index.get(edge_type)
+            .map(|ids| ids.iter().filter_map(|id| self.edges.get(id).cloned()).collect())
+            .unwrap_or_default();
+        Ok(self.ordered(edges, |e| e.id))
     }
 }
 
 impl GraphWriteStore for InMemoryGraphStore {
     fn add_node(&mut self, labels: Vec<String>, properties: HashMap<String, Value>) -> Result<NodeId, EngineError> {
-        let id = self.next_node_id;
-        self.next_node_id += 1;
+        self.check_unique_node(&[], &labels, &properties)?;
+        let id = self.node_ids.next_id();
+        self.log_wal(|| WalRecord::AddNode { id, labels: labels.clone(), properties: properties.clone() });
+        self.insert_node(Node { id, labels, properties, deleted: false });
+        Ok(id)
+    }
 
-        let node = Node { id, labels: labels.clone(), properties };
-        self.nodes.insert(id, node);
+    fn add_edge(&mut self, from: NodeId, to: NodeId, edge_type: String, properties: HashMap<String, Value>) -> Result<EdgeId, EngineError> {
+        self.check_endpoints(from, to)?;
+        self.add_edge_unchecked(from, to, edge_type, properties)
+    }
 
-        // Update label index
-        for label in labels {
-            self.label_index.entry(label).or_insert_with(Vec::new).push(id);
+    fn delete_edge(&mut self, id: EdgeId) -> Result<bool, EngineError> {
+        if self.edge_mut(id).is_err() {
+            return Ok(false);
         }
+        if self.options.soft_delete {
+            self.log_wal(|| WalRecord::TombstoneEdge { id });
+            self.tombstone_edge(id);
+        } else {
+            self.log_wal(|| WalRecord::DeleteEdge { id });
+            self.detach_edge(id);
+        }
+        Ok(true)
+    }
 
-        Ok(id)
+    fn delete_node(&mut self, id: NodeId, detach: bool) -> Result<bool, EngineError> {
+        if self.live_node(id).is_none() {
+            return Ok(false);
+        }
+        if !detach {
+            let degree = self.incident_edge_ids(id).len();
+            if degree > 0 {
+                return Err(EngineError::InvalidArgument(format!(
+                    "node {} still has {} edges; delete with detach", id, degree
+                )));
+            }
+        }
+        self.remove_node(id, detach);
+        Ok(true)
     }
 
-    fn add_edge(&mut self, from: NodeId, to: NodeId, edge_type: String, properties: HashMap<String, Value>) -> Result<EdgeId, EngineError> {
-        let id = self.next_edge_id;
-        self.next_edge_id += 1;
+    fn set_node_property(&mut self, id: NodeId, key: String, value: Value) -> Result<(), EngineError> {
+        self.node_mut(id)?;
+        self.check_unique(Some(id), &self.nodes[&id].labels, &key, &value)?;
+        self.log_wal(|| WalRecord::SetNodeProperty { id, key: key.clone(), value: value.clone() });
+        self.unindex_node_properties(id);
+        self.node_mut(id)?.properties.insert(key, value);
+        self.index_node_properties(id);
+        Ok(())
+    }
 
-        let edge = Edge {
-            id,
-            from_node: from,
-            to_node: to,
-            edge_type,
-            properties,
-        };
-        self.edges.insert(id, edge);
+    fn remove_node_property(&mut self, id: NodeId, key: &str) -> Result<Option<Value>, EngineError> {
+        self.node_mut(id)?;
+        self.unindex_node_properties(id);
+        let removed = self.node_mut(id)?.properties.remove(key);
+        self.index_node_properties(id);
+        if removed.is_some() {
+            self.log_wal(|| WalRecord::RemoveNodeProperty { id, key: key.to_string() });
+        }
+        Ok(removed)
+    }
 
-        // Update adjacency indexes
-        self.adjacency_out.entry(from).or_insert_with(Vec::new).push(id);
-        self.adjacency_in.entry(to).or_insert_with(Vec::new).push(id);
+    fn update_node_properties(&mut self, id: NodeId, props: HashMap<String, Value>) -> Result<(), EngineError> {
+        self.node_mut(id)?;
+        // Check every key up front so a violation leaves the node untouched
+        for (key, value) in &props {
+            self.check_unique(Some(id), &self.nodes[&id].labels, key, value)?;
+        }
+        for (key, value) in props {
+            self.set_node_property(id, key, value)?;
+        }
+        Ok(())
+    }
 
-        Ok(id)
+    fn add_label(&mut self, id: NodeId, label: String) -> Result<bool, EngineError> {
+        let node = self.node_mut(id)?;
+        if node.labels.contains(&label) {
+            return Ok(false);
+        }
+        self.check_unique_node(&[id], std::slice::from_ref(&label), &self.nodes[&id].properties)?;
+        self.unindex_node_properties(id);
+        self.node_mut(id)?.labels.push(label.clone());
+        self.index_node_properties(id);
+        self.index_label(&label, id);
+        self.log_wal(|| WalRecord::AddLabel { id, label });
+        Ok(true)
+    }
+
+    fn remove_label(&mut self, id: NodeId, label: &str) -> Result<bool, EngineError> {
+        if !self.node_mut(id)?.labels.iter().any(|l| l == label) {
+            return Ok(false);
+        }
+        self.unindex_node_properties(id);
+        self.node_mut(id)?.labels.retain(|l| l != label);
+        self.index_node_properties(id);
+        self.unindex_label(label, id);
+        self.log_wal(|| WalRecord::RemoveLabel { id, label: label.to_string() });
+        Ok(true)
+    }
+
+    fn update_edge_properties(&mut self, id: EdgeId, props: HashMap<String, Value>) -> Result<(), EngineError> {
+        self.edge_mut(id)?;
+        self.unindex_edge_properties(id);
+        for (key, value) in props {
+            self.log_wal(|| WalRecord::SetEdgeProperty { id, key: key.clone(), value: value.clone() });
+            self.edge_mut(id)?.properties.insert(key, value);
+        }
+        self.index_edge_properties(id);
+        Ok(())
+    }
+
+    fn set_edge_type(&mut self, id: EdgeId, new_type: String) -> Result<(), EngineError> {
+        self.edge_mut(id)?;
+        self.log_wal(|| WalRecord::SetEdgeType { id, edge_type: new_type.clone() });
+        self.retype_edge(id, new_type);
+        Ok(())
+    }
+
+    fn add_nodes_bulk(&mut self, nodes: Vec<(Vec<String>, HashMap<String, Value>)>) -> Result<Vec<NodeId>, EngineError> {
+        self.check_unique_nodes(nodes.iter().map(|(labels, properties)| (labels.as_slice(), properties)))?;
+        // Reserve the whole id range up front
+        let first = self.node_ids.reserve(nodes.len() as u64).start;
+        self.nodes.reserve(nodes.len());
+
+        let mut ids = Vec::with_capacity(nodes.len());
+        let mut by_label: HashMap<String, Vec<NodeId>> = HashMap::new();
+        for (offset, (labels, properties)) in nodes.into_iter().enumerate() {
+            let id = first + offset as u64;
+            self.log_wal(|| WalRecord::AddNode { id, labels: labels.clone(), properties: properties.clone() });
+            for label in &labels {
+                by_label.entry(label.clone()).or_default().push(id);
+            }
+            self.node_changed(id);
+            self.nodes.insert(id, Node { id, labels, properties, deleted: false });
+            ids.push(id);
+        }
+
+        // One index update per label rather than per node
+        for (label, mut label_ids) in by_label {
+            let bucket = self.label_index.entry(label).or_default();
+            bucket.append(&mut label_ids);
+            // Reused ids can sort below the existing ones, and a label may repeat on a node
+            bucket.sort_unstable();
+            bucket.dedup();
+        }
+        for id in &ids {
+            self.index_node_properties(*id);
+        }
+
+        Ok(ids)
+    }
+
+    fn add_edges_bulk(&mut self, edges: Vec<(NodeId, NodeId, String, HashMap<String, Value>)>) -> Result<Vec<EdgeId>, EngineError> {
+        // Validate every endpoint before touching anything so a failure leaves the store unchanged
+        for (from, to, _, _) in &edges {
+            self.check_endpoints(*from, *to)?;
+        }
+
+        let first = self.edge_ids.reserve(edges.len() as u64).start;
+        self.edges.reserve(edges.len());
+
+        let mut ids = Vec::with_capacity(edges.len());
+        for (offset, (from, to, edge_type, properties)) in edges.into_iter().enumerate() {
+            let id = first + offset as u64;
+            self.log_wal(|| WalRecord::AddEdge {
+                id,
+                from_node: from,
+                to_node: to,
+                edge_type: edge_type.clone(),
+                properties: properties.clone(),
+            });
+            self.edge_type_index.entry(edge_type.clone()).or_default().push(id);
+            self.edge_changed(id);
+            self.edges.insert(id, Edge { id, from_node: from, to_node: to, edge_type, properties, deleted: false });
+            self.link_adjacency(id);
+            self.link_typed(id);
+            self.index_edge_properties(id);
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+}
+
+/// Remove `edge_id` from the adjacency bucket of `node_id`, dropping the bucket once empty.
+fn unlink_edge(adjacency: &mut HashMap<NodeId, Vec<EdgeId>>, node_id: NodeId, edge_id: EdgeId) {
+    if let Some(ids) = adjacency.get_mut(&node_id) {
+        ids.retain(|e| *e != edge_id);
+        if ids.is_empty() {
+            adjacency.remove(&node_id);
+        }
     }
 }
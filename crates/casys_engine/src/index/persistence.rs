@@ -3,17 +3,101 @@
 //! This module uses the SegmentStore trait from casys_core for hexagonal architecture.
 //! Storage adapters (FS, S3, etc.) implement SegmentStore and are injected by the caller.
 
-use super::{InMemoryGraphStore, Node, Edge, Value};
+use super::{InMemoryGraphStore, Node, Edge, Value, GraphWriteStore, Endpoint, StoreOptions};
+use super::computed_index::ComputedIndex;
+use super::index_stats::IndexKind;
+use super::property_index::IndexOptions;
+use super::index_segment::INDEX_SEGMENT_ID;
+use super::segment_binary::{corruption, read_error, segment_version, sorted_ids, SegmentFormat};
+use super::segment_delta::{ChangeSet, DeltaManifest, DELTA_MANIFEST_ID};
+use super::segment_partition::{PartitionManifest, PARTITION_MANIFEST_ID, UNLABELED_PARTITION_ID};
+use super::statistics::STATISTICS_SEGMENT_ID;
 use casys_core::{NodeId, EdgeId, SegmentId, SegmentStore};
 use crate::exec::executor::ValueExt; // Import extension trait for to_json/from_json
 use crate::types::{EngineError, DatabaseName};
 #[cfg(feature = "fs")]
 use crate::types::BranchName;
+use super::wal_binary;
+use super::wal_file::{decode_frame, decode_unnumbered_frame, encode_frame};
 use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::ops::ControlFlow;
 use std::path::Path;
 
+pub use super::group_commit::{GroupCommitConfig, GroupCommitWriter};
+pub use super::wal_archive::{ArchiveHook, FsArchive};
+pub use super::wal_binary::PayloadFormat;
+pub use super::wal_compress::{
+    register_compressor, Compression, Compressor, IdentityCompressor, DEFAULT_COMPRESSION_THRESHOLD, MAX_DECOMPRESSED_PAYLOAD,
+};
+#[cfg(feature = "deflate")]
+pub use super::wal_compress::DeflateCompressor;
+pub use super::wal_inspect::{
+    dump_wal, inspect_wal, DumpFormat, WalAnomaly, WalFrame, WalFrames, WalInspection, WalStats,
+};
+pub use super::wal_verify::{verify_against_wal, DivergenceReport, Mismatch};
+pub use super::wal_file::{
+    read_checkpoint, wal_dir, Checkpoint, Lsn, RecoveryPolicy, RecoveryReport, ReplayProgress, SyncPolicy, WalEntry,
+    WalReader, WalWriter,
+    DEFAULT_MAX_WAL_FILE_BYTES,
+};
+
+/// Identifies a WAL transaction; see `InMemoryGraphStore::begin_wal_txn`.
+pub type TxnId = u64;
+
+/// What a `CreateIndex` / `DropIndex` record defines: a secondary index or a unique
+/// constraint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DdlKind {
+    Index(IndexKind),
+    UniqueConstraint,
+}
+
+impl DdlKind {
+    /// The `kind` of the definition in the index segment and in JSON WAL payloads.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            DdlKind::Index(IndexKind::Property) => "property",
+            DdlKind::Index(IndexKind::Range) => "range",
+            DdlKind::Index(IndexKind::Composite) => "composite",
+            DdlKind::Index(IndexKind::Text) => "text",
+            DdlKind::Index(IndexKind::Prefix) => "prefix",
+            DdlKind::Index(IndexKind::Edge) => "edge",
+            DdlKind::Index(IndexKind::Computed) => "computed",
+            DdlKind::Index(IndexKind::Ttl) => "ttl",
+            DdlKind::UniqueConstraint => "unique",
+        }
+    }
+
+    pub(crate) fn parse(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "property" => DdlKind::Index(IndexKind::Property),
+            "range" => DdlKind::Index(IndexKind::Range),
+            "composite" => DdlKind::Index(IndexKind::Composite),
+            "text" => DdlKind::Index(IndexKind::Text),
+            "prefix" => DdlKind::Index(IndexKind::Prefix),
+            "edge" => DdlKind::Index(IndexKind::Edge),
+            "computed" => DdlKind::Index(IndexKind::Computed),
+            "ttl" => DdlKind::Index(IndexKind::Ttl),
+            "unique" => DdlKind::UniqueConstraint,
+            _ => return None,
+        })
+    }
+}
+
+/// The settings of a `CreateIndex` record beyond its label and keys. Each kind reads only
+/// its own: `property` and `covered` for property indexes, `case_sensitive` for prefix
+/// indexes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DdlOptions {
+    pub property: IndexOptions,
+    /// Covered keys of a covering index, empty otherwise
+    pub covered: Vec<String>,
+    pub case_sensitive: bool,
+}
+
 /// WAL record pour mutations graph
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WalRecord {
     AddNode {
         id: NodeId,
@@ -27,12 +111,180 @@ pub enum WalRecord {
         edge_type: String,
         properties: HashMap<String, Value>,
     },
+    DeleteNode {
+        id: NodeId,
+        detach: bool,
+    },
+    DeleteEdge {
+        id: EdgeId,
+    },
+    TombstoneNode {
+        id: NodeId,
+    },
+    TombstoneEdge {
+        id: EdgeId,
+    },
+    UndeleteNode {
+        id: NodeId,
+    },
+    UndeleteEdge {
+        id: EdgeId,
+    },
+    PurgeTombstones,
+    SetNodeProperty {
+        id: NodeId,
+        key: String,
+        value: Value,
+    },
+    RemoveNodeProperty {
+        id: NodeId,
+        key: String,
+    },
+    AddLabel {
+        id: NodeId,
+        label: String,
+    },
+    RemoveLabel {
+        id: NodeId,
+        label: String,
+    },
+    SetEdgeProperty {
+        id: EdgeId,
+        key: String,
+        value: Value,
+    },
+    SetEdgeType {
+        id: EdgeId,
+        edge_type: String,
+    },
+    SetEdgeEndpoint {
+        id: EdgeId,
+        endpoint: Endpoint,
+        node: NodeId,
+    },
+    ReverseEdge {
+        id: EdgeId,
+    },
+    RenameEdgeType {
+        old: String,
+        new: String,
+    },
+    MergeNodes {
+        keep: NodeId,
+        remove: NodeId,
+        keep_self_loops: bool,
+    },
+    Truncate {
+        reset_ids: bool,
+    },
+    /// An index or constraint created by one of the `create_*` methods. `label` is the
+    /// edge type of an edge index and `keys` the name of a computed index; only composite
+    /// indexes have several keys.
+    CreateIndex {
+        kind: DdlKind,
+        label: String,
+        keys: Vec<String>,
+        options: DdlOptions,
+    },
+    /// An index or constraint dropped by one of the `drop_*` methods.
+    DropIndex {
+        kind: DdlKind,
+        label: String,
+        keys: Vec<String>,
+    },
+    /// First record of a WAL file started by `WalWriter::checkpoint`: the records after
+    /// it are numbered from `lsn + 1`. Replaying it is a no-op.
+    Checkpoint {
+        generation: u64,
+        lsn: Lsn,
+    },
+    /// Opens transaction `txn_id`: its records are applied by a transactional replay only
+    /// once its `CommitTxn` is seen.
+    BeginTxn {
+        txn_id: TxnId,
+    },
+    CommitTxn {
+        txn_id: TxnId,
+    },
+    /// Discards the records of transaction `txn_id` on a transactional replay.
+    AbortTxn {
+        txn_id: TxnId,
+    },
+    /// A data record logged inside transaction `txn_id`, serialized as that record with a
+    /// `txn_id` field.
+    InTxn {
+        txn_id: TxnId,
+        record: Box<WalRecord>,
+    },
 }
 
 impl WalRecord {
-    /// Sérialise le record en bytes (format simple: type(1) + JSON)
+    /// Serialize the record as one frame carrying LSN 0 (not assigned); see `to_frame`.
     pub fn to_bytes(&self) -> Vec<u8> {
-        let json = match self {
+        self.to_frame(0)
+    }
+
+    /// Serialize the record as one frame: payload length (u32 LE), CRC32 of the LSN and
+    /// payload (u32 LE), `lsn` (u64 LE), then the JSON payload. Frames can be concatenated
+    /// in a file.
+    pub fn to_frame(&self, lsn: Lsn) -> Vec<u8> {
+        self.to_frame_as(lsn, PayloadFormat::Json)
+    }
+
+    /// `to_frame` with the payload in `format`. Readers accept either format in any frame.
+    pub fn to_frame_as(&self, lsn: Lsn, format: PayloadFormat) -> Vec<u8> {
+        encode_frame(lsn, &self.payload_as(format))
+    }
+
+    /// The payload of the record's frame in `format`.
+    pub(crate) fn payload_as(&self, format: PayloadFormat) -> Vec<u8> {
+        match format {
+            PayloadFormat::Json => self.json_payload(),
+            PayloadFormat::Binary => wal_binary::encode(self),
+        }
+    }
+
+    /// The `type` of the record in JSON payloads, such as `"add_node"`; that of the
+    /// wrapped record for `InTxn`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            WalRecord::AddNode { .. } => "add_node",
+            WalRecord::AddEdge { .. } => "add_edge",
+            WalRecord::DeleteNode { .. } => "delete_node",
+            WalRecord::DeleteEdge { .. } => "delete_edge",
+            WalRecord::TombstoneNode { .. } => "tombstone_node",
+            WalRecord::TombstoneEdge { .. } => "tombstone_edge",
+            WalRecord::UndeleteNode { .. } => "undelete_node",
+            WalRecord::UndeleteEdge { .. } => "undelete_edge",
+            WalRecord::PurgeTombstones => "purge_tombstones",
+            WalRecord::SetNodeProperty { .. } => "set_node_property",
+            WalRecord::RemoveNodeProperty { .. } => "remove_node_property",
+            WalRecord::AddLabel { .. } => "add_label",
+            WalRecord::RemoveLabel { .. } => "remove_label",
+            WalRecord::SetEdgeProperty { .. } => "set_edge_property",
+            WalRecord::SetEdgeType { .. } => "set_edge_type",
+            WalRecord::SetEdgeEndpoint { .. } => "set_edge_endpoint",
+            WalRecord::ReverseEdge { .. } => "reverse_edge",
+            WalRecord::RenameEdgeType { .. } => "rename_edge_type",
+            WalRecord::MergeNodes { .. } => "merge_nodes",
+            WalRecord::Truncate { .. } => "truncate",
+            WalRecord::CreateIndex { .. } => "create_index",
+            WalRecord::DropIndex { .. } => "drop_index",
+            WalRecord::Checkpoint { .. } => "checkpoint",
+            WalRecord::BeginTxn { .. } => "begin_txn",
+            WalRecord::CommitTxn { .. } => "commit_txn",
+            WalRecord::AbortTxn { .. } => "abort_txn",
+            WalRecord::InTxn { record, .. } => record.type_name(),
+        }
+    }
+
+    fn json_payload(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.json()).unwrap_or_default()
+    }
+
+    /// The record as its JSON payload holds it.
+    pub(crate) fn json(&self) -> serde_json::Value {
+        match self {
             WalRecord::AddNode { id, labels, properties } => {
                 serde_json::json!({
                     "type": "add_node",
@@ -51,19 +303,155 @@ impl WalRecord {
                     "properties": serialize_props(properties)
                 })
             }
-        };
-        serde_json::to_vec(&json).unwrap_or_default()
+            WalRecord::DeleteNode { id, detach } => {
+                serde_json::json!({ "type": "delete_node", "id": id, "detach": detach })
+            }
+            WalRecord::DeleteEdge { id } => {
+                serde_json::json!({ "type": "delete_edge", "id": id })
+            }
+            WalRecord::TombstoneNode { id } => {
+                serde_json::json!({ "type": "tombstone_node", "id": id })
+            }
+            WalRecord::TombstoneEdge { id } => {
+                serde_json::json!({ "type": "tombstone_edge", "id": id })
+            }
+            WalRecord::UndeleteNode { id } => {
+                serde_json::json!({ "type": "undelete_node", "id": id })
+            }
+            WalRecord::UndeleteEdge { id } => {
+                serde_json::json!({ "type": "undelete_edge", "id": id })
+            }
+            WalRecord::PurgeTombstones => {
+                serde_json::json!({ "type": "purge_tombstones" })
+            }
+            WalRecord::SetNodeProperty { id, key, value } => {
+                serde_json::json!({ "type": "set_node_property", "id": id, "key": key, "value": value.to_json() })
+            }
+            WalRecord::RemoveNodeProperty { id, key } => {
+                serde_json::json!({ "type": "remove_node_property", "id": id, "key": key })
+            }
+            WalRecord::AddLabel { id, label } => {
+                serde_json::json!({ "type": "add_label", "id": id, "label": label })
+            }
+            WalRecord::RemoveLabel { id, label } => {
+                serde_json::json!({ "type": "remove_label", "id": id, "label": label })
+            }
+            WalRecord::SetEdgeProperty { id, key, value } => {
+                serde_json::json!({ "type": "set_edge_property", "id": id, "key": key, "value": value.to_json() })
+            }
+            WalRecord::SetEdgeType { id, edge_type } => {
+                serde_json::json!({ "type": "set_edge_type", "id": id, "edge_type": edge_type })
+            }
+            WalRecord::SetEdgeEndpoint { id, endpoint, node } => {
+                let endpoint = match endpoint { Endpoint::From => "from", Endpoint::To => "to" };
+                serde_json::json!({ "type": "set_edge_endpoint", "id": id, "endpoint": endpoint, "node": node })
+            }
+            WalRecord::ReverseEdge { id } => {
+                serde_json::json!({ "type": "reverse_edge", "id": id })
+            }
+            WalRecord::RenameEdgeType { old, new } => {
+                serde_json::json!({ "type": "rename_edge_type", "old": old, "new": new })
+            }
+            WalRecord::MergeNodes { keep, remove, keep_self_loops } => {
+                serde_json::json!({ "type": "merge_nodes", "keep": keep, "remove": remove, "keep_self_loops": keep_self_loops })
+            }
+            WalRecord::Truncate { reset_ids } => {
+                serde_json::json!({ "type": "truncate", "reset_ids": reset_ids })
+            }
+            WalRecord::CreateIndex { kind, label, keys, options } => {
+                serde_json::json!({
+                    "type": "create_index",
+                    "kind": kind.as_str(),
+                    "label": label,
+                    "keys": keys,
+                    "case_insensitive": options.property.case_insensitive,
+                    "trim_whitespace": options.property.trim_whitespace,
+                    "multi_value": options.property.multi_value,
+                    "covered": options.covered,
+                    "case_sensitive": options.case_sensitive
+                })
+            }
+            WalRecord::DropIndex { kind, label, keys } => {
+                serde_json::json!({ "type": "drop_index", "kind": kind.as_str(), "label": label, "keys": keys })
+            }
+            WalRecord::Checkpoint { generation, lsn } => {
+                serde_json::json!({ "type": "checkpoint", "generation": generation, "lsn": lsn })
+            }
+            WalRecord::BeginTxn { txn_id } => {
+                serde_json::json!({ "type": "begin_txn", "txn_id": txn_id })
+            }
+            WalRecord::CommitTxn { txn_id } => {
+                serde_json::json!({ "type": "commit_txn", "txn_id": txn_id })
+            }
+            WalRecord::AbortTxn { txn_id } => {
+                serde_json::json!({ "type": "abort_txn", "txn_id": txn_id })
+            }
+            WalRecord::InTxn { txn_id, record } => {
+                let mut json = record.json();
+                json["txn_id"] = serde_json::json!(txn_id);
+                json
+            }
+        }
     }
 
-    /// Désérialise depuis bytes
+    /// Deserialize one frame written by `to_bytes` or `to_frame`, dropping its LSN.
+    ///
+    /// # Errors
+    /// As `from_frame`.
     pub fn from_bytes(data: &[u8]) -> Result<Self, EngineError> {
+        Self::from_frame(data).map(|(_, record)| record)
+    }
+
+    /// Deserialize one frame written by `to_frame`, with its LSN.
+    ///
+    /// Frames written before they carried an LSN, and bare JSON as records were written
+    /// before framing, are still accepted, with LSN 0.
+    ///
+    /// # Errors
+    /// `WalCorruption` if the frame is cut short, fails its checksum or is followed by
+    /// extra bytes; `StorageIo` if the payload does not decode.
+    pub fn from_frame(data: &[u8]) -> Result<(Lsn, Self), EngineError> {
+        match decode_frame(data).or_else(|| decode_unnumbered_frame(data)) {
+            Some(frame) if frame.len == data.len() => {
+                Ok((frame.lsn.unwrap_or(0), frame.record()?))
+            }
+            Some(frame) => Err(EngineError::WalCorruption { offset: frame.len as u64 }),
+            None if data.first() == Some(&b'{') => Ok((0, Self::from_json_payload(data)?)),
+            None => Err(EngineError::WalCorruption { offset: 0 }),
+        }
+    }
+
+    /// Decode a payload in either format, told apart by its first byte.
+    pub(crate) fn from_payload(data: &[u8]) -> Result<Self, EngineError> {
+        match data.first() {
+            Some(&wal_binary::BINARY_TAG) => wal_binary::decode(data),
+            _ => Self::from_json_payload(data),
+        }
+    }
+
+    fn from_json_payload(data: &[u8]) -> Result<Self, EngineError> {
         let json: serde_json::Value = serde_json::from_slice(data)
             .map_err(|e| EngineError::StorageIo(format!("WAL record parse: {}", e)))?;
 
+        Self::from_json(&json)
+    }
+
+    fn from_json(json: &serde_json::Value) -> Result<Self, EngineError> {
         let rec_type = json["type"].as_str()
             .ok_or_else(|| EngineError::StorageIo("missing type".into()))?;
 
         match rec_type {
+            "begin_txn" => Ok(WalRecord::BeginTxn { txn_id: require_u64(json, "txn_id")? }),
+            "commit_txn" => Ok(WalRecord::CommitTxn { txn_id: require_u64(json, "txn_id")? }),
+            "abort_txn" => Ok(WalRecord::AbortTxn { txn_id: require_u64(json, "txn_id")? }),
+            _ if json.get("txn_id").is_some() => {
+                let txn_id = require_u64(json, "txn_id")?;
+                let mut inner = json.clone();
+                if let Some(fields) = inner.as_object_mut() {
+                    fields.remove("txn_id");
+                }
+                Ok(WalRecord::InTxn { txn_id, record: Box::new(Self::from_json(&inner)?) })
+            }
             "add_node" => {
                 let id = json["id"].as_u64().unwrap_or(0);
                 let labels: Vec<String> = serde_json::from_value(json["labels"].clone())
@@ -79,11 +467,153 @@ impl WalRecord {
                 let properties = deserialize_props(&json["properties"])?;
                 Ok(WalRecord::AddEdge { id, from_node, to_node, edge_type, properties })
             }
+            "delete_node" => Ok(WalRecord::DeleteNode {
+                id: require_u64(json, "id")?,
+                detach: json["detach"].as_bool().unwrap_or(false),
+            }),
+            "delete_edge" => Ok(WalRecord::DeleteEdge { id: require_u64(json, "id")? }),
+            "tombstone_node" => Ok(WalRecord::TombstoneNode {
+                id: require_u64(json, "id")?,
+            }),
+            "tombstone_edge" => Ok(WalRecord::TombstoneEdge {
+                id: require_u64(json, "id")?,
+            }),
+            "undelete_node" => Ok(WalRecord::UndeleteNode {
+                id: require_u64(json, "id")?,
+            }),
+            "undelete_edge" => Ok(WalRecord::UndeleteEdge {
+                id: require_u64(json, "id")?,
+            }),
+            "purge_tombstones" => Ok(WalRecord::PurgeTombstones),
+            "set_node_property" => Ok(WalRecord::SetNodeProperty {
+                id: require_u64(json, "id")?,
+                key: require_str(json, "key")?,
+                value: require_value(json, "value")?,
+            }),
+            "remove_node_property" => Ok(WalRecord::RemoveNodeProperty {
+                id: require_u64(json, "id")?,
+                key: require_str(json, "key")?,
+            }),
+            "add_label" => Ok(WalRecord::AddLabel {
+                id: require_u64(json, "id")?,
+                label: require_str(json, "label")?,
+            }),
+            "remove_label" => Ok(WalRecord::RemoveLabel {
+                id: require_u64(json, "id")?,
+                label: require_str(json, "label")?,
+            }),
+            "set_edge_property" => Ok(WalRecord::SetEdgeProperty {
+                id: require_u64(json, "id")?,
+                key: require_str(json, "key")?,
+                value: require_value(json, "value")?,
+            }),
+            "set_edge_type" => Ok(WalRecord::SetEdgeType {
+                id: require_u64(json, "id")?,
+                edge_type: require_str(json, "edge_type")?,
+            }),
+            "set_edge_endpoint" => Ok(WalRecord::SetEdgeEndpoint {
+                id: require_u64(json, "id")?,
+                endpoint: match require_str(json, "endpoint")?.as_str() {
+                    "from" => Endpoint::From,
+                    "to" => Endpoint::To,
+                    other => return Err(EngineError::StorageIo(format!("invalid edge endpoint: {}", other))),
+                },
+                node: require_u64(json, "node")?,
+            }),
+            "reverse_edge" => Ok(WalRecord::ReverseEdge {
+                id: require_u64(json, "id")?,
+            }),
+            "rename_edge_type" => Ok(WalRecord::RenameEdgeType {
+                old: require_str(json, "old")?,
+                new: require_str(json, "new")?,
+            }),
+            "merge_nodes" => Ok(WalRecord::MergeNodes {
+                keep: require_u64(json, "keep")?,
+                remove: require_u64(json, "remove")?,
+                keep_self_loops: json["keep_self_loops"].as_bool().unwrap_or(true),
+            }),
+            "truncate" => Ok(WalRecord::Truncate {
+                reset_ids: json["reset_ids"].as_bool().unwrap_or(false),
+            }),
+            "create_index" => Ok(WalRecord::CreateIndex {
+                kind: require_kind(json)?,
+                label: require_str(json, "label")?,
+                keys: require_strings(json, "keys")?,
+                options: DdlOptions {
+                    property: IndexOptions {
+                        case_insensitive: json["case_insensitive"].as_bool().unwrap_or(false),
+                        trim_whitespace: json["trim_whitespace"].as_bool().unwrap_or(false),
+                        multi_value: json["multi_value"].as_bool().unwrap_or(false),
+                    },
+                    covered: require_strings(json, "covered")?,
+                    case_sensitive: json["case_sensitive"].as_bool().unwrap_or(false),
+                },
+            }),
+            "drop_index" => Ok(WalRecord::DropIndex {
+                kind: require_kind(json)?,
+                label: require_str(json, "label")?,
+                keys: require_strings(json, "keys")?,
+            }),
+            "checkpoint" => Ok(WalRecord::Checkpoint {
+                generation: require_u64(json, "generation")?,
+                lsn: require_u64(json, "lsn")?,
+            }),
             _ => Err(EngineError::StorageIo(format!("unknown WAL record type: {}", rec_type))),
         }
     }
 }
 
+fn require_u64(json: &serde_json::Value, field: &str) -> Result<u64, EngineError> {
+    json[field].as_u64()
+        .ok_or_else(|| EngineError::StorageIo(format!("WAL record missing field: {}", field)))
+}
+
+fn require_str(json: &serde_json::Value, field: &str) -> Result<String, EngineError> {
+    json[field].as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| EngineError::StorageIo(format!("WAL record missing field: {}", field)))
+}
+
+fn require_strings(json: &serde_json::Value, field: &str) -> Result<Vec<String>, EngineError> {
+    serde_json::from_value(json[field].clone())
+        .map_err(|_| EngineError::StorageIo(format!("WAL record missing field: {}", field)))
+}
+
+fn require_kind(json: &serde_json::Value) -> Result<DdlKind, EngineError> {
+    let kind = require_str(json, "kind")?;
+    DdlKind::parse(&kind).ok_or_else(|| EngineError::StorageIo(format!("unknown index kind: {}", kind)))
+}
+
+fn require_value(json: &serde_json::Value, field: &str) -> Result<Value, EngineError> {
+    Value::from_json(&json[field])
+        .ok_or_else(|| EngineError::StorageIo(format!("WAL record invalid value: {}", field)))
+}
+
+/// The key of a logged index definition other than a composite one.
+fn single_key<'a>(keys: &[&'a str]) -> Result<&'a str, EngineError> {
+    match keys {
+        [key] => Ok(key),
+        _ => Err(EngineError::StorageIo(format!("WAL index record needs one key, got {:?}", keys))),
+    }
+}
+
+/// `(label, key)` of a persisted index definition.
+pub(crate) fn index_definition(json: &serde_json::Value) -> Result<(&str, &str), EngineError> {
+    match (json["label"].as_str(), json["key"].as_str()) {
+        (Some(label), Some(key)) => Ok((label, key)),
+        _ => Err(EngineError::StorageIo(format!("invalid index definition: {}", json))),
+    }
+}
+
+/// Persisted `(next_id, free_ids)` of a segment, defaulting to an empty state.
+fn allocator_state(json: &serde_json::Value) -> (u64, Vec<u64>) {
+    let high_water = json["next_id"].as_u64().unwrap_or(1);
+    let free = json["free_ids"].as_array()
+        .map(|ids| ids.iter().filter_map(|id| id.as_u64()).collect())
+        .unwrap_or_default();
+    (high_water, free)
+}
+
 fn serialize_props(props: &HashMap<String, Value>) -> serde_json::Value {
     let mut m = serde_json::Map::new();
     for (k, v) in props {
@@ -104,9 +634,153 @@ fn deserialize_props(json: &serde_json::Value) -> Result<HashMap<String, Value>,
     Ok(props)
 }
 
+fn node_json(n: &Node) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "id": n.id,
+        "labels": n.labels,
+        "properties": serialize_props(&n.properties)
+    });
+    // Only tombstones carry the flag, so live-only segments keep their old shape
+    if n.deleted {
+        json["deleted"] = serde_json::Value::Bool(true);
+    }
+    json
+}
+
+fn edge_json(e: &Edge) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "id": e.id,
+        "from": e.from_node,
+        "to": e.to_node,
+        "type": e.edge_type,
+        "properties": serialize_props(&e.properties)
+    });
+    if e.deleted {
+        json["deleted"] = serde_json::Value::Bool(true);
+    }
+    json
+}
+
+/// Write the `records` array of a JSON segment, one element at a time, then close the
+/// object its caller opened.
+fn write_json_records<'a, T: 'a>(
+    out: &mut dyn Write,
+    records: impl Iterator<Item = &'a T>,
+    to_json: fn(&T) -> serde_json::Value,
+) -> io::Result<()> {
+    out.write_all(b"[")?;
+    for (i, record) in records.enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, &to_json(record))?;
+    }
+    out.write_all(b"]}")
+}
+
+/// Parse a JSON segment from `input`, handing each element of its `records` array to
+/// `insert` as soon as it is parsed, and return the other top-level fields as an object.
+/// `records` also names the segment in `EngineError::SegmentCorruption`.
+fn read_json_segment(
+    input: impl Read,
+    records: &'static str,
+    mut insert: impl FnMut(&serde_json::Value) -> Result<(), EngineError>,
+) -> Result<serde_json::Value, EngineError> {
+    use serde::de::DeserializeSeed;
+
+    let mut failed = None;
+    let mut de = serde_json::Deserializer::from_reader(input);
+    let segment = JsonSegment {
+        records,
+        insert: |record: serde_json::Value| {
+            insert(&record).map_err(|e| {
+                let msg = e.to_string();
+                failed = Some(e);
+                msg
+            })
+        },
+    };
+    let parsed = segment.deserialize(&mut de).and_then(|fields| de.end().map(|_| fields));
+    if let Some(e) = failed {
+        return Err(e);
+    }
+    parsed.map(serde_json::Value::Object).map_err(|e| {
+        if e.is_io() {
+            read_error(records, e.into())
+        } else {
+            corruption(records, format!("parse: {}", e))
+        }
+    })
+}
+
+/// Field `key` of a record of JSON segment `segment`, which must hold an id.
+fn record_id(segment: &str, record: &serde_json::Value, key: &str) -> Result<u64, EngineError> {
+    record[key].as_u64().ok_or_else(|| corruption(segment, format!("record without a valid `{}`", key)))
+}
+
+/// A JSON segment object whose `records` array is streamed to `insert`.
+struct JsonSegment<F> {
+    records: &'static str,
+    insert: F,
+}
+
+impl<'de, F: FnMut(serde_json::Value) -> Result<(), String>> serde::de::DeserializeSeed<'de> for JsonSegment<F> {
+    type Value = serde_json::Map<String, serde_json::Value>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F: FnMut(serde_json::Value) -> Result<(), String>> serde::de::Visitor<'de> for JsonSegment<F> {
+    type Value = serde_json::Map<String, serde_json::Value>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a segment object")
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(mut self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = serde_json::Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == self.records {
+                map.next_value_seed(JsonRecords(&mut self.insert))?;
+            } else {
+                fields.insert(key, map.next_value()?);
+            }
+        }
+        Ok(fields)
+    }
+}
+
+/// The records array of a `JsonSegment`.
+struct JsonRecords<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(serde_json::Value) -> Result<(), String>> serde::de::DeserializeSeed<'de> for JsonRecords<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(serde_json::Value) -> Result<(), String>> serde::de::Visitor<'de> for JsonRecords<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "an array of records")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(record) = seq.next_element()? {
+            (self.0)(record).map_err(serde::de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
 // Segment IDs for graph data
-const NODE_SEGMENT_ID: &str = "nodes";
-const EDGE_SEGMENT_ID: &str = "edges";
+pub(crate) const NODE_SEGMENT_ID: &str = "nodes";
+pub(crate) const EDGE_SEGMENT_ID: &str = "edges";
 
 impl InMemoryGraphStore {
     /// Flush the graph to segments using the provided SegmentStore.
@@ -119,6 +793,17 @@ impl InMemoryGraphStore {
     /// # Errors
     /// Returns `EngineError::StorageIo` if serialization or segment writing fails.
     ///
+    /// # Atomicity
+    /// Segments are staged with `SegmentStore::stage_segment_with`, then made current together
+    /// with `publish_segments`. On stores that stage (the fs ones do), a flush that fails or
+    /// crashes part way leaves the segments of the previous flush to load.
+    ///
+    /// The node and edge segments written are a new base: the delta manifest, if
+    /// `flush_incremental` wrote one, is emptied along with them. With
+    /// `StoreOptions::partition_nodes_by_label` the nodes are written as one segment per
+    /// label (see `segment_partition`). A store loaded by `load_labels` is refused with
+    /// `EngineError::InvalidArgument`.
+    ///
     /// # Hexagonal Architecture
     /// This method depends only on the SegmentStore trait (port), not on any
     /// concrete storage adapter. The caller is responsible for constructing
@@ -132,33 +817,71 @@ impl InMemoryGraphStore {
         root: &Path,
         db: &DatabaseName,
     ) -> Result<(), EngineError> {
-        // Serialize and write nodes segment
-        let nodes_data = self.serialize_nodes()?;
-        let node_count = self.nodes.len() as u64;
-        store.write_segment(
-            root,
-            db,
-            &SegmentId(NODE_SEGMENT_ID.to_string()),
-            &nodes_data,
-            node_count,
-            0,
-        )?;
+        self.check_complete()?;
+        let mut ids: Vec<String> = [EDGE_SEGMENT_ID, INDEX_SEGMENT_ID, STATISTICS_SEGMENT_ID].map(String::from).to_vec();
 
-        // Serialize and write edges segment
-        let edges_data = self.serialize_edges()?;
-        let edge_count = self.edges.len() as u64;
-        store.write_segment(
+        // Node and edge segments are streamed record by record, never built in memory
+        let (format, compression) = (self.options.segment_format, self.options.segment_compression);
+        compression.check_writable(format)?;
+        if self.options.partition_nodes_by_label {
+            compression.check_writable(SegmentFormat::Binary)?;
+            self.stage_partitions(store, root, db, &mut ids)?;
+        } else {
+            store.stage_segment_with(
+                root,
+                db,
+                &SegmentId(NODE_SEGMENT_ID.to_string()),
+                self.nodes.len() as u64,
+                0,
+                &mut |out| match format {
+                    SegmentFormat::Json => self.write_json_nodes(out),
+                    SegmentFormat::Binary => self.write_binary_nodes(out, compression),
+                },
+            )?;
+            ids.push(NODE_SEGMENT_ID.to_string());
+            // Loading reads the partitions while the manifest says they hold the nodes
+            if PartitionManifest::read(store, root, db)?.is_some_and(|m| m.partitioned) {
+                PartitionManifest::default().stage(store, root, db)?;
+                ids.push(PARTITION_MANIFEST_ID.to_string());
+            }
+        }
+        store.stage_segment_with(
             root,
             db,
             &SegmentId(EDGE_SEGMENT_ID.to_string()),
-            &edges_data,
             0,
-            edge_count,
+            self.edges.len() as u64,
+            &mut |out| match format {
+                SegmentFormat::Json => self.write_json_edges(out),
+                SegmentFormat::Binary => self.write_binary_edges(out, compression),
+            },
         )?;
 
+        self.stage_index_and_statistics(store, root, db)?;
+
+        // The new base holds what the deltas did
+        if let Some(manifest) = DeltaManifest::read(store, root, db)?.filter(|m| !m.deltas.is_empty()) {
+            DeltaManifest { next: manifest.next, deltas: Vec::new() }.stage(store, root, db)?;
+            ids.push(DELTA_MANIFEST_ID.to_string());
+        }
+
+        let ids: Vec<SegmentId> = ids.into_iter().map(SegmentId).collect();
+        store.publish_segments(root, db, &ids)?;
+
         Ok(())
     }
 
+    /// Stage the index and statistics segments, which every flush writes whole.
+    pub(crate) fn stage_index_and_statistics(&self, store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        // Always written, so a store whose indexes were all dropped does not load stale ones
+        let index_data = self.serialize_indexes()?;
+        store.stage_segment_with(root, db, &SegmentId(INDEX_SEGMENT_ID.to_string()), 0, 0, &mut |out| out.write_all(&index_data))?;
+
+        // Likewise written without statistics, so a truncated store does not load old ones
+        let statistics_data = self.serialize_statistics()?;
+        store.stage_segment_with(root, db, &SegmentId(STATISTICS_SEGMENT_ID.to_string()), 0, 0, &mut |out| out.write_all(&statistics_data))
+    }
+
     /// Load the graph from segments using the provided SegmentStore.
     ///
     /// # Arguments
@@ -168,10 +891,12 @@ impl InMemoryGraphStore {
     ///
     /// # Returns
     /// A new InMemoryGraphStore populated with the loaded data, or an empty graph
-    /// if no segments exist yet.
+    /// if no segments exist yet. The deltas `flush_incremental` wrote are applied over the
+    /// node and edge segments, and the store tracks its changes for the next one.
     ///
     /// # Errors
-    /// Returns `EngineError::StorageIo` if segment reading or deserialization fails.
+    /// Returns `EngineError::SegmentCorruption` if a node or edge segment fails its checksum
+    /// or does not decode, and `EngineError::StorageIo` if segment reading fails.
     /// Note: `EngineError::NotFound` for missing segments is handled gracefully (empty graph).
     ///
     /// For filesystem storage, use `load_from_fs()` convenience method (requires `fs` feature),
@@ -182,170 +907,730 @@ impl InMemoryGraphStore {
         root: &Path,
         db: &DatabaseName,
     ) -> Result<Self, EngineError> {
-        let mut graph = Self::new();
+        Self::load_with_options(store, root, db, StoreOptions::default())
+    }
 
-        // Load nodes segment (may not exist yet)
-        match store.read_segment(root, db, &SegmentId(NODE_SEGMENT_ID.to_string())) {
-            Ok((data, _node_count, _edge_count)) => {
-                graph.deserialize_nodes(&data)?;
+    /// Same as `load()`, but the returned store is built with `options`.
+    ///
+    /// With `StoreOptions::verify_on_load`, index drift detected after loading (e.g. a
+    /// segment listing the same id twice) fails the load with `EngineError::StorageIo`.
+    #[must_use = "load returns a new graph store that should be used"]
+    pub fn load_with_options(
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        options: StoreOptions,
+    ) -> Result<Self, EngineError> {
+        let mut graph = Self::with_options(options);
+
+        // Load nodes segment (may not exist yet), inserting records as they are read
+        if let Some(manifest) = PartitionManifest::read(store, root, db)?.filter(|m| m.partitioned) {
+            graph.load_partitions(store, root, db, &manifest.segments())?;
+        } else {
+            match store.open_segment(root, db, &SegmentId(NODE_SEGMENT_ID.to_string())) {
+                Ok((input, _node_count, _edge_count)) => match segment_version(BufReader::new(input), NODE_SEGMENT_ID)? {
+                    (0, input) => graph.read_json_nodes(input)?,
+                    (_, input) => graph.read_binary_nodes(input, NODE_SEGMENT_ID)?,
+                },
+                Err(EngineError::NotFound(_)) => {
+                    // No nodes segment yet - that's OK for a new graph
+                }
+                Err(e) => return Err(e),
             }
+        }
+
+        // Load edges segment (may not exist yet)
+        match store.open_segment(root, db, &SegmentId(EDGE_SEGMENT_ID.to_string())) {
+            Ok((input, _node_count, _edge_count)) => match segment_version(BufReader::new(input), EDGE_SEGMENT_ID)? {
+                (0, input) => graph.read_json_edges(input)?,
+                (_, input) => graph.read_binary_edges(input, EDGE_SEGMENT_ID)?,
+            },
             Err(EngineError::NotFound(_)) => {
-                // No nodes segment yet - that's OK for a new graph
+                // No edges segment yet - that's OK for a new graph
             }
             Err(e) => return Err(e),
         }
 
-        // Load edges segment (may not exist yet)
-        match store.read_segment(root, db, &SegmentId(EDGE_SEGMENT_ID.to_string())) {
+        // Before the indexes, which describe the state after the last delta
+        graph.load_deltas(store, root, db)?;
+
+        // Written by every flush since indexes got their own segment; older stores have none
+        match store.read_segment(root, db, &SegmentId(INDEX_SEGMENT_ID.to_string())) {
             Ok((data, _node_count, _edge_count)) => {
-                graph.deserialize_edges(&data)?;
+                graph.deserialize_indexes(&data)?;
             }
-            Err(EngineError::NotFound(_)) => {
-                // No edges segment yet - that's OK for a new graph
+            Err(EngineError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        match store.read_segment(root, db, &SegmentId(STATISTICS_SEGMENT_ID.to_string())) {
+            Ok((data, _node_count, _edge_count)) => {
+                graph.deserialize_statistics(&data)?;
             }
+            Err(EngineError::NotFound(_)) => {}
             Err(e) => return Err(e),
         }
 
+        graph.verify_loaded()?;
+        graph.changes = Some(ChangeSet::default());
         Ok(graph)
     }
 
-    fn serialize_nodes(&self) -> Result<Vec<u8>, EngineError> {
-        let nodes: Vec<_> = self.nodes.values().collect();
-        let json = serde_json::json!({
-            "count": nodes.len(),
-            "nodes": nodes.iter().map(|n| {
-                serde_json::json!({
-                    "id": n.id,
-                    "labels": n.labels,
-                    "properties": serialize_props(&n.properties)
-                })
-            }).collect::<Vec<_>>()
-        });
+    /// With `StoreOptions::verify_on_load`, fail a load whose indexes drifted.
+    pub(crate) fn verify_loaded(&self) -> Result<(), EngineError> {
+        if self.options.verify_on_load {
+            if let Err(problems) = self.verify_indexes() {
+                let sample: Vec<String> = problems.iter().take(5).map(|p| p.to_string()).collect();
+                return Err(EngineError::StorageIo(format!(
+                    "index verification failed after load ({} problems): {}",
+                    problems.len(),
+                    sample.join("; ")
+                )));
+            }
+        }
+        Ok(())
+    }
 
-        serde_json::to_vec(&json)
-            .map_err(|e| EngineError::StorageIo(format!("serialize nodes: {}", e)))
+    /// Rewrite the node and edge segments in format version `to_version` (see
+    /// `SegmentFormat::version`): load them with the reader their version needs, then
+    /// `flush` with the writer of the new one, which publishes every segment at once.
+    /// Returns the version the node segment had, or `None`, writing nothing, if there is
+    /// no node segment. Nodes partitioned by label stay partitioned if `to_version` is
+    /// binary; their version is that of the partitions.
+    ///
+    /// Free ids carry over. Index and statistics segments are rewritten as `flush` writes
+    /// them, and migrating to JSON loses the values it cannot represent exactly.
+    pub fn migrate_segments(
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        to_version: u32,
+    ) -> Result<Option<u32>, EngineError> {
+        let format = SegmentFormat::from_version(to_version).ok_or_else(|| {
+            EngineError::InvalidArgument(format!("no writer for segment format version {}", to_version))
+        })?;
+        let partitioned = PartitionManifest::read(store, root, db)?.is_some_and(|m| m.partitioned);
+        let nodes = if partitioned { UNLABELED_PARTITION_ID } else { NODE_SEGMENT_ID };
+        let from = match store.open_segment(root, db, &SegmentId(nodes.to_string())) {
+            Ok((input, _node_count, _edge_count)) => segment_version(input, nodes)?.0,
+            Err(EngineError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let partition_nodes_by_label = partitioned && format == SegmentFormat::Binary;
+        let options = StoreOptions { reuse_ids: true, segment_format: format, partition_nodes_by_label, ..Default::default() };
+        Self::load_with_options(store, root, db, options)?.flush(store, root, db)?;
+        Ok(Some(from))
     }
 
-    fn serialize_edges(&self) -> Result<Vec<u8>, EngineError> {
-        let edges: Vec<_> = self.edges.values().collect();
-        let json = serde_json::json!({
-            "count": edges.len(),
-            "edges": edges.iter().map(|e| {
-                serde_json::json!({
-                    "id": e.id,
-                    "from": e.from_node,
-                    "to": e.to_node,
-                    "type": e.edge_type,
-                    "properties": serialize_props(&e.properties)
-                })
-            }).collect::<Vec<_>>()
-        });
+    /// Write the node segment as JSON to `out`, one record at a time.
+    fn write_json_nodes(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(
+            out,
+            r#"{{"count":{},"next_id":{},"free_ids":{},"last_applied_lsn":{},"next_txn_id":{},"nodes":"#,
+            self.nodes.len(),
+            self.node_ids.high_water(),
+            serde_json::to_string(&self.node_ids.free_ids())?,
+            self.last_applied_lsn,
+            self.next_txn_id,
+        )?;
+        let ids = sorted_ids(self.nodes.keys());
+        write_json_records(out, ids.iter().map(|id| &self.nodes[id]), node_json)
+    }
 
-        serde_json::to_vec(&json)
-            .map_err(|e| EngineError::StorageIo(format!("serialize edges: {}", e)))
+    /// Write the edge segment as JSON to `out`, one record at a time.
+    fn write_json_edges(&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(
+            out,
+            r#"{{"count":{},"next_id":{},"free_ids":{},"edges":"#,
+            self.edges.len(),
+            self.edge_ids.high_water(),
+            serde_json::to_string(&self.edge_ids.free_ids())?,
+        )?;
+        let ids = sorted_ids(self.edges.keys());
+        write_json_records(out, ids.iter().map(|id| &self.edges[id]), edge_json)
     }
 
-    fn deserialize_nodes(&mut self, data: &[u8]) -> Result<(), EngineError> {
-        let json: serde_json::Value = serde_json::from_slice(data)
-            .map_err(|e| EngineError::StorageIo(format!("parse nodes: {}", e)))?;
+    /// Read a JSON node segment from `input` into the store, record by record.
+    fn read_json_nodes(&mut self, input: impl Read) -> Result<(), EngineError> {
+        let json = read_json_segment(input, NODE_SEGMENT_ID, |node_json| {
+            let id = record_id(NODE_SEGMENT_ID, node_json, "id")?;
+            let labels: Vec<String> = serde_json::from_value(node_json["labels"].clone())
+                .unwrap_or_default();
+            let properties = deserialize_props(&node_json["properties"])?;
+            let deleted = node_json["deleted"].as_bool().unwrap_or(false);
 
-        if let Some(nodes_array) = json["nodes"].as_array() {
-            for node_json in nodes_array {
-                let id = node_json["id"].as_u64().unwrap_or(0);
-                let labels: Vec<String> = serde_json::from_value(node_json["labels"].clone())
-                    .unwrap_or_default();
-                let properties = deserialize_props(&node_json["properties"])?;
+            // Rebuilds the label index and marks the id as used
+            self.insert_node(Node { id, labels, properties, deleted });
+            Ok(())
+        })?;
+        // Segments written before allocator state was persisted only carry the records
+        let (high_water, free) = allocator_state(&json);
+        self.node_ids.restore(high_water, &free);
+        self.last_applied_lsn = json["last_applied_lsn"].as_u64().unwrap_or(0);
+        self.next_txn_id = json["next_txn_id"].as_u64().unwrap_or(1);
+        // Segments written before the index segment existed carry the index definitions;
+        // creating each index backfills it from the nodes above
+        for def in json["property_indexes"].as_array().into_iter().flatten() {
+            let (label, key) = index_definition(def)?;
+            if !self.has_property_index(label, key) {
+                self.create_property_index(label, key)?;
+            }
+        }
+        for def in json["range_indexes"].as_array().into_iter().flatten() {
+            let (label, key) = index_definition(def)?;
+            if !self.has_range_index(label, key) {
+                self.create_range_index(label, key)?;
+            }
+        }
+        for def in json["composite_indexes"].as_array().into_iter().flatten() {
+            let label = def["label"].as_str();
+            let keys: Option<Vec<&str>> = def["keys"].as_array().and_then(|keys| keys.iter().map(|k| k.as_str()).collect());
+            let (Some(label), Some(keys)) = (label, keys) else {
+                return Err(EngineError::StorageIo(format!("invalid index definition: {}", def)));
+            };
+            let defined = self.composite_indexes.contains_key(&(label.to_string(), keys.iter().map(|k| k.to_string()).collect()));
+            if !defined {
+                self.create_composite_index(label, &keys)?;
+            }
+        }
+        // Tokenizers are not persisted; indexes come back with `SimpleTokenizer`
+        for def in json["text_indexes"].as_array().into_iter().flatten() {
+            let (label, key) = index_definition(def)?;
+            if !self.text_indexes.contains_key(&(label.to_string(), key.to_string())) {
+                self.create_text_index(label, key)?;
+            }
+        }
+        for def in json["prefix_indexes"].as_array().into_iter().flatten() {
+            let (label, key) = index_definition(def)?;
+            if !self.prefix_indexes.contains_key(&(label.to_string(), key.to_string())) {
+                self.create_prefix_index(label, key, def["case_sensitive"].as_bool().unwrap_or(true))?;
+            }
+        }
+        // Re-validated against the loaded nodes; duplicates fail the load
+        for def in json["unique_constraints"].as_array().into_iter().flatten() {
+            let (label, key) = index_definition(def)?;
+            if !self.unique_constraints.contains(&(label.to_string(), key.to_string())) {
+                self.create_unique_constraint(label, key)?;
+            }
+        }
 
-                let node = Node { id, labels: labels.clone(), properties };
-                self.nodes.insert(id, node);
+        Ok(())
+    }
 
-                // Rebuild label index
-                for label in labels {
-                    self.label_index.entry(label).or_insert_with(Vec::new).push(id);
-                }
+    /// Read a JSON edge segment from `input` into the store, record by record.
+    fn read_json_edges(&mut self, input: impl Read) -> Result<(), EngineError> {
+        let json = read_json_segment(input, EDGE_SEGMENT_ID, |edge_json| {
+            let id = record_id(EDGE_SEGMENT_ID, edge_json, "id")?;
+            let from_node = record_id(EDGE_SEGMENT_ID, edge_json, "from")?;
+            let to_node = record_id(EDGE_SEGMENT_ID, edge_json, "to")?;
+            let edge_type = edge_json["type"].as_str()
+                .ok_or_else(|| corruption(EDGE_SEGMENT_ID, "record without a valid `type`"))?
+                .to_string();
+            let properties = deserialize_props(&edge_json["properties"])?;
+            let deleted = edge_json["deleted"].as_bool().unwrap_or(false);
 
-                // Update next_node_id
-                if id >= self.next_node_id {
-                    self.next_node_id = id + 1;
-                }
+            // Rebuilds adjacency indexes and marks the id as used
+            self.insert_edge(Edge { id, from_node, to_node, edge_type, properties, deleted });
+            Ok(())
+        })?;
+        let (high_water, free) = allocator_state(&json);
+        self.edge_ids.restore(high_water, &free);
+        // Pre-index-segment edge index definitions
+        for def in json["edge_indexes"].as_array().into_iter().flatten() {
+            let (edge_type, key) = index_definition(def)?;
+            if !self.edge_indexes.contains_key(&(edge_type.to_string(), key.to_string())) {
+                self.create_edge_index(edge_type, key)?;
             }
         }
 
         Ok(())
     }
 
-    fn deserialize_edges(&mut self, data: &[u8]) -> Result<(), EngineError> {
-        let json: serde_json::Value = serde_json::from_slice(data)
-            .map_err(|e| EngineError::StorageIo(format!("parse edges: {}", e)))?;
+    /// Start capturing a WalRecord for every mutation applied through GraphWriteStore.
+    ///
+    /// Captured records are drained with `take_wal_records()`; serialize them with
+    /// `WalRecord::to_bytes` to hand them to `Engine::commit_tx`.
+    pub fn enable_wal_capture(&mut self) {
+        if self.wal_log.is_none() {
+            self.wal_log = Some(Vec::new());
+        }
+    }
+
+    /// Stop capturing WAL records, returning whatever was still buffered.
+    pub fn disable_wal_capture(&mut self) -> Vec<WalRecord> {
+        self.wal_log.take().unwrap_or_default()
+    }
 
-        if let Some(edges_array) = json["edges"].as_array() {
-            for edge_json in edges_array {
-                let id = edge_json["id"].as_u64().unwrap_or(0);
-                let from_node = edge_json["from"].as_u64().unwrap_or(0);
-                let to_node = edge_json["to"].as_u64().unwrap_or(0);
-                let edge_type = edge_json["type"].as_str().unwrap_or("").to_string();
-                let properties = deserialize_props(&edge_json["properties"])?;
+    /// Drain the records captured since the last call (empty when capture is disabled).
+    pub fn take_wal_records(&mut self) -> Vec<WalRecord> {
+        self.wal_log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
 
-                let edge = Edge { id, from_node, to_node, edge_type, properties };
-                self.edges.insert(id, edge);
+    /// Append every mutation applied through GraphWriteStore to `writer` from now on,
+    /// replacing (and returning) the writer attached before. Works alongside capture.
+    ///
+    /// A failed append does not fail the mutation that triggered it: the writer stops
+    /// appending and reports the error from `sync_wal` and `detach_wal_writer`.
+    pub fn attach_wal_writer(&mut self, writer: WalWriter) -> Option<WalWriter> {
+        self.wal_writer.replace(writer)
+    }
 
-                // Rebuild adjacency indexes
-                self.adjacency_out.entry(from_node).or_insert_with(Vec::new).push(id);
-                self.adjacency_in.entry(to_node).or_insert_with(Vec::new).push(id);
+    /// Stop logging to the attached writer and hand it back.
+    pub fn detach_wal_writer(&mut self) -> Option<WalWriter> {
+        self.wal_writer.take()
+    }
 
-                // Update next_edge_id
-                if id >= self.next_edge_id {
-                    self.next_edge_id = id + 1;
-                }
+    pub fn wal_writer(&self) -> Option<&WalWriter> {
+        self.wal_writer.as_ref()
+    }
+
+    /// Fsync the attached writer (a no-op without one), for `SyncPolicy::Manual`.
+    pub fn sync_wal(&mut self) -> Result<(), EngineError> {
+        self.wal_writer.as_ref().map_or(Ok(()), WalWriter::sync)
+    }
+
+    /// Buffer a record if capture is enabled and append it to the attached writer; the
+    /// record is only built when needed.
+    pub(crate) fn log_wal(&mut self, record: impl FnOnce() -> WalRecord) {
+        if self.wal_log.is_none() && self.wal_writer.is_none() {
+            return;
+        }
+        let record = match self.wal_txn {
+            Some(txn_id) => WalRecord::InTxn { txn_id, record: Box::new(record()) },
+            None => record(),
+        };
+        if let Some(writer) = self.wal_writer.as_ref() {
+            // The writer keeps the error for sync_wal
+            if let Ok(lsn) = writer.append(&record) {
+                self.last_applied_lsn = lsn;
             }
         }
+        if let Some(log) = self.wal_log.as_mut() {
+            log.push(record);
+        }
+    }
 
-        Ok(())
+    /// Log the creation of an index or constraint, after the `create_*` call succeeded.
+    pub(crate) fn log_create_index(&mut self, kind: DdlKind, label: &str, keys: &[&str], options: impl FnOnce() -> DdlOptions) {
+        self.log_wal(|| WalRecord::CreateIndex {
+            kind,
+            label: label.to_string(),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            options: options(),
+        });
+    }
+
+    /// Log the drop of an index or constraint that existed.
+    pub(crate) fn log_drop_index(&mut self, kind: DdlKind, label: &str, keys: &[&str]) {
+        self.log_wal(|| WalRecord::DropIndex {
+            kind,
+            label: label.to_string(),
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+        });
+    }
+
+    /// Run `f` with the capture buffer and the attached writer set aside, so nothing it
+    /// does is logged.
+    pub(crate) fn without_wal<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let captured = self.wal_log.take();
+        let writer = self.wal_writer.take();
+        let result = f(self);
+        self.wal_log = captured;
+        self.wal_writer = writer;
+        result
+    }
+
+    /// Open a WAL transaction: until `commit_wal_txn` or `abort_wal_txn`, every logged
+    /// record is wrapped in `WalRecord::InTxn` with the returned id, after a `BeginTxn`.
+    ///
+    /// The transaction only scopes the log: mutations apply to this store as they are
+    /// made, and an abort does not undo them here, so `checkpoint` refuses after one.
+    /// `replay_wal_transactional` applies the records of a transaction only if its
+    /// `CommitTxn` was logged.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if a transaction is already open.
+    pub fn begin_wal_txn(&mut self) -> Result<TxnId, EngineError> {
+        if let Some(open) = self.wal_txn {
+            return Err(EngineError::InvalidArgument(format!("WAL transaction {} is still open", open)));
+        }
+        let txn_id = self.next_txn_id;
+        self.next_txn_id += 1;
+        self.log_wal(|| WalRecord::BeginTxn { txn_id });
+        self.wal_txn = Some(txn_id);
+        Ok(txn_id)
+    }
+
+    /// Log the `CommitTxn` of the open transaction and close it.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if no transaction is open.
+    pub fn commit_wal_txn(&mut self) -> Result<TxnId, EngineError> {
+        let txn_id = self.close_wal_txn()?;
+        self.log_wal(|| WalRecord::CommitTxn { txn_id });
+        Ok(txn_id)
+    }
+
+    /// Log the `AbortTxn` of the open transaction and close it. Its mutations stay
+    /// applied to this store, which `checkpoint` then refuses: recover from the WAL to get
+    /// a store without them.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if no transaction is open.
+    pub fn abort_wal_txn(&mut self) -> Result<TxnId, EngineError> {
+        let txn_id = self.close_wal_txn()?;
+        self.log_wal(|| WalRecord::AbortTxn { txn_id });
+        self.aborted_txn = Some(txn_id);
+        Ok(txn_id)
+    }
+
+    /// The open WAL transaction, if any.
+    pub fn wal_txn(&self) -> Option<TxnId> {
+        self.wal_txn
+    }
+
+    fn close_wal_txn(&mut self) -> Result<TxnId, EngineError> {
+        self.wal_txn.take()
+            .ok_or_else(|| EngineError::InvalidArgument("no WAL transaction is open".into()))
     }
 
     /// Rejouer des WAL records
+    ///
+    /// Records targeting an id that no longer exists are skipped so replay stays idempotent.
+    /// `AddNode` and `AddEdge` for an id that exists, as over segments flushed after them
+    /// or in a log replayed twice, replace the record: its label, property and adjacency
+    /// entries are dropped before the new ones are added, never duplicated. The older
+    /// state this restores lasts only until the records after it are replayed, so a log
+    /// replayed to its end leaves the newer data in place. Replay never feeds the capture buffer or the attached writer. Transaction markers
+    /// are ignored and the records of every transaction are applied as logged; see
+    /// `replay_wal_transactional`.
+    ///
+    /// `CreateIndex` recreates the index and backfills it from the nodes as they are at
+    /// that point of the log, and is skipped when the index already exists, as it does
+    /// over segments flushed after it. A unique constraint is installed without checking
+    /// the nodes: over segments they reflect the end of the log, not the point where the
+    /// constraint was accepted. A computed index comes back awaiting re-registration of
+    /// its function and a text index with `SimpleTokenizer`.
     pub fn replay_wal(&mut self, records: &[WalRecord]) -> Result<(), EngineError> {
+        self.without_wal(|store| store.apply_wal_records(records))
+    }
+
+    /// `replay_wal`, applying the records of a transaction when its `CommitTxn` is reached
+    /// and discarding those of aborted transactions and of transactions the log ends
+    /// inside, as a crash mid-transaction leaves them. Records outside any transaction
+    /// apply in place. Returns the number of records applied.
+    pub fn replay_wal_transactional(&mut self, records: &[WalRecord]) -> Result<u64, EngineError> {
+        let mut open: HashMap<TxnId, Vec<WalRecord>> = HashMap::new();
+        let mut committed = Vec::new();
+        for record in records {
+            match record {
+                WalRecord::BeginTxn { txn_id } => {
+                    open.insert(*txn_id, Vec::new());
+                }
+                WalRecord::InTxn { txn_id, record } => {
+                    open.entry(*txn_id).or_default().push((**record).clone());
+                }
+                WalRecord::CommitTxn { txn_id } => {
+                    committed.extend(open.remove(txn_id).unwrap_or_default());
+                }
+                WalRecord::AbortTxn { txn_id } => {
+                    open.remove(txn_id);
+                }
+                record => committed.push(record.clone()),
+            }
+            if let WalRecord::BeginTxn { txn_id } | WalRecord::InTxn { txn_id, .. } = record {
+                self.next_txn_id = self.next_txn_id.max(txn_id + 1);
+            }
+        }
+        self.replay_wal(&committed)?;
+        Ok(committed.len() as u64)
+    }
+
+    /// `replay_wal_transactional` for the records of `WalReader::read_entries` with an
+    /// LSN above `after_lsn`, so a log replayed over segments or twice applies each record
+    /// once. Raises `last_applied_lsn` to the last record read and returns the number of
+    /// records applied.
+    pub fn replay_wal_from(&mut self, records: &[(Lsn, WalRecord)], after_lsn: Lsn) -> Result<u64, EngineError> {
+        let newer: Vec<WalRecord> = records.iter()
+            .filter(|(lsn, _)| *lsn > after_lsn)
+            .map(|(_, record)| record.clone())
+            .collect();
+        let applied = self.replay_wal_transactional(&newer)?;
+        if let Some(last) = records.iter().map(|(lsn, _)| *lsn).filter(|lsn| *lsn > after_lsn).max() {
+            self.last_applied_lsn = self.last_applied_lsn.max(last);
+        }
+        Ok(applied)
+    }
+
+    /// `replay_wal_from` over entries read one at a time, such as `WalReader::entries`, so
+    /// the log is never held in memory whole. Entries at or before `last_applied_lsn` are
+    /// passed over.
+    ///
+    /// `progress`, if given, is called after each entry with how far the replay got;
+    /// returning `Break` stops it there. The store keeps what was applied, and
+    /// `last_applied_lsn` is raised only up to the record before the first of a
+    /// transaction still open, so a replay from the LSN after it picks up the rest. At the
+    /// end of the entries, open transactions are discarded as `replay_wal_from` discards
+    /// them. Returns how far the replay got.
+    ///
+    /// # Errors
+    /// The first error among `entries`, the store left as a `Break` there would leave it.
+    pub fn replay_wal_with(
+        &mut self,
+        entries: impl Iterator<Item = Result<WalEntry, EngineError>>,
+        mut progress: Option<&mut dyn FnMut(ReplayProgress) -> ControlFlow<()>>,
+    ) -> Result<ReplayProgress, EngineError> {
+        let after = self.last_applied_lsn;
+        // Records of the transactions not committed yet, with the LSN of their first record
+        let mut open: HashMap<TxnId, (Lsn, Vec<WalRecord>)> = HashMap::new();
+        let mut reached = ReplayProgress::default();
+        let mut stopped = Ok(false);
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    stopped = Err(e);
+                    break;
+                }
+            };
+            reached.bytes += entry.bytes;
+            reached.last_lsn = entry.lsn;
+            if entry.lsn > after {
+                reached.applied += self.replay_entry(entry.lsn, entry.record, &mut open)?;
+            }
+            if progress.as_mut().is_some_and(|progress| progress(reached).is_break()) {
+                stopped = Ok(true);
+                break;
+            }
+        }
+        let resume = match stopped {
+            Ok(false) => reached.last_lsn,
+            _ => open.values().map(|(first, _)| first - 1).min().unwrap_or(reached.last_lsn),
+        };
+        self.last_applied_lsn = self.last_applied_lsn.max(resume);
+        stopped.map(|_| reached)
+    }
+
+    /// Apply one record of `replay_wal_with`, holding the records of a transaction until
+    /// its commit, and return how many records were applied.
+    fn replay_entry(
+        &mut self,
+        lsn: Lsn,
+        record: WalRecord,
+        open: &mut HashMap<TxnId, (Lsn, Vec<WalRecord>)>,
+    ) -> Result<u64, EngineError> {
+        if let WalRecord::BeginTxn { txn_id } | WalRecord::InTxn { txn_id, .. } = record {
+            self.next_txn_id = self.next_txn_id.max(txn_id + 1);
+        }
+        match record {
+            WalRecord::BeginTxn { txn_id } => {
+                open.insert(txn_id, (lsn, Vec::new()));
+            }
+            WalRecord::InTxn { txn_id, record } => {
+                open.entry(txn_id).or_insert((lsn, Vec::new())).1.push(*record);
+            }
+            WalRecord::CommitTxn { txn_id } => {
+                let (_, records) = open.remove(&txn_id).unwrap_or_default();
+                self.replay_wal(&records)?;
+                return Ok(records.len() as u64);
+            }
+            WalRecord::AbortTxn { txn_id } => {
+                open.remove(&txn_id);
+            }
+            record => {
+                self.replay_wal(std::slice::from_ref(&record))?;
+                return Ok(1);
+            }
+        }
+        Ok(0)
+    }
+
+    /// LSN of the last WAL record this store appended to its writer or applied with
+    /// `replay_wal_from` or `replay_wal_with`, 0 before any. `flush` saves it with the node segment and
+    /// `recover` replays only the records after it.
+    pub fn last_applied_lsn(&self) -> Lsn {
+        self.last_applied_lsn
+    }
+
+    fn apply_wal_records(&mut self, records: &[WalRecord]) -> Result<(), EngineError> {
         for record in records {
             match record {
                 WalRecord::AddNode { id, labels, properties } => {
-                    let node = Node {
+                    // Over a loaded segment the node may exist; insert_node re-indexes it
+                    self.insert_node(Node {
                         id: *id,
                         labels: labels.clone(),
                         properties: properties.clone(),
-                    };
-                    self.nodes.insert(*id, node);
-
-                    // Update indexes
-                    for label in labels {
-                        self.label_index.entry(label.clone()).or_insert_with(Vec::new).push(*id);
-                    }
-
-                    if *id >= self.next_node_id {
-                        self.next_node_id = id + 1;
-                    }
+                        deleted: false,
+                    });
                 }
                 WalRecord::AddEdge { id, from_node, to_node, edge_type, properties } => {
-                    let edge = Edge {
+                    // Over a loaded segment the edge may already be linked
+                    self.detach_edge(*id);
+                    self.insert_edge(Edge {
                         id: *id,
                         from_node: *from_node,
                         to_node: *to_node,
                         edge_type: edge_type.clone(),
                         properties: properties.clone(),
-                    };
-                    self.edges.insert(*id, edge);
-
-                    // Update adjacency
-                    self.adjacency_out.entry(*from_node).or_insert_with(Vec::new).push(*id);
-                    self.adjacency_in.entry(*to_node).or_insert_with(Vec::new).push(*id);
-
-                    if *id >= self.next_edge_id {
-                        self.next_edge_id = id + 1;
+                        deleted: false,
+                    });
+                }
+                // Physical and soft deletes are replayed as logged, whatever this store's options
+                WalRecord::DeleteNode { id, .. } => {
+                    self.detach_node(*id);
+                }
+                WalRecord::DeleteEdge { id } => {
+                    self.detach_edge(*id);
+                }
+                WalRecord::TombstoneNode { id } => {
+                    self.tombstone_node(*id);
+                }
+                WalRecord::TombstoneEdge { id } => {
+                    self.tombstone_edge(*id);
+                }
+                WalRecord::UndeleteNode { id } => {
+                    self.restore_node(*id);
+                }
+                WalRecord::UndeleteEdge { id } => {
+                    self.restore_edge(*id);
+                }
+                WalRecord::PurgeTombstones => {
+                    self.purge_tombstones();
+                }
+                WalRecord::SetNodeProperty { id, key, value } => {
+                    self.node_changed(*id);
+                    self.unindex_node_properties(*id);
+                    if let Some(node) = self.nodes.get_mut(id) {
+                        node.properties.insert(key.clone(), value.clone());
+                    }
+                    self.index_node_properties(*id);
+                }
+                WalRecord::RemoveNodeProperty { id, key } => {
+                    self.node_changed(*id);
+                    self.unindex_node_properties(*id);
+                    if let Some(node) = self.nodes.get_mut(id) {
+                        node.properties.remove(key);
                     }
+                    self.index_node_properties(*id);
                 }
+                WalRecord::AddLabel { id, label } => {
+                    if self.nodes.contains_key(id) {
+                        self.add_label(*id, label.clone())?;
+                    }
+                }
+                WalRecord::RemoveLabel { id, label } => {
+                    if self.nodes.contains_key(id) {
+                        self.remove_label(*id, label)?;
+                    }
+                }
+                WalRecord::SetEdgeProperty { id, key, value } => {
+                    self.edge_changed(*id);
+                    self.unindex_edge_properties(*id);
+                    if let Some(edge) = self.edges.get_mut(id) {
+                        edge.properties.insert(key.clone(), value.clone());
+                    }
+                    self.index_edge_properties(*id);
+                }
+                WalRecord::SetEdgeType { id, edge_type } => {
+                    self.retype_edge(*id, edge_type.clone());
+                }
+                WalRecord::SetEdgeEndpoint { id, endpoint, node } => {
+                    if self.edges.contains_key(id) && self.nodes.contains_key(node) {
+                        self.set_edge_endpoint(*id, *endpoint, *node)?;
+                    }
+                }
+                WalRecord::ReverseEdge { id } => {
+                    if self.edges.contains_key(id) {
+                        self.reverse_edge(*id)?;
+                    }
+                }
+                WalRecord::RenameEdgeType { old, new } => {
+                    self.rename_edge_type(old, new)?;
+                }
+                WalRecord::MergeNodes { keep, remove, keep_self_loops } => {
+                    if self.nodes.contains_key(keep) && self.nodes.contains_key(remove) {
+                        self.merge_nodes(*keep, *remove, *keep_self_loops)?;
+                    }
+                }
+                WalRecord::Truncate { reset_ids } => {
+                    self.truncate(*reset_ids);
+                }
+                WalRecord::CreateIndex { kind, label, keys, options } => {
+                    self.replay_create_index(*kind, label, keys, options)?;
+                }
+                WalRecord::DropIndex { kind, label, keys } => {
+                    self.replay_drop_index(*kind, label, keys)?;
+                }
+                WalRecord::InTxn { record, .. } => {
+                    self.apply_wal_records(std::slice::from_ref(record))?;
+                }
+                WalRecord::Checkpoint { .. }
+                | WalRecord::BeginTxn { .. }
+                | WalRecord::CommitTxn { .. }
+                | WalRecord::AbortTxn { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn replay_create_index(&mut self, kind: DdlKind, label: &str, keys: &[String], options: &DdlOptions) -> Result<(), EngineError> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        if kind == DdlKind::Index(IndexKind::Composite) {
+            if !self.composite_indexes.contains_key(&(label.to_string(), keys.iter().map(|k| k.to_string()).collect())) {
+                self.create_composite_index(label, &keys)?;
+            }
+            return Ok(());
+        }
+        let key = single_key(&keys)?;
+        let def = (label.to_string(), key.to_string());
+        match kind {
+            DdlKind::Index(IndexKind::Property) if !self.property_indexes.contains_key(&def) => {
+                if options.covered.is_empty() {
+                    self.create_property_index_with(label, key, options.property)?;
+                } else {
+                    let covered: Vec<&str> = options.covered.iter().map(String::as_str).collect();
+                    self.create_covering_index(label, key, options.property, &covered)?;
+                }
+            }
+            DdlKind::Index(IndexKind::Range) if !self.range_indexes.contains_key(&def) => self.create_range_index(label, key)?,
+            DdlKind::Index(IndexKind::Text) if !self.text_indexes.contains_key(&def) => self.create_text_index(label, key)?,
+            DdlKind::Index(IndexKind::Prefix) if !self.prefix_indexes.contains_key(&def) => {
+                self.create_prefix_index(label, key, options.case_sensitive)?;
+            }
+            DdlKind::Index(IndexKind::Edge) if !self.edge_indexes.contains_key(&def) => self.create_edge_index(label, key)?,
+            DdlKind::Index(IndexKind::Ttl) if !self.ttl_indexes.contains_key(&def) => self.create_ttl_index(label, key)?,
+            DdlKind::Index(IndexKind::Computed) if !self.computed_indexes.contains_key(&def) => {
+                // Awaits re-registration of its function
+                self.computed_indexes.insert(def, ComputedIndex { function: None, buckets: HashMap::new() });
+                self.track_index(IndexKind::Computed, label, &[key]);
+            }
+            DdlKind::UniqueConstraint if !self.unique_constraints.contains(&def) => {
+                if !self.has_property_index(label, key) {
+                    self.create_property_index(label, key)?;
+                }
+                self.unique_constraints.insert(def);
             }
+            _ => {}
         }
         Ok(())
     }
+
+    fn replay_drop_index(&mut self, kind: DdlKind, label: &str, keys: &[String]) -> Result<(), EngineError> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        match kind {
+            DdlKind::Index(IndexKind::Composite) => self.drop_composite_index(label, &keys)?,
+            DdlKind::Index(IndexKind::Property) => self.drop_property_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Range) => self.drop_range_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Text) => self.drop_text_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Prefix) => self.drop_prefix_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Edge) => self.drop_edge_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Computed) => self.drop_computed_index(label, single_key(&keys)?)?,
+            DdlKind::Index(IndexKind::Ttl) => self.drop_ttl_index(label, single_key(&keys)?)?,
+            DdlKind::UniqueConstraint => self.drop_unique_constraint(label, single_key(&keys)?)?,
+        };
+        Ok(())
+    }
 }
 
 // =============================================================================
@@ -366,6 +1651,10 @@ impl InMemoryGraphStore {
 #[cfg(feature = "fs")]
 mod fs_convenience {
     use super::*;
+    use super::super::segment_delta::is_delta_id;
+    use super::super::segment_partition::is_partition_id;
+    use super::super::PartialEdges;
+    use super::super::CompactionReport;
     use casys_storage_fs::catalog;
 
     impl InMemoryGraphStore {
@@ -389,6 +1678,17 @@ mod fs_convenience {
             self.flush(&store, &segments_root, db)
         }
 
+        /// `flush_incremental` into the branch's segments directory.
+        pub fn flush_incremental_to_fs(
+            &mut self,
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+        ) -> Result<(), EngineError> {
+            let segments_root = catalog::branch_dir(root, db, branch);
+            self.flush_incremental(&FsSegmentStoreImpl, &segments_root, db)
+        }
+
         /// Convenience method to load from filesystem.
         ///
         /// This is a helper that constructs the FsSegmentStore internally.
@@ -402,6 +1702,142 @@ mod fs_convenience {
             let store = FsSegmentStoreImpl;
             Self::load(&store, &segments_root, db)
         }
+
+        /// `load_labels` from the branch's segments.
+        pub fn load_labels_from_fs(
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+            labels: &[&str],
+            edges: PartialEdges,
+            options: StoreOptions,
+        ) -> Result<Self, EngineError> {
+            let segments_root = catalog::branch_dir(root, db, branch);
+            Self::load_labels(&FsSegmentStoreImpl, &segments_root, db, labels, edges, options)
+        }
+
+        /// Fold the branch's segments and WAL into a fresh base, then delete the files it
+        /// supersedes: deltas, versions a crash kept from being removed, generations staged
+        /// but never published, and the WAL files the new checkpoint covers. The branch must
+        /// not be open meanwhile, and WAL files an `ArchiveHook` has not accepted yet are
+        /// deleted too; run `WalWriter::archive_sealed` first.
+        ///
+        /// The state is recovered as `recover_from_fs` does, and written as a checkpoint
+        /// (just a flush without a WAL), so the old segments stay current until the new
+        /// ones are published together. Only files nothing references any more are
+        /// deleted, after that; a compaction cut short at any point loads as the state
+        /// before it, and the next one finishes the cleanup. Tombstones are part of the
+        /// state and are kept; see `purge_tombstones`. Nodes stay partitioned by label if
+        /// they were, and partitions of labels no node carries first any more are deleted.
+        pub fn compact_segments_on_fs(
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+        ) -> Result<CompactionReport, EngineError> {
+            use casys_storage_fs::segments::{read_segment_manifest, remove_unreferenced_segments, retire_segments, segment_usage};
+
+            let segments_root = catalog::branch_dir(root, db, branch);
+            let wal = wal_dir(&segments_root);
+            let before = segment_usage(&segments_root, db)?;
+            let wal_bytes_before = dir_bytes(&wal)?;
+            let store = FsSegmentStoreImpl;
+            let wal_records_folded = if wal.exists() {
+                let options = Self::compaction_options(&store, &segments_root, db)?;
+                let (graph, report) = Self::recover_with_policy(&store, &segments_root, db, &segments_root, options, RecoveryPolicy::TolerateTail)?;
+                WalWriter::open(&segments_root, SyncPolicy::Manual)?.checkpoint(&graph, &store, &segments_root, db)?;
+                report.applied
+            } else {
+                Self::compact_segments(&store, &segments_root, db)?;
+                0
+            };
+
+            // The new base lists no deltas: every one still in the manifest is folded in.
+            // Nor does it hold the nodes both as partitions and as the `nodes` segment
+            let partitions = PartitionManifest::read(&store, &segments_root, db)?.filter(|m| m.partitioned).map(|m| m.segments());
+            let superseded = |id: &str| match &partitions {
+                Some(current) => id == NODE_SEGMENT_ID || (is_partition_id(id) && !current.contains(id)),
+                None => is_partition_id(id),
+            };
+            let manifest = read_segment_manifest(&segments_root, db)?;
+            let retired: Vec<&str> = manifest.segments.keys().map(String::as_str).filter(|id| is_delta_id(id) || superseded(id)).collect();
+            retire_segments(&segments_root, db, &retired)?;
+            remove_unreferenced_segments(&segments_root, db)?;
+
+            let after = segment_usage(&segments_root, db)?;
+            Ok(CompactionReport {
+                files_before: before.files,
+                files_after: after.files,
+                bytes_before: before.bytes,
+                bytes_after: after.bytes,
+                records_before: before.node_records + before.edge_records,
+                records_after: after.node_records + after.edge_records,
+                wal_bytes_before,
+                wal_bytes_after: dir_bytes(&wal)?,
+                wal_records_folded,
+            })
+        }
+
+        /// `migrate_segments` on the branch's segments.
+        pub fn migrate_segments_on_fs(
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+            to_version: u32,
+        ) -> Result<Option<u32>, EngineError> {
+            let segments_root = catalog::branch_dir(root, db, branch);
+            Self::migrate_segments(&FsSegmentStoreImpl, &segments_root, db, to_version)
+        }
+
+        /// `recover` from the branch's segments and WAL.
+        pub fn recover_from_fs(
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+            options: StoreOptions,
+        ) -> Result<Self, EngineError> {
+            let branch_dir = catalog::branch_dir(root, db, branch);
+            Self::recover(&FsSegmentStoreImpl, &branch_dir, db, &branch_dir, options)
+        }
+
+        /// `recover_with_policy` from the branch's segments and WAL: the startup entry
+        /// point of a store kept on disk.
+        pub fn recover_from_fs_with_policy(
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+            options: StoreOptions,
+            policy: RecoveryPolicy,
+        ) -> Result<(Self, RecoveryReport), EngineError> {
+            let branch_dir = catalog::branch_dir(root, db, branch);
+            Self::recover_with_policy(&FsSegmentStoreImpl, &branch_dir, db, &branch_dir, options, policy)
+        }
+    }
+
+    impl WalWriter {
+        /// `checkpoint` into the branch's segments directory.
+        pub fn checkpoint_to_fs(
+            &self,
+            store: &InMemoryGraphStore,
+            root: &Path,
+            db: &DatabaseName,
+            branch: &BranchName,
+        ) -> Result<(), EngineError> {
+            let segments_root = catalog::branch_dir(root, db, branch);
+            self.checkpoint(store, &FsSegmentStoreImpl, &segments_root, db)
+        }
+    }
+
+    /// Bytes of the files in `dir`, 0 if there is none.
+    fn dir_bytes(dir: &Path) -> Result<u64, EngineError> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(EngineError::StorageIo(format!("read_dir({}): {e}", dir.display()))),
+        };
+        entries
+            .map(|entry| entry.and_then(|entry| entry.metadata()).map(|metadata| metadata.len()))
+            .sum::<io::Result<u64>>()
+            .map_err(|e| EngineError::StorageIo(format!("read_dir({}): {e}", dir.display())))
     }
 
     /// Filesystem SegmentStore implementation
@@ -433,6 +1869,48 @@ mod fs_convenience {
             let seg = read_segment(root, db, &segment_id.0)?;
             Ok((seg.data, seg.header.node_count, seg.header.edge_count))
         }
+
+        fn write_segment_with(
+            &self,
+            root: &Path,
+            db: &DatabaseName,
+            segment_id: &SegmentId,
+            node_count: u64,
+            edge_count: u64,
+            write: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+        ) -> Result<(), EngineError> {
+            casys_storage_fs::segments::write_segment_with(root, db, &segment_id.0, node_count, edge_count, write)?;
+            Ok(())
+        }
+
+        fn stage_segment_with(
+            &self,
+            root: &Path,
+            db: &DatabaseName,
+            segment_id: &SegmentId,
+            node_count: u64,
+            edge_count: u64,
+            write: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+        ) -> Result<(), EngineError> {
+            casys_storage_fs::segments::stage_segment_with(root, db, &segment_id.0, node_count, edge_count, write)?;
+            Ok(())
+        }
+
+        fn publish_segments(&self, root: &Path, db: &DatabaseName, segment_ids: &[SegmentId]) -> Result<(), EngineError> {
+            let ids: Vec<&str> = segment_ids.iter().map(|id| id.0.as_str()).collect();
+            casys_storage_fs::segments::publish_segments(root, db, &ids)
+        }
+
+        fn open_segment(
+            &self,
+            root: &Path,
+            db: &DatabaseName,
+            segment_id: &SegmentId,
+        ) -> Result<(Box<dyn Read + '_>, u64, u64), EngineError> {
+            let seg = casys_storage_fs::segments::open_segment(root, db, &segment_id.0)?;
+            let (node_count, edge_count) = (seg.header.node_count, seg.header.edge_count);
+            Ok((Box::new(seg), node_count, edge_count))
+        }
     }
 }
 
@@ -0,0 +1,137 @@
+//! Prefix indexes: `(label, key) -> string value -> node ids`, in value order
+//!
+//! A prefix index files every live node carrying `label` under its `String` value for
+//! `key` (other value types are not filed), so `scan_by_prefix` is a range scan starting
+//! at the prefix. A case-insensitive index files and looks up lowercased values. Like the
+//! other node indexes it is maintained through `index_node_properties` /
+//! `unindex_node_properties`, and only its definition is persisted.
+
+use super::index_stats::IndexKind;
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, Node, NodeId, Value};
+use crate::types::EngineError;
+use std::collections::BTreeMap;
+
+/// One prefix index and how it folds case.
+pub(crate) struct PrefixIndex {
+    pub(crate) case_sensitive: bool,
+    pub(crate) entries: BTreeMap<String, Vec<NodeId>>,
+}
+
+impl PrefixIndex {
+    /// `text` as the index stores and compares it.
+    fn fold(&self, text: &str) -> String {
+        if self.case_sensitive { text.to_string() } else { text.to_lowercase() }
+    }
+
+    /// The entry `node` is filed under for `(label, key)`, if any.
+    pub(crate) fn entry_of(&self, node: &Node, label: &str, key: &str) -> Option<String> {
+        match node.properties.get(key).filter(|_| node.labels.iter().any(|l| l == label)) {
+            Some(Value::String(text)) => Some(self.fold(text)),
+            _ => None,
+        }
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Index the `key` string property of nodes carrying `label` for `scan_by_prefix`,
+    /// backfilling from the nodes already stored. With `case_sensitive` false, values and
+    /// prefixes are compared lowercased.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty or the index already exists.
+    pub fn create_prefix_index(&mut self, label: &str, key: &str, case_sensitive: bool) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("prefix index needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.prefix_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("prefix index on {}.{} already exists", label, key)));
+        }
+        let mut index = PrefixIndex { case_sensitive, entries: BTreeMap::new() };
+        for node in self.iter_nodes_matching(Some(label)) {
+            if let Some(entry) = index.entry_of(node, label, key) {
+                index.entries.entry(entry).or_default().push(node.id);
+            }
+        }
+        self.prefix_indexes.insert(def, index);
+        self.track_index(IndexKind::Prefix, label, &[key]);
+        self.log_create_index(DdlKind::Index(IndexKind::Prefix), label, &[key], || DdlOptions { case_sensitive, ..DdlOptions::default() });
+        Ok(())
+    }
+
+    /// Drop the prefix index on `(label, key)`. Returns `false` if there was none.
+    pub fn drop_prefix_index(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Prefix, label, &[key]);
+        let dropped = self.prefix_indexes.remove(&(label.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Prefix), label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every prefix index as `(label, key, case_sensitive)`, sorted.
+    pub fn prefix_indexes(&self) -> Vec<(String, String, bool)> {
+        let mut defs: Vec<(String, String, bool)> = self.prefix_indexes.iter()
+            .map(|((label, key), index)| (label.clone(), key.clone(), index.case_sensitive))
+            .collect();
+        defs.sort();
+        defs
+    }
+
+    /// At most `limit` nodes carrying `label` whose `key` value starts with `prefix`,
+    /// ordered by value (nodes sharing a value in insertion order). Only the returned
+    /// entries are visited, so short prefixes stay cheap.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `(label, key)` has no prefix index.
+    pub fn scan_by_prefix(&self, label: &str, key: &str, prefix: &str, limit: usize) -> Result<Vec<Node>, EngineError> {
+        let index = self.prefix_indexes.get(&(label.to_string(), key.to_string()))
+            .ok_or_else(|| EngineError::InvalidArgument(format!("no prefix index on {}.{}", label, key)))?;
+        let prefix = index.fold(prefix);
+        let nodes: Vec<Node> = index.entries.range(prefix.clone()..)
+            .take_while(|(value, _)| value.starts_with(&prefix))
+            .flat_map(|(_, ids)| ids.iter())
+            .filter_map(|id| self.nodes.get(id).cloned())
+            .take(limit)
+            .collect();
+        self.record_index_lookup(IndexKind::Prefix, label, &[key], !nodes.is_empty());
+        Ok(nodes)
+    }
+
+    /// File live node `id` in the prefix indexes it qualifies for. Called from
+    /// `index_node_properties`.
+    pub(crate) fn index_node_prefix(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.prefix_indexes.iter_mut() {
+            if let Some(entry) = index.entry_of(node, label, key) {
+                index.entries.entry(entry).or_default().push(id);
+            }
+        }
+    }
+
+    /// Remove live node `id` from the prefix index entries it is currently filed under.
+    /// Called from `unindex_node_properties`.
+    pub(crate) fn unindex_node_prefix(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.prefix_indexes.iter_mut() {
+            let Some(entry) = index.entry_of(node, label, key) else { continue };
+            if let Some(ids) = index.entries.get_mut(&entry) {
+                ids.retain(|n| *n != id);
+                if ids.is_empty() {
+                    index.entries.remove(&entry);
+                }
+            }
+        }
+    }
+
+    /// Refill every prefix index from the live nodes, keeping definitions and case modes.
+    pub(crate) fn rebuild_prefix_indexes(&mut self) {
+        let defs: Vec<((String, String), bool)> = self.prefix_indexes.drain()
+            .map(|(def, index)| (def, index.case_sensitive))
+            .collect();
+        for ((label, key), case_sensitive) in defs {
+            self.create_prefix_index(&label, &key, case_sensitive).expect("definitions are valid and distinct");
+        }
+    }
+}
@@ -0,0 +1,670 @@
+//! Secondary property indexes: `(label, key) -> value -> node ids`
+//!
+//! An equality index on `(label, key)` lists every live node carrying `label` that has
+//! `key` set, bucketed by the `ValueKey` projection of the value, after the index's
+//! `IndexOptions` normalization of strings; a multi-value index files an `Array` value
+//! under each of its elements instead. A range index does the same in a `BTreeMap`
+//! keyed by `OrderedValue`, so it only holds `Int`, `Float` (not NaN)
+//! and `String` values; other values are simply not filed. A composite index files a node
+//! under the tuple of its values for several keys, and skips nodes missing any of them.
+//! Edge indexes are equality indexes over the live edges of one type. The store's mutation
+//! paths keep the buckets in step by unindexing a record before changing it and indexing
+//! it again afterwards; tombstones are not indexed. Only the definitions are persisted: buckets are
+//! backfilled when a segment is loaded.
+
+use super::index_stats::IndexKind;
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, Node, NodeId, EdgeId, Value};
+use crate::types::EngineError;
+use casys_core::{NumericRange, OrderedValue, ValueKey};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+/// Normalization a property index applies to `String` values, both when filing nodes and
+/// when looking values up. Other values are used unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexOptions {
+    /// Compare strings lowercased, so `"Paris"` and `"PARIS"` share a bucket.
+    pub case_insensitive: bool,
+    /// Ignore leading and trailing whitespace.
+    pub trim_whitespace: bool,
+    /// File an `Array` value under each distinct element rather than the whole list, so
+    /// looking up one value finds every node whose list contains it.
+    pub multi_value: bool,
+}
+
+impl IndexOptions {
+    /// The bucket `value` belongs to under these options.
+    pub(crate) fn key(&self, value: &Value) -> ValueKey {
+        match value {
+            Value::String(s) if self.case_insensitive || self.trim_whitespace => {
+                let s = if self.trim_whitespace { s.trim() } else { s.as_str() };
+                ValueKey::String(if self.case_insensitive { s.to_lowercase() } else { s.to_string() })
+            }
+            _ => ValueKey::from(value),
+        }
+    }
+
+    /// Every bucket `value` is filed under: one, except for an `Array` on a multi-value
+    /// index, which is filed once per distinct element (and nowhere if empty).
+    pub(crate) fn keys(&self, value: &Value) -> Vec<ValueKey> {
+        match value {
+            Value::Array(items) if self.multi_value => {
+                let mut keys: Vec<ValueKey> = items.iter().map(|item| self.key(item)).collect();
+                keys.sort();
+                keys.dedup();
+                keys
+            }
+            _ => vec![self.key(value)],
+        }
+    }
+}
+
+/// One property index: its normalization and its buckets. A covering index also keeps,
+/// per bucket, each filed node with copies of its `covered` properties (`None` where
+/// unset), in `covered` order.
+pub(crate) struct PropertyIndex {
+    pub(crate) options: IndexOptions,
+    pub(crate) buckets: HashMap<ValueKey, Vec<NodeId>>,
+    pub(crate) covered: Vec<String>,
+    pub(crate) rows: HashMap<ValueKey, Vec<CoveredRow>>,
+}
+
+/// A node found by `scan_covered`, with the covered properties it sets.
+pub type CoveredNode = (NodeId, HashMap<String, Value>);
+
+/// A node filed in a covering index, with its covered values.
+pub(crate) type CoveredRow = (NodeId, Vec<Option<Value>>);
+
+impl PropertyIndex {
+    pub(crate) fn new(options: IndexOptions, covered: Vec<String>) -> Self {
+        Self { options, buckets: HashMap::new(), covered, rows: HashMap::new() }
+    }
+
+    /// File `node` under every bucket its `value` belongs to.
+    fn file(&mut self, node: &Node, value: &Value) {
+        for bucket in self.options.keys(value) {
+            if !self.covered.is_empty() {
+                let row = (node.id, self.covered_values(node));
+                self.rows.entry(bucket.clone()).or_default().push(row);
+            }
+            self.buckets.entry(bucket).or_default().push(node.id);
+        }
+    }
+
+    /// Remove node `id` from the buckets its `value` belongs to, dropping emptied ones.
+    fn unfile(&mut self, id: NodeId, value: &Value) {
+        for bucket in self.options.keys(value) {
+            if self.buckets.get_mut(&bucket).is_some_and(|ids| unfile(ids, id)) {
+                self.buckets.remove(&bucket);
+            }
+            if let Some(rows) = self.rows.get_mut(&bucket) {
+                rows.retain(|(n, _)| *n != id);
+                if rows.is_empty() {
+                    self.rows.remove(&bucket);
+                }
+            }
+        }
+    }
+
+    /// Copies of the covered properties of `node`.
+    pub(crate) fn covered_values(&self, node: &Node) -> Vec<Option<Value>> {
+        self.covered.iter().map(|key| node.properties.get(key).cloned()).collect()
+    }
+
+    /// Rebuild the covered rows from `buckets`, e.g. after loading flushed buckets.
+    pub(crate) fn refill_rows(&mut self, nodes: &HashMap<NodeId, Node>) {
+        self.rows.clear();
+        if self.covered.is_empty() {
+            return;
+        }
+        for (bucket, ids) in &self.buckets {
+            let rows = ids.iter()
+                .filter_map(|id| nodes.get(id))
+                .map(|node| (node.id, self.covered_values(node)))
+                .collect();
+            self.rows.insert(bucket.clone(), rows);
+        }
+    }
+}
+
+/// Buckets of one range index, in value order.
+pub(crate) type RangeIndex = BTreeMap<OrderedValue, Vec<NodeId>>;
+
+/// Buckets of one edge property index.
+pub(crate) type EdgeIndex = HashMap<ValueKey, Vec<EdgeId>>;
+
+/// Buckets of one composite index, keyed by the values in declared key order.
+pub(crate) type CompositeIndex = HashMap<Vec<ValueKey>, Vec<NodeId>>;
+
+/// The tuple `node` is filed under in a composite index on `keys`, if it has them all.
+fn composite_key(node: &Node, keys: &[String]) -> Option<Vec<ValueKey>> {
+    keys.iter().map(|k| node.properties.get(k).map(ValueKey::from)).collect()
+}
+
+/// Drop `id` from a bucket; returns whether the bucket is now empty.
+fn unfile(ids: &mut Vec<NodeId>, id: NodeId) -> bool {
+    ids.retain(|n| *n != id);
+    ids.is_empty()
+}
+
+impl InMemoryGraphStore {
+    /// Index the `key` property of nodes carrying `label`, backfilling from the nodes
+    /// already stored. `scan_by_property` and `merge_node` use the index from then on.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty or the index already exists.
+    pub fn create_property_index(&mut self, label: &str, key: &str) -> Result<(), EngineError> {
+        self.create_property_index_with(label, key, IndexOptions::default())
+    }
+
+    /// `create_property_index` with string normalization or multi-value filing.
+    /// `scan_by_index` looks values up the same way; `scan_by_property` still matches
+    /// exactly, using the bucket of the normalized value as its candidates (and not using
+    /// a multi-value index at all for an `Array`).
+    pub fn create_property_index_with(&mut self, label: &str, key: &str, options: IndexOptions) -> Result<(), EngineError> {
+        self.create_indexed_property(label, key, PropertyIndex::new(options, Vec::new()))?;
+        self.log_create_index(DdlKind::Index(IndexKind::Property), label, &[key], || DdlOptions { property: options, ..DdlOptions::default() });
+        Ok(())
+    }
+
+    /// `create_property_index_with` for a covering index: each bucket also holds copies of
+    /// the `covered` properties of its nodes, kept in step by every write, so
+    /// `scan_covered` answers without reading the nodes. `scan_by_property` and the other
+    /// users of property indexes treat it like any other property index.
+    ///
+    /// # Errors
+    /// `InvalidArgument` as for `create_property_index`, and if `covered` is empty or
+    /// names a key twice or an empty key.
+    pub fn create_covering_index(&mut self, label: &str, key: &str, options: IndexOptions, covered: &[&str]) -> Result<(), EngineError> {
+        if covered.is_empty() || covered.iter().any(|k| k.is_empty()) {
+            return Err(EngineError::InvalidArgument("covering index needs non-empty covered keys".into()));
+        }
+        if covered.iter().enumerate().any(|(i, k)| covered[..i].contains(k)) {
+            return Err(EngineError::InvalidArgument(format!("covering index on {}.{} covers a key twice", label, key)));
+        }
+        let covered: Vec<String> = covered.iter().map(|k| k.to_string()).collect();
+        self.create_indexed_property(label, key, PropertyIndex::new(options, covered.clone()))?;
+        self.log_create_index(DdlKind::Index(IndexKind::Property), label, &[key], || DdlOptions { property: options, covered, case_sensitive: false });
+        Ok(())
+    }
+
+    /// The covered keys of the index on `(label, key)`: empty unless it is a covering
+    /// index, `None` if there is no index.
+    pub fn covered_keys(&self, label: &str, key: &str) -> Option<Vec<String>> {
+        self.property_indexes.get(&(label.to_string(), key.to_string())).map(|index| index.covered.clone())
+    }
+
+    /// Nodes filed under `value` by the covering index on `(label, key)`, looked up as
+    /// by `scan_by_index`, each with its covered properties (unset ones left out). Only the
+    /// index is read.
+    ///
+    /// # Errors
+    /// `InvalidArgument` as for `scan_by_index`, and if the index is not covering.
+    pub fn scan_covered(&self, label: &str, key: &str, value: &Value) -> Result<Vec<CoveredNode>, EngineError> {
+        let index = self.property_indexes.get(&(label.to_string(), key.to_string()))
+            .ok_or_else(|| EngineError::InvalidArgument(format!("no property index on {}.{}", label, key)))?;
+        if index.covered.is_empty() {
+            return Err(EngineError::InvalidArgument(format!("property index on {}.{} is not covering", label, key)));
+        }
+        if index.options.multi_value && matches!(value, Value::Array(_)) {
+            return Err(EngineError::InvalidArgument(format!("multi-value index on {}.{} is looked up by element", label, key)));
+        }
+        let rows = index.rows.get(&index.options.key(value)).map_or(&[][..], Vec::as_slice);
+        self.record_index_lookup(IndexKind::Property, label, &[key], !rows.is_empty());
+        let rows = rows.iter()
+            .map(|(id, values)| {
+                let values = index.covered.iter()
+                    .zip(values)
+                    .filter_map(|(k, v)| Some((k.clone(), v.clone()?)))
+                    .collect();
+                (*id, values)
+            })
+            .collect();
+        Ok(self.ordered(rows, |(id, _)| *id))
+    }
+
+    /// Backfill `index` from the nodes already stored and install it on `(label, key)`.
+    fn create_indexed_property(&mut self, label: &str, key: &str, mut index: PropertyIndex) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("property index needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.property_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("property index on {}.{} already exists", label, key)));
+        }
+        for id in self.label_index.get(label).into_iter().flatten() {
+            let Some(node) = self.nodes.get(id) else { continue };
+            if let Some(value) = node.properties.get(key) {
+                index.file(node, value);
+            }
+        }
+        self.property_indexes.insert(def, index);
+        self.track_index(IndexKind::Property, label, &[key]);
+        Ok(())
+    }
+
+    /// Drop the index on `(label, key)`. Returns `false` if there was none.
+    pub fn drop_property_index(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Property, label, &[key]);
+        let dropped = self.property_indexes.remove(&(label.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Property), label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Whether `(label, key)` is indexed.
+    pub fn has_property_index(&self, label: &str, key: &str) -> bool {
+        self.property_indexes.contains_key(&(label.to_string(), key.to_string()))
+    }
+
+    /// Every indexed `(label, key)`, sorted.
+    pub fn property_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.property_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// The normalization of the index on `(label, key)`, if there is one.
+    pub fn property_index_options(&self, label: &str, key: &str) -> Option<IndexOptions> {
+        self.property_indexes.get(&(label.to_string(), key.to_string())).map(|index| index.options)
+    }
+
+    /// Nodes filed under `value` by the index on `(label, key)`, with `value` normalized
+    /// like the indexed values: on a case-insensitive index `"paris"` finds `"Paris"`, and
+    /// on a multi-value index `"rust"` finds a node tagged `["db", "rust"]`.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `(label, key)` has no property index, or if `value` is an
+    /// `Array` and the index is multi-value.
+    pub fn scan_by_index(&self, label: &str, key: &str, value: &Value) -> Result<Vec<Node>, EngineError> {
+        let options = self.property_index_options(label, key)
+            .ok_or_else(|| EngineError::InvalidArgument(format!("no property index on {}.{}", label, key)))?;
+        if options.multi_value && matches!(value, Value::Array(_)) {
+            return Err(EngineError::InvalidArgument(format!("multi-value index on {}.{} is looked up by element", label, key)));
+        }
+        let ids = self.indexed_nodes(label, key, value).unwrap_or_default();
+        let nodes = ids.iter().filter_map(|id| self.nodes.get(id).cloned()).collect();
+        Ok(self.ordered(nodes, |n| n.id))
+    }
+
+    /// Index the `key` property of nodes carrying `label` in value order, backfilling from
+    /// the nodes already stored. `scan_by_property_range` uses it from then on.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty or the index already exists.
+    pub fn create_range_index(&mut self, label: &str, key: &str) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("range index needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.range_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("range index on {}.{} already exists", label, key)));
+        }
+        let mut buckets = RangeIndex::new();
+        for id in self.label_index.get(label).into_iter().flatten() {
+            if let Some(value) = self.nodes.get(id).and_then(|n| n.properties.get(key)).and_then(OrderedValue::from_value) {
+                buckets.entry(value).or_default().push(*id);
+            }
+        }
+        self.range_indexes.insert(def, buckets);
+        self.track_index(IndexKind::Range, label, &[key]);
+        self.log_create_index(DdlKind::Index(IndexKind::Range), label, &[key], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the range index on `(label, key)`. Returns `false` if there was none.
+    pub fn drop_range_index(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Range, label, &[key]);
+        let dropped = self.range_indexes.remove(&(label.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Range), label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Whether `(label, key)` has a range index.
+    pub fn has_range_index(&self, label: &str, key: &str) -> bool {
+        self.range_indexes.contains_key(&(label.to_string(), key.to_string()))
+    }
+
+    /// Every `(label, key)` with a range index, sorted.
+    pub fn range_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.range_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// Ids of the nodes whose value lies in `range`, in value order, read from the range
+    /// index on `(label, key)`; `None` when that pair has no range index.
+    pub(crate) fn range_indexed_nodes(&self, label: &str, key: &str, range: &NumericRange) -> Option<Vec<NodeId>> {
+        let buckets = self.range_indexes.get(&(label.to_string(), key.to_string()))?;
+        // Bounds are always inclusive: a widened bound shares its bucket with the ints it
+        // cannot tell apart, which an exclusive one would skip. Callers recheck the range
+        let bound = |value: Option<&Value>| match value.and_then(OrderedValue::from_value) {
+            Some(v) => Bound::Included(v),
+            None => Bound::Unbounded,
+        };
+        let lower = bound(range.min());
+        // Strings sort after every number, and "" before every other string
+        let upper = match bound(range.max()) {
+            Bound::Unbounded => Bound::Excluded(OrderedValue::String(String::new())),
+            upper => upper,
+        };
+        // BTreeMap::range panics on inverted bounds
+        let empty = match (&lower, &upper) {
+            (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+            _ => false,
+        };
+        let ids: Vec<NodeId> = if empty {
+            Vec::new()
+        } else {
+            buckets.range((lower, upper)).flat_map(|(_, ids)| ids.iter().copied()).collect()
+        };
+        self.record_index_lookup(IndexKind::Range, label, &[key], !ids.is_empty());
+        Some(ids)
+    }
+
+    /// Ids of the nodes the range index on `(label, key)` files under `value`, or `None`
+    /// when that pair has no range index or `value` is not orderable. `Int` and `Float`
+    /// share buckets, so callers recheck equality.
+    pub(crate) fn range_point_nodes(&self, label: &str, key: &str, value: &Value) -> Option<&[NodeId]> {
+        let buckets = self.range_indexes.get(&(label.to_string(), key.to_string()))?;
+        let ids = buckets.get(&OrderedValue::from_value(value)?).map_or(&[][..], Vec::as_slice);
+        self.record_index_lookup(IndexKind::Range, label, &[key], !ids.is_empty());
+        Some(ids)
+    }
+
+    /// Index nodes carrying `label` by the tuple of their `keys` values, in declared order,
+    /// backfilling from the nodes already stored. Nodes missing any of the keys are not
+    /// indexed. Query it with `scan_by_composite`.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` is empty, `keys` is empty, contains an empty or
+    /// repeated key, or the index already exists.
+    pub fn create_composite_index(&mut self, label: &str, keys: &[&str]) -> Result<(), EngineError> {
+        if label.is_empty() || keys.is_empty() || keys.iter().any(|k| k.is_empty()) {
+            return Err(EngineError::InvalidArgument("composite index needs a label and non-empty keys".into()));
+        }
+        if keys.iter().enumerate().any(|(i, k)| keys[..i].contains(k)) {
+            return Err(EngineError::InvalidArgument(format!("composite index keys repeat: {:?}", keys)));
+        }
+        let def = (label.to_string(), keys.iter().map(|k| k.to_string()).collect::<Vec<_>>());
+        if self.composite_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("composite index on {}{:?} already exists", label, keys)));
+        }
+        let mut buckets = CompositeIndex::new();
+        for id in self.label_index.get(label).into_iter().flatten() {
+            if let Some(tuple) = self.nodes.get(id).and_then(|n| composite_key(n, &def.1)) {
+                buckets.entry(tuple).or_default().push(*id);
+            }
+        }
+        self.composite_indexes.insert(def, buckets);
+        self.track_index(IndexKind::Composite, label, keys);
+        self.log_create_index(DdlKind::Index(IndexKind::Composite), label, keys, DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the composite index on `label` and exactly `keys` (in declared order).
+    /// Returns `false` if there was none.
+    pub fn drop_composite_index(&mut self, label: &str, keys: &[&str]) -> Result<bool, EngineError> {
+        let def = (label.to_string(), keys.iter().map(|k| k.to_string()).collect::<Vec<_>>());
+        self.untrack_index(IndexKind::Composite, label, keys);
+        let dropped = self.composite_indexes.remove(&def).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Composite), label, keys);
+        }
+        Ok(dropped)
+    }
+
+    /// Every composite index as `(label, keys)`, sorted.
+    pub fn composite_indexes(&self) -> Vec<(String, Vec<String>)> {
+        let mut defs: Vec<(String, Vec<String>)> = self.composite_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// Nodes carrying `label` whose properties equal every `(key, value)` in `pairs`,
+    /// answered from the composite index on that key set. `pairs` may come in any order.
+    ///
+    /// # Errors
+    /// `InvalidArgument` unless a composite index on `label` covers exactly the keys of
+    /// `pairs`; the lookup never falls back to a scan.
+    pub fn scan_by_composite(&self, label: &str, pairs: &[(&str, Value)]) -> Result<Vec<Node>, EngineError> {
+        let index = self.composite_indexes.iter().find(|((l, keys), _)| {
+            l == label
+                && keys.len() == pairs.len()
+                && keys.iter().all(|k| pairs.iter().filter(|(p, _)| p == k).count() == 1)
+        });
+        let Some(((_, keys), buckets)) = index else {
+            let keys: Vec<&str> = pairs.iter().map(|(k, _)| *k).collect();
+            return Err(EngineError::InvalidArgument(format!("no composite index on {}{:?}", label, keys)));
+        };
+        let tuple: Vec<ValueKey> = keys.iter()
+            .map(|k| pairs.iter().find(|(p, _)| p == k).map(|(_, v)| ValueKey::from(v)).expect("matched above"))
+            .collect();
+        let nodes: Vec<Node> = buckets.get(&tuple)
+            .map(|ids| ids.iter().filter_map(|id| self.nodes.get(id).cloned()).collect())
+            .unwrap_or_default();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.record_index_lookup(IndexKind::Composite, label, &keys, !nodes.is_empty());
+        Ok(self.ordered(nodes, |n| n.id))
+    }
+
+    /// Ids of the nodes whose `key` value is `value`, read from a composite index on
+    /// `label` whose first key is `key` (the one with the fewest keys if there are
+    /// several); `None` when there is none. Walks every bucket of that index, which still
+    /// beats a label scan: there is at most one bucket per indexed node.
+    pub(crate) fn composite_prefix_nodes(&self, label: &str, key: &str, value: &Value) -> Option<Vec<NodeId>> {
+        let ((_, keys), buckets) = self.composite_indexes.iter()
+            .filter(|((l, keys), _)| l == label && keys[0] == key)
+            .min_by(|((_, a), _), ((_, b), _)| (a.len(), a).cmp(&(b.len(), b)))?;
+        let first = ValueKey::from(value);
+        let ids: Vec<NodeId> = buckets.iter()
+            .filter(|(tuple, _)| tuple[0] == first)
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.record_index_lookup(IndexKind::Composite, label, &keys, !ids.is_empty());
+        Some(ids)
+    }
+
+    /// Index the `key` property of live edges of `edge_type`, backfilling from the edges
+    /// already stored. `scan_edges_by_property` uses it from then on.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `edge_type` or `key` is empty or the index already exists.
+    pub fn create_edge_index(&mut self, edge_type: &str, key: &str) -> Result<(), EngineError> {
+        if edge_type.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("edge index needs an edge type and a key".into()));
+        }
+        let def = (edge_type.to_string(), key.to_string());
+        if self.edge_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("edge index on {}.{} already exists", edge_type, key)));
+        }
+        let mut buckets = EdgeIndex::new();
+        for id in self.edge_type_index.get(edge_type).into_iter().flatten() {
+            if let Some(value) = self.edges.get(id).and_then(|e| e.properties.get(key)) {
+                buckets.entry(ValueKey::from(value)).or_default().push(*id);
+            }
+        }
+        self.edge_indexes.insert(def, buckets);
+        self.track_index(IndexKind::Edge, edge_type, &[key]);
+        self.log_create_index(DdlKind::Index(IndexKind::Edge), edge_type, &[key], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the edge index on `(edge_type, key)`. Returns `false` if there was none.
+    pub fn drop_edge_index(&mut self, edge_type: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Edge, edge_type, &[key]);
+        let dropped = self.edge_indexes.remove(&(edge_type.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Edge), edge_type, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every indexed edge `(edge_type, key)`, sorted.
+    pub fn edge_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.edge_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// Ids of the edges filed under `value` by the edge index on `(edge_type, key)`, or
+    /// `None` when that pair is not indexed.
+    pub(crate) fn indexed_edges(&self, edge_type: &str, key: &str, value: &Value) -> Option<&[EdgeId]> {
+        let buckets = self.edge_indexes.get(&(edge_type.to_string(), key.to_string()))?;
+        let ids = buckets.get(&ValueKey::from(value)).map_or(&[][..], Vec::as_slice);
+        self.record_index_lookup(IndexKind::Edge, edge_type, &[key], !ids.is_empty());
+        Some(ids)
+    }
+
+    /// File live edge `id` in the edge indexes of its type. Pairs with
+    /// `unindex_edge_properties` like the node variant.
+    pub(crate) fn index_edge_properties(&mut self, id: EdgeId) {
+        if self.edge_indexes.is_empty() {
+            return;
+        }
+        let Some(edge) = self.edges.get(&id).filter(|e| !e.deleted) else { return };
+        for ((edge_type, key), buckets) in self.edge_indexes.iter_mut() {
+            if let Some(value) = edge.properties.get(key).filter(|_| edge.edge_type == *edge_type) {
+                buckets.entry(ValueKey::from(value)).or_default().push(id);
+            }
+        }
+    }
+
+    /// Remove live edge `id` from the edge index buckets it is currently filed under.
+    pub(crate) fn unindex_edge_properties(&mut self, id: EdgeId) {
+        if self.edge_indexes.is_empty() {
+            return;
+        }
+        let Some(edge) = self.edges.get(&id).filter(|e| !e.deleted) else { return };
+        for ((edge_type, key), buckets) in self.edge_indexes.iter_mut() {
+            let Some(value) = edge.properties.get(key).filter(|_| edge.edge_type == *edge_type) else { continue };
+            let value = ValueKey::from(value);
+            if buckets.get_mut(&value).is_some_and(|ids| unfile(ids, id)) {
+                buckets.remove(&value);
+            }
+        }
+    }
+
+    /// Whether any property, range, composite, text or prefix index is defined.
+    fn has_secondary_indexes(&self) -> bool {
+        !(self.property_indexes.is_empty()
+            && self.range_indexes.is_empty()
+            && self.composite_indexes.is_empty()
+            && self.text_indexes.is_empty()
+            && self.prefix_indexes.is_empty()
+            && self.computed_indexes.is_empty()
+            && self.ttl_indexes.is_empty())
+    }
+
+    /// Ids of the nodes filed under the normalized `value` by the index on `(label, key)`,
+    /// or `None` when that pair is not indexed, or when `value` is an `Array` and the index
+    /// is multi-value (it files elements, not lists). With a normalizing or multi-value
+    /// index the bucket may hold values that only match once normalized, or lists that
+    /// merely contain `value`.
+    pub(crate) fn indexed_nodes(&self, label: &str, key: &str, value: &Value) -> Option<&[NodeId]> {
+        let index = self.property_indexes.get(&(label.to_string(), key.to_string()))?;
+        if index.options.multi_value && matches!(value, Value::Array(_)) {
+            return None;
+        }
+        let ids = index.buckets.get(&index.options.key(value)).map_or(&[][..], Vec::as_slice);
+        self.record_index_lookup(IndexKind::Property, label, &[key], !ids.is_empty());
+        Some(ids)
+    }
+
+    /// File live node `id` in every index it qualifies for. Pairs with
+    /// `unindex_node_properties`, which must run first if the node was already filed.
+    pub(crate) fn index_node_properties(&mut self, id: NodeId) {
+        if !self.has_secondary_indexes() {
+            return;
+        }
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        let indexed = |(label, key): &(String, String)| node.properties.get(key).filter(|_| node.labels.contains(label));
+        for (def, index) in self.property_indexes.iter_mut() {
+            if let Some(value) = indexed(def) {
+                index.file(node, value);
+            }
+        }
+        for (def, buckets) in self.range_indexes.iter_mut() {
+            if let Some(value) = indexed(def).and_then(OrderedValue::from_value) {
+                buckets.entry(value).or_default().push(id);
+            }
+        }
+        for ((label, keys), buckets) in self.composite_indexes.iter_mut() {
+            if let Some(tuple) = composite_key(node, keys).filter(|_| node.labels.contains(label)) {
+                buckets.entry(tuple).or_default().push(id);
+            }
+        }
+        self.index_node_text(id);
+        self.index_node_prefix(id);
+        self.index_node_computed(id);
+        self.index_node_ttl(id);
+    }
+
+    /// Remove node `id` from the buckets its current labels and properties file it under,
+    /// dropping buckets once empty.
+    pub(crate) fn unindex_node_properties(&mut self, id: NodeId) {
+        if !self.has_secondary_indexes() {
+            return;
+        }
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        let indexed = |(label, key): &(String, String)| node.properties.get(key).filter(|_| node.labels.contains(label));
+        for (def, index) in self.property_indexes.iter_mut() {
+            if let Some(value) = indexed(def) {
+                index.unfile(id, value);
+            }
+        }
+        for (def, buckets) in self.range_indexes.iter_mut() {
+            let Some(value) = indexed(def).and_then(OrderedValue::from_value) else { continue };
+            if buckets.get_mut(&value).is_some_and(|ids| unfile(ids, id)) {
+                buckets.remove(&value);
+            }
+        }
+        for ((label, keys), buckets) in self.composite_indexes.iter_mut() {
+            let Some(tuple) = composite_key(node, keys).filter(|_| node.labels.contains(label)) else { continue };
+            if buckets.get_mut(&tuple).is_some_and(|ids| unfile(ids, id)) {
+                buckets.remove(&tuple);
+            }
+        }
+        self.unindex_node_text(id);
+        self.unindex_node_prefix(id);
+        self.unindex_node_computed(id);
+        self.unindex_node_ttl(id);
+    }
+
+    /// Refill every property, range, composite, edge, text and prefix index from the live
+    /// records, keeping the definitions. Nothing is logged: no definition changes.
+    pub(crate) fn rebuild_property_indexes(&mut self) {
+        self.without_wal(Self::refill_property_indexes);
+    }
+
+    fn refill_property_indexes(&mut self) {
+        let defs: Vec<((String, String), PropertyIndex)> = self.property_indexes.drain()
+            .map(|(def, index)| (def, PropertyIndex::new(index.options, index.covered)))
+            .collect();
+        for ((label, key), index) in defs {
+            self.create_indexed_property(&label, &key, index).expect("definitions are valid and distinct");
+        }
+        let defs: Vec<(String, String)> = self.range_indexes.drain().map(|(def, _)| def).collect();
+        for (label, key) in defs {
+            self.create_range_index(&label, &key).expect("definitions are valid and distinct");
+        }
+        let defs: Vec<(String, Vec<String>)> = self.composite_indexes.drain().map(|(def, _)| def).collect();
+        for (label, keys) in defs {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            self.create_composite_index(&label, &keys).expect("definitions are valid and distinct");
+        }
+        let defs: Vec<(String, String)> = self.edge_indexes.drain().map(|(def, _)| def).collect();
+        for (edge_type, key) in defs {
+            self.create_edge_index(&edge_type, &key).expect("definitions are valid and distinct");
+        }
+        self.rebuild_text_indexes();
+        self.rebuild_prefix_indexes();
+        self.rebuild_computed_indexes();
+        self.rebuild_ttl_indexes();
+    }
+}
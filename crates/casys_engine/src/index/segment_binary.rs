@@ -0,0 +1,448 @@
+//! Binary node and edge segments
+//!
+//! A binary segment starts with `SEGMENT_MAGIC` and its format version (u32 LE), from
+//! version 3 a compression byte (see `segment_compress`), then one byte telling nodes
+//! (1) from edges (2), the record count (u64 LE), the id allocator state (next id, then
+//! a u32 count and the free ids) and, for nodes only, the `last_applied_lsn` and
+//! `next_txn_id` of the store. Each record follows as a u32 length
+//! then its fields, encoded as in binary WAL payloads (see `wal_binary`): a node is its
+//! id, tombstone flag, labels and properties; an edge its id, endpoints, type, tombstone
+//! flag and properties. The CRC32 of everything before it, before any compression (u32
+//! LE), ends the segment; version 1 segments, written before it, end with the last record.
+//!
+//! Both directions stream: records are encoded one at a time straight to the output, and
+//! decoded one at a time from the input into the store, so neither holds more than one
+//! record beyond the store itself. Writing does list the ids (8 bytes a record) to emit
+//! records in id order, which lets loading append to the sorted label buckets instead
+//! of inserting into them. A damaged segment fails the load with
+//! `EngineError::SegmentCorruption`, at the latest once its checksum is read.
+//!
+//! JSON segments have no header: they count as format version 0, told apart by the
+//! missing magic, and stay readable. `segment_version` reads the version off a segment
+//! so loading can dispatch on it. Like binary WAL payloads, binary segments keep every
+//! `Value` exactly.
+
+use super::segment_compress::{decompressed, CompressionOption, COMPRESSION_SINCE};
+#[cfg(not(feature = "compression"))]
+use super::segment_compress::without_compression;
+use super::wal_binary::{put_len, put_props, put_str, put_u64, Input};
+use super::{Edge, EdgeId, InMemoryGraphStore, Node, NodeId};
+use crate::types::EngineError;
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+/// First bytes of a binary segment.
+pub(crate) const SEGMENT_MAGIC: [u8; 4] = *b"CSGB";
+
+/// Layout version of binary segments written. Loading also reads versions 1 (no checksum)
+/// and 2 (no compression byte), and rejects any other.
+pub(crate) const FORMAT_VERSION: u32 = 3;
+
+const NODES: u8 = 1;
+const EDGES: u8 = 2;
+/// Record kinds of delta segments; see `segment_delta`.
+const NODE_DELTA: u8 = 3;
+const EDGE_DELTA: u8 = 4;
+
+/// How `flush` writes the node and edge segments. Loading reads every format version.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SegmentFormat {
+    /// One JSON document per segment, as every store flushed before binary segments
+    Json,
+    /// The binary layout of this module
+    #[default]
+    Binary,
+}
+
+impl SegmentFormat {
+    /// Format version of the segments written: 0 for JSON, which has no header.
+    pub fn version(self) -> u32 {
+        match self {
+            SegmentFormat::Json => 0,
+            SegmentFormat::Binary => FORMAT_VERSION,
+        }
+    }
+
+    /// The format writing segments of `version`, if this build writes it.
+    pub fn from_version(version: u32) -> Option<Self> {
+        [SegmentFormat::Json, SegmentFormat::Binary].into_iter().find(|format| format.version() == version)
+    }
+}
+
+/// Writes a binary segment, hashing it into the checksum that `finish` appends.
+struct SegmentOutput<'a> {
+    out: Box<dyn Write + 'a>,
+    hasher: crc32fast::Hasher,
+    buf: Vec<u8>,
+}
+
+impl<'a> SegmentOutput<'a> {
+    /// Write the magic, version and compression byte to `out`, and continue through the
+    /// compressor if `compression` asks for one.
+    fn new(
+        out: &'a mut dyn Write,
+        compression: CompressionOption,
+        kind: u8,
+        count: usize,
+        high_water: u64,
+        free: &[u64],
+    ) -> io::Result<Self> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = SEGMENT_MAGIC.to_vec();
+        buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buf.push(compression.marker());
+        hasher.update(&buf);
+        out.write_all(&buf)?;
+        let out: Box<dyn Write + 'a> = match compression {
+            CompressionOption::None => Box::new(out),
+            #[cfg(feature = "compression")]
+            _ => Box::new(super::segment_compress::BlockWriter::new(out, compression)),
+            #[cfg(not(feature = "compression"))]
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, without_compression("flushing compressed segments"))),
+        };
+        buf.clear();
+        buf.push(kind);
+        put_u64(&mut buf, count as u64);
+        put_u64(&mut buf, high_water);
+        put_ids(&mut buf, free);
+        Ok(Self { out, hasher, buf })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.update(bytes);
+        self.out.write_all(bytes)
+    }
+
+    /// Write the pending header fields.
+    fn flush_header(&mut self) -> io::Result<()> {
+        let header = std::mem::take(&mut self.buf);
+        self.write(&header)
+    }
+
+    /// Write a record behind its length.
+    fn record(&mut self, encode: impl FnOnce(&mut Vec<u8>)) -> io::Result<()> {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.clear();
+        encode(&mut buf);
+        self.write(&(buf.len() as u32).to_le_bytes())?;
+        self.write(&buf)?;
+        self.buf = buf;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.out.write_all(&self.hasher.finalize().to_le_bytes())?;
+        // Writes the last compressed block
+        self.out.flush()
+    }
+}
+
+/// `ids` in ascending order, the order segments list their records in.
+pub(crate) fn sorted_ids<'a>(ids: impl Iterator<Item = &'a u64>) -> Vec<u64> {
+    let mut ids: Vec<u64> = ids.copied().collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// A u32 count, then each of `ids`.
+fn put_ids(out: &mut Vec<u8>, ids: &[u64]) {
+    put_len(out, ids.len());
+    for id in ids {
+        put_u64(out, *id);
+    }
+}
+
+fn put_node(out: &mut Vec<u8>, node: &Node) {
+    put_u64(out, node.id);
+    out.push(node.deleted as u8);
+    put_len(out, node.labels.len());
+    for label in &node.labels {
+        put_str(out, label);
+    }
+    put_props(out, &node.properties);
+}
+
+fn put_edge(out: &mut Vec<u8>, edge: &Edge) {
+    put_u64(out, edge.id);
+    put_u64(out, edge.from_node);
+    put_u64(out, edge.to_node);
+    put_str(out, &edge.edge_type);
+    out.push(edge.deleted as u8);
+    put_props(out, &edge.properties);
+}
+
+/// The format version of the segment `input` starts: the one after the magic, or 0 for
+/// JSON. Returns the input whole again, for the reader of that version.
+pub(crate) fn segment_version(mut input: impl Read, segment: &str) -> Result<(u32, impl Read), EngineError> {
+    let mut head = Vec::with_capacity(8);
+    (&mut input).take(8).read_to_end(&mut head).map_err(|e| read_error(segment, e))?;
+    let version = match head.strip_prefix(&SEGMENT_MAGIC) {
+        Some(version) if version.len() == 4 => u32::from_le_bytes(version.try_into().expect("4 bytes")),
+        _ => 0,
+    };
+    Ok((version, io::Cursor::new(head).chain(input)))
+}
+
+pub(crate) fn corruption(segment: &str, detail: impl Into<String>) -> EngineError {
+    EngineError::SegmentCorruption { path: segment.to_string(), detail: detail.into() }
+}
+
+/// `e`, met reading `segment`: a store reporting corruption through the reader (as the fs
+/// one does for its checksum) keeps its error, and a segment ending early is corrupt.
+pub(crate) fn read_error(segment: &str, e: io::Error) -> EngineError {
+    if let Some(error) = e.get_ref().and_then(|inner| inner.downcast_ref::<EngineError>()) {
+        return error.clone();
+    }
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        corruption(segment, "segment cut short")
+    } else {
+        EngineError::StorageIo(format!("read segment {}: {}", segment, e))
+    }
+}
+
+/// A binary segment read from a stream: the header fields, then one record at a time,
+/// hashed as they are read for the checksum that ends it.
+struct SegmentInput<'s, R> {
+    inner: R,
+    segment: &'s str,
+    hasher: crc32fast::Hasher,
+    record: Vec<u8>,
+}
+
+impl<'s, R: Read> SegmentInput<'s, R> {
+    fn new(inner: R, segment: &'s str) -> Self {
+        Self { inner, segment, hasher: crc32fast::Hasher::new(), record: Vec::new() }
+    }
+
+    fn corrupt(&self, detail: impl Into<String>) -> EngineError {
+        corruption(self.segment, detail)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], EngineError> {
+        let mut bytes = [0; N];
+        self.inner.read_exact(&mut bytes).map_err(|e| read_error(self.segment, e))?;
+        self.hasher.update(&bytes);
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, EngineError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, EngineError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, EngineError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// The header fields after the magic, checked against the segment `kind` expected,
+    /// and whether a checksum ends the segment.
+    fn header(&mut self, kind: u8) -> Result<(u64, u64, Vec<u64>, bool), EngineError> {
+        if self.array()? != SEGMENT_MAGIC {
+            return Err(self.corrupt("not a binary segment"));
+        }
+        let version = self.u32()?;
+        if !(1..=FORMAT_VERSION).contains(&version) {
+            return Err(self.corrupt(format!("unsupported segment format version {}", version)));
+        }
+        if version >= COMPRESSION_SINCE {
+            // Checked and undone by `decompressed`
+            self.u8()?;
+        }
+        let found = self.u8()?;
+        if found != kind {
+            return Err(self.corrupt(format!("segment holds record kind {}, expected {}", found, kind)));
+        }
+        let count = self.u64()?;
+        let high_water = self.u64()?;
+        let free = (0..self.u32()?).map(|_| self.u64()).collect::<Result<_, _>>()?;
+        Ok((count, high_water, free, version >= 2))
+    }
+
+    /// Decode each of the `count` records with `decode`, which must consume it whole, and
+    /// hand it to `insert`; then check the checksum if there is one, which must end the
+    /// input.
+    fn records<T>(
+        &mut self,
+        count: u64,
+        checksummed: bool,
+        mut decode: impl FnMut(&mut Input<'_>) -> Result<T, EngineError>,
+        mut insert: impl FnMut(T),
+    ) -> Result<(), EngineError> {
+        for _ in 0..count {
+            let len = self.u32()? as u64;
+            self.record.clear();
+            // Grows with the bytes actually there, whatever a damaged length claims
+            (&mut self.inner).take(len).read_to_end(&mut self.record).map_err(|e| read_error(self.segment, e))?;
+            if self.record.len() as u64 != len {
+                return Err(self.corrupt("segment cut short"));
+            }
+            self.hasher.update(&self.record);
+            let mut record = Input::new(&self.record, 0, "segment parse");
+            let item = decode(&mut record).map_err(|e| match e {
+                EngineError::StorageIo(detail) => corruption(self.segment, detail),
+                e => e,
+            })?;
+            if !record.is_empty() {
+                return Err(self.corrupt("trailing bytes in record"));
+            }
+            insert(item);
+        }
+        if checksummed {
+            let computed = self.hasher.clone().finalize();
+            let expected = u32::from_le_bytes(self.array()?);
+            if computed != expected {
+                return Err(self.corrupt(format!("checksum mismatch: expected {:#x}, got {:#x}", expected, computed)));
+            }
+        }
+        if self.inner.read(&mut [0]).map_err(|e| read_error(self.segment, e))? != 0 {
+            return Err(self.corrupt(format!("bytes after the {} records the header counts", count)));
+        }
+        Ok(())
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Write the node segment in the binary layout to `out`.
+    pub(crate) fn write_binary_nodes(&self, out: &mut dyn Write, compression: CompressionOption) -> io::Result<()> {
+        self.write_node_records(out, compression, NODES, &sorted_ids(self.nodes.keys()), &[])
+    }
+
+    /// Write the node partition of `ids`, sorted, to `out`; see `segment_partition`.
+    pub(crate) fn write_binary_node_partition(&self, out: &mut dyn Write, compression: CompressionOption, ids: &[NodeId]) -> io::Result<()> {
+        self.write_node_records(out, compression, NODES, ids, &[])
+    }
+
+    /// Write a node delta to `out`: the records of the `changed` ids still there, and
+    /// the others as removed.
+    pub(crate) fn write_binary_node_delta(&self, out: &mut dyn Write, compression: CompressionOption, changed: &HashSet<NodeId>) -> io::Result<()> {
+        let (kept, removed): (Vec<_>, Vec<_>) = sorted_ids(changed.iter()).into_iter().partition(|id| self.nodes.contains_key(id));
+        self.write_node_records(out, compression, NODE_DELTA, &kept, &removed)
+    }
+
+    fn write_node_records(&self, out: &mut dyn Write, compression: CompressionOption, kind: u8, ids: &[NodeId], removed: &[NodeId]) -> io::Result<()> {
+        let (high_water, free) = (self.node_ids.high_water(), self.node_ids.free_ids());
+        let mut out = SegmentOutput::new(out, compression, kind, ids.len(), high_water, &free)?;
+        put_u64(&mut out.buf, self.last_applied_lsn);
+        put_u64(&mut out.buf, self.next_txn_id);
+        if kind == NODE_DELTA {
+            put_ids(&mut out.buf, removed);
+        }
+        out.flush_header()?;
+        for id in ids {
+            out.record(|buf| put_node(buf, &self.nodes[id]))?;
+        }
+        out.finish()
+    }
+
+    /// Write the edge segment in the binary layout to `out`.
+    pub(crate) fn write_binary_edges(&self, out: &mut dyn Write, compression: CompressionOption) -> io::Result<()> {
+        self.write_edge_records(out, compression, EDGES, &sorted_ids(self.edges.keys()), &[])
+    }
+
+    /// Write an edge delta to `out`, as `write_binary_node_delta` does nodes.
+    pub(crate) fn write_binary_edge_delta(&self, out: &mut dyn Write, compression: CompressionOption, changed: &HashSet<EdgeId>) -> io::Result<()> {
+        let (kept, removed): (Vec<_>, Vec<_>) = sorted_ids(changed.iter()).into_iter().partition(|id| self.edges.contains_key(id));
+        self.write_edge_records(out, compression, EDGE_DELTA, &kept, &removed)
+    }
+
+    fn write_edge_records(&self, out: &mut dyn Write, compression: CompressionOption, kind: u8, ids: &[EdgeId], removed: &[EdgeId]) -> io::Result<()> {
+        let (high_water, free) = (self.edge_ids.high_water(), self.edge_ids.free_ids());
+        let mut out = SegmentOutput::new(out, compression, kind, ids.len(), high_water, &free)?;
+        if kind == EDGE_DELTA {
+            put_ids(&mut out.buf, removed);
+        }
+        out.flush_header()?;
+        for id in ids {
+            out.record(|buf| put_edge(buf, &self.edges[id]))?;
+        }
+        out.finish()
+    }
+
+    /// Read binary node segment `segment` from `input` into the store, record by record.
+    pub(crate) fn read_binary_nodes(&mut self, input: impl Read, segment: &str) -> Result<(), EngineError> {
+        self.read_node_records(input, segment, NODES)
+    }
+
+    /// Apply node delta `segment` from `input` to the store: drop the nodes it removes
+    /// (with their edges), then put its records in place of those loaded before.
+    pub(crate) fn read_binary_node_delta(&mut self, input: impl Read, segment: &str) -> Result<(), EngineError> {
+        self.read_node_records(input, segment, NODE_DELTA)
+    }
+
+    fn read_node_records(&mut self, input: impl Read, segment: &str, kind: u8) -> Result<(), EngineError> {
+        let mut input = SegmentInput::new(decompressed(input, segment)?, segment);
+        let (count, high_water, free, checksummed) = input.header(kind)?;
+        let last_applied_lsn = input.u64()?;
+        let next_txn_id = input.u64()?;
+        if kind == NODE_DELTA {
+            for _ in 0..input.u32()? {
+                let id = input.u64()?;
+                self.detach_node(id);
+            }
+        }
+        let decode = |input: &mut Input<'_>| -> Result<Node, EngineError> {
+            Ok(Node { id: input.u64()?, deleted: input.bool()?, labels: input.strings()?, properties: input.props()? })
+        };
+        // Rebuilds the label index and marks each id as used; a record replaces the one loaded before.
+        // A partial load skips the nodes it does not keep, and drops them if they lost the label
+        input.records(count, checksummed, decode, |node| {
+            if self.keeps_node(&node) {
+                self.insert_node(node);
+            } else if kind == NODE_DELTA {
+                self.detach_node(node.id);
+            }
+        })?;
+        self.node_ids.restore(high_water, &free);
+        self.last_applied_lsn = last_applied_lsn;
+        self.next_txn_id = next_txn_id;
+        Ok(())
+    }
+
+    /// Read binary edge segment `segment` from `input` into the store, record by record.
+    pub(crate) fn read_binary_edges(&mut self, input: impl Read, segment: &str) -> Result<(), EngineError> {
+        self.read_edge_records(input, segment, EDGES)
+    }
+
+    /// Apply edge delta `segment` from `input` to the store, as `read_binary_node_delta`
+    /// does nodes.
+    pub(crate) fn read_binary_edge_delta(&mut self, input: impl Read, segment: &str) -> Result<(), EngineError> {
+        self.read_edge_records(input, segment, EDGE_DELTA)
+    }
+
+    fn read_edge_records(&mut self, input: impl Read, segment: &str, kind: u8) -> Result<(), EngineError> {
+        let mut input = SegmentInput::new(decompressed(input, segment)?, segment);
+        let (count, high_water, free, checksummed) = input.header(kind)?;
+        let delta = kind == EDGE_DELTA;
+        if delta {
+            for _ in 0..input.u32()? {
+                let id = input.u64()?;
+                self.detach_edge(id);
+            }
+        }
+        let decode = |input: &mut Input<'_>| -> Result<Edge, EngineError> {
+            Ok(Edge {
+                id: input.u64()?,
+                from_node: input.u64()?,
+                to_node: input.u64()?,
+                edge_type: input.string()?,
+                deleted: input.bool()?,
+                properties: input.props()?,
+            })
+        };
+        // Rebuilds the adjacency indexes and marks each id as used. `insert_edge` only adds
+        // adjacency entries, so a delta first drops the edge loaded before
+        input.records(count, checksummed, decode, |edge| {
+            if delta {
+                self.detach_edge(edge.id);
+            }
+            if self.keeps_edge(&edge) {
+                self.insert_edge(edge);
+            }
+        })?;
+        self.edge_ids.restore(high_water, &free);
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Compressed binary segments
+//!
+//! From format version 3 a compression byte follows the version of a binary segment:
+//! `UNCOMPRESSED`, or `ZSTD_BLOCKS` when the rest of the segment (from the record kind to
+//! the checksum, which still covers the bytes before compression) is a run of blocks.
+//! Each block is the length of its data before (u32 LE, at most `BLOCK_SIZE`) and after
+//! compression (u32 LE), the CRC32 of the data after (u32 LE), then that data as one
+//! zstd frame. The block checksum tells a damaged block from a bug before zstd sees it,
+//! and the length before bounds what decompressing may allocate. Blocks keep writing and
+//! loading streamed, a block of buffer at a time.
+//!
+//! Compressing and decompressing need the `compression` feature, which brings in the
+//! `zstd` crate. Builds without it reject compressed segments with `NotImplemented`
+//! instead of misreading them, and flushes asking for compression likewise.
+
+use super::segment_binary::{corruption, read_error, segment_version, SegmentFormat, FORMAT_VERSION};
+use crate::types::EngineError;
+use std::io::{self, Read};
+
+/// Compression byte of a segment written as it is.
+pub(crate) const UNCOMPRESSED: u8 = 0;
+/// Compression byte of a segment written as zstd blocks.
+pub(crate) const ZSTD_BLOCKS: u8 = b'Z';
+/// First format version with a compression byte.
+pub(crate) const COMPRESSION_SINCE: u32 = 3;
+
+/// Bytes of a segment compressed together.
+#[cfg(feature = "compression")]
+const BLOCK_SIZE: usize = 1 << 20;
+
+/// zstd levels of `CompressionOption::Fast` and `CompressionOption::Best`.
+#[cfg(feature = "compression")]
+const FAST_LEVEL: i32 = 3;
+#[cfg(feature = "compression")]
+const BEST_LEVEL: i32 = 19;
+
+/// How `flush` compresses binary node and edge segments. Loading decompresses whatever
+/// it finds, given the `compression` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionOption {
+    /// Segments are written as they are.
+    #[default]
+    None,
+    /// zstd at its default level 3.
+    Fast,
+    /// zstd at level 19: tens of times slower to write, a third smaller again, and as
+    /// fast to read. For segments written once and loaded often.
+    Best,
+}
+
+impl CompressionOption {
+    /// The compression byte of segments written with this option.
+    pub(crate) fn marker(self) -> u8 {
+        match self {
+            CompressionOption::None => UNCOMPRESSED,
+            CompressionOption::Fast | CompressionOption::Best => ZSTD_BLOCKS,
+        }
+    }
+
+    /// Fail before anything is written if `flush` cannot write segments of `format`
+    /// compressed with this option.
+    pub(crate) fn check_writable(self, format: SegmentFormat) -> Result<(), EngineError> {
+        match self {
+            CompressionOption::None => Ok(()),
+            _ if format == SegmentFormat::Json => Err(EngineError::InvalidArgument(
+                "JSON segments are written uncompressed; compression needs binary segments".into(),
+            )),
+            _ if !cfg!(feature = "compression") => Err(without_compression("flushing compressed segments")),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn without_compression(what: &str) -> EngineError {
+    EngineError::NotImplemented(format!("{}: compiled without compression support (feature `compression`)", what))
+}
+
+/// `input`, a binary segment, with the part after its compression byte decompressed. The
+/// bytes before are read again from the result, for the checksum.
+pub(crate) fn decompressed<'a>(input: impl Read + 'a, segment: &str) -> Result<Box<dyn Read + 'a>, EngineError> {
+    let (version, mut input) = segment_version(input, segment)?;
+    if !(COMPRESSION_SINCE..=FORMAT_VERSION).contains(&version) {
+        return Ok(Box::new(input));
+    }
+    // The magic, the version and the compression byte
+    let mut head = [0; 9];
+    input.read_exact(&mut head).map_err(|e| read_error(segment, e))?;
+    match head[8] {
+        UNCOMPRESSED => Ok(Box::new(io::Cursor::new(head).chain(input))),
+        #[cfg(feature = "compression")]
+        ZSTD_BLOCKS => Ok(Box::new(io::Cursor::new(head).chain(BlockReader::new(input, segment)))),
+        #[cfg(not(feature = "compression"))]
+        ZSTD_BLOCKS => Err(without_compression(&format!("segment {} is compressed", segment))),
+        other => Err(corruption(segment, format!("unknown compression {:#04x}", other))),
+    }
+}
+
+/// Compresses what is written to it into blocks of `BLOCK_SIZE`, the last one on `flush`.
+#[cfg(feature = "compression")]
+pub(crate) struct BlockWriter<W> {
+    inner: W,
+    option: CompressionOption,
+    block: Vec<u8>,
+}
+
+#[cfg(feature = "compression")]
+impl<W: io::Write> BlockWriter<W> {
+    pub(crate) fn new(inner: W, option: CompressionOption) -> Self {
+        Self { inner, option, block: Vec::with_capacity(BLOCK_SIZE) }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        let level = match self.option {
+            CompressionOption::Best => BEST_LEVEL,
+            _ => FAST_LEVEL,
+        };
+        let compressed = zstd::bulk::compress(&self.block, level)?;
+        self.inner.write_all(&(self.block.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&(compressed.len() as u32).to_le_bytes())?;
+        self.inner.write_all(&crc32fast::hash(&compressed).to_le_bytes())?;
+        self.inner.write_all(&compressed)?;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<W: io::Write> io::Write for BlockWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let taken = bytes.len().min(BLOCK_SIZE - self.block.len());
+        self.block.extend_from_slice(&bytes[..taken]);
+        if self.block.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        self.inner.flush()
+    }
+}
+
+/// Reads the blocks `BlockWriter` wrote back as the bytes written to it. Damaged blocks
+/// fail the read with `InvalidData`, wrapping an `EngineError::SegmentCorruption`.
+#[cfg(feature = "compression")]
+struct BlockReader<R> {
+    inner: R,
+    segment: String,
+    block: Vec<u8>,
+    at: usize,
+    compressed: Vec<u8>,
+}
+
+#[cfg(feature = "compression")]
+impl<R: Read> BlockReader<R> {
+    fn new(inner: R, segment: &str) -> Self {
+        Self { inner, segment: segment.to_string(), block: Vec::new(), at: 0, compressed: Vec::new() }
+    }
+
+    fn corrupt(&self, detail: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, corruption(&self.segment, detail))
+    }
+
+    /// Read the next block; false at the end of the input.
+    fn next_block(&mut self) -> io::Result<bool> {
+        let mut header = [0; 12];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.inner.read(&mut header[filled..])? {
+                0 if filled == 0 => return Ok(false),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => filled += n,
+            }
+        }
+        let raw_len = u32::from_le_bytes(header[..4].try_into().expect("4 bytes")) as usize;
+        let len = u32::from_le_bytes(header[4..8].try_into().expect("4 bytes")) as u64;
+        let checksum = u32::from_le_bytes(header[8..].try_into().expect("4 bytes"));
+        if raw_len == 0 || raw_len > BLOCK_SIZE {
+            return Err(self.corrupt("compressed block of impossible length"));
+        }
+        self.compressed.clear();
+        // Grows with the bytes actually there, whatever a damaged length claims
+        (&mut self.inner).take(len).read_to_end(&mut self.compressed)?;
+        if self.compressed.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if crc32fast::hash(&self.compressed) != checksum {
+            return Err(self.corrupt("compressed block checksum mismatch"));
+        }
+        // Decompressing into more than the length recorded fails instead of allocating it
+        self.block = zstd::bulk::decompress(&self.compressed, raw_len).map_err(|_| self.corrupt("compressed block does not decompress"))?;
+        if self.block.len() != raw_len {
+            return Err(self.corrupt("compressed block decompresses to the wrong length"));
+        }
+        self.at = 0;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "compression")]
+impl<R: Read> Read for BlockReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.at == self.block.len() && !self.next_block()? {
+            return Ok(0);
+        }
+        let n = buf.len().min(self.block.len() - self.at);
+        buf[..n].copy_from_slice(&self.block[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
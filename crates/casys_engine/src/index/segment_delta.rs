@@ -0,0 +1,243 @@
+//! Delta segments, written by `flush_incremental`
+//!
+//! A store loaded from segments, or written by `flush_incremental`, notes in a
+//! `ChangeSet` the id of every node and edge added, changed or removed since. The next
+//! `flush_incremental` writes just those as a pair of delta segments, `nodes-<seq>` and
+//! `edges-<seq>` (`seq` zero-padded to six digits). They use the binary segment layout
+//! with their own record kinds, and after the header fields of a full segment hold the
+//! ids removed (a u32 count, then u64 LE each) before the current record of every other
+//! id. Deltas are binary whatever `StoreOptions::segment_format` says, and compressed as
+//! `StoreOptions::segment_compression` says.
+//!
+//! The delta manifest (`DELTA_MANIFEST_ID`) lists the deltas in the order written:
+//! `{"format_version": 1, "next": <seq>, "deltas": [<seq>, ...]}`. Loading applies them
+//! over the base node and edge segments in that order, so the last write of an id wins
+//! and a removal drops it, before reading the index and statistics segments. Those are
+//! rewritten whole by every flush, incremental or not, and describe the latest state.
+//!
+//! A full `flush` writes a new base and empties the list, as `compact_segments` does for
+//! segments no store holds in memory. The deltas it drops stay in the segment store,
+//! listed nowhere, until `compact_segments_on_fs` retires them with the rest of what the
+//! new base supersedes.
+
+use super::index_segment::INDEX_SEGMENT_ID;
+use super::persistence::{EDGE_SEGMENT_ID, NODE_SEGMENT_ID};
+use super::segment_binary::{corruption, SegmentFormat};
+use super::segment_partition::PartitionManifest;
+use super::statistics::STATISTICS_SEGMENT_ID;
+use super::{InMemoryGraphStore, StoreOptions};
+use crate::types::{DatabaseName, EngineError};
+use casys_core::{EdgeId, NodeId, SegmentId, SegmentStore};
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::BufReader;
+use std::path::Path;
+
+pub(crate) const DELTA_MANIFEST_ID: &str = "deltas";
+
+/// Layout version of the delta manifest; loading rejects any other version.
+const FORMAT_VERSION: u64 = 1;
+
+/// Ids of the nodes and edges added, changed or removed since the last delta.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChangeSet {
+    pub(crate) nodes: HashSet<NodeId>,
+    pub(crate) edges: HashSet<EdgeId>,
+}
+
+/// The deltas to apply over the base segments, in order, and the number of the next one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DeltaManifest {
+    pub(crate) next: u64,
+    pub(crate) deltas: Vec<u64>,
+}
+
+impl Default for DeltaManifest {
+    fn default() -> Self {
+        Self { next: 1, deltas: Vec::new() }
+    }
+}
+
+impl DeltaManifest {
+    /// The manifest in `store`, or `None` if no delta was ever written there.
+    pub(crate) fn read(store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<Option<Self>, EngineError> {
+        let data = match store.read_segment(root, db, &SegmentId(DELTA_MANIFEST_ID.to_string())) {
+            Ok((data, _node_count, _edge_count)) => data,
+            Err(EngineError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let corrupt = |detail: &str| corruption(DELTA_MANIFEST_ID, detail);
+        let json: serde_json::Value = serde_json::from_slice(&data).map_err(|e| corrupt(&e.to_string()))?;
+        if json["format_version"].as_u64() != Some(FORMAT_VERSION) {
+            return Err(corrupt(&format!("unsupported delta manifest format version {}", json["format_version"])));
+        }
+        let next = json["next"].as_u64().ok_or_else(|| corrupt("missing `next`"))?;
+        let deltas = json["deltas"]
+            .as_array()
+            .ok_or_else(|| corrupt("missing `deltas`"))?
+            .iter()
+            .map(|seq| seq.as_u64().filter(|seq| *seq < next).ok_or_else(|| corrupt("bad delta number")))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Self { next, deltas }))
+    }
+
+    /// Stage the manifest, for `publish_segments` to make current with the segments it lists.
+    pub(crate) fn stage(&self, store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        let data = serde_json::to_vec(&json!({ "format_version": FORMAT_VERSION, "next": self.next, "deltas": self.deltas }))
+            .map_err(|e| EngineError::StorageIo(format!("serialize delta manifest: {}", e)))?;
+        store.stage_segment_with(root, db, &SegmentId(DELTA_MANIFEST_ID.to_string()), 0, 0, &mut |out| out.write_all(&data))
+    }
+}
+
+/// Segment id of node delta `seq`.
+pub(crate) fn node_delta_id(seq: u64) -> String {
+    format!("{}-{:06}", NODE_SEGMENT_ID, seq)
+}
+
+/// Segment id of edge delta `seq`.
+pub(crate) fn edge_delta_id(seq: u64) -> String {
+    format!("{}-{:06}", EDGE_SEGMENT_ID, seq)
+}
+
+/// Whether `id` names a node or edge delta.
+#[cfg(feature = "fs")]
+pub(crate) fn is_delta_id(id: &str) -> bool {
+    [NODE_SEGMENT_ID, EDGE_SEGMENT_ID].iter().any(|base| {
+        id.strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix('-'))
+            .is_some_and(|seq| seq.len() >= 6 && seq.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+/// Outcome of `InMemoryGraphStore::compact_segments_on_fs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Segment files before compaction, current or superseded
+    pub files_before: u64,
+    /// Segment files after
+    pub files_after: u64,
+    /// Bytes of the segment files before
+    pub bytes_before: u64,
+    /// Bytes of the segment files after
+    pub bytes_after: u64,
+    /// Node and edge records the current segments held before, counting every record of
+    /// the base, of each delta and of each delta a full flush dropped
+    pub records_before: u64,
+    /// Node and edge records of the new base
+    pub records_after: u64,
+    /// Bytes of the WAL files before
+    pub wal_bytes_before: u64,
+    /// Bytes of the WAL files after
+    pub wal_bytes_after: u64,
+    /// WAL records replayed over the segments and now held by the new base
+    pub wal_records_folded: u64,
+}
+
+impl InMemoryGraphStore {
+    /// Note node `id` for the next delta, if changes are tracked.
+    pub(crate) fn node_changed(&mut self, id: NodeId) {
+        if let Some(changes) = &mut self.changes {
+            changes.nodes.insert(id);
+        }
+    }
+
+    /// Note edge `id` for the next delta, if changes are tracked.
+    pub(crate) fn edge_changed(&mut self, id: EdgeId) {
+        if let Some(changes) = &mut self.changes {
+            changes.edges.insert(id);
+        }
+    }
+
+    /// Write only the nodes and edges added, changed or removed since the store was
+    /// loaded or last flushed incrementally, as a new delta appended to the delta manifest
+    /// (see `segment_delta`), together with the index and statistics segments. Everything
+    /// is published at once, as by `flush`.
+    ///
+    /// A store holding no segments yet, neither loaded nor flushed incrementally, is
+    /// written whole with `flush` instead, and tracks its changes from then on. Deltas
+    /// must go to the segments the store was loaded from or flushed to.
+    ///
+    /// # Errors
+    /// As `flush`.
+    pub fn flush_incremental(&mut self, store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        self.check_complete()?;
+        let Some(changes) = &self.changes else {
+            self.flush(store, root, db)?;
+            self.changes = Some(ChangeSet::default());
+            return Ok(());
+        };
+        let compression = self.options.segment_compression;
+        compression.check_writable(SegmentFormat::Binary)?;
+        let mut manifest = DeltaManifest::read(store, root, db)?.unwrap_or_default();
+        let seq = manifest.next;
+        manifest.next += 1;
+        manifest.deltas.push(seq);
+
+        let (nodes, edges) = (node_delta_id(seq), edge_delta_id(seq));
+        store.stage_segment_with(root, db, &SegmentId(nodes.clone()), changes.nodes.len() as u64, 0, &mut |out| {
+            self.write_binary_node_delta(out, compression, &changes.nodes)
+        })?;
+        store.stage_segment_with(root, db, &SegmentId(edges.clone()), 0, changes.edges.len() as u64, &mut |out| {
+            self.write_binary_edge_delta(out, compression, &changes.edges)
+        })?;
+        self.stage_index_and_statistics(store, root, db)?;
+        manifest.stage(store, root, db)?;
+        let ids = [nodes.as_str(), edges.as_str(), INDEX_SEGMENT_ID, STATISTICS_SEGMENT_ID, DELTA_MANIFEST_ID];
+        store.publish_segments(root, db, &ids.map(|id| SegmentId(id.to_string())))?;
+
+        self.changes = Some(ChangeSet::default());
+        Ok(())
+    }
+
+    /// Fold the deltas in `store` into a fresh base: load the segments with every delta
+    /// applied, then `flush` them, which publishes the new base with an empty delta
+    /// manifest at once. A store held in memory compacts by calling `flush` itself.
+    ///
+    /// The base is written binary and uncompressed, partitioned by label if it was, and
+    /// free ids carry over.
+    pub fn compact_segments(store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        let options = Self::compaction_options(store, root, db)?;
+        Self::load_with_options(store, root, db, options)?.flush(store, root, db)
+    }
+
+    /// Options to rewrite the segments in `store` with, as `compact_segments` does.
+    pub(crate) fn compaction_options(store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<StoreOptions, EngineError> {
+        let partitioned = PartitionManifest::read(store, root, db)?.is_some_and(|m| m.partitioned);
+        Ok(StoreOptions { reuse_ids: true, partition_nodes_by_label: partitioned, ..Default::default() })
+    }
+
+    /// Apply the deltas the manifest in `store` lists over the loaded base, in order.
+    pub(crate) fn load_deltas(&mut self, store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        let Some(manifest) = DeltaManifest::read(store, root, db)? else { return Ok(()) };
+        for seq in manifest.deltas {
+            self.load_deltas_of(store, root, db, &[seq], true)?;
+            self.load_deltas_of(store, root, db, &[seq], false)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the node deltas, or the edge deltas, numbered `seqs` in order.
+    pub(crate) fn load_deltas_of(
+        &mut self,
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        seqs: &[u64],
+        nodes: bool,
+    ) -> Result<(), EngineError> {
+        for seq in seqs {
+            let id = if nodes { node_delta_id(*seq) } else { edge_delta_id(*seq) };
+            let input = match store.open_segment(root, db, &SegmentId(id.clone())) {
+                Ok((input, _node_count, _edge_count)) => BufReader::new(input),
+                Err(EngineError::NotFound(_)) => return Err(corruption(&id, "listed in the delta manifest but missing")),
+                Err(e) => return Err(e),
+            };
+            if nodes {
+                self.read_binary_node_delta(input, &id)?;
+            } else {
+                self.read_binary_edge_delta(input, &id)?;
+            }
+        }
+        Ok(())
+    }
+}
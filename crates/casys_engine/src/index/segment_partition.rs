@@ -0,0 +1,323 @@
+//! Node segments partitioned by label, and loading some labels only
+//!
+//! With `StoreOptions::partition_nodes_by_label`, `flush` writes the nodes as one binary
+//! node segment per label instead of the `nodes` segment: `nodes-label-<hash>`, the hash
+//! being 64-bit FNV-1a of the label in hex. A node goes to the partition of its first
+//! label, and nodes without labels to `nodes-unlabeled`, which is written even when empty.
+//! Every partition carries the id allocator state, the `last_applied_lsn` and the
+//! `next_txn_id` of the store, so any one of them restores those. Partitions are binary
+//! whatever `StoreOptions::segment_format` says, and compressed as
+//! `StoreOptions::segment_compression` says.
+//!
+//! The partition manifest (`PARTITION_MANIFEST_ID`) maps each first label to its
+//! partition, and each label some node carries after its first to the first labels of
+//! those nodes, whose partitions it must read too:
+//! `{"format_version": 1, "partitioned": true, "partitions": {"<label>": "<segment>", ...},
+//! "aliases": {"<label>": ["<first label>", ...], ...}}`. A flush without partitioning
+//! over partitioned segments writes `"partitioned": false` with the `nodes` segment, and
+//! the partitions it replaces stay in the segment store, listed nowhere, until
+//! `compact_segments_on_fs` retires them. Two labels whose hashes collide share a
+//! partition.
+//!
+//! `load_labels` loads the nodes carrying any of some labels: from their partitions and
+//! those they alias, or from the whole `nodes` segment if it is not partitioned, and from
+//! the deltas over them (see `segment_delta`). Edges are read whole and kept by
+//! `PartialEdges`. Indexes are backfilled from the nodes loaded, whatever
+//! `StoreOptions::persist_index_data` wrote, and statistics, which describe the whole
+//! graph, are not loaded. The store knows it is partial: `flush` and `flush_incremental`
+//! refuse it, as they would write it over the segments of the whole graph.
+
+use super::index_segment::INDEX_SEGMENT_ID;
+use super::persistence::{EDGE_SEGMENT_ID, NODE_SEGMENT_ID};
+use super::segment_binary::{corruption, segment_version};
+use super::segment_delta::DeltaManifest;
+use super::{Edge, InMemoryGraphStore, Node, StoreOptions};
+use crate::types::{DatabaseName, EngineError};
+use casys_core::{NodeId, SegmentId, SegmentStore};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+pub(crate) const PARTITION_MANIFEST_ID: &str = "partitions";
+
+/// Partition of the nodes without labels.
+pub(crate) const UNLABELED_PARTITION_ID: &str = "nodes-unlabeled";
+
+/// Layout version of the partition manifest; loading rejects any other version.
+const FORMAT_VERSION: u64 = 1;
+
+/// What `InMemoryGraphStore::load_labels` does with an edge whose endpoints are not both
+/// loaded. Edges with neither endpoint loaded are always left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PartialEdges {
+    /// Leave it out: the store holds the edges between the nodes loaded.
+    #[default]
+    Skip,
+    /// Load it, with a stub for the endpoint not loaded: a node with its id but no labels
+    /// or properties, told apart by `InMemoryGraphStore::is_stub`. Edges are not followed
+    /// from stubs.
+    Stubs,
+}
+
+/// What a store loaded by `load_labels` holds of the graph.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PartialLoad {
+    labels: HashSet<String>,
+    edges: PartialEdges,
+    stubs: HashSet<NodeId>,
+}
+
+/// Where `flush` put the nodes, as the partition manifest records it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PartitionManifest {
+    pub(crate) partitioned: bool,
+    /// Partition of each first label
+    pub(crate) partitions: BTreeMap<String, String>,
+    /// First labels of the nodes carrying each label after their first
+    pub(crate) aliases: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl PartitionManifest {
+    /// The manifest in `store`, or `None` if nodes were never partitioned there.
+    pub(crate) fn read(store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<Option<Self>, EngineError> {
+        let data = match store.read_segment(root, db, &SegmentId(PARTITION_MANIFEST_ID.to_string())) {
+            Ok((data, _node_count, _edge_count)) => data,
+            Err(EngineError::NotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let corrupt = |detail: &str| corruption(PARTITION_MANIFEST_ID, detail);
+        let json: serde_json::Value = serde_json::from_slice(&data).map_err(|e| corrupt(&e.to_string()))?;
+        if json["format_version"].as_u64() != Some(FORMAT_VERSION) {
+            return Err(corrupt(&format!("unsupported partition manifest format version {}", json["format_version"])));
+        }
+        let partitioned = json["partitioned"].as_bool().ok_or_else(|| corrupt("missing `partitioned`"))?;
+        let partitions = serde_json::from_value(json["partitions"].clone()).map_err(|_| corrupt("bad `partitions`"))?;
+        let aliases = serde_json::from_value(json["aliases"].clone()).map_err(|_| corrupt("bad `aliases`"))?;
+        Ok(Some(Self { partitioned, partitions, aliases }))
+    }
+
+    /// Stage the manifest, for `publish_segments` to make current with the partitions.
+    pub(crate) fn stage(&self, store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        let data = serde_json::to_vec(&json!({
+            "format_version": FORMAT_VERSION,
+            "partitioned": self.partitioned,
+            "partitions": self.partitions,
+            "aliases": self.aliases,
+        }))
+        .map_err(|e| EngineError::StorageIo(format!("serialize partition manifest: {}", e)))?;
+        store.stage_segment_with(root, db, &SegmentId(PARTITION_MANIFEST_ID.to_string()), 0, 0, &mut |out| out.write_all(&data))
+    }
+
+    /// Every partition, the unlabeled one included.
+    pub(crate) fn segments(&self) -> BTreeSet<String> {
+        let mut segments: BTreeSet<String> = self.partitions.values().cloned().collect();
+        segments.insert(UNLABELED_PARTITION_ID.to_string());
+        segments
+    }
+
+    /// The partitions holding the nodes that carry any of `labels`, and the unlabeled one
+    /// for the allocator state.
+    fn segments_for(&self, labels: &HashSet<String>) -> BTreeSet<String> {
+        let mut segments = BTreeSet::from([UNLABELED_PARTITION_ID.to_string()]);
+        for label in labels {
+            let firsts = self.aliases.get(label).into_iter().flatten();
+            segments.extend(std::iter::once(label).chain(firsts).filter_map(|first| self.partitions.get(first).cloned()));
+        }
+        segments
+    }
+}
+
+/// Segment id of the partition of the nodes whose first label is `label`.
+pub(crate) fn partition_id(label: &str) -> String {
+    // FNV-1a, which unlike `DefaultHasher` is the same in every build
+    let hash = label.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+    format!("{}-label-{:016x}", NODE_SEGMENT_ID, hash)
+}
+
+/// Whether `id` names a node partition.
+#[cfg(feature = "fs")]
+pub(crate) fn is_partition_id(id: &str) -> bool {
+    id == UNLABELED_PARTITION_ID || id.strip_prefix(NODE_SEGMENT_ID).is_some_and(|rest| rest.starts_with("-label-"))
+}
+
+impl InMemoryGraphStore {
+    /// Stage the node partitions and their manifest, and add their ids to `ids` for
+    /// `flush` to publish.
+    pub(crate) fn stage_partitions(
+        &self,
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        ids: &mut Vec<String>,
+    ) -> Result<(), EngineError> {
+        let mut manifest = PartitionManifest { partitioned: true, ..Default::default() };
+        let mut members: BTreeMap<String, Vec<NodeId>> = BTreeMap::from([(UNLABELED_PARTITION_ID.to_string(), Vec::new())]);
+        for node in self.nodes.values() {
+            let segment = match node.labels.split_first() {
+                Some((first, rest)) => {
+                    for label in rest {
+                        manifest.aliases.entry(label.clone()).or_default().insert(first.clone());
+                    }
+                    manifest.partitions.entry(first.clone()).or_insert_with(|| partition_id(first)).clone()
+                }
+                None => UNLABELED_PARTITION_ID.to_string(),
+            };
+            members.entry(segment).or_default().push(node.id);
+        }
+
+        let compression = self.options.segment_compression;
+        for (segment, mut members) in members {
+            members.sort_unstable();
+            store.stage_segment_with(root, db, &SegmentId(segment.clone()), members.len() as u64, 0, &mut |out| {
+                self.write_binary_node_partition(out, compression, &members)
+            })?;
+            ids.push(segment);
+        }
+        manifest.stage(store, root, db)?;
+        ids.push(PARTITION_MANIFEST_ID.to_string());
+        Ok(())
+    }
+
+    /// Read the node partitions `segments` into the store.
+    pub(crate) fn load_partitions(
+        &mut self,
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        segments: &BTreeSet<String>,
+    ) -> Result<(), EngineError> {
+        for segment in segments {
+            let input = match store.open_segment(root, db, &SegmentId(segment.clone())) {
+                Ok((input, _node_count, _edge_count)) => BufReader::new(input),
+                Err(EngineError::NotFound(_)) => return Err(corruption(segment, "listed in the partition manifest but missing")),
+                Err(e) => return Err(e),
+            };
+            self.read_binary_nodes(input, segment)?;
+        }
+        Ok(())
+    }
+
+    /// Load the nodes carrying any of `labels` from the segments in `store`, and the
+    /// edges between them, or reaching them as `edges` says; see `segment_partition`. The
+    /// fewer nodes the partitions read hold beyond those, the faster: with unpartitioned
+    /// segments the whole node segment is read.
+    ///
+    /// The store is partial: see `is_partial`.
+    ///
+    /// # Errors
+    /// As `load_with_options`. `InvalidArgument` if the node or edge segment is JSON,
+    /// which this does not read; `migrate_segments` them to binary first.
+    pub fn load_labels(
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        labels: &[&str],
+        edges: PartialEdges,
+        options: StoreOptions,
+    ) -> Result<Self, EngineError> {
+        let mut graph = Self::with_options(options);
+        let labels: HashSet<String> = labels.iter().map(|label| label.to_string()).collect();
+        graph.partial = Some(PartialLoad { labels, edges, stubs: HashSet::new() });
+
+        match PartitionManifest::read(store, root, db)?.filter(|m| m.partitioned) {
+            Some(manifest) => {
+                let segments = manifest.segments_for(&graph.partial.as_ref().expect("set above").labels);
+                graph.load_partitions(store, root, db, &segments)?;
+            }
+            None => {
+                if let Some(input) = open_binary(store, root, db, NODE_SEGMENT_ID)? {
+                    graph.read_binary_nodes(input, NODE_SEGMENT_ID)?;
+                }
+            }
+        }
+        // Every node before any edge, so edges are kept by the nodes finally loaded
+        let deltas = DeltaManifest::read(store, root, db)?.map(|m| m.deltas).unwrap_or_default();
+        graph.load_deltas_of(store, root, db, &deltas, true)?;
+        if let Some(input) = open_binary(store, root, db, EDGE_SEGMENT_ID)? {
+            graph.read_binary_edges(input, EDGE_SEGMENT_ID)?;
+        }
+        graph.load_deltas_of(store, root, db, &deltas, false)?;
+
+        match store.read_segment(root, db, &SegmentId(INDEX_SEGMENT_ID.to_string())) {
+            Ok((data, _node_count, _edge_count)) => graph.deserialize_indexes(&data)?,
+            Err(EngineError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        graph.verify_loaded()?;
+        Ok(graph)
+    }
+
+    /// Whether the store was loaded by `load_labels`, and so holds only part of the
+    /// graph. A partial store is for reading: changes to it are kept in memory, and
+    /// logged to an attached WAL, but `flush` and `flush_incremental` refuse it.
+    pub fn is_partial(&self) -> bool {
+        self.partial.is_some()
+    }
+
+    /// Whether node `id` is a stub that `load_labels` made for the endpoint of an edge;
+    /// see `PartialEdges::Stubs`.
+    pub fn is_stub(&self, id: NodeId) -> bool {
+        self.partial.as_ref().is_some_and(|partial| partial.stubs.contains(&id))
+    }
+
+    /// Fail unless the store holds the whole graph, before a flush writes it as such.
+    pub(crate) fn check_complete(&self) -> Result<(), EngineError> {
+        match &self.partial {
+            None => Ok(()),
+            Some(partial) => {
+                let mut labels: Vec<&str> = partial.labels.iter().map(String::as_str).collect();
+                labels.sort_unstable();
+                Err(EngineError::InvalidArgument(format!(
+                    "the store holds the nodes labelled {} only; flushing it would drop the rest of the graph",
+                    labels.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Whether a partial load keeps `node`: it carries a label asked for.
+    pub(crate) fn keeps_node(&self, node: &Node) -> bool {
+        self.partial.as_ref().is_none_or(|partial| node.labels.iter().any(|label| partial.labels.contains(label)))
+    }
+
+    /// Whether a partial load keeps `edge`, by `PartialEdges`; a stub is made for an
+    /// endpoint it keeps without.
+    pub(crate) fn keeps_edge(&mut self, edge: &Edge) -> bool {
+        let Some(partial) = &mut self.partial else { return true };
+        let loaded = |id: NodeId| self.nodes.contains_key(&id) && !partial.stubs.contains(&id);
+        let missing: Vec<NodeId> = [edge.from_node, edge.to_node].into_iter().filter(|id| !loaded(*id)).collect();
+        match (missing.len(), partial.edges) {
+            (0, _) => true,
+            (1, PartialEdges::Stubs) => {
+                let id = missing[0];
+                if partial.stubs.insert(id) {
+                    self.insert_node(Node { id, labels: Vec::new(), properties: HashMap::new(), deleted: false });
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Open binary segment `segment`, or `None` if there is none.
+fn open_binary<'a>(
+    store: &'a dyn SegmentStore,
+    root: &Path,
+    db: &DatabaseName,
+    segment: &str,
+) -> Result<Option<impl Read + 'a>, EngineError> {
+    let input = match store.open_segment(root, db, &SegmentId(segment.to_string())) {
+        Ok((input, _node_count, _edge_count)) => BufReader::new(input),
+        Err(EngineError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match segment_version(input, segment)? {
+        (0, _) => Err(EngineError::InvalidArgument(format!(
+            "segment {} is JSON; partial loads read binary segments only, migrate_segments first",
+            segment
+        ))),
+        (_, input) => Ok(Some(input)),
+    }
+}
@@ -0,0 +1,115 @@
+//! Adjacency lists kept in order, when `StoreOptions::sorted_adjacency` is on
+//!
+//! `adjacency_out[node]` is then sorted by `(edge_type, to_node, edge id)` and
+//! `adjacency_in[node]` by `(edge_type, from_node, edge id)`. Linking an edge end is a
+//! binary-search insert; the rarer writes that change an edge's type or far end (retyping,
+//! moving or reversing an edge, merging nodes) re-sort the lists they touch. With the
+//! option off every list stays in insertion order and linking is a plain push.
+//! `get_neighbors_ordered` works either way; on a sorted store its `ById` order is a merge
+//! of the per-type runs instead of a full sort.
+
+use super::{Edge, EdgeId, EngineError, InMemoryGraphStore, Node, NodeId, Value};
+use casys_core::OrderedValue;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// The order `get_neighbors_ordered` returns outgoing neighbors in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NeighborOrder {
+    /// By neighbor id, parallel edges by edge id
+    ById,
+    /// By the value of this edge property: numbers (`Int` and `Float` by value) before
+    /// strings; edges without the property, or with a value of another type, come last.
+    /// Ties keep `ById` order.
+    ByEdgeProperty(String),
+}
+
+impl InMemoryGraphStore {
+    /// The outgoing `(edge, neighbor)` pairs of `node_id` in `order`. An unknown node has
+    /// no neighbors.
+    pub fn get_neighbors_ordered(&self, node_id: NodeId, order: NeighborOrder) -> Result<Vec<(Edge, Node)>, EngineError> {
+        let mut pairs: Vec<(&Edge, &Node)> = self.adjacency_out.get(&node_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.edges.get(id))
+            .filter_map(|edge| self.nodes.get(&edge.to_node).map(|node| (edge, node)))
+            .collect();
+        // Stable sorts merge already sorted runs cheaply, which is what a sorted list holds
+        pairs.sort_by_key(|(edge, _)| (edge.to_node, edge.id));
+        if let NeighborOrder::ByEdgeProperty(key) = &order {
+            pairs.sort_by(|(a, _), (b, _)| compare_property(a.properties.get(key), b.properties.get(key)));
+        }
+        Ok(pairs.into_iter().map(|(edge, node)| (edge.clone(), node.clone())).collect())
+    }
+
+    /// Add live edge `id` to the outgoing list of its source and the incoming list of its
+    /// target. The edge record must already be stored.
+    pub(crate) fn link_adjacency(&mut self, id: EdgeId) {
+        self.link_adjacency_end(id, true);
+        self.link_adjacency_end(id, false);
+    }
+
+    /// Add live edge `id` to one list: `adjacency_out[from]` when `outgoing`, otherwise
+    /// `adjacency_in[to]`.
+    pub(crate) fn link_adjacency_end(&mut self, id: EdgeId, outgoing: bool) {
+        let Some(edge) = self.edges.get(&id) else { return };
+        let (node, adjacency) = if outgoing {
+            (edge.from_node, &mut self.adjacency_out)
+        } else {
+            (edge.to_node, &mut self.adjacency_in)
+        };
+        let list = adjacency.entry(node).or_default();
+        if self.options.sorted_adjacency {
+            let key = adjacency_key(&self.edges, id, outgoing);
+            let pos = list.partition_point(|e| adjacency_key(&self.edges, *e, outgoing) < key);
+            list.insert(pos, id);
+        } else {
+            list.push(id);
+        }
+    }
+
+    /// Restore the order of both lists of every node in `nodes`, after writes that changed
+    /// the type or far end of edges already linked. A no-op unless `sorted_adjacency`.
+    pub(crate) fn resort_adjacency(&mut self, nodes: impl IntoIterator<Item = NodeId>) {
+        if !self.options.sorted_adjacency {
+            return;
+        }
+        for node in nodes {
+            for (adjacency, outgoing) in [(&mut self.adjacency_out, true), (&mut self.adjacency_in, false)] {
+                if let Some(list) = adjacency.get_mut(&node) {
+                    list.sort_by_key(|e| adjacency_key(&self.edges, *e, outgoing));
+                }
+            }
+        }
+    }
+
+    /// Sort every adjacency list, after a rebuild. A no-op unless `sorted_adjacency`.
+    pub(crate) fn resort_all_adjacency(&mut self) {
+        if !self.options.sorted_adjacency {
+            return;
+        }
+        for (adjacency, outgoing) in [(&mut self.adjacency_out, true), (&mut self.adjacency_in, false)] {
+            for list in adjacency.values_mut() {
+                list.sort_by_key(|e| adjacency_key(&self.edges, *e, outgoing));
+            }
+        }
+    }
+}
+
+/// Sort key of edge `id` in a list of its source (`outgoing`) or of its target.
+fn adjacency_key(edges: &HashMap<EdgeId, Edge>, id: EdgeId, outgoing: bool) -> (&str, NodeId, EdgeId) {
+    match edges.get(&id) {
+        Some(edge) => (edge.edge_type.as_str(), if outgoing { edge.to_node } else { edge.from_node }, id),
+        None => ("", 0, id),
+    }
+}
+
+/// `ByEdgeProperty` order of two property values.
+fn compare_property(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a.and_then(OrderedValue::from_value), b.and_then(OrderedValue::from_value)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
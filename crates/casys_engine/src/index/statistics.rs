@@ -0,0 +1,369 @@
+//! Sampled value statistics per `(label, key)`, for query planning and manual tuning
+//!
+//! `collect_statistics` reads the properties of a sample of the live nodes: a node is in
+//! the sample when a hash of its id falls below `sample_rate`, so the same store yields
+//! the same sample on every run, whatever the insertion order. For every `(label, key)`
+//! seen in the sample it records how many sampled nodes set the key, the distinct values
+//! among them, the smallest and largest orderable values and a histogram of the numeric
+//! ones, then scales the counts to the whole store.
+//!
+//! Values are only held as 64-bit hashes, so memory does not grow with the size of the
+//! values: a `(label, key)` counts each distinct hash exactly up to
+//! `EXACT_DISTINCT_LIMIT` of them, then switches to a HyperLogLog sketch of
+//! `SKETCH_REGISTERS` one-byte registers (about 1.6% standard error). Distinct counts
+//! scale to the store with the Haas-Stokes `Duj1` estimator `n·d / (n - f1 + f1·n/N)`,
+//! where the `n` sampled values of an estimated `N` hold `d` distinct values, `f1` of them
+//! seen once. Past the exact limit `f1` is unknown and `d` is scaled by `N / n`, which
+//! is right for the mostly-unique values that get there.
+//!
+//! Statistics are a snapshot: writes do not update them until the next
+//! `collect_statistics`. `flush` writes them to their own segment so a loaded store
+//! starts with them; `truncate` drops them.
+
+use super::index_segment::{decode_ordered, encode_ordered};
+use super::{InMemoryGraphStore, NodeId, Value};
+use crate::types::EngineError;
+use casys_core::{OrderedValue, ValueKey};
+use serde_json::{json, Value as Json};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+
+pub(crate) const STATISTICS_SEGMENT_ID: &str = "statistics";
+
+/// Layout version of the statistics segment; loading rejects any other version.
+const FORMAT_VERSION: u64 = 1;
+
+/// Distinct value hashes a `(label, key)` counts exactly before switching to a sketch.
+const EXACT_DISTINCT_LIMIT: usize = 512;
+
+/// Bits of a value hash that pick a sketch register.
+const SKETCH_BITS: u32 = 12;
+const SKETCH_REGISTERS: usize = 1 << SKETCH_BITS;
+
+/// Buckets of a numeric histogram.
+pub const HISTOGRAM_BUCKETS: usize = 16;
+
+/// What `collect_statistics` found, per `(label, key)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphStatistics {
+    /// The rate the sample was drawn at, after clamping to `0.0..=1.0`
+    pub sample_rate: f64,
+    /// Live nodes when the statistics were collected
+    pub node_count: u64,
+    /// Nodes in the sample
+    pub sampled_nodes: u64,
+    /// Every `(label, key)` set on a sampled node
+    pub properties: BTreeMap<(String, String), PropertyStatistics>,
+}
+
+/// Statistics of one `(label, key)`. The `sampled_*` fields and the histogram describe
+/// the sample; the `estimated_*` ones are scaled to the whole store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyStatistics {
+    /// Sampled nodes carrying the label and setting the key
+    pub sampled_values: u64,
+    /// Distinct values among them (estimated past `EXACT_DISTINCT_LIMIT`)
+    pub sampled_distinct: u64,
+    /// Live nodes carrying the label and setting the key
+    pub estimated_count: u64,
+    /// Distinct values among those nodes
+    pub estimated_distinct: u64,
+    /// Smallest and largest sampled values in `OrderedValue` order (numbers before
+    /// strings); `None` if no sampled value is orderable
+    pub min: Option<OrderedValue>,
+    pub max: Option<OrderedValue>,
+    /// Sampled `Int` and `Float` values over their range, if there were any
+    pub histogram: Option<Histogram>,
+}
+
+/// Equal-width buckets over `low..=high`: bucket `i` covers
+/// `[low + i·w, low + (i + 1)·w)` with `w = (high - low) / HISTOGRAM_BUCKETS`, the last
+/// one including `high`. When `low == high` every value is in the first bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub low: f64,
+    pub high: f64,
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn bucket(&self, x: f64) -> usize {
+        if self.high <= self.low {
+            return 0;
+        }
+        let at = (x - self.low) / (self.high - self.low) * HISTOGRAM_BUCKETS as f64;
+        (at as usize).min(HISTOGRAM_BUCKETS - 1)
+    }
+}
+
+/// Distinct values seen so far, by hash.
+enum DistinctCounter {
+    /// Occurrences of each hash
+    Exact(HashMap<u64, u64>),
+    /// HyperLogLog registers: the longest run of leading zeros seen, plus one
+    Sketch(Box<[u8; SKETCH_REGISTERS]>),
+}
+
+impl DistinctCounter {
+    fn insert(&mut self, hash: u64) {
+        match self {
+            DistinctCounter::Exact(counts) => {
+                *counts.entry(hash).or_default() += 1;
+                if counts.len() > EXACT_DISTINCT_LIMIT {
+                    let mut registers = Box::new([0u8; SKETCH_REGISTERS]);
+                    for hash in counts.keys() {
+                        sketch_insert(&mut registers, *hash);
+                    }
+                    *self = DistinctCounter::Sketch(registers);
+                }
+            }
+            DistinctCounter::Sketch(registers) => sketch_insert(registers, hash),
+        }
+    }
+
+    /// `(d, f1)`: distinct values, and values seen once when still counted exactly.
+    fn counts(&self) -> (f64, Option<f64>) {
+        match self {
+            DistinctCounter::Exact(counts) => (counts.len() as f64, Some(counts.values().filter(|c| **c == 1).count() as f64)),
+            DistinctCounter::Sketch(registers) => (sketch_estimate(registers), None),
+        }
+    }
+}
+
+fn sketch_insert(registers: &mut [u8; SKETCH_REGISTERS], hash: u64) {
+    let register = (hash >> (64 - SKETCH_BITS)) as usize;
+    let rank = ((hash << SKETCH_BITS).leading_zeros() + 1).min(64 - SKETCH_BITS + 1) as u8;
+    registers[register] = registers[register].max(rank);
+}
+
+/// HyperLogLog estimate with the small-range (linear counting) correction; 64-bit hashes
+/// need no large-range one.
+fn sketch_estimate(registers: &[u8; SKETCH_REGISTERS]) -> f64 {
+    let m = SKETCH_REGISTERS as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let sum: f64 = registers.iter().map(|r| 2f64.powi(-i32::from(*r))).sum();
+    let estimate = alpha * m * m / sum;
+    let empty = registers.iter().filter(|r| **r == 0).count();
+    if estimate <= 2.5 * m && empty > 0 {
+        m * (m / empty as f64).ln()
+    } else {
+        estimate
+    }
+}
+
+/// Running statistics of one `(label, key)` during collection.
+struct Accumulator {
+    values: u64,
+    distinct: DistinctCounter,
+    min: Option<OrderedValue>,
+    max: Option<OrderedValue>,
+    numeric: Option<(f64, f64)>,
+}
+
+impl Accumulator {
+    fn new() -> Self {
+        Self { values: 0, distinct: DistinctCounter::Exact(HashMap::new()), min: None, max: None, numeric: None }
+    }
+
+    fn add(&mut self, value: &Value) {
+        self.values += 1;
+        self.distinct.insert(value_hash(value));
+        let Some(ordered) = OrderedValue::from_value(value) else { return };
+        if let OrderedValue::Number(x) = ordered {
+            self.numeric = Some(self.numeric.map_or((x, x), |(low, high)| (low.min(x), high.max(x))));
+        }
+        if self.min.as_ref().is_none_or(|min| ordered < *min) {
+            self.min = Some(ordered.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| ordered > *max) {
+            self.max = Some(ordered);
+        }
+    }
+}
+
+/// Whether node `id` is in a sample drawn at `rate`.
+fn sampled(id: NodeId, rate: f64) -> bool {
+    // splitmix64 finalizer: consecutive ids land far apart
+    let mut x = id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    ((x >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+fn value_hash(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ValueKey::from(value).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// `Duj1` estimate of the distinct values among `population` from `sample` values holding
+/// `distinct` of them, `once` seen a single time; see the module docs.
+fn scale_distinct(distinct: f64, once: Option<f64>, sample: f64, population: f64) -> u64 {
+    if sample == 0.0 {
+        return 0;
+    }
+    let estimate = match once {
+        Some(once) => sample * distinct / (sample - once + once * sample / population),
+        None => distinct * population / sample,
+    };
+    estimate.round().clamp(distinct.round(), population) as u64
+}
+
+impl InMemoryGraphStore {
+    /// Sample the live nodes at `sample_rate` (clamped to `0.0..=1.0`; NaN counts as
+    /// `1.0`) and replace the stored statistics with what the sample shows. `1.0` reads
+    /// every node and gives exact counts.
+    pub fn collect_statistics(&mut self, sample_rate: f64) -> GraphStatistics {
+        let rate = if sample_rate.is_nan() { 1.0 } else { sample_rate.clamp(0.0, 1.0) };
+        let sample = || self.nodes.values().filter(move |n| !n.deleted && sampled(n.id, rate));
+
+        let mut sampled_nodes = 0u64;
+        let mut per_label: HashMap<&str, u64> = HashMap::new();
+        let mut acc: BTreeMap<(&str, &str), Accumulator> = BTreeMap::new();
+        for node in sample() {
+            sampled_nodes += 1;
+            for label in &node.labels {
+                *per_label.entry(label.as_str()).or_default() += 1;
+                for (key, value) in &node.properties {
+                    acc.entry((label.as_str(), key.as_str())).or_insert_with(Accumulator::new).add(value);
+                }
+            }
+        }
+
+        // A second pass over the same sample, now that the numeric ranges are known
+        let mut histograms: BTreeMap<(&str, &str), Histogram> = acc.iter()
+            .filter_map(|(def, a)| a.numeric.map(|(low, high)| (*def, Histogram { low, high, counts: vec![0; HISTOGRAM_BUCKETS] })))
+            .collect();
+        if !histograms.is_empty() {
+            for node in sample() {
+                for label in &node.labels {
+                    for (key, value) in &node.properties {
+                        let Some(OrderedValue::Number(x)) = OrderedValue::from_value(value) else { continue };
+                        if let Some(histogram) = histograms.get_mut(&(label.as_str(), key.as_str())) {
+                            let bucket = histogram.bucket(x);
+                            histogram.counts[bucket] += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        let properties = acc.into_iter()
+            .map(|((label, key), a)| {
+                let labelled = self.label_index.get(label).map_or(0, Vec::len) as f64;
+                let count = (a.values as f64 * labelled / per_label[&label] as f64).round();
+                let (distinct, once) = a.distinct.counts();
+                // A sketch can overshoot on a sample of unique values
+                let distinct = distinct.min(a.values as f64);
+                let stats = PropertyStatistics {
+                    sampled_values: a.values,
+                    sampled_distinct: distinct.round() as u64,
+                    estimated_count: count as u64,
+                    estimated_distinct: scale_distinct(distinct, once, a.values as f64, count),
+                    min: a.min,
+                    max: a.max,
+                    histogram: histograms.remove(&(label, key)),
+                };
+                ((label.to_string(), key.to_string()), stats)
+            })
+            .collect();
+        let statistics = GraphStatistics { sample_rate: rate, node_count: (self.nodes.len() - self.deleted_nodes) as u64, sampled_nodes, properties };
+        self.statistics = Some(statistics.clone());
+        statistics
+    }
+
+    /// The statistics of the last `collect_statistics`, or of the loaded segments.
+    pub fn statistics(&self) -> Option<&GraphStatistics> {
+        self.statistics.as_ref()
+    }
+
+    /// The statistics of `(label, key)`, if they were collected and it was sampled.
+    pub fn property_statistics(&self, label: &str, key: &str) -> Option<&PropertyStatistics> {
+        self.statistics.as_ref()?.properties.get(&(label.to_string(), key.to_string()))
+    }
+
+    /// `{"format_version": 1, "statistics": null | {...}}`, floats stored as their bits
+    /// so they come back exactly.
+    pub(crate) fn serialize_statistics(&self) -> Result<Vec<u8>, EngineError> {
+        let statistics = self.statistics.as_ref().map(|stats| {
+            let properties: Vec<Json> = stats.properties.iter()
+                .map(|((label, key), p)| {
+                    json!({
+                        "label": label,
+                        "key": key,
+                        "sampled_values": p.sampled_values,
+                        "sampled_distinct": p.sampled_distinct,
+                        "estimated_count": p.estimated_count,
+                        "estimated_distinct": p.estimated_distinct,
+                        "min": p.min.as_ref().map(encode_ordered),
+                        "max": p.max.as_ref().map(encode_ordered),
+                        "histogram": p.histogram.as_ref().map(|h| json!({
+                            "low": h.low.to_bits(),
+                            "high": h.high.to_bits(),
+                            "counts": h.counts,
+                        })),
+                    })
+                })
+                .collect();
+            json!({
+                "sample_rate": stats.sample_rate.to_bits(),
+                "node_count": stats.node_count,
+                "sampled_nodes": stats.sampled_nodes,
+                "properties": properties,
+            })
+        });
+        serde_json::to_vec(&json!({ "format_version": FORMAT_VERSION, "statistics": statistics }))
+            .map_err(|e| EngineError::StorageIo(format!("serialize statistics: {}", e)))
+    }
+
+    pub(crate) fn deserialize_statistics(&mut self, data: &[u8]) -> Result<(), EngineError> {
+        let json: Json = serde_json::from_slice(data)
+            .map_err(|e| EngineError::StorageIo(format!("parse statistics: {}", e)))?;
+        match json["format_version"].as_u64() {
+            Some(FORMAT_VERSION) => {}
+            other => {
+                return Err(EngineError::StorageIo(format!("unsupported statistics segment format version: {:?}", other)));
+            }
+        }
+        self.statistics = match &json["statistics"] {
+            Json::Null => None,
+            stats => Some(parse_statistics(stats).ok_or_else(|| EngineError::StorageIo("invalid statistics segment".into()))?),
+        };
+        Ok(())
+    }
+}
+
+fn parse_statistics(json: &Json) -> Option<GraphStatistics> {
+    let float = |j: &Json| j.as_u64().map(f64::from_bits);
+    let ordered = |j: &Json| if j.is_null() { Some(None) } else { decode_ordered(j).map(Some) };
+    let properties = json["properties"].as_array()?.iter()
+        .map(|p| {
+            let histogram = match &p["histogram"] {
+                Json::Null => None,
+                h => Some(Histogram {
+                    low: float(&h["low"])?,
+                    high: float(&h["high"])?,
+                    counts: h["counts"].as_array()?.iter().map(Json::as_u64).collect::<Option<_>>()?,
+                }),
+            };
+            let stats = PropertyStatistics {
+                sampled_values: p["sampled_values"].as_u64()?,
+                sampled_distinct: p["sampled_distinct"].as_u64()?,
+                estimated_count: p["estimated_count"].as_u64()?,
+                estimated_distinct: p["estimated_distinct"].as_u64()?,
+                min: ordered(&p["min"])?,
+                max: ordered(&p["max"])?,
+                histogram,
+            };
+            Some(((p["label"].as_str()?.to_string(), p["key"].as_str()?.to_string()), stats))
+        })
+        .collect::<Option<_>>()?;
+    Some(GraphStatistics {
+        sample_rate: float(&json["sample_rate"])?,
+        node_count: json["node_count"].as_u64()?,
+        sampled_nodes: json["sampled_nodes"].as_u64()?,
+        properties,
+    })
+}
@@ -0,0 +1,190 @@
+//! Full-text indexes: `(label, key) -> token -> node ids`
+//!
+//! A text index tokenizes the `String` value of `key` on every live node carrying `label`
+//! and files the node under each distinct token; other value types are not filed. Tokens
+//! come from a `Tokenizer`, `SimpleTokenizer` unless the index was created with another
+//! one, and queries are tokenized the same way. Postings are kept in token order so
+//! `TextMatch::Prefix` can walk a contiguous range. Maintenance rides on the property
+//! index hooks, and like them only the definitions are persisted: a loaded index is
+//! rebuilt with `SimpleTokenizer`.
+
+use super::index_stats::IndexKind;
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, Node, NodeId, Value};
+use crate::types::EngineError;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+/// Splits text into the tokens a text index files nodes under.
+pub trait Tokenizer: Send + Sync {
+    /// The tokens of `text`, in any order; duplicates are ignored.
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Lowercased runs of alphanumeric characters; whitespace and punctuation separate tokens.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimpleTokenizer;
+
+impl Tokenizer for SimpleTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|t| !t.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+}
+
+/// How `search_text` combines the tokens of a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMatch {
+    /// Nodes whose value contains every query token.
+    AllTokens,
+    /// Nodes whose value contains at least one query token.
+    AnyToken,
+    /// Nodes whose value has, for every query token, a token starting with it.
+    Prefix,
+}
+
+/// One text index: its tokenizer and the postings it produced.
+pub(crate) struct TextIndex {
+    pub(crate) tokenizer: Arc<dyn Tokenizer>,
+    pub(crate) postings: BTreeMap<String, Vec<NodeId>>,
+}
+
+impl TextIndex {
+    /// The distinct tokens `node` is filed under for `(label, key)`, sorted.
+    pub(crate) fn tokens_of(&self, node: &Node, label: &str, key: &str) -> Vec<String> {
+        let Some(Value::String(text)) = node.properties.get(key).filter(|_| node.labels.iter().any(|l| l == label)) else {
+            return Vec::new();
+        };
+        let mut tokens = self.tokenizer.tokenize(text);
+        tokens.sort_unstable();
+        tokens.dedup();
+        tokens
+    }
+
+    /// Ids of the nodes with a token starting with `prefix`.
+    fn prefixed(&self, prefix: &str) -> BTreeSet<NodeId> {
+        self.postings.range(prefix.to_string()..)
+            .take_while(|(token, _)| token.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+
+    /// Ids of the nodes filed under exactly `token`.
+    fn exact(&self, token: &str) -> BTreeSet<NodeId> {
+        self.postings.get(token).into_iter().flatten().copied().collect()
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Index the `key` string property of nodes carrying `label` for `search_text`, using
+    /// `SimpleTokenizer`, and backfill from the nodes already stored.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty or the index already exists.
+    pub fn create_text_index(&mut self, label: &str, key: &str) -> Result<(), EngineError> {
+        self.create_text_index_with(label, key, Arc::new(SimpleTokenizer))
+    }
+
+    /// `create_text_index` with a custom tokenizer, used for both values and queries.
+    pub fn create_text_index_with(&mut self, label: &str, key: &str, tokenizer: Arc<dyn Tokenizer>) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("text index needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.text_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("text index on {}.{} already exists", label, key)));
+        }
+        let mut index = TextIndex { tokenizer, postings: BTreeMap::new() };
+        for node in self.iter_nodes_matching(Some(label)) {
+            for token in index.tokens_of(node, label, key) {
+                index.postings.entry(token).or_default().push(node.id);
+            }
+        }
+        self.text_indexes.insert(def, index);
+        self.track_index(IndexKind::Text, label, &[key]);
+        self.log_create_index(DdlKind::Index(IndexKind::Text), label, &[key], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the text index on `(label, key)`. Returns `false` if there was none.
+    pub fn drop_text_index(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Text, label, &[key]);
+        let dropped = self.text_indexes.remove(&(label.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Text), label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every text-indexed `(label, key)`, sorted.
+    pub fn text_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.text_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// Ids of the nodes carrying `label` whose `key` text matches `query` under `mode`,
+    /// in ascending order. A query without tokens matches nothing.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `(label, key)` has no text index.
+    pub fn search_text(&self, label: &str, key: &str, query: &str, mode: TextMatch) -> Result<Vec<NodeId>, EngineError> {
+        let index = self.text_indexes.get(&(label.to_string(), key.to_string()))
+            .ok_or_else(|| EngineError::InvalidArgument(format!("no text index on {}.{}", label, key)))?;
+        let mut tokens = index.tokenizer.tokenize(query);
+        tokens.sort_unstable();
+        tokens.dedup();
+        let mut sets = tokens.iter().map(|t| match mode {
+            TextMatch::Prefix => index.prefixed(t),
+            TextMatch::AllTokens | TextMatch::AnyToken => index.exact(t),
+        });
+        let matched = match sets.next() {
+            Some(first) => sets.fold(first, |acc, set| match mode {
+                TextMatch::AnyToken => &acc | &set,
+                TextMatch::AllTokens | TextMatch::Prefix => &acc & &set,
+            }),
+            None => BTreeSet::new(),
+        };
+        self.record_index_lookup(IndexKind::Text, label, &[key], !matched.is_empty());
+        Ok(matched.into_iter().collect())
+    }
+
+    /// File live node `id` in the text indexes it qualifies for. Called from
+    /// `index_node_properties`.
+    pub(crate) fn index_node_text(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.text_indexes.iter_mut() {
+            for token in index.tokens_of(node, label, key) {
+                index.postings.entry(token).or_default().push(id);
+            }
+        }
+    }
+
+    /// Remove live node `id` from the postings it is currently filed under. Called from
+    /// `unindex_node_properties`.
+    pub(crate) fn unindex_node_text(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.text_indexes.iter_mut() {
+            for token in index.tokens_of(node, label, key) {
+                if let Some(ids) = index.postings.get_mut(&token) {
+                    ids.retain(|n| *n != id);
+                    if ids.is_empty() {
+                        index.postings.remove(&token);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Refill every text index from the live nodes, keeping definitions and tokenizers.
+    pub(crate) fn rebuild_text_indexes(&mut self) {
+        let defs: Vec<((String, String), Arc<dyn Tokenizer>)> = self.text_indexes.drain()
+            .map(|(def, index)| (def, index.tokenizer))
+            .collect();
+        for ((label, key), tokenizer) in defs {
+            self.create_text_index_with(&label, &key, tokenizer).expect("definitions are valid and distinct");
+        }
+    }
+}
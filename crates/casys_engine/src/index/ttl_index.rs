@@ -0,0 +1,137 @@
+//! TTL indexes: `(label, key) -> expiry timestamp -> node ids`, in timestamp order
+//!
+//! A TTL index files every live node carrying `label` under its `key` value, when that
+//! value is an `Int` timestamp in whatever unit the application uses. Nodes without the
+//! property, or with a value of any other type, are not filed and never expire. `expire`
+//! walks the indexes from the oldest timestamp up to `now` and detach-deletes what it
+//! finds, so its cost follows the number of expired nodes rather than the size of the
+//! store. Nothing runs in the background: expiry happens only when `expire` is called.
+//! Like the other node indexes it is maintained through `index_node_properties` /
+//! `unindex_node_properties`.
+
+use super::index_stats::IndexKind;
+use super::persistence::{DdlKind, DdlOptions};
+use super::{InMemoryGraphStore, Node, NodeId, Value};
+use crate::types::EngineError;
+use std::collections::BTreeMap;
+
+/// Buckets of one TTL index, by timestamp.
+pub(crate) type TtlIndex = BTreeMap<i64, Vec<NodeId>>;
+
+/// What one `expire` call deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpiredReport {
+    /// Nodes deleted (tombstoned with `soft_delete`)
+    pub nodes: usize,
+    /// Edges deleted along with them
+    pub edges: usize,
+}
+
+/// The timestamp `node` is filed under for `(label, key)`, if any.
+pub(crate) fn expiry_of(node: &Node, label: &str, key: &str) -> Option<i64> {
+    match node.properties.get(key).filter(|_| node.labels.iter().any(|l| l == label)) {
+        Some(Value::Int(at)) => Some(*at),
+        _ => None,
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Index the `key` timestamp of nodes carrying `label` for `expire`, backfilling from
+    /// the nodes already stored.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if `label` or `key` is empty or the index already exists.
+    pub fn create_ttl_index(&mut self, label: &str, key: &str) -> Result<(), EngineError> {
+        if label.is_empty() || key.is_empty() {
+            return Err(EngineError::InvalidArgument("TTL index needs a label and a key".into()));
+        }
+        let def = (label.to_string(), key.to_string());
+        if self.ttl_indexes.contains_key(&def) {
+            return Err(EngineError::InvalidArgument(format!("TTL index on {}.{} already exists", label, key)));
+        }
+        let mut index = TtlIndex::new();
+        for id in self.label_index.get(label).into_iter().flatten() {
+            if let Some(at) = self.nodes.get(id).and_then(|node| expiry_of(node, label, key)) {
+                index.entry(at).or_default().push(*id);
+            }
+        }
+        self.ttl_indexes.insert(def, index);
+        self.track_index(IndexKind::Ttl, label, &[key]);
+        self.log_create_index(DdlKind::Index(IndexKind::Ttl), label, &[key], DdlOptions::default);
+        Ok(())
+    }
+
+    /// Drop the TTL index on `(label, key)`. Returns `false` if there was none.
+    pub fn drop_ttl_index(&mut self, label: &str, key: &str) -> Result<bool, EngineError> {
+        self.untrack_index(IndexKind::Ttl, label, &[key]);
+        let dropped = self.ttl_indexes.remove(&(label.to_string(), key.to_string())).is_some();
+        if dropped {
+            self.log_drop_index(DdlKind::Index(IndexKind::Ttl), label, &[key]);
+        }
+        Ok(dropped)
+    }
+
+    /// Every TTL index as `(label, key)`, sorted.
+    pub fn ttl_indexes(&self) -> Vec<(String, String)> {
+        let mut defs: Vec<(String, String)> = self.ttl_indexes.keys().cloned().collect();
+        defs.sort();
+        defs
+    }
+
+    /// Detach-delete every node whose timestamp in some TTL index is at most `now`, in
+    /// ascending id order. A node filed by several TTL indexes is deleted once. Each
+    /// deletion is logged to the WAL like a `delete_node` with `detach`, and tombstones
+    /// instead with `soft_delete`.
+    pub fn expire(&mut self, now: i64) -> Result<ExpiredReport, EngineError> {
+        let mut ids: Vec<NodeId> = Vec::new();
+        for ((label, key), index) in &self.ttl_indexes {
+            let before = ids.len();
+            ids.extend(index.range(..=now).flat_map(|(_, ids)| ids.iter().copied()));
+            self.record_index_lookup(IndexKind::Ttl, label, &[key], ids.len() > before);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        let mut report = ExpiredReport::default();
+        for id in ids {
+            if self.live_node(id).is_some() {
+                report.edges += self.remove_node(id, true);
+                report.nodes += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// File live node `id` in the TTL indexes it qualifies for. Called from
+    /// `index_node_properties`.
+    pub(crate) fn index_node_ttl(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.ttl_indexes.iter_mut() {
+            if let Some(at) = expiry_of(node, label, key) {
+                index.entry(at).or_default().push(id);
+            }
+        }
+    }
+
+    /// Remove live node `id` from the TTL index buckets it is currently filed under.
+    /// Called from `unindex_node_properties`.
+    pub(crate) fn unindex_node_ttl(&mut self, id: NodeId) {
+        let Some(node) = self.nodes.get(&id).filter(|n| !n.deleted) else { return };
+        for ((label, key), index) in self.ttl_indexes.iter_mut() {
+            let Some(at) = expiry_of(node, label, key) else { continue };
+            if let Some(ids) = index.get_mut(&at) {
+                ids.retain(|n| *n != id);
+                if ids.is_empty() {
+                    index.remove(&at);
+                }
+            }
+        }
+    }
+
+    /// Refill every TTL index from the live nodes, keeping definitions.
+    pub(crate) fn rebuild_ttl_indexes(&mut self) {
+        let defs: Vec<(String, String)> = self.ttl_indexes.drain().map(|(def, _)| def).collect();
+        for (label, key) in defs {
+            self.create_ttl_index(&label, &key).expect("definitions are valid and distinct");
+        }
+    }
+}
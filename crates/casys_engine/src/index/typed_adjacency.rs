@@ -0,0 +1,91 @@
+//! Adjacency lists split by edge type, kept when `StoreOptions::typed_adjacency` is on
+//!
+//! `typed_out[node][edge_type]` and `typed_in[node][edge_type]` hold the same live edge ids
+//! as `adjacency_out[node]` and `adjacency_in[node]`, bucketed by type. Typed neighbor
+//! queries and `degree` then walk one bucket instead of filtering every incident edge,
+//! which matters for nodes with many edges of which only one type is wanted. Untyped
+//! queries keep using the plain lists. Maintenance mirrors the plain lists: every write
+//! that links or unlinks an edge end also calls `link_typed` / `unlink_typed`.
+
+use super::{InMemoryGraphStore, NodeId, EdgeId};
+use std::collections::HashMap;
+
+/// node -> edge type -> edge ids, for one direction.
+pub(crate) type TypedAdjacency = HashMap<NodeId, HashMap<String, Vec<EdgeId>>>;
+
+impl InMemoryGraphStore {
+    /// The edge ids to walk for `node`'s edges in one direction: the bucket of `edge_type`
+    /// when typed adjacency is on, the node's whole list otherwise. Callers still filter
+    /// by type, which is a no-op on a typed bucket.
+    pub(crate) fn adjacency_list(&self, node: NodeId, outgoing: bool, edge_type: Option<&str>) -> &[EdgeId] {
+        let list = match edge_type.filter(|_| self.options.typed_adjacency) {
+            Some(t) => {
+                let typed = if outgoing { &self.typed_out } else { &self.typed_in };
+                typed.get(&node).and_then(|buckets| buckets.get(t))
+            }
+            None => if outgoing { self.adjacency_out.get(&node) } else { self.adjacency_in.get(&node) },
+        };
+        list.map_or(&[][..], Vec::as_slice)
+    }
+
+    /// File live edge `id` under both of its ends. Call after the edge is linked.
+    pub(crate) fn link_typed(&mut self, id: EdgeId) {
+        if !self.options.typed_adjacency {
+            return;
+        }
+        let Some(edge) = self.edges.get(&id).filter(|e| !e.deleted) else { return };
+        self.typed_out.entry(edge.from_node).or_default().entry(edge.edge_type.clone()).or_default().push(id);
+        self.typed_in.entry(edge.to_node).or_default().entry(edge.edge_type.clone()).or_default().push(id);
+    }
+
+    /// Remove live edge `id` from the buckets of both of its ends. Call before the edge's
+    /// type or endpoints change, or before it is unlinked.
+    pub(crate) fn unlink_typed(&mut self, id: EdgeId) {
+        if !self.options.typed_adjacency {
+            return;
+        }
+        let Some(edge) = self.edges.get(&id).filter(|e| !e.deleted) else { return };
+        unfile_typed(&mut self.typed_out, edge.from_node, &edge.edge_type, id);
+        unfile_typed(&mut self.typed_in, edge.to_node, &edge.edge_type, id);
+    }
+
+    /// Hand every bucket of `from` over to `to`, as `merge_nodes` does with the plain lists.
+    pub(crate) fn move_typed_adjacency(&mut self, from: NodeId, to: NodeId) {
+        for typed in [&mut self.typed_out, &mut self.typed_in] {
+            for (edge_type, ids) in typed.remove(&from).unwrap_or_default() {
+                typed.entry(to).or_default().entry(edge_type).or_default().extend(ids);
+            }
+        }
+    }
+
+    /// Refill both typed maps from the plain adjacency lists.
+    pub(crate) fn rebuild_typed_adjacency(&mut self) {
+        self.typed_out.clear();
+        self.typed_in.clear();
+        if !self.options.typed_adjacency {
+            return;
+        }
+        for (adjacency, typed) in [(&self.adjacency_out, &mut self.typed_out), (&self.adjacency_in, &mut self.typed_in)] {
+            for (node, ids) in adjacency {
+                for id in ids {
+                    let edge_type = self.edges[id].edge_type.clone();
+                    typed.entry(*node).or_default().entry(edge_type).or_default().push(*id);
+                }
+            }
+        }
+    }
+}
+
+/// Remove `id` from `typed[node][edge_type]`, dropping buckets and nodes once empty.
+fn unfile_typed(typed: &mut TypedAdjacency, node: NodeId, edge_type: &str, id: EdgeId) {
+    let Some(buckets) = typed.get_mut(&node) else { return };
+    if let Some(ids) = buckets.get_mut(edge_type) {
+        ids.retain(|e| *e != id);
+        if ids.is_empty() {
+            buckets.remove(edge_type);
+        }
+    }
+    if buckets.is_empty() {
+        typed.remove(&node);
+    }
+}
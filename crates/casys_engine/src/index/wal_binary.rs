@@ -0,0 +1,407 @@
+//! Binary WAL payloads
+//!
+//! A binary payload starts with `BINARY_TAG`, then the record: a type byte and its fields
+//! in declaration order. Integers are little-endian (`u64` ids and LSNs, `i64`, `f64`
+//! bits), booleans one byte, strings and byte strings a `u32` length then the bytes,
+//! lists and maps a `u32` count then the items. A `Value` is a type byte then its
+//! content. `InTxn` carries its txn id then the wrapped record, without a second tag.
+//! Index records carry their kind as one byte, its position in `DDL_KINDS`.
+//!
+//! A JSON payload always starts with `{`, so the first byte of a payload tells the two
+//! formats apart and logs written before this one stay readable. Unlike JSON, the binary
+//! form keeps every `Value` exactly: bytes, node ids and NaN come back as written.
+
+use super::persistence::{DdlKind, DdlOptions, WalRecord};
+use super::{Endpoint, IndexKind, IndexOptions};
+use crate::types::EngineError;
+use casys_core::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// First byte of a binary payload.
+pub(crate) const BINARY_TAG: u8 = 0x01;
+
+/// How a `WalWriter` serializes record payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadFormat {
+    /// JSON, as every log written before binary payloads
+    #[default]
+    Json,
+    /// The compact binary form of this module
+    Binary,
+}
+
+pub(crate) fn encode(record: &WalRecord) -> Vec<u8> {
+    let mut out = vec![BINARY_TAG];
+    put_record(&mut out, record);
+    out
+}
+
+/// Decode a payload starting with `BINARY_TAG`.
+pub(crate) fn decode(data: &[u8]) -> Result<WalRecord, EngineError> {
+    let mut input = Input::new(data, 1, "WAL record parse");
+    let record = input.record()?;
+    if input.at != data.len() {
+        return Err(input.error("trailing bytes"));
+    }
+    Ok(record)
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+pub(crate) fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+pub(crate) fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+pub(crate) fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_bytes(out, s.as_bytes());
+}
+
+pub(crate) fn put_props(out: &mut Vec<u8>, props: &HashMap<String, Value>) {
+    put_len(out, props.len());
+    for (key, value) in props {
+        put_str(out, key);
+        put_value(out, value);
+    }
+}
+
+pub(crate) fn put_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(0),
+        Value::Bool(b) => out.extend_from_slice(&[1, *b as u8]),
+        Value::Int(i) => {
+            out.push(2);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Value::Float(f) => {
+            out.push(3);
+            put_u64(out, f.to_bits());
+        }
+        Value::String(s) => {
+            out.push(4);
+            put_str(out, s);
+        }
+        Value::Bytes(b) => {
+            out.push(5);
+            put_bytes(out, b);
+        }
+        Value::Array(items) => {
+            out.push(6);
+            put_len(out, items.len());
+            for item in items {
+                put_value(out, item);
+            }
+        }
+        Value::Map(map) => {
+            out.push(7);
+            put_len(out, map.len());
+            for (key, value) in map {
+                put_str(out, key);
+                put_value(out, value);
+            }
+        }
+        Value::NodeId(id) => {
+            out.push(8);
+            put_u64(out, *id);
+        }
+    }
+}
+
+fn put_record(out: &mut Vec<u8>, record: &WalRecord) {
+    match record {
+        WalRecord::AddNode { id, labels, properties } => {
+            out.push(1);
+            put_u64(out, *id);
+            put_strings(out, labels);
+            put_props(out, properties);
+        }
+        WalRecord::AddEdge { id, from_node, to_node, edge_type, properties } => {
+            out.push(2);
+            put_u64(out, *id);
+            put_u64(out, *from_node);
+            put_u64(out, *to_node);
+            put_str(out, edge_type);
+            put_props(out, properties);
+        }
+        WalRecord::DeleteNode { id, detach } => {
+            out.push(3);
+            put_u64(out, *id);
+            out.push(*detach as u8);
+        }
+        WalRecord::DeleteEdge { id } => id_record(out, 4, *id),
+        WalRecord::TombstoneNode { id } => id_record(out, 5, *id),
+        WalRecord::TombstoneEdge { id } => id_record(out, 6, *id),
+        WalRecord::UndeleteNode { id } => id_record(out, 7, *id),
+        WalRecord::UndeleteEdge { id } => id_record(out, 8, *id),
+        WalRecord::PurgeTombstones => out.push(9),
+        WalRecord::SetNodeProperty { id, key, value } => {
+            id_record(out, 10, *id);
+            put_str(out, key);
+            put_value(out, value);
+        }
+        WalRecord::RemoveNodeProperty { id, key } => {
+            id_record(out, 11, *id);
+            put_str(out, key);
+        }
+        WalRecord::AddLabel { id, label } => {
+            id_record(out, 12, *id);
+            put_str(out, label);
+        }
+        WalRecord::RemoveLabel { id, label } => {
+            id_record(out, 13, *id);
+            put_str(out, label);
+        }
+        WalRecord::SetEdgeProperty { id, key, value } => {
+            id_record(out, 14, *id);
+            put_str(out, key);
+            put_value(out, value);
+        }
+        WalRecord::SetEdgeType { id, edge_type } => {
+            id_record(out, 15, *id);
+            put_str(out, edge_type);
+        }
+        WalRecord::SetEdgeEndpoint { id, endpoint, node } => {
+            id_record(out, 16, *id);
+            out.push(match endpoint { Endpoint::From => 0, Endpoint::To => 1 });
+            put_u64(out, *node);
+        }
+        WalRecord::ReverseEdge { id } => id_record(out, 17, *id),
+        WalRecord::RenameEdgeType { old, new } => {
+            out.push(18);
+            put_str(out, old);
+            put_str(out, new);
+        }
+        WalRecord::MergeNodes { keep, remove, keep_self_loops } => {
+            out.push(19);
+            put_u64(out, *keep);
+            put_u64(out, *remove);
+            out.push(*keep_self_loops as u8);
+        }
+        WalRecord::Truncate { reset_ids } => out.extend_from_slice(&[20, *reset_ids as u8]),
+        WalRecord::Checkpoint { generation, lsn } => {
+            out.push(21);
+            put_u64(out, *generation);
+            put_u64(out, *lsn);
+        }
+        WalRecord::BeginTxn { txn_id } => id_record(out, 22, *txn_id),
+        WalRecord::CommitTxn { txn_id } => id_record(out, 23, *txn_id),
+        WalRecord::AbortTxn { txn_id } => id_record(out, 24, *txn_id),
+        WalRecord::InTxn { txn_id, record } => {
+            id_record(out, 25, *txn_id);
+            put_record(out, record);
+        }
+        WalRecord::CreateIndex { kind, label, keys, options } => {
+            out.push(26);
+            put_definition(out, *kind, label, keys);
+            let IndexOptions { case_insensitive, trim_whitespace, multi_value } = options.property;
+            out.extend_from_slice(&[case_insensitive as u8, trim_whitespace as u8, multi_value as u8]);
+            put_strings(out, &options.covered);
+            out.push(options.case_sensitive as u8);
+        }
+        WalRecord::DropIndex { kind, label, keys } => {
+            out.push(27);
+            put_definition(out, *kind, label, keys);
+        }
+    }
+}
+
+const DDL_KINDS: [DdlKind; 9] = [
+    DdlKind::Index(IndexKind::Property),
+    DdlKind::Index(IndexKind::Range),
+    DdlKind::Index(IndexKind::Composite),
+    DdlKind::Index(IndexKind::Text),
+    DdlKind::Index(IndexKind::Prefix),
+    DdlKind::Index(IndexKind::Edge),
+    DdlKind::Index(IndexKind::Computed),
+    DdlKind::Index(IndexKind::Ttl),
+    DdlKind::UniqueConstraint,
+];
+
+fn put_strings(out: &mut Vec<u8>, strings: &[String]) {
+    put_len(out, strings.len());
+    for s in strings {
+        put_str(out, s);
+    }
+}
+
+/// The kind (its position in `DDL_KINDS`), label and keys of an index record.
+fn put_definition(out: &mut Vec<u8>, kind: DdlKind, label: &str, keys: &[String]) {
+    out.push(DDL_KINDS.iter().position(|k| *k == kind).expect("every kind is listed") as u8);
+    put_str(out, label);
+    put_strings(out, keys);
+}
+
+fn id_record(out: &mut Vec<u8>, kind: u8, id: u64) {
+    out.push(kind);
+    put_u64(out, id);
+}
+
+/// Reads what the `put_*` functions write, failing with `StorageIo` errors prefixed with
+/// `context`.
+pub(crate) struct Input<'a> {
+    data: &'a [u8],
+    pub(crate) at: usize,
+    context: &'static str,
+}
+
+impl<'a> Input<'a> {
+    pub(crate) fn new(data: &'a [u8], at: usize, context: &'static str) -> Self {
+        Self { data, at, context }
+    }
+
+    pub(crate) fn error(&self, what: &str) -> EngineError {
+        EngineError::StorageIo(format!("{}: {}", self.context, what))
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.at >= self.data.len()
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], EngineError> {
+        let end = self.at.checked_add(n).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| self.error("binary payload cut short"))?;
+        let bytes = &self.data[self.at..end];
+        self.at += n;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, EngineError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, EngineError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(self.error(&format!("invalid bool byte {}", other))),
+        }
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, EngineError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    pub(crate) fn len(&mut self) -> Result<usize, EngineError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("4 bytes")) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, EngineError> {
+        let len = self.len()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, EngineError> {
+        String::from_utf8(self.bytes()?).map_err(|_| self.error("string is not UTF-8"))
+    }
+
+    pub(crate) fn strings(&mut self) -> Result<Vec<String>, EngineError> {
+        let count = self.len()?;
+        (0..count).map(|_| self.string()).collect()
+    }
+
+    fn definition(&mut self) -> Result<(DdlKind, String, Vec<String>), EngineError> {
+        let kind = self.u8()?;
+        let kind = *DDL_KINDS.get(kind as usize).ok_or_else(|| self.error(&format!("unknown index kind {}", kind)))?;
+        Ok((kind, self.string()?, self.strings()?))
+    }
+
+    pub(crate) fn props(&mut self) -> Result<HashMap<String, Value>, EngineError> {
+        let count = self.len()?;
+        let mut props = HashMap::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            props.insert(key, self.value()?);
+        }
+        Ok(props)
+    }
+
+    fn value(&mut self) -> Result<Value, EngineError> {
+        Ok(match self.u8()? {
+            0 => Value::Null,
+            1 => Value::Bool(self.bool()?),
+            2 => Value::Int(self.u64()? as i64),
+            3 => Value::Float(f64::from_bits(self.u64()?)),
+            4 => Value::String(self.string()?),
+            5 => Value::Bytes(self.bytes()?),
+            6 => {
+                let count = self.len()?;
+                Value::Array((0..count).map(|_| self.value()).collect::<Result<_, _>>()?)
+            }
+            7 => {
+                let count = self.len()?;
+                let mut map = BTreeMap::new();
+                for _ in 0..count {
+                    let key = self.string()?;
+                    map.insert(key, self.value()?);
+                }
+                Value::Map(map)
+            }
+            8 => Value::NodeId(self.u64()?),
+            other => return Err(self.error(&format!("unknown value type {}", other))),
+        })
+    }
+
+    fn record(&mut self) -> Result<WalRecord, EngineError> {
+        Ok(match self.u8()? {
+            1 => {
+                let id = self.u64()?;
+                WalRecord::AddNode { id, labels: self.strings()?, properties: self.props()? }
+            }
+            2 => WalRecord::AddEdge {
+                id: self.u64()?,
+                from_node: self.u64()?,
+                to_node: self.u64()?,
+                edge_type: self.string()?,
+                properties: self.props()?,
+            },
+            3 => WalRecord::DeleteNode { id: self.u64()?, detach: self.bool()? },
+            4 => WalRecord::DeleteEdge { id: self.u64()? },
+            5 => WalRecord::TombstoneNode { id: self.u64()? },
+            6 => WalRecord::TombstoneEdge { id: self.u64()? },
+            7 => WalRecord::UndeleteNode { id: self.u64()? },
+            8 => WalRecord::UndeleteEdge { id: self.u64()? },
+            9 => WalRecord::PurgeTombstones,
+            10 => WalRecord::SetNodeProperty { id: self.u64()?, key: self.string()?, value: self.value()? },
+            11 => WalRecord::RemoveNodeProperty { id: self.u64()?, key: self.string()? },
+            12 => WalRecord::AddLabel { id: self.u64()?, label: self.string()? },
+            13 => WalRecord::RemoveLabel { id: self.u64()?, label: self.string()? },
+            14 => WalRecord::SetEdgeProperty { id: self.u64()?, key: self.string()?, value: self.value()? },
+            15 => WalRecord::SetEdgeType { id: self.u64()?, edge_type: self.string()? },
+            16 => WalRecord::SetEdgeEndpoint {
+                id: self.u64()?,
+                endpoint: match self.u8()? {
+                    0 => Endpoint::From,
+                    1 => Endpoint::To,
+                    other => return Err(self.error(&format!("invalid edge endpoint {}", other))),
+                },
+                node: self.u64()?,
+            },
+            17 => WalRecord::ReverseEdge { id: self.u64()? },
+            18 => WalRecord::RenameEdgeType { old: self.string()?, new: self.string()? },
+            19 => WalRecord::MergeNodes { keep: self.u64()?, remove: self.u64()?, keep_self_loops: self.bool()? },
+            20 => WalRecord::Truncate { reset_ids: self.bool()? },
+            21 => WalRecord::Checkpoint { generation: self.u64()?, lsn: self.u64()? },
+            22 => WalRecord::BeginTxn { txn_id: self.u64()? },
+            23 => WalRecord::CommitTxn { txn_id: self.u64()? },
+            24 => WalRecord::AbortTxn { txn_id: self.u64()? },
+            25 => WalRecord::InTxn { txn_id: self.u64()?, record: Box::new(self.record()?) },
+            26 => {
+                let (kind, label, keys) = self.definition()?;
+                let property = IndexOptions { case_insensitive: self.bool()?, trim_whitespace: self.bool()?, multi_value: self.bool()? };
+                let options = DdlOptions { property, covered: self.strings()?, case_sensitive: self.bool()? };
+                WalRecord::CreateIndex { kind, label, keys, options }
+            }
+            27 => {
+                let (kind, label, keys) = self.definition()?;
+                WalRecord::DropIndex { kind, label, keys }
+            }
+            other => return Err(self.error(&format!("unknown binary record type {}", other))),
+        })
+    }
+}
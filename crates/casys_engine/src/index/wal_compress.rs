@@ -0,0 +1,195 @@
+//! WAL payload compression
+//!
+//! A `WalWriter` built `with_compression` compresses each payload larger than the
+//! threshold of its `Compression` and keeps the result if it is smaller. Such a frame has
+//! `COMPRESSED_FLAG` set in its length field, and its payload is the id of the
+//! `Compressor` (one byte) then the compressed bytes; the checksum covers them as
+//! written. Readers decompress by that id, so a log may mix compressed and plain frames,
+//! and frames of several compressors. Readers written before compression take a
+//! compressed frame for a bad one.
+//!
+//! `DeflateCompressor` is the built-in, behind the `deflate` feature (which brings in
+//! `flate2`); `IdentityCompressor` stores payloads as they are. Other compressors are
+//! registered with `register_compressor` so readers in the process know their id. No
+//! payload decompresses to more than `MAX_DECOMPRESSED_PAYLOAD`.
+
+use crate::types::EngineError;
+use std::sync::{Arc, RwLock};
+
+/// Set in a frame's length field when its payload is compressed.
+pub(crate) const COMPRESSED_FLAG: u32 = 1 << 31;
+
+/// Payload size above which `Compression` compresses, unless told otherwise.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Compresses WAL payloads, and decompresses what it compressed.
+pub trait Compressor: Send + Sync {
+    /// Written before the compressed bytes. 0 and 1 belong to the built-ins.
+    fn id(&self) -> u8;
+
+    fn compress(&self, data: &[u8]) -> Vec<u8>;
+
+    /// # Errors
+    /// `StorageIo` if `data` is not the output of `compress`.
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, EngineError>;
+}
+
+/// A `Compressor` that stores payloads as they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentityCompressor;
+
+impl Compressor for IdentityCompressor {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, EngineError> {
+        Ok(data.to_vec())
+    }
+}
+
+/// The built-in `Compressor`: DEFLATE (RFC 1951) at the default level of `flate2`.
+/// Needs the `deflate` feature; builds without it fail its frames with `NotImplemented`.
+#[cfg(feature = "deflate")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeflateCompressor;
+
+#[cfg(feature = "deflate")]
+impl Compressor for DeflateCompressor {
+    fn id(&self) -> u8 {
+        DEFLATE_ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::with_capacity(data.len() / 2), flate2::Compression::default());
+        encoder.write_all(data).expect("writing to a Vec does not fail");
+        encoder.finish().expect("writing to a Vec does not fail")
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, EngineError> {
+        use std::io::Read;
+        let mut out = Vec::with_capacity(data.len().saturating_mul(4).min(MAX_DECOMPRESSED_PAYLOAD));
+        // One byte past the limit tells a payload at the limit from one beyond it
+        flate2::read::DeflateDecoder::new(data)
+            .take(MAX_DECOMPRESSED_PAYLOAD as u64 + 1)
+            .read_to_end(&mut out)
+            .map_err(|e| decompress_error(&e.to_string()))?;
+        check_decompressed(out)
+    }
+}
+
+/// Id of `DeflateCompressor`, taken whether or not it is compiled in.
+const DEFLATE_ID: u8 = 1;
+
+/// Largest payload a frame decompresses to. Writers leave larger payloads plain, so a
+/// frame claiming more is damaged, and reading it stops there instead of exhausting
+/// memory.
+pub const MAX_DECOMPRESSED_PAYLOAD: usize = 64 << 20;
+
+fn check_decompressed(out: Vec<u8>) -> Result<Vec<u8>, EngineError> {
+    if out.len() > MAX_DECOMPRESSED_PAYLOAD {
+        return Err(decompress_error(&format!("more than {} bytes", MAX_DECOMPRESSED_PAYLOAD)));
+    }
+    Ok(out)
+}
+
+fn decompress_error(what: &str) -> EngineError {
+    EngineError::StorageIo(format!("WAL payload decompress: {}", what))
+}
+
+/// Compressors registered beyond the built-ins
+static REGISTERED: RwLock<Vec<Arc<dyn Compressor>>> = RwLock::new(Vec::new());
+
+/// Let readers in this process decompress the payloads of `compressor`.
+///
+/// # Errors
+/// `InvalidArgument` if its id is that of a built-in or of a compressor registered before.
+pub fn register_compressor(compressor: Arc<dyn Compressor>) -> Result<(), EngineError> {
+    let id = compressor.id();
+    let mut registered = REGISTERED.write().expect("compressor registry poisoned");
+    if id <= DEFLATE_ID || registered.iter().any(|c| c.id() == id) {
+        return Err(EngineError::InvalidArgument(format!("compressor id {} is already taken", id)));
+    }
+    registered.push(compressor);
+    Ok(())
+}
+
+/// Decompress a frame payload written by `Compression::compress`.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, EngineError> {
+    let (&id, rest) = data.split_first().ok_or_else(|| decompress_error("empty payload"))?;
+    match id {
+        0 => IdentityCompressor.decompress(rest),
+        #[cfg(feature = "deflate")]
+        DEFLATE_ID => DeflateCompressor.decompress(rest),
+        #[cfg(not(feature = "deflate"))]
+        DEFLATE_ID => Err(EngineError::NotImplemented(
+            "WAL payload compressed with DEFLATE: compiled without deflate support (feature `deflate`)".into(),
+        )),
+        _ => {
+            let registered = REGISTERED.read().expect("compressor registry poisoned");
+            let compressor = registered.iter().find(|c| c.id() == id)
+                .ok_or_else(|| decompress_error(&format!("unknown compressor id {}", id)))?;
+            check_decompressed(compressor.decompress(rest)?)
+        }
+    }
+}
+
+/// How a `WalWriter` compresses payloads; see `WalWriter::with_compression`.
+#[derive(Clone)]
+pub struct Compression {
+    compressor: Arc<dyn Compressor>,
+    threshold: usize,
+}
+
+impl Compression {
+    /// Compress with `compressor` the payloads larger than
+    /// `DEFAULT_COMPRESSION_THRESHOLD`.
+    pub fn new(compressor: Arc<dyn Compressor>) -> Self {
+        Self { compressor, threshold: DEFAULT_COMPRESSION_THRESHOLD }
+    }
+
+    /// `new` with `DeflateCompressor`.
+    #[cfg(feature = "deflate")]
+    pub fn deflate() -> Self {
+        Self::new(Arc::new(DeflateCompressor))
+    }
+
+    /// Compress only payloads larger than `threshold` bytes.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn compressor(&self) -> &Arc<dyn Compressor> {
+        &self.compressor
+    }
+
+    /// The frame payload for `payload`, if compressing it is due and makes it smaller.
+    /// Payloads over `MAX_DECOMPRESSED_PAYLOAD` stay plain, so readers can check it.
+    pub(crate) fn compress(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        if payload.len() <= self.threshold || payload.len() > MAX_DECOMPRESSED_PAYLOAD {
+            return None;
+        }
+        let mut out = vec![self.compressor.id()];
+        out.extend_from_slice(&self.compressor.compress(payload));
+        (out.len() < payload.len()).then_some(out)
+    }
+}
+
+impl std::fmt::Debug for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compression")
+            .field("compressor", &self.compressor.id())
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
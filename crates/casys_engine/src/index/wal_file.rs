@@ -0,0 +1,1337 @@
+//! WAL files: the records of a branch, appended under `<branch_dir>/wal/`
+//!
+//! Records go to numbered files `wal-000001.wal`, `wal-000002.wal`, ..., one
+//! `WalRecord::to_bytes` frame after another. `manifest.json` lists the files in order
+//! with the LSN of their first record, and holds the last checkpoint; the last file listed
+//! is the one being appended to. The writer starts a new file when the next frame would
+//! take the active one past its `max_file_bytes`, and on every checkpoint. A reader asked
+//! for records from some LSN on skips the files that end before it. The manifest is
+//! replaced by rename, and a new file is created before the manifest lists it, so a crash
+//! leaves either layout; files the manifest does not list are removed on the next open.
+//! Payloads are JSON unless the writer is set to `PayloadFormat::Binary`; readers take
+//! either, frame by frame. A writer given a `Compression` compresses large payloads, and
+//! flags their frames so readers decompress them.
+//!
+//! `WalWriter::checkpoint` flushes the store to segments, writes the LSN they reflect to
+//! the checkpoint segment, starts a new file and deletes the ones before it.
+//! `InMemoryGraphStore::recover` loads the segments and replays what follows.
+//!
+//! A writer given an `ArchiveHook` hands it each file it seals, once the next one is
+//! active, and records in the manifest the last LSN the hook accepted. A checkpoint keeps
+//! the files the hook has not accepted listed, ahead of its own, and they are deleted
+//! once it does.
+//!
+//! A branch written before rotation holds a single `current.wal` and no manifest. Readers
+//! read it as it is; the writer makes it the first numbered file.
+
+use super::persistence::WalRecord;
+use super::wal_archive::ArchiveHook;
+use super::wal_binary::PayloadFormat;
+use super::wal_compress::{self, Compression, COMPRESSED_FLAG};
+use super::wal_inspect::WalStats;
+use super::{InMemoryGraphStore, StoreOptions};
+use crate::types::{DatabaseName, EngineError};
+use casys_core::{SegmentId, SegmentStore};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Position of a record in the WAL of a branch, counting from 1.
+pub type Lsn = u64;
+
+/// When a `WalWriter` fsyncs its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// After every appended record
+    EveryRecord,
+    /// After every `n` appended records (`0` behaves like `1`)
+    EveryN(u32),
+    /// Only on an explicit `WalWriter::sync`
+    Manual,
+}
+
+/// What `WalReader::read_entries_with` and `InMemoryGraphStore::recover_with_policy` do
+/// with a record that is cut short, fails its checksum or does not decode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecoveryPolicy {
+    /// Fail with the record's error
+    Strict,
+    /// Accept a bad record with no valid one after it in the last file, as the torn
+    /// write of a crash, and fail on any other
+    #[default]
+    TolerateTail,
+    /// Skip every bad record and go on with the next valid one
+    SkipCorrupt,
+}
+
+/// Outcome of `InMemoryGraphStore::recover_with_policy`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecoveryReport {
+    /// WAL records replayed over the segments
+    pub applied: u64,
+    /// Bad records passed over, counting a stretch of bytes with no valid frame as one
+    pub skipped: u64,
+    /// Offset of the first bad record in its file, if there was one
+    pub first_error_offset: Option<u64>,
+    /// The file holding it
+    pub first_error_file: Option<PathBuf>,
+}
+
+/// A record read back from a WAL by `WalReader::entries`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalEntry {
+    pub lsn: Lsn,
+    pub record: WalRecord,
+    /// Bytes read to reach it: its frame, and a `Checkpoint` frame it came after
+    pub bytes: u64,
+}
+
+/// How far `InMemoryGraphStore::replay_wal_with` got.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayProgress {
+    /// Records applied
+    pub applied: u64,
+    /// Bytes of the entries read
+    pub bytes: u64,
+    /// LSN of the last entry read, 0 before any
+    pub last_lsn: Lsn,
+}
+
+/// A point up to which the segments reflect the WAL; see `WalWriter::checkpoint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Number of checkpoints taken on the branch so far, this one included
+    pub generation: u64,
+    /// Last record reflected in the segments
+    pub lsn: Lsn,
+}
+
+/// Directory holding the WAL files of the branch stored under `branch_dir`.
+pub fn wal_dir(branch_dir: &Path) -> PathBuf {
+    branch_dir.join("wal")
+}
+
+/// Size a `WalWriter` lets a file reach before starting the next, unless told otherwise.
+pub const DEFAULT_MAX_WAL_FILE_BYTES: u64 = 4 * 1024 * 1024;
+
+const MANIFEST_FILE: &str = "manifest.json";
+/// Single file of a branch written before rotation
+const LEGACY_FILE: &str = "current.wal";
+const CHECKPOINT_SEGMENT_ID: &str = "checkpoint";
+
+/// Frame header: payload length (u32 LE, its top bit `COMPRESSED_FLAG`), CRC32 of the
+/// LSN and payload (u32 LE), then the LSN (u64 LE).
+const FRAME_HEADER: usize = 16;
+/// Header of frames written before they carried an LSN: length, then CRC32 of the payload.
+const UNNUMBERED_FRAME_HEADER: usize = 8;
+
+/// One frame decoded from the start of some bytes.
+pub(crate) struct Frame<'a> {
+    /// `None` for a frame written before frames carried an LSN
+    pub(crate) lsn: Option<Lsn>,
+    /// As written: compressed if `compressed`
+    pub(crate) payload: &'a [u8],
+    pub(crate) compressed: bool,
+    /// Length of the whole frame
+    pub(crate) len: usize,
+}
+
+impl Frame<'_> {
+    /// Decode the frame's record, decompressing its payload first if need be.
+    pub(crate) fn record(&self) -> Result<WalRecord, EngineError> {
+        if self.compressed {
+            WalRecord::from_payload(&wal_compress::decompress(self.payload)?)
+        } else {
+            WalRecord::from_payload(self.payload)
+        }
+    }
+}
+
+/// `payload` framed with its length, checksum and `lsn`.
+pub(crate) fn encode_frame(lsn: Lsn, payload: &[u8]) -> Vec<u8> {
+    let mut frame = PendingFrame::new(payload);
+    frame.number(lsn);
+    frame.bytes
+}
+
+/// A frame built before its LSN is known, so a writer can serialize a record outside its
+/// lock and only number the frame under it, without another pass over the payload.
+pub(crate) struct PendingFrame {
+    /// The frame, its checksum and LSN filled in by `number`
+    bytes: Vec<u8>,
+    /// CRC32 of the payload alone
+    payload_crc: crc32fast::Hasher,
+}
+
+impl PendingFrame {
+    pub(crate) fn new(payload: &[u8]) -> Self {
+        Self::with_flags(payload, 0)
+    }
+
+    /// A frame for a payload written by `Compression::compress`.
+    pub(crate) fn compressed(payload: &[u8]) -> Self {
+        Self::with_flags(payload, COMPRESSED_FLAG)
+    }
+
+    fn with_flags(payload: &[u8], flags: u32) -> Self {
+        let mut bytes = Vec::with_capacity(FRAME_HEADER + payload.len());
+        bytes.extend_from_slice(&(payload.len() as u32 | flags).to_le_bytes());
+        bytes.resize(FRAME_HEADER, 0);
+        bytes.extend_from_slice(payload);
+        let mut payload_crc = crc32fast::Hasher::new();
+        payload_crc.update(payload);
+        Self { bytes, payload_crc }
+    }
+
+    /// Number the frame `lsn` and return it.
+    pub(crate) fn number(&mut self, lsn: Lsn) -> &[u8] {
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&lsn.to_le_bytes());
+        crc.combine(&self.payload_crc);
+        self.bytes[4..8].copy_from_slice(&crc.finalize().to_le_bytes());
+        self.bytes[8..FRAME_HEADER].copy_from_slice(&lsn.to_le_bytes());
+        &self.bytes
+    }
+
+    /// Size of the whole frame.
+    pub(crate) fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+/// `InvalidArgument` if a plain payload of `len` bytes would set `COMPRESSED_FLAG` in its
+/// frame's length, which readers would then take for a compressed payload.
+fn check_plain_payload(len: usize) -> Result<(), EngineError> {
+    if len >= COMPRESSED_FLAG as usize {
+        return Err(EngineError::InvalidArgument(format!(
+            "WAL payload of {} bytes is too long for a frame (at most {})",
+            len,
+            COMPRESSED_FLAG - 1
+        )));
+    }
+    Ok(())
+}
+
+/// The frame at the start of `data`, or `None` if it is cut short or fails its checksum.
+pub(crate) fn decode_frame(data: &[u8]) -> Option<Frame<'_>> {
+    let header = data.get(..FRAME_HEADER)?;
+    let len = u32::from_le_bytes(header[..4].try_into().ok()?);
+    let compressed = len & COMPRESSED_FLAG != 0;
+    let len = (len & !COMPRESSED_FLAG) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().ok()?);
+    let payload = data.get(FRAME_HEADER..FRAME_HEADER + len)?;
+    (crc32fast::hash(&data[8..FRAME_HEADER + len]) == crc).then(|| Frame {
+        lsn: Some(u64::from_le_bytes(header[8..].try_into().expect("8 bytes"))),
+        payload,
+        compressed,
+        len: FRAME_HEADER + len,
+    })
+}
+
+/// Like `decode_frame`, for a frame written before frames carried an LSN.
+pub(crate) fn decode_unnumbered_frame(data: &[u8]) -> Option<Frame<'_>> {
+    let header = data.get(..UNNUMBERED_FRAME_HEADER)?;
+    let len = u32::from_le_bytes(header[..4].try_into().ok()?) as usize;
+    let crc = u32::from_le_bytes(header[4..].try_into().ok()?);
+    let payload = data.get(UNNUMBERED_FRAME_HEADER..UNNUMBERED_FRAME_HEADER + len)?;
+    (crc32fast::hash(payload) == crc).then_some(Frame {
+        lsn: None,
+        payload,
+        compressed: false,
+        len: UNNUMBERED_FRAME_HEADER + len,
+    })
+}
+
+/// How the frames of a WAL file are laid out, from its first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileFormat {
+    /// Frames carrying their LSN
+    Numbered,
+    /// Frames without one, numbered by position
+    Unnumbered,
+    /// A single bare JSON record
+    Bare,
+}
+
+impl FileFormat {
+    pub(crate) fn of(data: &[u8]) -> Self {
+        if data.is_empty() || decode_frame(data).is_some() {
+            FileFormat::Numbered
+        } else if decode_unnumbered_frame(data).is_some() {
+            FileFormat::Unnumbered
+        } else if data[0] == b'{' {
+            FileFormat::Bare
+        } else {
+            FileFormat::Numbered
+        }
+    }
+
+    /// The frame at the start of `data` in this layout; a bare record is all of `data`.
+    pub(crate) fn decode(self, data: &[u8]) -> Option<Frame<'_>> {
+        match self {
+            FileFormat::Numbered => decode_frame(data),
+            FileFormat::Unnumbered => decode_unnumbered_frame(data),
+            FileFormat::Bare => Some(Frame { lsn: None, payload: data, compressed: false, len: data.len() }),
+        }
+    }
+
+    /// Length of the valid frame at the start of `data`, if there is one. A bare record
+    /// has no frame to resynchronize on.
+    fn frame_len(self, data: &[u8]) -> Option<usize> {
+        match self {
+            FileFormat::Bare => None,
+            _ => self.decode(data).map(|frame| frame.len),
+        }
+    }
+
+    /// Offset of the first valid frame in `data` after its start, if any.
+    pub(crate) fn resync(self, data: &[u8]) -> Option<usize> {
+        (1..data.len()).find(|i| self.frame_len(&data[*i..]).is_some())
+    }
+}
+
+fn file_name(seq: u64) -> String {
+    format!("wal-{:06}.wal", seq)
+}
+
+/// Sequence number of a numbered WAL file name.
+fn file_seq(name: &str) -> Option<u64> {
+    name.strip_prefix("wal-")?.strip_suffix(".wal")?.parse().ok()
+}
+
+pub(crate) fn io_error(what: &str, path: &Path, e: std::io::Error) -> EngineError {
+    EngineError::StorageIo(format!("{}({}): {e}", what, path.display()))
+}
+
+/// One file listed in the manifest.
+#[derive(Debug, Clone)]
+struct WalFile {
+    name: String,
+    first_lsn: Lsn,
+}
+
+/// `{"format_version": 1, "files": [{"name", "first_lsn"}, ...], "checkpoint": null | {"generation", "lsn"}, "archived_lsn"}`
+#[derive(Debug, Clone, Default)]
+struct WalManifest {
+    files: Vec<WalFile>,
+    checkpoint: Option<Checkpoint>,
+    /// Last LSN of the sealed files an `ArchiveHook` accepted, 0 before any
+    archived_lsn: Lsn,
+}
+
+impl WalManifest {
+    fn read(dir: &Path) -> Result<Option<Self>, EngineError> {
+        let path = dir.join(MANIFEST_FILE);
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error("read", &path, e)),
+        };
+        let bad = || EngineError::StorageIo(format!("invalid WAL manifest {}", path.display()));
+        let json: serde_json::Value = serde_json::from_slice(&data).map_err(|_| bad())?;
+        if json["format_version"].as_u64() != Some(1) {
+            return Err(EngineError::StorageIo(format!(
+                "unsupported WAL manifest format_version: {}",
+                json["format_version"]
+            )));
+        }
+        let files = json["files"].as_array().ok_or_else(bad)?.iter()
+            .map(|file| match (file["name"].as_str(), file["first_lsn"].as_u64()) {
+                (Some(name), Some(first_lsn)) => Ok(WalFile { name: name.to_string(), first_lsn }),
+                _ => Err(bad()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let checkpoint = match &json["checkpoint"] {
+            serde_json::Value::Null => None,
+            cp => Some(checkpoint_from_json(cp).ok_or_else(bad)?),
+        };
+        let archived_lsn = json["archived_lsn"].as_u64().unwrap_or(0);
+        Ok(Some(Self { files, checkpoint, archived_lsn }))
+    }
+
+    fn write(&self, dir: &Path) -> Result<(), EngineError> {
+        let files: Vec<serde_json::Value> = self.files.iter()
+            .map(|f| serde_json::json!({ "name": f.name, "first_lsn": f.first_lsn }))
+            .collect();
+        let checkpoint = self.checkpoint.map(|c| serde_json::json!({ "generation": c.generation, "lsn": c.lsn }));
+        let data = serde_json::to_vec(&serde_json::json!({
+            "format_version": 1,
+            "files": files,
+            "checkpoint": checkpoint,
+            "archived_lsn": self.archived_lsn,
+        }))
+            .map_err(|e| EngineError::StorageIo(format!("WAL manifest serialize: {e}")))?;
+        let path = dir.join(MANIFEST_FILE);
+        let tmp = dir.join(format!("{}.tmp", MANIFEST_FILE));
+        fs::write(&tmp, &data)
+            .and_then(|_| File::open(&tmp)?.sync_all())
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| io_error("write", &path, e))
+    }
+}
+
+fn checkpoint_from_json(json: &serde_json::Value) -> Option<Checkpoint> {
+    Some(Checkpoint { generation: json["generation"].as_u64()?, lsn: json["lsn"].as_u64()? })
+}
+
+/// The checkpoint last written to the segments by `WalWriter::checkpoint`, if any.
+pub fn read_checkpoint(store: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<Option<Checkpoint>, EngineError> {
+    let data = match store.read_segment(root, db, &SegmentId(CHECKPOINT_SEGMENT_ID.to_string())) {
+        Ok((data, _node_count, _edge_count)) => data,
+        Err(EngineError::NotFound(_)) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let json: serde_json::Value = serde_json::from_slice(&data)
+        .map_err(|e| EngineError::StorageIo(format!("checkpoint parse: {e}")))?;
+    match json["format_version"].as_u64() {
+        Some(1) => checkpoint_from_json(&json)
+            .map(Some)
+            .ok_or_else(|| EngineError::StorageIo(format!("invalid checkpoint: {}", json))),
+        other => Err(EngineError::StorageIo(format!("unsupported checkpoint format_version: {:?}", other))),
+    }
+}
+
+/// Appends `WalRecord`s to the WAL files of a branch.
+///
+/// Records reach the active file as they are appended; `SyncPolicy` only decides when
+/// they are fsynced. A file is always fsynced before the next one is started. An append
+/// that fails leaves the writer failed: later appends and `sync` return the same error
+/// without writing, so the log never holds a gap.
+///
+/// The writer can be shared, as an `Arc<WalWriter>`, by threads appending concurrently.
+/// Each serializes its record before taking the writer's lock, and holds it only to
+/// number the frame and write it, so LSNs follow the order of frames in the file. An
+/// append due for an fsync takes it after releasing the lock, one at a time: an fsync
+/// covers every frame written when it starts, so appenders that queued behind it find
+/// their records synced and return without another.
+pub struct WalWriter {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    policy: SyncPolicy,
+    format: PayloadFormat,
+    compression: Option<Compression>,
+    archive: Option<Arc<dyn ArchiveHook>>,
+    state: Mutex<WriterState>,
+    /// Held by the appender fsyncing the active file
+    syncing: Mutex<()>,
+    /// Held while the hook is handed sealed files
+    archiving: Mutex<()>,
+}
+
+/// What appends change, behind the writer's lock.
+struct WriterState {
+    manifest: WalManifest,
+    file: Arc<File>,
+    file_bytes: u64,
+    next_lsn: Lsn,
+    /// Last LSN known to be fsynced
+    synced_lsn: Lsn,
+    failed: Option<EngineError>,
+    archive_error: Option<EngineError>,
+    stats: WalStats,
+}
+
+impl WriterState {
+    fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.manifest.files.last().expect("the manifest lists the active file").name)
+    }
+
+    fn unsynced(&self) -> u64 {
+        self.next_lsn - 1 - self.synced_lsn
+    }
+
+    fn fail(&mut self, error: EngineError) -> EngineError {
+        self.failed = Some(error.clone());
+        error
+    }
+}
+
+impl WalWriter {
+    /// Open the WAL of the branch under `branch_dir` for appending, creating it if needed.
+    /// Files are started at `DEFAULT_MAX_WAL_FILE_BYTES`; see `with_max_file_bytes`.
+    ///
+    /// The records already written are kept and numbering continues after them.
+    /// Everything in the active file from its first bad frame on (a frame torn by a crash)
+    /// is cut off first, so new records are not appended behind bytes a `WalReader` would
+    /// stop at. An active file holding one unframed record is rewritten as a frame, and
+    /// one holding frames without LSNs is sealed so new frames go to a file of their own.
+    pub fn open(branch_dir: &Path, policy: SyncPolicy) -> Result<Self, EngineError> {
+        let dir = wal_dir(branch_dir);
+        fs::create_dir_all(&dir).map_err(|e| io_error("create_dir_all", &dir, e))?;
+        let manifest = match WalManifest::read(&dir)? {
+            Some(manifest) if !manifest.files.is_empty() => manifest,
+            _ => Self::start_manifest(&dir)?,
+        };
+        Self::remove_unlisted(&dir, &manifest)?;
+
+        let active = manifest.files.last().expect("the manifest lists the active file");
+        let path = dir.join(&active.name);
+        let (kept, valid_len, format, next_lsn) = match WalReader::open_file(&path, active.first_lsn) {
+            Ok(mut reader) => {
+                let kept = reader.read_entries()?;
+                (kept, reader.offset, reader.format, reader.next_lsn)
+            }
+            Err(EngineError::NotFound(_)) => (Vec::new(), 0, FileFormat::Numbered, active.first_lsn),
+            Err(e) => return Err(e),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)
+            .map_err(|e| io_error("open", &path, e))?;
+        let bare = format == FileFormat::Bare;
+        let mut file_bytes = if bare { 0 } else { valid_len };
+        file.set_len(file_bytes).map_err(|e| io_error("truncate", &path, e))?;
+        if bare {
+            for (lsn, record) in &kept {
+                let frame = record.to_frame(*lsn);
+                file.write_all(&frame).map_err(|e| io_error("write", &path, e))?;
+                file_bytes += frame.len() as u64;
+            }
+            file.sync_data().map_err(|e| io_error("fsync", &path, e))?;
+        }
+        let writer = Self {
+            dir,
+            max_file_bytes: DEFAULT_MAX_WAL_FILE_BYTES,
+            policy,
+            format: PayloadFormat::Json,
+            compression: None,
+            archive: None,
+            state: Mutex::new(WriterState {
+                manifest,
+                file: Arc::new(file),
+                file_bytes,
+                next_lsn,
+                synced_lsn: next_lsn - 1,
+                failed: None,
+                archive_error: None,
+                stats: WalStats::default(),
+            }),
+            syncing: Mutex::new(()),
+            archiving: Mutex::new(()),
+        };
+        if format == FileFormat::Unnumbered && file_bytes > 0 {
+            writer.start_file(&mut writer.lock(), None)?;
+        }
+        Ok(writer)
+    }
+
+    /// Write the manifest of a WAL that has none: its `current.wal`, if written before
+    /// rotation, becomes the first file.
+    fn start_manifest(dir: &Path) -> Result<WalManifest, EngineError> {
+        let first = dir.join(file_name(1));
+        let legacy = dir.join(LEGACY_FILE);
+        let mut manifest = WalManifest::default();
+        let mut first_lsn = 1;
+        match WalReader::open(&legacy) {
+            Ok(mut reader) => {
+                reader.read_all()?;
+                manifest.checkpoint = reader.checkpoint();
+                first_lsn = manifest.checkpoint.map_or(1, |c| c.lsn + 1);
+                fs::rename(&legacy, &first).map_err(|e| io_error("rename", &legacy, e))?;
+            }
+            Err(EngineError::NotFound(_)) => {
+                File::create(&first).map_err(|e| io_error("create", &first, e))?;
+            }
+            Err(e) => return Err(e),
+        }
+        manifest.files.push(WalFile { name: file_name(1), first_lsn });
+        manifest.write(dir)?;
+        Ok(manifest)
+    }
+
+    /// Delete the numbered files the manifest does not list: files a checkpoint made
+    /// obsolete, or a file a rotation created but never listed.
+    fn remove_unlisted(dir: &Path, manifest: &WalManifest) -> Result<(), EngineError> {
+        let entries = fs::read_dir(dir).map_err(|e| io_error("read_dir", dir, e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| io_error("read_dir", dir, e))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if file_seq(&name).is_some() && !manifest.files.iter().any(|f| f.name == name) {
+                fs::remove_file(entry.path()).map_err(|e| io_error("remove", &entry.path(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a new file once appending the next frame would take the active one past
+    /// `max_file_bytes`. A frame larger than that gets a file of its own.
+    pub fn with_max_file_bytes(mut self, max_file_bytes: u64) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Serialize the records appended from now on in `format` (JSON by default). A file
+    /// may mix frames of both formats.
+    pub fn with_payload_format(mut self, format: PayloadFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn payload_format(&self) -> PayloadFormat {
+        self.format
+    }
+
+    /// Compress the payloads appended from now on by `compression`, those above its
+    /// threshold that come out smaller. Readers decompress them whatever they are set to.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref()
+    }
+
+    /// `record`'s frame, its payload in the writer's format and compressed if due.
+    /// `InvalidArgument` if the payload stays plain and is too long for a frame.
+    fn frame(&self, record: &WalRecord) -> Result<PendingFrame, EngineError> {
+        let payload = record.payload_as(self.format);
+        match self.compression.as_ref().and_then(|c| c.compress(&payload)) {
+            Some(compressed) => Ok(PendingFrame::compressed(&compressed)),
+            None => {
+                check_plain_payload(payload.len())?;
+                Ok(PendingFrame::new(&payload))
+            }
+        }
+    }
+
+    /// Hand every file this writer seals to `hook`, on the appending thread, once the
+    /// next file is active. A hook that fails does not fail the append: the error is kept
+    /// for `archive_error`, and the file is handed again on the next rotation or
+    /// `archive_sealed`. Files sealed before the hook was set are handed over with them.
+    pub fn with_archive_hook(mut self, hook: Arc<dyn ArchiveHook>) -> Self {
+        self.archive = Some(hook);
+        self
+    }
+
+    /// Hand the hook, oldest first, the sealed files it has not accepted yet, stopping at
+    /// the first it rejects, then delete the accepted ones a checkpoint made obsolete. A
+    /// no-op without a hook. Appends go on while the hook runs.
+    ///
+    /// # Errors
+    /// The hook's error, also kept for `archive_error` until an attempt succeeds;
+    /// `StorageIo` if the manifest cannot be written.
+    pub fn archive_sealed(&self) -> Result<(), EngineError> {
+        let Some(hook) = self.archive.as_deref() else { return Ok(()) };
+        let _turn = self.archiving.lock().expect("WAL archive lock poisoned");
+        let (sealed, from) = {
+            let state = self.lock();
+            let files = &state.manifest.files;
+            let sealed: Vec<(PathBuf, Lsn, Lsn)> = files.iter().zip(&files[1..])
+                .map(|(file, next)| (self.dir.join(&file.name), file.first_lsn, next.first_lsn - 1))
+                .filter(|(_, _, last_lsn)| *last_lsn > state.manifest.archived_lsn)
+                .collect();
+            (sealed, state.manifest.archived_lsn)
+        };
+        let mut archived = from;
+        let mut result = Ok(());
+        for (path, first_lsn, last_lsn) in sealed {
+            // A file without records, such as one holding only a checkpoint, is passed over
+            if first_lsn <= last_lsn {
+                if let Err(e) = hook.on_rotate(&path, first_lsn, last_lsn) {
+                    result = Err(e);
+                    break;
+                }
+            }
+            archived = last_lsn;
+        }
+        let mut state = self.lock();
+        if archived > from {
+            if let Err(e) = self.record_archived(&mut state, archived) {
+                result = Err(e);
+            }
+        }
+        state.archive_error = result.as_ref().err().cloned();
+        result
+    }
+
+    /// Record in the manifest that the hook accepted every file up to `archived`, and
+    /// delete those a checkpoint made obsolete.
+    fn record_archived(&self, state: &mut WriterState, archived: Lsn) -> Result<(), EngineError> {
+        let mut manifest = state.manifest.clone();
+        manifest.archived_lsn = archived;
+        // Files before the checkpoint were only kept for the hook
+        let reflected = manifest.checkpoint.map_or(0, |c| c.lsn).min(archived);
+        let obsolete = (0..manifest.files.len() - 1)
+            .take_while(|i| manifest.files[i + 1].first_lsn - 1 <= reflected)
+            .count();
+        let obsolete: Vec<WalFile> = manifest.files.drain(..obsolete).collect();
+        manifest.write(&self.dir)?;
+        state.manifest = manifest;
+        for old in obsolete {
+            let path = self.dir.join(&old.name);
+            fs::remove_file(&path).map_err(|e| io_error("remove", &path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Error of the last attempt at archiving, if it failed.
+    pub fn archive_error(&self) -> Option<EngineError> {
+        self.lock().archive_error.clone()
+    }
+
+    /// Last LSN of the sealed files the hook accepted, 0 before any.
+    pub fn archived_lsn(&self) -> Lsn {
+        self.lock().manifest.archived_lsn
+    }
+
+    /// Path of the file being appended to.
+    pub fn path(&self) -> PathBuf {
+        self.lock().path(&self.dir)
+    }
+
+    /// Paths of every file of the log, oldest first, the active one last.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.lock().manifest.files.iter().map(|f| self.dir.join(&f.name)).collect()
+    }
+
+    pub fn policy(&self) -> SyncPolicy {
+        self.policy
+    }
+
+    /// LSN the next appended record gets.
+    pub fn next_lsn(&self) -> Lsn {
+        self.lock().next_lsn
+    }
+
+    /// Append `record` and return its LSN, fsyncing if the policy says so.
+    pub fn append(&self, record: &WalRecord) -> Result<Lsn, EngineError> {
+        let mut frame = self.frame(record)?;
+        let (lsn, unsynced, rotated) = {
+            let mut state = self.lock();
+            if let Some(e) = &state.failed {
+                return Err(e.clone());
+            }
+            let rotated = self.make_room(&mut state, frame.len())?;
+            let lsn = state.next_lsn;
+            self.write(&mut state, frame.number(lsn))?;
+            state.stats.count(lsn, record, frame.len() as u64);
+            state.next_lsn += 1;
+            (lsn, state.unsynced(), rotated)
+        };
+        if rotated {
+            // A rejected file is handed again later; the error is kept for archive_error
+            self.archive_sealed().ok();
+        }
+        let due = match self.policy {
+            SyncPolicy::EveryRecord => true,
+            SyncPolicy::EveryN(n) => unsynced >= u64::from(n.max(1)),
+            SyncPolicy::Manual => false,
+        };
+        if due {
+            self.sync_through(lsn)?;
+        }
+        Ok(lsn)
+    }
+
+    /// Append `records` with one write and one fsync, whatever the policy, and return the
+    /// LSN of the first; the others follow it. The group goes to a new file when it would
+    /// take the active one past `max_file_bytes`, and never straddles two files. Used by
+    /// `GroupCommitWriter`.
+    pub fn append_group(&self, records: &[WalRecord]) -> Result<Lsn, EngineError> {
+        let mut frames = records.iter()
+            .map(|record| self.frame(record))
+            .collect::<Result<Vec<_>, _>>()?;
+        let size = frames.iter().map(PendingFrame::len).sum();
+        let (first, rotated) = {
+            let mut state = self.lock();
+            if let Some(e) = &state.failed {
+                return Err(e.clone());
+            }
+            let rotated = self.make_room(&mut state, size)?;
+            let first = state.next_lsn;
+            let mut group = Vec::with_capacity(size);
+            for (lsn, frame) in (first..).zip(&mut frames) {
+                group.extend_from_slice(frame.number(lsn));
+            }
+            self.write(&mut state, &group)?;
+            for ((lsn, record), frame) in (first..).zip(records).zip(&frames) {
+                state.stats.count(lsn, record, frame.len() as u64);
+            }
+            state.next_lsn += records.len() as u64;
+            (first, rotated)
+        };
+        if rotated {
+            self.archive_sealed().ok();
+        }
+        self.sync_through(first + records.len() as u64 - 1)?;
+        Ok(first)
+    }
+
+    /// Start a new file if `size` more bytes would take the active one past
+    /// `max_file_bytes`, and say whether it did.
+    fn make_room(&self, state: &mut WriterState, size: usize) -> Result<bool, EngineError> {
+        if state.file_bytes == 0 || state.file_bytes + size as u64 <= self.max_file_bytes {
+            return Ok(false);
+        }
+        match self.start_file(state, None) {
+            Ok(()) => Ok(true),
+            Err(e) => Err(state.fail(e)),
+        }
+    }
+
+    fn write(&self, state: &mut WriterState, bytes: &[u8]) -> Result<(), EngineError> {
+        if let Err(e) = (&*state.file).write_all(bytes) {
+            return Err(state.fail(io_error("write", &state.path(&self.dir), e)));
+        }
+        state.file_bytes += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Fsync every record appended so far.
+    pub fn sync(&self) -> Result<(), EngineError> {
+        let last = self.lock().next_lsn - 1;
+        self.sync_through(last)
+    }
+
+    /// Fsync the active file unless an fsync already covered `lsn`. The fsync runs outside
+    /// the writer's lock, covering every frame written when it starts.
+    fn sync_through(&self, lsn: Lsn) -> Result<(), EngineError> {
+        let _turn = self.syncing.lock().expect("WAL sync lock poisoned");
+        let (file, path, last) = {
+            let state = self.lock();
+            if let Some(e) = &state.failed {
+                return Err(e.clone());
+            }
+            if state.synced_lsn >= lsn {
+                return Ok(());
+            }
+            (state.file.clone(), state.path(&self.dir), state.next_lsn - 1)
+        };
+        let result = file.sync_data();
+        let mut state = self.lock();
+        if let Err(e) = result {
+            return Err(state.fail(io_error("fsync", &path, e)));
+        }
+        state.synced_lsn = state.synced_lsn.max(last);
+        state.stats.synced();
+        Ok(())
+    }
+
+    /// What this writer appended since it was opened.
+    pub fn stats(&self) -> WalStats {
+        self.lock().stats.clone()
+    }
+
+    /// Number of appended records not fsynced yet.
+    pub fn unsynced(&self) -> u32 {
+        self.lock().unsynced() as u32
+    }
+
+    /// The last checkpoint taken on the branch, if any.
+    pub fn last_checkpoint(&self) -> Option<Checkpoint> {
+        self.lock().manifest.checkpoint
+    }
+
+    /// Flush `store` to segments and drop the WAL files it now reflects.
+    ///
+    /// `store` must hold every record appended so far; appends wait until the checkpoint
+    /// is taken. The records are fsynced, the segments written, then a `Checkpoint` with
+    /// the last LSN is written to the checkpoint segment. Last, a new file is started with
+    /// the matching `WalRecord::Checkpoint`, the manifest is replaced to list only that
+    /// file, and the older files are deleted. A crash before the checkpoint segment is
+    /// written leaves the previous checkpoint in place, and `InMemoryGraphStore::recover`
+    /// replays records the new segments already reflect; replay is idempotent, so that
+    /// yields the same graph. A crash before the manifest is replaced leaves the old
+    /// files, whose records are all at or before the new checkpoint.
+    pub fn checkpoint(
+        &self,
+        store: &InMemoryGraphStore,
+        segments: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+    ) -> Result<(), EngineError> {
+        {
+            let mut state = self.lock();
+            if let Some(e) = &state.failed {
+                return Err(e.clone());
+            }
+            if state.unsynced() > 0 {
+                if let Err(e) = state.file.sync_data() {
+                    let error = io_error("fsync", &state.path(&self.dir), e);
+                    return Err(state.fail(error));
+                }
+                state.synced_lsn = state.next_lsn - 1;
+                state.stats.synced();
+            }
+            store.flush(segments, root, db)?;
+            let checkpoint = Checkpoint {
+                generation: state.manifest.checkpoint.map_or(0, |c| c.generation) + 1,
+                lsn: state.next_lsn - 1,
+            };
+            let data = serde_json::to_vec(&serde_json::json!({
+                "format_version": 1,
+                "generation": checkpoint.generation,
+                "lsn": checkpoint.lsn,
+            })).map_err(|e| EngineError::StorageIo(format!("checkpoint serialize: {e}")))?;
+            segments.write_segment(root, db, &SegmentId(CHECKPOINT_SEGMENT_ID.to_string()), &data, 0, 0)?;
+            self.start_file(&mut state, Some(checkpoint))?;
+        }
+        self.archive_sealed().ok();
+        Ok(())
+    }
+
+    /// Seal the active file and append to a new one, listed in the manifest. With a
+    /// checkpoint the new file starts with its record and replaces every older file.
+    /// The caller hands the sealed file to the hook once the lock is released.
+    fn start_file(&self, state: &mut WriterState, checkpoint: Option<Checkpoint>) -> Result<(), EngineError> {
+        state.file.sync_data().map_err(|e| io_error("fsync", &state.path(&self.dir), e))?;
+        state.synced_lsn = state.next_lsn - 1;
+        state.stats.synced();
+        let seq = state.manifest.files.last().and_then(|f| file_seq(&f.name)).unwrap_or(0) + 1;
+        let name = file_name(seq);
+        let path = self.dir.join(&name);
+        let mut file = File::create(&path).map_err(|e| io_error("create", &path, e))?;
+        let mut file_bytes = 0;
+        if let Some(cp) = checkpoint {
+            let frame = WalRecord::Checkpoint { generation: cp.generation, lsn: cp.lsn }.to_frame_as(cp.lsn, self.format);
+            file.write_all(&frame).map_err(|e| io_error("write", &path, e))?;
+            file_bytes = frame.len() as u64;
+        }
+        file.sync_all().map_err(|e| io_error("fsync", &path, e))?;
+
+        let mut manifest = state.manifest.clone();
+        let obsolete = if checkpoint.is_some() {
+            manifest.checkpoint = checkpoint;
+            // With a hook, the files it has not accepted stay listed until it does
+            let unarchived = manifest.files.iter()
+                .position(|f| self.archive.is_some() && f.first_lsn > manifest.archived_lsn && f.first_lsn < state.next_lsn)
+                .unwrap_or(manifest.files.len());
+            manifest.files.drain(..unarchived).collect()
+        } else {
+            Vec::new()
+        };
+        manifest.files.push(WalFile { name, first_lsn: state.next_lsn });
+        manifest.write(&self.dir)?;
+        state.manifest = manifest;
+        state.file = Arc::new(file);
+        state.file_bytes = file_bytes;
+        for old in obsolete {
+            let path = self.dir.join(&old.name);
+            fs::remove_file(&path).map_err(|e| io_error("remove", &path, e))?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WriterState> {
+        self.state.lock().expect("WAL writer lock poisoned")
+    }
+}
+
+/// Reads back the records of a WAL, in order, from one file or across the files of a
+/// branch.
+///
+/// Reading stops after the last file or at the first frame that is cut short or fails
+/// its checksum. A torn last frame is what a crash mid-write leaves, so `read_all` returns
+/// the records before it and `corruption` tells where it stopped. A file holding one bare
+/// JSON record, as written before framing, reads as that single record.
+pub struct WalReader {
+    /// Files still to read, with the LSN of their first record
+    pending: VecDeque<(PathBuf, Lsn)>,
+    /// Records before this LSN are skipped
+    from: Lsn,
+    /// File being read
+    path: Option<PathBuf>,
+    data: Vec<u8>,
+    pub(crate) offset: u64,
+    pub(crate) next_lsn: Lsn,
+    read: u64,
+    /// Bytes of the frames read, across files
+    consumed: u64,
+    pub(crate) format: FileFormat,
+    corruption: Option<u64>,
+    skipped: u64,
+    first_error: Option<(PathBuf, u64)>,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl WalReader {
+    /// Read the single WAL file at `path`. Records carry their LSN; in a file written
+    /// before they did, they are numbered from 1, or from the LSN of a `Checkpoint` record
+    /// the file starts with.
+    ///
+    /// # Errors
+    /// `NotFound` if there is no such file, `StorageIo` if it cannot be read.
+    pub fn open(path: &Path) -> Result<Self, EngineError> {
+        Self::open_file(path, 1)
+    }
+
+    /// Read WAL frames from `input`, such as an archived file, as `open` reads a file.
+    ///
+    /// # Errors
+    /// `StorageIo` if `input` cannot be read.
+    pub fn from_reader(mut input: impl Read) -> Result<Self, EngineError> {
+        let mut reader = Self::over(VecDeque::new(), 1);
+        input.read_to_end(&mut reader.data)
+            .map_err(|e| EngineError::StorageIo(format!("read WAL input: {e}")))?;
+        reader.format = FileFormat::of(&reader.data);
+        Ok(reader)
+    }
+
+    fn open_file(path: &Path, first_lsn: Lsn) -> Result<Self, EngineError> {
+        let mut reader = Self::over(VecDeque::new(), 1);
+        reader.load(path, first_lsn)?;
+        Ok(reader)
+    }
+
+    /// Read the records of the branch under `branch_dir` with an LSN of at least `from`,
+    /// across its files, skipping the files that end before `from`. A branch without a
+    /// WAL has no records.
+    ///
+    /// # Errors
+    /// `NotFound` if a file the manifest lists is missing, `StorageIo` if the manifest or a
+    /// file cannot be read.
+    pub fn open_branch(branch_dir: &Path, from: Lsn) -> Result<Self, EngineError> {
+        let dir = wal_dir(branch_dir);
+        let Some(manifest) = WalManifest::read(&dir)? else {
+            let legacy = dir.join(LEGACY_FILE);
+            let pending = if legacy.exists() { VecDeque::from([(legacy, 1)]) } else { VecDeque::new() };
+            return Ok(Self::over(pending, from));
+        };
+        let files = &manifest.files;
+        let pending = files.iter().enumerate()
+            .filter(|(i, _)| files.get(i + 1).is_none_or(|next| next.first_lsn > from))
+            .map(|(_, f)| (dir.join(&f.name), f.first_lsn))
+            .collect();
+        let mut reader = Self::over(pending, from);
+        reader.checkpoint = manifest.checkpoint;
+        Ok(reader)
+    }
+
+    fn over(pending: VecDeque<(PathBuf, Lsn)>, from: Lsn) -> Self {
+        Self {
+            pending,
+            from,
+            path: None,
+            data: Vec::new(),
+            offset: 0,
+            next_lsn: 1,
+            read: 0,
+            consumed: 0,
+            format: FileFormat::Numbered,
+            corruption: None,
+            skipped: 0,
+            first_error: None,
+            checkpoint: None,
+        }
+    }
+
+    /// Make `path` the file being read.
+    fn load(&mut self, path: &Path, first_lsn: Lsn) -> Result<(), EngineError> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(EngineError::NotFound(format!("WAL file {}", path.display())));
+            }
+            Err(e) => return Err(io_error("open", path, e)),
+        };
+        self.data.clear();
+        file.read_to_end(&mut self.data).map_err(|e| io_error("read", path, e))?;
+        self.offset = 0;
+        self.next_lsn = first_lsn;
+        self.format = FileFormat::of(&self.data);
+        self.path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// The next record and its LSN, or `None` after the last one. `Checkpoint` records are
+    /// not returned.
+    ///
+    /// # Errors
+    /// `WalCorruption` at a frame that is cut short or fails its checksum, with its offset
+    /// in the file being read; `StorageIo` if a complete frame does not decode. The
+    /// reader returns `None` after either.
+    pub fn next_record(&mut self) -> Result<Option<(Lsn, WalRecord)>, EngineError> {
+        loop {
+            match self.next_frame()? {
+                Some((_, WalRecord::Checkpoint { generation, lsn })) => {
+                    self.checkpoint = Some(Checkpoint { generation, lsn });
+                    self.next_lsn = lsn + 1;
+                }
+                Some((lsn, record)) => {
+                    let lsn = lsn.unwrap_or(self.next_lsn);
+                    self.next_lsn = lsn + 1;
+                    if lsn >= self.from {
+                        self.read += 1;
+                        return Ok(Some((lsn, record)));
+                    }
+                }
+                None => match self.pending.pop_front() {
+                    Some((path, first_lsn)) if self.corruption.is_none() => self.load(&path, first_lsn)?,
+                    _ => return Ok(None),
+                },
+            }
+        }
+    }
+
+    /// The next frame's record and the LSN it carries, if any.
+    fn next_frame(&mut self) -> Result<Option<(Option<Lsn>, WalRecord)>, EngineError> {
+        let rest = &self.data[self.offset as usize..];
+        if rest.is_empty() || self.corruption.is_some() {
+            return Ok(None);
+        }
+        let Some(frame) = self.format.decode(rest) else {
+            self.corruption = Some(self.offset);
+            return Err(EngineError::WalCorruption { offset: self.offset });
+        };
+        let record = match frame.record() {
+            Ok(record) => record,
+            Err(e) => {
+                self.corruption = Some(self.offset);
+                return Err(e);
+            }
+        };
+        self.offset += frame.len as u64;
+        self.consumed += frame.len as u64;
+        Ok(Some((frame.lsn, record)))
+    }
+
+    /// Move past the bad record reading stopped at, to the next valid frame of the file
+    /// or, if there is none, its end. Returns whether a valid frame follows. A frame
+    /// whose payload did not decode is skipped whole; otherwise the next offset where a
+    /// frame passes its checksum is searched for. In a file written before frames carried
+    /// an LSN, what is skipped counts as one record for the numbering.
+    fn skip_corrupt(&mut self) -> bool {
+        let Some(at) = self.corruption.take() else { return false };
+        let at = at as usize;
+        let resume = match self.format.frame_len(&self.data[at..]) {
+            Some(len) => Some(at + len),
+            None => self.format.resync(&self.data[at..]).map(|next| at + next),
+        };
+        self.next_lsn += 1;
+        match resume {
+            Some(next) => {
+                self.offset = next as u64;
+                next < self.data.len()
+            }
+            None => {
+                self.offset = self.data.len() as u64;
+                false
+            }
+        }
+    }
+
+    /// The remaining records as they are read, with the bytes each took, for
+    /// `InMemoryGraphStore::replay_wal_with`. Like the reader itself, this ends after the
+    /// first bad frame, with its error.
+    pub fn entries(&mut self) -> impl Iterator<Item = Result<WalEntry, EngineError>> + '_ {
+        std::iter::from_fn(move || {
+            let before = self.consumed;
+            let entry = self.next_record().transpose()?;
+            Some(entry.map(|(lsn, record)| WalEntry { lsn, record, bytes: self.consumed - before }))
+        })
+    }
+
+    /// Every remaining record up to the end of the log or the first bad frame.
+    ///
+    /// # Errors
+    /// `StorageIo` if a complete frame does not decode.
+    pub fn read_all(&mut self) -> Result<Vec<WalRecord>, EngineError> {
+        Ok(self.read_entries()?.into_iter().map(|(_, record)| record).collect())
+    }
+
+    /// `read_all`, with the LSN of each record, for `InMemoryGraphStore::replay_wal_from`.
+    pub fn read_entries(&mut self) -> Result<Vec<(Lsn, WalRecord)>, EngineError> {
+        let mut records = Vec::new();
+        loop {
+            match self.next_record() {
+                Ok(Some(entry)) => records.push(entry),
+                Ok(None) | Err(EngineError::WalCorruption { .. }) => return Ok(records),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// `read_entries`, handling bad records by `policy`; `skipped` and `first_error` tell
+    /// what was passed over. `TolerateTail` stops at a tolerated torn record, like
+    /// `read_entries`.
+    ///
+    /// # Errors
+    /// The error of the first bad record under `Strict`, or of the first one that is not
+    /// a torn tail under `TolerateTail`; `NotFound` or `StorageIo` if a file cannot be read.
+    pub fn read_entries_with(&mut self, policy: RecoveryPolicy) -> Result<Vec<(Lsn, WalRecord)>, EngineError> {
+        let mut records = Vec::new();
+        loop {
+            let error = match self.next_record() {
+                Ok(Some(entry)) => {
+                    records.push(entry);
+                    continue;
+                }
+                Ok(None) => return Ok(records),
+                Err(e) => e,
+            };
+            // Errors without a bad record are the files' own
+            let Some(at) = self.corruption else { return Err(error) };
+            let path = self.path.clone().unwrap_or_default();
+            self.first_error.get_or_insert((path, at));
+            if policy == RecoveryPolicy::Strict {
+                return Err(error);
+            }
+            self.skipped += 1;
+            let resumed = self.skip_corrupt();
+            if policy == RecoveryPolicy::TolerateTail && (resumed || !self.pending.is_empty()) {
+                return Err(error);
+            }
+        }
+    }
+
+    /// Number of bad records `read_entries_with` passed over.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// File and offset of the first bad record `read_entries_with` met, if any.
+    pub fn first_error(&self) -> Option<(&Path, u64)> {
+        self.first_error.as_ref().map(|(path, at)| (path.as_path(), *at))
+    }
+
+    /// Number of records returned so far.
+    pub fn valid_records(&self) -> u64 {
+        self.read
+    }
+
+    /// Offset, in the file being read, of the bad frame reading stopped at, if it did.
+    pub fn corruption(&self) -> Option<u64> {
+        self.corruption
+    }
+
+    /// The last checkpoint of the branch, or the one the file starts from once read past.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpoint
+    }
+}
+
+impl Iterator for WalReader {
+    type Item = Result<(Lsn, WalRecord), EngineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Where the LSN the segments reflect comes from, for the errors of `recover`.
+fn describe_checkpoint(checkpoint: Option<Checkpoint>) -> String {
+    match checkpoint {
+        Some(c) => format!("checkpoint {} at LSN {}", c.generation, c.lsn),
+        None => "no checkpoint".to_string(),
+    }
+}
+
+impl InMemoryGraphStore {
+    /// Rebuild the graph at startup: load the segments, then replay the WAL records of the
+    /// branch under `branch_dir` after the last LSN the segments reflect: the later of the
+    /// checkpoint and the `last_applied_lsn` they were flushed with (all records without
+    /// either). A torn last record is dropped, and so are the records of transactions
+    /// without a `CommitTxn`.
+    ///
+    /// The segments and the WAL must meet: the WAL must hold every record after the
+    /// segments' LSN, and reach it. Otherwise recovery fails instead of returning a store
+    /// missing records, or one whose next records would reuse LSNs the segments already
+    /// claim.
+    ///
+    /// # Errors
+    /// `WalCorruption` or `StorageIo` for a bad record before the end of the log; see
+    /// `recover_with_policy` to skip it instead. `StorageIo` if the WAL starts after the
+    /// segments' LSN, as when segments older than the WAL's checkpoint are restored, or
+    /// ends before it, as when the WAL was deleted or replaced by an older copy.
+    pub fn recover(
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        branch_dir: &Path,
+        options: StoreOptions,
+    ) -> Result<Self, EngineError> {
+        Self::recover_with_policy(store, root, db, branch_dir, options, RecoveryPolicy::TolerateTail)
+            .map(|(graph, _)| graph)
+    }
+
+    /// `recover`, handling bad WAL records by `policy`, with a report of what was
+    /// replayed and what was skipped.
+    ///
+    /// `WalWriter::open` cuts the active file at its first bad record, so after records
+    /// were skipped, `flush` the recovered store before opening a writer on the branch.
+    pub fn recover_with_policy(
+        store: &dyn SegmentStore,
+        root: &Path,
+        db: &DatabaseName,
+        branch_dir: &Path,
+        options: StoreOptions,
+        policy: RecoveryPolicy,
+    ) -> Result<(Self, RecoveryReport), EngineError> {
+        let mut graph = Self::load_with_options(store, root, db, options)?;
+        let checkpoint = read_checkpoint(store, root, db)?;
+        let after = checkpoint.map_or(0, |c| c.lsn).max(graph.last_applied_lsn);
+        let dir = wal_dir(branch_dir);
+        let first_lsn = WalManifest::read(&dir)?.and_then(|m| m.files.first().map(|f| (f.first_lsn, m.checkpoint)));
+        if let Some((first_lsn, wal_checkpoint)) = first_lsn.filter(|(first_lsn, _)| *first_lsn > after + 1) {
+            let taken = wal_checkpoint.map_or(String::new(), |c| format!(" by checkpoint {} at LSN {}", c.generation, c.lsn));
+            return Err(EngineError::StorageIo(format!(
+                "cannot recover {}: the segments reflect the WAL up to LSN {} ({}), but the WAL under {} \
+                 starts at LSN {}: records {}..={} were deleted{}. The segments are older than the WAL; \
+                 restore the segments written by its last checkpoint, or the WAL files holding those records",
+                db.as_str(), after, describe_checkpoint(checkpoint), dir.display(),
+                first_lsn, after + 1, first_lsn - 1, taken,
+            )));
+        }
+        let mut reader = WalReader::open_branch(branch_dir, after + 1)?;
+        let records = reader.read_entries_with(policy)?;
+        let end = reader.next_lsn - 1;
+        if end < after {
+            return Err(EngineError::StorageIo(format!(
+                "cannot recover {}: the segments reflect the WAL up to LSN {} ({}), but the WAL under {} \
+                 ends at LSN {}: records the segments hold are missing from it, and new records would reuse \
+                 their LSNs. The WAL was deleted, or replaced by an older copy; restore its files",
+                db.as_str(), after, describe_checkpoint(checkpoint), dir.display(), end,
+            )));
+        }
+        let applied = graph.replay_wal_from(&records, after)?;
+        let (first_error_file, first_error_offset) = reader.first_error()
+            .map(|(path, at)| (path.to_path_buf(), at))
+            .unzip();
+        let report = RecoveryReport { applied, skipped: reader.skipped(), first_error_offset, first_error_file };
+        Ok((graph, report))
+    }
+
+    /// `WalWriter::checkpoint` with the attached writer.
+    ///
+    /// # Errors
+    /// `InvalidArgument` if no writer is attached, if a WAL transaction is open, or if one
+    /// was aborted: the segments would hold records its abort or a crash must discard.
+    pub fn checkpoint(&mut self, segments: &dyn SegmentStore, root: &Path, db: &DatabaseName) -> Result<(), EngineError> {
+        if let Some(open) = self.wal_txn {
+            return Err(EngineError::InvalidArgument(format!("WAL transaction {} is still open", open)));
+        }
+        if let Some(aborted) = self.aborted_txn {
+            return Err(EngineError::InvalidArgument(format!(
+                "WAL transaction {} was aborted and its mutations are still applied; recover the store to drop them",
+                aborted
+            )));
+        }
+        let writer = self.wal_writer.as_ref()
+            .ok_or_else(|| EngineError::InvalidArgument("no WAL writer attached".into()))?;
+        writer.checkpoint(self, segments, root, db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_payloads_must_stay_below_the_compressed_flag() {
+        // The length field a plain payload of `COMPRESSED_FLAG` bytes would get reads back
+        // as an empty compressed payload
+        let mut frame = PendingFrame::with_flags(&[], COMPRESSED_FLAG);
+        let bytes = frame.number(7).to_vec();
+        assert_eq!(u32::from_le_bytes(bytes[..4].try_into().unwrap()), COMPRESSED_FLAG);
+        let decoded = decode_frame(&bytes).expect("frame decodes");
+        assert!(decoded.compressed);
+        assert!(decoded.payload.is_empty());
+
+        assert!(matches!(
+            check_plain_payload(COMPRESSED_FLAG as usize),
+            Err(EngineError::InvalidArgument(_))
+        ));
+        assert!(check_plain_payload(COMPRESSED_FLAG as usize - 1).is_ok());
+    }
+}
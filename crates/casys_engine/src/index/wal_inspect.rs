@@ -0,0 +1,328 @@
+//! WAL statistics and inspection
+//!
+//! `WalStats` counts what a `WalWriter` appends, for monitoring. `inspect_wal` scans one
+//! WAL file without applying it and reports what it holds and what is wrong with it,
+//! which is also how an archived or backed-up file is checked. It walks the file with
+//! `WalFrames`, which, unlike `WalReader`, yields every frame with its offset and size
+//! and reports the bytes that are not a valid frame instead of stopping at them.
+//! `dump_wal` prints the records of a file the same way, for reading or for export.
+
+use super::persistence::WalRecord;
+use super::wal_file::{io_error, Checkpoint, FileFormat, Lsn};
+use crate::types::EngineError;
+use casys_core::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// What a `WalWriter` appended since it was opened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalStats {
+    pub records: u64,
+    /// Size of their frames
+    pub bytes: u64,
+    /// Records by `WalRecord::type_name`
+    pub by_type: BTreeMap<&'static str, u64>,
+    pub first_lsn: Option<Lsn>,
+    pub last_lsn: Option<Lsn>,
+    /// When a file was last fsynced
+    pub last_sync: Option<SystemTime>,
+}
+
+impl WalStats {
+    pub(crate) fn count(&mut self, lsn: Lsn, record: &WalRecord, bytes: u64) {
+        self.records += 1;
+        self.bytes += bytes;
+        *self.by_type.entry(record.type_name()).or_default() += 1;
+        self.first_lsn.get_or_insert(lsn);
+        self.last_lsn = Some(lsn);
+    }
+
+    pub(crate) fn synced(&mut self) {
+        self.last_sync = Some(SystemTime::now());
+    }
+}
+
+/// One valid frame of a WAL file, as yielded by `WalFrames`.
+#[derive(Debug, Clone)]
+pub struct WalFrame {
+    /// Offset of the frame in the file
+    pub offset: u64,
+    /// Size of the whole frame
+    pub len: u64,
+    /// The LSN in its header; `None` in a file written before frames carried one
+    pub lsn: Option<Lsn>,
+    /// Whether its payload is compressed; `len` is the compressed size
+    pub compressed: bool,
+    /// Its record, or why the payload does not decode
+    pub record: Result<WalRecord, EngineError>,
+}
+
+/// Something wrong in a WAL file, as reported by `inspect_wal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalAnomaly {
+    /// `len` bytes at `offset` that are no valid frame (cut short or failing their
+    /// checksum), up to the next valid frame or the end of the file
+    BadFrame { offset: u64, len: u64 },
+    /// A frame whose checksum holds but whose payload does not decode
+    BadPayload { offset: u64, error: String },
+    /// A frame numbered `found` where the previous one calls for `expected`
+    LsnGap { offset: u64, expected: Lsn, found: Lsn },
+}
+
+/// The frames of one WAL file, in order. A stretch of bytes holding no valid frame is
+/// yielded as one `WalAnomaly::BadFrame`, and the walk goes on at the next valid frame.
+pub struct WalFrames {
+    data: Vec<u8>,
+    offset: usize,
+    format: FileFormat,
+}
+
+impl WalFrames {
+    /// Walk the WAL file at `path`.
+    ///
+    /// # Errors
+    /// `NotFound` if there is no such file, `StorageIo` if it cannot be read.
+    pub fn open(path: &Path) -> Result<Self, EngineError> {
+        match fs::read(path) {
+            Ok(data) => Ok(Self::from_bytes(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(EngineError::NotFound(format!("WAL file {}", path.display())))
+            }
+            Err(e) => Err(io_error("read", path, e)),
+        }
+    }
+
+    /// Walk WAL file contents already in memory.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        let format = FileFormat::of(&data);
+        Self { data, offset: 0, format }
+    }
+
+    /// Size of the file being walked.
+    pub fn file_len(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+impl Iterator for WalFrames {
+    type Item = Result<WalFrame, WalAnomaly>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.data[self.offset..];
+        if rest.is_empty() {
+            return None;
+        }
+        let offset = self.offset as u64;
+        let Some(frame) = self.format.decode(rest) else {
+            let len = self.format.resync(rest).unwrap_or(rest.len());
+            self.offset += len;
+            return Some(Err(WalAnomaly::BadFrame { offset, len: len as u64 }));
+        };
+        let item = WalFrame {
+            offset,
+            len: frame.len as u64,
+            lsn: frame.lsn,
+            compressed: frame.compressed,
+            record: frame.record(),
+        };
+        self.offset += frame.len;
+        Some(Ok(item))
+    }
+}
+
+/// What `inspect_wal` found in a WAL file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalInspection {
+    /// Records that decode, the `Checkpoint` record a file may start with aside
+    pub records: u64,
+    /// Size of the file
+    pub bytes: u64,
+    /// Records by `WalRecord::type_name`
+    pub by_type: BTreeMap<&'static str, u64>,
+    pub first_lsn: Option<Lsn>,
+    pub last_lsn: Option<Lsn>,
+    /// The checkpoint the file starts after, if it holds one
+    pub checkpoint: Option<Checkpoint>,
+    /// In file order
+    pub anomalies: Vec<WalAnomaly>,
+}
+
+impl WalInspection {
+    /// Whether the file holds nothing but valid, consecutively numbered frames.
+    pub fn is_clean(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+/// Scan the WAL file at `path` without applying it.
+///
+/// Records are numbered as `WalReader` numbers them: by their header or, in a file
+/// written before frames carried an LSN, by position from 1 or after the file's
+/// checkpoint, a stretch of bad bytes counting as one record. LSNs are only checked for
+/// gaps between frames with nothing wrong between them.
+///
+/// # Errors
+/// `NotFound` if there is no such file, `StorageIo` if it cannot be read. What is wrong
+/// inside the file is reported in `WalInspection::anomalies`.
+pub fn inspect_wal(path: &Path) -> Result<WalInspection, EngineError> {
+    let frames = WalFrames::open(path)?;
+    let mut report = WalInspection { bytes: frames.file_len(), ..WalInspection::default() };
+    // LSN of the next frame, checked once a valid frame precedes it
+    let mut next: Lsn = 1;
+    let mut checked = false;
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(anomaly) => {
+                report.anomalies.push(anomaly);
+                next += 1;
+                checked = false;
+                continue;
+            }
+        };
+        if let Some(found) = frame.lsn.filter(|found| checked && *found != next) {
+            report.anomalies.push(WalAnomaly::LsnGap { offset: frame.offset, expected: next, found });
+        }
+        let lsn = frame.lsn.unwrap_or(next);
+        checked = frame.record.is_ok();
+        match frame.record {
+            Ok(WalRecord::Checkpoint { generation, lsn }) => {
+                report.checkpoint = Some(Checkpoint { generation, lsn });
+                next = lsn + 1;
+            }
+            Ok(record) => {
+                report.records += 1;
+                *report.by_type.entry(record.type_name()).or_default() += 1;
+                report.first_lsn.get_or_insert(lsn);
+                report.last_lsn = Some(lsn);
+                next = lsn + 1;
+            }
+            Err(e) => {
+                report.anomalies.push(WalAnomaly::BadPayload { offset: frame.offset, error: e.to_string() });
+                next = lsn + 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// How `dump_wal` prints records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// `<lsn> <type> <key fields>`, such as `7 set_node_property id=3 key=name`. Property
+    /// values are left out. A line starting with `!` marks what could not be read.
+    #[default]
+    Text,
+    /// `{"lsn": 7, "record": {...}}`, the record as its JSON payload holds it. What could
+    /// not be read is `{"error": "bad_frame" | "bad_payload", "offset": ..., ...}`.
+    JsonLines,
+}
+
+/// Print the records of the WAL file at `path` to `out`, one line each, numbered as
+/// `inspect_wal` numbers them. Bad bytes and payloads that do not decode get a marker
+/// line, and the dump goes on at the next valid frame.
+///
+/// # Errors
+/// `NotFound` if there is no such file, `StorageIo` if it cannot be read or `out` fails.
+pub fn dump_wal(path: &Path, out: &mut dyn Write, format: DumpFormat) -> Result<(), EngineError> {
+    let mut next: Lsn = 1;
+    for frame in WalFrames::open(path)? {
+        let line = match frame {
+            Ok(frame) => {
+                let lsn = frame.lsn.unwrap_or(next);
+                // The records after a checkpoint are numbered from its LSN on
+                next = match &frame.record {
+                    Ok(WalRecord::Checkpoint { lsn: at, .. }) => at + 1,
+                    _ => lsn + 1,
+                };
+                match (frame.record, format) {
+                    (Ok(record), DumpFormat::Text) => format!("{} {}", lsn, text_line(&record)),
+                    (Ok(record), DumpFormat::JsonLines) => {
+                        serde_json::json!({ "lsn": lsn, "record": record.json() }).to_string()
+                    }
+                    (Err(e), DumpFormat::Text) => {
+                        format!("! offset {}: record {} does not decode: {}", frame.offset, lsn, e)
+                    }
+                    (Err(e), DumpFormat::JsonLines) => serde_json::json!({
+                        "error": "bad_payload",
+                        "offset": frame.offset,
+                        "lsn": lsn,
+                        "message": e.to_string(),
+                    }).to_string(),
+                }
+            }
+            Err(anomaly) => {
+                next += 1;
+                let WalAnomaly::BadFrame { offset, len } = anomaly else {
+                    unreachable!("WalFrames only yields bad frames")
+                };
+                match format {
+                    DumpFormat::Text => format!("! offset {}: {} bytes hold no valid frame", offset, len),
+                    DumpFormat::JsonLines => {
+                        serde_json::json!({ "error": "bad_frame", "offset": offset, "len": len }).to_string()
+                    }
+                }
+            }
+        };
+        writeln!(out, "{}", line).map_err(|e| EngineError::StorageIo(format!("WAL dump write: {e}")))?;
+    }
+    Ok(())
+}
+
+/// `<type> <key fields>` of `record`.
+fn text_line(record: &WalRecord) -> String {
+    let fields = match record {
+        WalRecord::AddNode { id, labels, properties } => {
+            format!("id={} labels={} keys={}", id, labels.join(","), sorted_keys(properties))
+        }
+        WalRecord::AddEdge { id, from_node, to_node, edge_type, properties } => format!(
+            "id={} from={} to={} edge_type={} keys={}",
+            id, from_node, to_node, edge_type, sorted_keys(properties),
+        ),
+        WalRecord::DeleteNode { id, detach } => format!("id={} detach={}", id, detach),
+        WalRecord::DeleteEdge { id }
+        | WalRecord::TombstoneNode { id }
+        | WalRecord::TombstoneEdge { id }
+        | WalRecord::UndeleteNode { id }
+        | WalRecord::UndeleteEdge { id }
+        | WalRecord::ReverseEdge { id } => format!("id={}", id),
+        WalRecord::PurgeTombstones => String::new(),
+        WalRecord::SetNodeProperty { id, key, .. }
+        | WalRecord::RemoveNodeProperty { id, key }
+        | WalRecord::SetEdgeProperty { id, key, .. } => format!("id={} key={}", id, key),
+        WalRecord::AddLabel { id, label } | WalRecord::RemoveLabel { id, label } => {
+            format!("id={} label={}", id, label)
+        }
+        WalRecord::SetEdgeType { id, edge_type } => format!("id={} edge_type={}", id, edge_type),
+        WalRecord::SetEdgeEndpoint { id, endpoint, node } => {
+            format!("id={} endpoint={:?} node={}", id, endpoint, node)
+        }
+        WalRecord::RenameEdgeType { old, new } => format!("old={} new={}", old, new),
+        WalRecord::MergeNodes { keep, remove, keep_self_loops } => {
+            format!("keep={} remove={} keep_self_loops={}", keep, remove, keep_self_loops)
+        }
+        WalRecord::Truncate { reset_ids } => format!("reset_ids={}", reset_ids),
+        WalRecord::CreateIndex { kind, label, keys, .. } | WalRecord::DropIndex { kind, label, keys } => {
+            format!("kind={} label={} keys={}", kind.as_str(), label, keys.join(","))
+        }
+        WalRecord::Checkpoint { generation, lsn } => format!("generation={} lsn={}", generation, lsn),
+        WalRecord::BeginTxn { txn_id } | WalRecord::CommitTxn { txn_id } | WalRecord::AbortTxn { txn_id } => {
+            format!("txn={}", txn_id)
+        }
+        WalRecord::InTxn { txn_id, record } => return format!("{} txn={}", text_line(record), txn_id),
+    };
+    if fields.is_empty() {
+        record.type_name().to_string()
+    } else {
+        format!("{} {}", record.type_name(), fields)
+    }
+}
+
+fn sorted_keys(properties: &HashMap<String, Value>) -> String {
+    let mut keys: Vec<&str> = properties.keys().map(String::as_str).collect();
+    keys.sort_unstable();
+    keys.join(",")
+}
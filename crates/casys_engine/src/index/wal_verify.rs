@@ -0,0 +1,135 @@
+//! WAL divergence detection: compare a store with what its log alone produces
+//!
+//! `verify_against_wal` replays a log into a scratch store with the options of the store
+//! under test, as `InMemoryGraphStore::recover` replays it, and compares the two record
+//! by record with `Node::semantically_equals` and `Edge::semantically_equals`. Tombstoned
+//! records are compared too. Secondary indexes are left to `check_indexes`.
+
+use super::persistence::WalRecord;
+use super::{Edge, InMemoryGraphStore, Node};
+use crate::types::EngineError;
+use std::collections::{BTreeSet, HashMap};
+
+/// A record both stores hold, described differently.
+#[derive(Debug, Clone)]
+pub struct Mismatch<T> {
+    /// As replayed from the log
+    pub expected: T,
+    /// As in the store
+    pub found: T,
+    /// What differs: `labels`, `deleted`, `endpoints`, `edge_type`, or `property <key>`
+    pub differences: Vec<String>,
+}
+
+/// What `verify_against_wal` found. Every list is sorted by id.
+#[derive(Debug, Clone, Default)]
+pub struct DivergenceReport {
+    /// Nodes the log produces that the store lacks
+    pub missing_nodes: Vec<Node>,
+    /// Nodes of the store the log does not produce
+    pub extra_nodes: Vec<Node>,
+    pub mismatched_nodes: Vec<Mismatch<Node>>,
+    pub missing_edges: Vec<Edge>,
+    pub extra_edges: Vec<Edge>,
+    pub mismatched_edges: Vec<Mismatch<Edge>>,
+    /// Why the log could not be replayed to its end; the comparison covers the records
+    /// applied before it
+    pub replay_error: Option<EngineError>,
+}
+
+impl DivergenceReport {
+    /// Whether the store is exactly what the log produces.
+    pub fn is_clean(&self) -> bool {
+        self.missing_nodes.is_empty()
+            && self.extra_nodes.is_empty()
+            && self.mismatched_nodes.is_empty()
+            && self.missing_edges.is_empty()
+            && self.extra_edges.is_empty()
+            && self.mismatched_edges.is_empty()
+            && self.replay_error.is_none()
+    }
+}
+
+/// Replay `records`, a whole log from its first record, into an empty store with the
+/// options of `store`, and report how `store` differs from it. Transactions are applied
+/// as `replay_wal_transactional` applies them. The files a checkpoint deleted are part of
+/// the log: read them back from an archive.
+pub fn verify_against_wal(store: &InMemoryGraphStore, records: impl Iterator<Item = WalRecord>) -> DivergenceReport {
+    let records: Vec<WalRecord> = records.collect();
+    let mut expected = InMemoryGraphStore::with_options(store.options().clone());
+    let replay_error = expected.replay_wal_transactional(&records).err();
+    let (missing_nodes, extra_nodes, mismatched_nodes) =
+        compare(&expected.nodes, &store.nodes, Node::semantically_equals, node_differences);
+    let (missing_edges, extra_edges, mismatched_edges) =
+        compare(&expected.edges, &store.edges, Edge::semantically_equals, edge_differences);
+    DivergenceReport {
+        missing_nodes,
+        extra_nodes,
+        mismatched_nodes,
+        missing_edges,
+        extra_edges,
+        mismatched_edges,
+        replay_error,
+    }
+}
+
+type Comparison<T> = (Vec<T>, Vec<T>, Vec<Mismatch<T>>);
+
+fn compare<T: Clone>(
+    expected: &HashMap<u64, T>,
+    found: &HashMap<u64, T>,
+    equals: fn(&T, &T) -> bool,
+    differences: fn(&T, &T) -> Vec<String>,
+) -> Comparison<T> {
+    let ids: BTreeSet<u64> = expected.keys().chain(found.keys()).copied().collect();
+    let mut comparison: Comparison<T> = (Vec::new(), Vec::new(), Vec::new());
+    for id in ids {
+        match (expected.get(&id), found.get(&id)) {
+            (Some(e), None) => comparison.0.push(e.clone()),
+            (None, Some(f)) => comparison.1.push(f.clone()),
+            (Some(e), Some(f)) if !equals(e, f) => comparison.2.push(Mismatch {
+                expected: e.clone(),
+                found: f.clone(),
+                differences: differences(e, f),
+            }),
+            _ => {}
+        }
+    }
+    comparison
+}
+
+fn node_differences(expected: &Node, found: &Node) -> Vec<String> {
+    let mut differences = Vec::new();
+    if expected.labels.iter().collect::<BTreeSet<_>>() != found.labels.iter().collect::<BTreeSet<_>>() {
+        differences.push("labels".to_string());
+    }
+    if expected.deleted != found.deleted {
+        differences.push("deleted".to_string());
+    }
+    differences.extend(property_differences(&expected.properties, &found.properties));
+    differences
+}
+
+fn edge_differences(expected: &Edge, found: &Edge) -> Vec<String> {
+    let mut differences = Vec::new();
+    if (expected.from_node, expected.to_node) != (found.from_node, found.to_node) {
+        differences.push("endpoints".to_string());
+    }
+    if expected.edge_type != found.edge_type {
+        differences.push("edge_type".to_string());
+    }
+    if expected.deleted != found.deleted {
+        differences.push("deleted".to_string());
+    }
+    differences.extend(property_differences(&expected.properties, &found.properties));
+    differences
+}
+
+/// `property <key>` for each key set on one side only or to different values, by key.
+fn property_differences<V: PartialEq>(expected: &HashMap<String, V>, found: &HashMap<String, V>) -> Vec<String> {
+    let keys: BTreeSet<&String> = expected.keys().chain(found.keys()).collect();
+    keys.into_iter()
+        .filter(|key| expected.get(*key) != found.get(*key))
+        .map(|key| format!("property {}", key))
+        .collect()
+}
@@ -0,0 +1,134 @@
+//! Tests for InMemoryGraphStore::apply_batch (all-or-nothing mutation batches)
+
+use casys_engine::index::{EdgeRef, InMemoryGraphStore, Mutation, NodeRef};
+use casys_core::{EngineError, GraphReadStore, GraphWriteStore, Value};
+use std::collections::HashMap;
+
+fn add_node(label: &str) -> Mutation {
+    Mutation::AddNode { labels: vec![label.to_string()], properties: HashMap::new() }
+}
+
+fn add_edge(from: NodeRef, to: NodeRef) -> Mutation {
+    Mutation::AddEdge { from, to, edge_type: "LINK".into(), properties: HashMap::new() }
+}
+
+#[test]
+fn batch_handles_resolve_to_real_ids() {
+    let mut store = InMemoryGraphStore::new();
+    let existing = store.add_node(vec!["Old".into()], HashMap::new()).unwrap();
+
+    let result = store.apply_batch(vec![
+        add_node("New"),
+        add_node("New"),
+        add_edge(NodeRef::Batch(0), NodeRef::Batch(1)),
+        add_edge(NodeRef::Existing(existing), NodeRef::Batch(0)),
+        Mutation::SetNodeProperty { node: NodeRef::Batch(1), key: "n".into(), value: Value::Int(1) },
+        Mutation::SetEdgeProperty { edge: EdgeRef::Batch(0), key: "w".into(), value: Value::Float(0.5) },
+    ]).unwrap();
+
+    assert_eq!(result.nodes.len(), 2);
+    assert_eq!(result.edges.len(), 2);
+    let (a, b) = (result.nodes[0], result.nodes[1]);
+    let out = store.get_neighbors(a, None).unwrap();
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1.id, b);
+    assert_eq!(out[0].0.id, result.edges[0]);
+    assert_eq!(out[0].0.properties.get("w"), Some(&Value::Float(0.5)));
+    assert_eq!(store.get_neighbors(existing, None).unwrap()[0].1.id, a);
+    assert_eq!(store.get_node(b).unwrap().unwrap().properties.get("n"), Some(&Value::Int(1)));
+    assert_eq!(result.node_id(NodeRef::Existing(existing)), Some(existing));
+}
+
+#[test]
+fn failing_mutation_leaves_store_untouched() {
+    let mut store = InMemoryGraphStore::new();
+    let existing = store.add_node(vec!["Old".into()], HashMap::new()).unwrap();
+    store.enable_wal_capture();
+
+    let err = store.apply_batch(vec![
+        add_node("New"),
+        add_edge(NodeRef::Batch(0), NodeRef::Existing(existing)),
+        Mutation::SetNodeProperty { node: NodeRef::Existing(existing), key: "k".into(), value: Value::Int(1) },
+        add_edge(NodeRef::Batch(0), NodeRef::Existing(404)),
+    ]).unwrap_err();
+
+    assert!(matches!(err, EngineError::NodeNotFound(404)));
+    assert_eq!(store.scan_all().unwrap().len(), 1);
+    assert!(store.get_node(existing).unwrap().unwrap().properties.is_empty());
+    assert!(store.get_neighbors_incoming(existing, None).unwrap().is_empty());
+    assert!(store.take_wal_records().is_empty());
+    // No ids were consumed
+    assert_eq!(store.add_node(vec![], HashMap::new()).unwrap(), existing + 1);
+}
+
+#[test]
+fn staged_view_tracks_deletes_within_the_batch() {
+    let mut store = InMemoryGraphStore::new();
+    let a = store.add_node(vec![], HashMap::new()).unwrap();
+
+    // Referencing a node deleted earlier in the batch fails
+    let err = store.apply_batch(vec![
+        Mutation::DeleteNode { node: NodeRef::Existing(a), detach: false },
+        Mutation::SetNodeProperty { node: NodeRef::Existing(a), key: "k".into(), value: Value::Null },
+    ]).unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+
+    // A batch-created edge blocks a non-detach delete of its endpoint
+    let err = store.apply_batch(vec![
+        add_node("N"),
+        add_edge(NodeRef::Existing(a), NodeRef::Batch(0)),
+        Mutation::DeleteNode { node: NodeRef::Batch(0), detach: false },
+    ]).unwrap_err();
+    assert!(matches!(err, EngineError::InvalidArgument(_)));
+
+    // ...but deleting the edge first makes it valid, and detach covers the rest
+    let result = store.apply_batch(vec![
+        add_node("N"),
+        add_edge(NodeRef::Existing(a), NodeRef::Batch(0)),
+        Mutation::DeleteEdge { edge: EdgeRef::Batch(0) },
+        Mutation::DeleteNode { node: NodeRef::Batch(0), detach: false },
+        add_node("N"),
+        add_edge(NodeRef::Batch(1), NodeRef::Existing(a)),
+        Mutation::DeleteNode { node: NodeRef::Existing(a), detach: true },
+    ]).unwrap();
+    assert_eq!(store.scan_all().unwrap().len(), 1);
+    assert_eq!(store.get_node(result.nodes[1]).unwrap().unwrap().id, result.nodes[1]);
+    assert!(store.get_neighbors(result.nodes[1], None).unwrap().is_empty());
+}
+
+#[test]
+fn forward_batch_handles_are_rejected() {
+    let mut store = InMemoryGraphStore::new();
+    let err = store.apply_batch(vec![
+        add_edge(NodeRef::Batch(0), NodeRef::Batch(1)),
+        add_node("N"),
+        add_node("N"),
+    ]).unwrap_err();
+    assert!(matches!(err, EngineError::InvalidArgument(_)));
+    assert!(store.scan_all().unwrap().is_empty());
+}
+
+#[test]
+fn unique_constraints_are_checked_against_the_staged_view() {
+    let mut store = InMemoryGraphStore::new();
+    let email = |s: &str| HashMap::from([("email".to_string(), Value::String(s.into()))]);
+    let alice = store.add_node(vec!["User".into()], email("a@x")).unwrap();
+    store.create_unique_constraint("User", "email").unwrap();
+    let user = |s: &str| Mutation::AddNode { labels: vec!["User".into()], properties: email(s) };
+
+    // Two new nodes claiming the same value: rejected, nothing applied
+    let err = store.apply_batch(vec![user("b@x"), user("b@x")]).unwrap_err();
+    assert!(matches!(err, EngineError::UniqueViolation { .. }));
+    assert_eq!(store.node_count().unwrap(), 1);
+
+    // A value freed earlier in the batch can be reused
+    store.apply_batch(vec![
+        Mutation::SetNodeProperty { node: NodeRef::Existing(alice), key: "email".into(), value: Value::String("old@x".into()) },
+        user("a@x"),
+        Mutation::RemoveNodeProperty { node: NodeRef::Existing(alice), key: "email".into() },
+        user("old@x"),
+    ]).unwrap();
+    assert_eq!(store.node_count().unwrap(), 3);
+    assert!(store.apply_batch(vec![user("a@x")]).is_err());
+    assert!(store.verify_indexes().is_ok());
+}
@@ -0,0 +1,60 @@
+//! Executor expands over InMemoryGraphStore: WHERE conditions on relationships are pushed
+//! into the Expand and tested before neighbors are fetched
+
+use casys_engine::exec::{executor::Executor, parser, planner::{PlanNode, Planner}};
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::{Direction, GraphReadStore, GraphWriteStore, Value};
+use std::collections::HashMap;
+
+fn weighted_star() -> (InMemoryGraphStore, u64) {
+    let mut store = InMemoryGraphStore::new();
+    let hub = store.add_node(vec!["Hub".into()], HashMap::new()).unwrap();
+    for i in 0..10 {
+        let leaf = store.add_node(
+            vec!["Leaf".into()],
+            HashMap::from([("name".to_string(), Value::String(format!("leaf{}", i)))]),
+        ).unwrap();
+        let weight = HashMap::from([("weight".to_string(), Value::Float(i as f64 / 10.0))]);
+        store.add_edge(hub, leaf, "LINK".into(), weight).unwrap();
+    }
+    (store, hub)
+}
+
+fn find_expand(node: &PlanNode) -> Option<&PlanNode> {
+    match node {
+        PlanNode::Expand { .. } => Some(node),
+        PlanNode::Filter { input, .. } | PlanNode::Project { input, .. } | PlanNode::Limit { input, .. } => find_expand(input),
+        _ => None,
+    }
+}
+
+#[test]
+fn get_neighbors_where_tests_edges_before_cloning() {
+    let (store, hub) = weighted_star();
+    let heavy = |e: &casys_core::Edge| matches!(e.properties.get("weight"), Some(Value::Float(w)) if *w > 0.5);
+    assert_eq!(store.get_neighbors_where(hub, None, Direction::Outgoing, &heavy).unwrap().len(), 4);
+    assert_eq!(store.get_neighbors_where(hub, Some("OTHER"), Direction::Outgoing, &heavy).unwrap().len(), 0);
+    assert_eq!(store.get_neighbors_where(hub, None, Direction::Incoming, &|_| true).unwrap().len(), 0);
+}
+
+#[test]
+fn where_on_relationship_is_pushed_into_expand() {
+    let (store, _) = weighted_star();
+    let gql = "MATCH (h:Hub)-[r:LINK]->(l) WHERE r.weight > 0.5 AND l.name != 'leaf9' RETURN l.name";
+    let plan = Planner::plan(&parser::parse(gql).unwrap()).unwrap();
+
+    // The edge-only conjunct moved into the Expand, the other one stays a Filter
+    match find_expand(&plan.root) {
+        Some(PlanNode::Expand { edge_filter, .. }) => assert!(edge_filter.is_some()),
+        other => panic!("expected an Expand, got {:?}", other),
+    }
+    let PlanNode::Project { input, .. } = &plan.root else { panic!("expected Project root") };
+    assert!(matches!(input.as_ref(), PlanNode::Filter { .. }));
+
+    let result = Executor::new(&store as &dyn GraphReadStore).execute(&plan, None).unwrap();
+    let mut names: Vec<String> = result.rows.iter().map(|r| r[0].as_str().unwrap().to_string()).collect();
+    names.sort();
+    assert_eq!(names, vec!["leaf6", "leaf7", "leaf8"]);
+    // Only the edges passing the pushed-down condition were expanded
+    assert_eq!(result.stats.unwrap().expanded, 4);
+}
@@ -0,0 +1,69 @@
+//! Executor scans over InMemoryGraphStore: streaming through iter_nodes and LIMIT pushdown
+
+use casys_engine::exec::{executor::Executor, parser, planner::Planner};
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::{GraphReadStore, GraphWriteStore, Value};
+use std::collections::HashMap;
+
+fn store_with_users(n: i64) -> InMemoryGraphStore {
+    let mut store = InMemoryGraphStore::new();
+    for i in 0..n {
+        store.add_node(vec!["User".into()], HashMap::from([("age".to_string(), Value::Int(i))])).unwrap();
+    }
+    store.add_node(vec!["Group".into()], HashMap::new()).unwrap();
+    store
+}
+
+fn run(store: &InMemoryGraphStore, gql: &str) -> casys_engine::QueryResult {
+    let plan = Planner::plan(&parser::parse(gql).unwrap()).unwrap();
+    Executor::new(store as &dyn GraphReadStore).execute(&plan, None).unwrap()
+}
+
+#[test]
+fn iter_nodes_borrows_live_nodes() {
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        soft_delete: true,
+        ..Default::default()
+    });
+    let a = store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    let b = store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    store.add_node(vec![], HashMap::new()).unwrap();
+    store.delete_node(a, false).unwrap();
+
+    assert_eq!(store.iter_nodes().unwrap().count(), 2);
+    let users: Vec<u64> = store.iter_nodes_by_label("User").unwrap().map(|n| n.id).collect();
+    assert_eq!(users, vec![b]);
+    assert_eq!(store.iter_nodes_by_label("Nobody").unwrap().count(), 0);
+}
+
+#[test]
+fn limit_stops_label_and_full_scans_early() {
+    let store = store_with_users(1_000);
+
+    let result = run(&store, "MATCH (n:User) RETURN n.age LIMIT 3");
+    assert_eq!(result.rows.len(), 3);
+    assert_eq!(result.stats.unwrap().scanned, 3);
+
+    let result = run(&store, "MATCH (n) RETURN n LIMIT 5");
+    assert_eq!(result.rows.len(), 5);
+    assert_eq!(result.stats.unwrap().scanned, 5);
+
+    // Without LIMIT every node is visited
+    let result = run(&store, "MATCH (n:User) RETURN n.age");
+    assert_eq!(result.rows.len(), 1_000);
+    assert_eq!(result.stats.unwrap().scanned, 1_000);
+}
+
+#[test]
+fn limit_with_where_keeps_only_matching_rows() {
+    let store = store_with_users(1_000);
+
+    let result = run(&store, "MATCH (n:User) WHERE n.age >= 990 RETURN n.age LIMIT 4");
+    assert_eq!(result.rows.len(), 4);
+    assert!(result.rows.iter().all(|row| row[0].as_i64().unwrap() >= 990));
+    assert!(result.stats.unwrap().scanned <= 1_000);
+
+    // Fewer matches than the limit still returns all of them
+    let result = run(&store, "MATCH (n:User) WHERE n.age >= 998 RETURN n.age LIMIT 10");
+    assert_eq!(result.rows.len(), 2);
+}
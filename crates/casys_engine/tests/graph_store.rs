@@ -0,0 +1,4279 @@
+//! Integration tests for InMemoryGraphStore mutations
+//!
+//! These tests exercise the GraphWriteStore surface of the in-memory store and
+//! check that the secondary structures (label_index, adjacency) stay consistent.
+
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::{EngineError, GraphReadStore, GraphWriteStore, Value};
+use std::collections::HashMap;
+
+fn node(store: &mut InMemoryGraphStore, label: &str) -> u64 {
+    store.add_node(vec![label.to_string()], HashMap::new()).unwrap()
+}
+
+// =============================================================================
+// delete_edge
+// =============================================================================
+
+#[test]
+fn delete_only_edge_between_two_nodes() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let e = store.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+
+    assert!(store.delete_edge(e).unwrap());
+
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert!(store.get_neighbors_incoming(b, None).unwrap().is_empty());
+    // Both endpoints survive the edge removal
+    assert!(store.get_node(a).unwrap().is_some());
+    assert!(store.get_node(b).unwrap().is_some());
+}
+
+#[test]
+fn delete_one_of_several_parallel_edges() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let e1 = store.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+    let e2 = store.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+    let e3 = store.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+
+    assert!(store.delete_edge(e2).unwrap());
+
+    let mut out: Vec<u64> = store.get_neighbors(a, Some("KNOWS")).unwrap()
+        .into_iter().map(|(e, _)| e.id).collect();
+    out.sort();
+    assert_eq!(out, vec![e1, e3]);
+
+    let mut incoming: Vec<u64> = store.get_neighbors_incoming(b, Some("KNOWS")).unwrap()
+        .into_iter().map(|(e, _)| e.id).collect();
+    incoming.sort();
+    assert_eq!(incoming, vec![e1, e3]);
+}
+
+#[test]
+fn delete_unknown_edge_returns_false() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let e = store.add_edge(a, a, "SELF".to_string(), HashMap::new()).unwrap();
+
+    assert!(!store.delete_edge(999).unwrap());
+    assert!(store.delete_edge(e).unwrap());
+    // Deleting twice is not an error
+    assert!(!store.delete_edge(e).unwrap());
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert!(store.get_neighbors_incoming(a, None).unwrap().is_empty());
+}
+
+// =============================================================================
+// Node property updates
+// =============================================================================
+
+#[test]
+fn set_and_remove_node_property_in_place() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Person");
+
+    store.set_node_property(a, "name".to_string(), Value::String("Alice".into())).unwrap();
+    store.set_node_property(a, "age".to_string(), Value::Int(30)).unwrap();
+    store.set_node_property(a, "age".to_string(), Value::Int(31)).unwrap();
+
+    let n = store.get_node(a).unwrap().unwrap();
+    assert_eq!(n.id, a);
+    assert_eq!(n.properties.get("age"), Some(&Value::Int(31)));
+
+    assert_eq!(store.remove_node_property(a, "age").unwrap(), Some(Value::Int(31)));
+    assert_eq!(store.remove_node_property(a, "age").unwrap(), None);
+
+    let persons = store.scan_by_label("Person").unwrap();
+    assert_eq!(persons.len(), 1);
+    assert!(!persons[0].properties.contains_key("age"));
+    assert_eq!(persons[0].properties.get("name"), Some(&Value::String("Alice".into())));
+}
+
+#[test]
+fn update_node_properties_merges() {
+    let mut store = InMemoryGraphStore::new();
+    let mut props = HashMap::new();
+    props.insert("a".to_string(), Value::Int(1));
+    props.insert("b".to_string(), Value::Int(2));
+    let id = store.add_node(vec!["N".into()], props).unwrap();
+
+    let mut update = HashMap::new();
+    update.insert("b".to_string(), Value::Int(20));
+    update.insert("c".to_string(), Value::Int(30));
+    store.update_node_properties(id, update).unwrap();
+
+    let n = store.get_node(id).unwrap().unwrap();
+    assert_eq!(n.properties.len(), 3);
+    assert_eq!(n.properties.get("a"), Some(&Value::Int(1)));
+    assert_eq!(n.properties.get("b"), Some(&Value::Int(20)));
+    assert_eq!(n.properties.get("c"), Some(&Value::Int(30)));
+}
+
+#[test]
+fn property_updates_on_missing_node_are_not_found() {
+    let mut store = InMemoryGraphStore::new();
+
+    let err = store.set_node_property(42, "k".into(), Value::Int(1)).unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+    let err = store.remove_node_property(42, "k").unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+    let err = store.update_node_properties(42, HashMap::new()).unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+}
+
+// =============================================================================
+// Label add/remove
+// =============================================================================
+
+fn ids_with_label(store: &InMemoryGraphStore, label: &str) -> Vec<u64> {
+    let mut ids: Vec<u64> = store.scan_by_label(label).unwrap().into_iter().map(|n| n.id).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn add_and_remove_labels_keep_index_exact() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Task");
+    let b = node(&mut store, "Task");
+
+    assert!(store.add_label(a, "Pending".into()).unwrap());
+    assert!(store.add_label(b, "Pending".into()).unwrap());
+    assert_eq!(ids_with_label(&store, "Pending"), vec![a, b]);
+
+    assert!(store.remove_label(a, "Pending").unwrap());
+    assert!(store.add_label(a, "Done".into()).unwrap());
+    assert_eq!(ids_with_label(&store, "Pending"), vec![b]);
+    assert_eq!(ids_with_label(&store, "Done"), vec![a]);
+
+    assert!(store.remove_label(b, "Pending").unwrap());
+    assert!(ids_with_label(&store, "Pending").is_empty());
+
+    let n = store.get_node(a).unwrap().unwrap();
+    assert_eq!(n.labels, vec!["Task".to_string(), "Done".to_string()]);
+}
+
+#[test]
+fn add_label_twice_does_not_duplicate_index_entry() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Task");
+
+    assert!(store.add_label(a, "Pending".into()).unwrap());
+    assert!(!store.add_label(a, "Pending".into()).unwrap());
+    assert!(!store.add_label(a, "Task".into()).unwrap());
+
+    assert_eq!(ids_with_label(&store, "Pending"), vec![a]);
+    assert_eq!(ids_with_label(&store, "Task"), vec![a]);
+    assert_eq!(store.get_node(a).unwrap().unwrap().labels.len(), 2);
+}
+
+#[test]
+fn remove_absent_label_and_missing_node() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Task");
+
+    assert!(!store.remove_label(a, "Nope").unwrap());
+    assert!(matches!(store.add_label(99, "X".into()), Err(EngineError::NotFound(_))));
+    assert!(matches!(store.remove_label(99, "X"), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// Edge property updates and retyping
+// =============================================================================
+
+#[test]
+fn update_edge_properties_in_place() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let mut props = HashMap::new();
+    props.insert("weight".to_string(), Value::Float(0.5));
+    props.insert("label".to_string(), Value::String("x".into()));
+    let e = store.add_edge(a, b, "LINK".into(), props).unwrap();
+
+    let mut update = HashMap::new();
+    update.insert("weight".to_string(), Value::Float(0.9));
+    store.update_edge_properties(e, update).unwrap();
+
+    let (edge, _) = &store.get_neighbors(a, None).unwrap()[0];
+    assert_eq!(edge.id, e);
+    assert_eq!(edge.properties.get("weight"), Some(&Value::Float(0.9)));
+    assert_eq!(edge.properties.get("label"), Some(&Value::String("x".into())));
+}
+
+#[test]
+fn set_edge_type_is_seen_by_filtered_traversal() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let e = store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+
+    store.set_edge_type(e, "FOLLOWS".into()).unwrap();
+
+    assert!(store.get_neighbors(a, Some("KNOWS")).unwrap().is_empty());
+    assert_eq!(store.get_neighbors(a, Some("FOLLOWS")).unwrap()[0].0.id, e);
+    assert_eq!(store.get_neighbors_incoming(b, Some("FOLLOWS")).unwrap()[0].1.id, a);
+}
+
+#[test]
+fn edge_updates_on_missing_edge_are_not_found() {
+    let mut store = InMemoryGraphStore::new();
+    assert!(matches!(store.update_edge_properties(7, HashMap::new()), Err(EngineError::NotFound(_))));
+    assert!(matches!(store.set_edge_type(7, "X".into()), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// WAL capture and replay of mutations
+// =============================================================================
+
+#[test]
+fn captured_wal_replays_to_same_final_state() {
+    use casys_engine::index::persistence::WalRecord;
+
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "Task");
+    let b = node(&mut store, "Task");
+    let mut props = HashMap::new();
+    props.insert("weight".to_string(), Value::Int(1));
+    let e1 = store.add_edge(a, b, "KNOWS".into(), props).unwrap();
+    let e2 = store.add_edge(b, a, "KNOWS".into(), HashMap::new()).unwrap();
+    store.set_node_property(a, "name".into(), Value::String("first".into())).unwrap();
+    store.set_node_property(b, "tmp".into(), Value::Bool(true)).unwrap();
+    store.remove_node_property(b, "tmp").unwrap();
+    store.add_label(a, "Done".into()).unwrap();
+    store.remove_label(b, "Task").unwrap();
+    let mut update = HashMap::new();
+    update.insert("weight".to_string(), Value::Int(5));
+    store.update_edge_properties(e1, update).unwrap();
+    store.set_edge_type(e1, "FOLLOWS".into()).unwrap();
+    store.delete_edge(e2).unwrap();
+
+    // Round-trip every record through its byte encoding before replaying
+    let records: Vec<WalRecord> = store.take_wal_records().iter()
+        .map(|r| WalRecord::from_bytes(&r.to_bytes()).unwrap())
+        .collect();
+    assert!(store.take_wal_records().is_empty(), "take drains the buffer");
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+
+    let na = replayed.get_node(a).unwrap().unwrap();
+    assert_eq!(na.properties.get("name"), Some(&Value::String("first".into())));
+    assert_eq!(na.labels, vec!["Task".to_string(), "Done".to_string()]);
+    let nb = replayed.get_node(b).unwrap().unwrap();
+    assert!(nb.labels.is_empty());
+    assert!(nb.properties.is_empty());
+    assert_eq!(ids_with_label(&replayed, "Task"), vec![a]);
+
+    let out = replayed.get_neighbors(a, None).unwrap();
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].0.id, e1);
+    assert_eq!(out[0].0.edge_type, "FOLLOWS");
+    assert_eq!(out[0].0.properties.get("weight"), Some(&Value::Int(5)));
+    assert!(replayed.get_neighbors(b, None).unwrap().is_empty());
+}
+
+#[test]
+fn replay_of_updates_for_missing_ids_is_a_no_op() {
+    use casys_engine::index::persistence::WalRecord;
+
+    let mut store = InMemoryGraphStore::new();
+    store.replay_wal(&[
+        WalRecord::SetEdgeType { id: 3, edge_type: "X".into() },
+        WalRecord::SetNodeProperty { id: 9, key: "k".into(), value: Value::Int(1) },
+        WalRecord::AddLabel { id: 9, label: "L".into() },
+        WalRecord::DeleteEdge { id: 3 },
+        WalRecord::DeleteNode { id: 9, detach: true },
+        WalRecord::RemoveNodeProperty { id: 9, key: "k".into() },
+        WalRecord::RemoveLabel { id: 9, label: "L".into() },
+        WalRecord::SetEdgeProperty { id: 3, key: "k".into(), value: Value::Int(1) },
+        WalRecord::TombstoneNode { id: 9 },
+        WalRecord::UndeleteEdge { id: 3 },
+    ]).unwrap();
+    assert!(store.scan_all().unwrap().is_empty());
+    assert!(store.scan_by_label("L").unwrap().is_empty());
+}
+
+#[test]
+fn replaying_deletes_twice_is_idempotent() {
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let e = store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.delete_edge(e).unwrap();
+    store.delete_node(a, true).unwrap();
+    let records = store.take_wal_records();
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    replayed.replay_wal(&records[3..]).unwrap();
+    assert_eq!(ids_with_label(&replayed, "N"), vec![b]);
+    assert!(replayed.verify_indexes().is_ok());
+}
+
+#[test]
+fn every_wal_record_roundtrips_through_bytes() {
+    use casys_engine::index::persistence::WalRecord;
+    use casys_engine::index::Endpoint;
+
+    let props = HashMap::from([
+        ("s".to_string(), Value::String("x".into())),
+        ("f".to_string(), Value::Float(1.5)),
+        ("l".to_string(), Value::Array(vec![Value::Int(1), Value::Null, Value::Bool(false)])),
+    ]);
+    let records = vec![
+        WalRecord::AddNode { id: 1, labels: vec!["A".into(), "B".into()], properties: props.clone() },
+        WalRecord::AddEdge { id: 2, from_node: 1, to_node: 3, edge_type: "T".into(), properties: props },
+        WalRecord::DeleteNode { id: 1, detach: true },
+        WalRecord::DeleteNode { id: 1, detach: false },
+        WalRecord::DeleteEdge { id: 2 },
+        WalRecord::TombstoneNode { id: 1 },
+        WalRecord::TombstoneEdge { id: 2 },
+        WalRecord::UndeleteNode { id: 1 },
+        WalRecord::UndeleteEdge { id: 2 },
+        WalRecord::PurgeTombstones,
+        WalRecord::SetNodeProperty { id: 1, key: "k".into(), value: Value::Int(-7) },
+        WalRecord::RemoveNodeProperty { id: 1, key: "k".into() },
+        WalRecord::AddLabel { id: 1, label: "C".into() },
+        WalRecord::RemoveLabel { id: 1, label: "A".into() },
+        WalRecord::SetEdgeProperty { id: 2, key: "w".into(), value: Value::String("heavy".into()) },
+        WalRecord::SetEdgeType { id: 2, edge_type: "U".into() },
+        WalRecord::SetEdgeEndpoint { id: 2, endpoint: Endpoint::From, node: 4 },
+        WalRecord::SetEdgeEndpoint { id: 2, endpoint: Endpoint::To, node: 5 },
+        WalRecord::ReverseEdge { id: 2 },
+        WalRecord::RenameEdgeType { old: "U".into(), new: "V".into() },
+        WalRecord::MergeNodes { keep: 1, remove: 3, keep_self_loops: false },
+        WalRecord::Truncate { reset_ids: true },
+        WalRecord::Checkpoint { generation: 2, lsn: 40 },
+        WalRecord::BeginTxn { txn_id: 3 },
+        WalRecord::InTxn { txn_id: 3, record: Box::new(WalRecord::AddLabel { id: 1, label: "T".into() }) },
+        WalRecord::CommitTxn { txn_id: 3 },
+        WalRecord::AbortTxn { txn_id: 4 },
+    ];
+    for record in &records {
+        assert_eq!(&WalRecord::from_bytes(&record.to_bytes()).unwrap(), record);
+    }
+    assert!(matches!(WalRecord::from_bytes(br#"{"type":"delete_node"}"#), Err(EngineError::StorageIo(_))));
+    assert!(matches!(WalRecord::from_bytes(br#"{"type":"nope"}"#), Err(EngineError::StorageIo(_))));
+}
+
+/// Pseudo-random WAL records for the round-trip tests (xorshift, so failures reproduce).
+struct RecordGen {
+    state: u64,
+    /// Only what JSON payloads carry exactly: no bytes, node ids, NaN or floats with
+    /// more digits than serde_json parses back exactly
+    json: bool,
+}
+
+impl RecordGen {
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn string(&mut self) -> String {
+        const CHARS: [char; 6] = ['a', 'Z', '0', ' ', 'é', '🦀'];
+        (0..self.below(6)).map(|_| CHARS[self.below(6) as usize]).collect()
+    }
+
+    fn value(&mut self, depth: u32) -> Value {
+        let kinds = if depth == 0 { 7 } else { 9 };
+        match self.below(kinds) {
+            0 => Value::Null,
+            1 => Value::Bool(self.below(2) == 1),
+            2 => Value::Int(self.next() as i64),
+            3 if !self.json && self.below(4) == 0 => Value::Float(f64::NAN),
+            3 if self.json => Value::Float((self.below(1 << 20) as f64 - 524_288.0) / 8.0),
+            3 => Value::Float(f64::from_bits(self.next())),
+            4 => Value::String(self.string()),
+            5 if self.json => Value::String(self.string()),
+            5 => Value::Bytes((0..self.below(5)).map(|_| self.next() as u8).collect()),
+            6 if self.json => Value::Int(self.below(100) as i64),
+            6 => Value::NodeId(self.next()),
+            7 => Value::Array((0..self.below(4)).map(|_| self.value(depth - 1)).collect()),
+            _ => Value::Map((0..self.below(4)).map(|_| (self.string(), self.value(depth - 1))).collect()),
+        }
+    }
+
+    fn props(&mut self) -> HashMap<String, Value> {
+        (0..self.below(4)).map(|_| (self.string(), self.value(2))).collect()
+    }
+
+    fn ddl_kind(&mut self) -> casys_engine::index::persistence::DdlKind {
+        use casys_engine::index::persistence::DdlKind;
+        use casys_engine::index::IndexKind;
+
+        const KINDS: [IndexKind; 8] = [
+            IndexKind::Property, IndexKind::Range, IndexKind::Composite, IndexKind::Text,
+            IndexKind::Prefix, IndexKind::Edge, IndexKind::Computed, IndexKind::Ttl,
+        ];
+        match KINDS.get(self.below(9) as usize) {
+            Some(kind) => DdlKind::Index(*kind),
+            None => DdlKind::UniqueConstraint,
+        }
+    }
+
+    fn record(&mut self, in_txn: bool) -> casys_engine::index::persistence::WalRecord {
+        use casys_engine::index::persistence::{DdlOptions, WalRecord};
+        use casys_engine::index::{Endpoint, IndexOptions};
+
+        let kinds = if in_txn { 23 } else { 27 };
+        match self.below(kinds) {
+            0 => WalRecord::AddNode { id: self.next(), labels: (0..self.below(3)).map(|_| self.string()).collect(), properties: self.props() },
+            1 => WalRecord::AddEdge { id: self.next(), from_node: self.next(), to_node: self.next(), edge_type: self.string(), properties: self.props() },
+            2 => WalRecord::DeleteNode { id: self.next(), detach: self.below(2) == 1 },
+            3 => WalRecord::DeleteEdge { id: self.next() },
+            4 => WalRecord::TombstoneNode { id: self.next() },
+            5 => WalRecord::TombstoneEdge { id: self.next() },
+            6 => WalRecord::UndeleteNode { id: self.next() },
+            7 => WalRecord::UndeleteEdge { id: self.next() },
+            8 => WalRecord::PurgeTombstones,
+            9 => WalRecord::SetNodeProperty { id: self.next(), key: self.string(), value: self.value(2) },
+            10 => WalRecord::RemoveNodeProperty { id: self.next(), key: self.string() },
+            11 => WalRecord::AddLabel { id: self.next(), label: self.string() },
+            12 => WalRecord::RemoveLabel { id: self.next(), label: self.string() },
+            13 => WalRecord::SetEdgeProperty { id: self.next(), key: self.string(), value: self.value(2) },
+            14 => WalRecord::SetEdgeType { id: self.next(), edge_type: self.string() },
+            15 => WalRecord::SetEdgeEndpoint {
+                id: self.next(),
+                endpoint: if self.below(2) == 0 { Endpoint::From } else { Endpoint::To },
+                node: self.next(),
+            },
+            16 => WalRecord::ReverseEdge { id: self.next() },
+            17 => WalRecord::RenameEdgeType { old: self.string(), new: self.string() },
+            18 => WalRecord::MergeNodes { keep: self.next(), remove: self.next(), keep_self_loops: self.below(2) == 1 },
+            19 => WalRecord::Truncate { reset_ids: self.below(2) == 1 },
+            20 => WalRecord::CreateIndex {
+                kind: self.ddl_kind(),
+                label: self.string(),
+                keys: (0..self.below(3)).map(|_| self.string()).collect(),
+                options: DdlOptions {
+                    property: IndexOptions {
+                        case_insensitive: self.below(2) == 1,
+                        trim_whitespace: self.below(2) == 1,
+                        multi_value: self.below(2) == 1,
+                    },
+                    covered: (0..self.below(3)).map(|_| self.string()).collect(),
+                    case_sensitive: self.below(2) == 1,
+                },
+            },
+            21 => WalRecord::DropIndex { kind: self.ddl_kind(), label: self.string(), keys: (0..self.below(3)).map(|_| self.string()).collect() },
+            22 => WalRecord::Checkpoint { generation: self.next(), lsn: self.next() },
+            23 => WalRecord::BeginTxn { txn_id: self.next() },
+            24 => WalRecord::CommitTxn { txn_id: self.next() },
+            25 => WalRecord::AbortTxn { txn_id: self.next() },
+            _ => WalRecord::InTxn { txn_id: self.next(), record: Box::new(self.record(true)) },
+        }
+    }
+}
+
+#[test]
+fn arbitrary_wal_records_roundtrip_in_both_payload_formats() {
+    use casys_engine::index::persistence::{PayloadFormat, WalRecord};
+
+    for (format, json) in [(PayloadFormat::Json, true), (PayloadFormat::Binary, false)] {
+        let mut gen = RecordGen { state: 0x9e37_79b9_7f4a_7c15, json };
+        for lsn in 1..=2_000 {
+            let record = gen.record(false);
+            let frame = record.to_frame_as(lsn, format);
+            assert_eq!(WalRecord::from_frame(&frame).unwrap(), (lsn, record.clone()), "{:?}", format);
+        }
+    }
+
+    // Binary keeps what JSON cannot, and is smaller
+    let record = WalRecord::SetNodeProperty {
+        id: 7,
+        key: "blob".into(),
+        value: Value::Array(vec![Value::Bytes(vec![0, 255]), Value::NodeId(3), Value::Float(f64::NAN)]),
+    };
+    let binary = record.to_frame_as(1, PayloadFormat::Binary);
+    assert_eq!(WalRecord::from_bytes(&binary).unwrap(), record);
+    assert_ne!(WalRecord::from_bytes(&record.to_frame(1)).unwrap(), record);
+    assert!(binary.len() < record.to_frame(1).len());
+}
+
+#[test]
+fn wal_writer_appends_binary_payloads_alongside_json_ones() {
+    use casys_engine::index::persistence::{PayloadFormat, SyncPolicy, WalReader, WalRecord, WalWriter};
+
+    let dir = branch_dir("wal_binary");
+    let mut gen = RecordGen { state: 42, json: true };
+    // Checkpoint records only mark where a file starts; the reader does not return them
+    let records: Vec<_> = std::iter::repeat_with(|| gen.record(false))
+        .filter(|r| !matches!(r, WalRecord::Checkpoint { .. }))
+        .take(20)
+        .collect();
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    for record in &records[..10] {
+        writer.append(record).unwrap();
+    }
+    drop(writer);
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_payload_format(PayloadFormat::Binary);
+    assert_eq!(writer.payload_format(), PayloadFormat::Binary);
+    for record in &records[10..] {
+        writer.append(record).unwrap();
+    }
+    writer.sync().unwrap();
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    assert_eq!(entries, (1..).zip(records).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "deflate")]
+#[test]
+fn deflate_compressor_round_trips_and_bounds_what_it_decompresses() {
+    use casys_engine::index::persistence::{Compressor, DeflateCompressor, MAX_DECOMPRESSED_PAYLOAD};
+
+    let mut gen = RecordGen { state: 7, json: false };
+    let noise: Vec<u8> = (0..5000).map(|_| gen.next() as u8).collect();
+    let blob = r#"{"name":"x","tags":["a","b"],"score":0.5}"#.repeat(200);
+    let inputs: Vec<Vec<u8>> = vec![
+        Vec::new(),
+        b"abc".to_vec(),
+        vec![b'a'; 100_000],
+        noise.clone(),
+        blob.clone().into_bytes(),
+        [noise, blob.into_bytes(), vec![0; 300]].concat(),
+    ];
+    for input in &inputs {
+        let compressed = DeflateCompressor.compress(input);
+        assert_eq!(&DeflateCompressor.decompress(&compressed).unwrap(), input);
+    }
+    assert!(DeflateCompressor.compress(&inputs[2]).len() < 1000);
+    assert!(DeflateCompressor.decompress(&[0xFF, 0xFF]).is_err());
+
+    // About 64 KiB that would inflate past the limit
+    let bomb = DeflateCompressor.compress(&vec![0; MAX_DECOMPRESSED_PAYLOAD + 1]);
+    let error = DeflateCompressor.decompress(&bomb).unwrap_err();
+    assert!(error.to_string().contains("more than"), "{}", error);
+}
+
+#[cfg(feature = "deflate")]
+#[test]
+fn wal_writer_compresses_large_payloads_and_readers_decompress_them() {
+    use casys_engine::index::persistence::{
+        inspect_wal, Compression, PayloadFormat, SyncPolicy, WalFrames, WalReader, WalRecord, WalWriter,
+    };
+
+    let dir = branch_dir("wal_compressed");
+    let blob = |id: u64| WalRecord::AddNode {
+        id,
+        labels: vec!["Doc".into()],
+        properties: HashMap::from([("body".to_string(), Value::String(r#"{"k":"some text"}"#.repeat(100)))]),
+    };
+    let small = WalRecord::SetNodeProperty { id: 1, key: "n".into(), value: Value::Int(1) };
+    let records = vec![blob(1), small.clone(), blob(2), blob(3)];
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_compression(Compression::deflate());
+    assert_eq!(writer.compression().unwrap().threshold(), 1024);
+    writer.append(&records[0]).unwrap();
+    writer.append(&records[1]).unwrap();
+    drop(writer);
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap()
+        .with_payload_format(PayloadFormat::Binary)
+        .with_compression(Compression::deflate().with_threshold(16));
+    writer.append_group(&records[2..]).unwrap();
+    let path = writer.path();
+    drop(writer);
+
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    assert_eq!(entries, (1..).zip(records.clone()).collect::<Vec<_>>());
+    // Only the large payloads are compressed, and they shrink
+    let frames: Vec<_> = WalFrames::open(&path).unwrap().map(Result::unwrap).collect();
+    assert_eq!(frames.iter().map(|f| f.compressed).collect::<Vec<_>>(), [true, false, true, true]);
+    assert!(frames[0].len < records[0].to_frame(1).len() as u64 / 4);
+    assert_eq!(frames[1].len, small.to_frame(2).len() as u64);
+    assert_eq!(inspect_wal(&path).unwrap().records, 4);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wal_readers_decompress_registered_compressors() {
+    use casys_engine::index::persistence::{
+        register_compressor, Compression, Compressor, IdentityCompressor, SyncPolicy, WalReader, WalRecord, WalWriter,
+    };
+    use std::sync::Arc;
+
+    /// Drops the `{"` every JSON payload starts with
+    struct StripOpening;
+    impl Compressor for StripOpening {
+        fn id(&self) -> u8 {
+            200
+        }
+        fn compress(&self, data: &[u8]) -> Vec<u8> {
+            data[2..].to_vec()
+        }
+        fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, EngineError> {
+            Ok([b"{\"", data].concat())
+        }
+    }
+
+    let dir = branch_dir("wal_custom_compressor");
+    let record = WalRecord::AddNode { id: 1, labels: vec!["N".into()], properties: HashMap::new() };
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap()
+        .with_compression(Compression::new(Arc::new(StripOpening)).with_threshold(0));
+    writer.append(&record).unwrap();
+    drop(writer);
+    let error = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap_err();
+    assert!(error.to_string().contains("unknown compressor id 200"), "{}", error);
+
+    register_compressor(Arc::new(StripOpening)).unwrap();
+    assert!(matches!(register_compressor(Arc::new(StripOpening)), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(register_compressor(Arc::new(IdentityCompressor)), Err(EngineError::InvalidArgument(_))));
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap(), vec![(1, record)]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(feature = "deflate"))]
+#[test]
+fn wal_readers_without_deflate_refuse_its_frames() {
+    use casys_engine::index::persistence::{Compression, Compressor, SyncPolicy, WalReader, WalRecord, WalWriter};
+    use std::sync::Arc;
+
+    /// Writes frames under the id of the DEFLATE built-in
+    struct Pretend;
+    impl Compressor for Pretend {
+        fn id(&self) -> u8 {
+            1
+        }
+        fn compress(&self, _data: &[u8]) -> Vec<u8> {
+            Vec::new()
+        }
+        fn decompress(&self, _data: &[u8]) -> Result<Vec<u8>, EngineError> {
+            unreachable!()
+        }
+    }
+
+    let dir = branch_dir("wal_without_deflate");
+    let record = WalRecord::AddNode { id: 1, labels: vec!["N".into()], properties: HashMap::new() };
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap()
+        .with_compression(Compression::new(Arc::new(Pretend)).with_threshold(0));
+    writer.append(&record).unwrap();
+    drop(writer);
+    match WalReader::open_branch(&dir, 1).unwrap().read_entries() {
+        Err(EngineError::NotImplemented(detail)) => assert!(detail.contains("feature `deflate`"), "{}", detail),
+        other => panic!("expected NotImplemented, got {:?}", other),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Fresh branch directory under target/tmp for WAL file tests.
+fn branch_dir(name: &str) -> std::path::PathBuf {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    std::env::current_dir().unwrap().join("target").join("tmp").join(format!("{}_{}", name, now))
+}
+
+#[test]
+fn wal_reader_stops_at_a_record_torn_mid_write() {
+    use casys_engine::index::persistence::{SyncPolicy, WalReader, WalRecord, WalWriter};
+
+    let dir = branch_dir("wal_torn");
+    let records: Vec<WalRecord> = (1..=4)
+        .map(|id| WalRecord::AddNode { id, labels: vec!["N".into()], properties: HashMap::new() })
+        .collect();
+    let writer = WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap();
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(writer.append(record).unwrap(), i as u64 + 1);
+    }
+    let path = writer.path();
+    assert!(path.ends_with("wal/wal-000001.wal"));
+    drop(writer);
+
+    // Kill the writer while it writes a fifth frame: only part of it reaches the file
+    let full = std::fs::read(&path).unwrap();
+    let mut fifth = full.clone();
+    // The four frames have the same length
+    fifth.extend_from_slice(&full[..full.len() / 4 - 3]);
+    std::fs::write(&path, &fifth).unwrap();
+
+    let read: Vec<_> = WalReader::open(&path).unwrap().collect();
+    assert_eq!(read.len(), 5);
+    for (i, record) in records.iter().enumerate() {
+        assert_eq!(read[i].as_ref().unwrap(), &(i as u64 + 1, record.clone()));
+    }
+    assert!(matches!(read[4], Err(EngineError::WalCorruption { offset }) if offset == full.len() as u64));
+    let mut reader = WalReader::open(&path).unwrap();
+    assert_eq!(reader.read_all().unwrap(), records);
+    assert_eq!(reader.valid_records(), 4);
+    assert_eq!(reader.corruption(), Some(full.len() as u64));
+
+    // Reopening cuts the torn frame off and numbering continues after the last full record
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), full.len() as u64);
+    assert_eq!(writer.append(&WalRecord::PurgeTombstones).unwrap(), 5);
+    writer.sync().unwrap();
+    let read = WalReader::open(&path).unwrap().read_all().unwrap();
+    assert_eq!(read.len(), 5);
+    assert_eq!(read[4], WalRecord::PurgeTombstones);
+
+    // A flipped payload byte fails the checksum and ends the log there too
+    let mut corrupt = std::fs::read(&path).unwrap();
+    let last = corrupt.len() - 2;
+    corrupt[last] ^= 0xff;
+    std::fs::write(&path, &corrupt).unwrap();
+    let mut reader = WalReader::open(&path).unwrap();
+    assert_eq!(reader.read_all().unwrap().len(), 4);
+    assert_eq!(reader.corruption(), Some(full.len() as u64));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wal_frames_are_checksummed_and_bare_json_still_reads() {
+    use casys_engine::index::persistence::{wal_dir, SyncPolicy, WalReader, WalRecord, WalWriter};
+
+    let record = WalRecord::SetNodeProperty { id: 1, key: "k".into(), value: Value::Int(3) };
+    let frame = record.to_frame(7);
+    let payload = &frame[16..];
+    assert_eq!(u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize, payload.len());
+    assert_eq!(u64::from_le_bytes(frame[8..16].try_into().unwrap()), 7);
+    assert_eq!(WalRecord::from_frame(&frame).unwrap(), (7, record.clone()));
+    assert_eq!(WalRecord::from_bytes(payload).unwrap(), record);
+
+    // Frames from before the LSN was in the header still read, with no LSN
+    let mut unnumbered = (payload.len() as u32).to_le_bytes().to_vec();
+    unnumbered.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+    unnumbered.extend_from_slice(payload);
+    assert_eq!(WalRecord::from_frame(&unnumbered).unwrap(), (0, record.clone()));
+    let frame = record.to_bytes();
+
+    let mut flipped = frame.clone();
+    *flipped.last_mut().unwrap() ^= 0x01;
+    assert!(matches!(WalRecord::from_bytes(&flipped), Err(EngineError::WalCorruption { offset: 0 })));
+    assert!(matches!(WalRecord::from_bytes(&frame[..frame.len() - 1]), Err(EngineError::WalCorruption { offset: 0 })));
+    let mut two = frame.clone();
+    two.extend_from_slice(&frame);
+    assert!(matches!(WalRecord::from_bytes(&two), Err(EngineError::WalCorruption { offset }) if offset == frame.len() as u64));
+
+    // A current.wal holding one record from before framing reads, and the writer reframes
+    // it as the first numbered file
+    let dir = branch_dir("wal_legacy");
+    let legacy = wal_dir(&dir).join("current.wal");
+    std::fs::create_dir_all(wal_dir(&dir)).unwrap();
+    std::fs::write(&legacy, payload).unwrap();
+    assert_eq!(WalReader::open(&legacy).unwrap().read_all().unwrap(), vec![record.clone()]);
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap(), vec![record.clone()]);
+    let writer = WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap();
+    assert!(!legacy.exists());
+    assert_eq!(writer.append(&WalRecord::PurgeTombstones).unwrap(), 2);
+    assert_eq!(std::fs::read(writer.path()).unwrap()[..frame.len()], record.to_frame(1)[..]);
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap(), vec![record, WalRecord::PurgeTombstones]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wal_writer_syncs_by_policy() {
+    use casys_engine::index::persistence::{SyncPolicy, WalRecord, WalWriter};
+
+    let dir = branch_dir("wal_policy");
+    let record = WalRecord::DeleteEdge { id: 1 };
+    let writer = WalWriter::open(&dir, SyncPolicy::EveryN(3)).unwrap();
+    writer.append(&record).unwrap();
+    writer.append(&record).unwrap();
+    assert_eq!(writer.unsynced(), 2);
+    writer.append(&record).unwrap();
+    assert_eq!(writer.unsynced(), 0);
+    drop(writer);
+
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    assert_eq!(writer.next_lsn(), 4);
+    writer.append(&record).unwrap();
+    writer.append(&record).unwrap();
+    assert_eq!(writer.unsynced(), 2);
+    writer.sync().unwrap();
+    assert_eq!(writer.unsynced(), 0);
+    drop(writer);
+
+    let writer = WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap();
+    writer.append(&record).unwrap();
+    assert_eq!(writer.unsynced(), 0);
+    assert_eq!(writer.next_lsn(), 7);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wal_stats_and_inspection_account_for_every_frame() {
+    use casys_engine::index::persistence::{inspect_wal, SyncPolicy, WalAnomaly, WalFrames, WalRecord, WalWriter};
+    use std::io::Write;
+
+    let dir = branch_dir("wal_inspect");
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    assert_eq!(writer.stats().records, 0);
+    for id in 1..=3 {
+        writer.append(&WalRecord::AddNode { id, labels: vec!["N".into()], properties: HashMap::new() }).unwrap();
+    }
+    writer.append(&WalRecord::SetNodeProperty { id: 1, key: "k".into(), value: Value::Int(1) }).unwrap();
+    assert_eq!(writer.stats().last_sync, None);
+    writer.append_group(&[WalRecord::DeleteEdge { id: 1 }, WalRecord::DeleteEdge { id: 2 }]).unwrap();
+    let stats = writer.stats();
+    let path = writer.path();
+    let len = std::fs::metadata(&path).unwrap().len();
+    assert_eq!((stats.records, stats.bytes, stats.first_lsn, stats.last_lsn), (6, len, Some(1), Some(6)));
+    assert!(stats.last_sync.is_some());
+    let by_type: Vec<(&str, u64)> = stats.by_type.iter().map(|(t, n)| (*t, *n)).collect();
+    assert_eq!(by_type, vec![("add_node", 3), ("delete_edge", 2), ("set_node_property", 1)]);
+    drop(writer);
+
+    let clean = inspect_wal(&path).unwrap();
+    assert!(clean.is_clean());
+    assert_eq!((clean.records, clean.bytes, clean.first_lsn, clean.last_lsn), (6, len, Some(1), Some(6)));
+    assert_eq!(clean.by_type, stats.by_type);
+    let frames: Vec<_> = WalFrames::open(&path).unwrap().map(Result::unwrap).collect();
+    assert_eq!(frames.iter().map(|f| f.len).sum::<u64>(), len);
+    assert_eq!(frames.iter().map(|f| f.lsn.unwrap()).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6]);
+
+    // A damaged frame, a frame that does not decode, a misnumbered one and a torn tail
+    let mut data = std::fs::read(&path).unwrap();
+    let second = &frames[1];
+    data[(second.offset + second.len - 1) as usize] ^= 0xff;
+    let payload = b"{\"type\": \"no_such_record\"}";
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&7u64.to_le_bytes());
+    crc.update(payload);
+    data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    data.extend_from_slice(&crc.finalize().to_le_bytes());
+    data.extend_from_slice(&7u64.to_le_bytes());
+    data.extend_from_slice(payload);
+    data.extend_from_slice(&WalRecord::DeleteEdge { id: 3 }.to_frame(8));
+    data.extend_from_slice(&WalRecord::DeleteEdge { id: 4 }.to_frame(10));
+    data.extend_from_slice(&[1, 2, 3]);
+    std::fs::File::create(&path).unwrap().write_all(&data).unwrap();
+
+    let report = inspect_wal(&path).unwrap();
+    assert_eq!(report.records, 7);
+    assert_eq!((report.by_type["add_node"], report.by_type["delete_edge"]), (2, 4));
+    assert_eq!((report.first_lsn, report.last_lsn), (Some(1), Some(10)));
+    assert_eq!(report.anomalies.len(), 4, "{:?}", report.anomalies);
+    assert_eq!(report.anomalies[0], WalAnomaly::BadFrame { offset: second.offset, len: second.len });
+    assert!(matches!(&report.anomalies[1], WalAnomaly::BadPayload { offset, .. } if *offset == len));
+    assert!(matches!(report.anomalies[2], WalAnomaly::LsnGap { expected: 9, found: 10, .. }));
+    assert_eq!(report.anomalies[3], WalAnomaly::BadFrame { offset: data.len() as u64 - 3, len: 3 });
+    assert!(matches!(inspect_wal(&dir.join("missing.wal")), Err(EngineError::NotFound(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Type names are the JSON `type` of each record
+    let mut gen = RecordGen { state: 0x2545_f491_4f6c_dd1d, json: true };
+    for _ in 0..500 {
+        let record = gen.record(false);
+        let json: serde_json::Value = serde_json::from_slice(&record.to_frame(1)[16..]).unwrap();
+        assert_eq!(json["type"], record.type_name());
+    }
+}
+
+#[test]
+fn dump_wal_prints_a_line_per_record_and_marks_what_it_cannot_read() {
+    use casys_engine::index::persistence::{dump_wal, DumpFormat, SyncPolicy, WalFrames, WalRecord, WalWriter};
+
+    let dir = branch_dir("wal_dump");
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    writer.append(&WalRecord::AddNode {
+        id: 1,
+        labels: vec!["Person".into(), "Admin".into()],
+        properties: HashMap::from([("name".to_string(), Value::String("a".into())), ("age".to_string(), Value::Int(3))]),
+    }).unwrap();
+    writer.append(&WalRecord::InTxn {
+        txn_id: 4,
+        record: Box::new(WalRecord::SetNodeProperty { id: 1, key: "name".into(), value: Value::String("b".into()) }),
+    }).unwrap();
+    writer.append(&WalRecord::DeleteEdge { id: 2 }).unwrap();
+    writer.append(&WalRecord::PurgeTombstones).unwrap();
+    writer.sync().unwrap();
+    let path = writer.path();
+    drop(writer);
+
+    let dump = |format| {
+        let mut out = Vec::new();
+        dump_wal(&path, &mut out, format).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!(dump(DumpFormat::Text), "\
+1 add_node id=1 labels=Person,Admin keys=age,name
+2 set_node_property id=1 key=name txn=4
+3 delete_edge id=2
+4 purge_tombstones
+");
+    let lines: Vec<serde_json::Value> = dump(DumpFormat::JsonLines).lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1], serde_json::json!({
+        "lsn": 2,
+        "record": { "type": "set_node_property", "id": 1, "key": "name", "value": "b", "txn_id": 4 },
+    }));
+
+    // Damage the second frame: its line becomes a marker and the dump goes on
+    let mut data = std::fs::read(&path).unwrap();
+    let offset = WalFrames::open(&path).unwrap().nth(1).unwrap().unwrap().offset;
+    data[offset as usize + 20] ^= 0xff;
+    std::fs::write(&path, &data).unwrap();
+    let text = dump(DumpFormat::Text);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", text);
+    assert!(lines[1].starts_with(&format!("! offset {}: ", offset)), "{}", text);
+    assert_eq!(lines[2], "3 delete_edge id=2");
+    let marker: serde_json::Value = serde_json::from_str(dump(DumpFormat::JsonLines).lines().nth(1).unwrap()).unwrap();
+    assert_eq!((marker["error"].as_str(), marker["offset"].as_u64()), (Some("bad_frame"), Some(offset)));
+
+    assert!(matches!(dump_wal(&dir.join("missing.wal"), &mut Vec::new(), DumpFormat::Text), Err(EngineError::NotFound(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn attached_wal_writer_logs_mutations_for_replay() {
+    use casys_engine::index::persistence::{SyncPolicy, WalReader, WalWriter};
+
+    let dir = branch_dir("wal_attached");
+    let mut store = InMemoryGraphStore::new();
+    let before = node(&mut store, "N");
+    assert!(store.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap()).is_none());
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let e = store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.set_node_property(a, "name".into(), Value::String("a".into())).unwrap();
+    store.sync_wal().unwrap();
+    assert_eq!(store.wal_writer().unwrap().next_lsn(), 5);
+
+    // Replay does not log again
+    let records = WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap();
+    assert_eq!(records.len(), 4);
+    store.replay_wal(&records).unwrap();
+    assert_eq!(store.detach_wal_writer().unwrap().next_lsn(), 5);
+    node(&mut store, "N");
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap().len(), 4);
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    assert!(replayed.get_node(before).unwrap().is_none());
+    assert_eq!(ids_with_label(&replayed, "N"), vec![a, b]);
+    assert_eq!(replayed.get_neighbors(a, None).unwrap()[0].0.id, e);
+    assert_eq!(replayed.get_node(a).unwrap().unwrap().properties.get("name"), Some(&Value::String("a".into())));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn wal_rotates_across_files_and_replays_identically() {
+    use casys_engine::index::persistence::{SyncPolicy, WalReader, WalWriter};
+
+    let dir = branch_dir("wal_rotation");
+    let mut store = InMemoryGraphStore::new();
+    store.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap().with_max_file_bytes(400));
+    let mut ids = Vec::new();
+    for i in 0..12 {
+        let id = node(&mut store, "N");
+        store.set_node_property(id, "i".into(), Value::Int(i)).unwrap();
+        if let Some(prev) = ids.last() {
+            store.add_edge(*prev, id, "NEXT".into(), HashMap::new()).unwrap();
+        }
+        ids.push(id);
+    }
+    store.delete_node(ids[5], true).unwrap();
+    let writer = store.wal_writer().unwrap();
+    let files = writer.files();
+    assert!(files.len() >= 4, "three rotations, got {} files", files.len());
+    assert!(files.iter().all(|f| std::fs::metadata(f).unwrap().len() <= 400));
+    assert_eq!(files.last(), Some(&writer.path()));
+    let last = writer.next_lsn() - 1;
+
+    let mut reader = WalReader::open_branch(&dir, 1).unwrap();
+    let records = reader.read_all().unwrap();
+    assert_eq!(records.len() as u64, last);
+    assert_eq!(reader.corruption(), None);
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    let rows = |s: &InMemoryGraphStore| {
+        let mut nodes: Vec<_> = s.scan_all().unwrap().into_iter().map(|n| (n.id, n.properties.get("i").cloned())).collect();
+        nodes.sort_by_key(|n| n.0);
+        let mut edges: Vec<_> = s.scan_all_edges().unwrap().into_iter().map(|e| (e.id, e.from_node, e.to_node)).collect();
+        edges.sort_unstable();
+        (nodes, edges)
+    };
+    assert_eq!(rows(&replayed), rows(&store));
+    assert!(replayed.verify_indexes().is_ok());
+
+    // Reading from a later LSN skips whole files: the first one is not even opened
+    std::fs::remove_file(&files[0]).unwrap();
+    assert!(matches!(WalReader::open_branch(&dir, 1).unwrap().read_all(), Err(EngineError::NotFound(_))));
+    let tail: Vec<u64> = WalReader::open_branch(&dir, last - 2).unwrap().map(|r| r.unwrap().0).collect();
+    assert_eq!(tail, vec![last - 2, last - 1, last]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replaying_a_log_twice_from_the_last_applied_lsn_changes_nothing() {
+    use casys_engine::index::persistence::{SyncPolicy, WalReader, WalWriter};
+
+    let dir = branch_dir("wal_lsn_replay");
+    let mut store = InMemoryGraphStore::new();
+    store.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_max_file_bytes(300));
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    store.add_label(b, "M".into()).unwrap();
+    store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.set_node_property(a, "name".into(), Value::String("a".into())).unwrap();
+    assert_eq!(store.last_applied_lsn(), 5);
+    drop(store.detach_wal_writer());
+
+    // LSNs come from the frame headers, across rotated files
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    assert_eq!(entries.iter().map(|(lsn, _)| *lsn).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    assert_eq!(WalReader::open_branch(&dir, 4).unwrap().read_entries().unwrap(), entries[3..].to_vec());
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal_from(&entries, 0).unwrap();
+    assert_eq!(replayed.last_applied_lsn(), 5);
+    let applied = replayed.last_applied_lsn();
+    replayed.replay_wal_from(&entries, applied).unwrap();
+    replayed.replay_wal_from(&entries[..2], applied).unwrap();
+    assert_eq!(replayed.last_applied_lsn(), 5);
+    assert_eq!(ids_with_label(&replayed, "N"), vec![a, b]);
+    assert_eq!(ids_with_label(&replayed, "M"), vec![b]);
+    assert_eq!(replayed.get_neighbors(a, None).unwrap().len(), 1);
+    assert!(replayed.verify_indexes().is_ok());
+    assert_eq!(node(&mut replayed, "N"), b + 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replay_wal_with_reports_progress_and_resumes_after_a_break() {
+    use casys_engine::index::persistence::{ReplayProgress, SyncPolicy, WalReader, WalWriter};
+    use std::ops::ControlFlow;
+
+    let dir = branch_dir("wal_replay_progress");
+    let mut store = InMemoryGraphStore::new();
+    store.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_max_file_bytes(300));
+    let a = node(&mut store, "N");
+    store.begin_wal_txn().unwrap();
+    let b = node(&mut store, "N");
+    store.commit_wal_txn().unwrap();
+    store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    let files = store.wal_writer().unwrap().files();
+    assert!(files.len() > 1);
+    drop(store.detach_wal_writer());
+    let log_bytes: u64 = files.iter().map(|f| std::fs::metadata(f).unwrap().len()).sum();
+
+    let mut full = InMemoryGraphStore::new();
+    let reached = full.replay_wal_with(WalReader::open_branch(&dir, 1).unwrap().entries(), None).unwrap();
+    assert_eq!(reached, ReplayProgress { applied: 3, bytes: log_bytes, last_lsn: 5 });
+    assert_eq!(full.last_applied_lsn(), 5);
+
+    // Stop inside the transaction: its node is not applied yet
+    let mut seen = Vec::new();
+    let mut stop_in_txn = |progress: ReplayProgress| {
+        seen.push((progress.applied, progress.last_lsn));
+        if progress.last_lsn == 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    };
+    let mut replayed = InMemoryGraphStore::new();
+    let reached = replayed
+        .replay_wal_with(WalReader::open_branch(&dir, 1).unwrap().entries(), Some(&mut stop_in_txn))
+        .unwrap();
+    assert_eq!(seen, vec![(1, 1), (1, 2), (1, 3)]);
+    assert_eq!((reached.applied, reached.last_lsn), (1, 3));
+    assert!(reached.bytes > 0 && reached.bytes < log_bytes);
+    assert_eq!(ids_with_label(&replayed, "N"), vec![a]);
+    // Resuming must read the transaction again from its BeginTxn
+    assert_eq!(replayed.last_applied_lsn(), 1);
+
+    let from = replayed.last_applied_lsn() + 1;
+    let rest = replayed.replay_wal_with(WalReader::open_branch(&dir, from).unwrap().entries(), None).unwrap();
+    assert_eq!((rest.applied, rest.last_lsn), (2, 5));
+    assert_eq!(replayed.last_applied_lsn(), 5);
+    assert_eq!(ids_with_label(&replayed, "N"), vec![a, b]);
+    assert_eq!(replayed.get_neighbors(a, None).unwrap().len(), 1);
+    assert!(replayed.verify_indexes().is_ok());
+
+    // Entries at or before last_applied_lsn are read but not applied again
+    let again = replayed.replay_wal_with(WalReader::open_branch(&dir, 1).unwrap().entries(), None).unwrap();
+    assert_eq!((again.applied, again.last_lsn), (0, 5));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn group_commit_writes_concurrent_commits_in_one_group() {
+    use casys_engine::index::persistence::{GroupCommitConfig, GroupCommitWriter, SyncPolicy, WalReader, WalRecord, WalWriter};
+    use std::time::Duration;
+
+    let dir = branch_dir("wal_group");
+    let config = GroupCommitConfig { max_records: 4, max_wait: Duration::from_secs(60) };
+    let group = GroupCommitWriter::new(WalWriter::open(&dir, SyncPolicy::Manual).unwrap(), config);
+    let mut lsns: Vec<u64> = std::thread::scope(|s| {
+        let handles: Vec<_> = (1..=4)
+            .map(|id| {
+                let group = &group;
+                s.spawn(move || group.commit(&WalRecord::DeleteEdge { id }).unwrap())
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    lsns.sort_unstable();
+    assert_eq!(lsns, vec![1, 2, 3, 4]);
+    let writer = group.into_inner();
+    assert_eq!((writer.next_lsn(), writer.unsynced()), (5, 0));
+
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    assert_eq!(entries.iter().map(|(lsn, _)| *lsn).collect::<Vec<_>>(), lsns);
+    let mut ids: Vec<u64> = entries.iter()
+        .map(|(_, record)| match record {
+            WalRecord::DeleteEdge { id } => *id,
+            other => panic!("unexpected record {:?}", other),
+        })
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+
+    // A group waits no longer than max_wait for others to join it
+    let config = GroupCommitConfig { max_records: 100, max_wait: Duration::from_millis(10) };
+    let group = GroupCommitWriter::new(writer, config);
+    assert_eq!(group.commit(&WalRecord::PurgeTombstones).unwrap(), 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn group_commit_fails_every_member_of_a_group_whose_write_fails() {
+    use casys_engine::index::persistence::{wal_dir, GroupCommitConfig, GroupCommitWriter, SyncPolicy, WalReader, WalRecord, WalWriter};
+    use std::time::Duration;
+
+    let dir = branch_dir("wal_group_failure");
+    let record = WalRecord::DeleteEdge { id: 1 };
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap()
+        .with_max_file_bytes(record.to_frame(1).len() as u64 + 1);
+    writer.append(&record).unwrap();
+    // The group must go to a new file, and a directory is in the way of creating it
+    std::fs::create_dir(wal_dir(&dir).join("wal-000002.wal")).unwrap();
+
+    let config = GroupCommitConfig { max_records: 3, max_wait: Duration::from_secs(60) };
+    let group = GroupCommitWriter::new(writer, config);
+    let results: Vec<_> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..3).map(|_| s.spawn(|| group.commit(&record))).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert!(results.iter().all(|r| matches!(r, Err(EngineError::StorageIo(_)))), "{:?}", results);
+    assert!(matches!(group.commit(&record), Err(EngineError::StorageIo(_))));
+    drop(group);
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap(), vec![record]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shared_wal_writer_numbers_concurrent_appends_in_file_order() {
+    use casys_engine::index::persistence::{
+        inspect_wal, GroupCommitConfig, GroupCommitWriter, SyncPolicy, WalFrames, WalReader, WalRecord, WalWriter,
+    };
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const THREADS: u64 = 8;
+    const PER_THREAD: u64 = 250;
+    // Frames of varying sizes, so an interleaved or torn write cannot go unnoticed
+    let record = |thread: u64, seq: u64| WalRecord::AddNode {
+        id: thread * 10_000 + seq,
+        labels: vec!["N".repeat((seq % 40 + 1) as usize)],
+        properties: HashMap::new(),
+    };
+
+    let dir = branch_dir("wal_shared_writer");
+    let writer = Arc::new(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap().with_max_file_bytes(16 * 1024));
+    let appended: Vec<Vec<u64>> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let writer = Arc::clone(&writer);
+                s.spawn(move || (0..PER_THREAD).map(|seq| writer.append(&record(thread, seq)).unwrap()).collect())
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    assert_eq!(writer.unsynced(), 0);
+    assert_eq!(writer.stats().records, THREADS * PER_THREAD);
+    let mut expected = HashMap::new();
+    for (thread, lsns) in (0..THREADS).zip(&appended) {
+        assert!(lsns.windows(2).all(|w| w[0] < w[1]), "thread {} got {:?}", thread, lsns);
+        expected.extend((0..PER_THREAD).zip(lsns).map(|(seq, lsn)| (*lsn, record(thread, seq))));
+    }
+    assert_eq!(expected.len() as u64, THREADS * PER_THREAD);
+
+    let files = writer.files();
+    assert!(files.len() > 1, "the appends should have rotated");
+    let mut next = 1;
+    for path in &files {
+        assert!(inspect_wal(path).unwrap().is_clean(), "{}", path.display());
+        for frame in WalFrames::open(path).unwrap() {
+            let frame = frame.unwrap();
+            assert_eq!(frame.lsn, Some(next));
+            assert_eq!(frame.record.unwrap(), expected[&next]);
+            next += 1;
+        }
+    }
+    assert_eq!(next, THREADS * PER_THREAD + 1);
+    drop(writer);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Direct appends go on alongside group commits
+    let dir = branch_dir("wal_shared_group");
+    let config = GroupCommitConfig { max_records: 4, max_wait: Duration::from_millis(1) };
+    let group = GroupCommitWriter::new(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap(), config);
+    std::thread::scope(|s| {
+        for thread in 0..THREADS {
+            let group = &group;
+            s.spawn(move || {
+                for seq in 0..PER_THREAD / 5 {
+                    let lsn = if thread % 2 == 0 {
+                        group.commit(&record(thread, seq))
+                    } else {
+                        group.writer().append(&record(thread, seq))
+                    };
+                    lsn.unwrap();
+                }
+            });
+        }
+    });
+    let writer = group.into_inner();
+    assert_eq!((writer.next_lsn(), writer.unsynced()), (THREADS * (PER_THREAD / 5) + 1, 0));
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    assert!(entries.iter().map(|(lsn, _)| *lsn).eq(1..writer.next_lsn()));
+    drop(writer);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// =============================================================================
+// Bulk inserts
+// =============================================================================
+
+#[test]
+fn add_nodes_bulk_returns_ids_in_input_order() {
+    let mut store = InMemoryGraphStore::new();
+    let first = node(&mut store, "Seed");
+
+    let batch: Vec<(Vec<String>, HashMap<String, Value>)> = (0..100)
+        .map(|i| {
+            let mut props = HashMap::new();
+            props.insert("i".to_string(), Value::Int(i));
+            let label = if i % 2 == 0 { "Even" } else { "Odd" };
+            (vec![label.to_string(), "Num".to_string()], props)
+        })
+        .collect();
+    let ids = store.add_nodes_bulk(batch).unwrap();
+
+    assert_eq!(ids.len(), 100);
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(*id, first + 1 + i as u64);
+        let n = store.get_node(*id).unwrap().unwrap();
+        assert_eq!(n.properties.get("i"), Some(&Value::Int(i as i64)));
+    }
+    assert_eq!(store.scan_by_label("Num").unwrap().len(), 100);
+    assert_eq!(store.scan_by_label("Even").unwrap().len(), 50);
+
+    // Regular inserts continue after the reserved range
+    assert_eq!(node(&mut store, "After"), first + 101);
+}
+
+#[test]
+fn add_edges_bulk_builds_adjacency() {
+    let mut store = InMemoryGraphStore::new();
+    let ids = store.add_nodes_bulk(vec![(vec!["N".into()], HashMap::new()); 3]).unwrap();
+    let edge_ids = store.add_edges_bulk(vec![
+        (ids[0], ids[1], "LINK".into(), HashMap::new()),
+        (ids[0], ids[2], "LINK".into(), HashMap::new()),
+        (ids[1], ids[2], "OTHER".into(), HashMap::new()),
+    ]).unwrap();
+
+    assert_eq!(edge_ids.len(), 3);
+    assert!(edge_ids.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(store.get_neighbors(ids[0], Some("LINK")).unwrap().len(), 2);
+    assert_eq!(store.get_neighbors_incoming(ids[2], None).unwrap().len(), 2);
+}
+
+#[test]
+fn add_edges_bulk_with_bad_endpoint_applies_nothing() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+
+    let err = store.add_edges_bulk(vec![
+        (a, b, "LINK".into(), HashMap::new()),
+        (a, 404, "LINK".into(), HashMap::new()),
+    ]).unwrap_err();
+
+    assert!(matches!(err, EngineError::NodeNotFound(404)));
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    // No edge ids were consumed by the failed batch
+    assert_eq!(store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap(), 1);
+}
+
+// =============================================================================
+// merge_node (get-or-create)
+// =============================================================================
+
+#[test]
+fn merge_node_creates_then_finds() {
+    let mut store = InMemoryGraphStore::new();
+    let email = Value::String("a@example.com".into());
+    let mut extra = HashMap::new();
+    extra.insert("name".to_string(), Value::String("Alice".into()));
+
+    let (id, created) = store.merge_node("Person", "email", email.clone(), extra).unwrap();
+    assert!(created);
+    let n = store.get_node(id).unwrap().unwrap();
+    assert_eq!(n.labels, vec!["Person".to_string()]);
+    assert_eq!(n.properties.get("email"), Some(&email));
+    assert_eq!(n.properties.get("name"), Some(&Value::String("Alice".into())));
+
+    let (again, created) = store.merge_node("Person", "email", email, HashMap::new()).unwrap();
+    assert!(!created);
+    assert_eq!(again, id);
+    assert_eq!(store.scan_by_label("Person").unwrap().len(), 1);
+}
+
+#[test]
+fn merge_node_is_scoped_to_label() {
+    let mut store = InMemoryGraphStore::new();
+    let mut props = HashMap::new();
+    props.insert("email".to_string(), Value::String("x".into()));
+    let company = store.add_node(vec!["Company".into()], props).unwrap();
+
+    let (id, created) = store.merge_node("Person", "email", Value::String("x".into()), HashMap::new()).unwrap();
+    assert!(created);
+    assert_ne!(id, company);
+}
+
+#[test]
+fn merge_node_reports_duplicate_matches() {
+    let mut store = InMemoryGraphStore::new();
+    for _ in 0..2 {
+        let mut props = HashMap::new();
+        props.insert("email".to_string(), Value::String("dup".into()));
+        store.add_node(vec!["Person".into()], props).unwrap();
+    }
+
+    let err = store.merge_node("Person", "email", Value::String("dup".into()), HashMap::new()).unwrap_err();
+    assert!(matches!(err, EngineError::InvalidArgument(_)));
+    assert_eq!(store.scan_by_label("Person").unwrap().len(), 2);
+}
+
+// =============================================================================
+// merge_nodes (deduplication)
+// =============================================================================
+
+fn edge_ids(neighbors: Vec<(casys_core::Edge, casys_core::Node)>) -> Vec<u64> {
+    let mut ids: Vec<u64> = neighbors.into_iter().map(|(e, _)| e.id).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn merge_nodes_rewires_edges_and_combines_data() {
+    let mut store = InMemoryGraphStore::new();
+    let mut pa = HashMap::new();
+    pa.insert("name".to_string(), Value::String("A".into()));
+    let a = store.add_node(vec!["Person".into()], pa).unwrap();
+    let mut pb = HashMap::new();
+    pb.insert("name".to_string(), Value::String("B".into()));
+    pb.insert("email".to_string(), Value::String("b@x".into()));
+    let b = store.add_node(vec!["Person".into(), "Customer".into()], pb).unwrap();
+    let c = node(&mut store, "City");
+    let d = node(&mut store, "Person");
+
+    let out_b = store.add_edge(b, c, "LIVES_IN".into(), HashMap::new()).unwrap();
+    let in_b = store.add_edge(d, b, "KNOWS".into(), HashMap::new()).unwrap();
+
+    store.merge_nodes(a, b, true).unwrap();
+
+    assert!(store.get_node(b).unwrap().is_none());
+    let merged = store.get_node(a).unwrap().unwrap();
+    assert_eq!(merged.labels, vec!["Person".to_string(), "Customer".to_string()]);
+    assert_eq!(merged.properties.get("name"), Some(&Value::String("A".into())));
+    assert_eq!(merged.properties.get("email"), Some(&Value::String("b@x".into())));
+
+    assert_eq!(ids_with_label(&store, "Person"), vec![a, d]);
+    assert_eq!(ids_with_label(&store, "Customer"), vec![a]);
+
+    assert_eq!(edge_ids(store.get_neighbors(a, None).unwrap()), vec![out_b]);
+    assert_eq!(edge_ids(store.get_neighbors_incoming(a, None).unwrap()), vec![in_b]);
+    assert_eq!(store.get_neighbors_incoming(c, None).unwrap()[0].1.id, a);
+    assert_eq!(store.get_neighbors(d, None).unwrap()[0].1.id, a);
+    assert!(store.get_neighbors(b, None).unwrap().is_empty());
+    assert!(store.get_neighbors_incoming(b, None).unwrap().is_empty());
+}
+
+#[test]
+fn merge_nodes_self_loop_policy() {
+    for keep_loops in [true, false] {
+        let mut store = InMemoryGraphStore::new();
+        let a = node(&mut store, "N");
+        let b = node(&mut store, "N");
+        let existing_loop = store.add_edge(a, a, "SELF".into(), HashMap::new()).unwrap();
+        let ab = store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+        let ba = store.add_edge(b, a, "LINK".into(), HashMap::new()).unwrap();
+        let bb = store.add_edge(b, b, "SELF".into(), HashMap::new()).unwrap();
+
+        store.merge_nodes(a, b, keep_loops).unwrap();
+
+        let out = edge_ids(store.get_neighbors(a, None).unwrap());
+        let incoming = edge_ids(store.get_neighbors_incoming(a, None).unwrap());
+        if keep_loops {
+            assert_eq!(out, vec![existing_loop, ab, ba, bb]);
+            assert_eq!(incoming, vec![existing_loop, ab, ba, bb]);
+        } else {
+            assert_eq!(out, vec![existing_loop]);
+            assert_eq!(incoming, vec![existing_loop]);
+        }
+    }
+}
+
+#[test]
+fn merge_nodes_rejects_bad_input() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    assert!(matches!(store.merge_nodes(a, a, true), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.merge_nodes(a, 99, true), Err(EngineError::NotFound(_))));
+    assert!(matches!(store.merge_nodes(99, a, true), Err(EngineError::NotFound(_))));
+    assert!(store.get_node(a).unwrap().is_some());
+}
+
+#[test]
+fn merge_nodes_replays_from_wal() {
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "M");
+    let c = node(&mut store, "N");
+    store.add_edge(b, c, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.merge_nodes(a, b, false).unwrap();
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&store.take_wal_records()).unwrap();
+
+    assert!(replayed.get_node(b).unwrap().is_none());
+    assert_eq!(ids_with_label(&replayed, "M"), vec![a]);
+    let out = replayed.get_neighbors(a, None).unwrap();
+    assert_eq!(out.len(), 1);
+    assert_eq!(out[0].1.id, c);
+}
+
+// =============================================================================
+// Explicit-id inserts
+// =============================================================================
+
+#[test]
+fn explicit_ids_interleave_with_auto_ids() {
+    let mut store = InMemoryGraphStore::new();
+    let auto1 = node(&mut store, "N");
+    assert_eq!(auto1, 1);
+
+    assert_eq!(store.add_node_with_id(10, vec!["Imported".into()], HashMap::new()).unwrap(), 10);
+    let auto2 = node(&mut store, "N");
+    assert_eq!(auto2, 11, "auto ids continue above the explicit id");
+
+    // An explicit id below the high-water mark is fine when free
+    assert_eq!(store.add_node_with_id(5, vec!["Imported".into()], HashMap::new()).unwrap(), 5);
+    assert_eq!(node(&mut store, "N"), 12);
+    assert_eq!(ids_with_label(&store, "Imported"), vec![5, 10]);
+
+    let e = store.add_edge_with_id(100, 10, 5, "REF".into(), HashMap::new()).unwrap();
+    assert_eq!(e, 100);
+    assert_eq!(store.add_edge(auto1, auto2, "REF".into(), HashMap::new()).unwrap(), 101);
+    assert_eq!(store.get_neighbors(10, Some("REF")).unwrap()[0].1.id, 5);
+}
+
+#[test]
+fn explicit_id_collisions_are_rejected() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let e = store.add_edge(a, a, "SELF".into(), HashMap::new()).unwrap();
+
+    assert!(matches!(store.add_node_with_id(a, vec![], HashMap::new()), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.add_edge_with_id(e, a, a, "X".into(), HashMap::new()), Err(EngineError::InvalidArgument(_))));
+    // The original records are untouched
+    assert_eq!(store.get_node(a).unwrap().unwrap().labels, vec!["N".to_string()]);
+    assert_eq!(store.get_neighbors(a, None).unwrap()[0].0.edge_type, "SELF");
+}
+
+// =============================================================================
+// compare_and_set_property
+// =============================================================================
+
+#[test]
+fn compare_and_set_swaps_only_on_match() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Doc");
+
+    // Absent -> set
+    assert!(store.compare_and_set_property(a, "version", None, Value::Int(1)).unwrap());
+    // Stale expectation -> no swap
+    assert!(!store.compare_and_set_property(a, "version", None, Value::Int(9)).unwrap());
+    assert!(!store.compare_and_set_property(a, "version", Some(Value::Int(0)), Value::Int(9)).unwrap());
+    // Matching expectation -> swap
+    assert!(store.compare_and_set_property(a, "version", Some(Value::Int(1)), Value::Int(2)).unwrap());
+
+    let n = store.get_node(a).unwrap().unwrap();
+    assert_eq!(n.properties.get("version"), Some(&Value::Int(2)));
+}
+
+#[test]
+fn compare_and_set_uses_value_equality_for_floats() {
+    let mut store = InMemoryGraphStore::new();
+    let mut props = HashMap::new();
+    props.insert("score".to_string(), Value::Float(f64::NAN));
+    let a = store.add_node(vec!["Doc".into()], props).unwrap();
+
+    assert!(!store.compare_and_set_property(a, "score", Some(Value::Int(0)), Value::Float(1.0)).unwrap());
+    assert!(store.compare_and_set_property(a, "score", Some(Value::Float(f64::NAN)), Value::Float(1.0)).unwrap());
+    assert!(store.compare_and_set_property(a, "score", Some(Value::Float(1.0)), Value::Float(2.0)).unwrap());
+}
+
+#[test]
+fn compare_and_set_on_missing_node_is_not_found() {
+    let mut store = InMemoryGraphStore::new();
+    let err = store.compare_and_set_property(3, "k", None, Value::Int(1)).unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+}
+
+// =============================================================================
+// increment_property
+// =============================================================================
+
+#[test]
+fn increment_node_and_edge_properties() {
+    use casys_engine::index::PropertyTarget;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Page");
+    let b = node(&mut store, "Page");
+    let e = store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+
+    // Absent -> created as delta
+    assert_eq!(store.increment_property(PropertyTarget::Node(a), "visits", Value::Int(1)).unwrap(), Value::Int(1));
+    assert_eq!(store.increment_property(PropertyTarget::Node(a), "visits", Value::Int(4)).unwrap(), Value::Int(5));
+    assert_eq!(store.get_node(a).unwrap().unwrap().properties.get("visits"), Some(&Value::Int(5)));
+
+    assert_eq!(store.increment_property(PropertyTarget::Edge(e), "weight", Value::Float(0.5)).unwrap(), Value::Float(0.5));
+    assert_eq!(store.increment_property(PropertyTarget::Edge(e), "weight", Value::Int(1)).unwrap(), Value::Float(1.5));
+    let (edge, _) = &store.get_neighbors(a, None).unwrap()[0];
+    assert_eq!(edge.properties.get("weight"), Some(&Value::Float(1.5)));
+}
+
+#[test]
+fn increment_rejects_non_numeric() {
+    use casys_engine::index::PropertyTarget;
+    let mut store = InMemoryGraphStore::new();
+    let mut props = HashMap::new();
+    props.insert("name".to_string(), Value::String("x".into()));
+    let a = store.add_node(vec!["Page".into()], props).unwrap();
+
+    let err = store.increment_property(PropertyTarget::Node(a), "name", Value::Int(1)).unwrap_err();
+    assert!(matches!(err, EngineError::TypeMismatch(_)));
+    let err = store.increment_property(PropertyTarget::Node(a), "n", Value::Bool(true)).unwrap_err();
+    assert!(matches!(err, EngineError::TypeMismatch(_)));
+    let err = store.increment_property(PropertyTarget::Edge(77), "n", Value::Int(1)).unwrap_err();
+    assert!(matches!(err, EngineError::NotFound(_)));
+    assert_eq!(store.get_node(a).unwrap().unwrap().properties.len(), 1);
+}
+
+#[test]
+fn increment_wal_records_resulting_value() {
+    use casys_engine::index::PropertyTarget;
+    use casys_engine::index::persistence::WalRecord;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "Page");
+    store.set_node_property(a, "visits".into(), Value::Int(10)).unwrap();
+
+    store.enable_wal_capture();
+    store.increment_property(PropertyTarget::Node(a), "visits", Value::Int(3)).unwrap();
+    let records = store.take_wal_records();
+    assert!(matches!(
+        records.as_slice(),
+        [WalRecord::SetNodeProperty { value: Value::Int(13), .. }]
+    ));
+
+    // Replaying twice yields the same value
+    store.replay_wal(&records).unwrap();
+    store.replay_wal(&records).unwrap();
+    assert_eq!(store.get_node(a).unwrap().unwrap().properties.get("visits"), Some(&Value::Int(13)));
+}
+
+// =============================================================================
+// delete_node, truncate, delete_nodes_by_label
+// =============================================================================
+
+#[test]
+fn delete_node_detach_removes_incident_edges() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let c = node(&mut store, "C");
+    store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(b, c, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(b, b, "SELF".into(), HashMap::new()).unwrap();
+
+    let err = store.delete_node(b, false).unwrap_err();
+    assert!(matches!(err, EngineError::InvalidArgument(_)));
+    assert!(store.get_node(b).unwrap().is_some());
+
+    assert!(store.delete_node(b, true).unwrap());
+    assert!(!store.delete_node(b, true).unwrap());
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert!(store.get_neighbors_incoming(c, None).unwrap().is_empty());
+    assert!(store.scan_by_label("B").unwrap().is_empty());
+
+    // A node without edges can be deleted without detach
+    assert!(store.delete_node(c, false).unwrap());
+}
+
+#[test]
+fn truncate_keeps_ids_monotonic_unless_reset() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    store.add_edge(a, a, "SELF".into(), HashMap::new()).unwrap();
+
+    store.truncate(false);
+    assert!(store.scan_all().unwrap().is_empty());
+    assert!(store.scan_by_label("A").unwrap().is_empty());
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert_eq!(node(&mut store, "A"), a + 1);
+
+    store.truncate(true);
+    assert_eq!(node(&mut store, "A"), 1);
+    assert_eq!(store.add_edge(1, 1, "SELF".into(), HashMap::new()).unwrap(), 1);
+}
+
+#[test]
+fn delete_nodes_by_label_fixes_up_surviving_neighbors() {
+    let mut store = InMemoryGraphStore::new();
+    let keep = node(&mut store, "Keep");
+    let t1 = node(&mut store, "Test");
+    let t2 = store.add_node(vec!["Test".into(), "Keep".into()], HashMap::new()).unwrap();
+    let t3 = node(&mut store, "Test");
+    store.add_edge(keep, t1, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(t2, keep, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(t1, t3, "LINK".into(), HashMap::new()).unwrap();
+
+    // Without detach nothing is removed
+    assert!(store.delete_nodes_by_label("Test", false).is_err());
+    assert_eq!(store.scan_by_label("Test").unwrap().len(), 3);
+
+    assert_eq!(store.delete_nodes_by_label("Test", true).unwrap(), 3);
+    assert!(store.scan_by_label("Test").unwrap().is_empty());
+    assert_eq!(ids_with_label(&store, "Keep"), vec![keep]);
+    assert!(store.get_neighbors(keep, None).unwrap().is_empty());
+    assert!(store.get_neighbors_incoming(keep, None).unwrap().is_empty());
+    assert_eq!(store.delete_nodes_by_label("Missing", false).unwrap(), 0);
+}
+
+#[test]
+fn bulk_removals_replay_from_wal() {
+    use casys_engine::index::persistence::WalRecord;
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "Keep");
+    let b = node(&mut store, "Test");
+    store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.delete_nodes_by_label("Test", true).unwrap();
+    store.truncate(false);
+    let c = node(&mut store, "Keep");
+
+    let records: Vec<WalRecord> = store.take_wal_records().iter()
+        .map(|r| WalRecord::from_bytes(&r.to_bytes()).unwrap())
+        .collect();
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+
+    assert_eq!(ids_with_label(&replayed, "Keep"), vec![c]);
+    assert!(replayed.scan_by_label("Test").unwrap().is_empty());
+    assert_eq!(replayed.scan_all().unwrap().len(), 1);
+}
+
+// =============================================================================
+// extract_subgraph
+// =============================================================================
+
+#[test]
+fn extract_subgraph_copies_internal_edges_only() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "Outside");
+    let ab = store.add_edge(a, b, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(b, c, "LINK".into(), HashMap::new()).unwrap();
+    store.add_edge(c, a, "LINK".into(), HashMap::new()).unwrap();
+
+    let sub = store.extract_subgraph(&[a, b], false).unwrap();
+
+    assert_eq!(sub.scan_all().unwrap().len(), 2);
+    assert_eq!(ids_with_label(&sub, "N"), vec![a, b]);
+    assert_eq!(edge_ids(sub.get_neighbors(a, None).unwrap()), vec![ab]);
+    assert!(sub.get_neighbors(b, None).unwrap().is_empty());
+    assert!(sub.get_neighbors_incoming(a, None).unwrap().is_empty());
+    // The source store is untouched
+    assert_eq!(store.scan_all().unwrap().len(), 3);
+}
+
+#[test]
+fn extract_subgraph_with_boundary_placeholders() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let mut props = HashMap::new();
+    props.insert("secret".to_string(), Value::Int(1));
+    let c = store.add_node(vec!["Outside".into()], props).unwrap();
+    let out = store.add_edge(a, c, "LINK".into(), HashMap::new()).unwrap();
+    let inc = store.add_edge(c, a, "LINK".into(), HashMap::new()).unwrap();
+
+    let mut sub = store.extract_subgraph(&[a], true).unwrap();
+
+    let placeholder = sub.get_node(c).unwrap().unwrap();
+    assert!(placeholder.labels.is_empty());
+    assert!(placeholder.properties.is_empty());
+    assert_eq!(edge_ids(sub.get_neighbors(a, None).unwrap()), vec![out]);
+    assert_eq!(edge_ids(sub.get_neighbors_incoming(a, None).unwrap()), vec![inc]);
+    assert!(sub.scan_by_label("Outside").unwrap().is_empty());
+
+    // Fresh inserts in the subgraph do not collide with preserved ids
+    let new_id = node(&mut sub, "New");
+    assert!(new_id > c);
+}
+
+#[test]
+fn extract_subgraph_unknown_node_is_not_found() {
+    let store = InMemoryGraphStore::new();
+    assert!(matches!(store.extract_subgraph(&[1], false), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// rename_edge_type
+// =============================================================================
+
+#[test]
+fn rename_edge_type_rewrites_matching_edges_only() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "P");
+    let b = node(&mut store, "P");
+    let k1 = store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    let k2 = store.add_edge(b, a, "KNOWS".into(), HashMap::new()).unwrap();
+    let other = store.add_edge(a, b, "LIKES".into(), HashMap::new()).unwrap();
+
+    assert_eq!(store.rename_edge_type("KNOWS", "FOLLOWS").unwrap(), 2);
+    assert_eq!(store.rename_edge_type("KNOWS", "FOLLOWS").unwrap(), 0);
+
+    assert!(store.get_neighbors(a, Some("KNOWS")).unwrap().is_empty());
+    assert_eq!(edge_ids(store.get_neighbors(a, Some("FOLLOWS")).unwrap()), vec![k1]);
+    assert_eq!(edge_ids(store.get_neighbors(b, Some("FOLLOWS")).unwrap()), vec![k2]);
+    assert_eq!(edge_ids(store.get_neighbors(a, Some("LIKES")).unwrap()), vec![other]);
+    assert!(matches!(store.rename_edge_type("LIKES", ""), Err(EngineError::InvalidArgument(_))));
+}
+
+#[test]
+fn rename_edge_type_logs_a_single_record() {
+    use casys_engine::index::persistence::WalRecord;
+
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "P");
+    let b = node(&mut store, "P");
+    for _ in 0..5 {
+        store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    }
+    store.enable_wal_capture();
+    store.rename_edge_type("KNOWS", "FOLLOWS").unwrap();
+    let records = store.disable_wal_capture();
+    assert_eq!(records.len(), 1);
+    let decoded = WalRecord::from_bytes(&records[0].to_bytes()).unwrap();
+    assert!(matches!(decoded, WalRecord::RenameEdgeType { ref old, ref new } if old == "KNOWS" && new == "FOLLOWS"));
+
+    store.rename_edge_type("FOLLOWS", "KNOWS").unwrap();
+    store.replay_wal(&[decoded]).unwrap();
+    assert_eq!(store.get_neighbors(a, Some("FOLLOWS")).unwrap().len(), 5);
+}
+
+// =============================================================================
+// copy_properties
+// =============================================================================
+
+fn node_with(store: &mut InMemoryGraphStore, props: &[(&str, Value)]) -> u64 {
+    let props = props.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+    store.add_node(vec!["N".into()], props).unwrap()
+}
+
+#[test]
+fn copy_properties_respects_conflict_policy() {
+    use casys_engine::index::CopyConflict;
+
+    let mut store = InMemoryGraphStore::new();
+    let parent = node_with(&mut store, &[("tier", Value::Int(1)), ("region", Value::String("eu".into()))]);
+    let child = node_with(&mut store, &[("tier", Value::Int(9))]);
+
+    assert_eq!(store.copy_properties(parent, child, None, CopyConflict::SkipExisting).unwrap(), 1);
+    let props = &store.get_node(child).unwrap().unwrap().properties;
+    assert_eq!(props.get("tier"), Some(&Value::Int(9)));
+    assert_eq!(props.get("region"), Some(&Value::String("eu".into())));
+
+    let err = store.copy_properties(parent, child, None, CopyConflict::Error).unwrap_err();
+    assert!(matches!(err, EngineError::InvalidArgument(_)));
+
+    assert_eq!(store.copy_properties(parent, child, None, CopyConflict::Overwrite).unwrap(), 2);
+    assert_eq!(store.get_node(child).unwrap().unwrap().properties.get("tier"), Some(&Value::Int(1)));
+}
+
+#[test]
+fn copy_properties_with_key_selection_and_missing_nodes() {
+    use casys_engine::index::CopyConflict;
+
+    let mut store = InMemoryGraphStore::new();
+    let parent = node_with(&mut store, &[("a", Value::Int(1)), ("b", Value::Int(2))]);
+    let child = node_with(&mut store, &[]);
+
+    let keys = vec!["b".to_string(), "absent".to_string()];
+    assert_eq!(store.copy_properties(parent, child, Some(&keys), CopyConflict::Error).unwrap(), 1);
+    let props = &store.get_node(child).unwrap().unwrap().properties;
+    assert_eq!(props.len(), 1);
+    assert_eq!(props.get("b"), Some(&Value::Int(2)));
+
+    assert_eq!(store.copy_properties(404, child, None, CopyConflict::Overwrite).unwrap(), 0);
+    assert!(matches!(
+        store.copy_properties(parent, 404, None, CopyConflict::Overwrite),
+        Err(EngineError::NotFound(_))
+    ));
+}
+
+// =============================================================================
+// set_default_property
+// =============================================================================
+
+#[test]
+fn set_default_property_backfills_only_missing() {
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let u1 = store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    let mut props = HashMap::new();
+    props.insert("active".to_string(), Value::Bool(false));
+    let u2 = store.add_node(vec!["User".into()], props).unwrap();
+    let other = node(&mut store, "Group");
+
+    assert_eq!(store.set_default_property("User", "active", Value::Bool(true)).unwrap(), 1);
+    assert_eq!(store.set_default_property("User", "active", Value::Bool(true)).unwrap(), 0);
+    assert_eq!(store.set_default_property("Nobody", "active", Value::Bool(true)).unwrap(), 0);
+
+    let active = |s: &InMemoryGraphStore, id| s.get_node(id).unwrap().unwrap().properties.get("active").cloned();
+    assert_eq!(active(&store, u1), Some(Value::Bool(true)));
+    assert_eq!(active(&store, u2), Some(Value::Bool(false)));
+    assert_eq!(active(&store, other), None);
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&store.take_wal_records()).unwrap();
+    assert_eq!(active(&replayed, u1), Some(Value::Bool(true)));
+    assert_eq!(active(&replayed, u2), Some(Value::Bool(false)));
+}
+
+// =============================================================================
+// delete_component
+// =============================================================================
+
+#[test]
+fn delete_component_removes_undirected_reachable_set() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "C");
+    let b = node(&mut store, "C");
+    let c = node(&mut store, "C");
+    let keep = node(&mut store, "Keep");
+    let keep2 = node(&mut store, "Keep");
+    store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    // c only points *into* the component; it must still be reached
+    store.add_edge(c, b, "E".into(), HashMap::new()).unwrap();
+    store.add_edge(b, b, "SELF".into(), HashMap::new()).unwrap();
+    let survivor = store.add_edge(keep, keep2, "E".into(), HashMap::new()).unwrap();
+
+    assert_eq!(store.delete_component(a).unwrap(), (3, 3));
+
+    assert!(ids_with_label(&store, "C").is_empty());
+    assert_eq!(ids_with_label(&store, "Keep"), vec![keep, keep2]);
+    assert_eq!(edge_ids(store.get_neighbors(keep, None).unwrap()), vec![survivor]);
+    for id in [a, b, c] {
+        assert!(store.get_neighbors(id, None).unwrap().is_empty());
+        assert!(store.get_neighbors_incoming(id, None).unwrap().is_empty());
+    }
+    assert!(matches!(store.delete_component(a), Err(EngineError::NotFound(_))));
+}
+
+#[test]
+fn delete_component_handles_long_chains() {
+    let mut store = InMemoryGraphStore::new();
+    let mut prev = node(&mut store, "Chain");
+    for _ in 0..10_000 {
+        let next = node(&mut store, "Chain");
+        store.add_edge(prev, next, "NEXT".into(), HashMap::new()).unwrap();
+        prev = next;
+    }
+
+    assert_eq!(store.delete_component(prev).unwrap(), (10_001, 10_000));
+    assert!(store.scan_all().unwrap().is_empty());
+}
+
+// =============================================================================
+// upsert_edge
+// =============================================================================
+
+#[test]
+fn upsert_edge_creates_once_then_merges() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let mut first = HashMap::new();
+    first.insert("runs".to_string(), Value::Int(1));
+    first.insert("source".to_string(), Value::String("import".into()));
+
+    let (e, created) = store.upsert_edge(a, b, "SYNCED".into(), first).unwrap();
+    assert!(created);
+
+    let mut second = HashMap::new();
+    second.insert("runs".to_string(), Value::Int(2));
+    assert_eq!(store.upsert_edge(a, b, "SYNCED".into(), second).unwrap(), (e, false));
+
+    let out = store.get_neighbors(a, None).unwrap();
+    assert_eq!(edge_ids(out.clone()), vec![e]);
+    assert_eq!(out[0].0.properties.get("runs"), Some(&Value::Int(2)));
+    assert_eq!(out[0].0.properties.get("source"), Some(&Value::String("import".into())));
+}
+
+#[test]
+fn upsert_edge_distinguishes_type_and_direction() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+
+    let (e1, _) = store.upsert_edge(a, b, "X".into(), HashMap::new()).unwrap();
+    let (e2, created_type) = store.upsert_edge(a, b, "Y".into(), HashMap::new()).unwrap();
+    let (e3, created_rev) = store.upsert_edge(b, a, "X".into(), HashMap::new()).unwrap();
+
+    assert!(created_type && created_rev);
+    assert!(e1 != e2 && e1 != e3 && e2 != e3);
+    assert_eq!(store.upsert_edge(a, b, "X".into(), HashMap::new()).unwrap(), (e1, false));
+}
+
+// =============================================================================
+// set_edge_endpoint / reverse_edge
+// =============================================================================
+
+#[test]
+fn set_edge_endpoint_moves_adjacency_and_keeps_identity() {
+    use casys_engine::index::Endpoint;
+
+    let mut store = InMemoryGraphStore::new();
+    let parent = node(&mut store, "Dir");
+    let new_parent = node(&mut store, "Dir");
+    let child = node(&mut store, "File");
+    let mut props = HashMap::new();
+    props.insert("since".to_string(), Value::Int(2020));
+    let e = store.add_edge(parent, child, "CONTAINS".into(), props).unwrap();
+
+    store.set_edge_endpoint(e, Endpoint::From, new_parent).unwrap();
+    assert!(store.get_neighbors(parent, None).unwrap().is_empty());
+    let out = store.get_neighbors(new_parent, None).unwrap();
+    assert_eq!(edge_ids(out.clone()), vec![e]);
+    assert_eq!(out[0].0.properties.get("since"), Some(&Value::Int(2020)));
+    assert_eq!(store.get_neighbors_incoming(child, None).unwrap()[0].1.id, new_parent);
+
+    store.set_edge_endpoint(e, Endpoint::To, parent).unwrap();
+    assert!(store.get_neighbors_incoming(child, None).unwrap().is_empty());
+    assert_eq!(edge_ids(store.get_neighbors_incoming(parent, None).unwrap()), vec![e]);
+
+    assert!(matches!(store.set_edge_endpoint(e, Endpoint::To, 99), Err(EngineError::NotFound(_))));
+    assert!(matches!(store.set_edge_endpoint(99, Endpoint::To, parent), Err(EngineError::NotFound(_))));
+}
+
+#[test]
+fn reverse_edge_swaps_direction_and_replays() {
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let c = node(&mut store, "C");
+    let e = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+
+    store.reverse_edge(e).unwrap();
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert_eq!(edge_ids(store.get_neighbors(b, None).unwrap()), vec![e]);
+    assert_eq!(edge_ids(store.get_neighbors_incoming(a, None).unwrap()), vec![e]);
+    store.set_edge_endpoint(e, casys_engine::index::Endpoint::To, c).unwrap();
+
+    use casys_engine::index::persistence::WalRecord;
+    let records: Vec<WalRecord> = store.take_wal_records().iter()
+        .map(|r| WalRecord::from_bytes(&r.to_bytes()).unwrap())
+        .collect();
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    let out = replayed.get_neighbors(b, None).unwrap();
+    assert_eq!(edge_ids(out.clone()), vec![e]);
+    assert_eq!(out[0].1.id, c);
+    assert!(replayed.get_neighbors_incoming(a, None).unwrap().is_empty());
+}
+
+// =============================================================================
+// Soft delete / tombstones
+// =============================================================================
+
+fn soft_store() -> InMemoryGraphStore {
+    InMemoryGraphStore::with_options(casys_engine::index::StoreOptions { soft_delete: true, ..Default::default() })
+}
+
+#[test]
+fn soft_deleted_records_are_hidden_from_reads() {
+    let mut store = soft_store();
+    let a = node(&mut store, "P");
+    let b = node(&mut store, "P");
+    let ab = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    let ba = store.add_edge(b, a, "E".into(), HashMap::new()).unwrap();
+
+    assert!(store.delete_edge(ba).unwrap());
+    assert!(!store.delete_edge(ba).unwrap(), "already tombstoned");
+    assert!(store.get_neighbors(b, None).unwrap().is_empty());
+    assert!(store.get_edge_including_deleted(ba).unwrap().unwrap().deleted);
+
+    assert!(store.delete_node(b, true).unwrap());
+    assert!(store.get_node(b).unwrap().is_none());
+    assert_eq!(ids_with_label(&store, "P"), vec![a]);
+    assert_eq!(store.scan_all().unwrap().len(), 1);
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+    assert!(store.get_edge_including_deleted(ab).unwrap().unwrap().deleted);
+    assert!(matches!(store.set_node_property(b, "k".into(), Value::Int(1)), Err(EngineError::NotFound(_))));
+
+    let tomb = store.get_node_including_deleted(b).unwrap().unwrap();
+    assert!(tomb.deleted);
+    assert_eq!(tomb.labels, vec!["P".to_string()]);
+}
+
+#[test]
+fn undelete_restores_indexes_and_requires_live_endpoints() {
+    let mut store = soft_store();
+    let a = node(&mut store, "P");
+    let b = node(&mut store, "P");
+    let ab = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    store.delete_node(b, true).unwrap();
+
+    assert!(matches!(store.undelete_edge(ab), Err(EngineError::InvalidArgument(_))));
+    assert!(store.undelete_node(b).unwrap());
+    assert!(!store.undelete_node(b).unwrap());
+    assert_eq!(ids_with_label(&store, "P"), vec![a, b]);
+    assert!(store.get_neighbors(a, None).unwrap().is_empty(), "edges stay deleted");
+
+    assert!(store.undelete_edge(ab).unwrap());
+    assert_eq!(edge_ids(store.get_neighbors(a, None).unwrap()), vec![ab]);
+    assert!(matches!(store.undelete_node(99), Err(EngineError::NotFound(_))));
+}
+
+#[test]
+fn purge_tombstones_removes_records_and_replays() {
+    let mut store = soft_store();
+    store.enable_wal_capture();
+    let a = node(&mut store, "P");
+    let b = node(&mut store, "P");
+    let c = node(&mut store, "P");
+    let ab = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    store.add_edge(b, c, "E".into(), HashMap::new()).unwrap();
+    store.delete_node(c, true).unwrap();
+    store.delete_edge(ab).unwrap();
+    store.undelete_edge(ab).unwrap();
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&store.take_wal_records()).unwrap();
+
+    for s in [&mut store, &mut replayed] {
+        assert!(s.get_node_including_deleted(c).unwrap().unwrap().deleted);
+        assert_eq!(s.purge_tombstones(), (1, 1));
+        assert!(s.get_node_including_deleted(c).unwrap().is_none());
+        assert_eq!(edge_ids(s.get_neighbors(a, None).unwrap()), vec![ab]);
+        assert!(s.get_neighbors(b, None).unwrap().is_empty());
+        assert!(matches!(s.undelete_node(c), Err(EngineError::NotFound(_))));
+    }
+}
+
+#[test]
+fn hard_delete_is_still_the_default() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "P");
+    store.delete_node(a, false).unwrap();
+    assert!(store.get_node_including_deleted(a).unwrap().is_none());
+}
+
+// =============================================================================
+// Edge endpoint validation
+// =============================================================================
+
+#[test]
+fn add_edge_rejects_missing_endpoints_by_default() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "A");
+
+    let err = store.add_edge(a, 77, "E".into(), HashMap::new()).unwrap_err();
+    assert!(matches!(err, EngineError::NodeNotFound(77)));
+    assert!(matches!(store.add_edge(78, a, "E".into(), HashMap::new()), Err(EngineError::NodeNotFound(78))));
+    assert!(matches!(store.add_edge_with_id(5, a, 79, "E".into(), HashMap::new()), Err(EngineError::NodeNotFound(79))));
+    assert!(store.validate_references().is_empty());
+    assert!(store.get_neighbors(a, None).unwrap().is_empty());
+}
+
+#[test]
+fn unchecked_edges_are_reported_until_nodes_arrive() {
+    let mut store = InMemoryGraphStore::new();
+    let e1 = store.add_edge_unchecked(10, 11, "E".into(), HashMap::new()).unwrap();
+    let e2 = store.add_edge_unchecked(11, 12, "E".into(), HashMap::new()).unwrap();
+    assert_eq!(store.validate_references(), vec![e1, e2]);
+
+    for id in [10, 11] {
+        store.add_node_with_id(id, vec!["N".into()], HashMap::new()).unwrap();
+    }
+    assert_eq!(store.validate_references(), vec![e2]);
+    assert_eq!(edge_ids(store.get_neighbors(10, None).unwrap()), vec![e1]);
+}
+
+#[test]
+fn lenient_store_accepts_dangling_edges() {
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        strict_edges: false,
+        ..Default::default()
+    });
+    let e = store.add_edge(1, 2, "E".into(), HashMap::new()).unwrap();
+    assert_eq!(store.validate_references(), vec![e]);
+}
+
+// =============================================================================
+// Id allocation
+// =============================================================================
+
+#[test]
+fn deleted_ids_are_not_reused_by_default() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    store.delete_node(a, false).unwrap();
+    assert_ne!(node(&mut store, "N"), a);
+}
+
+#[test]
+fn reuse_ids_recycles_physically_removed_records() {
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        reuse_ids: true,
+        ..Default::default()
+    });
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let e = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    store.delete_node(a, true).unwrap();
+
+    assert_eq!(node(&mut store, "N"), a);
+    let c = node(&mut store, "N");
+    assert!(c > b);
+    assert_eq!(store.add_edge(b, c, "E".into(), HashMap::new()).unwrap(), e);
+}
+
+#[test]
+fn bulk_insert_takes_a_fresh_contiguous_range() {
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        reuse_ids: true,
+        ..Default::default()
+    });
+    let a = node(&mut store, "N");
+    node(&mut store, "N");
+    store.delete_node(a, false).unwrap();
+
+    let ids = store.add_nodes_bulk(vec![(vec!["N".into()], HashMap::new()); 3]).unwrap();
+    assert_eq!(ids, vec![3, 4, 5]);
+    assert_eq!(node(&mut store, "N"), a);
+}
+
+// =============================================================================
+// verify_indexes / rebuild_indexes
+// =============================================================================
+
+#[test]
+fn indexes_stay_consistent_through_mutations() {
+    use casys_engine::index::Endpoint;
+
+    let mut store = soft_store();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    let c = node(&mut store, "C");
+    let ab = store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    let bc = store.add_edge(b, c, "E".into(), HashMap::new()).unwrap();
+    store.add_edge(c, a, "E".into(), HashMap::new()).unwrap();
+    store.add_label(a, "Extra".into()).unwrap();
+    store.remove_label(b, "B").unwrap();
+    store.set_edge_endpoint(ab, Endpoint::To, c).unwrap();
+    store.reverse_edge(bc).unwrap();
+    store.delete_node(b, true).unwrap();
+    store.merge_nodes(a, c, false).unwrap();
+    assert_eq!(store.verify_indexes(), Ok(()));
+
+    store.rebuild_indexes();
+    assert_eq!(store.verify_indexes(), Ok(()));
+    assert_eq!(ids_with_label(&store, "C"), vec![a]);
+}
+
+#[test]
+fn check_indexes_is_clean_and_repair_is_a_no_op_on_a_consistent_store() {
+    let mut store = soft_store();
+    let a = node(&mut store, "A");
+    let b = node(&mut store, "B");
+    store.add_edge(a, b, "E".into(), HashMap::new()).unwrap();
+    store.create_property_index("A", "name").unwrap();
+    store.delete_node(b, true).unwrap();
+
+    let report = store.check_indexes();
+    assert!(report.is_clean(), "{:?}", report);
+    assert!(report.affected().is_empty());
+    assert!(store.repair_indexes().repaired.is_empty());
+    assert_eq!(store.node_count().unwrap(), 1);
+}
+
+#[test]
+fn repeated_label_is_indexed_once() {
+    use casys_engine::index::{IndexEntry, IndexInconsistency};
+
+    let mut store = InMemoryGraphStore::new();
+    let a = store.add_node(vec!["Tag".into(), "Tag".into()], HashMap::new()).unwrap();
+    store.add_nodes_bulk(vec![(vec!["Tag".into(), "Tag".into()], HashMap::new())]).unwrap();
+    assert_eq!(store.verify_indexes(), Ok(()));
+    assert_eq!(store.scan_by_label("Tag").unwrap().len(), 2);
+
+    store.rebuild_indexes();
+    assert_eq!(store.verify_indexes(), Ok(()));
+    assert_eq!(store.scan_by_label("Tag").unwrap().len(), 2);
+
+    let duplicate = IndexInconsistency::Duplicate { entry: IndexEntry::Label { label: "Tag".into(), node: a }, count: 2 };
+    assert_eq!(duplicate.to_string(), format!("label_index[Tag] -> node {} listed 2 times", a));
+}
+
+// =============================================================================
+// scan_all_edges / scan_edges_by_type
+// =============================================================================
+
+fn typed_edge_ids(store: &InMemoryGraphStore, edge_type: &str) -> Vec<u64> {
+    let mut ids: Vec<u64> = store.scan_edges_by_type(edge_type).unwrap().into_iter().map(|e| e.id).collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn scan_edges_by_type_follows_retypes_and_deletes() {
+    let mut store = soft_store();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let k1 = store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    let k2 = store.add_edge(b, a, "KNOWS".into(), HashMap::new()).unwrap();
+    let l1 = store.add_edges_bulk(vec![(a, b, "LIKES".into(), HashMap::new())]).unwrap()[0];
+
+    assert_eq!(store.scan_all_edges().unwrap().len(), 3);
+    assert_eq!(typed_edge_ids(&store, "KNOWS"), vec![k1, k2]);
+
+    store.set_edge_type(k1, "LIKES".into()).unwrap();
+    assert_eq!(typed_edge_ids(&store, "KNOWS"), vec![k2]);
+    assert_eq!(typed_edge_ids(&store, "LIKES"), vec![k1, l1]);
+
+    store.rename_edge_type("LIKES", "FOLLOWS").unwrap();
+    assert!(typed_edge_ids(&store, "LIKES").is_empty());
+    assert_eq!(typed_edge_ids(&store, "FOLLOWS"), vec![k1, l1]);
+
+    store.delete_edge(l1).unwrap();
+    assert_eq!(typed_edge_ids(&store, "FOLLOWS"), vec![k1]);
+    assert_eq!(store.scan_all_edges().unwrap().len(), 2);
+    store.undelete_edge(l1).unwrap();
+    assert_eq!(typed_edge_ids(&store, "FOLLOWS"), vec![k1, l1]);
+
+    store.delete_node(b, true).unwrap();
+    assert!(store.scan_all_edges().unwrap().is_empty());
+    assert!(typed_edge_ids(&store, "KNOWS").is_empty());
+    assert_eq!(store.verify_indexes(), Ok(()));
+}
+
+#[test]
+fn edge_type_index_survives_replay() {
+    let mut store = InMemoryGraphStore::new();
+    store.enable_wal_capture();
+    let a = node(&mut store, "N");
+    let e = store.add_edge(a, a, "LOOP".into(), HashMap::new()).unwrap();
+    store.set_edge_type(e, "SELF".into()).unwrap();
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&store.take_wal_records()).unwrap();
+    assert!(typed_edge_ids(&replayed, "LOOP").is_empty());
+    assert_eq!(typed_edge_ids(&replayed, "SELF"), vec![e]);
+    assert_eq!(replayed.verify_indexes(), Ok(()));
+}
+
+// =============================================================================
+// get_edge / get_edges_between
+// =============================================================================
+
+#[test]
+fn get_edge_hides_deleted_edges() {
+    let mut store = soft_store();
+    let a = node(&mut store, "N");
+    let mut props = HashMap::new();
+    props.insert("w".to_string(), Value::Int(3));
+    let e = store.add_edge(a, a, "SELF".into(), props).unwrap();
+
+    let edge = store.get_edge(e).unwrap().unwrap();
+    assert_eq!((edge.from_node, edge.to_node, edge.edge_type.as_str()), (a, a, "SELF"));
+    assert_eq!(edge.properties.get("w"), Some(&Value::Int(3)));
+
+    store.delete_edge(e).unwrap();
+    assert!(store.get_edge(e).unwrap().is_none());
+    assert!(store.get_edge(999).unwrap().is_none());
+}
+
+#[test]
+fn get_edges_between_returns_all_parallel_edges() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let hub = node(&mut store, "N");
+    let p1 = store.add_edge(a, b, "PAID".into(), HashMap::new()).unwrap();
+    let p2 = store.add_edge(a, b, "PAID".into(), HashMap::new()).unwrap();
+    let k = store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    store.add_edge(b, a, "PAID".into(), HashMap::new()).unwrap();
+    // Make a's outgoing list the longer one so the incoming side is walked
+    for _ in 0..5 {
+        store.add_edge(a, hub, "PAID".into(), HashMap::new()).unwrap();
+    }
+
+    let ids = |edges: Vec<casys_core::Edge>| {
+        let mut ids: Vec<u64> = edges.into_iter().map(|e| e.id).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(store.get_edges_between(a, b, None).unwrap()), vec![p1, p2, k]);
+    assert_eq!(ids(store.get_edges_between(a, b, Some("PAID")).unwrap()), vec![p1, p2]);
+    assert!(store.get_edges_between(b, hub, None).unwrap().is_empty());
+    assert!(store.get_edges_between(a, 404, None).unwrap().is_empty());
+}
+
+// =============================================================================
+// scan_by_property
+// =============================================================================
+
+#[test]
+fn scan_by_property_narrows_by_label_and_compares_values() {
+    let mut store = InMemoryGraphStore::new();
+    let status = |store: &mut InMemoryGraphStore, labels: &[&str], value: Value| {
+        let labels = labels.iter().map(|l| l.to_string()).collect();
+        store.add_node(labels, HashMap::from([("status".to_string(), value)])).unwrap()
+    };
+    let u1 = status(&mut store, &["User"], Value::String("active".into()));
+    let u2 = status(&mut store, &["User", "Admin"], Value::String("active".into()));
+    let g = status(&mut store, &["Group"], Value::String("active".into()));
+    status(&mut store, &["User"], Value::String("banned".into()));
+    status(&mut store, &["User"], Value::Int(1));
+    node(&mut store, "User");
+
+    let ids = |nodes: Vec<casys_core::Node>| {
+        let mut ids: Vec<u64> = nodes.into_iter().map(|n| n.id).collect();
+        ids.sort();
+        ids
+    };
+    let active = Value::String("active".into());
+    assert_eq!(ids(store.scan_by_property(Some("User"), "status", &active).unwrap()), vec![u1, u2]);
+    assert_eq!(ids(store.scan_by_property(Some("Admin"), "status", &active).unwrap()), vec![u2]);
+    assert_eq!(ids(store.scan_by_property(None, "status", &active).unwrap()), vec![u1, u2, g]);
+
+    // Missing keys and type-mismatched values never match
+    assert!(store.scan_by_property(None, "missing", &active).unwrap().is_empty());
+    assert!(store.scan_by_property(Some("User"), "status", &Value::String("1".into())).unwrap().is_empty());
+    assert!(store.scan_by_property(Some("User"), "status", &Value::Float(1.0)).unwrap().is_empty());
+    assert_eq!(store.scan_by_property(Some("User"), "status", &Value::Int(1)).unwrap().len(), 1);
+    assert!(store.scan_by_property(Some("Nobody"), "status", &active).unwrap().is_empty());
+}
+
+// =============================================================================
+// Counts
+// =============================================================================
+
+#[test]
+fn counts_track_live_records() {
+    let mut store = soft_store();
+    let a = node(&mut store, "User");
+    let b = node(&mut store, "User");
+    let c = node(&mut store, "Group");
+    store.add_node(vec![], HashMap::new()).unwrap();
+    store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    store.add_edge(a, c, "MEMBER_OF".into(), HashMap::new()).unwrap();
+    let e = store.add_edge(b, c, "MEMBER_OF".into(), HashMap::new()).unwrap();
+
+    assert_eq!(store.node_count().unwrap(), 4);
+    assert_eq!(store.edge_count().unwrap(), 3);
+    assert_eq!(store.count_by_label("User").unwrap(), 2);
+    assert_eq!(store.count_by_label("Nobody").unwrap(), 0);
+    assert_eq!(store.count_edges_by_type("MEMBER_OF").unwrap(), 2);
+    assert_eq!(store.count_edges_by_type("NOPE").unwrap(), 0);
+
+    // Tombstones are not counted, and come back on undelete
+    store.delete_edge(e).unwrap();
+    store.delete_node(a, true).unwrap();
+    assert_eq!(store.node_count().unwrap(), 3);
+    assert_eq!(store.edge_count().unwrap(), 0);
+    assert_eq!(store.count_by_label("User").unwrap(), 1);
+    store.undelete_node(a).unwrap();
+    store.undelete_edge(e).unwrap();
+    assert_eq!(store.node_count().unwrap(), 4);
+    assert_eq!(store.edge_count().unwrap(), 1);
+
+    store.purge_tombstones();
+    assert_eq!(store.node_count().unwrap(), 4);
+    assert_eq!(store.edge_count().unwrap(), 1);
+    store.truncate(false);
+    assert_eq!(store.node_count().unwrap(), 0);
+    assert_eq!(store.edge_count().unwrap(), 0);
+}
+
+#[test]
+fn counts_on_a_large_store_do_not_scan() {
+    let mut store = InMemoryGraphStore::new();
+    let props = HashMap::from([("payload".to_string(), Value::String("x".repeat(64)))]);
+    let mut prev = store.add_node(vec!["N".into()], props.clone()).unwrap();
+    for _ in 1..50_000 {
+        let id = store.add_node(vec!["N".into()], props.clone()).unwrap();
+        store.add_edge(prev, id, "NEXT".into(), HashMap::new()).unwrap();
+        prev = id;
+    }
+
+    // 4 × 10_000 calls would clone billions of records if any of them scanned
+    let start = std::time::Instant::now();
+    for _ in 0..10_000 {
+        assert_eq!(store.node_count().unwrap(), 50_000);
+        assert_eq!(store.edge_count().unwrap(), 49_999);
+        assert_eq!(store.count_by_label("N").unwrap(), 50_000);
+        assert_eq!(store.count_edges_by_type("NEXT").unwrap(), 49_999);
+    }
+    assert!(start.elapsed() < std::time::Duration::from_secs(2), "counts took {:?}", start.elapsed());
+}
+
+// =============================================================================
+// degree
+// =============================================================================
+
+#[test]
+fn degree_counts_adjacency_by_direction_and_type() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    store.add_edge(a, c, "LIKES".into(), HashMap::new()).unwrap();
+    store.add_edge(c, a, "KNOWS".into(), HashMap::new()).unwrap();
+    store.add_edge(a, a, "KNOWS".into(), HashMap::new()).unwrap();
+
+    assert_eq!(store.degree(a, Direction::Outgoing, None).unwrap(), 3);
+    assert_eq!(store.degree(a, Direction::Incoming, None).unwrap(), 2);
+    // The self-loop is one incident edge
+    assert_eq!(store.degree(a, Direction::Both, None).unwrap(), 4);
+    assert_eq!(store.degree(a, Direction::Outgoing, Some("KNOWS")).unwrap(), 2);
+    assert_eq!(store.degree(a, Direction::Both, Some("KNOWS")).unwrap(), 3);
+    assert_eq!(store.degree(a, Direction::Both, Some("NOPE")).unwrap(), 0);
+    assert_eq!(store.degree(b, Direction::Outgoing, None).unwrap(), 0);
+    assert_eq!(store.degree(b, Direction::Both, None).unwrap(), 1);
+
+    assert!(matches!(store.degree(999, Direction::Both, None), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// scan_by_labels
+// =============================================================================
+
+#[test]
+fn scan_by_labels_intersects_or_unions_label_sets() {
+    use casys_core::LabelMatch;
+    let mut store = InMemoryGraphStore::new();
+    let mut labeled = |labels: &[&str]| {
+        store.add_node(labels.iter().map(|l| l.to_string()).collect(), HashMap::new()).unwrap()
+    };
+    let user = labeled(&["User"]);
+    let admin = labeled(&["User", "Admin"]);
+    let bot = labeled(&["Bot", "Admin"]);
+    labeled(&["Group"]);
+
+    let ids = |nodes: Vec<casys_core::Node>| {
+        let mut ids: Vec<u64> = nodes.into_iter().map(|n| n.id).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(store.scan_by_labels(&["User", "Admin"], LabelMatch::All).unwrap()), vec![admin]);
+    // A node matching several labels is listed once
+    assert_eq!(ids(store.scan_by_labels(&["User", "Admin"], LabelMatch::Any).unwrap()), vec![user, admin, bot]);
+    assert!(store.scan_by_labels(&["User", "Nobody"], LabelMatch::All).unwrap().is_empty());
+    assert_eq!(ids(store.scan_by_labels(&["Bot", "Nobody"], LabelMatch::Any).unwrap()), vec![bot]);
+    assert_eq!(store.scan_by_labels(&[], LabelMatch::All).unwrap().len(), 4);
+    assert!(store.scan_by_labels(&[], LabelMatch::Any).unwrap().is_empty());
+}
+
+// =============================================================================
+// scan_page
+// =============================================================================
+
+#[test]
+fn scan_page_walks_ids_in_order_with_resumable_cursor() {
+    use casys_core::ScanCursor;
+    let mut store = InMemoryGraphStore::new();
+    let mut ids: Vec<u64> = (0..25).map(|i| node(&mut store, if i % 2 == 0 { "Even" } else { "Odd" })).collect();
+
+    let mut seen = Vec::new();
+    let mut cursor = None;
+    let mut pages = 0;
+    loop {
+        let (page, next) = store.scan_page(None, cursor, 10).unwrap();
+        assert!(page.len() <= 10);
+        seen.extend(page.iter().map(|n| n.id));
+        pages += 1;
+        // Writes between pages only ever land after the cursor
+        if pages == 1 {
+            ids.push(node(&mut store, "Even"));
+        }
+        match next {
+            // The token survives a round trip through a client
+            Some(c) => cursor = Some(c.to_string().parse::<ScanCursor>().unwrap()),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ids);
+    assert_eq!(pages, 3);
+
+    let (evens, next) = store.scan_page(Some("Even"), None, 100).unwrap();
+    assert_eq!(evens.len(), 14);
+    assert!(evens.windows(2).all(|w| w[0].id < w[1].id));
+    assert!(next.is_none());
+    let (rest, _) = store.scan_page(Some("Odd"), Some(ScanCursor::after(ids[20])), 100).unwrap();
+    assert_eq!(rest.iter().map(|n| n.id).collect::<Vec<_>>(), vec![ids[21], ids[23]]);
+
+    assert!(store.scan_page(Some("Nobody"), None, 10).unwrap().0.is_empty());
+    assert!(matches!(store.scan_page(None, None, 0), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!("garbage".parse::<ScanCursor>(), Err(EngineError::InvalidArgument(_))));
+}
+
+// =============================================================================
+// get_nodes / get_edges
+// =============================================================================
+
+#[test]
+fn bulk_lookups_preserve_order_and_map_missing_to_none() {
+    let mut store = soft_store();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    let e1 = store.add_edge(a, b, "R".into(), HashMap::new()).unwrap();
+    let e2 = store.add_edge(b, c, "R".into(), HashMap::new()).unwrap();
+    store.delete_node(c, true).unwrap();
+
+    let nodes = store.get_nodes(&[b, 999, a, c, b]).unwrap();
+    let ids: Vec<Option<u64>> = nodes.iter().map(|n| n.as_ref().map(|n| n.id)).collect();
+    assert_eq!(ids, vec![Some(b), None, Some(a), None, Some(b)]);
+
+    let edges = store.get_edges(&[e2, e1, 999]).unwrap();
+    let ids: Vec<Option<u64>> = edges.iter().map(|e| e.as_ref().map(|e| e.id)).collect();
+    assert_eq!(ids, vec![None, Some(e1), None]);
+
+    assert!(store.get_nodes(&[]).unwrap().is_empty());
+}
+
+// =============================================================================
+// get_neighbors_undirected
+// =============================================================================
+
+#[test]
+fn undirected_neighbors_list_each_incident_edge_once() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    let out = store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    let inc = store.add_edge(c, a, "KNOWS".into(), HashMap::new()).unwrap();
+    let other = store.add_edge(a, c, "LIKES".into(), HashMap::new()).unwrap();
+    let lp = store.add_edge(a, a, "KNOWS".into(), HashMap::new()).unwrap();
+
+    let mut found: Vec<(u64, u64, Direction)> = store.get_neighbors_undirected(a, None).unwrap()
+        .into_iter()
+        .map(|(e, n, d)| (e.id, n.id, d))
+        .collect();
+    found.sort_by_key(|(e, _, _)| *e);
+    assert_eq!(found, vec![
+        (out, b, Direction::Outgoing),
+        (inc, c, Direction::Incoming),
+        (other, c, Direction::Outgoing),
+        (lp, a, Direction::Outgoing),
+    ]);
+
+    let knows = store.get_neighbors_undirected(a, Some("KNOWS")).unwrap();
+    assert_eq!(knows.len(), 3);
+    let from_b = store.get_neighbors_undirected(b, None).unwrap();
+    assert_eq!(from_b.len(), 1);
+    assert_eq!((from_b[0].1.id, from_b[0].2), (a, Direction::Incoming));
+}
+
+// =============================================================================
+// neighborhood
+// =============================================================================
+
+#[test]
+fn neighborhood_reports_minimum_hop_distance_within_depth() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let n: Vec<u64> = (0..6).map(|_| node(&mut store, "N")).collect();
+    // 0 -> 1 -> 2 -> 3 with a shortcut 0 -> 2, a cycle 3 -> 0 and an edge of another type
+    for (from, to) in [(0, 1), (1, 2), (2, 3), (0, 2), (3, 0)] {
+        store.add_edge(n[from], n[to], "PARENT_OF".into(), HashMap::new()).unwrap();
+    }
+    store.add_edge(n[0], n[4], "LIKES".into(), HashMap::new()).unwrap();
+    store.add_edge(n[5], n[0], "PARENT_OF".into(), HashMap::new()).unwrap();
+
+    let hood = store.neighborhood(n[0], 0, None, Direction::Outgoing).unwrap();
+    assert_eq!(hood, HashMap::from([(n[0], 0)]));
+
+    let hood = store.neighborhood(n[0], 3, Some("PARENT_OF"), Direction::Outgoing).unwrap();
+    assert_eq!(hood, HashMap::from([(n[0], 0), (n[1], 1), (n[2], 1), (n[3], 2)]));
+
+    let hood = store.neighborhood(n[0], 1, None, Direction::Outgoing).unwrap();
+    assert_eq!(hood.len(), 4);
+    assert_eq!(hood[&n[4]], 1);
+
+    let hood = store.neighborhood(n[0], 1, Some("PARENT_OF"), Direction::Incoming).unwrap();
+    assert_eq!(hood, HashMap::from([(n[0], 0), (n[3], 1), (n[5], 1)]));
+    let hood = store.neighborhood(n[1], 2, Some("PARENT_OF"), Direction::Both).unwrap();
+    assert_eq!(hood, HashMap::from([(n[1], 0), (n[0], 1), (n[2], 1), (n[3], 2), (n[5], 2)]));
+
+    assert!(matches!(store.neighborhood(999, 2, None, Direction::Both), Err(EngineError::NotFound(_))));
+}
+
+#[test]
+fn neighborhood_tree_records_reaching_edges() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    let ab = store.add_edge(a, b, "R".into(), HashMap::new()).unwrap();
+    let bc = store.add_edge(b, c, "R".into(), HashMap::new()).unwrap();
+
+    let tree = store.neighborhood_tree(a, 5, None, Direction::Outgoing).unwrap();
+    assert_eq!(tree, HashMap::from([(a, (0, None)), (b, (1, Some(ab))), (c, (2, Some(bc)))]));
+}
+
+// =============================================================================
+// node_exists / has_edge
+// =============================================================================
+
+#[test]
+fn existence_checks_respect_direction_type_and_tombstones() {
+    let mut store = soft_store();
+    let hub = node(&mut store, "N");
+    let spokes: Vec<u64> = (0..50).map(|_| node(&mut store, "N")).collect();
+    for s in &spokes {
+        store.add_edge(hub, *s, "OWNS".into(), HashMap::new()).unwrap();
+    }
+    let back = store.add_edge(spokes[7], hub, "KNOWS".into(), HashMap::new()).unwrap();
+
+    assert!(store.node_exists(hub).unwrap());
+    assert!(!store.node_exists(999).unwrap());
+    assert!(store.has_edge(hub, spokes[10], None).unwrap());
+    assert!(store.has_edge(hub, spokes[10], Some("OWNS")).unwrap());
+    assert!(!store.has_edge(hub, spokes[10], Some("KNOWS")).unwrap());
+    assert!(!store.has_edge(spokes[10], hub, None).unwrap());
+    assert!(store.has_edge(spokes[7], hub, Some("KNOWS")).unwrap());
+    assert!(!store.has_edge(hub, 999, None).unwrap());
+
+    store.delete_edge(back).unwrap();
+    assert!(!store.has_edge(spokes[7], hub, None).unwrap());
+    store.delete_node(spokes[10], true).unwrap();
+    assert!(!store.node_exists(spokes[10]).unwrap());
+    assert!(!store.has_edge(hub, spokes[10], None).unwrap());
+}
+
+// =============================================================================
+// labels / edge_types
+// =============================================================================
+
+#[test]
+fn labels_and_edge_types_are_counted_most_common_first() {
+    let mut store = InMemoryGraphStore::new();
+    let a = store.add_node(vec!["User".into(), "Admin".into()], HashMap::new()).unwrap();
+    let b = node(&mut store, "User");
+    let c = node(&mut store, "Group");
+    node(&mut store, "Bot");
+    store.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    store.add_edge(a, c, "MEMBER_OF".into(), HashMap::new()).unwrap();
+    let e = store.add_edge(b, c, "MEMBER_OF".into(), HashMap::new()).unwrap();
+
+    let owned = |pairs: &[(&str, usize)]| pairs.iter().map(|(n, c)| (n.to_string(), *c)).collect::<Vec<_>>();
+    assert_eq!(store.labels().unwrap(), owned(&[("User", 2), ("Admin", 1), ("Bot", 1), ("Group", 1)]));
+    assert_eq!(store.edge_types().unwrap(), owned(&[("MEMBER_OF", 2), ("KNOWS", 1)]));
+
+    // Emptied buckets disappear
+    store.delete_edge(e).unwrap();
+    store.remove_label(a, "Admin").unwrap();
+    assert_eq!(store.labels().unwrap(), owned(&[("User", 2), ("Bot", 1), ("Group", 1)]));
+    assert_eq!(store.edge_types().unwrap(), owned(&[("KNOWS", 1), ("MEMBER_OF", 1)]));
+}
+
+// =============================================================================
+// Property keys
+// =============================================================================
+
+#[test]
+fn property_keys_and_stats_per_label() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node_with(&mut store, &[("name", Value::String("a".into())), ("age", Value::Int(3))]);
+    node_with(&mut store, &[("name", Value::String("b".into()))]);
+    let bare = node(&mut store, "Other");
+    store.add_node(vec!["Other".into()], HashMap::from([("name".to_string(), Value::Null)])).unwrap();
+
+    assert_eq!(store.node_property_keys(a).unwrap(), Some(vec!["age".to_string(), "name".to_string()]));
+    assert_eq!(store.node_property_keys(bare).unwrap(), Some(vec![]));
+    assert_eq!(store.node_property_keys(999).unwrap(), None);
+
+    let all = store.property_key_stats(None).unwrap();
+    assert_eq!(all, HashMap::from([("name".to_string(), 3), ("age".to_string(), 1)]));
+    let other = store.property_key_stats(Some("Other")).unwrap();
+    assert_eq!(other, HashMap::from([("name".to_string(), 1)]));
+    assert!(store.property_key_stats(Some("Nobody")).unwrap().is_empty());
+}
+
+// =============================================================================
+// scan_by_property_range
+// =============================================================================
+
+#[test]
+fn range_scan_compares_ints_and_floats_by_value() {
+    let mut store = InMemoryGraphStore::new();
+    let reading = |store: &mut InMemoryGraphStore, v: Value| node_with(store, &[("reading", v)]);
+    let r10 = reading(&mut store, Value::Int(10));
+    let r15 = reading(&mut store, Value::Float(15.5));
+    let r20 = reading(&mut store, Value::Float(20.0));
+    let r25 = reading(&mut store, Value::Int(25));
+    reading(&mut store, Value::String("12".into()));
+    reading(&mut store, Value::Float(f64::NAN));
+    node(&mut store, "N");
+
+    let ids = |nodes: Vec<casys_core::Node>| {
+        let mut ids: Vec<u64> = nodes.into_iter().map(|n| n.id).collect();
+        ids.sort();
+        ids
+    };
+    let scan = |min: Option<Value>, max: Option<Value>, inclusive: bool| {
+        ids(store.scan_by_property_range(Some("N"), "reading", min, max, inclusive).unwrap())
+    };
+    assert_eq!(scan(Some(Value::Float(10.0)), Some(Value::Int(20)), true), vec![r10, r15, r20]);
+    assert_eq!(scan(Some(Value::Float(10.0)), Some(Value::Int(20)), false), vec![r15]);
+    assert_eq!(scan(Some(Value::Int(16)), None, true), vec![r20, r25]);
+    assert_eq!(scan(None, Some(Value::Float(15.5)), true), vec![r10, r15]);
+    // Unbounded still skips missing and non-numeric values
+    assert_eq!(scan(None, None, true), vec![r10, r15, r20, r25]);
+    assert!(scan(Some(Value::Int(30)), None, true).is_empty());
+
+    assert!(store.scan_by_property_range(Some("Other"), "reading", None, None, true).unwrap().is_empty());
+    assert!(matches!(
+        store.scan_by_property_range(None, "reading", Some(Value::String("a".into())), None, true),
+        Err(EngineError::InvalidArgument(_))
+    ));
+}
+
+// =============================================================================
+// scan_filter
+// =============================================================================
+
+#[test]
+fn scan_filter_sees_references_and_stops_at_limit() {
+    let mut store = InMemoryGraphStore::new();
+    for i in 0..20 {
+        node_with(&mut store, &[("n", Value::Int(i))]);
+    }
+    node(&mut store, "Other");
+
+    let even = |n: &casys_core::Node| matches!(n.properties.get("n"), Some(Value::Int(i)) if i % 2 == 0);
+    assert_eq!(store.scan_filter(None, &even).unwrap().len(), 10);
+    assert_eq!(store.scan_filter(Some("N"), &|n| n.properties.is_empty()).unwrap().len(), 0);
+    assert_eq!(store.scan_filter(None, &|n| n.properties.is_empty()).unwrap().len(), 1);
+
+    let calls = std::cell::Cell::new(0);
+    let counted = |n: &casys_core::Node| {
+        calls.set(calls.get() + 1);
+        even(n)
+    };
+    let first = store.scan_filter_limit(Some("N"), &counted, 3).unwrap();
+    assert_eq!(first.len(), 3);
+    assert!(first.iter().all(even));
+    // The scan stopped once the third match was found
+    assert!(calls.get() < 20);
+}
+
+// =============================================================================
+// Sampling
+// =============================================================================
+
+#[test]
+fn sampling_is_reproducible_with_a_seed_and_caps_at_population() {
+    let build = || {
+        let mut store = InMemoryGraphStore::new();
+        let ids: Vec<u64> = (0..200).map(|i| node(&mut store, if i < 50 { "A" } else { "B" })).collect();
+        for w in ids.windows(2) {
+            store.add_edge(w[0], w[1], "NEXT".into(), HashMap::new()).unwrap();
+        }
+        store
+    };
+    let (s1, s2) = (build(), build());
+    let ids = |nodes: Vec<casys_core::Node>| nodes.into_iter().map(|n| n.id).collect::<Vec<_>>();
+
+    let first = ids(s1.sample_nodes(10, None, Some(42)).unwrap());
+    assert_eq!(first.len(), 10);
+    assert!(first.windows(2).all(|w| w[0] < w[1]));
+    // Same seed, same data, different store instance
+    assert_eq!(first, ids(s2.sample_nodes(10, None, Some(42)).unwrap()));
+    assert_ne!(first, ids(s1.sample_nodes(10, None, Some(7)).unwrap()));
+
+    let a = s1.sample_nodes(5, Some("A"), Some(1)).unwrap();
+    assert!(a.iter().all(|n| n.labels == vec!["A".to_string()]));
+    assert_eq!(s1.sample_nodes(500, Some("A"), None).unwrap().len(), 50);
+    assert!(s1.sample_nodes(0, None, None).unwrap().is_empty());
+    assert!(s1.sample_nodes(3, Some("Nobody"), None).unwrap().is_empty());
+
+    let edges = s1.sample_edges(20, Some("NEXT"), Some(3)).unwrap();
+    assert_eq!(edges.len(), 20);
+    let again = s2.sample_edges(20, Some("NEXT"), Some(3)).unwrap();
+    assert_eq!(edges.iter().map(|e| e.id).collect::<Vec<_>>(), again.iter().map(|e| e.id).collect::<Vec<_>>());
+    assert_eq!(s1.sample_edges(1_000, None, None).unwrap().len(), 199);
+}
+
+#[test]
+fn reservoir_sampling_is_roughly_uniform() {
+    let mut hits = [0usize; 10];
+    for seed in 0..2_000 {
+        for i in casys_core::sample_reservoir(0..10, 3, Some(seed)) {
+            hits[i] += 1;
+        }
+    }
+    // Each item is expected 600 times
+    assert!(hits.iter().all(|h| (450..750).contains(h)), "{:?}", hits);
+}
+
+// =============================================================================
+// get_neighbor_ids
+// =============================================================================
+
+#[test]
+fn neighbor_ids_follow_direction_and_type() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    let ab = store.add_edge(a, b, "R".into(), HashMap::new()).unwrap();
+    let ca = store.add_edge(c, a, "R".into(), HashMap::new()).unwrap();
+    let ac = store.add_edge(a, c, "S".into(), HashMap::new()).unwrap();
+    let aa = store.add_edge(a, a, "R".into(), HashMap::new()).unwrap();
+
+    let sorted = |mut v: Vec<u64>| {
+        v.sort();
+        v
+    };
+    assert_eq!(sorted(store.get_neighbor_ids(a, None, Direction::Outgoing).unwrap()), vec![a, b, c]);
+    assert_eq!(sorted(store.get_neighbor_ids(a, None, Direction::Incoming).unwrap()), vec![a, c]);
+    assert_eq!(sorted(store.get_neighbor_ids(a, Some("R"), Direction::Both).unwrap()), vec![a, b, c]);
+
+    let mut pairs = store.get_neighbor_edge_ids(a, None, Direction::Both).unwrap();
+    pairs.sort();
+    assert_eq!(pairs, vec![(ab, b), (ca, c), (ac, c), (aa, a)]);
+    assert!(store.get_neighbor_ids(999, None, Direction::Both).unwrap().is_empty());
+}
+
+// =============================================================================
+// top_by_property
+// =============================================================================
+
+#[test]
+fn top_by_property_ranks_numeric_values_with_id_tiebreak() {
+    let mut store = InMemoryGraphStore::new();
+    let price = |store: &mut InMemoryGraphStore, v: Value| node_with(store, &[("price", v)]);
+    let p5 = price(&mut store, Value::Int(5));
+    let p9 = price(&mut store, Value::Float(9.5));
+    let p7a = price(&mut store, Value::Int(7));
+    let p7b = price(&mut store, Value::Float(7.0));
+    let p1 = price(&mut store, Value::Float(1.25));
+    price(&mut store, Value::String("100".into()));
+    price(&mut store, Value::Float(f64::NAN));
+    node(&mut store, "N");
+
+    let ids = |nodes: Vec<casys_core::Node>| nodes.into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert_eq!(ids(store.top_by_property(Some("N"), "price", 3, false).unwrap()), vec![p9, p7a, p7b]);
+    assert_eq!(ids(store.top_by_property(None, "price", 2, true).unwrap()), vec![p1, p5]);
+    assert_eq!(ids(store.top_by_property(None, "price", 100, true).unwrap()), vec![p1, p5, p7a, p7b, p9]);
+    assert!(store.top_by_property(None, "price", 0, false).unwrap().is_empty());
+    assert!(store.top_by_property(None, "missing", 5, false).unwrap().is_empty());
+}
+
+// =============================================================================
+// Orphans, sources and sinks
+// =============================================================================
+
+#[test]
+fn orphans_sources_and_sinks_honour_edge_type() {
+    let mut store = soft_store();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    let c = node(&mut store, "N");
+    let lone = node(&mut store, "N");
+    let gone = node(&mut store, "N");
+    // a -R-> b -R-> c, c -S-> a, and an edge to a node that gets deleted
+    store.add_edge(a, b, "R".into(), HashMap::new()).unwrap();
+    store.add_edge(b, c, "R".into(), HashMap::new()).unwrap();
+    store.add_edge(c, a, "S".into(), HashMap::new()).unwrap();
+    let looped = store.add_edge(lone, gone, "R".into(), HashMap::new()).unwrap();
+    store.delete_node(gone, true).unwrap();
+    assert!(store.get_edge(looped).unwrap().is_none());
+
+    assert_eq!(store.find_orphans().unwrap(), vec![lone]);
+    assert_eq!(store.find_sources(None).unwrap(), vec![lone]);
+    assert_eq!(store.find_sinks(None).unwrap(), vec![lone]);
+    // a only has an incoming S edge, c only an outgoing S edge
+    assert_eq!(store.find_sources(Some("R")).unwrap(), vec![a, lone]);
+    assert_eq!(store.find_sinks(Some("R")).unwrap(), vec![c, lone]);
+    assert_eq!(store.find_sources(Some("S")).unwrap(), vec![b, c, lone]);
+}
+
+// =============================================================================
+// labels_of / index_references
+// =============================================================================
+
+#[test]
+fn index_references_read_the_indexes_not_the_record() {
+    use casys_engine::index::IndexRefs;
+    let mut store = soft_store();
+    let a = store.add_node(vec!["User".into(), "Admin".into()], HashMap::new()).unwrap();
+    let b = node(&mut store, "User");
+    let ab = store.add_edge(a, b, "R".into(), HashMap::new()).unwrap();
+    let ba = store.add_edge(b, a, "R".into(), HashMap::new()).unwrap();
+
+    assert_eq!(store.labels_of(a).unwrap(), vec!["User".to_string(), "Admin".to_string()]);
+    assert!(matches!(store.labels_of(999), Err(EngineError::NotFound(_))));
+    assert_eq!(store.index_references(a), IndexRefs {
+        label_buckets: vec!["Admin".into(), "User".into()],
+        outgoing: vec![ab],
+        incoming: vec![ba],
+    });
+
+    // A tombstoned node is gone from every index
+    store.delete_node(a, true).unwrap();
+    assert!(store.index_references(a).is_empty());
+    assert!(matches!(store.labels_of(a), Err(EngineError::NotFound(_))));
+    assert!(store.index_references(999).is_empty());
+}
+
+// =============================================================================
+// shortest_path
+// =============================================================================
+
+#[test]
+fn shortest_path_finds_fewest_hops_and_respects_bounds() {
+    use casys_core::{Direction, GraphPath};
+    let mut store = InMemoryGraphStore::new();
+    let n: Vec<u64> = (0..6).map(|_| node(&mut store, "N")).collect();
+    let e01 = store.add_edge(n[0], n[1], "R".into(), HashMap::new()).unwrap();
+    store.add_edge(n[1], n[2], "R".into(), HashMap::new()).unwrap();
+    store.add_edge(n[2], n[3], "R".into(), HashMap::new()).unwrap();
+    let e13 = store.add_edge(n[1], n[3], "R".into(), HashMap::new()).unwrap();
+    store.add_edge(n[3], n[0], "R".into(), HashMap::new()).unwrap();
+    let e34 = store.add_edge(n[3], n[4], "S".into(), HashMap::new()).unwrap();
+
+    let path = store.shortest_path(n[0], n[3], None, Direction::Outgoing, None).unwrap().unwrap();
+    assert_eq!(path, GraphPath { nodes: vec![n[0], n[1], n[3]], edges: vec![e01, e13] });
+    assert_eq!(path.len(), 2);
+
+    let path = store.shortest_path(n[0], n[4], None, Direction::Outgoing, None).unwrap().unwrap();
+    assert_eq!(path.edges.last(), Some(&e34));
+    assert_eq!(path.len(), 3);
+    // Type filter, depth bound and direction each cut the route
+    assert!(store.shortest_path(n[0], n[4], Some("R"), Direction::Outgoing, None).unwrap().is_none());
+    assert!(store.shortest_path(n[0], n[4], None, Direction::Outgoing, Some(2)).unwrap().is_none());
+    assert!(store.shortest_path(n[0], n[4], None, Direction::Outgoing, Some(3)).unwrap().is_some());
+    assert!(store.shortest_path(n[4], n[0], None, Direction::Outgoing, None).unwrap().is_none());
+    assert_eq!(store.shortest_path(n[4], n[0], None, Direction::Incoming, None).unwrap().unwrap().len(), 3);
+    assert_eq!(store.shortest_path(n[4], n[0], None, Direction::Both, None).unwrap().unwrap().len(), 2);
+
+    // Unreachable, self and missing nodes
+    assert!(store.shortest_path(n[0], n[5], None, Direction::Both, None).unwrap().is_none());
+    let own = store.shortest_path(n[2], n[2], None, Direction::Outgoing, Some(0)).unwrap().unwrap();
+    assert_eq!(own, GraphPath { nodes: vec![n[2]], edges: vec![] });
+    assert!(own.is_empty());
+    assert!(matches!(store.shortest_path(n[0], 999, None, Direction::Both, None), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// neighbor_summary
+// =============================================================================
+
+#[test]
+fn neighbor_summary_groups_by_type_and_direction() {
+    use casys_core::Direction;
+    let mut store = InMemoryGraphStore::new();
+    let a = node(&mut store, "N");
+    let b = node(&mut store, "N");
+    for _ in 0..3 {
+        store.add_edge(a, b, "WROTE".into(), HashMap::new()).unwrap();
+    }
+    store.add_edge(b, a, "CITED".into(), HashMap::new()).unwrap();
+    store.add_edge(a, a, "CITED".into(), HashMap::new()).unwrap();
+
+    let summary = store.neighbor_summary(a).unwrap();
+    assert_eq!(summary, HashMap::from([
+        (("WROTE".to_string(), Direction::Outgoing), 3),
+        (("CITED".to_string(), Direction::Incoming), 2),
+        (("CITED".to_string(), Direction::Outgoing), 1),
+    ]));
+    let lone = node(&mut store, "N");
+    assert!(store.neighbor_summary(lone).unwrap().is_empty());
+    assert!(matches!(store.neighbor_summary(999), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// is_reachable
+// =============================================================================
+
+#[test]
+fn is_reachable_meets_in_the_middle_within_depth() {
+    let mut store = InMemoryGraphStore::new();
+    let n: Vec<u64> = (0..8).map(|_| node(&mut store, "N")).collect();
+    // Chain 0 -> 1 -> ... -> 6 with a cycle 3 -> 1, plus 7 -> 0
+    for i in 0..6 {
+        store.add_edge(n[i], n[i + 1], "DEPENDS_ON".into(), HashMap::new()).unwrap();
+    }
+    store.add_edge(n[3], n[1], "DEPENDS_ON".into(), HashMap::new()).unwrap();
+    store.add_edge(n[7], n[0], "OTHER".into(), HashMap::new()).unwrap();
+
+    assert!(store.is_reachable(n[0], n[6], None, None).unwrap());
+    assert!(store.is_reachable(n[0], n[6], Some("DEPENDS_ON"), Some(6)).unwrap());
+    assert!(!store.is_reachable(n[0], n[6], None, Some(5)).unwrap());
+    assert!(store.is_reachable(n[3], n[2], None, None).unwrap());
+    assert!(!store.is_reachable(n[6], n[0], None, None).unwrap());
+    assert!(store.is_reachable(n[7], n[6], None, None).unwrap());
+    assert!(!store.is_reachable(n[7], n[6], Some("DEPENDS_ON"), None).unwrap());
+    assert!(store.is_reachable(n[4], n[4], None, Some(0)).unwrap());
+    assert!(!store.is_reachable(n[0], n[1], None, Some(0)).unwrap());
+    assert!(store.is_reachable(n[0], n[1], None, Some(1)).unwrap());
+    assert!(matches!(store.is_reachable(n[0], 999, None, None), Err(EngineError::NotFound(_))));
+}
+
+// =============================================================================
+// Property presence scans
+// =============================================================================
+
+#[test]
+fn presence_scans_distinguish_missing_from_null() {
+    let mut store = InMemoryGraphStore::new();
+    let set = node_with(&mut store, &[("due_date", Value::String("2026-01-01".into()))]);
+    let null = node_with(&mut store, &[("due_date", Value::Null)]);
+    let missing = node_with(&mut store, &[("amount", Value::Int(3))]);
+    store.add_node(vec!["Other".into()], HashMap::new()).unwrap();
+
+    assert_eq!(store.scan_has_property("N", "due_date").unwrap(), vec![set, null]);
+    assert_eq!(store.scan_missing_property("N", "due_date").unwrap(), vec![missing]);
+    assert_eq!(store.scan_null_property("N", "due_date").unwrap(), vec![null]);
+    assert!(store.scan_missing_property("Nobody", "due_date").unwrap().is_empty());
+
+    store.remove_node_property(null, "due_date").unwrap();
+    assert_eq!(store.scan_missing_property("N", "due_date").unwrap(), vec![null, missing]);
+}
+
+// =============================================================================
+// deterministic_iteration
+// =============================================================================
+
+fn is_sorted(ids: &[u64]) -> bool {
+    ids.windows(2).all(|w| w[0] < w[1])
+}
+
+#[test]
+fn deterministic_iteration_sorts_every_read_by_id() {
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        deterministic_iteration: true,
+        ..Default::default()
+    });
+    // Caller-chosen ids inserted out of order, so index buckets are out of order too
+    let ids: Vec<u64> = (0..200u64).map(|i| (i * 37) % 200 + 1).collect();
+    for id in &ids {
+        store.add_node_with_id(*id, vec!["N".into()], HashMap::from([("k".into(), Value::Int(1))])).unwrap();
+    }
+    let hub = ids[0];
+    for (i, id) in ids.iter().enumerate().skip(1).rev() {
+        store.add_edge_with_id(1000 - i as u64, hub, *id, "E".into(), HashMap::new()).unwrap();
+        store.add_edge_with_id(2000 - i as u64, *id, hub, "E".into(), HashMap::new()).unwrap();
+    }
+
+    let node_ids = |nodes: Vec<casys_core::Node>| nodes.into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert!(is_sorted(&node_ids(store.scan_all().unwrap())));
+    assert!(is_sorted(&node_ids(store.scan_by_label("N").unwrap())));
+    assert!(is_sorted(&node_ids(store.scan_by_property(None, "k", &Value::Int(1)).unwrap())));
+    assert!(is_sorted(&node_ids(store.scan_filter(Some("N"), &|_| true).unwrap())));
+    assert!(is_sorted(&store.iter_nodes().unwrap().map(|n| n.id).collect::<Vec<_>>()));
+    assert_eq!(node_ids(store.scan_filter_limit(None, &|_| true, 3).unwrap()), vec![1, 2, 3]);
+
+    let edge_ids = |edges: Vec<casys_core::Edge>| edges.into_iter().map(|e| e.id).collect::<Vec<_>>();
+    assert!(is_sorted(&edge_ids(store.scan_all_edges().unwrap())));
+    assert!(is_sorted(&edge_ids(store.scan_edges_by_type("E").unwrap())));
+    let out: Vec<u64> = store.get_neighbors(hub, None).unwrap().into_iter().map(|(e, _)| e.id).collect();
+    assert_eq!(out.len(), 199);
+    assert!(is_sorted(&out));
+    let both: Vec<u64> = store.get_neighbor_edge_ids(hub, None, casys_core::Direction::Both).unwrap()
+        .into_iter().map(|(e, _)| e).collect();
+    assert_eq!(both.len(), 398);
+    assert!(is_sorted(&both));
+}
+
+#[test]
+fn deterministic_iteration_is_off_by_default() {
+    assert!(!InMemoryGraphStore::new().options().deterministic_iteration);
+}
+
+// =============================================================================
+// Borrowing visitors
+// =============================================================================
+
+#[test]
+fn with_node_and_label_visitor_borrow_live_nodes() {
+    let mut store = soft_store();
+    let a = node_with(&mut store, &[("text", Value::String("hello".into()))]);
+    let b = node_with(&mut store, &[("text", Value::String("hi".into()))]);
+    node(&mut store, "Other");
+
+    let len = |n: &casys_core::Node| match n.properties.get("text") {
+        Some(Value::String(s)) => s.len(),
+        _ => 0,
+    };
+    assert_eq!(store.with_node(a, len).unwrap(), Some(5));
+    assert_eq!(store.with_node(999, len).unwrap(), None);
+
+    let mut total = 0;
+    store.for_each_node_with_label("N", &mut |n| total += len(n)).unwrap();
+    assert_eq!(total, 7);
+
+    store.delete_node(b, false).unwrap();
+    assert_eq!(store.with_node(b, len).unwrap(), None);
+    let reader: &dyn GraphReadStore = &store;
+    let mut seen = Vec::new();
+    assert!(reader.visit_node(a, &mut |n| seen.push(n.id)).unwrap());
+    assert!(!reader.visit_node(b, &mut |n| seen.push(n.id)).unwrap());
+    reader.for_each_node_with_label("N", &mut |n| seen.push(n.id)).unwrap();
+    assert_eq!(seen, vec![a, a]);
+}
+
+// =============================================================================
+// Property indexes
+// =============================================================================
+
+fn indexed_ids(store: &InMemoryGraphStore, key: &str, value: Value) -> Vec<u64> {
+    let mut ids: Vec<u64> = store.scan_by_property(Some("N"), key, &value).unwrap().into_iter().map(|n| n.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn property_index_backfills_and_answers_scans() {
+    let mut store = InMemoryGraphStore::new();
+    let a = node_with(&mut store, &[("status", Value::String("open".into()))]);
+    let b = node_with(&mut store, &[("status", Value::String("open".into()))]);
+    node_with(&mut store, &[("status", Value::String("done".into()))]);
+    store.add_node(vec!["Other".into()], HashMap::from([("status".into(), Value::String("open".into()))])).unwrap();
+
+    store.create_property_index("N", "status").unwrap();
+    assert!(store.has_property_index("N", "status"));
+    assert!(matches!(store.create_property_index("N", "status"), Err(EngineError::InvalidArgument(_))));
+    assert_eq!(indexed_ids(&store, "status", Value::String("open".into())), vec![a, b]);
+
+    // New nodes, bulk inserts and label changes are filed as they happen
+    let c = node_with(&mut store, &[("status", Value::String("open".into()))]);
+    let bulk = store.add_nodes_bulk(vec![(vec!["N".into()], HashMap::from([("status".into(), Value::String("open".into()))]))]).unwrap();
+    let other = store.add_node(vec!["Other".into()], HashMap::from([("status".into(), Value::String("open".into()))])).unwrap();
+    store.add_label(other, "N".into()).unwrap();
+    store.remove_label(a, "N").unwrap();
+    assert_eq!(indexed_ids(&store, "status", Value::String("open".into())), vec![b, c, bulk[0], other]);
+    assert!(store.verify_indexes().is_ok());
+
+    assert!(store.drop_property_index("N", "status").unwrap());
+    assert!(!store.drop_property_index("N", "status").unwrap());
+    assert_eq!(indexed_ids(&store, "status", Value::String("open".into())), vec![b, c, bulk[0], other]);
+}
+
+#[test]
+fn property_index_moves_updated_values_between_buckets() {
+    let mut store = soft_store();
+    store.create_property_index("N", "status").unwrap();
+    let a = node_with(&mut store, &[("status", Value::String("open".into()))]);
+    let b = node_with(&mut store, &[("status", Value::String("open".into()))]);
+
+    store.set_node_property(a, "status".into(), Value::String("done".into())).unwrap();
+    assert_eq!(indexed_ids(&store, "status", Value::String("open".into())), vec![b]);
+    assert_eq!(indexed_ids(&store, "status", Value::String("done".into())), vec![a]);
+
+    store.remove_node_property(b, "status").unwrap();
+    assert!(indexed_ids(&store, "status", Value::String("open".into())).is_empty());
+    store.increment_property(casys_engine::index::PropertyTarget::Node(b), "status", Value::Int(1)).unwrap();
+    assert_eq!(indexed_ids(&store, "status", Value::Int(1)), vec![b]);
+
+    // Tombstones leave the index and come back with undelete
+    store.delete_node(a, false).unwrap();
+    assert!(indexed_ids(&store, "status", Value::String("done".into())).is_empty());
+    assert!(store.verify_indexes().is_ok());
+    store.undelete_node(a).unwrap();
+    assert_eq!(indexed_ids(&store, "status", Value::String("done".into())), vec![a]);
+
+    // -0.0 and 0.0 are equal, so they share a bucket
+    store.set_node_property(a, "status".into(), Value::Float(-0.0)).unwrap();
+    assert_eq!(indexed_ids(&store, "status", Value::Float(0.0)), vec![a]);
+    assert!(store.verify_indexes().is_ok());
+
+    store.truncate(false);
+    assert!(store.has_property_index("N", "status"));
+    assert!(indexed_ids(&store, "status", Value::Float(0.0)).is_empty());
+}
+
+#[test]
+fn property_index_follows_merges_and_rebuilds() {
+    let mut store = InMemoryGraphStore::new();
+    store.create_property_index("N", "email").unwrap();
+    let keep = node_with(&mut store, &[]);
+    let remove = node_with(&mut store, &[("email", Value::String("x@y".into()))]);
+    store.merge_nodes(keep, remove, false).unwrap();
+    assert_eq!(indexed_ids(&store, "email", Value::String("x@y".into())), vec![keep]);
+    assert_eq!(store.merge_node("N", "email", Value::String("x@y".into()), HashMap::new()).unwrap(), (keep, false));
+
+    store.delete_node(keep, true).unwrap();
+    assert!(indexed_ids(&store, "email", Value::String("x@y".into())).is_empty());
+    store.rebuild_indexes();
+    assert!(store.verify_indexes().is_ok());
+}
+
+#[test]
+fn normalized_property_index_matches_case_and_whitespace_variants() {
+    use casys_engine::index::IndexOptions;
+
+    let mut store = InMemoryGraphStore::new();
+    let upper = node_with(&mut store, &[("city", Value::String("Paris".into()))]);
+    let options = IndexOptions { case_insensitive: true, trim_whitespace: true, ..Default::default() };
+    store.create_property_index_with("N", "city", options).unwrap();
+    assert_eq!(store.property_index_options("N", "city"), Some(options));
+    assert_eq!(store.property_index_options("N", "other"), None);
+    let lower = node_with(&mut store, &[("city", Value::String("paris".into()))]);
+    let padded = node_with(&mut store, &[("city", Value::String(" PARIS ".into()))]);
+    let number = node_with(&mut store, &[("city", Value::Int(75))]);
+
+    let scan = |store: &InMemoryGraphStore, value: Value| -> Vec<u64> {
+        let mut ids: Vec<u64> = store.scan_by_index("N", "city", &value).unwrap().into_iter().map(|n| n.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+    assert_eq!(scan(&store, Value::String("  paRIS".into())), vec![upper, lower, padded]);
+    assert_eq!(scan(&store, Value::Int(75)), vec![number]);
+    assert!(scan(&store, Value::String("75".into())).is_empty());
+    // scan_by_property keeps exact equality
+    assert_eq!(indexed_ids(&store, "city", Value::String("paris".into())), vec![lower]);
+    assert!(matches!(store.scan_by_index("N", "name", &Value::Int(1)), Err(EngineError::InvalidArgument(_))));
+
+    store.set_node_property(lower, "city".into(), Value::String("Lyon".into())).unwrap();
+    assert_eq!(scan(&store, Value::String("paris".into())), vec![upper, padded]);
+    assert_eq!(scan(&store, Value::String("LYON ".into())), vec![lower]);
+    assert!(store.verify_indexes().is_ok());
+    store.rebuild_indexes();
+    assert_eq!(store.property_index_options("N", "city"), Some(options));
+    assert_eq!(scan(&store, Value::String("paris".into())), vec![upper, padded]);
+    assert!(store.verify_indexes().is_ok());
+}
+
+#[test]
+fn multi_value_property_index_files_every_list_element() {
+    use casys_engine::index::IndexOptions;
+
+    let list = |items: &[&str]| Value::Array(items.iter().map(|s| Value::String(s.to_string())).collect());
+    let mut store = InMemoryGraphStore::new();
+    let both = node_with(&mut store, &[("tags", list(&["rust", "db", "rust"]))]);
+    let options = IndexOptions { multi_value: true, ..Default::default() };
+    store.create_property_index_with("N", "tags", options).unwrap();
+    let db = node_with(&mut store, &[("tags", list(&["db"]))]);
+    let scalar = node_with(&mut store, &[("tags", Value::String("rust".into()))]);
+    node_with(&mut store, &[("tags", list(&[]))]);
+
+    let scan = |store: &InMemoryGraphStore, tag: &str| -> Vec<u64> {
+        let mut ids: Vec<u64> = store.scan_by_index("N", "tags", &Value::String(tag.into())).unwrap().into_iter().map(|n| n.id).collect();
+        ids.sort_unstable();
+        ids
+    };
+    assert_eq!(scan(&store, "rust"), vec![both, scalar]);
+    assert_eq!(scan(&store, "db"), vec![both, db]);
+    assert!(matches!(store.scan_by_index("N", "tags", &list(&["db"])), Err(EngineError::InvalidArgument(_))));
+    // scan_by_property still compares whole values
+    assert_eq!(indexed_ids(&store, "tags", list(&["db"])), vec![db]);
+    assert_eq!(indexed_ids(&store, "tags", Value::String("rust".into())), vec![scalar]);
+    assert!(store.verify_indexes().is_ok());
+
+    store.set_node_property(both, "tags".into(), list(&["graph"])).unwrap();
+    assert_eq!(scan(&store, "rust"), vec![scalar]);
+    assert_eq!(scan(&store, "db"), vec![db]);
+    assert_eq!(scan(&store, "graph"), vec![both]);
+    store.delete_node(db, true).unwrap();
+    assert!(scan(&store, "db").is_empty());
+    assert!(store.verify_indexes().is_ok());
+    store.rebuild_indexes();
+    assert_eq!(scan(&store, "graph"), vec![both]);
+    assert!(store.verify_indexes().is_ok());
+}
+
+#[test]
+fn covering_index_keeps_covered_copies_in_step_with_writes() {
+    use casys_engine::index::IndexOptions;
+
+    let covered = |store: &InMemoryGraphStore, city: &str| -> Vec<(u64, HashMap<String, Value>)> {
+        let mut rows = store.scan_covered("N", "city", &Value::String(city.into())).unwrap();
+        rows.sort_by_key(|(id, _)| *id);
+        rows
+    };
+    let name = |s: &str| HashMap::from([("name".to_string(), Value::String(s.into()))]);
+    let mut store = InMemoryGraphStore::new();
+    let ann = node_with(&mut store, &[("city", Value::String("Paris".into())), ("name", Value::String("Ann".into()))]);
+    store.create_covering_index("N", "city", IndexOptions::default(), &["name", "age"]).unwrap();
+    let bob = node_with(&mut store, &[("city", Value::String("Paris".into())), ("name", Value::String("Bob".into()))]);
+    assert_eq!(store.covered_keys("N", "city"), Some(vec!["name".to_string(), "age".to_string()]));
+    assert_eq!(covered(&store, "Paris"), vec![(ann, name("Ann")), (bob, name("Bob"))]);
+
+    store.set_node_property(ann, "name".into(), Value::String("Anne".into())).unwrap();
+    store.set_node_property(bob, "age".into(), Value::Int(40)).unwrap();
+    let mut bob_row = name("Bob");
+    bob_row.insert("age".into(), Value::Int(40));
+    assert_eq!(covered(&store, "Paris"), vec![(ann, name("Anne")), (bob, bob_row)]);
+    store.remove_node_property(bob, "name").unwrap();
+    store.set_node_property(ann, "city".into(), Value::String("Lyon".into())).unwrap();
+    assert_eq!(covered(&store, "Paris"), vec![(bob, HashMap::from([("age".to_string(), Value::Int(40))]))]);
+    assert_eq!(covered(&store, "Lyon"), vec![(ann, name("Anne"))]);
+    store.remove_label(ann, "N").unwrap();
+    assert!(covered(&store, "Lyon").is_empty());
+    assert!(store.verify_indexes().is_ok());
+    store.rebuild_indexes();
+    assert_eq!(covered(&store, "Paris").len(), 1);
+    assert!(store.verify_indexes().is_ok());
+    // Still an ordinary property index for everything else
+    assert_eq!(indexed_ids(&store, "city", Value::String("Paris".into())), vec![bob]);
+
+    store.create_property_index("N", "name").unwrap();
+    assert!(matches!(store.scan_covered("N", "name", &Value::Null), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.scan_covered("N", "other", &Value::Null), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_covering_index("N", "age", IndexOptions::default(), &[]), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_covering_index("N", "age", IndexOptions::default(), &["a", "a"]), Err(EngineError::InvalidArgument(_))));
+    assert_eq!(store.covered_keys("N", "name"), Some(Vec::new()));
+    assert_eq!(store.covered_keys("N", "age"), None);
+}
+
+// =============================================================================
+// Range indexes
+// =============================================================================
+
+fn range_ids(store: &InMemoryGraphStore, min: Option<Value>, max: Option<Value>, inclusive: bool) -> Vec<u64> {
+    store.scan_by_property_range(Some("N"), "at", min, max, inclusive).unwrap().into_iter().map(|n| n.id).collect()
+}
+
+#[test]
+fn range_index_matches_full_scan_with_mixed_types() {
+    let mut indexed = InMemoryGraphStore::new();
+    let mut plain = InMemoryGraphStore::new();
+    let values = [
+        Value::Int(5), Value::Float(2.5), Value::String("7".into()), Value::Int(10),
+        Value::Float(f64::NAN), Value::Bool(true), Value::Float(-0.0), Value::Int(7),
+    ];
+    for value in &values {
+        node_with(&mut indexed, &[("at", value.clone())]);
+        node_with(&mut plain, &[("at", value.clone())]);
+    }
+    node_with(&mut indexed, &[]);
+    node_with(&mut plain, &[]);
+    indexed.create_range_index("N", "at").unwrap();
+    assert!(matches!(indexed.create_range_index("N", "at"), Err(EngineError::InvalidArgument(_))));
+
+    let sorted = |mut ids: Vec<u64>| { ids.sort_unstable(); ids };
+    let cases = [
+        (Some(Value::Int(3)), Some(Value::Float(7.0)), true),
+        (Some(Value::Int(5)), Some(Value::Int(7)), false),
+        (Some(Value::Int(7)), Some(Value::Int(7)), false),
+        (Some(Value::Int(9)), Some(Value::Int(1)), true),
+        (None, Some(Value::Int(5)), true),
+        (Some(Value::Float(0.0)), None, true),
+        (None, None, true),
+    ];
+    for (min, max, inclusive) in cases {
+        assert_eq!(
+            sorted(range_ids(&indexed, min.clone(), max.clone(), inclusive)),
+            sorted(range_ids(&plain, min, max, inclusive)),
+        );
+    }
+    // Served from the index in value order
+    assert_eq!(range_ids(&indexed, Some(Value::Int(0)), Some(Value::Int(7)), true), vec![7, 2, 1, 8]);
+    assert!(indexed.verify_indexes().is_ok());
+}
+
+#[test]
+fn range_index_matches_full_scan_beyond_f64_precision() {
+    let mut indexed = InMemoryGraphStore::new();
+    let mut plain = InMemoryGraphStore::new();
+    let big = 1_i64 << 53;
+    // Keys are widened to f64: big and big + 1 share a bucket, as do the negative pair
+    for i in [big - 1, big, big + 1, big + 2, -big, -big - 1] {
+        node_with(&mut indexed, &[("at", Value::Int(i))]);
+        node_with(&mut plain, &[("at", Value::Int(i))]);
+    }
+    indexed.create_range_index("N", "at").unwrap();
+
+    let sorted = |mut ids: Vec<u64>| { ids.sort_unstable(); ids };
+    let cases = [
+        (Some(Value::Int(big)), None, false),
+        (Some(Value::Int(big)), Some(Value::Int(big + 2)), false),
+        (None, Some(Value::Int(big + 1)), false),
+        (Some(Value::Int(-big - 1)), Some(Value::Int(-big)), true),
+        (Some(Value::Int(-big - 1)), Some(Value::Int(big)), false),
+    ];
+    for (min, max, inclusive) in cases {
+        let expected = sorted(range_ids(&plain, min.clone(), max.clone(), inclusive));
+        assert!(!expected.is_empty());
+        assert_eq!(sorted(range_ids(&indexed, min, max, inclusive)), expected);
+    }
+}
+
+#[test]
+fn range_index_tracks_updates_and_deletes() {
+    let mut store = soft_store();
+    store.create_range_index("N", "at").unwrap();
+    let a = node_with(&mut store, &[("at", Value::Int(1))]);
+    let b = node_with(&mut store, &[("at", Value::Int(2))]);
+
+    store.set_node_property(a, "at".into(), Value::Int(20)).unwrap();
+    assert_eq!(range_ids(&store, Some(Value::Int(0)), Some(Value::Int(10)), true), vec![b]);
+    assert_eq!(range_ids(&store, Some(Value::Int(10)), None, true), vec![a]);
+
+    // Switching the property to a string moves it out of every numeric range without panicking
+    store.set_node_property(b, "at".into(), Value::String("soon".into())).unwrap();
+    assert!(range_ids(&store, None, Some(Value::Int(10)), true).is_empty());
+
+    store.delete_node(a, false).unwrap();
+    assert!(range_ids(&store, None, None, true).is_empty());
+    store.undelete_node(a).unwrap();
+    assert_eq!(range_ids(&store, None, None, true), vec![a]);
+    assert!(store.verify_indexes().is_ok());
+
+    assert!(store.drop_range_index("N", "at").unwrap());
+    assert_eq!(range_ids(&store, None, None, true), vec![a]);
+}
+
+// =============================================================================
+// Composite indexes
+// =============================================================================
+
+fn person(store: &mut InMemoryGraphStore, props: &[(&str, &str)]) -> u64 {
+    let props = props.iter().map(|(k, v)| (k.to_string(), Value::String(v.to_string()))).collect();
+    store.add_node(vec!["Person".into()], props).unwrap()
+}
+
+fn composite_ids(store: &InMemoryGraphStore, pairs: &[(&str, &str)]) -> Vec<u64> {
+    let pairs: Vec<(&str, Value)> = pairs.iter().map(|(k, v)| (*k, Value::String(v.to_string()))).collect();
+    let mut ids: Vec<u64> = store.scan_by_composite("Person", &pairs).unwrap().into_iter().map(|n| n.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn composite_index_answers_full_key_lookups() {
+    let mut store = InMemoryGraphStore::new();
+    let a = person(&mut store, &[("country", "FR"), ("city", "Lyon")]);
+    let b = person(&mut store, &[("country", "FR"), ("city", "Paris")]);
+    person(&mut store, &[("country", "FR")]);
+    store.create_composite_index("Person", &["country", "city"]).unwrap();
+    let c = person(&mut store, &[("country", "FR"), ("city", "Lyon")]);
+
+    assert_eq!(composite_ids(&store, &[("country", "FR"), ("city", "Lyon")]), vec![a, c]);
+    assert_eq!(composite_ids(&store, &[("city", "Paris"), ("country", "FR")]), vec![b]);
+    assert!(composite_ids(&store, &[("country", "DE"), ("city", "Lyon")]).is_empty());
+    assert_eq!(store.composite_indexes(), vec![("Person".to_string(), vec!["country".to_string(), "city".to_string()])]);
+
+    // Key sets without a matching index are rejected, not scanned
+    for pairs in [vec![("country", "FR")], vec![("country", "FR"), ("city", "Lyon"), ("zip", "1")], vec![("country", "FR"), ("country", "FR")]] {
+        let pairs: Vec<(&str, Value)> = pairs.into_iter().map(|(k, v)| (k, Value::String(v.into()))).collect();
+        assert!(matches!(store.scan_by_composite("Person", &pairs), Err(EngineError::InvalidArgument(_))));
+    }
+    assert!(matches!(store.create_composite_index("Person", &["country", "city"]), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_composite_index("Person", &["city", "city"]), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_composite_index("Person", &[]), Err(EngineError::InvalidArgument(_))));
+
+    assert!(store.drop_composite_index("Person", &["country", "city"]).unwrap());
+    assert!(store.scan_by_composite("Person", &[("country", Value::String("FR".into())), ("city", Value::String("Lyon".into()))]).is_err());
+}
+
+#[test]
+fn composite_index_tracks_writes() {
+    let mut store = InMemoryGraphStore::new();
+    store.create_composite_index("Person", &["country", "city"]).unwrap();
+    let a = person(&mut store, &[("country", "FR"), ("city", "Lyon")]);
+    let partial = person(&mut store, &[("country", "FR")]);
+
+    store.set_node_property(a, "city".into(), Value::String("Nice".into())).unwrap();
+    assert!(composite_ids(&store, &[("country", "FR"), ("city", "Lyon")]).is_empty());
+    assert_eq!(composite_ids(&store, &[("country", "FR"), ("city", "Nice")]), vec![a]);
+
+    // Completing the tuple files the node; removing a key unfiles it
+    store.set_node_property(partial, "city".into(), Value::String("Nice".into())).unwrap();
+    assert_eq!(composite_ids(&store, &[("country", "FR"), ("city", "Nice")]), vec![a, partial]);
+    store.remove_node_property(a, "country").unwrap();
+    assert_eq!(composite_ids(&store, &[("country", "FR"), ("city", "Nice")]), vec![partial]);
+    assert!(store.verify_indexes().is_ok());
+
+    store.delete_node(partial, false).unwrap();
+    assert!(composite_ids(&store, &[("country", "FR"), ("city", "Nice")]).is_empty());
+    store.rebuild_indexes();
+    assert!(store.verify_indexes().is_ok());
+}
+
+// =============================================================================
+// Unique constraints
+// =============================================================================
+
+fn user(store: &mut InMemoryGraphStore, email: &str) -> Result<u64, EngineError> {
+    store.add_node(vec!["User".into()], HashMap::from([("email".into(), Value::String(email.into()))]))
+}
+
+fn is_violation(result: Result<impl std::fmt::Debug, EngineError>, email: &str) -> bool {
+    matches!(result, Err(EngineError::UniqueViolation { label, key, value })
+        if label == "User" && key == "email" && value == Value::String(email.into()))
+}
+
+#[test]
+fn unique_constraint_creation_reports_existing_duplicates() {
+    let mut store = InMemoryGraphStore::new();
+    user(&mut store, "a@x").unwrap();
+    user(&mut store, "a@x").unwrap();
+    user(&mut store, "b@x").unwrap();
+    match store.create_unique_constraint("User", "email") {
+        Err(EngineError::InvalidArgument(msg)) => assert!(msg.contains("\"a@x\"") && msg.contains("[1, 2]"), "{}", msg),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(store.unique_constraints().is_empty());
+
+    store.set_node_property(2, "email".into(), Value::String("c@x".into())).unwrap();
+    store.create_unique_constraint("User", "email").unwrap();
+    assert!(store.has_property_index("User", "email"));
+    assert!(matches!(store.create_unique_constraint("User", "email"), Err(EngineError::InvalidArgument(_))));
+}
+
+#[test]
+fn unique_constraint_rejects_duplicating_writes() {
+    let mut store = soft_store();
+    store.create_unique_constraint("User", "email").unwrap();
+    let a = user(&mut store, "a@x").unwrap();
+    let b = user(&mut store, "b@x").unwrap();
+
+    assert!(is_violation(user(&mut store, "a@x"), "a@x"));
+    assert!(is_violation(store.set_node_property(b, "email".into(), Value::String("a@x".into())), "a@x"));
+    let props = HashMap::from([("name".into(), Value::String("B".into())), ("email".into(), Value::String("a@x".into()))]);
+    assert!(is_violation(store.update_node_properties(b, props), "a@x"));
+    assert!(!store.get_node(b).unwrap().unwrap().properties.contains_key("name"));
+    let bulk = vec![
+        (vec!["User".to_string()], HashMap::from([("email".to_string(), Value::String("n@x".into()))])),
+        (vec!["User".to_string()], HashMap::from([("email".to_string(), Value::String("n@x".into()))])),
+    ];
+    assert!(is_violation(store.add_nodes_bulk(bulk), "n@x"));
+    assert_eq!(store.node_count().unwrap(), 2);
+
+    // Rewriting a node's own value, or nodes without the label, are fine
+    store.set_node_property(a, "email".into(), Value::String("a@x".into())).unwrap();
+    let guest = store.add_node(vec!["Guest".into()], HashMap::from([("email".into(), Value::String("a@x".into()))])).unwrap();
+    assert!(is_violation(store.add_label(guest, "User".into()), "a@x"));
+
+    // A deleted node frees its value, and cannot come back while someone else holds it
+    store.delete_node(a, false).unwrap();
+    let c = user(&mut store, "a@x").unwrap();
+    assert!(is_violation(store.undelete_node(a), "a@x"));
+    store.set_node_property(c, "email".into(), Value::String("c@x".into())).unwrap();
+    assert!(store.undelete_node(a).unwrap());
+
+    // The same default on two constrained nodes is rejected before either is written
+    let blank = store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    assert!(is_violation(store.set_default_property("User", "email", Value::String("z@x".into())), "z@x"));
+    assert!(!store.get_node(blank).unwrap().unwrap().properties.contains_key("email"));
+    assert!(store.drop_unique_constraint("User", "email").unwrap());
+    user(&mut store, "a@x").unwrap();
+}
+
+#[test]
+fn unique_constraint_keeps_merge_node_single() {
+    let mut store = InMemoryGraphStore::new();
+    store.create_unique_constraint("User", "email").unwrap();
+    let (first, created) = store.merge_node("User", "email", Value::String("a@x".into()), HashMap::new()).unwrap();
+    assert!(created);
+    for _ in 0..3 {
+        assert_eq!(store.merge_node("User", "email", Value::String("a@x".into()), HashMap::new()).unwrap(), (first, false));
+    }
+    assert_eq!(store.count_by_label("User").unwrap(), 1);
+
+    let other = user(&mut store, "b@x").unwrap();
+    let twin = store.add_node(vec!["Admin".into()], HashMap::from([("email".into(), Value::String("a@x".into()))])).unwrap();
+    // Merging `twin` (no User label) into `other` is fine: `other` keeps its own email
+    store.merge_nodes(other, twin, false).unwrap();
+    let stray = store.add_node(vec!["User".into()], HashMap::new()).unwrap();
+    let donor = store.add_node(vec!["Admin".into()], HashMap::from([("email".into(), Value::String("a@x".into()))])).unwrap();
+    assert!(is_violation(store.merge_nodes(stray, donor, false), "a@x"));
+}
+
+// =============================================================================
+// Edge indexes
+// =============================================================================
+
+fn edge_ids_by(store: &InMemoryGraphStore, edge_type: &str, since: i64) -> Vec<u64> {
+    let mut ids: Vec<u64> = store.scan_edges_by_property(edge_type, "since", &Value::Int(since)).unwrap()
+        .into_iter().map(|e| e.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn edge_index_answers_lookups_like_a_scan() {
+    let mut store = InMemoryGraphStore::new();
+    let a = store.add_node(vec![], HashMap::new()).unwrap();
+    let b = store.add_node(vec![], HashMap::new()).unwrap();
+    let since = |y: i64| HashMap::from([("since".to_string(), Value::Int(y))]);
+    let e1 = store.add_edge(a, b, "KNOWS".into(), since(2020)).unwrap();
+    store.add_edge(b, a, "KNOWS".into(), since(2021)).unwrap();
+    store.add_edge(a, b, "LIKES".into(), since(2020)).unwrap();
+
+    // Unindexed lookups fall back to a scan with the same answer
+    assert_eq!(edge_ids_by(&store, "KNOWS", 2020), vec![e1]);
+    store.create_edge_index("KNOWS", "since").unwrap();
+    let e2 = store.add_edge(b, a, "KNOWS".into(), since(2020)).unwrap();
+    assert_eq!(edge_ids_by(&store, "KNOWS", 2020), vec![e1, e2]);
+    assert!(edge_ids_by(&store, "KNOWS", 1999).is_empty());
+    assert_eq!(store.edge_indexes(), vec![("KNOWS".to_string(), "since".to_string())]);
+    assert!(store.verify_indexes().is_ok());
+
+    assert!(matches!(store.create_edge_index("KNOWS", "since"), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_edge_index("", "since"), Err(EngineError::InvalidArgument(_))));
+    assert!(store.drop_edge_index("KNOWS", "since").unwrap());
+    assert!(!store.drop_edge_index("KNOWS", "since").unwrap());
+    assert_eq!(edge_ids_by(&store, "KNOWS", 2020), vec![e1, e2]);
+}
+
+#[test]
+fn edge_index_tracks_updates_retyping_and_deletes() {
+    for soft_delete in [false, true] {
+        let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions { soft_delete, ..Default::default() });
+        store.create_edge_index("KNOWS", "since").unwrap();
+        let a = store.add_node(vec![], HashMap::new()).unwrap();
+        let b = store.add_node(vec![], HashMap::new()).unwrap();
+        let e = store.add_edge(a, b, "KNOWS".into(), HashMap::from([("since".into(), Value::Int(2020))])).unwrap();
+
+        // Updating moves the edge out of its old bucket
+        store.update_edge_properties(e, HashMap::from([("since".into(), Value::Int(2022))])).unwrap();
+        assert!(edge_ids_by(&store, "KNOWS", 2020).is_empty());
+        assert_eq!(edge_ids_by(&store, "KNOWS", 2022), vec![e]);
+
+        store.set_edge_type(e, "LIKES".into()).unwrap();
+        assert!(edge_ids_by(&store, "KNOWS", 2022).is_empty());
+        store.rename_edge_type("LIKES", "KNOWS").unwrap();
+        assert_eq!(edge_ids_by(&store, "KNOWS", 2022), vec![e]);
+        assert!(store.verify_indexes().is_ok());
+
+        store.delete_edge(e).unwrap();
+        assert!(edge_ids_by(&store, "KNOWS", 2022).is_empty());
+        assert!(store.verify_indexes().is_ok());
+        store.rebuild_indexes();
+        assert!(store.verify_indexes().is_ok());
+    }
+}
+
+// =============================================================================
+// Typed adjacency
+// =============================================================================
+
+/// Typed neighbor answers for every node, sorted so stores can be compared.
+fn typed_view(store: &InMemoryGraphStore, nodes: &[u64], types: &[&str]) -> Vec<String> {
+    use casys_core::Direction;
+    let mut view = Vec::new();
+    for &n in nodes {
+        for t in types {
+            let mut out: Vec<u64> = store.get_neighbors(n, Some(t)).unwrap().into_iter().map(|(e, _)| e.id).collect();
+            let mut both = store.get_neighbor_edge_ids(n, Some(t), Direction::Both).unwrap().into_iter().map(|(e, _)| e).collect::<Vec<_>>();
+            let degree = store.degree(n, Direction::Both, Some(t)).unwrap();
+            let mut between: Vec<u64> = nodes.iter().flat_map(|m| store.get_edges_between(n, *m, Some(t)).unwrap()).map(|e| e.id).collect();
+            out.sort_unstable();
+            both.sort_unstable();
+            between.sort_unstable();
+            view.push(format!("{} {}: out {:?} both {:?} degree {} between {:?}", n, t, out, both, degree, between));
+        }
+    }
+    view
+}
+
+#[test]
+fn typed_adjacency_matches_plain_adjacency_through_edge_writes() {
+    use casys_engine::index::Endpoint;
+    let mut plain = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions { soft_delete: true, ..Default::default() });
+    let mut typed = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        soft_delete: true,
+        typed_adjacency: true,
+        ..Default::default()
+    });
+    let types = ["A", "B"];
+    let mut nodes = Vec::new();
+    for store in [&mut plain, &mut typed] {
+        nodes = (0..4).map(|_| store.add_node(vec![], HashMap::new()).unwrap()).collect();
+        let (a, b, c, d) = (nodes[0], nodes[1], nodes[2], nodes[3]);
+        let e1 = store.add_edge(a, b, "A".into(), HashMap::new()).unwrap();
+        let e2 = store.add_edge(a, c, "B".into(), HashMap::new()).unwrap();
+        store.add_edge(a, a, "A".into(), HashMap::new()).unwrap();
+        let e4 = store.add_edge(c, a, "A".into(), HashMap::new()).unwrap();
+        store.add_edges_bulk(vec![(b, d, "B".into(), HashMap::new()), (d, c, "A".into(), HashMap::new())]).unwrap();
+        assert!(store.verify_indexes().is_ok());
+
+        store.set_edge_type(e1, "B".into()).unwrap();
+        store.reverse_edge(e2).unwrap();
+        store.set_edge_endpoint(e4, Endpoint::To, d).unwrap();
+        store.rename_edge_type("A", "B").unwrap();
+        store.rename_edge_type("B", "A").unwrap();
+        store.delete_edge(e1).unwrap();
+        store.undelete_edge(e1).unwrap();
+        store.merge_nodes(b, d, true).unwrap();
+        assert!(store.verify_indexes().is_ok());
+    }
+    nodes.truncate(3);
+    assert_eq!(typed_view(&typed, &nodes, &types), typed_view(&plain, &nodes, &types));
+
+    typed.rebuild_indexes();
+    assert!(typed.verify_indexes().is_ok());
+    assert_eq!(typed_view(&typed, &nodes, &types), typed_view(&plain, &nodes, &types));
+}
+
+// =============================================================================
+// Text indexes
+// =============================================================================
+
+fn doc(store: &mut InMemoryGraphStore, body: &str) -> u64 {
+    store.add_node(vec!["Doc".into()], HashMap::from([("body".into(), Value::String(body.into()))])).unwrap()
+}
+
+#[test]
+fn text_index_matches_all_any_and_prefix() {
+    use casys_engine::index::TextMatch;
+    let mut store = InMemoryGraphStore::new();
+    let a = doc(&mut store, "Graph databases, in memory!");
+    let b = doc(&mut store, "An in-memory cache");
+    store.create_text_index("Doc", "body").unwrap();
+    let c = doc(&mut store, "Graphs and GRAPHICS");
+    store.add_node(vec!["Doc".into()], HashMap::from([("body".into(), Value::Int(7))])).unwrap();
+
+    assert_eq!(store.search_text("Doc", "body", "memory IN", TextMatch::AllTokens).unwrap(), vec![a, b]);
+    assert_eq!(store.search_text("Doc", "body", "graph cache", TextMatch::AllTokens).unwrap(), Vec::<u64>::new());
+    assert_eq!(store.search_text("Doc", "body", "graph cache", TextMatch::AnyToken).unwrap(), vec![a, b]);
+    assert_eq!(store.search_text("Doc", "body", "graph", TextMatch::Prefix).unwrap(), vec![a, c]);
+    assert_eq!(store.search_text("Doc", "body", "gra data", TextMatch::Prefix).unwrap(), vec![a]);
+    assert!(store.search_text("Doc", "body", " ,.", TextMatch::AnyToken).unwrap().is_empty());
+    assert_eq!(store.text_indexes(), vec![("Doc".to_string(), "body".to_string())]);
+
+    assert!(matches!(store.search_text("Doc", "title", "graph", TextMatch::AnyToken), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_text_index("Doc", "body"), Err(EngineError::InvalidArgument(_))));
+    assert!(store.drop_text_index("Doc", "body").unwrap());
+    assert!(!store.drop_text_index("Doc", "body").unwrap());
+}
+
+#[test]
+fn text_index_tracks_writes() {
+    use casys_engine::index::TextMatch;
+    let mut store = InMemoryGraphStore::new();
+    store.create_text_index("Doc", "body").unwrap();
+    let a = doc(&mut store, "red fox");
+    let b = doc(&mut store, "red hen");
+    let search = |store: &InMemoryGraphStore, q: &str| store.search_text("Doc", "body", q, TextMatch::AnyToken).unwrap();
+
+    store.set_node_property(a, "body".into(), Value::String("blue fox".into())).unwrap();
+    assert_eq!(search(&store, "red"), vec![b]);
+    assert_eq!(search(&store, "blue"), vec![a]);
+    store.remove_label(b, "Doc").unwrap();
+    assert!(search(&store, "hen").is_empty());
+    store.add_label(b, "Doc".into()).unwrap();
+    store.delete_node(a, false).unwrap();
+    assert!(search(&store, "fox").is_empty());
+    assert_eq!(search(&store, "hen"), vec![b]);
+    assert!(store.verify_indexes().is_ok());
+
+    store.rebuild_indexes();
+    assert!(store.verify_indexes().is_ok());
+    assert_eq!(search(&store, "red"), vec![b]);
+}
+
+#[test]
+fn text_index_uses_a_custom_tokenizer() {
+    use casys_engine::index::{TextMatch, Tokenizer};
+    use std::sync::Arc;
+
+    struct Comma;
+    impl Tokenizer for Comma {
+        fn tokenize(&self, text: &str) -> Vec<String> {
+            text.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect()
+        }
+    }
+    let mut store = InMemoryGraphStore::new();
+    let a = store.add_node(vec!["Doc".into()], HashMap::from([("tags".into(), Value::String("New York, rust".into()))])).unwrap();
+    store.create_text_index_with("Doc", "tags", Arc::new(Comma)).unwrap();
+    assert_eq!(store.search_text("Doc", "tags", "New York", TextMatch::AllTokens).unwrap(), vec![a]);
+    assert!(store.search_text("Doc", "tags", "york", TextMatch::AnyToken).unwrap().is_empty());
+    store.rebuild_indexes();
+    assert_eq!(store.search_text("Doc", "tags", "New York", TextMatch::AllTokens).unwrap(), vec![a]);
+}
+
+// =============================================================================
+// Prefix indexes
+// =============================================================================
+
+fn names(nodes: Vec<casys_core::Node>) -> Vec<String> {
+    nodes.into_iter().map(|n| match n.properties.get("name") {
+        Some(Value::String(s)) => s.clone(),
+        other => panic!("unexpected name {:?}", other),
+    }).collect()
+}
+
+#[test]
+fn prefix_index_scans_in_value_order_with_limit() {
+    let mut store = InMemoryGraphStore::new();
+    for name in ["Alice", "alan", "Albert", "Bob", "Al"] {
+        person(&mut store, &[("name", name)]);
+    }
+    store.add_node(vec!["Person".into()], HashMap::from([("name".into(), Value::Int(1))])).unwrap();
+    store.create_prefix_index("Person", "name", true).unwrap();
+
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "Al", 10).unwrap()), vec!["Al", "Albert", "Alice"]);
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "Al", 2).unwrap()), vec!["Al", "Albert"]);
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "", 100).unwrap()).len(), 5);
+    assert!(store.scan_by_prefix("Person", "name", "Al", 0).unwrap().is_empty());
+    assert!(store.scan_by_prefix("Person", "name", "z", 10).unwrap().is_empty());
+
+    assert!(store.drop_prefix_index("Person", "name").unwrap());
+    assert!(matches!(store.scan_by_prefix("Person", "name", "Al", 10), Err(EngineError::InvalidArgument(_))));
+    store.create_prefix_index("Person", "name", false).unwrap();
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "AL", 10).unwrap()), vec!["Al", "alan", "Albert", "Alice"]);
+    assert_eq!(store.prefix_indexes(), vec![("Person".to_string(), "name".to_string(), false)]);
+    assert!(matches!(store.create_prefix_index("Person", "name", true), Err(EngineError::InvalidArgument(_))));
+}
+
+#[test]
+fn prefix_index_tracks_writes() {
+    let mut store = InMemoryGraphStore::new();
+    store.create_prefix_index("Person", "name", false).unwrap();
+    let a = person(&mut store, &[("name", "Alice")]);
+    let b = person(&mut store, &[("name", "Alma")]);
+    store.set_node_property(a, "name".into(), Value::String("Bea".into())).unwrap();
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "al", 10).unwrap()), vec!["Alma"]);
+    assert_eq!(names(store.scan_by_prefix("Person", "name", "b", 10).unwrap()), vec!["Bea"]);
+    store.delete_node(b, false).unwrap();
+    assert!(store.scan_by_prefix("Person", "name", "al", 10).unwrap().is_empty());
+    assert!(store.verify_indexes().is_ok());
+    store.rebuild_indexes();
+    assert!(store.verify_indexes().is_ok());
+    assert_eq!(store.prefix_indexes(), vec![("Person".to_string(), "name".to_string(), false)]);
+}
+
+// =============================================================================
+// Index introspection
+// =============================================================================
+
+#[test]
+fn list_indexes_reports_every_kind_with_its_size() {
+    use casys_engine::index::IndexKind;
+
+    let mut store = InMemoryGraphStore::new();
+    let a = node_with(&mut store, &[("name", Value::String("alpha".into())), ("age", Value::Int(3))]);
+    let b = node_with(&mut store, &[("name", Value::String("beta".into())), ("age", Value::Int(3))]);
+    store.add_edge(a, b, "KNOWS".into(), HashMap::from([("since".into(), Value::Int(2020))])).unwrap();
+    store.create_property_index("N", "name").unwrap();
+    store.create_range_index("N", "age").unwrap();
+    store.create_composite_index("N", &["name", "age"]).unwrap();
+    store.create_text_index("N", "name").unwrap();
+    store.create_prefix_index("N", "name", true).unwrap();
+    store.create_edge_index("KNOWS", "since").unwrap();
+
+    let list = store.list_indexes();
+    let summary: Vec<(IndexKind, &str, Vec<&str>, usize)> = list.iter()
+        .map(|i| (i.kind, i.label.as_str(), i.keys.iter().map(String::as_str).collect(), i.entries))
+        .collect();
+    assert_eq!(summary, vec![
+        (IndexKind::Property, "N", vec!["name"], 2),
+        (IndexKind::Range, "N", vec!["age"], 2),
+        (IndexKind::Composite, "N", vec!["name", "age"], 2),
+        (IndexKind::Text, "N", vec!["name"], 2),
+        (IndexKind::Prefix, "N", vec!["name"], 2),
+        (IndexKind::Edge, "KNOWS", vec!["since"], 1),
+    ]);
+    assert!(list.iter().all(|i| i.approx_bytes > 0));
+
+    // More data means a bigger estimate
+    let before = list[0].approx_bytes;
+    for i in 0..50 {
+        node_with(&mut store, &[("name", Value::String(format!("node-{i}")))]);
+    }
+    assert!(store.list_indexes()[0].approx_bytes > before);
+
+    store.drop_text_index("N", "name").unwrap();
+    assert!(store.list_indexes().iter().all(|i| i.kind != IndexKind::Text));
+}
+
+#[test]
+fn index_stats_count_hits_and_misses_per_index() {
+    use casys_engine::index::{IndexKind, TextMatch};
+
+    let mut store = InMemoryGraphStore::new();
+    node_with(&mut store, &[("name", Value::String("alpha".into())), ("age", Value::Int(3))]);
+    store.create_property_index("N", "name").unwrap();
+    store.create_range_index("N", "age").unwrap();
+    store.create_text_index("N", "name").unwrap();
+    store.create_prefix_index("N", "name", true).unwrap();
+    let counts = |store: &InMemoryGraphStore, kind: IndexKind| {
+        let stats = store.index_stats().into_iter().find(|s| s.kind == kind).unwrap();
+        (stats.hits, stats.misses)
+    };
+    assert_eq!(counts(&store, IndexKind::Property), (0, 0));
+
+    store.scan_by_property(Some("N"), "name", &Value::String("alpha".into())).unwrap();
+    store.scan_by_property(Some("N"), "name", &Value::String("alpha".into())).unwrap();
+    store.scan_by_property(Some("N"), "name", &Value::String("gamma".into())).unwrap();
+    assert_eq!(counts(&store, IndexKind::Property), (2, 1));
+    store.scan_by_property_range(Some("N"), "age", Some(Value::Int(10)), None, true).unwrap();
+    assert_eq!(counts(&store, IndexKind::Range), (0, 1));
+    store.search_text("N", "name", "alpha", TextMatch::AllTokens).unwrap();
+    assert_eq!(counts(&store, IndexKind::Text), (1, 0));
+    store.scan_by_prefix("N", "name", "al", 10).unwrap();
+    store.scan_by_prefix("N", "name", "zz", 10).unwrap();
+    assert_eq!(counts(&store, IndexKind::Prefix), (1, 1));
+
+    // Counts survive rebuilds and are cleared by a reset or by dropping the index
+    store.rebuild_indexes();
+    assert_eq!(counts(&store, IndexKind::Property), (2, 1));
+    store.reset_index_stats();
+    assert!(store.index_stats().iter().all(|s| s.hits == 0 && s.misses == 0));
+    store.scan_by_property(Some("N"), "name", &Value::String("alpha".into())).unwrap();
+    store.drop_property_index("N", "name").unwrap();
+    store.create_property_index("N", "name").unwrap();
+    assert_eq!(counts(&store, IndexKind::Property), (0, 0));
+}
+
+// =============================================================================
+// Access paths
+// =============================================================================
+
+#[test]
+fn scan_by_property_explain_picks_indexes_in_priority_order() {
+    use casys_engine::index::{AccessPath, IndexKind};
+
+    let mut store = InMemoryGraphStore::new();
+    let mut plain = InMemoryGraphStore::new();
+    for (name, age) in [("a", 3), ("b", 3), ("c", 4)] {
+        for s in [&mut store, &mut plain] {
+            let props = [("name", Value::String(name.into())), ("age", Value::Int(age)), ("height", Value::Float(age as f64))];
+            node_with(s, &props);
+        }
+    }
+    let explain = |store: &InMemoryGraphStore, label: Option<&str>, key: &str, value: Value| {
+        let ids = |nodes: Vec<casys_core::Node>| {
+            let mut ids: Vec<u64> = nodes.iter().map(|n| n.id).collect();
+            ids.sort_unstable();
+            ids
+        };
+        let (nodes, path) = store.scan_by_property_explain(label, key, &value).unwrap();
+        let len = nodes.len();
+        assert_eq!(ids(nodes), ids(plain.scan_by_property(label, key, &value).unwrap()));
+        (len, path)
+    };
+
+    assert_eq!(explain(&store, Some("N"), "age", Value::Int(3)), (2, AccessPath::LabelScan));
+    assert_eq!(explain(&store, None, "age", Value::Int(3)), (2, AccessPath::FullScan));
+    store.create_composite_index("N", &["age", "name"]).unwrap();
+    assert_eq!(explain(&store, Some("N"), "age", Value::Int(3)), (2, AccessPath::IndexEquality(IndexKind::Composite)));
+    // Only a composite index leading with the key helps
+    assert_eq!(explain(&store, Some("N"), "name", Value::String("a".into())), (1, AccessPath::LabelScan));
+    store.create_range_index("N", "age").unwrap();
+    store.create_range_index("N", "height").unwrap();
+    assert_eq!(explain(&store, Some("N"), "age", Value::Int(3)), (2, AccessPath::IndexRange));
+    // Int and Float share range buckets but never compare equal
+    assert_eq!(explain(&store, Some("N"), "height", Value::Int(3)), (0, AccessPath::IndexRange));
+    store.create_property_index("N", "age").unwrap();
+    assert_eq!(explain(&store, Some("N"), "age", Value::Int(3)), (2, AccessPath::IndexEquality(IndexKind::Property)));
+    assert_eq!(explain(&store, None, "age", Value::Int(3)), (2, AccessPath::FullScan));
+}
+
+#[test]
+fn scan_by_property_range_explain_reports_range_index_or_scan() {
+    use casys_engine::index::AccessPath;
+
+    let mut store = InMemoryGraphStore::new();
+    for age in [1, 5, 9] {
+        node_with(&mut store, &[("age", Value::Int(age))]);
+    }
+    let path = |store: &InMemoryGraphStore, label: Option<&str>| {
+        let (nodes, path) = store.scan_by_property_range_explain(label, "age", Some(Value::Int(2)), None, true).unwrap();
+        assert_eq!(nodes.len(), 2);
+        path
+    };
+    assert_eq!(path(&store, Some("N")), AccessPath::LabelScan);
+    assert_eq!(path(&store, None), AccessPath::FullScan);
+    store.create_range_index("N", "age").unwrap();
+    assert_eq!(path(&store, Some("N")), AccessPath::IndexRange);
+    assert!(matches!(
+        store.scan_by_property_range_explain(Some("N"), "age", Some(Value::Bool(true)), None, true),
+        Err(EngineError::InvalidArgument(_))
+    ));
+}
+
+// =============================================================================
+// Sorted adjacency
+// =============================================================================
+
+/// Whether every adjacency list of `nodes` is in `(edge_type, far end, edge id)` order.
+fn adjacency_sorted(store: &InMemoryGraphStore, nodes: &[u64]) -> bool {
+    let key = |id: &u64, outgoing: bool| {
+        let edge = store.get_edge(*id).unwrap().unwrap();
+        (edge.edge_type, if outgoing { edge.to_node } else { edge.from_node }, *id)
+    };
+    nodes.iter().all(|node| {
+        let refs = store.index_references(*node);
+        refs.outgoing.windows(2).all(|w| key(&w[0], true) < key(&w[1], true))
+            && refs.incoming.windows(2).all(|w| key(&w[0], false) < key(&w[1], false))
+    })
+}
+
+#[test]
+fn sorted_adjacency_stays_sorted_through_edge_writes() {
+    use casys_engine::index::Endpoint;
+
+    let mut store = InMemoryGraphStore::with_options(casys_engine::index::StoreOptions {
+        sorted_adjacency: true,
+        soft_delete: true,
+        ..Default::default()
+    });
+    let nodes: Vec<u64> = (0..5).map(|_| node(&mut store, "N")).collect();
+    let (a, b, c, d, e) = (nodes[0], nodes[1], nodes[2], nodes[3], nodes[4]);
+    let ad = store.add_edge(a, d, "B".into(), HashMap::new()).unwrap();
+    let ac1 = store.add_edge(a, c, "A".into(), HashMap::new()).unwrap();
+    let ab = store.add_edge(a, b, "B".into(), HashMap::new()).unwrap();
+    let ac2 = store.add_edge(a, c, "A".into(), HashMap::new()).unwrap();
+    let ba = store.add_edge(b, a, "A".into(), HashMap::new()).unwrap();
+    let bulk = store.add_edges_bulk(vec![(e, a, "A".into(), HashMap::new()), (a, b, "A".into(), HashMap::new())]).unwrap();
+    assert_eq!(store.index_references(a).outgoing, vec![bulk[1], ac1, ac2, ab, ad]);
+    assert_eq!(store.index_references(a).incoming, vec![ba, bulk[0]]);
+
+    store.set_edge_type(ad, "A".into()).unwrap();
+    assert!(adjacency_sorted(&store, &nodes));
+    store.reverse_edge(ab).unwrap();
+    assert!(adjacency_sorted(&store, &nodes));
+    store.set_edge_endpoint(ac2, Endpoint::To, e).unwrap();
+    store.set_edge_endpoint(ba, Endpoint::From, d).unwrap();
+    assert!(adjacency_sorted(&store, &nodes));
+    store.rename_edge_type("A", "Z").unwrap();
+    assert!(adjacency_sorted(&store, &nodes));
+    store.delete_edge(ac1).unwrap();
+    store.undelete_edge(ac1).unwrap();
+    assert!(adjacency_sorted(&store, &nodes));
+    store.merge_nodes(b, c, true).unwrap();
+    assert!(adjacency_sorted(&store, &[a, b, d, e]));
+    store.rebuild_indexes();
+    assert!(adjacency_sorted(&store, &[a, b, d, e]));
+    assert!(store.verify_indexes().is_ok());
+}
+
+#[test]
+fn get_neighbors_ordered_by_id_and_by_edge_property() {
+    use casys_engine::index::{NeighborOrder, StoreOptions};
+
+    for sorted_adjacency in [false, true] {
+        let mut store = InMemoryGraphStore::with_options(StoreOptions { sorted_adjacency, ..Default::default() });
+        let a = node(&mut store, "N");
+        let b = node(&mut store, "N");
+        let c = node(&mut store, "N");
+        let at = |value: Option<Value>| value.map(|v| HashMap::from([("at".to_string(), v)])).unwrap_or_default();
+        let e1 = store.add_edge(a, c, "X".into(), at(Some(Value::Int(30)))).unwrap();
+        let e2 = store.add_edge(a, b, "Y".into(), at(None)).unwrap();
+        let e3 = store.add_edge(a, c, "X".into(), at(Some(Value::Float(10.5)))).unwrap();
+        let e4 = store.add_edge(a, b, "X".into(), at(Some(Value::String("late".into())))).unwrap();
+        let e5 = store.add_edge(a, b, "X".into(), at(Some(Value::Int(20)))).unwrap();
+        let e6 = store.add_edge(a, a, "Y".into(), at(Some(Value::Bool(true)))).unwrap();
+
+        let edges = |order| -> Vec<u64> {
+            store.get_neighbors_ordered(a, order).unwrap().into_iter().map(|(edge, _)| edge.id).collect()
+        };
+        // Parallel edges to the same neighbor come in edge id order
+        assert_eq!(edges(NeighborOrder::ById), vec![e6, e2, e4, e5, e1, e3]);
+        // Missing and non-orderable values go last, in ById order
+        assert_eq!(edges(NeighborOrder::ByEdgeProperty("at".into())), vec![e3, e5, e1, e4, e6, e2]);
+        assert_eq!(edges(NeighborOrder::ByEdgeProperty("none".into())), edges(NeighborOrder::ById));
+        assert!(store.get_neighbors_ordered(999, NeighborOrder::ById).unwrap().is_empty());
+    }
+}
+
+// =============================================================================
+// Statistics
+// =============================================================================
+
+#[test]
+fn full_sample_statistics_are_exact() {
+    use casys_core::OrderedValue;
+
+    let mut store = InMemoryGraphStore::new();
+    for i in 0..100 {
+        let city = ["Lyon", "Paris", "Nice"][i % 3];
+        node_with(&mut store, &[("age", Value::Int(i as i64)), ("city", Value::String(city.into()))]);
+    }
+    let gone = node_with(&mut store, &[("age", Value::Int(1000))]);
+    store.delete_node(gone, true).unwrap();
+    assert!(store.statistics().is_none());
+
+    let stats = store.collect_statistics(1.0);
+    assert_eq!((stats.node_count, stats.sampled_nodes), (100, 100));
+    assert_eq!(store.statistics(), Some(&stats));
+    let age = store.property_statistics("N", "age").unwrap();
+    assert_eq!((age.sampled_values, age.estimated_count), (100, 100));
+    assert_eq!((age.sampled_distinct, age.estimated_distinct), (100, 100));
+    assert_eq!((age.min.clone(), age.max.clone()), (Some(OrderedValue::Number(0.0)), Some(OrderedValue::Number(99.0))));
+    let histogram = age.histogram.as_ref().unwrap();
+    assert_eq!((histogram.low, histogram.high), (0.0, 99.0));
+    assert_eq!(histogram.counts.iter().sum::<u64>(), 100);
+    assert!(histogram.counts.iter().all(|c| (6..=7).contains(c)));
+
+    let city = store.property_statistics("N", "city").unwrap();
+    assert_eq!((city.estimated_count, city.estimated_distinct), (100, 3));
+    assert_eq!(city.min, Some(OrderedValue::String("Lyon".into())));
+    assert!(city.histogram.is_none());
+    assert!(store.property_statistics("N", "missing").is_none());
+
+    store.truncate(false);
+    assert!(store.statistics().is_none());
+}
+
+#[test]
+fn sampled_statistics_estimate_counts_and_high_cardinality() {
+    let mut store = InMemoryGraphStore::new();
+    for i in 0..4000 {
+        let email = Value::String(format!("user{}@example.com", i));
+        node_with(&mut store, &[("email", email), ("tier", Value::Int(i % 4))]);
+    }
+    let stats = store.collect_statistics(0.25);
+    assert_eq!(stats.sample_rate, 0.25);
+    assert!((800..1200).contains(&stats.sampled_nodes), "{}", stats.sampled_nodes);
+
+    let email = store.property_statistics("N", "email").unwrap();
+    assert_eq!(email.sampled_values, stats.sampled_nodes);
+    assert_eq!(email.estimated_count, 4000);
+    assert!((3600..=4000).contains(&email.estimated_distinct), "{}", email.estimated_distinct);
+    let tier = store.property_statistics("N", "tier").unwrap();
+    assert_eq!((tier.sampled_distinct, tier.estimated_distinct), (4, 4));
+
+    // The sample depends on the ids only, and out-of-range rates are clamped
+    assert_eq!(store.collect_statistics(0.25), stats);
+    assert_eq!(store.collect_statistics(7.0).sampled_nodes, 4000);
+    let none = store.collect_statistics(-1.0);
+    assert_eq!((none.sampled_nodes, none.properties.len()), (0, 0));
+}
+
+// =============================================================================
+// Computed indexes
+// =============================================================================
+
+fn lowercase_email() -> casys_engine::index::ComputedKey {
+    Box::new(|node| match node.properties.get("email") {
+        Some(Value::String(email)) => Some(Value::String(email.to_lowercase())),
+        _ => None,
+    })
+}
+
+fn computed_ids(store: &InMemoryGraphStore, name: &str, value: &str) -> Vec<u64> {
+    store.scan_by_computed("N", name, &Value::String(value.into())).unwrap().into_iter().map(|n| n.id).collect()
+}
+
+#[test]
+fn computed_index_follows_writes_to_the_inputs() {
+    let mut store = InMemoryGraphStore::new();
+    let ann = node_with(&mut store, &[("email", Value::String("Ann@X.org".into())), ("postcode", Value::String("75011".into()))]);
+    store.create_computed_index("N", "email_lower", lowercase_email()).unwrap();
+    let area: casys_engine::index::ComputedKey = Box::new(|node| match node.properties.get("postcode") {
+        Some(Value::String(code)) => Some(Value::String(code.chars().take(2).collect())),
+        _ => None,
+    });
+    store.create_computed_index("N", "area", area).unwrap();
+    let bob = node_with(&mut store, &[("email", Value::String("bob@x.org".into())), ("postcode", Value::String("75002".into()))]);
+    node_with(&mut store, &[("postcode", Value::Int(69))]);
+
+    assert_eq!(computed_ids(&store, "email_lower", "ann@x.org"), vec![ann]);
+    assert_eq!(computed_ids(&store, "area", "75"), vec![ann, bob]);
+    assert!(!store.get_node(ann).unwrap().unwrap().properties.contains_key("email_lower"));
+    assert_eq!(store.computed_indexes(), vec![("N".to_string(), "area".to_string()), ("N".to_string(), "email_lower".to_string())]);
+    assert!(store.unregistered_computed_indexes().is_empty());
+
+    store.set_node_property(bob, "postcode".into(), Value::String("69001".into())).unwrap();
+    store.set_node_property(ann, "email".into(), Value::String("ANN@y.org".into())).unwrap();
+    assert_eq!(computed_ids(&store, "area", "75"), vec![ann]);
+    assert_eq!(computed_ids(&store, "area", "69"), vec![bob]);
+    assert!(computed_ids(&store, "email_lower", "ann@x.org").is_empty());
+    assert_eq!(computed_ids(&store, "email_lower", "ann@y.org"), vec![ann]);
+    store.delete_node(ann, true).unwrap();
+    assert!(computed_ids(&store, "area", "75").is_empty());
+    assert!(store.verify_indexes().is_ok());
+    store.rebuild_indexes();
+    assert_eq!(computed_ids(&store, "area", "69"), vec![bob]);
+
+    assert!(matches!(store.create_computed_index("N", "area", lowercase_email()), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.create_computed_index("", "x", lowercase_email()), Err(EngineError::InvalidArgument(_))));
+    assert!(matches!(store.scan_by_computed("N", "nope", &Value::Null), Err(EngineError::InvalidArgument(_))));
+    assert!(store.drop_computed_index("N", "area").unwrap());
+    assert!(!store.drop_computed_index("N", "area").unwrap());
+}
+
+// =============================================================================
+// TTL indexes
+// =============================================================================
+
+#[test]
+fn expire_deletes_nodes_whose_timestamp_has_passed() {
+    use casys_engine::index::ExpiredReport;
+
+    let mut store = InMemoryGraphStore::new();
+    let early = node_with(&mut store, &[("expires_at", Value::Int(100))]);
+    store.create_ttl_index("N", "expires_at").unwrap();
+    let late = node_with(&mut store, &[("expires_at", Value::Int(300))]);
+    let moved = node_with(&mut store, &[("expires_at", Value::Int(150))]);
+    let never = node_with(&mut store, &[("name", Value::String("keep".into()))]);
+    let text = node_with(&mut store, &[("expires_at", Value::String("100".into()))]);
+    store.add_edge(early, never, "AT".into(), HashMap::new()).unwrap();
+    store.add_edge(late, early, "AT".into(), HashMap::new()).unwrap();
+    store.set_node_property(moved, "expires_at".into(), Value::Int(250)).unwrap();
+    assert_eq!(store.ttl_indexes(), vec![("N".to_string(), "expires_at".to_string())]);
+
+    assert_eq!(store.expire(99).unwrap(), ExpiredReport::default());
+    assert_eq!(store.expire(200).unwrap(), ExpiredReport { nodes: 1, edges: 2 });
+    assert!(store.get_node(early).unwrap().is_none());
+    assert!(store.verify_indexes().is_ok());
+    assert_eq!(store.expire(300).unwrap(), ExpiredReport { nodes: 2, edges: 0 });
+    assert_eq!(store.expire(i64::MAX).unwrap(), ExpiredReport::default());
+    assert!(store.get_node(never).unwrap().is_some());
+    assert!(store.get_node(text).unwrap().is_some());
+
+    assert!(matches!(store.create_ttl_index("N", "expires_at"), Err(EngineError::InvalidArgument(_))));
+    assert!(store.drop_ttl_index("N", "expires_at").unwrap());
+    assert!(!store.drop_ttl_index("N", "expires_at").unwrap());
+}
+
+#[test]
+fn expire_tombstones_with_soft_delete() {
+    let mut store = soft_store();
+    store.create_ttl_index("N", "expires_at").unwrap();
+    let event = node_with(&mut store, &[("expires_at", Value::Int(5))]);
+    assert_eq!(store.expire(5).unwrap().nodes, 1);
+    assert_eq!(store.node_count().unwrap(), 0);
+    assert!(store.undelete_node(event).unwrap());
+    assert_eq!(store.expire(5).unwrap().nodes, 1);
+    assert!(store.verify_indexes().is_ok());
+}
@@ -0,0 +1,58 @@
+//! Tests for IdAllocator: monotonic allocation, block reservation and recycling
+
+use casys_engine::index::ids::IdAllocator;
+
+#[test]
+fn allocates_monotonically_without_reuse() {
+    let mut ids = IdAllocator::new(false);
+    assert_eq!(ids.next_id(), 1);
+    assert_eq!(ids.next_id(), 2);
+
+    ids.release(1);
+    assert!(ids.free_ids().is_empty(), "release is ignored when reuse is off");
+    assert_eq!(ids.next_id(), 3);
+}
+
+#[test]
+fn reserve_returns_contiguous_block_above_free_list() {
+    let mut ids = IdAllocator::new(true);
+    for _ in 0..3 {
+        ids.next_id();
+    }
+    ids.release(2);
+
+    assert_eq!(ids.reserve(4), 4..8);
+    assert_eq!(ids.high_water(), 8);
+    assert_eq!(ids.next_id(), 2, "free list is still served by next_id");
+}
+
+#[test]
+fn recycles_lowest_released_id_first() {
+    let mut ids = IdAllocator::new(true);
+    for _ in 0..5 {
+        ids.next_id();
+    }
+    ids.release(4);
+    ids.release(2);
+    ids.release(4);
+    assert_eq!(ids.free_ids(), vec![2, 4]);
+
+    assert_eq!(ids.next_id(), 2);
+    assert_eq!(ids.next_id(), 4);
+    assert_eq!(ids.next_id(), 6);
+}
+
+#[test]
+fn observe_claims_ids_and_bumps_high_water() {
+    let mut ids = IdAllocator::new(true);
+    ids.observe(10);
+    assert_eq!(ids.high_water(), 11);
+
+    ids.release(3);
+    ids.release(7);
+    ids.observe(3);
+    assert_eq!(ids.free_ids(), vec![7]);
+
+    ids.reset();
+    assert_eq!(ids.next_id(), 1);
+}
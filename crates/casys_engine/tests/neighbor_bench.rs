@@ -0,0 +1,45 @@
+//! Timing comparison of cloning vs id-only neighbor queries on a 1M-edge graph.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test neighbor_bench -- --ignored --nocapture`
+
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::{Direction, GraphReadStore, GraphWriteStore, Value};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[test]
+#[ignore]
+fn neighbor_ids_vs_get_neighbors_1m_edges() {
+    const NODES: u64 = 100_000;
+    const DEGREE: u64 = 10;
+    let mut store = InMemoryGraphStore::new();
+    let props = HashMap::from([
+        ("name".to_string(), Value::String("x".repeat(32))),
+        ("score".to_string(), Value::Float(0.5)),
+    ]);
+    let ids: Vec<u64> = (0..NODES).map(|_| store.add_node(vec!["P".into()], props.clone()).unwrap()).collect();
+    for (i, from) in ids.iter().enumerate() {
+        for k in 1..=DEGREE {
+            let to = ids[(i as u64 * 7 + k * 13) as usize % ids.len()];
+            store.add_edge(*from, to, "LINK".into(), HashMap::new()).unwrap();
+        }
+    }
+
+    let start = Instant::now();
+    let mut cloned = 0;
+    for id in &ids {
+        cloned += store.get_neighbors(*id, None).unwrap().len();
+    }
+    let cloning = start.elapsed();
+
+    let start = Instant::now();
+    let mut by_id = 0;
+    for id in &ids {
+        by_id += store.get_neighbor_ids(*id, None, Direction::Outgoing).unwrap().len();
+    }
+    let ids_only = start.elapsed();
+
+    assert_eq!(cloned, by_id);
+    println!("{} edges: get_neighbors {:?}, get_neighbor_ids {:?}", cloned, cloning, ids_only);
+}
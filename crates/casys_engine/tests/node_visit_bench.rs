@@ -0,0 +1,45 @@
+//! Timing comparison of cloning reads vs borrowing visitors on nodes with 10KB properties.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test node_visit_bench -- --ignored --nocapture`
+
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::{GraphReadStore, GraphWriteStore, Node, Value};
+use std::collections::HashMap;
+use std::time::Instant;
+
+fn body_len(node: &Node) -> usize {
+    match node.properties.get("body") {
+        Some(Value::String(s)) => s.len(),
+        _ => 0,
+    }
+}
+
+#[test]
+#[ignore]
+fn visitors_vs_cloning_reads_10kb_properties() {
+    const NODES: u64 = 20_000;
+    let mut store = InMemoryGraphStore::new();
+    let props = HashMap::from([("body".to_string(), Value::String("x".repeat(10 * 1024)))]);
+    let ids: Vec<u64> = (0..NODES).map(|_| store.add_node(vec!["Doc".into()], props.clone()).unwrap()).collect();
+
+    let start = Instant::now();
+    let mut cloned = 0;
+    for id in &ids {
+        cloned += store.get_node(*id).unwrap().map_or(0, |n| body_len(&n));
+    }
+    cloned += store.scan_by_label("Doc").unwrap().iter().map(body_len).sum::<usize>();
+    let cloning = start.elapsed();
+
+    let start = Instant::now();
+    let mut borrowed = 0;
+    for id in &ids {
+        borrowed += store.with_node(*id, body_len).unwrap().unwrap_or(0);
+    }
+    store.for_each_node_with_label("Doc", &mut |n| borrowed += body_len(n)).unwrap();
+    let visiting = start.elapsed();
+
+    assert_eq!(cloned, borrowed);
+    println!("{} nodes x 2 reads: get_node + scan_by_label {:?}, with_node + for_each_node_with_label {:?}",
+        NODES, cloning, visiting);
+}
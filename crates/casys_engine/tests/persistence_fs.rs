@@ -44,3 +44,426 @@ fn persistence_roundtrip_flush_load() {
 fn skip_persistence_roundtrip_without_fs() {
     // This test is a no-op when fs feature is not enabled
 }
+
+/// A flush that dies after staging nodes, before edges are renamed into place, leaves the
+/// previous flush to load
+#[cfg(feature = "fs")]
+#[test]
+fn crashed_flush_loads_previous_segments() {
+    use casys_core::{DatabaseName, EngineError, GraphReadStore, GraphWriteStore, SegmentId, SegmentStore};
+    use casys_engine::index::InMemoryGraphStore;
+    use casys_storage_fs::backend::FsBackend;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Stages like `FsBackend` but fails on the edge segment
+    struct CrashOnEdges(FsBackend);
+
+    impl SegmentStore for CrashOnEdges {
+        fn write_segment(&self, root: &Path, db: &DatabaseName, id: &SegmentId, data: &[u8], n: u64, e: u64) -> Result<(), EngineError> {
+            self.0.write_segment(root, db, id, data, n, e)
+        }
+
+        fn read_segment(&self, root: &Path, db: &DatabaseName, id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError> {
+            self.0.read_segment(root, db, id)
+        }
+
+        fn stage_segment_with(
+            &self,
+            root: &Path,
+            db: &DatabaseName,
+            id: &SegmentId,
+            n: u64,
+            e: u64,
+            write: &mut dyn FnMut(&mut dyn Write) -> std::io::Result<()>,
+        ) -> Result<(), EngineError> {
+            if id.0 == "edges" {
+                return Err(EngineError::StorageIo("crash".into()));
+            }
+            self.0.stage_segment_with(root, db, id, n, e, write)
+        }
+
+        fn publish_segments(&self, root: &Path, db: &DatabaseName, ids: &[SegmentId]) -> Result<(), EngineError> {
+            self.0.publish_segments(root, db, ids)
+        }
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("engine_fs_crash_{}", now));
+    fs::create_dir_all(&root).unwrap();
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    let mut graph = InMemoryGraphStore::new();
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.flush(&FsBackend::new(), &root, &db).unwrap();
+
+    // The second flush would pair three nodes with an edge to the third
+    let c = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_edge(b, c, "KNOWS".into(), HashMap::new()).unwrap();
+    assert!(graph.flush(&CrashOnEdges(FsBackend::new()), &root, &db).is_err());
+
+    let loaded = InMemoryGraphStore::load(&FsBackend::new(), &root, &db).unwrap();
+    assert_eq!(loaded.node_count().unwrap(), 2);
+    assert_eq!(loaded.edge_count().unwrap(), 1);
+
+    graph.flush(&FsBackend::new(), &root, &db).unwrap();
+    let loaded = InMemoryGraphStore::load(&FsBackend::new(), &root, &db).unwrap();
+    assert_eq!(loaded.node_count().unwrap(), 3);
+    assert_eq!(loaded.edge_count().unwrap(), 2);
+}
+
+/// Segments written before the format version, at their unversioned paths, migrate to
+/// binary segments published through the manifest
+#[cfg(feature = "fs")]
+#[test]
+fn migrate_segments_on_fs_rewrites_legacy_segments() {
+    use casys_core::{BranchName, DatabaseName, GraphReadStore};
+    use casys_engine::index::{InMemoryGraphStore, SegmentFormat};
+    use casys_storage_fs::catalog::branch_dir;
+    use casys_storage_fs::segments::{current_segment_path, segment_path, Segment};
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("engine_fs_migrate_{}", now));
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let segments_root = branch_dir(&root, &db, &branch);
+    let legacy = [
+        ("nodes", include_bytes!("fixtures/segments_v0/nodes.json").to_vec(), 3, 0),
+        ("edges", include_bytes!("fixtures/segments_v0/edges.json").to_vec(), 0, 2),
+    ];
+    for (id, data, nodes, edges) in legacy {
+        let path = segment_path(&segments_root, &db, id);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        Segment::new(nodes, edges, data).write_to_path(&path).unwrap();
+    }
+
+    let binary = SegmentFormat::Binary.version();
+    assert_eq!(InMemoryGraphStore::migrate_segments_on_fs(&root, &db, &branch, binary).unwrap(), Some(0));
+    for id in ["nodes", "edges"] {
+        let path = current_segment_path(&segments_root, &db, id).unwrap();
+        assert_ne!(path, segment_path(&segments_root, &db, id));
+        assert_eq!(&Segment::read_from_path(&path).unwrap().data[..4], b"CSGB");
+    }
+    let loaded = InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap();
+    assert_eq!(loaded.node_count().unwrap(), 3);
+    assert_eq!(loaded.get_edge(2).unwrap().unwrap().edge_type, "LIVES_IN");
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn flush_incremental_to_fs_publishes_deltas() {
+    use casys_core::{BranchName, DatabaseName, GraphReadStore, GraphWriteStore, Value};
+    use casys_engine::index::InMemoryGraphStore;
+    use casys_storage_fs::catalog::branch_dir;
+    use casys_storage_fs::segments::current_segment_path;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("engine_fs_delta_{}", now));
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let segments_root = branch_dir(&root, &db, &branch);
+
+    let mut graph = InMemoryGraphStore::new();
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    graph.set_node_property(a, "name".into(), Value::String("Ada".into())).unwrap();
+    graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    for id in ["nodes-000001", "edges-000001", "nodes-000002", "deltas"] {
+        assert!(current_segment_path(&segments_root, &db, id).unwrap().exists(), "{} not published", id);
+    }
+
+    let loaded = InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap();
+    assert_eq!(loaded.get_neighbors(a, Some("KNOWS")).unwrap()[0].1.id, b);
+    assert_eq!(loaded.get_node(a).unwrap().unwrap().properties["name"], Value::String("Ada".into()));
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// Live nodes and edges of `graph` by id, for comparing two stores
+#[cfg(feature = "fs")]
+#[allow(clippy::type_complexity)]
+fn rows(graph: &casys_engine::index::InMemoryGraphStore) -> (Vec<(u64, Vec<String>, String)>, Vec<(u64, u64, u64, String)>) {
+    use casys_core::GraphReadStore;
+    let mut nodes: Vec<_> = graph.scan_all().unwrap().into_iter()
+        .map(|n| (n.id, n.labels, format!("{:?}", { let mut p: Vec<_> = n.properties.into_iter().collect(); p.sort_by(|a, b| a.0.cmp(&b.0)); p })))
+        .collect();
+    nodes.sort();
+    let mut edges: Vec<_> = graph.scan_all_edges().unwrap().into_iter()
+        .map(|e| (e.id, e.from_node, e.to_node, e.edge_type))
+        .collect();
+    edges.sort();
+    (nodes, edges)
+}
+
+/// A branch holding a base, deltas a full flush dropped, a listed delta, and WAL records
+/// after all of them. Returns the branch directory and the rows it holds.
+#[cfg(feature = "fs")]
+#[allow(clippy::type_complexity)]
+fn branch_with_deltas(
+    name: &str,
+) -> (std::path::PathBuf, std::path::PathBuf, (Vec<(u64, Vec<String>, String)>, Vec<(u64, u64, u64, String)>)) {
+    use casys_core::{BranchName, DatabaseName, GraphWriteStore, Value};
+    use casys_engine::index::persistence::{SyncPolicy, WalWriter};
+    use casys_engine::index::InMemoryGraphStore;
+    use casys_storage_fs::catalog::branch_dir;
+    use std::collections::HashMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("engine_fs_{}_{}", name, now));
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let dir = branch_dir(&root, &db, &branch);
+
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let ids: Vec<u64> = (0..50)
+        .map(|i| graph.add_node(vec!["Person".into()], HashMap::from([("n".to_string(), Value::Int(i))])).unwrap())
+        .collect();
+    for pair in ids.windows(2) {
+        graph.add_edge(pair[0], pair[1], "NEXT".into(), HashMap::new()).unwrap();
+    }
+    graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    for round in 0..2 {
+        for id in &ids[..10] {
+            graph.set_node_property(*id, "n".into(), Value::Int(round)).unwrap();
+        }
+        graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    }
+    // Drops deltas 1 and 2 from the delta manifest, not from the segment manifest
+    graph.flush_to_fs(&root, &db, &branch).unwrap();
+    graph.delete_node(ids[0], true).unwrap();
+    graph.add_node(vec!["City".into()], HashMap::new()).unwrap();
+    graph.flush_incremental_to_fs(&root, &db, &branch).unwrap();
+    // Only in the WAL
+    graph.set_node_property(ids[1], "n".into(), Value::Int(-1)).unwrap();
+    graph.add_edge(ids[1], ids[3], "SKIP".into(), HashMap::new()).unwrap();
+    graph.sync_wal().unwrap();
+    (root, dir, rows(&graph))
+}
+
+#[cfg(feature = "fs")]
+#[test]
+fn compact_segments_on_fs_folds_deltas_and_the_wal() {
+    use casys_core::{BranchName, DatabaseName};
+    use casys_engine::index::persistence::wal_dir;
+    use casys_engine::index::{InMemoryGraphStore, StoreOptions};
+    use casys_storage_fs::segments::{read_segment_manifest, segment_usage};
+
+    let (root, dir, expected) = branch_with_deltas("compact");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let listed = read_segment_manifest(&dir, &db).unwrap().segments;
+    assert!(["nodes-000001", "nodes-000002", "nodes-000003"].iter().all(|id| listed.contains_key(*id)));
+
+    let report = InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert_eq!(report.wal_records_folded, 2);
+    // 50 nodes, 49 edges: one went with the deleted node, one came from the WAL
+    assert_eq!(report.records_after, 50 + 49);
+    assert!(report.records_before > report.records_after, "{:?}", report);
+    assert!(report.files_after < report.files_before, "{:?}", report);
+    assert!(report.bytes_after < report.bytes_before, "{:?}", report);
+    assert!(report.wal_bytes_after < report.wal_bytes_before, "{:?}", report);
+
+    // Only the current segments and the WAL file the checkpoint started are left
+    let manifest = read_segment_manifest(&dir, &db).unwrap();
+    assert!(manifest.segments.keys().all(|id| !id.contains('-')), "{:?}", manifest.segments);
+    let usage = segment_usage(&dir, &db).unwrap();
+    assert_eq!(usage.files, manifest.segments.len() as u64);
+    assert_eq!((usage.files, usage.bytes), (report.files_after, report.bytes_after));
+    assert_eq!(std::fs::read_dir(wal_dir(&dir)).unwrap().count(), 2, "the WAL manifest and the new file");
+
+    assert_eq!(rows(&InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap()), expected);
+    let recovered = InMemoryGraphStore::recover_from_fs(&root, &db, &branch, StoreOptions::default()).unwrap();
+    assert_eq!(rows(&recovered), expected);
+
+    // Compacting a compacted branch rewrites the same state
+    let again = InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert_eq!((again.files_before, again.records_before), (again.files_after, again.records_after));
+    assert_eq!(again.wal_records_folded, 0);
+    assert_eq!(rows(&InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap()), expected);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// A compaction cut short at any point leaves a branch that loads as before, and the next
+/// one finishes the job
+#[cfg(feature = "fs")]
+#[test]
+fn compaction_cut_short_leaves_a_loadable_branch() {
+    use casys_core::{BranchName, DatabaseName, EngineError, SegmentId, SegmentStore};
+    use casys_engine::index::persistence::wal_dir;
+    use casys_engine::index::{InMemoryGraphStore, StoreOptions};
+    use casys_storage_fs::backend::FsBackend;
+    use casys_storage_fs::segments::{read_segment_manifest, segment_usage, stage_segment_with};
+    use std::collections::BTreeMap;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    /// Stages like `FsBackend` but fails on the statistics segment, the last one of a flush
+    struct CrashOnStatistics(FsBackend);
+
+    impl SegmentStore for CrashOnStatistics {
+        fn write_segment(&self, root: &Path, db: &DatabaseName, id: &SegmentId, data: &[u8], n: u64, e: u64) -> Result<(), EngineError> {
+            self.0.write_segment(root, db, id, data, n, e)
+        }
+
+        fn read_segment(&self, root: &Path, db: &DatabaseName, id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError> {
+            self.0.read_segment(root, db, id)
+        }
+
+        fn stage_segment_with(
+            &self,
+            root: &Path,
+            db: &DatabaseName,
+            id: &SegmentId,
+            n: u64,
+            e: u64,
+            write: &mut dyn FnMut(&mut dyn Write) -> std::io::Result<()>,
+        ) -> Result<(), EngineError> {
+            if id.0 == "statistics" {
+                return Err(EngineError::StorageIo("crash".into()));
+            }
+            self.0.stage_segment_with(root, db, id, n, e, write)
+        }
+
+        fn publish_segments(&self, root: &Path, db: &DatabaseName, ids: &[SegmentId]) -> Result<(), EngineError> {
+            self.0.publish_segments(root, db, ids)
+        }
+    }
+
+    /// Every file under `dir` with its contents
+    fn snapshot(dir: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut files = BTreeMap::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(snapshot(&path));
+            } else {
+                files.insert(path.clone(), std::fs::read(&path).unwrap());
+            }
+        }
+        files
+    }
+
+    let (root, dir, expected) = branch_with_deltas("compact_crash");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let recover = || rows(&InMemoryGraphStore::recover_from_fs(&root, &db, &branch, StoreOptions::default()).unwrap());
+    let segments = dir.join(db.as_str()).join("segments");
+
+    // Cut short while the new base is staged: the new nodes and edges are written, but
+    // the segments read are still the old ones, deltas included
+    let before = snapshot(&segments);
+    assert!(InMemoryGraphStore::compact_segments(&CrashOnStatistics(FsBackend::new()), &dir, &db).is_err());
+    assert_eq!(recover(), expected);
+    assert!(snapshot(&segments).len() > before.len(), "the staged generation is left behind");
+    // So is a segment write cut short before its rename
+    let crashed = stage_segment_with(&dir, &db, "edges", 0, 0, &mut |out| {
+        out.write_all(b"edg")?;
+        Err(std::io::Error::other("crash"))
+    });
+    assert!(crashed.is_err());
+    assert_eq!(recover(), expected);
+
+    // Cut short once the new base is published, before the checkpoint: the segment
+    // manifest still lists the deltas the new base folded in, the WAL is untouched
+    let before = snapshot(&dir);
+    InMemoryGraphStore::compact_segments(&FsBackend::new(), &dir, &db).unwrap();
+    assert!(read_segment_manifest(&dir, &db).unwrap().segments.contains_key("nodes-000003"));
+    assert_eq!(recover(), expected);
+
+    // Cut short after the checkpoint, before the WAL manifest is replaced and before any
+    // superseded segment is deleted: the old WAL files come back as they were, and so
+    // does every segment file compaction removed, though the segment manifest it
+    // rewrote lists none of them
+    let report = InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert_eq!(report.files_after, segment_usage(&dir, &db).unwrap().files);
+    let wal = wal_dir(&dir);
+    for (path, data) in &before {
+        if path.starts_with(&wal) || !path.exists() {
+            std::fs::write(path, data).unwrap();
+        }
+    }
+    let listed = read_segment_manifest(&dir, &db).unwrap().segments.len() as u64;
+    assert_eq!(listed, report.files_after);
+    assert!(segment_usage(&dir, &db).unwrap().files > listed);
+    assert_eq!(recover(), expected);
+    assert_eq!(rows(&InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap()), expected);
+
+    // The next compaction removes what the cut-short ones left
+    let report = InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert_eq!((report.files_after, segment_usage(&dir, &db).unwrap().files), (listed, listed));
+    assert_eq!(report.wal_records_folded, 0);
+    assert!(report.wal_bytes_after < report.wal_bytes_before);
+    assert_eq!(recover(), expected);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Partitions load by label from the branch, and compaction deletes the ones no flush
+/// lists any more
+#[cfg(feature = "fs")]
+#[test]
+fn load_labels_from_fs_and_compaction_of_partitions() {
+    use casys_core::{BranchName, DatabaseName, GraphReadStore, GraphWriteStore};
+    use casys_engine::index::{InMemoryGraphStore, PartialEdges, StoreOptions};
+    use casys_storage_fs::catalog::branch_dir;
+    use casys_storage_fs::segments::{read_segment_manifest, segment_usage};
+    use std::collections::HashMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("engine_fs_partitions_{}", now));
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let branch = BranchName::try_from("main").unwrap();
+    let dir = branch_dir(&root, &db, &branch);
+    let partitions = |dir: &std::path::Path| -> Vec<String> {
+        read_segment_manifest(dir, &db).unwrap().segments.into_keys().filter(|id| id.starts_with("nodes-")).collect()
+    };
+
+    let mut graph = InMemoryGraphStore::with_options(StoreOptions { partition_nodes_by_label: true, ..Default::default() });
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let city = graph.add_node(vec!["City".into()], HashMap::new()).unwrap();
+    let pet = graph.add_node(vec!["Pet".into()], HashMap::new()).unwrap();
+    graph.add_edge(a, city, "LIVES_IN".into(), HashMap::new()).unwrap();
+    graph.flush_to_fs(&root, &db, &branch).unwrap();
+    assert_eq!(partitions(&dir).len(), 4, "three labels and the unlabeled partition");
+
+    let people = InMemoryGraphStore::load_labels_from_fs(&root, &db, &branch, &["Person"], PartialEdges::Stubs, StoreOptions::default()).unwrap();
+    assert_eq!(people.node_count().unwrap(), 2);
+    assert!(people.is_stub(city));
+    assert_eq!(people.get_neighbors(a, Some("LIVES_IN")).unwrap()[0].1.id, city);
+
+    // The Pet partition stays current in the segment store until compaction retires it
+    graph.delete_node(pet, false).unwrap();
+    graph.flush_to_fs(&root, &db, &branch).unwrap();
+    assert_eq!(partitions(&dir).len(), 4);
+    InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert_eq!(partitions(&dir).len(), 3);
+    assert_eq!(segment_usage(&dir, &db).unwrap().files, read_segment_manifest(&dir, &db).unwrap().segments.len() as u64);
+    assert_eq!(rows(&InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap()), rows(&graph));
+
+    // Unpartitioned, the partitions all go
+    InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap().flush_to_fs(&root, &db, &branch).unwrap();
+    InMemoryGraphStore::compact_segments_on_fs(&root, &db, &branch).unwrap();
+    assert!(partitions(&dir).is_empty());
+    assert_eq!(rows(&InMemoryGraphStore::load_from_fs(&root, &db, &branch).unwrap()), rows(&graph));
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -38,6 +38,10 @@ impl MockSegmentStore {
     fn has_segment(&self, segment_id: &str) -> bool {
         self.segments.lock().expect("segments mutex poisoned").contains_key(segment_id)
     }
+
+    fn segment(&self, segment_id: &str) -> Vec<u8> {
+        self.segments.lock().expect("segments mutex poisoned")[segment_id].clone()
+    }
 }
 
 impl SegmentStore for MockSegmentStore {
@@ -90,9 +94,11 @@ fn flush_calls_write_segment() {
     graph.flush(&store, root, &db).unwrap();
 
     // Verify write_segment was called for nodes and edges
-    assert_eq!(store.get_write_count(), 2, "Should write 2 segments (nodes, edges)");
+    assert_eq!(store.get_write_count(), 4, "Should write 4 segments (nodes, edges, indexes, statistics)");
     assert!(store.has_segment("nodes"), "Should have nodes segment");
     assert!(store.has_segment("edges"), "Should have edges segment");
+    assert!(store.has_segment("indexes"), "Should have indexes segment");
+    assert!(store.has_segment("statistics"), "Should have statistics segment");
 }
 
 /// Test that load() calls read_segment for nodes and edges (AC3, AC5)
@@ -117,7 +123,7 @@ fn load_calls_read_segment() {
     let _loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
 
     // Verify read_segment was called
-    assert_eq!(store.get_read_count(), 2, "Should read 2 segments (nodes, edges)");
+    assert_eq!(store.get_read_count(), 6, "Should read 6 segments (partition manifest, nodes, edges, delta manifest, indexes, statistics)");
 }
 
 /// Test round-trip: flush then load preserves data integrity (AC5)
@@ -169,6 +175,728 @@ fn roundtrip_data_integrity() {
     assert_eq!(target.id, id2);
 }
 
+/// Test that both segment formats round-trip records, tombstones and allocator state, and
+/// that binary segments also keep the values JSON cannot
+#[test]
+fn roundtrip_across_segment_formats() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::persistence::WalRecord;
+    use engine::index::{InMemoryGraphStore, SegmentFormat, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let build = |format| {
+        let options = StoreOptions { soft_delete: true, reuse_ids: true, segment_format: format, ..Default::default() };
+        let mut graph = InMemoryGraphStore::with_options(options);
+        let props = HashMap::from([
+            ("name".to_string(), Value::String("Ann".into())),
+            ("tags".to_string(), Value::Array(vec![Value::Int(1), Value::Bool(true), Value::Null])),
+            ("score".to_string(), Value::Float(0.5)),
+        ]);
+        let a = graph.add_node(vec!["Person".into(), "Admin".into()], props.clone()).unwrap();
+        let b = graph.add_node(vec![], HashMap::new()).unwrap();
+        let c = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+        graph.add_edge(a, b, "KNOWS".into(), props).unwrap();
+        let e = graph.add_edge(b, a, "KNOWS".into(), HashMap::new()).unwrap();
+        graph.delete_node(c, true).unwrap();
+        graph.delete_edge(e).unwrap();
+        graph.purge_tombstones();
+        graph.add_node(vec!["Gone".into()], HashMap::new()).unwrap();
+        let t = graph.add_node(vec!["Tombstoned".into()], HashMap::new()).unwrap();
+        graph.delete_node(t, true).unwrap();
+        graph.replay_wal_from(&[(5, WalRecord::PurgeTombstones)], 0).unwrap();
+        graph
+    };
+
+    for format in [SegmentFormat::Json, SegmentFormat::Binary] {
+        let graph = build(format);
+        let store = MockSegmentStore::new();
+        graph.flush(&store, root, &db).unwrap();
+        let first = store.segment("nodes")[0];
+        assert_eq!(first == b'{', format == SegmentFormat::Json, "{:?}", format);
+
+        let mut loaded = InMemoryGraphStore::load_with_options(&store, root, &db, graph.options().clone()).unwrap();
+        assert_eq!(loaded.last_applied_lsn(), 5);
+        for id in 1..=6 {
+            let (expected, found) = (graph.get_node_including_deleted(id).unwrap(), loaded.get_node_including_deleted(id).unwrap());
+            assert_eq!(expected.is_some(), found.is_some(), "node {} ({:?})", id, format);
+            if let (Some(expected), Some(found)) = (expected, found) {
+                assert!(expected.semantically_equals(&found), "{:?} vs {:?}", expected, found);
+            }
+            let (expected, found) = (graph.get_edge_including_deleted(id).unwrap(), loaded.get_edge_including_deleted(id).unwrap());
+            assert_eq!(expected.map(|e| e.id), found.map(|e| e.id), "edge {} ({:?})", id, format);
+        }
+        // The purged ids are handed out again, the same way
+        let mut fresh = build(format);
+        assert_eq!(loaded.add_node(vec![], HashMap::new()).unwrap(), fresh.add_node(vec![], HashMap::new()).unwrap());
+        assert_eq!(loaded.add_edge(1, 2, "T".into(), HashMap::new()).unwrap(), fresh.add_edge(1, 2, "T".into(), HashMap::new()).unwrap());
+    }
+
+    // Values only the binary layout keeps exactly
+    let exact = HashMap::from([
+        ("bytes".to_string(), Value::Bytes(vec![0, 255])),
+        ("ref".to_string(), Value::NodeId(7)),
+        ("nan".to_string(), Value::Float(f64::NAN)),
+    ]);
+    let mut graph = InMemoryGraphStore::new();
+    let id = graph.add_node(vec!["N".into()], exact.clone()).unwrap();
+    let store = MockSegmentStore::new();
+    graph.flush(&store, root, &db).unwrap();
+    let loaded = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(loaded.get_node_including_deleted(id).unwrap().unwrap().properties, exact);
+}
+
+/// Test that a binary segment cut short or in the wrong slot fails the load
+#[test]
+fn load_rejects_damaged_binary_segments() {
+    use casys_core::GraphWriteStore;
+    use engine::index::InMemoryGraphStore;
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = InMemoryGraphStore::new();
+    let a = graph.add_node(vec!["N".into()], HashMap::new()).unwrap();
+    graph.add_edge(a, a, "SELF".into(), HashMap::new()).unwrap();
+    let store = MockSegmentStore::new();
+    graph.flush(&store, root, &db).unwrap();
+    let nodes = store.segment("nodes");
+    let edges = store.segment("edges");
+
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), &nodes[..nodes.len() - 1], 1, 0).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => assert_eq!((path.as_str(), detail.as_str()), ("nodes", "segment cut short")),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), &nodes, 1, 0).unwrap();
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), &nodes, 0, 1).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => {
+            assert_eq!(path, "edges");
+            assert!(detail.contains("record kind 1, expected 2"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), &edges, 0, 1).unwrap();
+    assert!(InMemoryGraphStore::load(&store, root, &db).is_ok());
+}
+
+/// Test that a flipped byte anywhere in a binary node or edge segment fails the load with
+/// `SegmentCorruption` naming the segment, and that JSON records without ids do too
+#[test]
+fn load_reports_corrupt_segments() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::InMemoryGraphStore;
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = InMemoryGraphStore::new();
+    let props = HashMap::from([("name".to_string(), Value::String("Ann".into()))]);
+    let a = graph.add_node(vec!["Person".into()], props.clone()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], props.clone()).unwrap();
+    graph.add_edge(a, b, "KNOWS".into(), props).unwrap();
+    let store = MockSegmentStore::new();
+    graph.flush(&store, root, &db).unwrap();
+
+    for segment in ["nodes", "edges"] {
+        let good = store.segment(segment);
+        // Past the magic, which would make the segment read as JSON
+        for at in 4..good.len() {
+            let mut bad = good.clone();
+            bad[at] ^= 0x01;
+            store.write_segment(root, &db, &SegmentId(segment.to_string()), &bad, 0, 0).unwrap();
+            match InMemoryGraphStore::load(&store, root, &db) {
+                Err(EngineError::SegmentCorruption { path, .. }) => assert_eq!(path, segment, "byte {}", at),
+                other => panic!("{} byte {}: unexpected result {:?}", segment, at, other.map(|_| ())),
+            }
+        }
+        store.write_segment(root, &db, &SegmentId(segment.to_string()), &good, 0, 0).unwrap();
+    }
+    assert!(InMemoryGraphStore::load(&store, root, &db).is_ok());
+
+    // A record without its id is an error, not node 0
+    let nodes = br#"{"count":1,"nodes":[{"labels":["Person"],"properties":{}}]}"#;
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), nodes, 1, 0).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => {
+            assert_eq!(path, "nodes");
+            assert!(detail.contains("`id`"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    let nodes = br#"{"count":1,"nodes":[{"id":1,"labels":[],"properties":{}}]}"#;
+    let edges = br#"{"count":1,"edges":[{"id":1,"from":1,"type":"T","properties":{}}]}"#;
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), nodes, 1, 0).unwrap();
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), edges, 0, 1).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => {
+            assert_eq!(path, "edges");
+            assert!(detail.contains("`to`"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Test that compressed segments round-trip, come out smaller, and fail the load with
+/// `SegmentCorruption` when damaged anywhere
+#[cfg(feature = "compression")]
+#[test]
+fn roundtrip_compressed_segments() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::{CompressionOption, InMemoryGraphStore, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    // Spans several compressed blocks
+    let mut graph = InMemoryGraphStore::new();
+    for i in 0..20_000u64 {
+        let props = HashMap::from([
+            ("name".to_string(), Value::String(format!("user-{}", i))),
+            ("city".to_string(), Value::String(["Paris", "Lyon", "Oslo"][(i % 3) as usize].to_string())),
+        ]);
+        graph.add_node(vec!["Person".into()], props).unwrap();
+    }
+    for i in 1..20_000u64 {
+        graph.add_edge(i, i + 1, "NEXT".into(), HashMap::from([("at".to_string(), Value::Int(i as i64))])).unwrap();
+    }
+    let plain = MockSegmentStore::new();
+    graph.flush(&plain, root, &db).unwrap();
+
+    for compression in [CompressionOption::Fast, CompressionOption::Best] {
+        let options = StoreOptions { segment_compression: compression, ..Default::default() };
+        let mut compressed = InMemoryGraphStore::with_options(options.clone());
+        for id in 1..=20_000u64 {
+            let node = graph.get_node(id).unwrap().unwrap();
+            compressed.add_node(node.labels, node.properties).unwrap();
+        }
+        for id in 1..20_000u64 {
+            let edge = graph.get_edge(id).unwrap().unwrap();
+            compressed.add_edge(edge.from_node, edge.to_node, edge.edge_type, edge.properties).unwrap();
+        }
+        let store = MockSegmentStore::new();
+        compressed.flush(&store, root, &db).unwrap();
+        for segment in ["nodes", "edges"] {
+            let (size, plain_size) = (store.segment(segment).len(), plain.segment(segment).len());
+            assert!(size * 2 < plain_size, "{:?} {}: {} bytes, {} uncompressed", compression, segment, size, plain_size);
+        }
+        // Loading needs no options to decompress
+        let loaded = InMemoryGraphStore::load(&store, root, &db).unwrap();
+        for id in [1, 9_999, 20_000] {
+            assert!(loaded.get_node(id).unwrap().unwrap().semantically_equals(&graph.get_node(id).unwrap().unwrap()));
+        }
+        assert!(loaded.get_edge(19_999).unwrap().unwrap().semantically_equals(&graph.get_edge(19_999).unwrap().unwrap()));
+    }
+
+    let options = StoreOptions { segment_compression: CompressionOption::Fast, ..Default::default() };
+    let mut small = InMemoryGraphStore::with_options(options);
+    let props = HashMap::from([("name".to_string(), Value::String("Ann".into()))]);
+    let a = small.add_node(vec!["Person".into()], props.clone()).unwrap();
+    let b = small.add_node(vec!["Person".into()], props.clone()).unwrap();
+    small.add_edge(a, b, "KNOWS".into(), props).unwrap();
+    let store = MockSegmentStore::new();
+    small.flush(&store, root, &db).unwrap();
+    for segment in ["nodes", "edges"] {
+        let good = store.segment(segment);
+        for at in 4..good.len() {
+            let mut bad = good.clone();
+            bad[at] ^= 0x01;
+            store.write_segment(root, &db, &SegmentId(segment.to_string()), &bad, 0, 0).unwrap();
+            match InMemoryGraphStore::load(&store, root, &db) {
+                Err(EngineError::SegmentCorruption { path, .. }) => assert_eq!(path, segment, "byte {}", at),
+                other => panic!("{} byte {}: unexpected result {:?}", segment, at, other.map(|_| ())),
+            }
+        }
+        store.write_segment(root, &db, &SegmentId(segment.to_string()), &good, 0, 0).unwrap();
+    }
+    assert!(InMemoryGraphStore::load(&store, root, &db).is_ok());
+}
+
+/// Test that a build without the `compression` feature refuses to write or read
+/// compressed segments, saying why
+#[cfg(not(feature = "compression"))]
+#[test]
+fn compressed_segments_need_the_compression_feature() {
+    use casys_core::GraphWriteStore;
+    use engine::index::{CompressionOption, InMemoryGraphStore, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let store = MockSegmentStore::new();
+    let options = StoreOptions { segment_compression: CompressionOption::Best, ..Default::default() };
+    let mut graph = InMemoryGraphStore::with_options(options);
+    graph.add_node(vec!["N".into()], HashMap::new()).unwrap();
+    match graph.flush(&store, root, &db) {
+        Err(EngineError::NotImplemented(detail)) => assert!(detail.contains("compiled without compression support"), "{}", detail),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(store.get_write_count(), 0);
+
+    // Magic, version 3 and the compression byte of zstd blocks, then a block
+    let mut nodes = b"CSGB".to_vec();
+    nodes.extend_from_slice(&3u32.to_le_bytes());
+    nodes.push(b'Z');
+    nodes.extend_from_slice(&[4, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0x40, 1, 0, 0, 0]);
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), &nodes, 0, 0).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::NotImplemented(detail)) => {
+            assert!(detail.contains("segment nodes is compressed"), "{}", detail);
+            assert!(detail.contains("compiled without compression support"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Test that compression is refused for JSON segments, which are always written plain
+#[test]
+fn json_segments_are_not_compressed() {
+    use engine::index::{CompressionOption, InMemoryGraphStore, SegmentFormat, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let store = MockSegmentStore::new();
+    let options = StoreOptions { segment_format: SegmentFormat::Json, segment_compression: CompressionOption::Fast, ..Default::default() };
+    match InMemoryGraphStore::with_options(options).flush(&store, root, &db) {
+        Err(EngineError::InvalidArgument(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert_eq!(store.get_write_count(), 0);
+}
+
+/// Headerless JSON segments as written before segments carried a format version
+const LEGACY_NODES: &[u8] = include_bytes!("fixtures/segments_v0/nodes.json");
+const LEGACY_EDGES: &[u8] = include_bytes!("fixtures/segments_v0/edges.json");
+
+/// The format version a segment starts with: the u32 after the magic, or 0 for JSON
+fn segment_version(data: &[u8]) -> u32 {
+    match data {
+        [b'C', b'S', b'G', b'B', v0, v1, v2, v3, ..] => u32::from_le_bytes([*v0, *v1, *v2, *v3]),
+        _ => 0,
+    }
+}
+
+/// Test that legacy headerless segments load as version 0 and migrate to binary and back
+#[test]
+fn migrate_segments_rewrites_legacy_segments() {
+    use casys_core::{GraphReadStore, Value};
+    use engine::index::{InMemoryGraphStore, SegmentFormat};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let store = MockSegmentStore::new();
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), LEGACY_NODES, 3, 0).unwrap();
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), LEGACY_EDGES, 0, 2).unwrap();
+
+    let legacy = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(legacy.node_count().unwrap(), 3);
+    assert_eq!(legacy.edge_count().unwrap(), 2);
+    let alice = legacy.get_node(1).unwrap().unwrap();
+    assert_eq!(alice.labels, vec!["Person".to_string()]);
+    assert_eq!(alice.properties["age"], Value::Int(34));
+    let knows = legacy.get_edge(1).unwrap().unwrap();
+    assert_eq!((knows.from_node, knows.to_node, knows.edge_type.as_str()), (1, 2, "KNOWS"));
+
+    let binary = SegmentFormat::Binary.version();
+    assert_eq!(InMemoryGraphStore::migrate_segments(&store, root, &db, binary).unwrap(), Some(0));
+    for segment in ["nodes", "edges"] {
+        assert_eq!(segment_version(&store.segment(segment)), binary, "{}", segment);
+    }
+    let migrated = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    for id in 1..=3 {
+        assert!(migrated.get_node(id).unwrap().unwrap().semantically_equals(&legacy.get_node(id).unwrap().unwrap()));
+    }
+    for id in 1..=2 {
+        assert!(migrated.get_edge(id).unwrap().unwrap().semantically_equals(&legacy.get_edge(id).unwrap().unwrap()));
+    }
+
+    assert_eq!(InMemoryGraphStore::migrate_segments(&store, root, &db, 0).unwrap(), Some(binary));
+    assert_eq!(segment_version(&store.segment("nodes")), 0);
+    assert_eq!(InMemoryGraphStore::load(&store, root, &db).unwrap().node_count().unwrap(), 3);
+}
+
+/// Test that migrating to a version without a writer, or with no segments, writes nothing
+#[test]
+fn migrate_segments_rejects_unknown_versions() {
+    use engine::index::InMemoryGraphStore;
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let store = MockSegmentStore::new();
+    assert_eq!(InMemoryGraphStore::migrate_segments(&store, root, &db, 0).unwrap(), None);
+    assert_eq!(store.get_write_count(), 0);
+
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), LEGACY_NODES, 3, 0).unwrap();
+    for version in [1, 99] {
+        match InMemoryGraphStore::migrate_segments(&store, root, &db, version) {
+            Err(EngineError::InvalidArgument(_)) => {}
+            other => panic!("version {}: unexpected result {:?}", version, other),
+        }
+    }
+    assert_eq!(store.segment("nodes"), LEGACY_NODES);
+
+    // A segment from a later version is refused rather than misread
+    let mut future = b"CSGB".to_vec();
+    future.extend_from_slice(&99u32.to_le_bytes());
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), &future, 0, 0).unwrap();
+    match InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => {
+            assert_eq!(path, "nodes");
+            assert!(detail.contains("version 99"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Test that flush_incremental writes only the changes, as deltas load applies in order
+#[test]
+fn flush_incremental_writes_deltas_load_applies() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    let ids: Vec<u64> = (0..100)
+        .map(|i| graph.add_node(vec!["Person".to_string()], HashMap::from([("n".to_string(), Value::Int(i))])).unwrap())
+        .collect();
+    let knows = graph.add_edge(ids[0], ids[1], "KNOWS".to_string(), HashMap::new()).unwrap();
+    // Without segments to add to, the first one is a full flush
+    graph.flush_incremental(&store, root, &db).unwrap();
+    assert!(!store.has_segment("deltas"));
+    let base = store.segment("nodes");
+
+    graph.set_node_property(ids[5], "n".to_string(), Value::Int(-5)).unwrap();
+    let city = graph.add_node(vec!["City".to_string()], HashMap::new()).unwrap();
+    let lives_in = graph.add_edge(ids[5], city, "LIVES_IN".to_string(), HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    assert!(store.segment("nodes-000001").len() < base.len() / 10, "a delta holds the changed records only");
+
+    graph.delete_node(ids[0], true).unwrap();
+    graph.set_edge_type(lives_in, "BORN_IN".to_string()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    assert_eq!(store.segment("nodes"), base, "the base is left as it was");
+    assert!(store.has_segment("edges-000002"));
+
+    let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(loaded.get_edge(knows).unwrap().is_none());
+    assert_eq!(loaded.scan_by_label("Person").unwrap().len(), 99);
+    assert_eq!(loaded.get_neighbors(ids[5], Some("BORN_IN")).unwrap()[0].1.id, city);
+    assert_eq!(loaded.scan_by_property(None, "n", &Value::Int(-5)).unwrap()[0].id, ids[5]);
+}
+
+/// Test that modifying, deleting, then re-adding the same id across deltas loads the last write
+#[test]
+fn deltas_modify_delete_and_re_add_the_same_id() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::StoreOptions;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let options = StoreOptions { reuse_ids: true, ..Default::default() };
+
+    let mut graph = engine::index::InMemoryGraphStore::with_options(options.clone());
+    let anchor = graph.add_node(vec!["Anchor".to_string()], HashMap::new()).unwrap();
+    let node = graph.add_node(vec!["Old".to_string()], HashMap::from([("v".to_string(), Value::Int(0))])).unwrap();
+    let edge = graph.add_edge(anchor, node, "OLD".to_string(), HashMap::new()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options.clone()).unwrap();
+
+    // Delta 1: modify
+    graph.set_node_property(node, "v".to_string(), Value::Int(1)).unwrap();
+    graph.update_edge_properties(edge, HashMap::from([("w".to_string(), Value::Int(1))])).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    // Delta 2: delete
+    graph.delete_node(node, true).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    let loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options.clone()).unwrap();
+    assert!(loaded.get_node(node).unwrap().is_none());
+    assert!(loaded.get_edge(edge).unwrap().is_none());
+    assert!(loaded.get_neighbors(anchor, None).unwrap().is_empty());
+    // Delta 3: re-add under the same ids, reused from the free list
+    let again = graph.add_node(vec!["New".to_string()], HashMap::new()).unwrap();
+    let edge_again = graph.add_edge(again, anchor, "NEW".to_string(), HashMap::new()).unwrap();
+    assert_eq!((again, edge_again), (node, edge));
+    graph.flush_incremental(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(loaded.scan_by_label("Old").unwrap().is_empty(), "the old labels are unindexed");
+    assert_eq!(loaded.scan_by_label("New").unwrap().len(), 1);
+    assert!(loaded.get_neighbors(anchor, None).unwrap().is_empty(), "the old edge leaves no adjacency behind");
+    assert_eq!(loaded.get_neighbors_incoming(anchor, Some("NEW")).unwrap()[0].1.id, node);
+    assert!(loaded.scan_by_property(None, "v", &Value::Int(1)).unwrap().is_empty());
+    // The free list of the last delta carried over: nothing is free any more
+    assert_eq!(loaded.add_node(vec![], HashMap::new()).unwrap(), 3);
+}
+
+/// Test that tombstones, their purge and edges moved between nodes survive across deltas
+#[test]
+fn deltas_keep_tombstones_purges_and_moved_edges() {
+    use casys_core::{GraphReadStore, GraphWriteStore};
+    use engine::index::StoreOptions;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let options = StoreOptions { soft_delete: true, ..Default::default() };
+
+    let mut graph = engine::index::InMemoryGraphStore::with_options(options.clone());
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let c = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let ab = graph.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+    let bc = graph.add_edge(b, c, "KNOWS".to_string(), HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+
+    graph.delete_node(c, true).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    let mut loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options.clone()).unwrap();
+    assert!(loaded.get_node_including_deleted(c).unwrap().unwrap().deleted);
+    assert!(loaded.get_edge_including_deleted(bc).unwrap().unwrap().deleted);
+    assert_eq!(loaded.purge_tombstones(), (1, 1));
+
+    graph.undelete_node(c).unwrap();
+    graph.merge_nodes(a, b, false).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    graph.delete_node(c, true).unwrap();
+    graph.purge_tombstones();
+    graph.flush_incremental(&store, root, &db).unwrap();
+
+    let loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(loaded.get_node_including_deleted(b).unwrap().is_none());
+    assert!(loaded.get_node_including_deleted(c).unwrap().is_none());
+    assert!(loaded.get_edge_including_deleted(bc).unwrap().is_none());
+    assert!(loaded.get_edge(ab).unwrap().is_none(), "merging made it a self loop, dropped");
+    assert_eq!(loaded.node_count().unwrap(), 1);
+}
+
+/// Test that compact_segments and a full flush fold the deltas into a new base
+#[test]
+fn compact_segments_folds_deltas_into_the_base() {
+    use casys_core::{GraphReadStore, GraphWriteStore};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let manifest = |store: &MockSegmentStore| serde_json::from_slice::<serde_json::Value>(&store.segment("deltas")).unwrap();
+
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    for _ in 0..3 {
+        let b = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+        graph.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+        graph.flush_incremental(&store, root, &db).unwrap();
+    }
+    assert_eq!(manifest(&store)["deltas"], serde_json::json!([1, 2, 3]));
+
+    engine::index::InMemoryGraphStore::compact_segments(&store, root, &db).unwrap();
+    assert_eq!(manifest(&store)["deltas"], serde_json::json!([]));
+    let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert_eq!(loaded.get_neighbors(a, Some("KNOWS")).unwrap().len(), 3);
+
+    // Numbering goes on after a compaction, so a delta never shadows one dropped before
+    graph.set_node_property(a, "x".to_string(), casys_core::Value::Int(1)).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    assert_eq!(manifest(&store)["deltas"], serde_json::json!([4]));
+    graph.flush(&store, root, &db).unwrap();
+    assert_eq!(manifest(&store)["deltas"], serde_json::json!([]));
+    let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+}
+
+/// Test that a delta the manifest lists but the store lacks fails the load
+#[test]
+fn load_reports_missing_deltas() {
+    use casys_core::GraphWriteStore;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    let a = graph.add_node(vec![], HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    graph.delete_node(a, false).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    store.segments.lock().unwrap().remove("edges-000001");
+
+    match engine::index::InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::SegmentCorruption { path, detail }) => {
+            assert_eq!(path, "edges-000001");
+            assert!(detail.contains("missing"), "{}", detail);
+        }
+        other => panic!("expected SegmentCorruption, got {:?}", other.map(|_| ())),
+    }
+}
+
+/// Segment of the partition the partition manifest in `store` lists for `label`.
+fn partition_of(store: &MockSegmentStore, label: &str) -> String {
+    let manifest: serde_json::Value = serde_json::from_slice(&store.segment("partitions")).unwrap();
+    manifest["partitions"][label].as_str().unwrap().to_string()
+}
+
+/// Ids of `nodes`, sorted.
+fn node_ids(nodes: Vec<casys_core::Node>) -> Vec<u64> {
+    let mut ids: Vec<u64> = nodes.into_iter().map(|n| n.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Test that partitioned nodes load whole, and load_labels reads just the partitions it needs
+#[test]
+fn partitioned_segments_load_whole_or_by_label() {
+    use casys_core::{GraphReadStore, GraphWriteStore};
+    use engine::index::{InMemoryGraphStore, PartialEdges, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let options = StoreOptions { partition_nodes_by_label: true, ..Default::default() };
+
+    let mut graph = InMemoryGraphStore::with_options(options);
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let paris = graph.add_node(vec!["City".to_string(), "Capital".to_string()], HashMap::new()).unwrap();
+    let lyon = graph.add_node(vec!["City".to_string()], HashMap::new()).unwrap();
+    let acme = graph.add_node(vec!["Company".to_string()], HashMap::new()).unwrap();
+    let loose = graph.add_node(vec![], HashMap::new()).unwrap();
+    let knows = graph.add_edge(a, b, "KNOWS".to_string(), HashMap::new()).unwrap();
+    let lives_in = graph.add_edge(a, paris, "LIVES_IN".to_string(), HashMap::new()).unwrap();
+    graph.add_edge(acme, lyon, "BASED_IN".to_string(), HashMap::new()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    assert!(!store.has_segment("nodes"));
+    assert!(store.has_segment("nodes-unlabeled"));
+    assert!(store.has_segment(&partition_of(&store, "Person")));
+
+    let loaded = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(!loaded.is_partial());
+    assert_eq!(loaded.get_node(loose).unwrap().unwrap().id, loose);
+
+    // Nothing of the other partitions is read
+    let companies = partition_of(&store, "Company");
+    store.segments.lock().unwrap().remove(&companies);
+    let people = InMemoryGraphStore::load_labels(&store, root, &db, &["Person"], PartialEdges::Skip, StoreOptions::default()).unwrap();
+    assert!(people.is_partial());
+    assert_eq!(node_ids(people.scan_all().unwrap()), vec![a, b]);
+    assert_eq!(people.get_edge(knows).unwrap().unwrap().to_node, b);
+    assert!(people.get_edge(lives_in).unwrap().is_none(), "one endpoint is not loaded");
+    assert!(people.get_neighbors(a, Some("LIVES_IN")).unwrap().is_empty());
+    assert!(matches!(InMemoryGraphStore::load(&store, root, &db), Err(EngineError::SegmentCorruption { .. })));
+
+    // A label after the first reads the partition of the first, and keeps its nodes only
+    let capitals = InMemoryGraphStore::load_labels(&store, root, &db, &["Capital"], PartialEdges::Skip, StoreOptions::default()).unwrap();
+    assert_eq!(node_ids(capitals.scan_by_label("City").unwrap()), vec![paris]);
+
+    let cities = InMemoryGraphStore::load_labels(&store, root, &db, &["City"], PartialEdges::Stubs, StoreOptions::default()).unwrap();
+    assert_eq!(cities.get_neighbors_incoming(paris, Some("LIVES_IN")).unwrap()[0].1.id, a);
+    assert!(cities.is_stub(a) && !cities.is_stub(paris));
+    assert!(cities.get_node(a).unwrap().unwrap().labels.is_empty());
+    assert!(cities.get_edge(knows).unwrap().is_none(), "edges are not followed from stubs");
+    assert!(cities.scan_by_label("Person").unwrap().is_empty());
+}
+
+/// Test that a partial load applies deltas, relabels included, and cannot be flushed
+#[test]
+fn partial_load_applies_deltas_and_refuses_flush() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::{InMemoryGraphStore, PartialEdges, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let options = StoreOptions { partition_nodes_by_label: true, ..Default::default() };
+
+    let mut graph = InMemoryGraphStore::with_options(options);
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let city = graph.add_node(vec!["City".to_string()], HashMap::new()).unwrap();
+    let lives_in = graph.add_edge(a, city, "LIVES_IN".to_string(), HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+
+    graph.remove_label(b, "Person").unwrap();
+    graph.add_label(city, "Person".to_string()).unwrap();
+    graph.set_node_property(a, "age".to_string(), Value::Int(40)).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+
+    let mut people = InMemoryGraphStore::load_labels(&store, root, &db, &["Person"], PartialEdges::Skip, StoreOptions::default()).unwrap();
+    assert_eq!(node_ids(people.scan_by_label("Person").unwrap()), vec![a, city]);
+    assert!(people.get_node(b).unwrap().is_none(), "dropped by the delta that took its label");
+    assert_eq!(people.get_edge(lives_in).unwrap().unwrap().to_node, city);
+    assert_eq!(people.scan_by_property(None, "age", &Value::Int(40)).unwrap()[0].id, a);
+
+    people.set_node_property(a, "age".to_string(), Value::Int(41)).unwrap();
+    for result in [people.flush(&store, root, &db), people.flush_incremental(&store, root, &db)] {
+        match result {
+            Err(EngineError::InvalidArgument(message)) => assert!(message.contains("Person"), "{}", message),
+            other => panic!("expected InvalidArgument, got {:?}", other),
+        }
+    }
+    assert_eq!(graph_rows(&InMemoryGraphStore::load(&store, root, &db).unwrap()), graph_rows(&graph));
+}
+
+/// Test that load_labels reads unpartitioned binary segments whole, and refuses JSON ones
+#[test]
+fn load_labels_reads_unpartitioned_segments() {
+    use casys_core::{GraphReadStore, GraphWriteStore};
+    use engine::index::{InMemoryGraphStore, PartialEdges, SegmentFormat, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    let mut graph = InMemoryGraphStore::new();
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    graph.add_node(vec!["City".to_string()], HashMap::new()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let people = InMemoryGraphStore::load_labels(&store, root, &db, &["Person"], PartialEdges::Skip, StoreOptions::default()).unwrap();
+    assert_eq!(node_ids(people.scan_all().unwrap()), vec![a]);
+
+    let json = StoreOptions { segment_format: SegmentFormat::Json, ..Default::default() };
+    InMemoryGraphStore::load_with_options(&store, root, &db, json).unwrap().flush(&store, root, &db).unwrap();
+    assert!(matches!(
+        InMemoryGraphStore::load_labels(&store, root, &db, &["Person"], PartialEdges::Skip, StoreOptions::default()),
+        Err(EngineError::InvalidArgument(_))
+    ));
+}
+
+/// Test that a flush without partitioning supersedes the partitions, and compaction keeps them
+#[test]
+fn partitioning_is_switched_off_by_flush_and_kept_by_compaction() {
+    use casys_core::GraphWriteStore;
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let partitioned = |store: &MockSegmentStore| {
+        serde_json::from_slice::<serde_json::Value>(&store.segment("partitions")).unwrap()["partitioned"].clone()
+    };
+
+    let mut graph = InMemoryGraphStore::with_options(StoreOptions { partition_nodes_by_label: true, ..Default::default() });
+    let a = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    graph.add_node(vec!["City".to_string()], HashMap::new()).unwrap();
+    graph.flush_incremental(&store, root, &db).unwrap();
+    InMemoryGraphStore::compact_segments(&store, root, &db).unwrap();
+    assert_eq!(partitioned(&store), serde_json::json!(true));
+    assert!(store.has_segment(&partition_of(&store, "City")));
+    assert_eq!(graph_rows(&InMemoryGraphStore::load(&store, root, &db).unwrap()), graph_rows(&graph));
+
+    let mut graph = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    graph.delete_node(a, false).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    assert_eq!(partitioned(&store), serde_json::json!(false));
+    let loaded = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    InMemoryGraphStore::compact_segments(&store, root, &db).unwrap();
+    assert_eq!(partitioned(&store), serde_json::json!(false));
+}
+
 /// Test load on empty store returns empty graph (AC3)
 #[test]
 fn load_empty_store_returns_empty_graph() {
@@ -287,3 +1015,1080 @@ fn roundtrip_rebuilds_label_index() {
     let others = loaded.scan_by_label("Other").unwrap();
     assert_eq!(others.len(), 0, "Should find 0 Other nodes");
 }
+
+/// Test that tombstones survive a flush/load round-trip and stay hidden
+#[test]
+fn roundtrip_preserves_tombstones() {
+    use casys_core::{GraphWriteStore, GraphReadStore};
+    use engine::index::StoreOptions;
+
+    let store = MockSegmentStore::new();
+    let options = StoreOptions { soft_delete: true, ..Default::default() };
+    let mut graph = engine::index::InMemoryGraphStore::with_options(options.clone());
+
+    let alive = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let gone = graph.add_node(vec!["Person".to_string()], HashMap::new()).unwrap();
+    let edge = graph.add_edge(alive, gone, "KNOWS".to_string(), HashMap::new()).unwrap();
+    graph.delete_node(gone, true).unwrap();
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+
+    assert!(loaded.get_node(gone).unwrap().is_none(), "Tombstoned node should stay hidden");
+    assert!(loaded.get_node_including_deleted(gone).unwrap().unwrap().deleted);
+    assert!(loaded.get_edge_including_deleted(edge).unwrap().unwrap().deleted);
+    assert_eq!(loaded.scan_by_label("Person").unwrap().len(), 1);
+    assert!(loaded.get_neighbors(alive, None).unwrap().is_empty());
+
+    assert!(loaded.undelete_node(gone).unwrap());
+    assert!(loaded.undelete_edge(edge).unwrap());
+    assert_eq!(loaded.get_neighbors(alive, None).unwrap().len(), 1);
+}
+
+/// Test that id allocator state survives a round-trip so reloads never collide
+#[test]
+fn roundtrip_preserves_id_allocator_state() {
+    use casys_core::GraphWriteStore;
+    use engine::index::StoreOptions;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    // Without reuse the high-water mark must survive deleting the newest node
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    graph.add_node(vec![], HashMap::new()).unwrap();
+    let newest = graph.add_node(vec![], HashMap::new()).unwrap();
+    graph.delete_node(newest, false).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert!(loaded.add_node(vec![], HashMap::new()).unwrap() > newest);
+
+    // With reuse the free list comes back too
+    let options = StoreOptions { reuse_ids: true, ..Default::default() };
+    let mut graph = engine::index::InMemoryGraphStore::with_options(options.clone());
+    let first = graph.add_node(vec![], HashMap::new()).unwrap();
+    graph.add_node(vec![], HashMap::new()).unwrap();
+    graph.delete_node(first, false).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+    assert_eq!(loaded.add_node(vec![], HashMap::new()).unwrap(), first);
+    assert_eq!(loaded.add_node(vec![], HashMap::new()).unwrap(), 3);
+}
+
+/// Test that verify_on_load rejects a segment whose records produce index drift
+#[test]
+fn verify_on_load_reports_index_drift() {
+    use engine::index::StoreOptions;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    // The same edge listed twice ends up in the adjacency lists twice
+    let nodes = br#"{"count":2,"nodes":[
+        {"id":1,"labels":["Person"],"properties":{}},
+        {"id":2,"labels":["Person"],"properties":{}}
+    ]}"#;
+    let edges = br#"{"count":2,"edges":[
+        {"id":1,"from":1,"to":2,"type":"KNOWS","properties":{}},
+        {"id":1,"from":1,"to":2,"type":"KNOWS","properties":{}}
+    ]}"#;
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), nodes, 2, 0).unwrap();
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), edges, 0, 2).unwrap();
+
+    let lenient = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert!(lenient.verify_indexes().is_err());
+
+    let options = StoreOptions { verify_on_load: true, ..Default::default() };
+    let err = engine::index::InMemoryGraphStore::load_with_options(&store, root, &db, options).err().unwrap();
+    match err {
+        EngineError::StorageIo(msg) => assert!(msg.contains("edge 1 listed 2 times"), "{}", msg),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+/// Test that indexes and unique constraints survive a roundtrip, backfilled or loaded from their flushed contents
+#[test]
+fn roundtrip_restores_property_indexes() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::StoreOptions;
+
+    for persist_index_data in [false, true] {
+        let store = MockSegmentStore::new();
+        let root = Path::new("/fake/root");
+        let db = DatabaseName::try_from("testdb").unwrap();
+        let options = StoreOptions { persist_index_data, ..Default::default() };
+        let mut graph = engine::index::InMemoryGraphStore::with_options(options);
+        let alice = graph.add_node(vec!["Person".into()], HashMap::from([("name".into(), Value::String("Alice".into()))])).unwrap();
+        graph.add_node(vec!["Person".into()], HashMap::from([("name".into(), Value::String("Bob".into()))])).unwrap();
+        graph.create_property_index("Person", "name").unwrap();
+        graph.create_range_index("Person", "name").unwrap();
+        graph.create_composite_index("Person", &["name", "age"]).unwrap();
+        graph.create_unique_constraint("Person", "name").unwrap();
+        let bob = graph.scan_by_property(Some("Person"), "name", &Value::String("Bob".into())).unwrap()[0].id;
+        let knows = graph.add_edge(alice, bob, "KNOWS".into(), HashMap::from([("since".into(), Value::Int(2020))])).unwrap();
+        graph.create_edge_index("KNOWS", "since").unwrap();
+        graph.create_text_index("Person", "name").unwrap();
+        graph.create_prefix_index("Person", "name", false).unwrap();
+        graph.flush(&store, root, &db).unwrap();
+
+        let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+        assert_eq!(loaded.property_indexes(), vec![("Person".to_string(), "name".to_string())]);
+        assert_eq!(loaded.range_indexes(), vec![("Person".to_string(), "name".to_string())]);
+        assert_eq!(loaded.unique_constraints(), vec![("Person".to_string(), "name".to_string())]);
+        assert!(matches!(
+            loaded.add_node(vec!["Person".into()], HashMap::from([("name".into(), Value::String("Bob".into()))])),
+            Err(EngineError::UniqueViolation { .. })
+        ));
+        assert_eq!(loaded.composite_indexes(), vec![("Person".to_string(), vec!["name".to_string(), "age".to_string()])]);
+        // Loaded indexes count their lookups whether backfilled or read back
+        loaded.reset_index_stats();
+        let found = loaded.scan_by_property(Some("Person"), "name", &Value::String("Alice".into())).unwrap();
+        assert_eq!(found.iter().map(|n| n.id).collect::<Vec<_>>(), vec![alice]);
+        let property = loaded.index_stats().into_iter().find(|s| s.kind == engine::index::IndexKind::Property).unwrap();
+        assert_eq!(property.hits, 1);
+        loaded.set_node_property(alice, "name".into(), Value::String("Alicia".into())).unwrap();
+        assert!(loaded.scan_by_property(Some("Person"), "name", &Value::String("Alice".into())).unwrap().is_empty());
+        assert_eq!(loaded.edge_indexes(), vec![("KNOWS".to_string(), "since".to_string())]);
+        let edges = loaded.scan_edges_by_property("KNOWS", "since", &Value::Int(2020)).unwrap();
+        assert_eq!(edges.iter().map(|e| e.id).collect::<Vec<_>>(), vec![knows]);
+        assert_eq!(loaded.text_indexes(), vec![("Person".to_string(), "name".to_string())]);
+        assert_eq!(loaded.search_text("Person", "name", "ali", engine::index::TextMatch::Prefix).unwrap(), vec![alice]);
+        assert_eq!(loaded.prefix_indexes(), vec![("Person".to_string(), "name".to_string(), false)]);
+        assert_eq!(loaded.scan_by_prefix("Person", "name", "ALI", 10).unwrap().len(), 1);
+        assert!(loaded.verify_indexes().is_ok());
+    }
+}
+
+/// Test that a property index comes back with its normalization options
+#[test]
+fn roundtrip_keeps_property_index_options() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::{IndexOptions, StoreOptions};
+
+    for persist_index_data in [false, true] {
+        let store = MockSegmentStore::new();
+        let root = Path::new("/fake/root");
+        let db = DatabaseName::try_from("testdb").unwrap();
+        let mut graph = engine::index::InMemoryGraphStore::with_options(StoreOptions { persist_index_data, ..Default::default() });
+        let paris = graph.add_node(vec!["City".into()], HashMap::from([("name".into(), Value::String(" Paris".into()))])).unwrap();
+        let options = IndexOptions { case_insensitive: true, ..Default::default() };
+        graph.create_property_index_with("City", "name", options).unwrap();
+        let tags = Value::Array(vec![Value::String("port".into()), Value::String("capital".into())]);
+        graph.set_node_property(paris, "tags".into(), tags).unwrap();
+        let multi = IndexOptions { multi_value: true, ..Default::default() };
+        graph.create_property_index_with("City", "tags", multi).unwrap();
+        graph.flush(&store, root, &db).unwrap();
+
+        let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+        assert_eq!(loaded.property_index_options("City", "name"), Some(options));
+        let found = loaded.scan_by_index("City", "name", &Value::String(" PARIS".into())).unwrap();
+        assert_eq!(found.iter().map(|n| n.id).collect::<Vec<_>>(), vec![paris]);
+        assert!(loaded.scan_by_index("City", "name", &Value::String("paris".into())).unwrap().is_empty());
+        assert_eq!(loaded.property_index_options("City", "tags"), Some(multi));
+        assert_eq!(loaded.scan_by_index("City", "tags", &Value::String("port".into())).unwrap().len(), 1);
+        assert!(loaded.verify_indexes().is_ok());
+    }
+}
+
+/// Test that collected statistics come back with the store, and truncated ones do not
+#[test]
+fn roundtrip_keeps_statistics() {
+    use casys_core::{GraphWriteStore, Value};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    for i in 0..50 {
+        let props = HashMap::from([("score".into(), Value::Float(i as f64 / 3.0)), ("name".into(), Value::String(format!("n{}", i)))]);
+        graph.add_node(vec!["Person".into()], props).unwrap();
+    }
+    let stats = graph.collect_statistics(0.5);
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(loaded.statistics(), Some(&stats));
+    assert!(loaded.property_statistics("Person", "score").unwrap().histogram.is_some());
+
+    loaded.truncate(false);
+    loaded.flush(&store, root, &db).unwrap();
+    assert!(engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap().statistics().is_none());
+}
+
+/// Test that a computed index loads awaiting its function and works once re-registered
+#[test]
+fn computed_index_needs_re_registration_after_load() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::ComputedKey;
+
+    let lowercase = || -> ComputedKey {
+        Box::new(|node| match node.properties.get("email") {
+            Some(Value::String(email)) => Some(Value::String(email.to_lowercase())),
+            _ => None,
+        })
+    };
+    let email = |s: &str| HashMap::from([("email".to_string(), Value::String(s.into()))]);
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    let ann = graph.add_node(vec!["User".into()], email("Ann@X.org")).unwrap();
+    graph.create_computed_index("User", "email_lower", lowercase()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    let def = vec![("User".to_string(), "email_lower".to_string())];
+    assert_eq!(loaded.computed_indexes(), def);
+    assert_eq!(loaded.unregistered_computed_indexes(), def);
+    let lookup = Value::String("ann@x.org".into());
+    match loaded.scan_by_computed("User", "email_lower", &lookup) {
+        Err(EngineError::InvalidArgument(msg)) => assert!(msg.contains("re-register"), "{}", msg),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    // Writes before re-registration are picked up by its backfill
+    let bob = loaded.add_node(vec!["User".into()], email("BOB@x.org")).unwrap();
+    assert!(loaded.verify_indexes().is_ok());
+
+    loaded.create_computed_index("User", "email_lower", lowercase()).unwrap();
+    assert!(loaded.unregistered_computed_indexes().is_empty());
+    let found = |graph: &engine::index::InMemoryGraphStore, value: &str| -> Vec<u64> {
+        graph.scan_by_computed("User", "email_lower", &Value::String(value.into())).unwrap().iter().map(|n| n.id).collect()
+    };
+    assert_eq!(found(&loaded, "ann@x.org"), vec![ann]);
+    assert_eq!(found(&loaded, "bob@x.org"), vec![bob]);
+    assert!(loaded.verify_indexes().is_ok());
+    assert!(loaded.create_computed_index("User", "email_lower", lowercase()).is_err());
+}
+
+/// Test that a covering index comes back with its covered keys and copies
+#[test]
+fn roundtrip_keeps_covering_index() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::{IndexOptions, StoreOptions};
+
+    for persist_index_data in [false, true] {
+        let store = MockSegmentStore::new();
+        let root = Path::new("/fake/root");
+        let db = DatabaseName::try_from("testdb").unwrap();
+        let mut graph = engine::index::InMemoryGraphStore::with_options(StoreOptions { persist_index_data, ..Default::default() });
+        let props = HashMap::from([("city".into(), Value::String("Paris".into())), ("name".into(), Value::String("Ann".into()))]);
+        let ann = graph.add_node(vec!["Person".into()], props).unwrap();
+        graph.create_covering_index("Person", "city", IndexOptions::default(), &["name"]).unwrap();
+        graph.flush(&store, root, &db).unwrap();
+
+        let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+        assert_eq!(loaded.covered_keys("Person", "city"), Some(vec!["name".to_string()]));
+        let rows = loaded.scan_covered("Person", "city", &Value::String("Paris".into())).unwrap();
+        assert_eq!(rows, vec![(ann, HashMap::from([("name".to_string(), Value::String("Ann".into()))]))]);
+        assert!(loaded.verify_indexes().is_ok());
+    }
+}
+
+/// Test that a TTL index survives a roundtrip and expires the loaded nodes
+#[test]
+fn roundtrip_keeps_ttl_index() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::StoreOptions;
+
+    for persist_index_data in [false, true] {
+        let store = MockSegmentStore::new();
+        let root = Path::new("/fake/root");
+        let db = DatabaseName::try_from("testdb").unwrap();
+        let mut graph = engine::index::InMemoryGraphStore::with_options(StoreOptions { persist_index_data, ..Default::default() });
+        graph.create_ttl_index("Event", "expires_at").unwrap();
+        for at in [10, 20, 30] {
+            graph.add_node(vec!["Event".into()], HashMap::from([("expires_at".into(), Value::Int(at))])).unwrap();
+        }
+        graph.flush(&store, root, &db).unwrap();
+
+        let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+        assert_eq!(loaded.ttl_indexes(), vec![("Event".to_string(), "expires_at".to_string())]);
+        assert!(loaded.verify_indexes().is_ok());
+        assert_eq!(loaded.expire(20).unwrap().nodes, 2);
+        assert_eq!(loaded.node_count().unwrap(), 1);
+    }
+}
+
+/// Test that a unique constraint whose property index was dropped comes back without it
+#[test]
+fn roundtrip_keeps_constraint_without_its_property_index() {
+    use casys_core::{GraphWriteStore, Value};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    graph.create_unique_constraint("User", "email").unwrap();
+    graph.drop_property_index("User", "email").unwrap();
+    graph.add_node(vec!["User".into()], HashMap::from([("email".into(), Value::String("a@x".into()))])).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert!(loaded.property_indexes().is_empty());
+    assert_eq!(loaded.unique_constraints(), vec![("User".to_string(), "email".to_string())]);
+    assert!(loaded.add_node(vec!["User".into()], HashMap::from([("email".into(), Value::String("a@x".into()))])).is_err());
+}
+
+/// Test that segments written before the index segment existed still restore their indexes
+#[test]
+fn load_reads_index_definitions_from_legacy_segments() {
+    use casys_core::{GraphReadStore, Value};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let nodes = br#"{"count":1,"nodes":[{"id":1,"labels":["Person"],"properties":{"name":"Ann"}}],
+        "property_indexes":[{"label":"Person","key":"name"}]}"#;
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), nodes, 1, 0).unwrap();
+
+    let loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert_eq!(loaded.property_indexes(), vec![("Person".to_string(), "name".to_string())]);
+    assert!(loaded.verify_indexes().is_ok());
+    let found = loaded.scan_by_property(Some("Person"), "name", &Value::String("Ann".into())).unwrap();
+    assert_eq!(found.len(), 1);
+}
+
+/// Test that an index segment from a newer layout is rejected instead of half-loaded
+#[test]
+fn load_rejects_unknown_index_segment_version() {
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    store.write_segment(root, &db, &SegmentId("indexes".to_string()), br#"{"format_version":2,"indexes":[]}"#, 0, 0).unwrap();
+
+    match engine::index::InMemoryGraphStore::load(&store, root, &db) {
+        Err(EngineError::StorageIo(msg)) => assert!(msg.contains("format version"), "{}", msg),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+/// Test that replaying a WAL overlapping the loaded segments does not duplicate label entries
+#[test]
+fn replay_over_loaded_segments_keeps_label_buckets_unique() {
+    use casys_core::{GraphReadStore, GraphWriteStore};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    graph.enable_wal_capture();
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_label(b, "Admin".into()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let c = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let records = graph.take_wal_records();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    loaded.replay_wal(&records).unwrap();
+    let ids = |label: &str| loaded.scan_by_label(label).unwrap().into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert_eq!(ids("Person"), vec![a, b, c]);
+    assert_eq!(ids("Admin"), vec![b]);
+    assert!(loaded.verify_indexes().is_ok());
+}
+
+/// Test that replaying a whole log over segments flushed after it, twice, duplicates no
+/// index or adjacency entry and leaves the newer data in place
+#[test]
+fn replaying_a_log_the_segments_already_reflect_changes_nothing() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let mut graph = engine::index::InMemoryGraphStore::new();
+    graph.enable_wal_capture();
+    graph.create_property_index("Person", "age").unwrap();
+    let props = |age: i64| HashMap::from([("age".to_string(), Value::Int(age))]);
+    let a = graph.add_node(vec!["Person".into()], props(30)).unwrap();
+    let b = graph.add_node(vec!["Person".into()], props(40)).unwrap();
+    let knows = graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    let likes = graph.add_edge(a, b, "LIKES".into(), HashMap::new()).unwrap();
+    graph.add_edge(b, a, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.set_node_property(a, "age".into(), Value::Int(31)).unwrap();
+    graph.add_label(a, "Admin".into()).unwrap();
+    graph.delete_edge(likes).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let records = graph.take_wal_records();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    loaded.replay_wal(&records).unwrap();
+    loaded.replay_wal(&records).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(loaded.verify_indexes().is_ok());
+    let ids = |label: &str| loaded.scan_by_label(label).unwrap().into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert_eq!(ids("Person"), vec![a, b]);
+    assert_eq!(ids("Admin"), vec![a]);
+    let out: Vec<u64> = loaded.get_neighbors(a, None).unwrap().into_iter().map(|(edge, _)| edge.id).collect();
+    assert_eq!(out, vec![knows]);
+    assert_eq!(loaded.get_neighbors_incoming(a, None).unwrap().len(), 1);
+    let aged = |age: i64| loaded.scan_by_property(Some("Person"), "age", &Value::Int(age)).unwrap().len();
+    assert_eq!((aged(30), aged(31), aged(40)), (0, 1, 1));
+}
+
+/// Fresh branch directory under target/tmp for the WAL file of checkpoint tests.
+fn branch_dir(name: &str) -> std::path::PathBuf {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    std::env::current_dir().unwrap().join("target").join("tmp").join(format!("{}_{}", name, now))
+}
+
+type NodeRow = (u64, Vec<String>, HashMap<String, casys_core::Value>);
+type EdgeRow = (u64, u64, u64, String, HashMap<String, casys_core::Value>);
+
+/// Live nodes and edges of `graph`, by id, for comparing two stores.
+fn graph_rows(graph: &engine::index::InMemoryGraphStore) -> (Vec<NodeRow>, Vec<EdgeRow>) {
+    use casys_core::GraphReadStore;
+    let mut nodes: Vec<NodeRow> = graph.scan_all().unwrap().into_iter()
+        .map(|n| (n.id, n.labels, n.properties))
+        .collect();
+    nodes.sort_by_key(|n| n.0);
+    let mut edges: Vec<EdgeRow> = graph.scan_all_edges().unwrap().into_iter()
+        .map(|e| (e.id, e.from_node, e.to_node, e.edge_type, e.properties))
+        .collect();
+    edges.sort_by_key(|e| e.0);
+    (nodes, edges)
+}
+
+/// Test that a checkpoint empties the WAL file and recover replays only what came after it
+#[test]
+fn checkpoint_truncates_wal_and_recover_replays_only_newer_records() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::persistence::{read_checkpoint, Checkpoint, SyncPolicy, WalReader, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_checkpoint");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let e = graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.set_node_property(a, "name".into(), Value::String("a".into())).unwrap();
+    assert!(matches!(
+        InMemoryGraphStore::new().checkpoint(&store, root, &db),
+        Err(EngineError::InvalidArgument(_))
+    ));
+    graph.checkpoint(&store, root, &db).unwrap();
+
+    let first = Checkpoint { generation: 1, lsn: 4 };
+    assert_eq!(read_checkpoint(&store, root, &db).unwrap(), Some(first));
+    let mut reader = WalReader::open_branch(&dir, 1).unwrap();
+    assert!(reader.read_all().unwrap().is_empty());
+    assert_eq!(reader.checkpoint(), Some(first));
+
+    let c = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.set_node_property(a, "name".into(), Value::String("a2".into())).unwrap();
+    graph.delete_edge(e).unwrap();
+    assert_eq!(graph.wal_writer().unwrap().next_lsn(), 8);
+    let records: Vec<u64> = WalReader::open_branch(&dir, 1).unwrap().map(|r| r.unwrap().0).collect();
+    assert_eq!(records, vec![5, 6, 7]);
+
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+    assert_eq!(graph_rows(&recovered).0.iter().map(|n| n.0).collect::<Vec<_>>(), vec![a, b, c]);
+    assert!(recovered.verify_indexes().is_ok());
+
+    // A reopened writer continues the numbering and the generations
+    drop(graph.detach_wal_writer());
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+    assert_eq!((writer.next_lsn(), writer.last_checkpoint()), (8, Some(first)));
+    writer.checkpoint(&graph, &store, root, &db).unwrap();
+    assert_eq!(read_checkpoint(&store, root, &db).unwrap(), Some(Checkpoint { generation: 2, lsn: 7 }));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that recover is safe after a crash between the segment flush and the WAL truncation
+#[test]
+fn recover_after_crash_mid_checkpoint_replays_records_already_in_segments() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::persistence::{wal_dir, SyncPolicy, WalReader, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_checkpoint_crash");
+    let mut graph = InMemoryGraphStore::new();
+    graph.create_property_index("Person", "name").unwrap();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let c = graph.add_node(vec!["Temp".into()], HashMap::new()).unwrap();
+    let ab = graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.add_edge(b, c, "KNOWS".into(), HashMap::new()).unwrap();
+    graph.set_node_property(a, "name".into(), Value::String("first".into())).unwrap();
+    graph.set_node_property(a, "name".into(), Value::String("second".into())).unwrap();
+    graph.update_edge_properties(ab, HashMap::from([("w".to_string(), Value::Int(2))])).unwrap();
+    graph.add_label(b, "Admin".into()).unwrap();
+    graph.delete_node(c, true).unwrap();
+
+    // Crash after the flush, before the checkpoint segment: the segments carry the last
+    // applied LSN, so nothing is replayed over them
+    graph.flush(&store, root, &db).unwrap();
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(recovered.last_applied_lsn(), 10);
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+    assert!(recovered.verify_indexes().is_ok());
+    assert_eq!(recovered.scan_by_property(Some("Person"), "name", &Value::String("second".into())).unwrap().len(), 1);
+
+    // Replaying every record over them anyway is harmless
+    let entries = WalReader::open_branch(&dir, 1).unwrap().read_entries().unwrap();
+    let mut replayed = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    replayed.replay_wal_from(&entries, 0).unwrap();
+    assert_eq!(graph_rows(&replayed), graph_rows(&graph));
+    assert!(replayed.verify_indexes().is_ok());
+    assert_eq!(replayed.scan_by_property(Some("Person"), "name", &Value::String("second".into())).unwrap().len(), 1);
+
+    // Crash after the checkpoint segment, before the WAL manifest is replaced: the old
+    // records are all at or before the checkpoint and are skipped
+    let saved: Vec<(std::path::PathBuf, Vec<u8>)> = std::fs::read_dir(wal_dir(&dir)).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .map(|path| (path.clone(), std::fs::read(&path).unwrap()))
+        .collect();
+    graph.checkpoint(&store, root, &db).unwrap();
+    for (path, data) in &saved {
+        std::fs::write(path, data).unwrap();
+    }
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+    assert!(recovered.verify_indexes().is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that a checkpoint deletes the rotated WAL files it made obsolete
+#[test]
+fn checkpoint_deletes_rotated_wal_files() {
+    use casys_core::GraphWriteStore;
+    use engine::index::persistence::{wal_dir, SyncPolicy, WalReader, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_checkpoint_rotated");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_max_file_bytes(200));
+    for _ in 0..10 {
+        graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    }
+    let rotated = graph.wal_writer().unwrap().files();
+    assert!(rotated.len() > 2);
+    graph.checkpoint(&store, root, &db).unwrap();
+    let files = graph.wal_writer().unwrap().files();
+    assert_eq!(files.len(), 1);
+    assert!(rotated.iter().all(|f| !f.exists()));
+    let mut on_disk: Vec<String> = std::fs::read_dir(wal_dir(&dir)).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    on_disk.sort();
+    assert_eq!(on_disk, vec!["manifest.json".to_string(), files[0].file_name().unwrap().to_string_lossy().into_owned()]);
+
+    let last = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.sync_wal().unwrap();
+    assert_eq!(WalReader::open_branch(&dir, 1).unwrap().count(), 1);
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+    assert_eq!(graph_rows(&recovered).0.last().map(|n| n.0), Some(last));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that recover refuses segments and a WAL that do not meet, naming the LSNs involved
+#[test]
+fn recover_rejects_segments_the_wal_does_not_continue() {
+    use casys_core::GraphWriteStore;
+    use engine::index::persistence::{wal_dir, SyncPolicy, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_recover_mismatch");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap());
+    graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    // A backup of the segments, older than the checkpoint that then drops the WAL's files
+    let backup = MockSegmentStore::new();
+    graph.flush(&backup, root, &db).unwrap();
+    graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let store = MockSegmentStore::new();
+    graph.checkpoint(&store, root, &db).unwrap();
+    graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.sync_wal().unwrap();
+
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+    match InMemoryGraphStore::recover(&backup, root, &db, &dir, StoreOptions::default()) {
+        Err(EngineError::StorageIo(msg)) => {
+            assert!(msg.contains("up to LSN 2 (no checkpoint)"), "{}", msg);
+            assert!(msg.contains("starts at LSN 4: records 3..=3 were deleted by checkpoint 1 at LSN 3"), "{}", msg);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    // Segments the WAL does not reach: its files were lost
+    drop(graph.detach_wal_writer());
+    std::fs::remove_dir_all(wal_dir(&dir)).unwrap();
+    match InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()) {
+        Err(EngineError::StorageIo(msg)) => {
+            assert!(msg.contains("up to LSN 3 (checkpoint 1 at LSN 3)"), "{}", msg);
+            assert!(msg.contains("ends at LSN 0"), "{}", msg);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that verify_against_wal finds a recovered store clean, and names what was changed
+/// behind the WAL's back
+#[test]
+fn verify_against_wal_reports_divergence_by_id() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::persistence::{verify_against_wal, SyncPolicy, WalReader, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_verify");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::Manual).unwrap());
+    let a = graph.add_node(vec!["Person".into(), "Admin".into()], HashMap::new()).unwrap();
+    let b = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    let e = graph.add_edge(a, b, "KNOWS".into(), HashMap::new()).unwrap();
+    let store = MockSegmentStore::new();
+    graph.flush(&store, root, &db).unwrap();
+    graph.set_node_property(b, "name".into(), Value::String("b".into())).unwrap();
+    graph.sync_wal().unwrap();
+    drop(graph.detach_wal_writer());
+
+    let log = || WalReader::open_branch(&dir, 1).unwrap().read_all().unwrap().into_iter();
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    let report = verify_against_wal(&recovered, log());
+    assert!(report.is_clean(), "{:?}", report);
+
+    // Changes the WAL never saw
+    let mut drifted = recovered;
+    let c = drifted.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    drifted.set_node_property(b, "name".into(), Value::String("c".into())).unwrap();
+    drifted.remove_label(a, "Admin").unwrap();
+    drifted.delete_edge(e).unwrap();
+    let report = verify_against_wal(&drifted, log());
+    assert!(!report.is_clean());
+    assert_eq!(report.extra_nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![c]);
+    assert!(report.missing_nodes.is_empty());
+    let mismatched: Vec<_> = report.mismatched_nodes.iter().map(|m| (m.expected.id, m.differences.clone())).collect();
+    assert_eq!(mismatched, vec![(a, vec!["labels".to_string()]), (b, vec!["property name".to_string()])]);
+    assert_eq!(report.missing_edges.iter().map(|e| e.id).collect::<Vec<_>>(), vec![e]);
+    assert!(report.replay_error.is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that recover applies only the WAL transactions whose commit was logged
+#[test]
+fn recover_discards_wal_transactions_without_a_commit() {
+    use casys_core::{GraphReadStore, GraphWriteStore, Value};
+    use engine::index::persistence::{SyncPolicy, WalRecord, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_txn");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let before = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+
+    let committed = graph.begin_wal_txn().unwrap();
+    assert!(matches!(graph.begin_wal_txn(), Err(EngineError::InvalidArgument(_))));
+    let a = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.add_edge(before, a, "KNOWS".into(), HashMap::new()).unwrap();
+    assert_eq!(graph.commit_wal_txn().unwrap(), committed);
+    assert!(matches!(graph.commit_wal_txn(), Err(EngineError::InvalidArgument(_))));
+
+    let aborted = graph.begin_wal_txn().unwrap();
+    graph.set_node_property(before, "name".into(), Value::String("aborted".into())).unwrap();
+    assert_eq!(graph.abort_wal_txn().unwrap(), aborted);
+
+    // The log is cut right before the last transaction's commit
+    let torn = graph.begin_wal_txn().unwrap();
+    let b = graph.add_node(vec!["Temp".into()], HashMap::new()).unwrap();
+    let c = graph.add_node(vec!["Temp".into()], HashMap::new()).unwrap();
+    graph.add_edge(b, c, "KNOWS".into(), HashMap::new()).unwrap();
+    assert!(matches!(graph.checkpoint(&store, root, &db), Err(EngineError::InvalidArgument(_))));
+    graph.commit_wal_txn().unwrap();
+    let writer = graph.detach_wal_writer().unwrap();
+    let commit = WalRecord::CommitTxn { txn_id: torn }.to_frame(writer.next_lsn() - 1);
+    let path = writer.path();
+    drop(writer);
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - commit.len() as u64).unwrap();
+
+    let mut recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered).0.iter().map(|n| n.0).collect::<Vec<_>>(), vec![before, a]);
+    assert_eq!(recovered.get_neighbors(before, None).unwrap().len(), 1);
+    assert!(recovered.scan_by_label("Temp").unwrap().is_empty());
+    assert!(recovered.get_node(before).unwrap().unwrap().properties.is_empty());
+    assert!(recovered.verify_indexes().is_ok());
+    assert!(recovered.begin_wal_txn().unwrap() > torn);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that a checkpoint after an aborted WAL transaction is refused, so the segments
+/// never hold its mutations, and that a recovered store drops them and checkpoints again
+#[test]
+fn checkpoint_refuses_mutations_of_an_aborted_wal_transaction() {
+    use casys_core::GraphWriteStore;
+    use engine::index::persistence::{SyncPolicy, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_txn_abort_checkpoint");
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let kept = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.checkpoint(&store, root, &db).unwrap();
+
+    graph.begin_wal_txn().unwrap();
+    let aborted = graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    graph.abort_wal_txn().unwrap();
+    assert!(matches!(graph.checkpoint(&store, root, &db), Err(EngineError::InvalidArgument(_))));
+    drop(graph.detach_wal_writer());
+
+    let mut recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered).0.iter().map(|n| n.0).collect::<Vec<_>>(), vec![kept]);
+    recovered.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    recovered.checkpoint(&store, root, &db).unwrap();
+    drop(recovered.detach_wal_writer());
+
+    let reopened = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&reopened).0.iter().map(|n| n.0).collect::<Vec<_>>(), vec![kept]);
+    assert_ne!(kept, aborted);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that each recovery policy handles a corrupt WAL record at the start, middle and
+/// end of the log as documented
+#[test]
+fn recovery_policies_handle_corrupt_records_anywhere_in_the_log() {
+    use engine::index::persistence::{RecoveryPolicy, SyncPolicy, WalRecord, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let records: Vec<WalRecord> = (1..=6)
+        .map(|id| WalRecord::AddNode { id, labels: vec!["Person".into()], properties: HashMap::new() })
+        .collect();
+    let offsets: Vec<u64> = (1..=records.len() as u64)
+        .scan(0, |at, lsn| {
+            let start = *at;
+            *at += records[lsn as usize - 1].to_frame(lsn).len() as u64;
+            Some(start)
+        })
+        .collect();
+    // A log whose record `bad` has the byte at `byte` within its frame flipped
+    let corrupt_log = |name: &str, bad: usize, byte: u64| {
+        let dir = branch_dir(name);
+        let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap();
+        for record in &records {
+            writer.append(record).unwrap();
+        }
+        writer.sync().unwrap();
+        let path = writer.path();
+        let mut data = std::fs::read(&path).unwrap();
+        data[(offsets[bad] + byte) as usize] ^= 0x20;
+        std::fs::write(&path, data).unwrap();
+        (dir, path)
+    };
+    let recover = |dir: &std::path::Path, policy| {
+        InMemoryGraphStore::recover_with_policy(&store, root, &db, dir, StoreOptions::default(), policy)
+    };
+
+    for (bad, byte) in [(0, 20), (2, 20), (2, 1), (5, 20)] {
+        let name = format!("wal_recovery_{}_{}", bad, byte);
+        let (dir, path) = corrupt_log(&name, bad, byte);
+        let offset = offsets[bad];
+        assert!(matches!(
+            recover(&dir, RecoveryPolicy::Strict),
+            Err(EngineError::WalCorruption { offset: at }) if at == offset
+        ));
+
+        match recover(&dir, RecoveryPolicy::TolerateTail) {
+            Ok((graph, report)) => {
+                assert_eq!(bad, 5, "only a bad last record is a torn tail");
+                assert_eq!((report.applied, report.skipped, report.first_error_offset), (5, 1, Some(offset)));
+                assert_eq!(graph_rows(&graph).0.len(), 5);
+            }
+            Err(e) => {
+                assert_ne!(bad, 5);
+                assert!(matches!(e, EngineError::WalCorruption { offset: at } if at == offset), "{:?}", e);
+            }
+        }
+
+        // The flipped length header of (2, 1) is resynchronized past
+        let (graph, report) = recover(&dir, RecoveryPolicy::SkipCorrupt).unwrap();
+        assert_eq!((report.applied, report.skipped), (5, 1));
+        assert_eq!((report.first_error_offset, report.first_error_file.as_deref()), (Some(offset), Some(path.as_path())));
+        let ids: Vec<u64> = graph_rows(&graph).0.iter().map(|n| n.0).collect();
+        assert_eq!(ids, (1..=6).filter(|id| *id != bad as u64 + 1).collect::<Vec<_>>());
+        assert!(graph.verify_indexes().is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A clean log reports nothing skipped
+    let dir = branch_dir("wal_recovery_clean");
+    let writer = WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap();
+    writer.append(&records[0]).unwrap();
+    drop(writer);
+    let (_, report) = recover(&dir, RecoveryPolicy::Strict).unwrap();
+    assert_eq!(report, engine::index::persistence::RecoveryReport { applied: 1, ..Default::default() });
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that an index created mid-log is replayed with the nodes of that point and the
+/// later ones, and that recover brings back indexes created after the last flush
+#[test]
+fn wal_replay_recreates_indexes_where_they_were_created() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::persistence::{DdlKind, SyncPolicy, WalRecord, WalWriter};
+    use engine::index::{IndexKind, InMemoryGraphStore, StoreOptions};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_ddl");
+    let name = |s: &str| HashMap::from([("name".to_string(), Value::String(s.into()))]);
+    let mut graph = InMemoryGraphStore::new();
+    graph.enable_wal_capture();
+    graph.attach_wal_writer(WalWriter::open(&dir, SyncPolicy::EveryRecord).unwrap());
+    let a = graph.add_node(vec!["Person".into()], name("a")).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let gone = graph.add_node(vec!["Person".into()], name("b")).unwrap();
+    graph.delete_node(gone, false).unwrap();
+    graph.create_property_index("Person", "name").unwrap();
+    graph.create_prefix_index("Person", "name", false).unwrap();
+    graph.create_text_index("Person", "name").unwrap();
+    assert!(graph.drop_text_index("Person", "name").unwrap());
+    assert!(!graph.drop_text_index("Person", "name").unwrap());
+    let c = graph.add_node(vec!["Person".into()], name("c")).unwrap();
+    graph.rebuild_indexes();
+
+    let records = graph.take_wal_records();
+    let ddl: Vec<&WalRecord> = records.iter()
+        .filter(|r| matches!(r, WalRecord::CreateIndex { .. } | WalRecord::DropIndex { .. }))
+        .collect();
+    assert_eq!(ddl.len(), 4);
+    assert_eq!(ddl[3], &WalRecord::DropIndex {
+        kind: DdlKind::Index(IndexKind::Text),
+        label: "Person".into(),
+        keys: vec!["name".into()],
+    });
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    assert_eq!(replayed.list_indexes(), graph.list_indexes());
+    assert!(replayed.scan_by_index("Person", "name", &Value::String("b".into())).unwrap().is_empty());
+    let ids = |nodes: Vec<engine::index::Node>| nodes.into_iter().map(|n| n.id).collect::<Vec<_>>();
+    assert_eq!(ids(replayed.scan_by_index("Person", "name", &Value::String("c".into())).unwrap()), vec![c]);
+    assert_eq!(replayed.prefix_indexes(), vec![("Person".to_string(), "name".to_string(), false)]);
+    assert!(replayed.text_indexes().is_empty());
+    assert!(replayed.verify_indexes().is_ok());
+
+    // The indexes were created after the flush: only the WAL has them
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(recovered.list_indexes(), graph.list_indexes());
+    assert_eq!(ids(recovered.scan_by_index("Person", "name", &Value::String("a".into())).unwrap()), vec![a]);
+    assert!(recovered.verify_indexes().is_ok());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Test that a unique constraint created after its duplicates were deleted replays over
+/// segments still holding a later duplicate
+#[test]
+fn wal_replay_installs_unique_constraints_that_segments_would_fail() {
+    use casys_core::{GraphWriteStore, Value};
+    use engine::index::InMemoryGraphStore;
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let x = || HashMap::from([("x".to_string(), Value::Int(1))]);
+    let mut graph = InMemoryGraphStore::new();
+    graph.enable_wal_capture();
+    graph.add_node(vec!["Person".into()], x()).unwrap();
+    let duplicate = graph.add_node(vec!["Person".into()], x()).unwrap();
+    graph.delete_node(duplicate, false).unwrap();
+    graph.create_unique_constraint("Person", "x").unwrap();
+    assert!(graph.drop_unique_constraint("Person", "x").unwrap());
+    graph.add_node(vec!["Person".into()], x()).unwrap();
+    graph.flush(&store, root, &db).unwrap();
+    let records = graph.take_wal_records();
+
+    // Over the segments the second holder of x = 1 already exists when the constraint
+    // replays; checking it there would fail
+    let mut checked = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    assert!(matches!(checked.create_unique_constraint("Person", "x"), Err(EngineError::InvalidArgument(_))));
+    let mut loaded = InMemoryGraphStore::load(&store, root, &db).unwrap();
+    loaded.replay_wal(&records).unwrap();
+    assert_eq!(graph_rows(&loaded), graph_rows(&graph));
+    assert!(loaded.unique_constraints().is_empty());
+    assert!(loaded.has_property_index("Person", "x"));
+    assert!(loaded.verify_indexes().is_ok());
+
+    let mut replayed = InMemoryGraphStore::new();
+    replayed.replay_wal(&records).unwrap();
+    assert_eq!(graph_rows(&replayed), graph_rows(&graph));
+    assert_eq!(replayed.list_indexes(), graph.list_indexes());
+}
+
+/// Test that an archive hook receives every sealed WAL file, that a failing hook loses
+/// nothing, and that the archived files restore the graph
+#[test]
+fn wal_archive_hook_ships_sealed_files_that_restore_the_graph() {
+    use casys_core::GraphWriteStore;
+    use engine::index::persistence::{ArchiveHook, FsArchive, Lsn, SyncPolicy, WalWriter};
+    use engine::index::{InMemoryGraphStore, StoreOptions};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Flaky {
+        archive: FsArchive,
+        down: AtomicBool,
+    }
+    impl ArchiveHook for Flaky {
+        fn on_rotate(&self, path: &Path, first_lsn: Lsn, last_lsn: Lsn) -> Result<(), EngineError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(EngineError::StorageIo("archive unreachable".into()));
+            }
+            self.archive.on_rotate(path, first_lsn, last_lsn)
+        }
+    }
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let dir = branch_dir("wal_archive");
+    let hook = Arc::new(Flaky { archive: FsArchive::new(branch_dir("wal_archive_copies")), down: AtomicBool::new(false) });
+    let writer = WalWriter::open(&dir, SyncPolicy::Manual).unwrap().with_max_file_bytes(200).with_archive_hook(hook.clone());
+    let mut graph = InMemoryGraphStore::new();
+    graph.attach_wal_writer(writer);
+    for _ in 0..10 {
+        graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    }
+    let writer = graph.wal_writer().unwrap();
+    let sealed = writer.files().len() - 1;
+    assert!(sealed >= 2);
+    assert_eq!(hook.archive.files().unwrap().len(), sealed);
+    assert!(writer.archived_lsn() > 0 && writer.archive_error().is_none());
+    let archived = writer.archived_lsn();
+
+    // Appends and checkpoints go on while the hook fails; the unarchived files stay
+    hook.down.store(true, Ordering::SeqCst);
+    for _ in 0..5 {
+        graph.add_node(vec!["Person".into()], HashMap::new()).unwrap();
+    }
+    graph.checkpoint(&store, root, &db).unwrap();
+    let writer = graph.wal_writer().unwrap();
+    assert!(matches!(writer.archive_error(), Some(EngineError::StorageIo(_))));
+    assert_eq!(writer.archived_lsn(), archived);
+    let kept = writer.files();
+    assert!(kept.len() > 1 && kept.iter().all(|f| f.exists()));
+    let recovered = InMemoryGraphStore::recover(&store, root, &db, &dir, StoreOptions::default()).unwrap();
+    assert_eq!(graph_rows(&recovered), graph_rows(&graph));
+
+    hook.down.store(false, Ordering::SeqCst);
+    let writer = graph.detach_wal_writer().unwrap();
+    writer.archive_sealed().unwrap();
+    assert!(writer.archive_error().is_none());
+    assert_eq!((writer.archived_lsn(), writer.files().len()), (15, 1));
+    assert!(kept[..kept.len() - 1].iter().all(|f| !f.exists()));
+
+    // Restored from nothing but the archive, whole or file by file
+    let files = hook.archive.files().unwrap();
+    let names: Vec<String> = files.iter().map(|f| f.file_name().unwrap().to_string_lossy().into_owned()).collect();
+    assert!(names.first().unwrap().starts_with("00000000000000000001-"));
+    assert!(names.last().unwrap().ends_with("-00000000000000000015.wal"));
+    let mut whole = InMemoryGraphStore::new();
+    let bytes: Vec<u8> = files.iter().flat_map(|f| std::fs::read(f).unwrap()).collect();
+    assert_eq!(whole.replay_archived(&bytes[..]).unwrap(), 15);
+    assert_eq!(whole.replay_archived(&bytes[..]).unwrap(), 0);
+    let mut by_file = InMemoryGraphStore::new();
+    for file in &files {
+        by_file.replay_archived(std::fs::File::open(file).unwrap()).unwrap();
+    }
+    assert_eq!(graph_rows(&whole), graph_rows(&graph));
+    assert_eq!(graph_rows(&by_file), graph_rows(&graph));
+    assert_eq!(by_file.last_applied_lsn(), 15);
+
+    // A damaged archive fails the restore rather than stopping short
+    let mut torn = bytes.clone();
+    torn.truncate(bytes.len() - 1);
+    assert!(matches!(InMemoryGraphStore::new().replay_archived(&torn[..]), Err(EngineError::WalCorruption { .. })));
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(hook.archive.dir()).unwrap();
+}
+
+/// Test that check_indexes classifies drift in loaded segments and repair_indexes fixes it
+#[test]
+fn check_and_repair_indexes_after_load() {
+    use casys_core::{GraphReadStore, Value, ValueKey};
+    use engine::index::{IndexEntry, IndexKind, IndexStructure};
+
+    let store = MockSegmentStore::new();
+    let root = Path::new("/fake/root");
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let nodes = br#"{"count":2,"nodes":[
+        {"id":1,"labels":["Person"],"properties":{"name":"Ann"}},
+        {"id":2,"labels":["Person"],"properties":{"name":"Ann"},"deleted":true}
+    ]}"#;
+    let edges = br#"{"count":2,"edges":[
+        {"id":1,"from":1,"to":1,"type":"KNOWS","properties":{}},
+        {"id":1,"from":1,"to":1,"type":"KNOWS","properties":{}}
+    ]}"#;
+    // Node 2 is tombstoned, node 3 never existed, and node 1 is not called Bob
+    let indexes = br#"{"format_version":1,"indexes":[{"kind":"property","label":"Person","key":"name",
+        "data":[[["string","Ann"],[1,2,3]],[["string","Bob"],[1]]]}]}"#;
+    store.write_segment(root, &db, &SegmentId("nodes".to_string()), nodes, 2, 0).unwrap();
+    store.write_segment(root, &db, &SegmentId("edges".to_string()), edges, 0, 2).unwrap();
+    store.write_segment(root, &db, &SegmentId("indexes".to_string()), indexes, 0, 0).unwrap();
+
+    let mut loaded = engine::index::InMemoryGraphStore::load(&store, root, &db).unwrap();
+    let property = |value: &str, node| IndexEntry::Property {
+        label: "Person".into(),
+        key: "name".into(),
+        value: ValueKey::String(value.into()),
+        node,
+    };
+    let report = loaded.check_indexes();
+    assert!(!report.is_clean());
+    assert_eq!(report.dangling, vec![property("Ann", 2), property("Ann", 3)]);
+    assert_eq!(report.stale, vec![property("Bob", 1)]);
+    assert!(report.missing.is_empty());
+    assert_eq!(report.duplicates, vec![
+        (IndexEntry::Outgoing { node: 1, edge: 1 }, 2),
+        (IndexEntry::Incoming { node: 1, edge: 1 }, 2),
+        (IndexEntry::EdgeType { edge_type: "KNOWS".into(), edge: 1 }, 2),
+    ]);
+    assert!(report.count_mismatches.is_empty());
+    assert!(report.repaired.is_empty());
+    let affected = vec![IndexStructure::Adjacency, IndexStructure::EdgeTypes, IndexStructure::Secondary(IndexKind::Property)];
+    assert_eq!(report.affected(), affected);
+
+    let repaired = loaded.repair_indexes();
+    assert_eq!(repaired.repaired, affected);
+    assert_eq!(repaired.dangling, report.dangling);
+    assert!(loaded.check_indexes().is_clean());
+    assert!(loaded.verify_indexes().is_ok());
+    assert_eq!(loaded.scan_by_property(Some("Person"), "name", &Value::String("Ann".into())).unwrap().len(), 1);
+    assert_eq!(loaded.get_neighbors(1, None).unwrap().len(), 1);
+}
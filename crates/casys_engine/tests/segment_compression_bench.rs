@@ -0,0 +1,92 @@
+//! Flush and load time, segment size and compression ratio of a 500k-node, 1M-edge store
+//! with uncompressed, fast and best (zstd levels 3 and 19) compressed binary segments.
+//! The nodes carry the repeated keys, labels and short texts of a typical user graph.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --features compression --test segment_compression_bench -- --ignored --nocapture`
+#![cfg(feature = "compression")]
+
+use casys_core::{DatabaseName, EngineError, GraphReadStore, GraphWriteStore, SegmentId, SegmentStore, Value};
+use casys_engine::index::{CompressionOption, InMemoryGraphStore, StoreOptions};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Keeps segments in memory, so only serialization is measured
+#[derive(Default)]
+struct MemorySegments(Mutex<HashMap<String, Vec<u8>>>);
+
+impl SegmentStore for MemorySegments {
+    fn write_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId, data: &[u8], _: u64, _: u64) -> Result<(), EngineError> {
+        self.0.lock().unwrap().insert(id.0.clone(), data.to_vec());
+        Ok(())
+    }
+
+    fn read_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError> {
+        let data = self.0.lock().unwrap().get(&id.0).cloned().ok_or_else(|| EngineError::NotFound(id.0.clone()))?;
+        Ok((data, 0, 0))
+    }
+}
+
+#[test]
+#[ignore]
+fn compressed_flush_and_load_500k_nodes() {
+    const NODES: u64 = 500_000;
+    const CITIES: [&str; 8] = ["Paris", "Lyon", "Berlin", "Madrid", "Lisbon", "Rome", "Vienna", "Oslo"];
+    const ROLES: [&str; 4] = ["engineer", "designer", "manager", "analyst"];
+    let root = Path::new("/bench");
+    let db = DatabaseName::try_from("bench").unwrap();
+
+    let mut plain_bytes = 0;
+    for compression in [CompressionOption::None, CompressionOption::Fast, CompressionOption::Best] {
+        let options = StoreOptions { segment_compression: compression, ..Default::default() };
+        let mut graph = InMemoryGraphStore::with_options(options.clone());
+        for i in 0..NODES {
+            let city = CITIES[(i % 8) as usize];
+            let role = ROLES[(i % 4) as usize];
+            let props = HashMap::from([
+                ("name".to_string(), Value::String(format!("user-{}", i))),
+                ("email".to_string(), Value::String(format!("user-{}@example.com", i))),
+                ("city".to_string(), Value::String(city.to_string())),
+                ("role".to_string(), Value::String(role.to_string())),
+                ("bio".to_string(), Value::String(format!("{} based in {}, joined in {}", role, city, 2000 + i % 25))),
+                ("age".to_string(), Value::Int((i % 60 + 18) as i64)),
+                ("active".to_string(), Value::Bool(i % 3 != 0)),
+            ]);
+            let labels = if i % 5 == 0 { vec!["Person".into(), "Employee".into()] } else { vec!["Person".into()] };
+            graph.add_node(labels, props).unwrap();
+        }
+        for i in 0..2 * NODES {
+            let props = HashMap::from([("since".to_string(), Value::Int((2000 + i % 25) as i64))]);
+            let edge_type = if i % 2 == 0 { "KNOWS" } else { "WORKS_WITH" };
+            graph.add_edge(i % NODES + 1, (i * 7919) % NODES + 1, edge_type.into(), props).unwrap();
+        }
+
+        let store = MemorySegments::default();
+        let start = Instant::now();
+        graph.flush(&store, root, &db).unwrap();
+        let flushed = start.elapsed();
+        let bytes: usize = ["nodes", "edges"].iter().map(|id| store.0.lock().unwrap()[*id].len()).sum();
+        drop(graph);
+
+        let start = Instant::now();
+        let loaded = InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+        let load = start.elapsed();
+        assert_eq!(loaded.node_count().unwrap(), NODES as usize);
+        if compression == CompressionOption::None {
+            plain_bytes = bytes;
+        }
+        let mib = |bytes: usize| bytes as f64 / (1 << 20) as f64;
+        println!(
+            "{:?}: {:.1} MiB, ratio {:.2}:1, flush {:?} ({:.0} MiB/s), load {:?} ({:.0} MiB/s)",
+            compression,
+            mib(bytes),
+            plain_bytes as f64 / bytes as f64,
+            flushed,
+            mib(plain_bytes) / flushed.as_secs_f64(),
+            load,
+            mib(plain_bytes) / load.as_secs_f64(),
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Flush and load time and segment size of a 1M-node, 1M-edge store with JSON vs binary
+//! node and edge segments.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test segment_format_bench -- --ignored --nocapture`
+
+use casys_core::{DatabaseName, EngineError, GraphReadStore, GraphWriteStore, SegmentId, SegmentStore, Value};
+use casys_engine::index::{InMemoryGraphStore, SegmentFormat, StoreOptions};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Keeps segments in memory, so only serialization is measured
+#[derive(Default)]
+struct MemorySegments(Mutex<HashMap<String, Vec<u8>>>);
+
+impl SegmentStore for MemorySegments {
+    fn write_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId, data: &[u8], _: u64, _: u64) -> Result<(), EngineError> {
+        self.0.lock().unwrap().insert(id.0.clone(), data.to_vec());
+        Ok(())
+    }
+
+    fn read_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError> {
+        let data = self.0.lock().unwrap().get(&id.0).cloned().ok_or_else(|| EngineError::NotFound(id.0.clone()))?;
+        Ok((data, 0, 0))
+    }
+}
+
+#[test]
+#[ignore]
+fn json_vs_binary_flush_and_load_1m_nodes() {
+    const NODES: u64 = 1_000_000;
+    let root = Path::new("/bench");
+    let db = DatabaseName::try_from("bench").unwrap();
+
+    for format in [SegmentFormat::Json, SegmentFormat::Binary] {
+        let options = StoreOptions { segment_format: format, ..Default::default() };
+        let mut graph = InMemoryGraphStore::with_options(options.clone());
+        for i in 0..NODES {
+            let props = HashMap::from([
+                ("name".to_string(), Value::String(format!("node-{}", i))),
+                ("score".to_string(), Value::Float(i as f64 / 3.0)),
+                ("age".to_string(), Value::Int((i % 90) as i64)),
+            ]);
+            graph.add_node(vec!["Person".into()], props).unwrap();
+        }
+        for i in 0..NODES {
+            let props = HashMap::from([("weight".to_string(), Value::Int(i as i64))]);
+            graph.add_edge(i + 1, (i * 7919) % NODES + 1, "KNOWS".into(), props).unwrap();
+        }
+
+        let store = MemorySegments::default();
+        let start = Instant::now();
+        graph.flush(&store, root, &db).unwrap();
+        let flushed = start.elapsed();
+        let bytes: usize = ["nodes", "edges"].iter().map(|id| store.0.lock().unwrap()[*id].len()).sum();
+        drop(graph);
+
+        let start = Instant::now();
+        let loaded = InMemoryGraphStore::load_with_options(&store, root, &db, options).unwrap();
+        let load = start.elapsed();
+        assert_eq!(loaded.node_count().unwrap(), NODES as usize);
+        println!("{:?}: {} MiB, flush {:?}, load {:?}", format, bytes >> 20, flushed, load);
+    }
+}
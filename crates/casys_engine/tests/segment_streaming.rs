@@ -0,0 +1,134 @@
+//! Flush and load stream node and edge segments: the memory they allocate on top of the
+//! store stays well below the size of the segments.
+//!
+//! Allocations are counted by this binary's global allocator, so it holds a single test:
+//! another running alongside would count too.
+
+use casys_core::{DatabaseName, EngineError, GraphReadStore, GraphWriteStore, SegmentId, SegmentStore, Value};
+use casys_engine::index::{InMemoryGraphStore, SegmentFormat, StoreOptions};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+struct Tracking;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+/// Bytes allocated at the peak of `f` beyond what was live before it, and its result.
+fn peak_during<T>(f: impl FnOnce() -> T) -> (usize, T) {
+    let before = LIVE.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let result = f();
+    (PEAK.load(Ordering::Relaxed) - before, result)
+}
+
+/// Segments as plain files, streamed through small buffers
+struct FileSegments(PathBuf);
+
+impl FileSegments {
+    fn path(&self, id: &SegmentId) -> PathBuf {
+        self.0.join(&id.0)
+    }
+}
+
+impl SegmentStore for FileSegments {
+    fn write_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId, data: &[u8], _: u64, _: u64) -> Result<(), EngineError> {
+        fs::write(self.path(id), data).map_err(|e| EngineError::StorageIo(e.to_string()))
+    }
+
+    fn read_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId) -> Result<(Vec<u8>, u64, u64), EngineError> {
+        let data = fs::read(self.path(id)).map_err(|_| EngineError::NotFound(id.0.clone()))?;
+        Ok((data, 0, 0))
+    }
+
+    fn write_segment_with(
+        &self,
+        _: &Path,
+        _: &DatabaseName,
+        id: &SegmentId,
+        _: u64,
+        _: u64,
+        write: &mut dyn FnMut(&mut dyn Write) -> std::io::Result<()>,
+    ) -> Result<(), EngineError> {
+        let file = File::create(self.path(id)).map_err(|e| EngineError::StorageIo(e.to_string()))?;
+        let mut out = BufWriter::new(file);
+        write(&mut out).and_then(|_| out.flush()).map_err(|e| EngineError::StorageIo(e.to_string()))
+    }
+
+    fn open_segment(&self, _: &Path, _: &DatabaseName, id: &SegmentId) -> Result<(Box<dyn Read + '_>, u64, u64), EngineError> {
+        let file = File::open(self.path(id)).map_err(|_| EngineError::NotFound(id.0.clone()))?;
+        Ok((Box::new(BufReader::new(file)), 0, 0))
+    }
+}
+
+#[test]
+fn flush_and_load_allocate_far_less_than_the_segments() {
+    const NODES: u64 = 100_000;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let dir = std::env::temp_dir().join(format!("casys_segment_streaming_{}", now));
+    fs::create_dir_all(&dir).unwrap();
+    let store = FileSegments(dir.clone());
+    let root = Path::new("/unused");
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    for format in [SegmentFormat::Json, SegmentFormat::Binary] {
+        let options = StoreOptions { segment_format: format, ..Default::default() };
+        let mut graph = InMemoryGraphStore::with_options(options.clone());
+        let nodes: Vec<_> = (0..NODES)
+            .map(|i| {
+                let props = HashMap::from([
+                    ("name".to_string(), Value::String(format!("node-{:040}", i))),
+                    ("age".to_string(), Value::Int((i % 90) as i64)),
+                ]);
+                (vec!["Person".to_string()], props)
+            })
+            .collect();
+        graph.add_nodes_bulk(nodes).unwrap();
+        for i in 1..NODES {
+            graph.add_edge(i, i + 1, "NEXT".into(), HashMap::new()).unwrap();
+        }
+
+        let (flush_peak, flushed) = peak_during(|| graph.flush(&store, root, &db));
+        flushed.unwrap();
+        let segments: usize = ["nodes", "edges"].iter().map(|id| fs::metadata(dir.join(id)).unwrap().len() as usize).sum();
+        drop(graph);
+
+        // Loading allocates the store itself; compare what is left over once it is built
+        let (load_peak, loaded) = peak_during(|| InMemoryGraphStore::load_with_options(&store, root, &db, options));
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.node_count().unwrap(), NODES as usize);
+        assert_eq!(loaded.edge_count().unwrap(), NODES as usize - 1);
+        let held = LIVE.load(Ordering::Relaxed);
+        drop(loaded);
+        let store_size = held - LIVE.load(Ordering::Relaxed);
+
+        // The id list written in order is the only allocation growing with the store
+        assert!(flush_peak < segments / 8, "{:?}: flush peak {} for {} bytes of segments", format, flush_peak, segments);
+        assert!(
+            load_peak < store_size + segments / 8,
+            "{:?}: load peak {} for a store of {} and {} bytes of segments", format, load_peak, store_size, segments
+        );
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
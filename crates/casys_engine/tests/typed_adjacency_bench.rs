@@ -0,0 +1,40 @@
+//! Timing comparison of typed neighbor queries on a node with 100k incident edges, with
+//! and without `StoreOptions::typed_adjacency`.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test typed_adjacency_bench -- --ignored --nocapture`
+
+use casys_engine::index::{InMemoryGraphStore, StoreOptions};
+use casys_core::{Direction, GraphReadStore, GraphWriteStore};
+use std::collections::HashMap;
+use std::time::Instant;
+
+#[test]
+#[ignore]
+fn typed_adjacency_on_high_degree_node() {
+    const EDGES: u64 = 100_000;
+    const RARE_EVERY: u64 = 1_000;
+    const QUERIES: usize = 1_000;
+    let mut timings = Vec::new();
+    for typed_adjacency in [false, true] {
+        let mut store = InMemoryGraphStore::with_options(StoreOptions { typed_adjacency, ..Default::default() });
+        let hub = store.add_node(vec!["Hub".into()], HashMap::new()).unwrap();
+        for i in 0..EDGES {
+            let leaf = store.add_node(vec!["Leaf".into()], HashMap::new()).unwrap();
+            let edge_type = if i % RARE_EVERY == 0 { "RARE" } else { "COMMON" };
+            store.add_edge(hub, leaf, edge_type.into(), HashMap::new()).unwrap();
+        }
+
+        let start = Instant::now();
+        let mut found = 0;
+        for _ in 0..QUERIES {
+            found += store.get_neighbor_ids(hub, Some("RARE"), Direction::Outgoing).unwrap().len();
+            found += store.degree(hub, Direction::Outgoing, Some("RARE")).unwrap();
+        }
+        let elapsed = start.elapsed();
+        assert_eq!(found, QUERIES * 2 * (EDGES / RARE_EVERY) as usize);
+        timings.push(elapsed);
+    }
+    println!("{} edges, {} x (get_neighbor_ids + degree) for 1 in {}: plain {:?}, typed {:?}",
+        EDGES, QUERIES, RARE_EVERY, timings[0], timings[1]);
+}
@@ -0,0 +1,59 @@
+//! Size and replay time of a WAL of records carrying multi-kilobyte JSON blobs, plain vs
+//! compressed with `DeflateCompressor`.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --features deflate --test wal_compression_bench -- --ignored --nocapture`
+#![cfg(feature = "deflate")]
+
+use casys_engine::index::persistence::{Compression, SyncPolicy, WalReader, WalRecord, WalWriter};
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::Value;
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[test]
+#[ignore]
+fn plain_vs_compressed_replay_of_large_records() {
+    const RECORDS: u64 = 100_000;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let dir = std::env::current_dir().unwrap().join("target").join("tmp").join(format!("wal_compression_bench_{}", now));
+    // About 4 KiB of JSON per record, varying from one record to the next
+    let record = |id: u64| {
+        let items: Vec<String> = (0..40)
+            .map(|i| format!(r#"{{"id":{},"kind":"event","source":"sensor-{}","value":{}}}"#, id * 40 + i, i % 7, id % 1000))
+            .collect();
+        let blob = format!("[{}]", items.join(","));
+        WalRecord::AddNode {
+            id,
+            labels: vec!["Doc".into()],
+            properties: HashMap::from([("body".to_string(), Value::String(blob))]),
+        }
+    };
+
+    for compression in [None, Some(Compression::deflate())] {
+        let name = if compression.is_some() { "deflate" } else { "plain" };
+        let branch = dir.join(name);
+        let mut writer = WalWriter::open(&branch, SyncPolicy::Manual).unwrap();
+        if let Some(compression) = compression {
+            writer = writer.with_compression(compression);
+        }
+        let start = Instant::now();
+        for id in 1..=RECORDS {
+            writer.append(&record(id)).unwrap();
+        }
+        writer.sync().unwrap();
+        let written = start.elapsed();
+        let bytes: u64 = writer.files().iter().map(|f| std::fs::metadata(f).unwrap().len()).sum();
+        drop(writer);
+
+        let start = Instant::now();
+        let records = WalReader::open_branch(&branch, 1).unwrap().read_all().unwrap();
+        let read = start.elapsed();
+        let mut store = InMemoryGraphStore::new();
+        store.replay_wal(&records).unwrap();
+        let replayed = start.elapsed();
+        assert_eq!(records.len() as u64, RECORDS);
+        println!("{}: {} MiB, write {:?}, read {:?}, read + replay {:?}", name, bytes >> 20, written, read, replayed);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,63 @@
+//! Throughput of per-record fsync vs group commit with concurrent committers.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test wal_group_commit_bench -- --ignored --nocapture`
+
+use casys_engine::index::persistence::{GroupCommitConfig, GroupCommitWriter, SyncPolicy, WalRecord, WalWriter};
+use casys_core::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[test]
+#[ignore]
+fn group_commit_vs_fsync_per_record() {
+    const THREADS: u64 = 8;
+    const PER_THREAD: u64 = 500;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let dir = std::env::current_dir().unwrap().join("target").join("tmp").join(format!("wal_bench_{}", now));
+    let record = |id| WalRecord::AddNode {
+        id,
+        labels: vec!["P".into()],
+        properties: HashMap::from([("name".to_string(), Value::String("x".repeat(32)))]),
+    };
+
+    let writer = Mutex::new(WalWriter::open(&dir.join("per_record"), SyncPolicy::EveryRecord).unwrap());
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for t in 0..THREADS {
+            let writer = &writer;
+            s.spawn(move || {
+                for i in 0..PER_THREAD {
+                    writer.lock().unwrap().append(&record(t * PER_THREAD + i)).unwrap();
+                }
+            });
+        }
+    });
+    let per_record = start.elapsed();
+
+    let config = GroupCommitConfig { max_records: THREADS as usize, max_wait: Duration::from_millis(1) };
+    let group = GroupCommitWriter::new(WalWriter::open(&dir.join("group"), SyncPolicy::Manual).unwrap(), config);
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for t in 0..THREADS {
+            let group = &group;
+            s.spawn(move || {
+                for i in 0..PER_THREAD {
+                    group.commit(&record(t * PER_THREAD + i)).unwrap();
+                }
+            });
+        }
+    });
+    let grouped = start.elapsed();
+
+    let total = (THREADS * PER_THREAD) as f64;
+    println!(
+        "{} records, {} threads: fsync per record {:.0} records/s, group commit {:.0} records/s",
+        total,
+        THREADS,
+        total / per_record.as_secs_f64(),
+        total / grouped.as_secs_f64()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
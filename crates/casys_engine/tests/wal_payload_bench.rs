@@ -0,0 +1,54 @@
+//! Size and replay time of a 1M-record WAL with JSON vs binary payloads.
+//!
+//! Ignored by default; run with
+//! `cargo test --release -p casys_engine --test wal_payload_bench -- --ignored --nocapture`
+
+use casys_engine::index::persistence::{PayloadFormat, SyncPolicy, WalReader, WalRecord, WalWriter};
+use casys_engine::index::InMemoryGraphStore;
+use casys_core::Value;
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[test]
+#[ignore]
+fn json_vs_binary_replay_1m_records() {
+    const RECORDS: u64 = 1_000_000;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let dir = std::env::current_dir().unwrap().join("target").join("tmp").join(format!("wal_payload_bench_{}", now));
+    let props = HashMap::from([
+        ("name".to_string(), Value::String("x".repeat(32))),
+        ("score".to_string(), Value::Float(0.5)),
+        ("age".to_string(), Value::Int(42)),
+        ("tags".to_string(), Value::Array(vec![Value::String("a".into()), Value::String("b".into())])),
+    ]);
+    // Half the records add nodes, the other half set a property on them
+    let record = |i: u64| {
+        let id = i / 2 + 1;
+        if i.is_multiple_of(2) {
+            WalRecord::AddNode { id, labels: vec!["P".into()], properties: props.clone() }
+        } else {
+            WalRecord::SetNodeProperty { id, key: "score".into(), value: Value::Float(i as f64) }
+        }
+    };
+
+    for format in [PayloadFormat::Json, PayloadFormat::Binary] {
+        let branch = dir.join(format!("{:?}", format));
+        let writer = WalWriter::open(&branch, SyncPolicy::Manual).unwrap().with_payload_format(format);
+        for i in 0..RECORDS {
+            writer.append(&record(i)).unwrap();
+        }
+        writer.sync().unwrap();
+        let bytes: u64 = writer.files().iter().map(|f| std::fs::metadata(f).unwrap().len()).sum();
+        drop(writer);
+
+        let start = Instant::now();
+        let records = WalReader::open_branch(&branch, 1).unwrap().read_all().unwrap();
+        let read = start.elapsed();
+        let mut store = InMemoryGraphStore::new();
+        store.replay_wal(&records).unwrap();
+        let replayed = start.elapsed();
+        assert_eq!(records.len() as u64, RECORDS);
+        println!("{:?}: {} MiB, read {:?}, read + replay {:?}", format, bytes >> 20, read, replayed);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -26,6 +26,43 @@ impl SegmentStore for FsBackend {
         let seg = segments::read_segment(root, db, &segment_id.0)?;
         Ok((seg.data, seg.header.node_count, seg.header.edge_count))
     }
+
+    fn write_segment_with(
+        &self,
+        root: &Path,
+        db: &DatabaseName,
+        segment_id: &SegmentId,
+        node_count: u64,
+        edge_count: u64,
+        write: &mut dyn FnMut(&mut dyn std::io::Write) -> std::io::Result<()>,
+    ) -> Result<(), EngineError> {
+        segments::write_segment_with(root, db, &segment_id.0, node_count, edge_count, write)?;
+        Ok(())
+    }
+
+    fn stage_segment_with(
+        &self,
+        root: &Path,
+        db: &DatabaseName,
+        segment_id: &SegmentId,
+        node_count: u64,
+        edge_count: u64,
+        write: &mut dyn FnMut(&mut dyn std::io::Write) -> std::io::Result<()>,
+    ) -> Result<(), EngineError> {
+        segments::stage_segment_with(root, db, &segment_id.0, node_count, edge_count, write)?;
+        Ok(())
+    }
+
+    fn publish_segments(&self, root: &Path, db: &DatabaseName, segment_ids: &[SegmentId]) -> Result<(), EngineError> {
+        let ids: Vec<&str> = segment_ids.iter().map(|id| id.0.as_str()).collect();
+        segments::publish_segments(root, db, &ids)
+    }
+
+    fn open_segment(&self, root: &Path, db: &DatabaseName, segment_id: &SegmentId) -> Result<(Box<dyn std::io::Read + '_>, u64, u64), EngineError> {
+        let seg = segments::open_segment(root, db, &segment_id.0)?;
+        let (node_count, edge_count) = (seg.header.node_count, seg.header.edge_count);
+        Ok((Box::new(seg), node_count, edge_count))
+    }
 }
 
 fn to_meta(m: &mf::Manifest) -> ManifestMeta {
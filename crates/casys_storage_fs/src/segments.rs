@@ -1,13 +1,33 @@
+//! Segment files: a header (magic, version, counts, CRC32 of the data), then the data.
+//!
+//! Each write goes to a temporary file that is fsynced and renamed into place. Segments
+//! live in `<db>/segments/<2-char prefix>/<id>.<generation>.seg`, and
+//! `<db>/segments/manifest.json` records the current generation of each: a flush stages
+//! all its segments as a new generation with `stage_segment_with`, then makes them
+//! current together with `publish_segments`, so a crash part way leaves the previous set.
+//! Segments missing from the manifest are read from `<id>.seg`, where they were written
+//! before it existed.
+//!
+//! Files the manifest does not make current are never read again: `retire_segments`
+//! drops segments from it, and `remove_unreferenced_segments` deletes what is left over.
+
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
-    io::{Read, Write},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 
+use serde::{Deserialize, Serialize};
+
 use casys_core::{DatabaseName, EngineError};
+use crate::util::{atomic_write_file, fsync_dir};
 
 const SEGMENT_MAGIC: u32 = 0x43415353; // "CASS" for Casys
 const SEGMENT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 26;
+/// Offset of the checksum in the header, patched once streamed data is written
+const CHECKSUM_OFFSET: u64 = 22;
 
 #[derive(Debug, Clone)]
 pub struct SegmentHeader {
@@ -40,7 +60,7 @@ impl SegmentHeader {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, EngineError> {
-        if bytes.len() < 26 {
+        if bytes.len() < HEADER_LEN {
             return Err(EngineError::StorageIo("segment header too short".into()));
         }
         let magic = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
@@ -71,49 +91,377 @@ impl Segment {
     }
 
     pub fn write_to_path(&self, path: &Path) -> Result<(), EngineError> {
-        if let Some(p) = path.parent() {
-            fs::create_dir_all(p).map_err(|e| EngineError::StorageIo(format!("create_dir_all: {e}")))?;
-        }
-        let mut f = File::create(path).map_err(|e| EngineError::StorageIo(format!("create({}): {e}", path.display())))?;
-        f.write_all(&self.header.to_bytes())
-            .and_then(|_| f.write_all(&self.data))
-            .and_then(|_| f.sync_all())
-            .map_err(|e| EngineError::StorageIo(format!("write segment: {e}")))
+        let (node_count, edge_count) = (self.header.node_count, self.header.edge_count);
+        write_atomically(path, node_count, edge_count, &mut |out| out.write_all(&self.data))
     }
 
+    /// Read a segment, failing with `EngineError::SegmentCorruption` if its header does not
+    /// parse or its data does not match the checksum.
     pub fn read_from_path(path: &Path) -> Result<Self, EngineError> {
-        let mut f = File::open(path).map_err(|e| EngineError::StorageIo(format!("open({}): {e}", path.display())))?;
-        let mut hdr_bytes = vec![0u8; 26];
-        f.read_exact(&mut hdr_bytes).map_err(|e| EngineError::StorageIo(format!("read header: {e}")))?;
-        let header = SegmentHeader::from_bytes(&hdr_bytes)?;
+        let mut f = open_existing(path)?;
+        let header = read_header(&mut f, path)?;
         let mut data = Vec::new();
         f.read_to_end(&mut data).map_err(|e| EngineError::StorageIo(format!("read data: {e}")))?;
         let computed = crc32fast::hash(&data);
         if computed != header.checksum {
-            return Err(EngineError::StorageIo(format!("checksum mismatch: expected {:#x}, got {:#x}", header.checksum, computed)));
+            return Err(checksum_mismatch(path, header.checksum, computed));
         }
         Ok(Self { header, data })
     }
 }
 
+fn corruption(path: &Path, detail: impl std::fmt::Display) -> EngineError {
+    EngineError::SegmentCorruption { path: path.display().to_string(), detail: detail.to_string() }
+}
+
+fn checksum_mismatch(path: &Path, expected: u32, computed: u32) -> EngineError {
+    corruption(path, format!("checksum mismatch: expected {:#x}, got {:#x}", expected, computed))
+}
+
+fn read_header(f: &mut impl Read, path: &Path) -> Result<SegmentHeader, EngineError> {
+    let mut hdr_bytes = vec![0u8; HEADER_LEN];
+    f.read_exact(&mut hdr_bytes).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => corruption(path, "header cut short"),
+        _ => EngineError::StorageIo(format!("read header: {e}")),
+    })?;
+    SegmentHeader::from_bytes(&hdr_bytes).map_err(|e| match e {
+        EngineError::StorageIo(detail) => corruption(path, detail),
+        e => e,
+    })
+}
+
+/// Which generation of each segment is current, in `<db>/segments/manifest.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SegmentManifest {
+    /// Generation of the last publish, 0 before any
+    pub generation: u64,
+    /// Generation each published segment was last written in
+    #[serde(default)]
+    pub segments: BTreeMap<String, u64>,
+}
+
 fn segments_dir(root: &Path, db: &DatabaseName) -> PathBuf {
     root.join(db.as_str()).join("segments")
 }
 
-pub fn segment_path(root: &Path, db: &DatabaseName, segment_id: &str) -> PathBuf {
-    let dir = segments_dir(root, db);
+fn manifest_path(root: &Path, db: &DatabaseName) -> PathBuf {
+    segments_dir(root, db).join("manifest.json")
+}
+
+fn shard_dir(root: &Path, db: &DatabaseName, segment_id: &str) -> PathBuf {
     // Use first 2 chars as prefix for sharding
     let prefix = if segment_id.len() >= 2 { &segment_id[..2] } else { "00" };
-    dir.join(prefix).join(format!("{}.seg", segment_id))
+    segments_dir(root, db).join(prefix)
+}
+
+/// Path of a segment written before the segment manifest existed.
+pub fn segment_path(root: &Path, db: &DatabaseName, segment_id: &str) -> PathBuf {
+    shard_dir(root, db, segment_id).join(format!("{}.seg", segment_id))
+}
+
+/// Path of a segment as written in `generation`.
+pub fn generation_path(root: &Path, db: &DatabaseName, segment_id: &str, generation: u64) -> PathBuf {
+    shard_dir(root, db, segment_id).join(format!("{}.{}.seg", segment_id, generation))
+}
+
+/// The manifest of `db`, empty if nothing was published yet.
+pub fn read_segment_manifest(root: &Path, db: &DatabaseName) -> Result<SegmentManifest, EngineError> {
+    let path = manifest_path(root, db);
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| EngineError::StorageIo(format!("parse segment manifest ({}): {e}", path.display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SegmentManifest::default()),
+        Err(e) => Err(EngineError::StorageIo(format!("read({}): {e}", path.display()))),
+    }
+}
+
+/// Path of the current version of a segment.
+pub fn current_segment_path(root: &Path, db: &DatabaseName, segment_id: &str) -> Result<PathBuf, EngineError> {
+    let manifest = read_segment_manifest(root, db)?;
+    Ok(match manifest.segments.get(segment_id) {
+        Some(generation) => generation_path(root, db, segment_id, *generation),
+        None => segment_path(root, db, segment_id),
+    })
 }
 
+/// Write and publish a single segment.
 pub fn write_segment(root: &Path, db: &DatabaseName, segment_id: &str, seg: &Segment) -> Result<PathBuf, EngineError> {
-    let path = segment_path(root, db, segment_id);
+    let path = generation_path(root, db, segment_id, read_segment_manifest(root, db)?.generation + 1);
     seg.write_to_path(&path)?;
+    publish_segments(root, db, &[segment_id])?;
     Ok(path)
 }
 
 pub fn read_segment(root: &Path, db: &DatabaseName, segment_id: &str) -> Result<Segment, EngineError> {
-    let path = segment_path(root, db, segment_id);
-    Segment::read_from_path(&path)
+    Segment::read_from_path(&current_segment_path(root, db, segment_id)?)
+}
+
+/// Write and publish a single segment whose data `write` streams out; see
+/// `stage_segment_with`.
+pub fn write_segment_with(
+    root: &Path,
+    db: &DatabaseName,
+    segment_id: &str,
+    node_count: u64,
+    edge_count: u64,
+    write: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+) -> Result<PathBuf, EngineError> {
+    let path = stage_segment_with(root, db, segment_id, node_count, edge_count, write)?;
+    publish_segments(root, db, &[segment_id])?;
+    Ok(path)
+}
+
+/// Write a segment whose data `write` streams out as part of the next generation. Reads
+/// keep returning the current version until `publish_segments` lists it.
+pub fn stage_segment_with(
+    root: &Path,
+    db: &DatabaseName,
+    segment_id: &str,
+    node_count: u64,
+    edge_count: u64,
+    write: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+) -> Result<PathBuf, EngineError> {
+    let path = generation_path(root, db, segment_id, read_segment_manifest(root, db)?.generation + 1);
+    write_atomically(&path, node_count, edge_count, write)?;
+    Ok(path)
+}
+
+/// Make the staged `segment_ids` current together by rewriting the manifest, then remove
+/// the versions they replace. Staged segments left out stay invisible and are overwritten
+/// by the next stage.
+pub fn publish_segments(root: &Path, db: &DatabaseName, segment_ids: &[&str]) -> Result<(), EngineError> {
+    let mut manifest = read_segment_manifest(root, db)?;
+    let generation = manifest.generation + 1;
+    for id in segment_ids {
+        let path = generation_path(root, db, id, generation);
+        if !path.exists() {
+            return Err(EngineError::StorageIo(format!("publish: segment {} was not staged ({})", id, path.display())));
+        }
+    }
+    let replaced: Vec<PathBuf> = segment_ids.iter()
+        .map(|id| match manifest.segments.insert(id.to_string(), generation) {
+            Some(previous) => generation_path(root, db, id, previous),
+            None => segment_path(root, db, id),
+        })
+        .collect();
+    manifest.generation = generation;
+    write_segment_manifest(root, db, &manifest)?;
+    for path in replaced {
+        // Unreferenced once the manifest is written; a leftover only costs space
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+fn write_segment_manifest(root: &Path, db: &DatabaseName, manifest: &SegmentManifest) -> Result<(), EngineError> {
+    let path = manifest_path(root, db);
+    let bytes = serde_json::to_vec_pretty(manifest).map_err(|e| EngineError::StorageIo(format!("serialize segment manifest: {e}")))?;
+    atomic_write_file(&path, &bytes).map_err(|e| EngineError::StorageIo(format!("atomic_write_file({}): {e}", path.display())))
+}
+
+/// Drop `segment_ids` from the manifest, then delete their files; reads of them find
+/// nothing from then on. A crash before the manifest is replaced leaves them current,
+/// and one after leaves files `remove_unreferenced_segments` deletes.
+pub fn retire_segments(root: &Path, db: &DatabaseName, segment_ids: &[&str]) -> Result<(), EngineError> {
+    let mut manifest = read_segment_manifest(root, db)?;
+    let retired: Vec<PathBuf> = segment_ids.iter()
+        .filter_map(|id| manifest.segments.remove(*id).map(|generation| generation_path(root, db, id, generation)))
+        .collect();
+    if !retired.is_empty() {
+        write_segment_manifest(root, db, &manifest)?;
+    }
+    let legacy = segment_ids.iter().map(|id| segment_path(root, db, id));
+    for path in retired.into_iter().chain(legacy) {
+        remove_if_present(&path)?;
+    }
+    Ok(())
+}
+
+/// Delete the segment files the manifest does not make current: versions replaced or
+/// retired whose removal was cut short, generations staged but never published, and the
+/// temporary files of writes that never finished. Returns the number of files deleted.
+///
+/// A generation being staged is not current yet either, so nothing may flush to `db`
+/// meanwhile.
+pub fn remove_unreferenced_segments(root: &Path, db: &DatabaseName) -> Result<u64, EngineError> {
+    let manifest = read_segment_manifest(root, db)?;
+    let mut removed = 0;
+    for (path, current) in segment_files(root, db, &manifest)? {
+        if !current {
+            remove_if_present(&path)?;
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        fsync_dir(&segments_dir(root, db)).map_err(|e| EngineError::StorageIo(format!("fsync segments dir: {e}")))?;
+    }
+    Ok(removed)
+}
+
+/// Space the segment files of a database take, as `segment_usage` measures it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentUsage {
+    /// Segment files on disk, current or not
+    pub files: u64,
+    /// Their size in bytes
+    pub bytes: u64,
+    /// Node count in the headers of the current segments
+    pub node_records: u64,
+    /// Edge count in the headers of the current segments
+    pub edge_records: u64,
+}
+
+/// Measure the segment files of `db`; see `SegmentUsage`.
+pub fn segment_usage(root: &Path, db: &DatabaseName) -> Result<SegmentUsage, EngineError> {
+    let manifest = read_segment_manifest(root, db)?;
+    let mut usage = SegmentUsage::default();
+    for (path, current) in segment_files(root, db, &manifest)? {
+        let metadata = fs::metadata(&path).map_err(|e| EngineError::StorageIo(format!("metadata({}): {e}", path.display())))?;
+        usage.files += 1;
+        usage.bytes += metadata.len();
+        if current {
+            let header = read_header(&mut open_existing(&path)?, &path)?;
+            usage.node_records += header.node_count;
+            usage.edge_records += header.edge_count;
+        }
+    }
+    Ok(usage)
+}
+
+/// Every segment file of `db`, with whether `manifest` makes it current: the generation
+/// it lists for a segment, or the file from before the manifest of one it does not list.
+/// Temporary files, of segments or of the manifest, are never current.
+fn segment_files(root: &Path, db: &DatabaseName, manifest: &SegmentManifest) -> Result<Vec<(PathBuf, bool)>, EngineError> {
+    let dir = segments_dir(root, db);
+    let mut files = Vec::new();
+    for entry in read_dir_if_present(&dir)? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            for entry in read_dir_if_present(&path)? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let current = if let Some(stem) = name.strip_suffix(".seg") {
+                    match stem.rsplit_once('.').and_then(|(id, generation)| Some((id, generation.parse::<u64>().ok()?))) {
+                        Some((id, generation)) => manifest.segments.get(id) == Some(&generation),
+                        None => !manifest.segments.contains_key(stem),
+                    }
+                } else if name.ends_with(".seg.tmp") {
+                    false
+                } else {
+                    continue;
+                };
+                files.push((entry.path(), current));
+            }
+        } else if name.starts_with(".manifest.json.tmp") {
+            files.push((path, false));
+        }
+    }
+    Ok(files)
+}
+
+fn read_dir_if_present(dir: &Path) -> Result<Vec<fs::DirEntry>, EngineError> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.collect::<io::Result<_>>()
+            .map_err(|e| EngineError::StorageIo(format!("read_dir({}): {e}", dir.display()))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(EngineError::StorageIo(format!("read_dir({}): {e}", dir.display()))),
+    }
+}
+
+fn remove_if_present(path: &Path) -> Result<(), EngineError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(EngineError::StorageIo(format!("remove({}): {e}", path.display()))),
+        _ => Ok(()),
+    }
+}
+
+/// Write a segment at `path` through a temporary file, fsynced then renamed into place.
+/// The data `write` streams out is not held in memory: the header goes first with a
+/// zero checksum, patched once the data is written.
+fn write_atomically(
+    path: &Path,
+    node_count: u64,
+    edge_count: u64,
+    write: &mut dyn FnMut(&mut dyn Write) -> io::Result<()>,
+) -> Result<(), EngineError> {
+    let parent = path.parent().ok_or_else(|| EngineError::StorageIo(format!("no parent: {}", path.display())))?;
+    fs::create_dir_all(parent).map_err(|e| EngineError::StorageIo(format!("create_dir_all: {e}")))?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let f = File::create(&tmp).map_err(|e| EngineError::StorageIo(format!("create({}): {e}", tmp.display())))?;
+    let written = (|| {
+        let mut out = Checksummed { inner: BufWriter::new(f), hasher: crc32fast::Hasher::new() };
+        out.inner.write_all(&SegmentHeader::new(node_count, edge_count, 0).to_bytes())?;
+        write(&mut out)?;
+        let checksum = out.hasher.finalize();
+        let mut f = out.inner.into_inner().map_err(|e| e.into_error())?;
+        f.seek(SeekFrom::Start(CHECKSUM_OFFSET))?;
+        f.write_all(&checksum.to_le_bytes())?;
+        f.sync_all()?;
+        fs::rename(&tmp, path)?;
+        fsync_dir(parent)
+    })();
+    written.map_err(|e| EngineError::StorageIo(format!("write segment {}: {e}", path.display())))
+}
+
+/// Open a segment file; a missing one is `EngineError::NotFound`, so that callers can tell
+/// a segment never written, as in directories older than it, from one they cannot read.
+fn open_existing(path: &Path) -> Result<File, EngineError> {
+    File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => EngineError::NotFound(path.display().to_string()),
+        _ => EngineError::StorageIo(format!("open({}): {e}", path.display())),
+    })
+}
+
+/// Open a segment to stream its data; see `SegmentReader`.
+pub fn open_segment(root: &Path, db: &DatabaseName, segment_id: &str) -> Result<SegmentReader, EngineError> {
+    let path = current_segment_path(root, db, segment_id)?;
+    let f = open_existing(&path)?;
+    let mut inner = BufReader::new(f);
+    let header = read_header(&mut inner, &path)?;
+    Ok(SegmentReader { header, inner, hasher: crc32fast::Hasher::new(), path })
+}
+
+/// The data of a segment, read as a stream. The checksum is checked once the end is
+/// reached: the read that finds it fails with `InvalidData`, wrapping an
+/// `EngineError::SegmentCorruption`, on a mismatch.
+pub struct SegmentReader {
+    pub header: SegmentHeader,
+    inner: BufReader<File>,
+    hasher: crc32fast::Hasher,
+    path: PathBuf,
+}
+
+impl Read for SegmentReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() {
+            let computed = self.hasher.clone().finalize();
+            if computed != self.header.checksum {
+                let error = checksum_mismatch(&self.path, self.header.checksum, computed);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Hashes what goes through it into the segment checksum.
+struct Checksummed<W> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
@@ -0,0 +1,150 @@
+// Integration test: streamed segment writes and reads
+
+use casys_storage_fs::segments::{
+    current_segment_path, generation_path, open_segment, publish_segments, read_segment, read_segment_manifest,
+    remove_unreferenced_segments, retire_segments, segment_path, segment_usage, stage_segment_with, write_segment,
+    write_segment_with, Segment,
+};
+use casys_core::{DatabaseName, EngineError};
+use std::io::{ErrorKind, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::fs;
+
+#[test]
+fn streamed_segment_roundtrip_and_checksum() {
+    // Unique temp dir under target/tmp
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("segments_fs_{}", now));
+    fs::create_dir_all(&root).unwrap();
+    let db = DatabaseName::try_from("testdb").unwrap();
+
+    // Written in pieces, read back whole: the header checksum covers all of them
+    let path = write_segment_with(&root, &db, "nodes", 3, 0, &mut |out| {
+        for chunk in [&b"first,"[..], b"second,", b"third"] {
+            out.write_all(chunk)?;
+        }
+        Ok(())
+    }).unwrap();
+    let seg = read_segment(&root, &db, "nodes").unwrap();
+    assert_eq!(seg.data, b"first,second,third");
+    assert_eq!(seg.header.node_count, 3);
+
+    let mut reader = open_segment(&root, &db, "nodes").unwrap();
+    assert_eq!(reader.header.node_count, 3);
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"first,second,third");
+
+    // A flipped data byte is reported once the stream reaches its end
+    let mut bytes = fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(current_segment_path(&root, &db, "nodes").unwrap(), &bytes).unwrap();
+    let mut reader = open_segment(&root, &db, "nodes").unwrap();
+    let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+    match read_segment(&root, &db, "nodes") {
+        Err(EngineError::SegmentCorruption { path: corrupt, detail }) => {
+            assert_eq!(corrupt, path.display().to_string());
+            assert!(detail.contains("checksum mismatch"), "{}", detail);
+        }
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    // As is a header cut short
+    fs::write(&path, &bytes[..10]).unwrap();
+    assert!(matches!(read_segment(&root, &db, "nodes"), Err(EngineError::SegmentCorruption { .. })));
+}
+
+#[test]
+fn staged_segments_become_current_together() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("segments_fs_publish_{}", now));
+    fs::create_dir_all(&root).unwrap();
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let read = |id| read_segment(&root, &db, id).unwrap().data;
+    let stage = |id, data: &'static [u8]| stage_segment_with(&root, &db, id, 0, 0, &mut |out| out.write_all(data));
+
+    // A segment from before the manifest is read from its old path, then replaced
+    Segment::new(0, 0, b"legacy".to_vec()).write_to_path(&segment_path(&root, &db, "nodes")).unwrap();
+    assert_eq!(read("nodes"), b"legacy");
+    stage("nodes", b"nodes-1").unwrap();
+    stage("edges", b"edges-1").unwrap();
+    assert_eq!(read("nodes"), b"legacy");
+    publish_segments(&root, &db, &["nodes", "edges"]).unwrap();
+    assert_eq!((read("nodes"), read("edges")), (b"nodes-1".to_vec(), b"edges-1".to_vec()));
+    assert!(!segment_path(&root, &db, "nodes").exists());
+
+    // Crash between the two renames: nodes staged in place, edges never got past its
+    // temporary file, nothing published. The previous pair is still what reads return
+    stage("nodes", b"nodes-2").unwrap();
+    let crashed = stage_segment_with(&root, &db, "edges", 0, 0, &mut |out| {
+        out.write_all(b"edges-")?;
+        Err(std::io::Error::other("crash"))
+    });
+    assert!(crashed.is_err());
+    assert_eq!((read("nodes"), read("edges")), (b"nodes-1".to_vec(), b"edges-1".to_vec()));
+    assert!(publish_segments(&root, &db, &["nodes", "edges"]).is_err(), "edges was never staged");
+    assert_eq!(read_segment_manifest(&root, &db).unwrap().generation, 1);
+
+    // The next flush overwrites what the crashed one left
+    stage("nodes", b"nodes-3").unwrap();
+    stage("edges", b"edges-3").unwrap();
+    publish_segments(&root, &db, &["nodes", "edges"]).unwrap();
+    assert_eq!((read("nodes"), read("edges")), (b"nodes-3".to_vec(), b"edges-3".to_vec()));
+
+    // A single write publishes on its own and keeps the others
+    write_segment(&root, &db, "checkpoint", &Segment::new(0, 0, b"cp".to_vec())).unwrap();
+    assert_eq!((read("checkpoint"), read("edges")), (b"cp".to_vec(), b"edges-3".to_vec()));
+    let manifest = read_segment_manifest(&root, &db).unwrap();
+    assert_eq!(manifest.generation, 3);
+    assert_eq!(manifest.segments["edges"], 2);
+}
+
+#[test]
+fn retired_and_unreferenced_segments_are_removed() {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    let root = std::env::current_dir().unwrap()
+        .join("target").join("tmp").join(format!("segments_fs_gc_{}", now));
+    fs::create_dir_all(&root).unwrap();
+    let db = DatabaseName::try_from("testdb").unwrap();
+    let stage = |id, data: &'static [u8]| stage_segment_with(&root, &db, id, 2, 1, &mut |out| out.write_all(data));
+
+    // A pre-manifest segment no publish replaced stays current
+    Segment::new(0, 0, b"legacy".to_vec()).write_to_path(&segment_path(&root, &db, "checkpoint")).unwrap();
+    stage("nodes", b"nodes-1").unwrap();
+    stage("nodes-000001", b"delta").unwrap();
+    publish_segments(&root, &db, &["nodes", "nodes-000001"]).unwrap();
+    assert_eq!(segment_usage(&root, &db).unwrap().files, 3);
+
+    // Leftovers: a version whose removal was cut short, a generation never published and
+    // a write that never got to its rename
+    let replaced = generation_path(&root, &db, "nodes", 1);
+    let kept = fs::read(&replaced).unwrap();
+    stage("nodes", b"nodes-2").unwrap();
+    publish_segments(&root, &db, &["nodes"]).unwrap();
+    fs::write(&replaced, kept).unwrap();
+    stage("edges", b"never published").unwrap();
+    let crashed = stage_segment_with(&root, &db, "edges", 0, 0, &mut |out| {
+        out.write_all(b"edg")?;
+        Err(std::io::Error::other("crash"))
+    });
+    assert!(crashed.is_err());
+    let usage = segment_usage(&root, &db).unwrap();
+    assert_eq!(usage.files, 6);
+    assert_eq!((usage.node_records, usage.edge_records), (4, 2), "headers of the current segments only");
+
+    retire_segments(&root, &db, &["nodes-000001"]).unwrap();
+    assert!(!read_segment_manifest(&root, &db).unwrap().segments.contains_key("nodes-000001"));
+    assert!(matches!(read_segment(&root, &db, "nodes-000001"), Err(EngineError::NotFound(_))));
+    assert_eq!(remove_unreferenced_segments(&root, &db).unwrap(), 3);
+    assert_eq!(remove_unreferenced_segments(&root, &db).unwrap(), 0);
+    let usage = segment_usage(&root, &db).unwrap();
+    assert_eq!((usage.files, usage.node_records), (2, 2));
+    assert_eq!(read_segment(&root, &db, "nodes").unwrap().data, b"nodes-2");
+    assert_eq!(read_segment(&root, &db, "checkpoint").unwrap().data, b"legacy");
+    fs::remove_dir_all(&root).unwrap();
+}